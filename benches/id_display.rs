@@ -0,0 +1,42 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Compares the cost to write `Id` as hex string into a log-like sink.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mouse::data_types::{CryptoHash, Id};
+use std::io::{sink, Write};
+
+/// Formats `id` into a heap allocated `String` before writing it, as the naive logging does.
+fn allocating_hex(id: &Id) -> String {
+    id.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn bench_id_display(c: &mut Criterion) {
+    let id = Id::calculate(b"mouse");
+    let mut out = sink();
+
+    c.bench_function("id_hex_allocating", |b| {
+        b.iter(|| write!(out, "{}", allocating_hex(black_box(&id))).unwrap())
+    });
+
+    c.bench_function("id_hex_display_hex", |b| {
+        b.iter(|| write!(out, "{}", black_box(&id).display_hex()).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_id_display);
+criterion_main!(benches);
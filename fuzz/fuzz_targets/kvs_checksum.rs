@@ -14,9 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-fn main() {
-    cc::Build::new()
-        .cpp(false)
-        .file("c_src/signal.c")
-        .compile("mouse_signal")
-}
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    mouse::fuzzing::kvs_checksum(data);
+});
@@ -14,37 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-#include <errno.h>
-#include <signal.h>
-#include <string.h>
+#![no_main]
 
-#ifndef NULL
-#define NULL 0
-#endif
+use libfuzzer_sys::fuzz_target;
 
-/**
- * Wait for signal SIGHUP, SIGINT, and SIGTERM.
- *
- * On success, returns 0; otherwise, set errno and returns 1.
- */
-int sigwait_() {
-  if (errno)
-    return 1;
-
-  sigset_t ss;
-  if (sigemptyset(&ss))
-    return 1;
-
-  if (sigaddset(&ss, SIGHUP) || sigaddset(&ss, SIGINT) ||
-      sigaddset(&ss, SIGTERM))
-    return 1;
-
-  if (sigprocmask(SIG_BLOCK, &ss, NULL))
-    return 1;
-
-  int sig;
-  if (sigwait(&ss, &sig))
-    return 1;
-
-  return 0;
-}
+fuzz_target!(|data: &[u8]| {
+    mouse::fuzzing::kvs_dump(data);
+});
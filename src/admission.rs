@@ -0,0 +1,629 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `admission` decides whether the node accepts a new pending acid under the resource
+//! exhaustion.
+//! `admission` is independent from other modules.
+//!
+//! The blocks are always applied regardless of the decision; only the new pending acids are
+//! rejected so that the node can follow the chain.
+//!
+//! Besides, function [`check_size`] and [`throttle`] refuse a flood of acids from a source (e.g.
+//! a peer) before the expensive validation. [`throttle`] is a token bucket for each source.
+//!
+//! [`check_size`]: self::check_size
+//! [`throttle`]: self::throttle
+
+use crate::{byte_size, Config, ModuleEnvironment};
+use clap::{App, Arg};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+const DEFAULT_MIN_DISK_FREE: &'static str = "1GiB";
+
+/// The maximum number of the sources that [`throttle`] remembers. The least recently used one is
+/// forgotten when a new source comes.
+///
+/// [`throttle`]: self::throttle
+const MAX_SOURCES: usize = 4096;
+
+/// `Metrics` is the current resource usage to make the decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The free bytes of the disk storing the data, or `None` if unknown.
+    pub disk_free: Option<u64>,
+    /// The number of the KVS writing queries waiting for being flushed.
+    pub kvs_queue_depth: usize,
+    /// The byte size that the cache is using.
+    pub memory_used: usize,
+}
+
+/// `Rejection` is the reason why a new pending acid is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    /// The free disk space is less than '--admission-min-disk-free'.
+    DiskFull {
+        /// The free bytes of the disk.
+        free: u64,
+        /// '--admission-min-disk-free'
+        required: u64,
+    },
+    /// The KVS writing queue is longer than '--admission-max-kvs-queue'.
+    QueueSaturated {
+        /// The number of the waiting KVS writing queries.
+        depth: usize,
+        /// '--admission-max-kvs-queue'
+        limit: usize,
+    },
+    /// The memory usage exceeds '--admission-max-memory'.
+    MemoryExhausted {
+        /// The byte size of the memory in use.
+        used: usize,
+        /// '--admission-max-memory'
+        limit: usize,
+    },
+    /// The intrinsic data is larger than '--max-acid-bytes'.
+    TooLarge {
+        /// The byte size of the intrinsic data.
+        bytes: usize,
+        /// '--max-acid-bytes'
+        limit: usize,
+    },
+    /// The source sent more acids than '--admission-acid-rate' and '--admission-acid-burst'.
+    RateLimited {
+        /// '--admission-acid-rate'
+        rate: u32,
+        /// '--admission-acid-burst'
+        burst: u32,
+    },
+    /// The acid is already in the mempool or in the cache.
+    Duplicated,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::DiskFull { free, required } => write!(
+                f,
+                "Rejected: the free disk space is {} bytes (required: {} bytes)",
+                free, required
+            ),
+            Rejection::QueueSaturated { depth, limit } => write!(
+                f,
+                "Rejected: {} KVS writing queries are waiting (limit: {})",
+                depth, limit
+            ),
+            Rejection::MemoryExhausted { used, limit } => write!(
+                f,
+                "Rejected: {} bytes of memory is used (limit: {} bytes)",
+                used, limit
+            ),
+            Rejection::TooLarge { bytes, limit } => write!(
+                f,
+                "Rejected: the acid is {} bytes (limit: {} bytes)",
+                bytes, limit
+            ),
+            Rejection::RateLimited { rate, burst } => write!(
+                f,
+                "Rejected: too many acids from the source (limit: {} per second, burst: {})",
+                rate, burst
+            ),
+            Rejection::Duplicated => f.write_str("Rejected: the acid is already known"),
+        }
+    }
+}
+
+impl Error for Rejection {}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --admission-min-disk-free
+/// - --admission-max-kvs-queue
+/// - --admission-max-memory
+/// - --max-acid-bytes
+/// - --admission-acid-rate
+/// - --admission-acid-burst
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --admission-min-disk-free: 1GiB (= 1073741824 bytes)
+/// - --admission-max-kvs-queue: (not specified; i.e. no limit)
+/// - --admission-max-memory: (not specified; i.e. no limit)
+/// - --max-acid-bytes: (not specified; i.e. no limit)
+/// - --admission-acid-rate: (not specified; i.e. no limit)
+/// - --admission-acid-burst: (not specified; i.e. same to '--admission-acid-rate')
+pub struct Environment {
+    min_disk_free: u64,
+    max_kvs_queue: Option<usize>,
+    max_memory: Option<usize>,
+    max_acid_bytes: Option<usize>,
+    acid_rate: Option<u32>,
+    acid_burst: u32,
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            min_disk_free: byte_size::parse(DEFAULT_MIN_DISK_FREE).unwrap() as u64,
+            max_kvs_queue: None,
+            max_memory: None,
+            max_acid_bytes: None,
+            acid_rate: None,
+            acid_burst: 0,
+            buckets: Mutex::default(),
+        }
+    }
+}
+
+/// `Bucket` is the token bucket of a source.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// The key of `Buckets::order` .
+    seq: u64,
+}
+
+/// `Buckets` holds at most `MAX_SOURCES` buckets in the order of the last use.
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    /// The order of the last use and the source.
+    order: BTreeMap<u64, String>,
+    next_seq: u64,
+}
+
+impl Buckets {
+    fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Returns the bucket of `source` marking it as the most recently used. A full bucket is
+    /// created if not found, and the least recently used one is forgotten if `MAX_SOURCES`
+    /// buckets are there.
+    fn get_mut(&mut self, source: &str, burst: f64, now: Instant) -> &mut Bucket {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        if let Some(bucket) = self.buckets.get_mut(source) {
+            let source = self.order.remove(&bucket.seq).unwrap();
+            bucket.seq = seq;
+            self.order.insert(seq, source);
+        } else {
+            if MAX_SOURCES <= self.buckets.len() {
+                let oldest = *self.order.keys().next().unwrap();
+                let oldest = self.order.remove(&oldest).unwrap();
+                self.buckets.remove(&oldest);
+            }
+
+            let bucket = Bucket {
+                tokens: burst,
+                updated: now,
+                seq,
+            };
+            self.buckets.insert(String::from(source), bucket);
+            self.order.insert(seq, String::from(source));
+        }
+
+        self.buckets.get_mut(source).unwrap()
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.args(&[
+            Arg::with_name("admission_min_disk_free")
+                .help(
+                    "The new pending acids are rejected if the free disk space is less than this
+value. (0 disables the check.) Units are accepted as well as '--cache-size-soft-limit'.",
+                )
+                .long("--admission-min-disk-free")
+                .default_value(DEFAULT_MIN_DISK_FREE)
+                .takes_value(true),
+            Arg::with_name("admission_max_kvs_queue")
+                .help(
+                    "The new pending acids are rejected if more KVS writing queries than this
+value are waiting. (No limit by default.)",
+                )
+                .long("--admission-max-kvs-queue")
+                .takes_value(true),
+            Arg::with_name("admission_max_memory")
+                .help(
+                    "The new pending acids are rejected if the cache uses more bytes than this
+value. (No limit by default.) Units are accepted as well as '--cache-size-soft-limit'.",
+                )
+                .long("--admission-max-memory")
+                .takes_value(true),
+            Arg::with_name("max_acid_bytes")
+                .help(
+                    "The new pending acids whose intrinsic data is larger than this value are
+rejected. (No limit by default.) Units are accepted as well as '--cache-size-soft-limit'.",
+                )
+                .long("--max-acid-bytes")
+                .takes_value(true),
+            Arg::with_name("admission_acid_rate")
+                .help(
+                    "The number of the new pending acids per second that each source (e.g. a
+peer) can send. The excess is rejected. (No limit by default.)",
+                )
+                .long("--admission-acid-rate")
+                .takes_value(true),
+            Arg::with_name("admission_acid_burst")
+                .help(
+                    "The number of the new pending acids that each source can send at once.
+(Same to '--admission-acid-rate' by default.)",
+                )
+                .long("--admission-acid-burst")
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let min_disk_free = config.args().value_of("admission_min_disk_free").unwrap();
+        self.min_disk_free = byte_size::parse(min_disk_free).map_err(|e| {
+            let msg = format!("Failed to parse '--admission-min-disk-free': {}", e);
+            crate::Error::Config(msg)
+        })? as u64;
+
+        if let Some(max_kvs_queue) = config.args().value_of("admission_max_kvs_queue") {
+            let max_kvs_queue = max_kvs_queue.parse().map_err(|e| {
+                let msg = format!("Failed to parse '--admission-max-kvs-queue': {}", e);
+                crate::Error::Config(msg)
+            })?;
+            self.max_kvs_queue = Some(max_kvs_queue);
+        }
+
+        if let Some(max_memory) = config.args().value_of("admission_max_memory") {
+            let max_memory = byte_size::parse(max_memory).map_err(|e| {
+                let msg = format!("Failed to parse '--admission-max-memory': {}", e);
+                crate::Error::Config(msg)
+            })?;
+            self.max_memory = Some(max_memory);
+        }
+
+        if let Some(max_acid_bytes) = config.args().value_of("max_acid_bytes") {
+            let max_acid_bytes = byte_size::parse(max_acid_bytes).map_err(|e| {
+                let msg = format!("Failed to parse '--max-acid-bytes': {}", e);
+                crate::Error::Config(msg)
+            })?;
+            self.max_acid_bytes = Some(max_acid_bytes);
+        }
+
+        if let Some(acid_rate) = config.args().value_of("admission_acid_rate") {
+            let acid_rate = acid_rate.parse().map_err(|e| {
+                let msg = format!("Failed to parse '--admission-acid-rate': {}", e);
+                crate::Error::Config(msg)
+            })?;
+            self.acid_rate = Some(acid_rate);
+            self.acid_burst = acid_rate;
+        }
+
+        if let Some(acid_burst) = config.args().value_of("admission_acid_burst") {
+            self.acid_burst = acid_burst.parse().map_err(|e| {
+                let msg = format!("Failed to parse '--admission-acid-burst': {}", e);
+                crate::Error::Config(msg)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// Returns an error if a new pending acid should be rejected under `metrics` .
+///
+/// The disk is checked first, then the KVS writing queue, and the memory at last.
+pub fn check(metrics: &Metrics, env: &Environment) -> Result<(), Rejection> {
+    if let Some(free) = metrics.disk_free {
+        if free < env.min_disk_free {
+            return Err(Rejection::DiskFull {
+                free,
+                required: env.min_disk_free,
+            });
+        }
+    }
+
+    if let Some(limit) = env.max_kvs_queue {
+        if limit < metrics.kvs_queue_depth {
+            return Err(Rejection::QueueSaturated {
+                depth: metrics.kvs_queue_depth,
+                limit,
+            });
+        }
+    }
+
+    if let Some(limit) = env.max_memory {
+        if limit < metrics.memory_used {
+            return Err(Rejection::MemoryExhausted {
+                used: metrics.memory_used,
+                limit,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns an error if the intrinsic data of `bytes` bytes is larger than '--max-acid-bytes'.
+pub fn check_size(bytes: usize, env: &Environment) -> Result<(), Rejection> {
+    match env.max_acid_bytes {
+        Some(limit) if limit < bytes => Err(Rejection::TooLarge { bytes, limit }),
+        _ => Ok(()),
+    }
+}
+
+/// Takes a token from the bucket of `source` , or returns an error if the bucket is empty.
+///
+/// Each bucket holds at most '--admission-acid-burst' tokens, and is refilled at
+/// '--admission-acid-rate' tokens per second. It always succeeds if '--admission-acid-rate' is
+/// not specified.
+///
+/// At most 4096 buckets are remembered; the bucket of the least recently seen source is
+/// forgotten (i.e. it will be full again) when a new source comes.
+pub fn throttle(source: &str, env: &Environment) -> Result<(), Rejection> {
+    throttle_at(source, Instant::now(), env)
+}
+
+fn throttle_at(source: &str, now: Instant, env: &Environment) -> Result<(), Rejection> {
+    let rate = match env.acid_rate {
+        None => return Ok(()),
+        Some(rate) => rate,
+    };
+    let tokens_per_sec = rate as f64;
+    let burst = env.acid_burst as f64;
+
+    let mut buckets = env.buckets.lock().unwrap();
+    let bucket = buckets.get_mut(source, burst, now);
+
+    let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+    bucket.tokens = burst.min(bucket.tokens + elapsed * tokens_per_sec);
+    bucket.updated = now;
+
+    if 1.0 <= bucket.tokens {
+        bucket.tokens -= 1.0;
+        Ok(())
+    } else {
+        Err(Rejection::RateLimited {
+            rate,
+            burst: env.acid_burst,
+        })
+    }
+}
+
+/// Returns the free bytes of the disk that `path` is on.
+///
+/// It is available only on Linux; returns an error on other platforms.
+pub fn disk_free(path: &Path) -> io::Result<u64> {
+    imp::disk_free(path)
+}
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod imp {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::raw::{c_char, c_int, c_ulong};
+    use std::path::Path;
+
+    /// Same layout as C struct 'statvfs' on 64 bit Linux.
+    #[repr(C)]
+    #[derive(Default)]
+    struct StatVfs {
+        f_bsize: c_ulong,
+        f_frsize: c_ulong,
+        f_blocks: u64,
+        f_bfree: u64,
+        f_bavail: u64,
+        f_files: u64,
+        f_ffree: u64,
+        f_favail: u64,
+        f_fsid: c_ulong,
+        f_flag: c_ulong,
+        f_namemax: c_ulong,
+        spare: [c_int; 6],
+    }
+
+    extern "C" {
+        fn statvfs(path: *const c_char, buf: *mut StatVfs) -> c_int;
+    }
+
+    pub fn disk_free(path: &Path) -> io::Result<u64> {
+        let path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut buf = StatVfs::default();
+        match unsafe { statvfs(path.as_ptr(), &mut buf) } {
+            // 'f_bavail' is the number of the blocks available to the unprivileged user.
+            0 => Ok(buf.f_bavail * buf.f_frsize as u64),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+mod imp {
+    use std::io;
+    use std::path::Path;
+
+    pub fn disk_free(_path: &Path) -> io::Result<u64> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Not supported on this platform.",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn check_() {
+        let env = Environment {
+            min_disk_free: 100,
+            max_kvs_queue: Some(10),
+            max_memory: Some(1000),
+            ..Environment::default()
+        };
+
+        let ok = Metrics {
+            disk_free: Some(100),
+            kvs_queue_depth: 10,
+            memory_used: 1000,
+        };
+        assert_eq!(Ok(()), check(&ok, &env));
+
+        let unknown_disk = Metrics {
+            disk_free: None,
+            ..ok
+        };
+        assert_eq!(Ok(()), check(&unknown_disk, &env));
+
+        let disk_full = Metrics {
+            disk_free: Some(99),
+            ..ok
+        };
+        assert_eq!(
+            Err(Rejection::DiskFull {
+                free: 99,
+                required: 100
+            }),
+            check(&disk_full, &env)
+        );
+
+        let saturated = Metrics {
+            kvs_queue_depth: 11,
+            ..ok
+        };
+        assert_eq!(
+            Err(Rejection::QueueSaturated {
+                depth: 11,
+                limit: 10
+            }),
+            check(&saturated, &env)
+        );
+
+        let exhausted = Metrics {
+            memory_used: 1001,
+            ..ok
+        };
+        assert_eq!(
+            Err(Rejection::MemoryExhausted {
+                used: 1001,
+                limit: 1000
+            }),
+            check(&exhausted, &env)
+        );
+    }
+
+    #[test]
+    fn check_size_() {
+        let mut env = Environment::default();
+        assert_eq!(Ok(()), check_size(usize::MAX, &env));
+
+        env.max_acid_bytes = Some(100);
+        assert_eq!(Ok(()), check_size(100, &env));
+        assert_eq!(
+            Err(Rejection::TooLarge {
+                bytes: 101,
+                limit: 100
+            }),
+            check_size(101, &env)
+        );
+    }
+
+    #[test]
+    fn throttle_() {
+        let mut env = Environment::default();
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(Ok(()), throttle_at("peer", now, &env));
+        }
+
+        env.acid_rate = Some(2);
+        env.acid_burst = 3;
+        let limited = Err(Rejection::RateLimited { rate: 2, burst: 3 });
+
+        for _ in 0..3 {
+            assert_eq!(Ok(()), throttle_at("peer", now, &env));
+        }
+        assert_eq!(limited, throttle_at("peer", now, &env));
+
+        // The other source has its own bucket.
+        assert_eq!(Ok(()), throttle_at("other", now, &env));
+
+        // 2 tokens are refilled in a second.
+        let later = now + Duration::from_secs(1);
+        assert_eq!(Ok(()), throttle_at("peer", later, &env));
+        assert_eq!(Ok(()), throttle_at("peer", later, &env));
+        assert_eq!(limited, throttle_at("peer", later, &env));
+
+        // No more than the burst.
+        let later = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(Ok(()), throttle_at("peer", later, &env));
+        }
+        assert_eq!(limited, throttle_at("peer", later, &env));
+    }
+
+    #[test]
+    fn forget_sources() {
+        let env = Environment {
+            acid_rate: Some(1),
+            acid_burst: 1,
+            ..Environment::default()
+        };
+        let now = Instant::now();
+        let limited = Err(Rejection::RateLimited { rate: 1, burst: 1 });
+
+        for i in 0..MAX_SOURCES {
+            throttle_at(&i.to_string(), now, &env).unwrap();
+        }
+        assert_eq!(MAX_SOURCES, env.buckets.lock().unwrap().len());
+
+        // "0" becomes the most recently used.
+        assert_eq!(limited, throttle_at("0", now, &env));
+
+        // The number of the buckets is capped, and the least recently used "1" is forgotten.
+        throttle_at("new", now, &env).unwrap();
+        assert_eq!(MAX_SOURCES, env.buckets.lock().unwrap().len());
+        assert_eq!(MAX_SOURCES, env.buckets.lock().unwrap().order.len());
+        assert_eq!(Ok(()), throttle_at("1", now, &env));
+        assert_eq!(limited, throttle_at("0", now, &env));
+        assert_eq!(limited, throttle_at("new", now, &env));
+    }
+
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    #[test]
+    fn disk_free_() {
+        assert_eq!(true, disk_free(Path::new("/")).is_ok());
+        assert_eq!(true, disk_free(Path::new("/no/such/path")).is_err());
+    }
+}
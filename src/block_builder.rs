@@ -0,0 +1,298 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `block_builder` assembles a candidate block (= 'block template') from the pending acids for a
+//! miner or a validator to seal.
+//! `block_builder` depends on module `data_types` .
+//!
+//! See also function [`build_block_template`] .
+//!
+//! [`build_block_template`]: crate::build_block_template
+
+use crate::data_types::{merkle, Acid, AssetValue, Id, Resource, ResourceId};
+use std::collections::{HashMap, HashSet};
+
+/// `Outcome` is the result of method [`Builder::push`] .
+///
+/// [`Builder::push`]: self::Builder::push
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The acid is added.
+    Added,
+    /// The acid has already been added.
+    Duplicated,
+    /// Some parent is pending but not added yet. The acid can be added after the parent is.
+    Deferred,
+    /// The total byte size would exceed the budget.
+    TooLarge,
+    /// Some balance would be less than 0 or overflow.
+    Insufficient,
+}
+
+/// `Template` is the candidate block that [`Builder`] assembled.
+///
+/// [`Builder`]: self::Builder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    /// The ids of the acids in the order to be sealed. A parent always precedes the children.
+    pub ids: Vec<Id>,
+    /// The Merkle root of `ids` . (See also module [`merkle`] .)
+    ///
+    /// [`merkle`]: crate::data_types::merkle
+    pub merkle_root: Id,
+    /// The total byte size of the intrinsic data of the acids.
+    pub byte_size: usize,
+    /// The sum of the resource values for each [`ResourceId`] except for 0, ordered by the
+    /// owner and the asset type. It can be passed to function [`rdb::append_block`] as it is.
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    /// [`rdb::append_block`]: crate::rdb::append_block
+    pub balance_deltas: Vec<(ResourceId, AssetValue)>,
+}
+
+/// `Builder` adds the pending acids one by one under the byte size budget, verifying the
+/// balances cumulatively.
+pub struct Builder {
+    max_bytes: usize,
+    byte_size: usize,
+    ids: Vec<Id>,
+    added: HashSet<Id>,
+    merkle: merkle::Builder<Id>,
+    /// The balances before the block.
+    balances: HashMap<ResourceId, AssetValue>,
+    deltas: HashMap<ResourceId, AssetValue>,
+    /// The ids of the acids which are not in the main chain.
+    pending: HashSet<Id>,
+}
+
+impl Builder {
+    /// Creates a new instance.
+    ///
+    /// `balances` is the current balance of each [`ResourceId`] that the acids use. (The missing
+    /// one is regarded as 0.) `pending` is the ids of the acids which are not in the main chain
+    /// yet; an acid is deferred till all the parents in `pending` are added.
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    pub fn new(
+        max_bytes: usize,
+        balances: HashMap<ResourceId, AssetValue>,
+        pending: HashSet<Id>,
+    ) -> Self {
+        Self {
+            max_bytes,
+            byte_size: 0,
+            ids: Vec::new(),
+            added: HashSet::new(),
+            merkle: merkle::Builder::new(),
+            balances,
+            deltas: HashMap::new(),
+            pending,
+        }
+    }
+
+    /// Returns the number of the added acids.
+    pub fn count(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns the total byte size of the added acids.
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    /// Tries to add `acid` to the end.
+    pub fn push_acid(&mut self, acid: &dyn Acid) -> Outcome {
+        let parents: Vec<Id> = (0..acid.parent_count())
+            .filter_map(|i| acid.parent(i))
+            .collect();
+        let resources: Vec<Resource> = (0..acid.resource_count())
+            .filter_map(|i| acid.resource(i))
+            .collect();
+
+        self.push(acid.id(), acid.intrinsic().len(), &parents, &resources)
+    }
+
+    /// Tries to add the acid with `id` to the end.
+    ///
+    /// `byte_size` is the byte size of the acid, `parents` is the ids of the parents, and
+    /// `resources` is the resources that the acid consumes (negative value) or generates
+    /// (positive value.)
+    ///
+    /// The acid is not added unless the outcome is `Outcome::Added` .
+    pub fn push(
+        &mut self,
+        id: &Id,
+        byte_size: usize,
+        parents: &[Id],
+        resources: &[Resource],
+    ) -> Outcome {
+        if self.added.contains(id) {
+            return Outcome::Duplicated;
+        }
+
+        let is_deferred = parents
+            .iter()
+            .any(|parent| self.pending.contains(parent) && !self.added.contains(parent));
+        if is_deferred {
+            return Outcome::Deferred;
+        }
+
+        match self.byte_size.checked_add(byte_size) {
+            Some(total) if total <= self.max_bytes => (),
+            _ => return Outcome::TooLarge,
+        }
+
+        let deltas = match self.updated_deltas(resources) {
+            None => return Outcome::Insufficient,
+            Some(deltas) => deltas,
+        };
+
+        self.deltas.extend(deltas);
+        self.byte_size += byte_size;
+        self.ids.push(*id);
+        self.added.insert(*id);
+        self.merkle.push(id);
+
+        Outcome::Added
+    }
+
+    /// Returns the deltas updated by `resources` , or `None` if any balance would be less than 0
+    /// or overflow after all of `resources` are applied.
+    fn updated_deltas(&self, resources: &[Resource]) -> Option<HashMap<ResourceId, AssetValue>> {
+        let mut ret = HashMap::new();
+
+        for resource in resources {
+            let id = resource.id();
+            let delta = match ret.get(id) {
+                Some(&delta) => delta,
+                None => self.deltas.get(id).copied().unwrap_or(0),
+            };
+            ret.insert(*id, delta.checked_add(resource.value())?);
+        }
+
+        for (id, &delta) in ret.iter() {
+            let balance = self.balances.get(id).copied().unwrap_or(0);
+            if balance.checked_add(delta)? < 0 {
+                return None;
+            }
+        }
+
+        Some(ret)
+    }
+
+    /// Finishes building and returns the template.
+    pub fn finish(self) -> Template {
+        let mut balance_deltas: Vec<(ResourceId, AssetValue)> = self
+            .deltas
+            .into_iter()
+            .filter(|&(_, delta)| delta != 0)
+            .collect();
+        balance_deltas.sort_by(|(a, _), (b, _)| {
+            (a.owner(), a.asset_type()).cmp(&(b.owner(), b.asset_type()))
+        });
+
+        Template {
+            merkle_root: self.merkle.root(),
+            ids: self.ids,
+            byte_size: self.byte_size,
+            balance_deltas,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+
+    fn id(n: u8) -> Id {
+        let mut id = Id::zeroed();
+        id[0] = n;
+        id
+    }
+
+    fn resource_id(owner: &[u8]) -> ResourceId {
+        unsafe { ResourceId::new(owner, b"asset") }
+    }
+
+    #[test]
+    fn budget() {
+        let mut builder = Builder::new(10, HashMap::new(), HashSet::new());
+
+        assert_eq!(Outcome::Added, builder.push(&id(1), 6, &[], &[]));
+        assert_eq!(Outcome::Duplicated, builder.push(&id(1), 1, &[], &[]));
+        assert_eq!(Outcome::TooLarge, builder.push(&id(2), 5, &[], &[]));
+        assert_eq!(Outcome::Added, builder.push(&id(3), 4, &[], &[]));
+
+        let template = builder.finish();
+        assert_eq!(vec![id(1), id(3)], template.ids);
+        assert_eq!(10, template.byte_size);
+        assert_eq!(merkle::root(&[id(1), id(3)]), template.merkle_root);
+    }
+
+    #[test]
+    fn balances() {
+        let alice = resource_id(b"alice");
+        let bob = resource_id(b"bob");
+        let balances = [(alice, 10)].iter().copied().collect();
+        let mut builder = Builder::new(usize::MAX, balances, HashSet::new());
+
+        // Alice pays 7 to Bob.
+        let transfer = [Resource::new(&alice, -7), Resource::new(&bob, 7)];
+        assert_eq!(Outcome::Added, builder.push(&id(1), 1, &[], &transfer));
+
+        // Alice has only 3 left.
+        let transfer = [Resource::new(&alice, -4), Resource::new(&bob, 4)];
+        assert_eq!(
+            Outcome::Insufficient,
+            builder.push(&id(2), 1, &[], &transfer)
+        );
+
+        // Bob can spend what he received in the same block.
+        let transfer = [Resource::new(&bob, -7), Resource::new(&alice, 7)];
+        assert_eq!(Outcome::Added, builder.push(&id(3), 1, &[], &transfer));
+
+        // The same resource twice in an acid.
+        let resources = [Resource::new(&bob, 1), Resource::new(&bob, -2)];
+        assert_eq!(
+            Outcome::Insufficient,
+            builder.push(&id(4), 1, &[], &resources)
+        );
+
+        let template = builder.finish();
+        assert_eq!(vec![id(1), id(3)], template.ids);
+        assert_eq!(true, template.balance_deltas.is_empty());
+    }
+
+    #[test]
+    fn parents() {
+        let pending = [id(1), id(2)].iter().copied().collect();
+        let mut builder = Builder::new(usize::MAX, HashMap::new(), pending);
+
+        // id(1) is pending, and id(9) is not. (e.g. already in the chain)
+        assert_eq!(
+            Outcome::Deferred,
+            builder.push(&id(2), 1, &[id(1), id(9)], &[])
+        );
+        assert_eq!(Outcome::Added, builder.push(&id(1), 1, &[id(9)], &[]));
+        assert_eq!(
+            Outcome::Added,
+            builder.push(&id(2), 1, &[id(1), id(9)], &[])
+        );
+
+        assert_eq!(vec![id(1), id(2)], builder.finish().ids);
+    }
+}
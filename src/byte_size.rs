@@ -0,0 +1,118 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `byte_size` parses the byte size arguments like "64MB" or "1GiB".
+
+use std::error::Error;
+
+/// The accepted forms to be shown in the error message.
+const ACCEPTED_FORMS: &'static str = "an integer optionally followed by a unit \
+     B, KB, MB, GB, TB (powers of 1000) or KiB, MiB, GiB, TiB (powers of 1024) \
+     e.g. '67108864', '64MB', '1GiB'";
+
+const UNITS: [(&'static str, usize); 9] = [
+    ("B", 1),
+    ("KB", 1000),
+    ("MB", 1000 * 1000),
+    ("GB", 1000 * 1000 * 1000),
+    ("TB", 1000 * 1000 * 1000 * 1000),
+    ("KiB", 1 << 10),
+    ("MiB", 1 << 20),
+    ("GiB", 1 << 30),
+    ("TiB", 1 << 40),
+];
+
+/// Parses `s` as a byte size and returns the number of bytes.
+///
+/// `s` is an integer optionally followed by a unit. The unit is case insensitive, and white
+/// spaces between the integer and the unit are allowed.
+///
+/// - B
+/// - KB, MB, GB, TB (powers of 1000)
+/// - KiB, MiB, GiB, TiB (powers of 1024)
+///
+/// # Examples
+///
+/// ```
+/// use mouse::byte_size::parse;
+///
+/// assert_eq!(67108864, parse("67108864").unwrap());
+/// assert_eq!(64_000_000, parse("64MB").unwrap());
+/// assert_eq!(1 << 30, parse("1 GiB").unwrap());
+///
+/// assert!(parse("1.5GB").is_err());
+/// assert!(parse("64M").is_err());
+/// ```
+pub fn parse(s: &str) -> Result<usize, Box<dyn Error>> {
+    let error = || {
+        let msg = format!("Bad byte size '{}': expected {}", s, ACCEPTED_FORMS);
+        Box::<dyn Error>::from(msg)
+    };
+
+    let s = s.trim();
+    let pos = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(pos);
+
+    if number.is_empty() {
+        return Err(error());
+    }
+    let number: usize = number.parse().map_err(|_| error())?;
+
+    let unit = unit.trim_start();
+    if unit.is_empty() {
+        return Ok(number);
+    }
+
+    let scale = UNITS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(unit))
+        .map(|(_, scale)| *scale)
+        .ok_or_else(error)?;
+
+    number.checked_mul(scale).ok_or_else(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_without_unit() {
+        assert_eq!(0, parse("0").unwrap());
+        assert_eq!(1024, parse("1024").unwrap());
+        assert_eq!(1024, parse(" 1024 ").unwrap());
+    }
+
+    #[test]
+    fn parse_with_unit() {
+        assert_eq!(3, parse("3B").unwrap());
+        assert_eq!(3_000, parse("3KB").unwrap());
+        assert_eq!(3_000_000, parse("3 mb").unwrap());
+        assert_eq!(3 << 10, parse("3KiB").unwrap());
+        assert_eq!(3 << 20, parse("3mib").unwrap());
+        assert_eq!(3 << 30, parse("3 GiB").unwrap());
+    }
+
+    #[test]
+    fn parse_error() {
+        for s in &["", "MB", "-1", "1.5MB", "1M", "1 MB B", "1XB"] {
+            assert!(parse(s).is_err(), "{}", s);
+        }
+
+        let overflow = format!("{}TiB", usize::MAX);
+        assert!(parse(&overflow).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `accounting` counts the byte size that each cache `Environment` allocates.
+
+use crate::data_types::CMmapAlloc;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// `CountingAlloc` delegates to [`CMmapAlloc`] , and counts the allocating byte size in the
+/// counter shared by the instances of the same `Environment` .
+///
+/// The global caching byte size is increased/decreased by [`CMmapAlloc`] as well.
+///
+/// [`CMmapAlloc`]: crate::data_types::CMmapAlloc
+pub struct CountingAlloc {
+    inner: CMmapAlloc,
+    counter: Arc<AtomicUsize>,
+}
+
+impl CountingAlloc {
+    pub fn new(counter: Arc<AtomicUsize>) -> Self {
+        Self {
+            inner: CMmapAlloc::default(),
+            counter,
+        }
+    }
+}
+
+impl Clone for CountingAlloc {
+    fn clone(&self) -> Self {
+        Self::new(self.counter.clone())
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.counter.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.counter.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.dealloc(ptr, layout);
+        self.counter.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let ret = self.inner.realloc(ptr, layout, new_size);
+        if !ret.is_null() {
+            self.counter.fetch_add(new_size, Ordering::Relaxed);
+            self.counter.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let alloc = CountingAlloc::new(counter.clone());
+        let other = CountingAlloc::new(Arc::new(AtomicUsize::new(0)));
+
+        unsafe {
+            let layout = Layout::from_size_align(16, 8).unwrap();
+            let ptr = alloc.alloc(layout);
+            assert_eq!(16, counter.load(Ordering::Relaxed));
+
+            let ptr = alloc.realloc(ptr, layout, 64);
+            assert_eq!(64, counter.load(Ordering::Relaxed));
+
+            let p = other.alloc(layout);
+            assert_eq!(64, counter.load(Ordering::Relaxed));
+            other.dealloc(p, layout);
+
+            let layout = Layout::from_size_align(64, 8).unwrap();
+            alloc.clone().dealloc(ptr, layout);
+            assert_eq!(0, counter.load(Ordering::Relaxed));
+        }
+    }
+}
@@ -0,0 +1,307 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `integrity` verifies that each cache element is stored under its own id, and that the byte
+//! size accounting is consistent.
+//!
+//! Function [`verify`] scans all the shards of the LRU cache, the pinned elements, and the
+//! orphans. The anomalies are logged and counted per `Environment` ; see [`anomaly_count`] .
+//! Function [`health`] reports them as a health check.
+//!
+//! If feature "cache_self_check" is specified, function [`insert`] calls [`verify`] every
+//! `SELF_CHECK_INTERVAL` insertions.
+//!
+//! [`verify`]: self::verify
+//! [`anomaly_count`]: self::anomaly_count
+//! [`health`]: self::health
+//! [`insert`]: super::insert
+
+use super::{shard, Environment};
+use crate::data_types::{CryptoHash, Id};
+use crate::health::Check;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of the insertions between the self checks.
+#[cfg(feature = "cache_self_check")]
+const SELF_CHECK_INTERVAL: usize = 1024;
+
+/// `Counters` is the state of the integrity check of each `Environment` .
+#[derive(Default)]
+pub struct Counters {
+    /// The total number of the anomalies found so far.
+    anomalies: AtomicUsize,
+    /// The number of the insertions.
+    #[cfg(feature = "cache_self_check")]
+    insertions: AtomicUsize,
+}
+
+/// `Anomaly` is an inconsistency found in the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// The LRU cache element stored under `key` has id `actual` .
+    IdMismatch {
+        /// The id that the element is stored under.
+        key: Id,
+        /// The id of the element.
+        actual: Id,
+    },
+    /// The byte size recorded for the LRU cache element stored under `key` differs from the
+    /// byte size of the element now. (e.g. a merge changed the element without recording it.)
+    ByteSizeMismatch {
+        /// The id that the element is stored under.
+        key: Id,
+        /// The recorded byte size.
+        recorded: usize,
+        /// The byte size of the element.
+        actual: usize,
+    },
+    /// The byte size of the LRU cache differs from the sum of the byte size recorded for the
+    /// elements.
+    LruByteSizeMismatch {
+        /// The byte size of the LRU cache.
+        recorded: usize,
+        /// The sum of the byte size recorded for the elements.
+        actual: usize,
+    },
+    /// The pinned element stored under `key` has id `actual` .
+    PinnedIdMismatch {
+        /// The id that the element is stored under.
+        key: Id,
+        /// The id of the element.
+        actual: Id,
+    },
+    /// The orphan stored under `key` has id `actual` .
+    OrphanIdMismatch {
+        /// The id that the orphan is stored under.
+        key: Id,
+        /// The id of the orphan.
+        actual: Id,
+    },
+    /// The recorded byte size of the pinned elements differs from the sum of them.
+    PinnedByteSizeMismatch {
+        /// The recorded byte size.
+        recorded: usize,
+        /// The sum of the byte size of the pinned elements.
+        actual: usize,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Anomaly::IdMismatch { key, actual } => write!(
+                f,
+                "Cache element {} has id {}",
+                key.display_hex(),
+                actual.display_hex()
+            ),
+            Anomaly::ByteSizeMismatch {
+                key,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "The byte size of cache element {} is recorded as {}, but it is {}",
+                key.display_hex(),
+                recorded,
+                actual
+            ),
+            Anomaly::LruByteSizeMismatch { recorded, actual } => write!(
+                f,
+                "The byte size of the LRU cache is recorded as {}, but it is {}",
+                recorded, actual
+            ),
+            Anomaly::PinnedIdMismatch { key, actual } => write!(
+                f,
+                "Pinned element {} has id {}",
+                key.display_hex(),
+                actual.display_hex()
+            ),
+            Anomaly::OrphanIdMismatch { key, actual } => write!(
+                f,
+                "Orphan {} has id {}",
+                key.display_hex(),
+                actual.display_hex()
+            ),
+            Anomaly::PinnedByteSizeMismatch { recorded, actual } => write!(
+                f,
+                "The byte size of the pinned elements is recorded as {}, but it is {}",
+                recorded, actual
+            ),
+        }
+    }
+}
+
+/// Verifies all the elements of the LRU cache, all the pinned elements, and all the orphans, and
+/// returns the anomalies.
+///
+/// This function does not change the LRU order, but it takes the lock of each shard while it is
+/// scanned.
+///
+/// Each anomaly is logged at the error level, and counted in [`anomaly_count`] of `environment` .
+///
+/// [`anomaly_count`]: self::anomaly_count
+pub fn verify(environment: &Environment) -> Vec<Anomaly> {
+    let mut ret = Vec::new();
+
+    for s in environment.shards.iter() {
+        let (entries, recorded) = s.audit();
+        let mut sum = 0;
+
+        for (key, acid, size) in entries {
+            if key != *acid.id() {
+                ret.push(Anomaly::IdMismatch {
+                    key,
+                    actual: *acid.id(),
+                });
+            }
+
+            let actual = shard::byte_size(&acid);
+            if size != actual {
+                ret.push(Anomaly::ByteSizeMismatch {
+                    key,
+                    recorded: size,
+                    actual,
+                });
+            }
+
+            sum += size;
+        }
+
+        if recorded != sum {
+            ret.push(Anomaly::LruByteSizeMismatch {
+                recorded,
+                actual: sum,
+            });
+        }
+    }
+
+    {
+        let pins = environment.pins.lock().unwrap();
+        let (mismatches, actual) = pins.verify();
+        for (key, actual) in mismatches {
+            ret.push(Anomaly::PinnedIdMismatch { key, actual });
+        }
+
+        let recorded = pins.byte_size();
+        if recorded != actual {
+            ret.push(Anomaly::PinnedByteSizeMismatch { recorded, actual });
+        }
+    }
+
+    {
+        let orphans = environment.orphans.lock().unwrap();
+        for (key, actual) in orphans.verify() {
+            ret.push(Anomaly::OrphanIdMismatch { key, actual });
+        }
+    }
+
+    for anomaly in ret.iter() {
+        error!("Cache integrity check: {}", anomaly);
+    }
+    let anomalies = &environment.integrity.anomalies;
+    anomalies.fetch_add(ret.len(), Ordering::Relaxed);
+
+    ret
+}
+
+/// Returns the total number of the anomalies that [`verify`] has found in `environment` .
+///
+/// [`verify`]: self::verify
+pub fn anomaly_count(environment: &Environment) -> usize {
+    environment.integrity.anomalies.load(Ordering::Relaxed)
+}
+
+/// Returns [`Check::degraded`] if [`verify`] has found any anomaly in `environment` ; otherwise
+/// returns [`Check::ok`] .
+///
+/// [`Check::degraded`]: crate::health::Check::degraded
+/// [`Check::ok`]: crate::health::Check::ok
+/// [`verify`]: self::verify
+pub fn health(environment: &Environment) -> Check {
+    match anomaly_count(environment) {
+        0 => Check::ok(),
+        n => Check::degraded(format!("{} cache anomalies are found.", n)),
+    }
+}
+
+/// Called every time an element is inserted into the cache.
+#[cfg(feature = "cache_self_check")]
+pub(crate) fn on_insert(environment: &Environment) {
+    let insertions = &environment.integrity.insertions;
+    if insertions.fetch_add(1, Ordering::Relaxed) % SELF_CHECK_INTERVAL == 0 {
+        verify(environment);
+    }
+}
+
+#[cfg(not(feature = "cache_self_check"))]
+#[inline]
+pub(crate) fn on_insert(_environment: &Environment) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{insert, pin};
+    use crate::data_types::CAcid;
+    use crate::health::Status;
+    use crate::stub::Blob;
+    use crate::ModuleEnvironment;
+
+    #[test]
+    fn verify_consistent_cache() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes: &[u8] = &[1];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+        insert(acid, &environment).unwrap();
+        assert_eq!(true, pin(&id, &environment));
+
+        assert_eq!(Vec::<Anomaly>::new(), verify(&environment));
+        assert_eq!(0, anomaly_count(&environment));
+        assert_eq!(Check::ok(), health(&environment));
+    }
+
+    #[test]
+    fn verify_id_mismatch() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes: &[u8] = &[1];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+        insert(acid.clone(), &environment).unwrap();
+
+        // Overwrite the stored element with another id as a buggy merge could do.
+        let bytes: &[u8] = &[2; 16];
+        let other = CAcid::from(Blob::from(bytes));
+        let op = |element: &mut CAcid, _val: CAcid| *element = other.clone();
+        unsafe { environment.shard(&id).insert_with(acid, op, false) };
+
+        let anomalies = verify(&environment);
+        assert_eq!(
+            true,
+            anomalies.contains(&Anomaly::IdMismatch {
+                key: id,
+                actual: *other.id()
+            })
+        );
+        assert_eq!(anomalies.len(), anomaly_count(&environment));
+        assert_eq!(Status::Degraded, health(&environment).status);
+    }
+}
@@ -26,20 +26,43 @@
 //
 // //////////////////////////////////////
 
-use crate::data_types::{Acid, CAcid, CMmapAlloc, Id, Resource};
-use crate::{Config, ModuleEnvironment};
+mod accounting;
+pub mod integrity;
+mod orphans;
+mod pins;
+mod policy;
+mod shard;
+
+use crate::data_types::{Acid, CAcid, Id, Resource};
+use crate::{byte_size, profile, Config, ModuleEnvironment};
 use clap::{App, Arg};
 use core::any::TypeId;
+use core::cell::Cell;
 use core::mem::size_of;
 use core::result::Result;
-use mouse_containers::lru_hash_set::LruHashSet;
+use shard::Shard;
 use spin_sync::Mutex8;
-use std::borrow::Cow;
-use std::collections::hash_map::RandomState;
+use std::borrow::{Borrow, Cow};
+use std::collections::BTreeMap;
 use std::error::Error;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub use orphans::{add_orphan, orphan_count, remove_orphan, resolve_orphans};
+pub use pins::{is_pinned, pin, pinned_byte_size, unpin};
+pub use policy::{EvictionPolicy, Lru, SegmentedLru, SizeWeightedLru};
 
-/// 64 MB.
-const DEFAULT_SIZE_SOFT_LIMIT: &'static str = "67108864";
+const DEFAULT_SIZE_SOFT_LIMIT: &'static str = "64MiB";
+const DEFAULT_ORPHAN_SIZE_LIMIT: &'static str = "8MiB";
+const DEFAULT_SHARDS: &'static str = "16";
+const DEFAULT_POLICY: &'static str = "lru";
+
+fn new_shards(count: usize, byte_size: &Arc<AtomicUsize>) -> Vec<Shard> {
+    (0..count)
+        .map(|_| Shard::new(accounting::CountingAlloc::new(byte_size.clone())))
+        .collect()
+}
 
 /// `Environment` implements `ModuleEnvironment` for this module.
 ///
@@ -48,126 +71,344 @@ const DEFAULT_SIZE_SOFT_LIMIT: &'static str = "67108864";
 /// `Environment` requests the following arguments.
 ///
 /// - --cache-size-soft-limit
+/// - --cache-size-hard-limit
+/// - --cache-warmup-count
+/// - --cache-orphan-size-limit
+/// - --cache-shards
+/// - --cache-policy
 ///
 /// # Default
 ///
 /// The `Default` implementation assumes the following arguments.
 ///
-/// - --cache-size-soft-limit: 67108864 (= 64 MB)
+/// - --cache-size-soft-limit: 64MiB (= 67108864 bytes)
+/// - --cache-size-hard-limit: (not specified; i.e. no hard limit)
+/// - --cache-warmup-count: 0
+/// - --cache-orphan-size-limit: 8MiB (= 8388608 bytes)
+/// - --cache-shards: 16
+/// - --cache-policy: lru
+///
+/// # Sharding
+///
+/// The LRU cache is split into '--cache-shards' independent LRU sets to reduce the lock
+/// contention. Each element belongs to the shard selected by the first bytes of the [`Id`] .
+/// The LRU order is kept per shard; the expiration visits the shards in turn, so each shard holds
+/// about '--cache-size-soft-limit' / '--cache-shards' bytes.
+///
+/// # Eviction policy
+///
+/// '--cache-policy' selects the [`EvictionPolicy`] applied in [`insert`] and [`expire`] ; 'lru'
+/// ( [`Lru`] ,) 'size-weighted-lru' ( [`SizeWeightedLru`] ,) or 'segmented-lru'
+/// ( [`SegmentedLru`] .) Method [`set_eviction_policy`] plugs in another one.
+///
+/// [`Id`]: crate::data_types::Id
+/// [`EvictionPolicy`]: self::EvictionPolicy
+/// [`Lru`]: self::Lru
+/// [`SizeWeightedLru`]: self::SizeWeightedLru
+/// [`SegmentedLru`]: self::SegmentedLru
+/// [`insert`]: self::insert
+/// [`expire`]: self::expire
+/// [`set_eviction_policy`]: Self::set_eviction_policy
+///
+/// # Byte size accounting
+///
+/// Method [`using_byte_size`] returns the byte size that `self` uses; i.e. the memory that `self`
+/// allocates for the LRU cache, and the byte size of the acids in the LRU cache, the pinned and
+/// the protected elements, and the orphans. The limits are compared with it, so the other
+/// instances of `Environment` in the same process do not affect the expiration. Function
+/// [`cache_using_byte_size`] returns the byte size of the whole process.
+///
+/// The byte size of each acid is estimated from the length of the intrinsic and the extrinsic
+/// data when it is inserted. An acid both in the LRU cache and pinned (or protected) is counted
+/// twice, so the estimate does not fall below the real usage.
+///
+/// [`cache_using_byte_size`]: self::cache_using_byte_size
+/// [`using_byte_size`]: Self::using_byte_size
 pub struct Environment {
     size_soft_limit: usize,
-    cache: LruHashSet<CAcid, CMmapAlloc, RandomState>,
+    size_hard_limit: Option<usize>,
+    warmup_count: u32,
+    orphan_size_limit: usize,
+    shards: Vec<Shard>,
+    expire_cursor: AtomicUsize,
+    byte_size: Arc<AtomicUsize>,
+    integrity: integrity::Counters,
+    orphans: Mutex<orphans::OrphanPool>,
+    pins: Mutex<pins::PinSet>,
+    policy: Box<dyn EvictionPolicy>,
+    protected: Mutex<policy::Protected>,
 }
 
 impl Default for Environment {
     fn default() -> Environment {
+        let byte_size = Arc::new(AtomicUsize::new(0));
+
         Self {
-            size_soft_limit: DEFAULT_SIZE_SOFT_LIMIT.parse().unwrap(),
-            cache: LruHashSet::new(CMmapAlloc::default(), RandomState::new()),
+            size_soft_limit: byte_size::parse(DEFAULT_SIZE_SOFT_LIMIT).unwrap(),
+            size_hard_limit: None,
+            warmup_count: 0,
+            orphan_size_limit: byte_size::parse(DEFAULT_ORPHAN_SIZE_LIMIT).unwrap(),
+            shards: new_shards(DEFAULT_SHARDS.parse().unwrap(), &byte_size),
+            expire_cursor: AtomicUsize::new(0),
+            byte_size,
+            integrity: Default::default(),
+            orphans: Default::default(),
+            pins: Default::default(),
+            policy: Box::new(Lru),
+            protected: Default::default(),
         }
     }
 }
 
 impl ModuleEnvironment for Environment {
     fn args(app: App<'static, 'static>) -> App<'static, 'static> {
-        app.arg(
+        app.args(&[
             Arg::with_name("cache_size_soft_limit")
                 .help(
                     "The soft limit of cache byte size.
-The LRU cache is expired when the total cache size exceeds this value.",
+The LRU cache is expired when the total cache size exceeds this value.
+Units KB, MB, GB, TB, KiB, MiB, GiB and TiB are accepted. (e.g. '64MB', '1GiB')",
                 )
                 .long("--cache-size-soft-limit")
                 .default_value(DEFAULT_SIZE_SOFT_LIMIT)
                 .takes_value(true),
-        )
+            Arg::with_name("cache_size_hard_limit")
+                .help(
+                    "The hard limit of cache byte size.
+Inserting into the cache fails if the total cache size exceeds this value even after expiring
+the LRU cache down to the soft limit. (No hard limit by default.)
+Units are accepted as well as '--cache-size-soft-limit'.",
+                )
+                .long("--cache-size-hard-limit")
+                .takes_value(true),
+            Arg::with_name("cache_warmup_count")
+                .help("The number of the most recent blocks to be cached on startup.")
+                .long("--cache-warmup-count")
+                .default_value("0")
+                .takes_value(true),
+            Arg::with_name("cache_orphan_size_limit")
+                .help(
+                    "The limit of the byte size that the orphan pool holds.
+The oldest orphan is evicted when the orphan pool exceeds this value.
+Units are accepted as well as '--cache-size-soft-limit'.",
+                )
+                .long("--cache-orphan-size-limit")
+                .default_value(DEFAULT_ORPHAN_SIZE_LIMIT)
+                .takes_value(true),
+            Arg::with_name("cache_shards")
+                .help(
+                    "The number of the independent LRU sets that the cache is split into.
+'--cache-size-soft-limit' is divided across them.",
+                )
+                .long("--cache-shards")
+                .default_value(DEFAULT_SHARDS)
+                .takes_value(true),
+            Arg::with_name("cache_policy")
+                .help(
+                    "The eviction policy of the cache.
+'size-weighted-lru' holds large elements longer, and 'segmented-lru' holds the elements
+accessed twice or more longer than the others.",
+                )
+                .possible_values(&["lru", "size-weighted-lru", "segmented-lru"])
+                .long("--cache-policy")
+                .default_value(DEFAULT_POLICY)
+                .takes_value(true),
+        ])
     }
 
-    unsafe fn check(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
         let size_soft_limit = config.args().value_of("cache_size_soft_limit").unwrap();
-        self.size_soft_limit = size_soft_limit.parse().map_err(|e| {
+        self.size_soft_limit = byte_size::parse(size_soft_limit).map_err(|e| {
             let msg = format!("Failed to parse '--cache-size-soft-limit': {}", e);
-            Box::<dyn Error>::from(msg)
+            crate::Error::Config(msg)
+        })?;
+
+        if let Some(size_hard_limit) = config.args().value_of("cache_size_hard_limit") {
+            let size_hard_limit = byte_size::parse(size_hard_limit).map_err(|e| {
+                let msg = format!("Failed to parse '--cache-size-hard-limit': {}", e);
+                crate::Error::Config(msg)
+            })?;
+
+            if size_hard_limit < self.size_soft_limit {
+                let msg =
+                    "'--cache-size-hard-limit' must not be less than '--cache-size-soft-limit'";
+                return Err(crate::Error::Config(String::from(msg)));
+            }
+
+            self.size_hard_limit = Some(size_hard_limit);
+        }
+
+        let warmup_count = config.args().value_of("cache_warmup_count").unwrap();
+        self.warmup_count = warmup_count.parse().map_err(|e| {
+            let msg = format!("Failed to parse '--cache-warmup-count': {}", e);
+            crate::Error::Config(msg)
+        })?;
+
+        let orphan_size_limit = config.args().value_of("cache_orphan_size_limit").unwrap();
+        self.orphan_size_limit = byte_size::parse(orphan_size_limit).map_err(|e| {
+            let msg = format!("Failed to parse '--cache-orphan-size-limit': {}", e);
+            crate::Error::Config(msg)
+        })?;
+
+        let shards = config.args().value_of("cache_shards").unwrap();
+        let shards: usize = shards.parse().map_err(|e| {
+            let msg = format!("Failed to parse '--cache-shards': {}", e);
+            crate::Error::Config(msg)
+        })?;
+        if shards == 0 {
+            let msg = "'--cache-shards' must be greater than 0";
+            return Err(crate::Error::Config(String::from(msg)));
+        }
+        if shards != self.shards.len() {
+            self.shards = new_shards(shards, &self.byte_size);
+        }
+
+        let policy = config.args().value_of("cache_policy").unwrap();
+        self.policy = policy::by_name(policy).ok_or_else(|| {
+            let msg = format!("Bad parameter for '--cache-policy': {}", policy);
+            crate::Error::Config(msg)
         })?;
 
         Ok(())
     }
 
-    unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
         // Use about 1/128 bytes of '--cache-size-soft-limit' for bucket chain.
         // 8 buckets consumes '8 * size_of::<raw pointer>() + 1 * size_of::<Mutex8>()' bytes.
+        // The soft limit is divided across the shards.
         let bucket8_size = 8 * size_of::<*mut u8>() + size_of::<Mutex8>();
-        let chain_len = self.size_soft_limit / 128 * 8 / bucket8_size;
+        let shard_soft_limit = self.size_soft_limit / self.shards.len();
+        let chain_len = shard_soft_limit / 128 * 8 / bucket8_size;
 
         // 'chain_len' must be greater than 0 (excluding 0), and (I think) it should not be a round
         // value.
         let chain_len = chain_len + 1;
-        self.cache.init(chain_len);
+        for shard in self.shards.iter_mut() {
+            shard.init(chain_len);
+        }
 
         Ok(())
     }
 }
 
-/// `NotFound` represents the data is not found in KVS.
+impl Environment {
+    /// Returns the number of the blocks to be cached on startup. ('--cache-warmup-count')
+    ///
+    /// The cache module is independent from the KVS and the RDB, so `GlobalEnvironment` warms up
+    /// the cache after they are initialized.
+    pub fn warmup_count(&self) -> u32 {
+        self.warmup_count
+    }
+
+    /// Returns the number of the LRU sets that the cache is split into. ('--cache-shards')
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the byte size that `self` uses.
+    ///
+    /// Unlike function [`cache_using_byte_size`] , the other instances of `Environment` do not
+    /// affect the returned value. The byte size of the acids is estimated from the length of the
+    /// intrinsic and the extrinsic data. (See also [`Environment`] .)
+    ///
+    /// [`cache_using_byte_size`]: self::cache_using_byte_size
+    /// [`Environment`]: self::Environment
+    pub fn using_byte_size(&self) -> usize {
+        let allocated = self.byte_size.load(Ordering::Relaxed);
+        let cached: usize = self.shards.iter().map(Shard::byte_size).sum();
+        let pinned = self.pins.lock().unwrap().byte_size();
+        let protected = self.protected.lock().unwrap().byte_size();
+        let orphans = self.orphans.lock().unwrap().byte_size();
+        allocated + cached + pinned + protected + orphans
+    }
+
+    /// Replaces the eviction policy. ('--cache-policy')
+    ///
+    /// Call this method before the cache is used.
+    pub fn set_eviction_policy(&mut self, policy: Box<dyn EvictionPolicy>) {
+        self.policy = policy;
+    }
+
+    /// Returns the shard that the element with `id` belongs to.
+    fn shard(&self, id: &Id) -> &Shard {
+        let bytes = id.as_ref();
+        let prefix = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        &self.shards[prefix as usize % self.shards.len()]
+    }
+
+    /// Expires the LRU element of the shards in turn, and returns `true` if something is cached;
+    /// otherwise returns `false` .
+    fn expire_next(&self) -> bool {
+        let len = self.shards.len();
+        let start = self.expire_cursor.fetch_add(1, Ordering::Relaxed) % len;
+        (0..len).any(|i| unsafe { self.shards[(start + i) % len].expire() })
+    }
+}
+
+/// `Placeholder` is stored in the cache instead of a real [`Acid`] .
+///
+/// - `NotFound` represents the data is not found in KVS.
+/// - `Invalidated` represents the cache element was invalidated. It is regarded as not cached.
 ///
-/// `NotFound` implements [`Acid`] , but all the methods except for `id` and `type_id` causes a
+/// `Placeholder` implements [`Acid`] , but all the methods except for `id` and `type_id` causes a
 /// panic.
 ///
 /// [`Acid`]: crate::data_types::Acid
-struct NotFound(Id);
-
-impl From<Id> for NotFound {
-    fn from(id: Id) -> Self {
-        Self(id)
-    }
+enum Placeholder {
+    NotFound(Id),
+    Invalidated(Id),
 }
 
-impl Acid for NotFound {
+impl Acid for Placeholder {
     fn id(&self) -> &Id {
-        &self.0
+        match self {
+            Placeholder::NotFound(id) => id,
+            Placeholder::Invalidated(id) => id,
+        }
     }
 
     fn intrinsic(&self) -> Cow<[u8]> {
-        panic!("Method 'NotFound.intrinsic' is called.");
+        panic!("Method 'Placeholder.intrinsic' is called.");
     }
 
     fn extrinsic(&self) -> Cow<[u8]> {
-        panic!("Method 'NotFound.extrinsic' is called.");
+        panic!("Method 'Placeholder.extrinsic' is called.");
     }
 
     fn parent_count(&self) -> usize {
-        panic!("Method 'NotFound.parent_count' is called.");
+        panic!("Method 'Placeholder.parent_count' is called.");
     }
 
     fn parent(&self, _index: usize) -> Option<Id> {
-        panic!("Method 'NotFound.parent' is called.");
+        panic!("Method 'Placeholder.parent' is called.");
     }
 
     fn resource_count(&self) -> usize {
-        panic!("Method 'NotFound.resource_count' is called");
+        panic!("Method 'Placeholder.resource_count' is called");
     }
 
     fn resource(&self, _index: usize) -> Option<Resource> {
-        panic!("Method 'NotFound.resource' is called.");
+        panic!("Method 'Placeholder.resource' is called.");
     }
 
     fn is_traceable(&self) -> bool {
-        panic!("Method 'NotFound.is_traceable' is called.");
+        panic!("Method 'Placeholder.is_traceable' is called.");
     }
 
     fn set_traceable(&self) -> bool {
-        panic!("Method 'Notfound.set_traceable' is called.");
+        panic!("Method 'Placeholder.set_traceable' is called.");
     }
 
     fn is_invalid(&self) -> bool {
-        panic!("Method 'NotFound.is_invalid' is called.");
+        panic!("Method 'Placeholder.is_invalid' is called.");
     }
 
     fn invalid_reason(&self) -> Option<&dyn Error> {
-        panic!("Method 'NotFound.invalid_reason' is called.");
+        panic!("Method 'Placeholder.invalid_reason' is called.");
     }
 
     unsafe fn merge(&self, _other: &dyn Acid) -> bool {
-        panic!("Method 'NotFound.merge' is called.");
+        panic!("Method 'Placeholder.merge' is called.");
     }
 
     fn type_id(&self) -> TypeId {
@@ -188,10 +429,26 @@ pub enum CacheFindResult {
 }
 
 fn is_not_found(val: &CAcid) -> bool {
-    val.downcast::<NotFound>().is_some()
+    match val.downcast::<Placeholder>() {
+        Some(Placeholder::NotFound(_)) => true,
+        _ => false,
+    }
+}
+
+fn is_invalidated(val: &CAcid) -> bool {
+    match val.downcast::<Placeholder>() {
+        Some(Placeholder::Invalidated(_)) => true,
+        _ => false,
+    }
 }
 
 /// Returns the byte size that the cache system is using.
+///
+/// The returned value is shared by all the instances of [`Environment`] in the process. (See also
+/// method [`Environment::using_byte_size`] .)
+///
+/// [`Environment`]: self::Environment
+/// [`Environment::using_byte_size`]: self::Environment::using_byte_size
 pub fn cache_using_byte_size() -> usize {
     mouse_cache_alloc::cache_size()
 }
@@ -210,18 +467,28 @@ pub fn decrease_cache_using_size(bytes: usize) -> usize {
 ///
 /// The found cache element will be regarded as the 'Most Recently Used (MRU)'.
 pub fn find(id: &Id, environment: &Environment) -> CacheFindResult {
-    match unsafe { environment.cache.get(id) } {
-        None => CacheFindResult::Lost,
-        Some(entry) => {
-            entry.to_mru();
+    if let Some(acid) = held(id, environment) {
+        return CacheFindResult::Hit(acid);
+    }
 
-            if is_not_found(&*entry) {
+    // Make sure to drop the entry before locking the protected segment.
+    let ret = match unsafe { environment.shard(id).get(id, true) } {
+        None => CacheFindResult::Lost,
+        Some(acid) => {
+            if is_not_found(&acid) {
                 CacheFindResult::Fault
+            } else if is_invalidated(&acid) {
+                CacheFindResult::Lost
             } else {
-                CacheFindResult::Hit(entry.clone())
+                CacheFindResult::Hit(acid)
             }
         }
+    };
+
+    if let CacheFindResult::Hit(acid) = &ret {
+        protect(acid, true, environment);
     }
+    ret
 }
 
 /// Inserts `val` into the cache if not cached yet; otherwise merges the information into the
@@ -229,60 +496,161 @@ pub fn find(id: &Id, environment: &Environment) -> CacheFindResult {
 ///
 /// Inserted `val` or current cache element will be regarded as the 'Most Recently Used (MRU)'
 /// anyway.
-pub fn insert(val: CAcid, environment: &Environment) {
+///
+/// If the cache element is traceable after the insertion, the orphans waiting for it are
+/// promoted recursively, and the promoted acids are returned. (See also [`add_orphan`] .)
+///
+/// # Errors
+///
+/// If the caching size plus the size of `val` exceeds '--cache-size-hard-limit', this function
+/// expires the LRU cache synchronously down to '--cache-size-soft-limit' before the insertion.
+/// Then, if it still exceeds the hard limit (e.g. because other threads are using the expired
+/// elements, or because `val` is too large,) this function returns an error without inserting
+/// `val` .
+///
+/// [`add_orphan`]: self::add_orphan
+pub fn insert(val: CAcid, environment: &Environment) -> Result<Vec<CAcid>, Box<dyn Error>> {
     debug_assert_eq!(false, is_not_found(&val));
+    debug_assert_eq!(false, is_invalidated(&val));
+
+    let _profile = profile::scope("cache_insert");
+    check_hard_limit(shard::byte_size(&val), environment)?;
+
+    // If pinned or protected, merge the information into the held element, and make the LRU
+    // cache share it.
+    let (val, is_held) = match held(val.id(), environment) {
+        None => (val, false),
+        Some(held) => {
+            unsafe { held.merge(&*val) };
+            (held, true)
+        }
+    };
 
     // Insert into the cache.
     let op = |element: &mut CAcid, val: CAcid| {
-        if is_not_found(element) {
-            // If element represents 'Not found', replace it.
+        if is_held || is_not_found(element) || is_invalidated(element) {
+            // If element represents 'Not found' or 'Invalidated', replace it.
             *element = val;
         } else {
             // Merge the information.
             unsafe { element.merge(&*val) };
         }
     };
-    match unsafe { environment.cache.insert_with(val, op) } {
-        (Some(_), entry) => {
-            // The same id element exists.
-            // Update the LRU order.
-            entry.to_mru();
+    // If the same id element exists, update the LRU order; otherwise `val` is added as an MRU
+    // element.
+    let (is_hit, acid) = unsafe { environment.shard(val.id()).insert_with(val, op, true) };
+    let (id, is_traceable) = (*acid.id(), acid.is_traceable());
+
+    if !is_held {
+        protect(&acid, is_hit, environment);
+    }
+    drop(acid);
+
+    expire_to_soft_limit(environment);
+    integrity::on_insert(environment);
+
+    if is_traceable {
+        Ok(resolve_orphans(&id, environment))
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Expires the LRU cache down to the soft limit if the caching size would exceed the hard limit
+/// after `incoming` bytes are added, and returns an error if it still would exceed the hard limit.
+///
+/// The caching size is [`Environment::using_byte_size`] , which includes the acids in the LRU
+/// cache estimated in the same way as `incoming` .
+///
+/// [`Environment::using_byte_size`]: self::Environment::using_byte_size
+fn check_hard_limit(incoming: usize, environment: &Environment) -> Result<(), Box<dyn Error>> {
+    if let Some(size_hard_limit) = environment.size_hard_limit {
+        let exceeds = || size_hard_limit < environment.using_byte_size().saturating_add(incoming);
+        if exceeds() {
+            expire_to_soft_limit(environment);
         }
-        _ => {
-            // `val` is inserted newly.
-            // Do nothing because it is added as an MRU element.
+        if exceeds() {
+            let msg = format!(
+                "Cache byte size would exceed '--cache-size-hard-limit' ({}) by inserting {} bytes",
+                size_hard_limit, incoming
+            );
+            return Err(Box::from(msg));
         }
     }
 
-    // Expire the LRU cache if the caching size exceeds the soft limit.
-    while environment.size_soft_limit < cache_using_byte_size() {
-        if !unsafe { environment.cache.expire() } {
+    Ok(())
+}
+
+/// Expires the LRU cache while the caching size (i.e. [`Environment::using_byte_size`] ) exceeds
+/// the soft limit.
+///
+/// The pinned elements are excluded from the caching size.
+///
+/// [`Environment::using_byte_size`]: self::Environment::using_byte_size
+fn expire_to_soft_limit(environment: &Environment) {
+    {
+        // The protected segment uses at most half of the soft limit.
+        let mut protected = environment.protected.lock().unwrap();
+        while environment.size_soft_limit / 2 < protected.byte_size() {
+            protected.evict();
+        }
+    }
+
+    let pinned_byte_size = pinned_byte_size(environment);
+    let exceeds = || {
+        let using_byte_size = environment.using_byte_size();
+        environment.size_soft_limit < using_byte_size.saturating_sub(pinned_byte_size)
+    };
+    while exceeds() {
+        if !expire(environment) {
             break;
         }
     }
 }
 
+/// Returns the pinned or the protected element with `id` if any, or `None` .
+///
+/// The priority of the protected element is updated.
+fn held(id: &Id, environment: &Environment) -> Option<CAcid> {
+    {
+        let pins = environment.pins.lock().unwrap();
+        if let Some(acid) = pins.get(id) {
+            return Some(acid);
+        }
+    }
+
+    let mut protected = environment.protected.lock().unwrap();
+    let acid = protected.get(id)?;
+    protected.touch(id, environment.policy.weight(&acid));
+    Some(acid)
+}
+
+/// Holds `acid` in the protected segment if the eviction policy admits it.
+fn protect(acid: &CAcid, is_hit: bool, environment: &Environment) {
+    if environment.policy.admit(acid, is_hit) {
+        let weight = environment.policy.weight(acid);
+        let mut protected = environment.protected.lock().unwrap();
+        protected.insert(acid.clone(), weight);
+    }
+}
+
 /// Caches that the DataBase query failed to find the data with `id` .
 pub fn not_found(id: Id, environment: &Environment) {
-    let val = CAcid::from(NotFound::from(id));
+    let val = CAcid::from(Placeholder::NotFound(id));
 
-    // Do nothing if already cached.
+    // Do nothing if already cached except for 'Invalidated'.
     // (Do not update the LRU order.)
-    let op = |_element: &mut CAcid, _: CAcid| {};
-    match unsafe { environment.cache.insert_with(val, op) } {
-        (None, entry) => {
+    let op = |element: &mut CAcid, val: CAcid| {
+        if is_invalidated(element) {
+            *element = val;
+        }
+    };
+    match unsafe { environment.shard(val.id()).insert_with(val, op, false) } {
+        (false, _) => {
             // 'val' is inserted newly.
             // The cache size could be enlarged.
-
-            // Make sure to drop 'entry' to help a dead lock.
-            drop(entry);
-
             // Expire the LRU cache if the caching size exceeds the soft limit.
-            while environment.size_soft_limit < cache_using_byte_size() {
-                if !unsafe { environment.cache.expire() } {
-                    break;
-                }
-            }
+            expire_to_soft_limit(environment);
         }
         _ => {
             // Nothing is changed.
@@ -290,9 +658,157 @@ pub fn not_found(id: Id, environment: &Environment) {
     }
 }
 
+/// Overwrites the cache element whose id equals to `val.id()` with `val` , and returns `true` if
+/// such an element was cached; otherwise inserts `val` and returns `false` .
+///
+/// Unlike [`insert`] , this function does not merge the information into the current cache
+/// element but discards it. This function is used after the acid is replaced, for example.
+///
+/// `val` will be regarded as the 'Most Recently Used (MRU)'; the LRU order of the other elements
+/// is not changed.
+///
+/// Unlike [`insert`] , this function does not promote the orphans waiting for `val` .
+///
+/// If the element is pinned or protected, the held element is overwritten as well, and `val` is
+/// held instead.
+///
+/// # Errors
+///
+/// Returns an error without replacing if the caching size plus the size of `val` exceeds
+/// '--cache-size-hard-limit' as [`insert`] does.
+///
+/// [`insert`]: self::insert
+pub fn replace(val: CAcid, environment: &Environment) -> Result<bool, Box<dyn Error>> {
+    debug_assert_eq!(false, is_not_found(&val));
+    debug_assert_eq!(false, is_invalidated(&val));
+
+    check_hard_limit(shard::byte_size(&val), environment)?;
+
+    let ret = {
+        let mut pins = environment.pins.lock().unwrap();
+        match pins.get(val.id()) {
+            None => Cell::new(false),
+            Some(_) => {
+                pins.insert(val.clone());
+                Cell::new(true)
+            }
+        }
+    };
+    {
+        let mut protected = environment.protected.lock().unwrap();
+        if protected.get(val.id()).is_some() {
+            protected.insert(val.clone(), environment.policy.weight(&val));
+            ret.set(true);
+        }
+    }
+    {
+        let op = |element: &mut CAcid, val: CAcid| {
+            if !is_not_found(element) && !is_invalidated(element) {
+                ret.set(true);
+            }
+            *element = val;
+        };
+        unsafe { environment.shard(val.id()).insert_with(val, op, true) };
+    }
+
+    expire_to_soft_limit(environment);
+    Ok(ret.get())
+}
+
+/// Removes the cache element with `id` , and returns `true` if it was cached; otherwise does
+/// nothing and returns `false` .
+///
+/// After this function is called, the element is regarded as not cached, i.e. [`find`] returns
+/// `Lost` . The LRU order of the other elements is not changed.
+///
+/// Unlike [`invalidate`] , the element is removed from the LRU cache; no placeholder is left.
+///
+/// 'Not found' is regarded as cached. (See [`not_found`] .)
+/// The element is unpinned if pinned, and removed from the protected segment of the eviction
+/// policy.
+///
+/// [`find`]: self::find
+/// [`invalidate`]: self::invalidate
+/// [`not_found`]: self::not_found
+pub fn remove(id: &Id, environment: &Environment) -> bool {
+    let was_pinned = unpin(id, environment);
+    let was_protected = environment.protected.lock().unwrap().remove(id);
+
+    let was_cached = match unsafe { environment.shard(id).remove(id) } {
+        None => false,
+        Some(element) => !is_invalidated(&element),
+    };
+
+    was_pinned || was_protected || was_cached
+}
+
+/// Invalidates the cache element of each id in `ids` if cached, and returns the number of the
+/// invalidated elements.
+///
+/// Invalidated element is regarded as not cached, i.e. [`find`] returns `Lost` , until [`insert`]
+/// or [`not_found`] is called with the same id.
+/// The LRU order of the cache elements is not changed.
+///
+/// This function is used to drop the cache elements that a reorg made stale, for example.
+///
+/// The invalidated elements are unpinned if pinned, and removed from the protected segment of
+/// the eviction policy.
+///
+/// The placeholder is cached even if the element was not cached, so that the acid being fetched
+/// by another thread is not cached as valid. (See also [`remove`] .)
+///
+/// [`remove`]: self::remove
+/// [`find`]: self::find
+/// [`insert`]: self::insert
+/// [`not_found`]: self::not_found
+pub fn invalidate<I, A>(ids: I, environment: &Environment) -> usize
+where
+    I: Iterator<Item = A>,
+    A: Borrow<Id>,
+{
+    let mut ret = 0;
+
+    for id in ids {
+        let id = id.borrow();
+
+        let was_pinned = unpin(id, environment);
+        let was_protected = environment.protected.lock().unwrap().remove(id);
+
+        // Check and overwrite the element under the lock of the entry; otherwise another thread
+        // could insert the element between them.
+        let is_cached = Cell::new(false);
+        let op = |element: &mut CAcid, val: CAcid| {
+            if !is_invalidated(element) {
+                is_cached.set(true);
+            }
+            *element = val;
+        };
+        let val = CAcid::from(Placeholder::Invalidated(*id));
+        let (was_stored, _) = unsafe { environment.shard(id).insert_with(val, op, false) };
+        let is_inserted = !was_stored;
+        if is_inserted {
+            // The placeholder is inserted newly and the cache size could be enlarged.
+            expire_to_soft_limit(environment);
+        }
+        let is_cached = is_cached.get();
+
+        if was_pinned || was_protected || is_cached {
+            ret += 1;
+        }
+    }
+
+    ret
+}
+
 /// Expires the 'Least Recently Used (LRU)' cache element and returns `true` if something is
 /// cached; otherwise does nothing and returns `false` .
 ///
+/// The LRU order is kept per shard, and the shards are visited in turn; i.e. the expired element
+/// is the LRU one of the next shard that caches something. (See [`Environment`] .)
+///
+/// If the LRU cache is empty, the element with the least priority in the protected segment of the
+/// eviction policy is evicted instead.
+///
 /// # Warnings
 ///
 /// Cache memory is used for the following things.
@@ -303,8 +819,68 @@ pub fn not_found(id: Id, environment: &Environment) {
 /// - Cache elements that another thread is using.
 ///   (The cache element is really freed if it is expired and if all the threads finished to using
 ///   it.)
+///
+/// [`Environment`]: self::Environment
 pub fn expire(environment: &Environment) -> bool {
-    unsafe { environment.cache.expire() }
+    environment.expire_next() || environment.protected.lock().unwrap().evict()
+}
+
+/// Returns the id, the byte size, and whether it represents 'Not found' (See [`not_found`] .) of
+/// each cache element, ordered by the id.
+///
+/// The elements are the ones in the LRU cache, the pinned ones, and the ones in the protected
+/// segment of the eviction policy. The invalidated elements are not included because they are
+/// regarded as not cached. (See [`invalidate`] .)
+///
+/// This function does not change the LRU order nor the priority of the eviction policy.
+///
+/// The byte size is estimated from the length of the intrinsic and the extrinsic data when the
+/// element is inserted.
+///
+/// [`not_found`]: self::not_found
+/// [`invalidate`]: self::invalidate
+pub fn snapshot(environment: &Environment) -> Vec<(Id, usize, bool)> {
+    let mut entries = BTreeMap::new();
+
+    for shard in environment.shards.iter() {
+        for (id, acid, size) in shard.entries() {
+            if !is_invalidated(&acid) {
+                entries.insert(id, (size, is_not_found(&acid)));
+            }
+        }
+    }
+
+    // The pinned and the protected elements can be expired from the LRU cache.
+    let pinned = environment.pins.lock().unwrap().entries();
+    let protected = environment.protected.lock().unwrap().entries();
+    for (id, size) in pinned.into_iter().chain(protected) {
+        entries.entry(id).or_insert((size, false));
+    }
+
+    entries
+        .into_iter()
+        .map(|(id, (size, not_found))| (id, size, not_found))
+        .collect()
+}
+
+/// Writes [`snapshot`] into `writer` , and returns the number of the written elements.
+///
+/// Each element is written in a line of the hex id, the byte size, and "not_found" or "cached"
+/// separated by a tab.
+///
+/// [`snapshot`]: self::snapshot
+pub fn dump<W>(mut writer: W, environment: &Environment) -> io::Result<usize>
+where
+    W: io::Write,
+{
+    let entries = snapshot(environment);
+    for (id, size, not_found) in entries.iter() {
+        let state = if *not_found { "not_found" } else { "cached" };
+        writeln!(writer, "{}\t{}\t{}", id, size, state)?;
+    }
+
+    writer.flush()?;
+    Ok(entries.len())
 }
 
 /// `CacheState` is return value for function [`is_cached`] .
@@ -324,16 +900,330 @@ pub enum CacheState {
 /// If the element is cached (either `Cached` or `Fault` ,) the cache entry will be regarded as
 /// the 'Most Recently Used (MRU.)'
 pub fn is_cached(id: &Id, environment: &Environment) -> CacheState {
-    match unsafe { environment.cache.get(id) } {
-        None => CacheState::Lost,
-        Some(entry) => {
-            entry.to_mru();
+    match find(id, environment) {
+        CacheFindResult::Hit(_) => CacheState::Cached,
+        CacheFindResult::Lost => CacheState::Lost,
+        CacheFindResult::Fault => CacheState::Fault,
+    }
+}
 
-            if is_not_found(&*entry) {
-                CacheState::Fault
-            } else {
-                CacheState::Cached
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::Blob;
+
+    #[test]
+    fn snapshot_() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes: &[u8] = &[1, 2, 3];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+        let size = pins::byte_size(&acid);
+
+        assert_eq!(Vec::<(Id, usize, bool)>::new(), snapshot(&environment));
+
+        insert(acid, &environment).unwrap();
+        assert_eq!(vec![(id, size, false)], snapshot(&environment));
+
+        // The pinned element is included after it is expired from the LRU cache.
+        assert_eq!(true, pin(&id, &environment));
+        assert_eq!(true, expire(&environment));
+        assert_eq!(vec![(id, size, false)], snapshot(&environment));
+
+        let mut buffer = Vec::new();
+        assert_eq!(1, dump(&mut buffer, &environment).unwrap());
+        let expected = format!("{}\t{}\tcached\n", id, size);
+        assert_eq!(expected.as_bytes(), &buffer[..]);
+
+        // 'Not found' is included, but the invalidated one is not.
+        let bytes: &[u8] = &[4, 5, 6];
+        let other = *CAcid::from(Blob::from(bytes)).id();
+        not_found(other, &environment);
+        assert_eq!(1, invalidate(core::iter::once(&id), &environment));
+
+        let placeholder = CAcid::from(Placeholder::NotFound(other));
+        let size = shard::byte_size(&placeholder);
+        assert_eq!(vec![(other, size, true)], snapshot(&environment));
+    }
+
+    #[test]
+    fn replace_() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes: &[u8] = &[1, 2, 3];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+
+        // Not cached yet.
+        assert_eq!(false, replace(acid.clone(), &environment).unwrap());
+        assert_eq!(
+            true,
+            matches!(is_cached(&id, &environment), CacheState::Cached)
+        );
+
+        assert_eq!(true, replace(acid.clone(), &environment).unwrap());
+
+        // 'Not found' is overwritten, but is not regarded as cached.
+        let bytes: &[u8] = &[4, 5, 6];
+        let other = CAcid::from(Blob::from(bytes));
+        not_found(*other.id(), &environment);
+        assert_eq!(false, replace(other.clone(), &environment).unwrap());
+        match find(other.id(), &environment) {
+            CacheFindResult::Hit(found) => assert_eq!(true, found == other),
+            _ => panic!("The replaced acid is not found"),
+        }
+
+        // The pinned element is replaced as well.
+        assert_eq!(true, pin(&id, &environment));
+        assert_eq!(true, replace(acid.clone(), &environment).unwrap());
+        assert_eq!(true, pins::is_pinned(&id, &environment));
+    }
+
+    #[test]
+    fn remove_() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes: &[u8] = &[1, 2, 3];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+
+        assert_eq!(false, remove(&id, &environment));
+
+        insert(acid.clone(), &environment).unwrap();
+        assert_eq!(true, remove(&id, &environment));
+        assert_eq!(
+            true,
+            matches!(is_cached(&id, &environment), CacheState::Lost)
+        );
+        assert_eq!(false, remove(&id, &environment));
+
+        // 'Not found' is regarded as cached.
+        not_found(id, &environment);
+        assert_eq!(true, remove(&id, &environment));
+        assert_eq!(
+            true,
+            matches!(is_cached(&id, &environment), CacheState::Lost)
+        );
+
+        // The pinned element is unpinned.
+        insert(acid, &environment).unwrap();
+        assert_eq!(true, pin(&id, &environment));
+        assert_eq!(true, remove(&id, &environment));
+        assert_eq!(false, pins::is_pinned(&id, &environment));
+        assert_eq!(
+            true,
+            matches!(is_cached(&id, &environment), CacheState::Lost)
+        );
+
+        // The invalidated element is not regarded as cached.
+        let bytes: &[u8] = &[4, 5, 6];
+        let other = CAcid::from(Blob::from(bytes));
+        insert(other.clone(), &environment).unwrap();
+        assert_eq!(1, invalidate(core::iter::once(other.id()), &environment));
+        assert_eq!(false, remove(other.id(), &environment));
+    }
+
+    #[test]
+    fn invalidate_() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let acids: Vec<CAcid> = (0..3u8)
+            .map(|i| {
+                let bytes: &[u8] = &[i];
+                CAcid::from(Blob::from(bytes))
+            })
+            .collect();
+        let ids: Vec<Id> = acids.iter().map(|acid| *acid.id()).collect();
+
+        insert(acids[0].clone(), &environment).unwrap();
+        insert(acids[1].clone(), &environment).unwrap();
+        assert_eq!(true, pin(&ids[1], &environment));
+
+        // 'ids[2]' is not cached.
+        assert_eq!(2, invalidate(ids.iter(), &environment));
+        for id in ids.iter() {
+            assert_eq!(
+                true,
+                matches!(is_cached(id, &environment), CacheState::Lost)
+            );
+        }
+        assert_eq!(false, pins::is_pinned(&ids[1], &environment));
+
+        // Invalidating twice does nothing.
+        assert_eq!(0, invalidate(ids.iter(), &environment));
+
+        // Inserting again overwrites the placeholder.
+        insert(acids[0].clone(), &environment).unwrap();
+        assert_eq!(
+            true,
+            matches!(is_cached(&ids[0], &environment), CacheState::Cached)
+        );
+        assert_eq!(1, invalidate(ids.iter(), &environment));
+    }
+
+    #[test]
+    fn invalidate_concurrently() {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+        let environment = Arc::new(environment);
+
+        let bytes: &[u8] = &[1, 2, 3];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+
+        for _ in 0..100 {
+            insert(acid.clone(), &environment).unwrap();
+
+            // Only one of the threads invalidates the element.
+            let barrier = Arc::new(Barrier::new(4));
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let environment = environment.clone();
+                    let barrier = barrier.clone();
+                    thread::spawn(move || {
+                        barrier.wait();
+                        invalidate(core::iter::once(&id), &environment)
+                    })
+                })
+                .collect();
+
+            let invalidated: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+            assert_eq!(1, invalidated);
+        }
+    }
+
+    #[test]
+    fn hard_limit() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+
+        let bytes = vec![0; 1024];
+        let acid = CAcid::from(Blob::from(&bytes[..]));
+        let size = pins::byte_size(&acid);
+
+        // Another instance does not affect the limit.
+        let mut other = Environment::default();
+        unsafe { other.init() }.unwrap();
+        for i in 0..16u8 {
+            let bytes: &[u8] = &[i; 1024];
+            insert(CAcid::from(Blob::from(bytes)), &other).unwrap();
+        }
+
+        environment.size_hard_limit = Some(environment.using_byte_size() + size - 1);
+        assert_eq!(true, insert(acid.clone(), &environment).is_err());
+        assert_eq!(true, replace(acid.clone(), &environment).is_err());
+
+        environment.size_hard_limit = Some(usize::MAX);
+        assert_eq!(true, insert(acid, &environment).is_ok());
+    }
+
+    #[test]
+    fn soft_limit() {
+        let mut environment = Environment::default();
+        environment.size_soft_limit = 16 * 1024;
+        unsafe { environment.init() }.unwrap();
+
+        // The acids are counted, so the LRU cache is expired though the nodes are small.
+        for i in 0..64u8 {
+            let bytes: &[u8] = &[i; 1024];
+            insert(CAcid::from(Blob::from(bytes)), &environment).unwrap();
+            assert_eq!(
+                true,
+                environment.using_byte_size() <= environment.size_soft_limit
+            );
         }
     }
+
+    #[test]
+    fn lru_byte_size() {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+        let lru_byte_size = |environment: &Environment| -> usize {
+            environment.shards.iter().map(Shard::byte_size).sum()
+        };
+
+        let bytes: &[u8] = &[1; 1024];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+        let size = pins::byte_size(&acid);
+
+        insert(acid.clone(), &environment).unwrap();
+        assert_eq!(size, lru_byte_size(&environment));
+
+        // Merging does not count twice.
+        insert(acid.clone(), &environment).unwrap();
+        assert_eq!(size, lru_byte_size(&environment));
+
+        // The placeholder is counted instead.
+        assert_eq!(1, invalidate(core::iter::once(&id), &environment));
+        let placeholder = CAcid::from(Placeholder::Invalidated(id));
+        assert_eq!(shard::byte_size(&placeholder), lru_byte_size(&environment));
+
+        assert_eq!(false, replace(acid.clone(), &environment).unwrap());
+        assert_eq!(size, lru_byte_size(&environment));
+
+        assert_eq!(true, remove(&id, &environment));
+        assert_eq!(0, lru_byte_size(&environment));
+
+        insert(acid, &environment).unwrap();
+        assert_eq!(true, expire(&environment));
+        assert_eq!(0, lru_byte_size(&environment));
+    }
+
+    #[test]
+    fn hard_limit_full_lru() {
+        let mut environment = Environment::default();
+        environment.size_soft_limit = 16 * 1024;
+        environment.size_hard_limit = Some(16 * 1024);
+        unsafe { environment.init() }.unwrap();
+
+        // Expiring down to the soft limit does not make room for another acid after the LRU cache
+        // is filled with the large acids.
+        let acids: Vec<CAcid> = (0..32u8)
+            .map(|i| {
+                let bytes: &[u8] = &[i; 1024];
+                CAcid::from(Blob::from(bytes))
+            })
+            .collect();
+        let rejected = acids
+            .iter()
+            .position(|acid| insert(acid.clone(), &environment).is_err());
+
+        assert_eq!(true, matches!(rejected, Some(i) if 0 < i && i < 16));
+        let size = shard::byte_size(&acids[0]);
+        assert_eq!(true, 16 * 1024 < environment.using_byte_size() + size);
+
+        environment.size_hard_limit = Some(32 * 1024);
+        assert_eq!(true, insert(acids[31].clone(), &environment).is_ok());
+    }
+
+    #[test]
+    fn using_byte_size() {
+        let mut a = Environment::default();
+        let mut b = Environment::default();
+        unsafe { a.init() }.unwrap();
+        unsafe { b.init() }.unwrap();
+
+        let a_before = a.using_byte_size();
+        let b_before = b.using_byte_size();
+
+        let bytes: &[u8] = &[1, 2, 3];
+        let acid = CAcid::from(Blob::from(bytes));
+        let id = *acid.id();
+        let size = pins::byte_size(&acid);
+
+        insert(acid, &a).unwrap();
+        assert_eq!(true, pin(&id, &a));
+
+        assert_eq!(true, a_before + size <= a.using_byte_size());
+        assert_eq!(b_before, b.using_byte_size());
+    }
 }
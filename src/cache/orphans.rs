@@ -0,0 +1,339 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `orphans` holds the acids waiting for the parents to be traceable.
+//!
+//! The orphan pool holds at most '--cache-orphan-size-limit' bytes. The oldest orphan is evicted
+//! when it is exceeded, so that a peer sending the acids with made-up parents cannot exhaust the
+//! memory.
+
+use super::{find, pins, CacheFindResult, Environment};
+use crate::data_types::{CAcid, CryptoHash, Id};
+use core::mem::size_of;
+use std::collections::{BTreeMap, HashMap};
+
+/// Returns the byte size that `acid` is regarded to consume as an orphan; i.e. the acid itself
+/// and the parent id and the child id in `waiting` for each parent.
+fn byte_size(acid: &CAcid) -> usize {
+    pins::byte_size(acid) + acid.parent_count() * (size_of::<Id>() + size_of::<Id>())
+}
+
+struct Orphan {
+    acid: CAcid,
+    /// The number of the missing parents.
+    missing: usize,
+    /// The order of the insertion.
+    seq: u64,
+    byte_size: usize,
+}
+
+/// `OrphanPool` indexes the orphan acids by the missing parent ids.
+#[derive(Default)]
+pub struct OrphanPool {
+    orphans: HashMap<Id, Orphan>,
+    /// Missing parent id and the ids of the orphans waiting for it.
+    waiting: HashMap<Id, Vec<Id>>,
+    /// The insertion order and the id of each orphan.
+    order: BTreeMap<u64, Id>,
+    next_seq: u64,
+    byte_size: usize,
+}
+
+impl OrphanPool {
+    /// Returns the orphans whose key differs from the id.
+    pub fn verify(&self) -> Vec<(Id, Id)> {
+        self.orphans
+            .iter()
+            .filter(|(key, orphan)| *key != orphan.acid.id())
+            .map(|(key, orphan)| (*key, *orphan.acid.id()))
+            .collect()
+    }
+
+    /// Returns the byte size that the orphans are regarded to consume.
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    fn insert(&mut self, acid: CAcid, missing: usize) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let id = *acid.id();
+        let byte_size = byte_size(&acid);
+        self.byte_size += byte_size;
+        self.order.insert(seq, id);
+        self.orphans.insert(
+            id,
+            Orphan {
+                acid,
+                missing,
+                seq,
+                byte_size,
+            },
+        );
+    }
+
+    /// Removes the orphan with `id` from `orphans` and `order` , but not from `waiting` .
+    fn take(&mut self, id: &Id) -> Option<CAcid> {
+        let orphan = self.orphans.remove(id)?;
+        self.order.remove(&orphan.seq);
+        self.byte_size -= orphan.byte_size;
+        Some(orphan.acid)
+    }
+
+    /// Removes the orphan with `id` and the ids waiting for the parents.
+    fn remove(&mut self, id: &Id) -> Option<CAcid> {
+        let acid = self.take(id)?;
+
+        for i in 0..acid.parent_count() {
+            let parent = acid.parent(i).unwrap();
+            if let Some(children) = self.waiting.get_mut(&parent) {
+                children.retain(|child| child != id);
+                if children.is_empty() {
+                    self.waiting.remove(&parent);
+                }
+            }
+        }
+
+        Some(acid)
+    }
+
+    /// Evicts the oldest orphans while the byte size exceeds `limit` .
+    fn shrink(&mut self, limit: usize) {
+        while limit < self.byte_size {
+            let id = match self.order.values().next() {
+                None => break,
+                Some(id) => *id,
+            };
+            self.remove(&id);
+            debug!("Evicted orphan acid {}.", id.display_hex());
+        }
+    }
+
+    /// Promotes the orphans waiting for `parent` recursively, and returns the promoted acids.
+    fn resolve(&mut self, parent: &Id) -> Vec<CAcid> {
+        let mut ret = Vec::new();
+        let mut parents = vec![*parent];
+
+        while let Some(parent) = parents.pop() {
+            let children = match self.waiting.remove(&parent) {
+                None => continue,
+                Some(children) => children,
+            };
+
+            for child in children {
+                let is_resolved = match self.orphans.get_mut(&child) {
+                    // The orphan has already been removed.
+                    None => false,
+                    Some(orphan) => {
+                        orphan.missing -= 1;
+                        orphan.missing == 0
+                    }
+                };
+
+                if is_resolved {
+                    // No other parent is missing; i.e. `waiting` has no `child` any more.
+                    let acid = self.take(&child).unwrap();
+                    acid.set_traceable();
+                    debug!(
+                        "Promoted orphan acid {} to be traceable.",
+                        child.display_hex()
+                    );
+                    parents.push(child);
+                    ret.push(acid);
+                }
+            }
+        }
+
+        ret
+    }
+}
+
+/// Returns `true` if `id` is cached and the cached acid is traceable.
+fn is_traceable_parent(id: &Id, environment: &Environment) -> bool {
+    match find(id, environment) {
+        CacheFindResult::Hit(parent) => parent.is_traceable(),
+        _ => false,
+    }
+}
+
+/// Adds `acid` to the orphan pool unless all the parents are traceable, and returns the acids
+/// promoted to be traceable.
+///
+/// A parent is regarded as traceable only if it is cached and the cached acid is traceable.
+///
+/// If all the parents are traceable, `acid` is not added to the orphan pool but promoted at once,
+/// and the orphans waiting for `acid` are promoted recursively. Then, the return value includes
+/// `acid` itself.
+///
+/// Otherwise, `acid` waits in the orphan pool until all the missing parents are passed to
+/// [`insert`] or [`resolve_orphans`] . Then, the return value is empty.
+///
+/// Does nothing and returns an empty `Vec` if `acid` is already in the orphan pool. The oldest
+/// orphans are evicted if the orphan pool exceeds '--cache-orphan-size-limit'.
+///
+/// [`insert`]: super::insert
+/// [`resolve_orphans`]: self::resolve_orphans
+pub fn add_orphan(acid: CAcid, environment: &Environment) -> Vec<CAcid> {
+    // Keep the lock while checking the parents; otherwise the parent could be resolved after the
+    // check and before `acid` is registered.
+    let mut pool = environment.orphans.lock().unwrap();
+
+    let id = *acid.id();
+    if pool.orphans.contains_key(&id) {
+        return Vec::new();
+    }
+
+    let mut missing = 0;
+    for i in 0..acid.parent_count() {
+        let parent = acid.parent(i).unwrap();
+        if !is_traceable_parent(&parent, environment) {
+            pool.waiting.entry(parent).or_default().push(id);
+            missing += 1;
+        }
+    }
+
+    if missing == 0 {
+        acid.set_traceable();
+        let mut ret = vec![acid];
+        ret.extend(pool.resolve(&id));
+        ret
+    } else {
+        pool.insert(acid, missing);
+        pool.shrink(environment.orphan_size_limit);
+        Vec::new()
+    }
+}
+
+/// Notifies the orphan pool that the acid with `parent` became traceable, and returns the acids
+/// promoted to be traceable.
+///
+/// The orphans waiting for `parent` are re-evaluated, and they are promoted if no other parent is
+/// missing. The promotion is done recursively, i.e. the orphans waiting for the promoted acid are
+/// re-evaluated as well.
+///
+/// [`insert`] calls this function if the inserted acid is traceable, so the user need not call
+/// this function in such a case.
+///
+/// [`insert`]: super::insert
+pub fn resolve_orphans(parent: &Id, environment: &Environment) -> Vec<CAcid> {
+    let mut pool = environment.orphans.lock().unwrap();
+    pool.resolve(parent)
+}
+
+/// Removes the orphan acid with `id` from the orphan pool and returns it if any, or `None` .
+pub fn remove_orphan(id: &Id, environment: &Environment) -> Option<CAcid> {
+    let mut pool = environment.orphans.lock().unwrap();
+    pool.remove(id)
+}
+
+/// Returns the number of the acids in the orphan pool.
+pub fn orphan_count(environment: &Environment) -> usize {
+    let pool = environment.orphans.lock().unwrap();
+    pool.orphans.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{GeneratedAcid, Rng};
+
+    fn environment(orphan_size_limit: usize) -> Environment {
+        let mut environment = Environment::default();
+        environment.orphan_size_limit = orphan_size_limit;
+        unsafe { environment.init() }.unwrap();
+        environment
+    }
+
+    fn acid(parents: &[Id], rng: &mut Rng) -> CAcid {
+        CAcid::from(GeneratedAcid::new(parents.to_vec(), 8, rng))
+    }
+
+    #[test]
+    fn resolve() {
+        let environment = environment(usize::MAX);
+        let mut rng = Rng::new(1);
+
+        let parent = acid(&[], &mut rng);
+        let child = acid(&[*parent.id()], &mut rng);
+        let grandchild = acid(&[*child.id(), *parent.id()], &mut rng);
+
+        assert_eq!(
+            true,
+            add_orphan(grandchild.clone(), &environment).is_empty()
+        );
+        assert_eq!(true, add_orphan(child.clone(), &environment).is_empty());
+        assert_eq!(true, add_orphan(child.clone(), &environment).is_empty());
+        assert_eq!(2, orphan_count(&environment));
+
+        let promoted: Vec<Id> = resolve_orphans(parent.id(), &environment)
+            .iter()
+            .map(|acid| *acid.id())
+            .collect();
+        assert_eq!(vec![*child.id(), *grandchild.id()], promoted);
+        assert_eq!(0, orphan_count(&environment));
+
+        let pool = environment.orphans.lock().unwrap();
+        assert_eq!(0, pool.byte_size());
+        assert_eq!(true, pool.waiting.is_empty());
+        assert_eq!(true, pool.order.is_empty());
+    }
+
+    #[test]
+    fn remove() {
+        let environment = environment(usize::MAX);
+        let mut rng = Rng::new(2);
+
+        let parents = [acid(&[], &mut rng), acid(&[], &mut rng)];
+        let ids = [*parents[0].id(), *parents[1].id()];
+        let first = acid(&ids, &mut rng);
+        let second = acid(&ids[..1], &mut rng);
+        add_orphan(first.clone(), &environment);
+        add_orphan(second.clone(), &environment);
+
+        assert_eq!(
+            true,
+            remove_orphan(first.id(), &environment) == Some(first.clone())
+        );
+        assert_eq!(true, remove_orphan(first.id(), &environment).is_none());
+        {
+            let pool = environment.orphans.lock().unwrap();
+            assert_eq!(Some(&vec![*second.id()]), pool.waiting.get(&ids[0]));
+            assert_eq!(None, pool.waiting.get(&ids[1]));
+            assert_eq!(byte_size(&second), pool.byte_size());
+        }
+
+        assert_eq!(true, resolve_orphans(&ids[0], &environment) == vec![second]);
+        assert_eq!(true, environment.orphans.lock().unwrap().waiting.is_empty());
+    }
+
+    #[test]
+    fn evict() {
+        let mut rng = Rng::new(3);
+        let parent = acid(&[], &mut rng);
+        let orphans: Vec<CAcid> = (0..4).map(|_| acid(&[*parent.id()], &mut rng)).collect();
+
+        let environment = environment(byte_size(&orphans[0]) * 2);
+        for orphan in orphans.iter() {
+            add_orphan(orphan.clone(), &environment);
+        }
+
+        // The oldest ones are evicted.
+        assert_eq!(2, orphan_count(&environment));
+        let promoted = resolve_orphans(parent.id(), &environment);
+        assert_eq!(true, promoted == orphans[2..]);
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `pins` holds the cache elements which must not be expired.
+//!
+//! The LRU cache can expire a pinned element, but `PinSet` keeps a reference to it, so the element
+//! is still available via function [`find`] . (Expiring the pinned element from the LRU cache
+//! frees only the LRU node; the acid is shared with `PinSet` .)
+//!
+//! # Byte size
+//!
+//! The real allocation of an acid cannot be observed, so the byte size of each pinned element is
+//! estimated by function [`byte_size`] when it is pinned (or replaced,) and the same value is
+//! subtracted when it is unpinned. [`Environment::using_byte_size`] includes the estimate, and
+//! the soft limit excludes exactly the same value, so the estimate never drifts. (The LRU cache
+//! estimates the byte size of the acids in the same way.)
+//!
+//! [`find`]: super::find
+//! [`byte_size`]: self::byte_size
+//! [`Environment::using_byte_size`]: super::Environment::using_byte_size
+
+use super::{find, CacheFindResult, Environment};
+use crate::data_types::{CAcid, Id};
+use core::mem::size_of;
+use std::collections::HashMap;
+
+/// Returns the byte size that `acid` is regarded to consume as a pinned element; i.e. the handle
+/// and the intrinsic and the extrinsic data. The overhead of the allocator and of the
+/// implementation of the acid is not included.
+pub fn byte_size(acid: &CAcid) -> usize {
+    size_of::<CAcid>() + acid.intrinsic().len() + acid.extrinsic().len()
+}
+
+/// `PinSet` is a set of the pinned cache elements.
+#[derive(Default)]
+pub struct PinSet {
+    /// Pinned element and the byte size.
+    acids: HashMap<Id, (CAcid, usize)>,
+    byte_size: usize,
+}
+
+impl PinSet {
+    pub fn get(&self, id: &Id) -> Option<CAcid> {
+        self.acids.get(id).map(|(acid, _)| acid.clone())
+    }
+
+    /// Inserts or overwrites the pinned element.
+    pub fn insert(&mut self, acid: CAcid) {
+        let size = byte_size(&acid);
+        self.byte_size += size;
+
+        if let Some((_, prev)) = self.acids.insert(*acid.id(), (acid, size)) {
+            self.byte_size -= prev;
+        }
+    }
+
+    pub fn remove(&mut self, id: &Id) -> bool {
+        match self.acids.remove(id) {
+            None => false,
+            Some((_, size)) => {
+                self.byte_size -= size;
+                true
+            }
+        }
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    /// Returns the id and the byte size of each pinned element.
+    pub fn entries(&self) -> Vec<(Id, usize)> {
+        self.acids
+            .iter()
+            .map(|(id, (_, size))| (*id, *size))
+            .collect()
+    }
+
+    /// Returns the pinned elements whose key differs from the id, and the byte size recalculated
+    /// from the elements.
+    pub fn verify(&self) -> (Vec<(Id, Id)>, usize) {
+        let mut mismatches = Vec::new();
+        let mut byte_size_ = 0;
+
+        for (key, (acid, size)) in self.acids.iter() {
+            if key != acid.id() {
+                mismatches.push((*key, *acid.id()));
+            }
+            byte_size_ += size;
+        }
+
+        (mismatches, byte_size_)
+    }
+}
+
+/// Pins the cache element with `id` and returns `true` if it is cached; otherwise does nothing
+/// and returns `false` .
+///
+/// The pinned element is never expired until [`unpin`] is called; it is available via [`find`]
+/// even after the LRU cache expires it.
+/// The byte size of the pinned elements is excluded from '--cache-size-soft-limit' accounting.
+/// (See [`pinned_byte_size`] .)
+///
+/// [`find`]: super::find
+/// [`unpin`]: self::unpin
+/// [`pinned_byte_size`]: self::pinned_byte_size
+pub fn pin(id: &Id, environment: &Environment) -> bool {
+    match find(id, environment) {
+        CacheFindResult::Hit(acid) => {
+            let mut pins = environment.pins.lock().unwrap();
+            if pins.get(id).is_none() {
+                pins.insert(acid);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Unpins the cache element with `id` and returns `true` if it was pinned; otherwise does nothing
+/// and returns `false` .
+///
+/// Note that the element is no longer cached after this function is called if the LRU cache has
+/// already expired it.
+pub fn unpin(id: &Id, environment: &Environment) -> bool {
+    let mut pins = environment.pins.lock().unwrap();
+    pins.remove(id)
+}
+
+/// Returns `true` if the cache element with `id` is pinned, or `false` .
+pub fn is_pinned(id: &Id, environment: &Environment) -> bool {
+    let pins = environment.pins.lock().unwrap();
+    pins.get(id).is_some()
+}
+
+/// Returns the byte size that the pinned elements are regarded to consume.
+///
+/// The byte size of each element is estimated from the length of the intrinsic and the extrinsic
+/// data.
+pub fn pinned_byte_size(environment: &Environment) -> usize {
+    let pins = environment.pins.lock().unwrap();
+    pins.byte_size()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{expire, insert, is_cached, CacheState};
+    use crate::stub::Blob;
+
+    fn environment() -> Environment {
+        let mut environment = Environment::default();
+        unsafe { environment.init() }.unwrap();
+        environment
+    }
+
+    fn acid(bytes: &[u8]) -> CAcid {
+        CAcid::from(Blob::from(bytes))
+    }
+
+    #[test]
+    fn pin_() {
+        let environment = environment();
+        let acid = acid(&[1, 2, 3]);
+        let id = *acid.id();
+
+        // Not cached.
+        assert_eq!(false, pin(&id, &environment));
+        assert_eq!(false, is_pinned(&id, &environment));
+
+        insert(acid.clone(), &environment).unwrap();
+        assert_eq!(true, pin(&id, &environment));
+        assert_eq!(true, pin(&id, &environment));
+        assert_eq!(true, is_pinned(&id, &environment));
+        assert_eq!(byte_size(&acid), pinned_byte_size(&environment));
+
+        assert_eq!(true, unpin(&id, &environment));
+        assert_eq!(false, unpin(&id, &environment));
+        assert_eq!(false, is_pinned(&id, &environment));
+        assert_eq!(0, pinned_byte_size(&environment));
+    }
+
+    #[test]
+    fn expire_pinned() {
+        let environment = environment();
+        let pinned = acid(&[1, 2, 3]);
+        let other = acid(&[4, 5, 6]);
+
+        insert(pinned.clone(), &environment).unwrap();
+        insert(other.clone(), &environment).unwrap();
+        assert_eq!(true, pin(pinned.id(), &environment));
+
+        while expire(&environment) {}
+
+        let is_hit = |id| matches!(find(id, &environment), CacheFindResult::Hit(_));
+        assert_eq!(true, is_hit(pinned.id()));
+        assert_eq!(false, is_hit(other.id()));
+
+        // The element is no longer cached after unpinned.
+        assert_eq!(true, unpin(pinned.id(), &environment));
+        assert_eq!(
+            true,
+            matches!(is_cached(pinned.id(), &environment), CacheState::Lost)
+        );
+    }
+
+    #[test]
+    fn byte_size_accounting() {
+        let environment = environment();
+        let small = acid(&[1]);
+        let large = acid(&[2; 100]);
+
+        insert(small.clone(), &environment).unwrap();
+        insert(large.clone(), &environment).unwrap();
+        let before = environment.using_byte_size();
+
+        assert_eq!(true, pin(small.id(), &environment));
+        assert_eq!(true, pin(large.id(), &environment));
+        let expected = byte_size(&small) + byte_size(&large);
+        assert_eq!(expected, pinned_byte_size(&environment));
+        assert_eq!(before + expected, environment.using_byte_size());
+
+        // The same value is subtracted on unpinning.
+        assert_eq!(true, unpin(small.id(), &environment));
+        assert_eq!(true, unpin(large.id(), &environment));
+        assert_eq!(0, pinned_byte_size(&environment));
+        assert_eq!(before, environment.using_byte_size());
+
+        let (mismatches, size) = environment.pins.lock().unwrap().verify();
+        assert_eq!(true, mismatches.is_empty());
+        assert_eq!(0, size);
+    }
+}
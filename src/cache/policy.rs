@@ -0,0 +1,227 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `policy` defines the eviction policies of the cache.
+//!
+//! The LRU cache itself always expires the 'Least Recently Used (LRU)' element. An eviction
+//! policy selects the elements to be held in the 'protected segment' in addition; the protected
+//! segment keeps a reference to each element, so the element is still available via function
+//! [`find`] after the LRU cache expires it. (Like the pinned elements.)
+//!
+//! The protected segment uses at most half of '--cache-size-soft-limit'. When it exceeds the
+//! limit, the element with the least priority is evicted. The priority of each element is the
+//! 'inflation' plus the weight that the policy returns, where the inflation is the priority of the
+//! last evicted element. (i.e. the algorithm is 'GreedyDual'.) An element gets a new priority
+//! whenever it is accessed.
+//!
+//! [`find`]: super::find
+
+use super::pins::byte_size;
+use crate::data_types::{CAcid, Id};
+use std::collections::{BTreeMap, HashMap};
+
+/// `EvictionPolicy` decides which cache elements are held in the protected segment and how long.
+pub trait EvictionPolicy: Send + Sync {
+    /// Returns `true` if `acid` should be held in the protected segment.
+    ///
+    /// `is_hit` is `true` if `acid` is found in the cache, or `false` if it is inserted newly.
+    fn admit(&self, acid: &CAcid, is_hit: bool) -> bool;
+
+    /// Returns the weight of `acid` in the protected segment.
+    ///
+    /// The greater the weight is, the longer the element is held.
+    fn weight(&self, acid: &CAcid) -> u64;
+}
+
+/// `Lru` is the strict LRU; it holds nothing in the protected segment.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lru;
+
+impl EvictionPolicy for Lru {
+    fn admit(&self, _acid: &CAcid, _is_hit: bool) -> bool {
+        false
+    }
+
+    fn weight(&self, _acid: &CAcid) -> u64 {
+        0
+    }
+}
+
+/// `SizeWeightedLru` holds every element in the protected segment weighted by the byte size, so
+/// that a large element (e.g. a block) stays longer than a small one (e.g. a transaction.)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SizeWeightedLru;
+
+impl EvictionPolicy for SizeWeightedLru {
+    fn admit(&self, _acid: &CAcid, _is_hit: bool) -> bool {
+        true
+    }
+
+    fn weight(&self, acid: &CAcid) -> u64 {
+        byte_size(acid) as u64
+    }
+}
+
+/// `SegmentedLru` holds the elements which are found at least once after the insertion in the
+/// protected segment. The protected segment is an LRU of its own.
+///
+/// An element accessed only once is expired by the LRU cache before the elements accessed twice
+/// or more.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SegmentedLru;
+
+impl EvictionPolicy for SegmentedLru {
+    fn admit(&self, _acid: &CAcid, is_hit: bool) -> bool {
+        is_hit
+    }
+
+    fn weight(&self, _acid: &CAcid) -> u64 {
+        1
+    }
+}
+
+/// Returns the policy named `name` , or `None` . ('--cache-policy')
+pub fn by_name(name: &str) -> Option<Box<dyn EvictionPolicy>> {
+    match name {
+        "lru" => Some(Box::new(Lru)),
+        "size-weighted-lru" => Some(Box::new(SizeWeightedLru)),
+        "segmented-lru" => Some(Box::new(SegmentedLru)),
+        _ => None,
+    }
+}
+
+/// `Protected` is the protected segment.
+#[derive(Default)]
+pub struct Protected {
+    /// Element, the key of `order` and the byte size.
+    acids: HashMap<Id, (CAcid, (u64, u64), usize)>,
+    /// The priority and the access tick of each element, ordered by them.
+    order: BTreeMap<(u64, u64), Id>,
+    inflation: u64,
+    tick: u64,
+    byte_size: usize,
+}
+
+impl Protected {
+    pub fn get(&self, id: &Id) -> Option<CAcid> {
+        self.acids.get(id).map(|(acid, _, _)| acid.clone())
+    }
+
+    /// Inserts or overwrites the element with the priority calculated from `weight` .
+    pub fn insert(&mut self, acid: CAcid, weight: u64) {
+        self.remove(acid.id());
+
+        self.tick += 1;
+        let key = (self.inflation.saturating_add(weight), self.tick);
+        let size = byte_size(&acid);
+
+        self.order.insert(key, *acid.id());
+        self.byte_size += size;
+        self.acids.insert(*acid.id(), (acid, key, size));
+    }
+
+    /// Recalculates the priority of the element with `id` if any.
+    pub fn touch(&mut self, id: &Id, weight: u64) {
+        if let Some(acid) = self.get(id) {
+            self.insert(acid, weight);
+        }
+    }
+
+    pub fn remove(&mut self, id: &Id) -> bool {
+        match self.acids.remove(id) {
+            None => false,
+            Some((_, key, size)) => {
+                self.order.remove(&key);
+                self.byte_size -= size;
+                true
+            }
+        }
+    }
+
+    /// Evicts the element with the least priority and returns `true` , or returns `false` if
+    /// empty.
+    pub fn evict(&mut self) -> bool {
+        let (&key, &id) = match self.order.iter().next() {
+            None => return false,
+            Some(e) => e,
+        };
+
+        self.inflation = key.0;
+        self.remove(&id)
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    pub fn count(&self) -> usize {
+        self.acids.len()
+    }
+
+    /// Returns the id and the byte size of each element.
+    pub fn entries(&self) -> Vec<(Id, usize)> {
+        self.acids
+            .iter()
+            .map(|(id, (_, _, size))| (*id, *size))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::Blob;
+
+    fn acid(bytes: &[u8]) -> CAcid {
+        CAcid::from(Blob::from(bytes))
+    }
+
+    #[test]
+    fn evict_least_priority() {
+        let mut protected = Protected::default();
+        let small = acid(&[1]);
+        let large = acid(&[2; 1024]);
+
+        protected.insert(large.clone(), SizeWeightedLru.weight(&large));
+        protected.insert(small.clone(), SizeWeightedLru.weight(&small));
+        assert_eq!(2, protected.count());
+
+        // The small one is evicted first though it is newer.
+        assert_eq!(true, protected.evict());
+        assert_eq!(true, protected.get(small.id()).is_none());
+        assert_eq!(true, protected.get(large.id()).is_some());
+        assert_eq!(byte_size(&large), protected.byte_size());
+
+        assert_eq!(true, protected.evict());
+        assert_eq!(false, protected.evict());
+        assert_eq!(0, protected.byte_size());
+    }
+
+    #[test]
+    fn touch() {
+        let mut protected = Protected::default();
+        let a = acid(&[1]);
+        let b = acid(&[2]);
+
+        protected.insert(a.clone(), SegmentedLru.weight(&a));
+        protected.insert(b.clone(), SegmentedLru.weight(&b));
+        protected.touch(a.id(), SegmentedLru.weight(&a));
+
+        assert_eq!(true, protected.evict());
+        assert_eq!(true, protected.get(a.id()).is_some());
+        assert_eq!(true, protected.get(b.id()).is_none());
+    }
+}
@@ -0,0 +1,263 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `shard` wraps the LRU hash set to count the byte size of the acids that it holds.
+//!
+//! 'LruHashSet' does not tell which element it expires, and the acids are allocated outside of
+//! it. So [`Shard`] stores each acid wrapped in [`Element`] , which is recorded in the [`Ledger`]
+//! of the shard while the LRU hash set holds it. The record is removed when the LRU hash set
+//! drops the element; i.e. when the element is expired, removed, or overwritten.
+//!
+//! The byte size of each acid is estimated by [`byte_size`] when it is recorded.
+//!
+//! [`Shard`]: self::Shard
+//! [`Element`]: self::Element
+//! [`Ledger`]: self::Ledger
+//! [`byte_size`]: self::byte_size
+
+use super::accounting::CountingAlloc;
+use super::{pins, Placeholder};
+use crate::data_types::{CAcid, Id};
+use core::hash::{Hash, Hasher};
+use core::mem::size_of;
+use mouse_containers::lru_hash_set::LruHashSet;
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Returns the byte size that `acid` is regarded to consume in the LRU cache.
+///
+/// It is the same to function [`pins::byte_size`] except for the placeholders, which have
+/// neither the intrinsic nor the extrinsic data.
+///
+/// [`pins::byte_size`]: super::pins::byte_size
+pub fn byte_size(acid: &CAcid) -> usize {
+    match acid.downcast::<Placeholder>() {
+        Some(_) => size_of::<CAcid>() + size_of::<Placeholder>(),
+        None => pins::byte_size(acid),
+    }
+}
+
+/// `Record` is an acid that the LRU hash set holds.
+struct Record {
+    /// The serial number of the [`Element`] that holds `acid` .
+    ///
+    /// [`Element`]: self::Element
+    serial: u64,
+    acid: CAcid,
+    size: usize,
+}
+
+/// `Ledger` records the acids that the LRU hash set of a shard holds.
+#[derive(Default)]
+pub struct Ledger {
+    serial: AtomicU64,
+    byte_size: AtomicUsize,
+    records: Mutex<HashMap<Id, Record>>,
+}
+
+impl Ledger {
+    /// Records or overwrites the acid that `element` holds.
+    fn record(&self, element: &Element) {
+        let size = byte_size(&element.acid);
+        let record = Record {
+            serial: element.serial,
+            acid: element.acid.clone(),
+            size,
+        };
+
+        let mut records = self.records.lock().unwrap();
+        self.byte_size.fetch_add(size, Ordering::Relaxed);
+        if let Some(prev) = records.insert(element.key, record) {
+            self.byte_size.fetch_sub(prev.size, Ordering::Relaxed);
+        }
+    }
+
+    /// Removes the record of `element` if any.
+    fn forget(&self, element: &Element) {
+        let mut records = self.records.lock().unwrap();
+        match records.get(&element.key) {
+            Some(record) if record.serial == element.serial => {
+                self.byte_size.fetch_sub(record.size, Ordering::Relaxed);
+                records.remove(&element.key);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// `Element` is the value that the LRU hash set stores.
+///
+/// `Element` dereferences to the wrapped acid. It is hashed and compared by the id that it is
+/// stored under.
+pub struct Element {
+    key: Id,
+    acid: CAcid,
+    /// The serial number to tell the recorded element from a new one with the same id.
+    serial: u64,
+    ledger: Arc<Ledger>,
+}
+
+impl Element {
+    fn new(acid: CAcid, ledger: &Arc<Ledger>) -> Self {
+        Self {
+            key: *acid.id(),
+            acid,
+            serial: ledger.serial.fetch_add(1, Ordering::Relaxed),
+            ledger: ledger.clone(),
+        }
+    }
+}
+
+impl Drop for Element {
+    fn drop(&mut self) {
+        self.ledger.forget(self);
+    }
+}
+
+impl core::ops::Deref for Element {
+    type Target = CAcid;
+
+    fn deref(&self) -> &CAcid {
+        &self.acid
+    }
+}
+
+impl Borrow<Id> for Element {
+    fn borrow(&self) -> &Id {
+        &self.key
+    }
+}
+
+impl PartialEq<Self> for Element {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Element {}
+
+impl Hash for Element {
+    fn hash<S>(&self, hasher: &mut S)
+    where
+        S: Hasher,
+    {
+        self.key.hash(hasher);
+    }
+}
+
+/// `Shard` is one of the independent LRU sets of the cache.
+pub struct Shard {
+    lru: LruHashSet<Element, CountingAlloc, RandomState>,
+    ledger: Arc<Ledger>,
+}
+
+impl Shard {
+    pub fn new(alloc: CountingAlloc) -> Self {
+        Self {
+            lru: LruHashSet::new(alloc, RandomState::new()),
+            ledger: Arc::default(),
+        }
+    }
+
+    pub unsafe fn init(&mut self, chain_len: usize) {
+        self.lru.init(chain_len);
+    }
+
+    /// Returns the byte size of the acids that `self` holds.
+    pub fn byte_size(&self) -> usize {
+        self.ledger.byte_size.load(Ordering::Relaxed)
+    }
+
+    /// Returns the id that each element is stored under, the element, and the byte size recorded
+    /// for it.
+    ///
+    /// The LRU order is not changed.
+    pub fn entries(&self) -> Vec<(Id, CAcid, usize)> {
+        let records = self.ledger.records.lock().unwrap();
+        records
+            .iter()
+            .map(|(key, record)| (*key, record.acid.clone(), record.size))
+            .collect()
+    }
+
+    /// Returns the same as [`entries`] , and the byte size of `self` read at the same time.
+    ///
+    /// [`entries`]: Self::entries
+    pub fn audit(&self) -> (Vec<(Id, CAcid, usize)>, usize) {
+        let records = self.ledger.records.lock().unwrap();
+        let entries = records
+            .iter()
+            .map(|(key, record)| (*key, record.acid.clone(), record.size))
+            .collect();
+        (entries, self.byte_size())
+    }
+
+    /// Returns the element with `id` if any. The element is regarded as the MRU if `to_mru` is
+    /// `true` .
+    pub unsafe fn get(&self, id: &Id, to_mru: bool) -> Option<CAcid> {
+        let entry = self.lru.get(id)?;
+        if to_mru {
+            entry.to_mru();
+        }
+        Some(entry.acid.clone())
+    }
+
+    /// Inserts `val` if no element with the same id is stored; otherwise calls `op` with the
+    /// stored element and `val` , and regards the element as the MRU if `to_mru` is `true` .
+    ///
+    /// Returns whether the element with the same id was stored, and the element after the call.
+    pub unsafe fn insert_with<F>(&self, val: CAcid, op: F, to_mru: bool) -> (bool, CAcid)
+    where
+        F: FnOnce(&mut CAcid, CAcid),
+    {
+        let val = Element::new(val, &self.ledger);
+
+        // 'op' can overwrite or merge the acid, so the stored element is recorded again.
+        let op = |element: &mut Element, val: Element| {
+            op(&mut element.acid, val.acid.clone());
+            element.ledger.record(element);
+        };
+
+        match self.lru.insert_with(val, op) {
+            (Some(_), entry) => {
+                if to_mru {
+                    entry.to_mru();
+                }
+                (true, entry.acid.clone())
+            }
+            (None, entry) => {
+                // Record before releasing 'entry' ; otherwise another thread could expire the
+                // element before it is recorded.
+                self.ledger.record(&*entry);
+                (false, entry.acid.clone())
+            }
+        }
+    }
+
+    /// Removes the element with `id` and returns it if any.
+    pub unsafe fn remove(&self, id: &Id) -> Option<CAcid> {
+        self.lru.remove(id).map(|element| element.acid.clone())
+    }
+
+    /// Expires the LRU element and returns `true` if something is stored; otherwise returns
+    /// `false` .
+    pub unsafe fn expire(&self) -> bool {
+        self.lru.expire()
+    }
+}
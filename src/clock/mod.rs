@@ -0,0 +1,228 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `clock` provides the current time adjusted by the clock skew against the other nodes, and
+//! validates the timestamps with it.
+//! `clock` is independent from other modules.
+//!
+//! The skew is estimated as the median of the offsets that the user reports with function
+//! [`observe_peer_time`] .
+//!
+//! [`observe_peer_time`]: self::observe_peer_time
+
+use crate::{Config, ModuleEnvironment};
+use clap::{App, Arg};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `Timestamp` is the milliseconds since the UNIX epoch.
+pub type Timestamp = i64;
+
+/// 2 minutes.
+const DEFAULT_MAX_FUTURE_DRIFT_MS: &'static str = "120000";
+
+/// The number of the latest offsets to estimate the skew.
+const SKEW_SAMPLES: usize = 64;
+
+/// `SkewEstimator` estimates the clock skew from the latest `SKEW_SAMPLES` offsets.
+#[derive(Debug, Default)]
+struct SkewEstimator {
+    offsets: VecDeque<i64>,
+}
+
+impl SkewEstimator {
+    pub fn observe(&mut self, offset: i64) {
+        if self.offsets.len() == SKEW_SAMPLES {
+            self.offsets.pop_front();
+        }
+        self.offsets.push_back(offset);
+    }
+
+    /// Returns the median of the offsets, or 0 if nothing is observed.
+    pub fn estimate(&self) -> i64 {
+        let mut offsets: Vec<i64> = self.offsets.iter().cloned().collect();
+        offsets.sort_unstable();
+
+        let len = offsets.len();
+        match len {
+            0 => 0,
+            _ if len % 2 == 1 => offsets[len / 2],
+            _ => (offsets[len / 2 - 1] + offsets[len / 2]) / 2,
+        }
+    }
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --max-future-drift-ms
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --max-future-drift-ms: 120000 (= 2 minutes)
+pub struct Environment {
+    max_future_drift: i64,
+    skew: Mutex<SkewEstimator>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            max_future_drift: DEFAULT_MAX_FUTURE_DRIFT_MS.parse().unwrap(),
+            skew: Default::default(),
+        }
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.arg(
+            Arg::with_name("max_future_drift_ms")
+                .help(
+                    "The max milliseconds that a timestamp can be ahead of the current time.
+The clock skew against the other nodes is adjusted within this range as well.",
+                )
+                .long("--max-future-drift-ms")
+                .default_value(DEFAULT_MAX_FUTURE_DRIFT_MS)
+                .takes_value(true),
+        )
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let max_future_drift = config.args().value_of("max_future_drift_ms").unwrap();
+        let max_future_drift: u32 = max_future_drift.parse().map_err(|e| {
+            let msg = format!("Failed to parse '--max-future-drift-ms': {}", e);
+            crate::Error::Config(msg)
+        })?;
+        self.max_future_drift = max_future_drift as i64;
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// Returns the system time as `Timestamp` .
+pub fn system_now() -> Timestamp {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as Timestamp,
+        Err(e) => -(e.duration().as_millis() as Timestamp),
+    }
+}
+
+/// Reports that a peer node said the current time is `peer_now` .
+///
+/// The offset between `peer_now` and the system time is used to estimate the clock skew.
+pub fn observe_peer_time(peer_now: Timestamp, env: &Environment) {
+    let offset = peer_now - system_now();
+    env.skew.lock().unwrap().observe(offset);
+}
+
+/// Returns the estimated clock skew in milliseconds; i.e. how much the peer nodes are ahead of
+/// the system time.
+///
+/// The returned value is limited to the range of '--max-future-drift-ms' so that the peers
+/// cannot shift the clock arbitrarily.
+pub fn estimated_skew(env: &Environment) -> i64 {
+    let skew = env.skew.lock().unwrap().estimate();
+    skew.max(-env.max_future_drift).min(env.max_future_drift)
+}
+
+/// Returns the current time adjusted by the estimated clock skew.
+pub fn now(env: &Environment) -> Timestamp {
+    system_now() + estimated_skew(env)
+}
+
+/// Returns an error if `timestamp` is ahead of the adjusted current time by more than
+/// '--max-future-drift-ms'.
+pub fn check_timestamp(timestamp: Timestamp, env: &Environment) -> Result<(), Box<dyn Error>> {
+    check_timestamp_at(timestamp, now(env), env)
+}
+
+fn check_timestamp_at(
+    timestamp: Timestamp,
+    now: Timestamp,
+    env: &Environment,
+) -> Result<(), Box<dyn Error>> {
+    let drift = timestamp - now;
+    if env.max_future_drift < drift {
+        let msg = format!(
+            "Timestamp {} is {} ms ahead of the current time (max: {} ms)",
+            timestamp, drift, env.max_future_drift
+        );
+        Err(Box::from(msg))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimate() {
+        let mut skew = SkewEstimator::default();
+        assert_eq!(0, skew.estimate());
+
+        skew.observe(30);
+        assert_eq!(30, skew.estimate());
+
+        skew.observe(-10);
+        assert_eq!(10, skew.estimate());
+
+        skew.observe(1000);
+        assert_eq!(30, skew.estimate());
+
+        for _ in 0..SKEW_SAMPLES {
+            skew.observe(5);
+        }
+        assert_eq!(SKEW_SAMPLES, skew.offsets.len());
+        assert_eq!(5, skew.estimate());
+    }
+
+    #[test]
+    fn check_timestamp_() {
+        let env = Environment::default();
+        let now = 1_000_000;
+
+        assert_eq!(true, check_timestamp_at(0, now, &env).is_ok());
+        assert_eq!(true, check_timestamp_at(now, now, &env).is_ok());
+
+        let limit = now + env.max_future_drift;
+        assert_eq!(true, check_timestamp_at(limit, now, &env).is_ok());
+        assert_eq!(true, check_timestamp_at(limit + 1, now, &env).is_err());
+    }
+
+    #[test]
+    fn estimated_skew_is_limited() {
+        let env = Environment::default();
+        for _ in 0..3 {
+            observe_peer_time(system_now() + 10 * env.max_future_drift, &env);
+        }
+
+        assert_eq!(env.max_future_drift, estimated_skew(&env));
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `coin_selection` chooses the resources for a spending acid to consume.
+//! `coin_selection` depends on module `data_types` .
+//!
+//! See also function [`select_resources`] .
+//!
+//! [`select_resources`]: crate::select_resources
+
+use crate::data_types::{AssetValue, ResourceId};
+
+/// The maximum number of the nodes that `Strategy::BranchAndBound` visits.
+const MAX_TRIES: usize = 100_000;
+
+/// `Strategy` is the algorithm of function [`select`] .
+///
+/// [`select`]: self::select
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Takes the resources in the descending order of the value until the target is covered.
+    LargestFirst,
+    /// Searches the resources whose total is between the target and the target plus `tolerance`
+    /// so as not to make the change, (the excess is usually given up as the fee,) and falls back
+    /// to `LargestFirst` if not found.
+    BranchAndBound {
+        /// The maximum excess to give up instead of making the change.
+        tolerance: AssetValue,
+    },
+}
+
+/// `Selection` is the result of function [`select`] .
+///
+/// [`select`]: self::select
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// The selected resources in the descending order of the value.
+    pub resources: Vec<(ResourceId, AssetValue)>,
+    /// The sum of the selected values.
+    pub total: AssetValue,
+    /// `total` minus the target.
+    pub change: AssetValue,
+}
+
+/// Selects the resources from `candidates` whose total is at least `target` with `strategy` , or
+/// returns `None` if the total of all the candidates is less than `target` .
+///
+/// The candidates whose value is 0 or less are ignored.
+///
+/// # Panics
+///
+/// Panics if `target` is less than 0.
+pub fn select(
+    candidates: &[(ResourceId, AssetValue)],
+    target: AssetValue,
+    strategy: Strategy,
+) -> Option<Selection> {
+    assert!(0 <= target);
+
+    let mut candidates: Vec<(ResourceId, AssetValue)> = candidates
+        .iter()
+        .filter(|(_, value)| 0 < *value)
+        .cloned()
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let selected = match strategy {
+        Strategy::LargestFirst => largest_first(&candidates, target)?,
+        Strategy::BranchAndBound { tolerance } => {
+            let values: Vec<AssetValue> = candidates.iter().map(|(_, value)| *value).collect();
+            match branch_and_bound(&values, target, tolerance) {
+                Some(selected) => selected,
+                None => largest_first(&candidates, target)?,
+            }
+        }
+    };
+
+    let resources: Vec<(ResourceId, AssetValue)> =
+        selected.into_iter().map(|i| candidates[i]).collect();
+    let total = resources.iter().fold(0 as AssetValue, |acc, (_, value)| {
+        acc.saturating_add(*value)
+    });
+
+    Some(Selection {
+        resources,
+        total,
+        change: total - target,
+    })
+}
+
+/// Returns the indexes of the largest candidates covering `target` . `candidates` must be sorted
+/// in the descending order.
+fn largest_first(
+    candidates: &[(ResourceId, AssetValue)],
+    target: AssetValue,
+) -> Option<Vec<usize>> {
+    let mut total: AssetValue = 0;
+    let mut ret = Vec::new();
+
+    for (i, (_, value)) in candidates.iter().enumerate() {
+        if target <= total {
+            break;
+        }
+        total = total.saturating_add(*value);
+        ret.push(i);
+    }
+
+    if target <= total {
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+/// Searches the depth-first for the indexes of `values` whose sum is between `target` and
+/// `target + tolerance` with the least excess. `values` must be sorted in the descending order.
+fn branch_and_bound(
+    values: &[AssetValue],
+    target: AssetValue,
+    tolerance: AssetValue,
+) -> Option<Vec<usize>> {
+    let remaining = values
+        .iter()
+        .fold(0 as AssetValue, |acc, value| acc.saturating_add(*value));
+
+    let mut search = Search {
+        values,
+        target,
+        upper: target.saturating_add(tolerance.max(0)),
+        tries: MAX_TRIES,
+        current: Vec::new(),
+        best: None,
+    };
+    search.visit(0, 0, remaining);
+
+    search.best.map(|(_, selected)| selected)
+}
+
+struct Search<'a> {
+    values: &'a [AssetValue],
+    target: AssetValue,
+    upper: AssetValue,
+    tries: usize,
+    current: Vec<usize>,
+    /// The least excess and the indexes.
+    best: Option<(AssetValue, Vec<usize>)>,
+}
+
+impl Search<'_> {
+    /// `sum` is the total of `current` , and `remaining` is the total of `values[i..]` .
+    fn visit(&mut self, i: usize, sum: AssetValue, remaining: AssetValue) {
+        if self.tries == 0 || self.best.as_ref().map(|(excess, _)| *excess) == Some(0) {
+            return;
+        }
+        self.tries -= 1;
+
+        if self.upper < sum || sum.saturating_add(remaining) < self.target {
+            return;
+        }
+
+        if self.target <= sum {
+            // Adding more only increases the excess.
+            let excess = sum - self.target;
+            if self.best.as_ref().map_or(true, |(best, _)| excess < *best) {
+                self.best = Some((excess, self.current.clone()));
+            }
+            return;
+        }
+
+        if i == self.values.len() {
+            return;
+        }
+
+        let value = self.values[i];
+
+        self.current.push(i);
+        self.visit(i + 1, sum.saturating_add(value), remaining - value);
+        self.current.pop();
+
+        self.visit(i + 1, sum, remaining - value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(values: &[AssetValue]) -> Vec<(ResourceId, AssetValue)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (unsafe { ResourceId::new(&[i as u8], b"asset") }, *value))
+            .collect()
+    }
+
+    fn values(selection: &Selection) -> Vec<AssetValue> {
+        selection
+            .resources
+            .iter()
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    #[test]
+    fn largest_first_() {
+        let candidates = resources(&[3, 10, 0, 5, -4, 1]);
+
+        let selection = select(&candidates, 12, Strategy::LargestFirst).unwrap();
+        assert_eq!(vec![10, 5], values(&selection));
+        assert_eq!(15, selection.total);
+        assert_eq!(3, selection.change);
+        assert_eq!(candidates[1].0, selection.resources[0].0);
+
+        let selection = select(&candidates, 19, Strategy::LargestFirst).unwrap();
+        assert_eq!(vec![10, 5, 3, 1], values(&selection));
+        assert_eq!(0, selection.change);
+
+        assert_eq!(None, select(&candidates, 20, Strategy::LargestFirst));
+
+        let selection = select(&candidates, 0, Strategy::LargestFirst).unwrap();
+        assert_eq!(true, selection.resources.is_empty());
+        assert_eq!(0, selection.change);
+    }
+
+    #[test]
+    fn branch_and_bound_() {
+        let candidates = resources(&[3, 10, 5, 1, 7]);
+
+        // 7 + 5 exactly.
+        let strategy = Strategy::BranchAndBound { tolerance: 0 };
+        let selection = select(&candidates, 12, strategy).unwrap();
+        assert_eq!(vec![7, 5], values(&selection));
+        assert_eq!(0, selection.change);
+
+        // 10 + 3 is the least excess within the tolerance, while the largest first is 10 + 6.
+        let candidates = resources(&[6, 10, 3]);
+        let strategy = Strategy::BranchAndBound { tolerance: 2 };
+        let selection = select(&candidates, 12, strategy).unwrap();
+        assert_eq!(vec![10, 3], values(&selection));
+        assert_eq!(1, selection.change);
+        let selection = select(&candidates, 12, Strategy::LargestFirst).unwrap();
+        assert_eq!(vec![10, 6], values(&selection));
+
+        // Nothing within the tolerance; falls back to the largest first.
+        let strategy = Strategy::BranchAndBound { tolerance: 0 };
+        let selection = select(&candidates, 14, strategy).unwrap();
+        assert_eq!(vec![10, 6], values(&selection));
+        assert_eq!(2, selection.change);
+
+        assert_eq!(None, select(&candidates, 20, strategy));
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `config_file` merges the arguments in the TOML file specified by '--config' into the command
+//! line arguments.
+//!
+//! Each key of the file is the long name of an argument without the leading "--". The keys in a
+//! table are prefixed by the table name and "-"; i.e. the following 2 files are same.
+//!
+//! ```toml
+//! kvs-db-path = "/var/lib/mouse/kvs"
+//! kvs-verify-checksums = true
+//! ```
+//!
+//! ```toml
+//! [kvs]
+//! db-path = "/var/lib/mouse/kvs"
+//! verify-checksums = true
+//! ```
+//!
+//! A flag is given if the value is `true` , and is not if `false` .
+//! The arguments given in the command line take precedence over the file.
+
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use toml::Value;
+
+/// The long name of the argument to specify the config file.
+pub const CONFIG_ARG: &'static str = "config";
+
+/// Returns the value of '--config' in `args` if any.
+fn find_config_path(args: &[OsString]) -> Option<OsString> {
+    let long = format!("--{}", CONFIG_ARG);
+    let prefix = format!("--{}=", CONFIG_ARG);
+
+    let mut it = args.iter().skip(1);
+    while let Some(arg) = it.next() {
+        let arg_str = arg.to_string_lossy();
+        if arg_str == long {
+            return it.next().cloned();
+        }
+        if arg_str.starts_with(&prefix) {
+            return Some(OsString::from(&arg_str[prefix.len()..]));
+        }
+        if arg_str == "--" {
+            return None;
+        }
+    }
+
+    None
+}
+
+/// Returns `true` if argument `--name` is given in `args` .
+fn is_given(name: &str, args: &[OsString]) -> bool {
+    let long = format!("--{}", name);
+    let prefix = format!("--{}=", name);
+
+    args.iter().skip(1).any(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == long || arg.starts_with(&prefix)
+    })
+}
+
+/// Flattens `table` into the (long name, value) pairs.
+fn flatten(prefix: &str, table: &toml::value::Table, acc: &mut Vec<(String, Option<String>)>) {
+    for (key, value) in table {
+        let name = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}-{}", prefix, key)
+        };
+
+        match value {
+            Value::Table(t) => flatten(&name, t, acc),
+            Value::Boolean(true) => acc.push((name, None)),
+            Value::Boolean(false) => {}
+            Value::String(s) => acc.push((name, Some(s.clone()))),
+            Value::Integer(i) => acc.push((name, Some(i.to_string()))),
+            Value::Float(f) => acc.push((name, Some(f.to_string()))),
+            Value::Datetime(d) => acc.push((name, Some(d.to_string()))),
+            Value::Array(values) => {
+                for v in values {
+                    let mut t = toml::value::Table::new();
+                    t.insert(key.clone(), v.clone());
+                    flatten(prefix, &t, acc);
+                }
+            }
+        }
+    }
+}
+
+/// Parses `content` and inserts the arguments which are not in `args` right after the program
+/// name.
+fn merge_str(content: &str, args: Vec<OsString>) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let table = match content.parse::<Value>()? {
+        Value::Table(t) => t,
+        _ => return Err(Box::from("The top level is not a table")),
+    };
+
+    let mut pairs = Vec::new();
+    flatten("", &table, &mut pairs);
+
+    let mut it = args.into_iter();
+    let mut ret: Vec<OsString> = it.next().into_iter().collect();
+    let given: Vec<OsString> = it.collect();
+
+    // `is_given` skips the program name.
+    let mut cli = ret.clone();
+    cli.extend(given.iter().cloned());
+
+    for (name, value) in pairs {
+        if name == CONFIG_ARG || is_given(&name, &cli) {
+            continue;
+        }
+
+        // The value is joined with "=" not to be parsed as an argument even if it starts with
+        // "-" . (e.g. "flush-thread-nice = -5")
+        match value {
+            None => ret.push(OsString::from(format!("--{}", name))),
+            Some(value) => ret.push(OsString::from(format!("--{}={}", name, value))),
+        }
+    }
+
+    ret.extend(given);
+    Ok(ret)
+}
+
+/// Loads the file specified by '--config' in `args` if any, and returns `args` merged with the
+/// arguments in the file.
+pub fn merge(args: Vec<OsString>) -> Result<Vec<OsString>, Box<dyn Error>> {
+    let path = match find_config_path(&args) {
+        None => return Ok(args),
+        Some(path) => path,
+    };
+
+    let content = fs::read_to_string(&path).map_err(|e| {
+        let msg = format!("Failed to read '--config' {:?}: {}", path, e);
+        Box::<dyn Error>::from(msg)
+    })?;
+
+    merge_str(&content, args).map_err(|e| {
+        let msg = format!("Failed to parse '--config' {:?}: {}", path, e);
+        Box::<dyn Error>::from(msg)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os_strings(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn find_config_path_() {
+        let path = Some(OsString::from("a.toml"));
+        assert_eq!(
+            path,
+            find_config_path(&os_strings(&["m", "--config", "a.toml"]))
+        );
+        assert_eq!(
+            path,
+            find_config_path(&os_strings(&["m", "--config=a.toml"]))
+        );
+        assert_eq!(
+            None,
+            find_config_path(&os_strings(&["m", "--foo", "a.toml"]))
+        );
+        assert_eq!(
+            None,
+            find_config_path(&os_strings(&["m", "--", "--config=a"]))
+        );
+    }
+
+    #[test]
+    fn merge_str_() {
+        let content = r#"
+            max-future-drift-ms = 1000
+            kvs-verify-checksums = true
+            migrate-dry-run = false
+
+            [rdb]
+            data-path = "/rdb"
+        "#;
+
+        let args = os_strings(&["m", "--rdb-data-path=/cli", "run"]);
+        let merged = merge_str(content, args).unwrap();
+
+        assert_eq!(
+            os_strings(&[
+                "m",
+                "--kvs-verify-checksums",
+                "--max-future-drift-ms=1000",
+                "--rdb-data-path=/cli",
+                "run",
+            ]),
+            merged
+        );
+    }
+
+    #[test]
+    fn merge_str_hyphen() {
+        let content = r#"
+            flush-thread-nice = -5
+            kvs-db-path = "--kvs"
+            rdb-data-path = "-"
+        "#;
+
+        let merged = merge_str(content, os_strings(&["m", "run"])).unwrap();
+        assert_eq!(
+            os_strings(&[
+                "m",
+                "--flush-thread-nice=-5",
+                "--kvs-db-path=--kvs",
+                "--rdb-data-path=-",
+                "run",
+            ]),
+            merged
+        );
+    }
+
+    #[test]
+    fn merge_str_error() {
+        assert_eq!(true, merge_str("foo = ", os_strings(&["m"])).is_err());
+    }
+}
@@ -15,36 +15,44 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::{Acid, Id};
-use crate::data_types::CAlloc;
+use crate::data_types::{CAlloc, CryptoHash};
 use core::any::TypeId;
 use core::hash::{Hash, Hasher};
 use core::ops::Deref;
 use counting_pointer::Asc;
 use std::borrow::Borrow;
 
-/// `CAcid` is like `std::Arc<dyn 'static + Sync + Send + Acid>` except for the followings.
+/// `CAcid` is like `std::Arc<dyn 'static + Sync + Send + Acid<H>>` except for the followings.
 ///
 /// - `CAcid` does not support weak count for the performance.
 /// - `CAcid` uses [`CAlloc`] to allocate/deallocate heap memory.
+///
+/// The default type of `H` is [`Id`] .
 #[derive(Clone)]
-pub struct CAcid(Asc<dyn 'static + Sync + Send + Acid, CAlloc>);
+pub struct CAcid<H = Id>(Asc<dyn 'static + Sync + Send + Acid<H>, CAlloc>)
+where
+    H: 'static + CryptoHash;
 
-impl<T> From<T> for CAcid
+impl<T, H> From<T> for CAcid<H>
 where
-    T: 'static + Sync + Send + Acid,
+    T: 'static + Sync + Send + Acid<H>,
+    H: 'static + CryptoHash,
 {
     #[inline]
     fn from(val: T) -> Self {
         let asc = Asc::new(val, CAlloc::default());
         let (ptr, alloc) = Asc::into_raw_alloc(asc);
-        let ptr = ptr as *const (dyn 'static + Sync + Send + Acid);
+        let ptr = ptr as *const (dyn 'static + Sync + Send + Acid<H>);
         let asc = unsafe { Asc::from_raw_alloc(ptr, alloc) };
         Self(asc)
     }
 }
 
-impl Deref for CAcid {
-    type Target = dyn 'static + Sync + Send + Acid;
+impl<H> Deref for CAcid<H>
+where
+    H: 'static + CryptoHash,
+{
+    type Target = dyn 'static + Sync + Send + Acid<H>;
 
     #[inline]
     fn deref(&self) -> &Self::Target {
@@ -52,43 +60,55 @@ impl Deref for CAcid {
     }
 }
 
-impl Borrow<Id> for CAcid {
+impl<H> Borrow<H> for CAcid<H>
+where
+    H: 'static + CryptoHash,
+{
     #[inline]
-    fn borrow(&self) -> &Id {
+    fn borrow(&self) -> &H {
         self.0.id()
     }
 }
 
-impl PartialEq<Self> for CAcid {
+impl<H> PartialEq<Self> for CAcid<H>
+where
+    H: 'static + CryptoHash,
+{
     #[inline]
     fn eq(&self, other: &Self) -> bool {
-        let this: &Id = self.borrow();
-        let other: &Id = other.borrow();
+        let this: &H = self.borrow();
+        let other: &H = other.borrow();
         this == other
     }
 }
 
-impl Eq for CAcid {}
+impl<H> Eq for CAcid<H> where H: 'static + CryptoHash {}
 
-impl Hash for CAcid {
-    fn hash<H>(&self, hasher: &mut H)
+impl<H> Hash for CAcid<H>
+where
+    H: 'static + CryptoHash,
+{
+    fn hash<S>(&self, hasher: &mut S)
     where
-        H: Hasher,
+        S: Hasher,
     {
-        let this: &Id = self.borrow();
+        let this: &H = self.borrow();
         this.hash(hasher);
     }
 }
 
-impl CAcid {
+impl<H> CAcid<H>
+where
+    H: 'static + CryptoHash,
+{
     /// If the wrapped address points to an instance of `T` , provides a reference to it, or
     /// `None` .
     #[inline]
     pub fn downcast<T>(&self) -> Option<&T>
     where
-        T: 'static + Send + Sync + Acid,
+        T: 'static + Send + Sync + Acid<H>,
     {
-        let wrapped: &dyn Acid = &*self.0;
+        let wrapped: &dyn Acid<H> = &*self.0;
 
         if wrapped.type_id() == TypeId::of::<T>() {
             Some(unsafe { self.downcast_unchecked() })
@@ -102,11 +122,21 @@ impl CAcid {
     /// # Safety
     ///
     /// The behavior is undefined if the wrapped address does not point to an instance of `T` .
+    ///
+    /// # Panics
+    ///
+    /// If feature "strict" is specified, panics instead of the undefined behavior.
     #[inline]
     pub unsafe fn downcast_unchecked<T>(&self) -> &T
     where
-        T: 'static + Send + Sync + Acid,
+        T: 'static + Send + Sync + Acid<H>,
     {
+        #[cfg(feature = "strict")]
+        {
+            let wrapped: &dyn Acid<H> = &*self.0;
+            assert_eq!(TypeId::of::<T>(), wrapped.type_id());
+        }
+
         let ptr = Asc::as_ptr(&self.0);
         let ptr = ptr as *const T;
         &*ptr
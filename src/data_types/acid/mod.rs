@@ -18,14 +18,14 @@
 
 mod cacid;
 
-use crate::data_types::Resource;
+use crate::data_types::{CryptoHash, Resource};
 pub use cacid::CAcid;
 use core::any::TypeId;
 use std::borrow::Cow;
 use std::error::Error;
 
 #[cfg(feature = "sha256_id")]
-/// `Id` is an alias to [`CryptoHash`] and used as unique id of [`Acid`] .
+/// `Id` is an alias to [`CryptoHash`] and used as the default unique id type of [`Acid`] .
 ///
 /// [`CryptoHash`]: crate::data_types::CryptoHash
 pub type Id = super::crypto_hash::Sha256;
@@ -57,6 +57,32 @@ pub type Id = super::crypto_hash::Sha256;
 ///
 /// See also [`Id`] .
 ///
+/// ## Hash type
+///
+/// `Acid` is generic over the type of the id. The default type is [`Id`] , and another
+/// [`CryptoHash`] type can be used for the acids of another Blockchain.
+///
+/// The generic hash type is supported only by the following items.
+///
+/// - [`Acid`] and [`CAcid`]
+/// - the functions to fetch, to insert, and to update the acid data in module `kvs`
+/// - `Stmt::column_hash` in module `rdb`
+///
+/// The others are fixed to [`Id`] ; i.e. module `cache` , the tables and the functions in module
+/// `rdb` , module `mempool` , and the record format of module `storage` . A Blockchain with
+/// another hash type can use the KVS acid data functions, however, it can not be committed
+/// through [`GlobalEnvironment`] for now. The KVS rejects the id longer than [`Id`] .
+///
+/// ### Migration
+///
+/// The KVS and the RDB store the raw bytes of the id, and the bytes of [`Id`] are the same as
+/// before `Acid` became generic. The existing databases are used as they are.
+///
+/// Each database must always be used with the same hash type. To change the hash type of a
+/// Blockchain, create new databases and import the Blockchain into them again with the new type.
+/// (The RDB returns an error if the byte length of the stored id does not match, and the KVS
+/// returns `None` if no data is stored under the id.)
+///
 /// # Parent
 ///
 /// `Acid` may depend on some other `Acid` instance(s). Parent is such a dependent `Acid` .
@@ -108,11 +134,18 @@ pub type Id = super::crypto_hash::Sha256;
 /// `Validity` may depends on the extrinsic data.
 ///
 /// [`Resource`]: crate::data_types::Resource
-pub trait Acid {
-    /// Provides `Id` of `self` .
+/// [`CryptoHash`]: crate::data_types::CryptoHash
+/// [`Acid`]: self::Acid
+/// [`CAcid`]: crate::data_types::CAcid
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub trait Acid<H = Id>
+where
+    H: CryptoHash,
+{
+    /// Provides the id of `self` .
     ///
     /// This method should be functional; it must always returns same result.
-    fn id(&self) -> &Id;
+    fn id(&self) -> &H;
 
     /// Serializes the immutable intrinsic data.
     ///
@@ -131,7 +164,7 @@ pub trait Acid {
     /// `self.parent_count()`, None.
     ///
     /// This method should be functional; it must always returns same result if `index` is same.
-    fn parent(&self, index: usize) -> Option<Id>;
+    fn parent(&self, index: usize) -> Option<H>;
 
     /// Returns how many resources that `self` consumes and generates.
     ///
@@ -143,6 +176,17 @@ pub trait Acid {
     /// This method should be functional; it must always returns same result if `index` is same.
     fn resource(&self, index: usize) -> Option<Resource>;
 
+    /// Returns the witness (e.g. the signature) proving that `self` may consume `index` th
+    /// resource if any, or `None` .
+    ///
+    /// It is passed to [`OwnershipVerifier`] , and is usually a part of the extrinsic data.
+    /// The default implementation always returns `None` .
+    ///
+    /// [`OwnershipVerifier`]: crate::ownership::OwnershipVerifier
+    fn witness(&self, _index: usize) -> Option<Cow<[u8]>> {
+        None
+    }
+
     /// Returns true if it is sure that the node knows all the ancestors; or false.
     /// i.e. this method returns true if one of the following conditions is satisfied, or false.
     ///
@@ -194,7 +238,7 @@ pub trait Acid {
     /// # Safety
     ///
     /// The behavior is undefined if `id` or `data` is different between `self` and `other` .
-    unsafe fn merge(&self, other: &dyn Acid) -> bool;
+    unsafe fn merge(&self, other: &dyn Acid<H>) -> bool;
 
     /// Returns the type of `Self` .
     ///
@@ -0,0 +1,190 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `base58` encodes and decodes Base58 and Base58Check with the alphabet of Bitcoin.
+//!
+//! Base58Check appends the first 4 bytes of the double SHA-256 of the payload as the checksum.
+//! The version byte (if any) is regarded as a part of the payload.
+//!
+//! # Examples
+//!
+//! ```
+//! use mouse::data_types::base58;
+//!
+//! assert_eq!("1112", base58::encode(&[0, 0, 0, 1]));
+//! assert_eq!(vec![0, 0, 0, 1], base58::decode("1112").unwrap());
+//!
+//! let s = base58::encode_check(&[0, 1, 2, 3]);
+//! assert_eq!(vec![0, 1, 2, 3], base58::decode_check(&s).unwrap());
+//! ```
+
+use super::crypto_hash::Sha256;
+use super::CryptoHash;
+use std::error::Error;
+use std::fmt;
+
+const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const CHECKSUM_LEN: usize = 4;
+
+/// `ParseBase58Error` is the error returned by [`decode`] and [`decode_check`] .
+///
+/// [`decode`]: self::decode
+/// [`decode_check`]: self::decode_check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseBase58Error {
+    /// The string includes a character which is not in the alphabet.
+    InvalidCharacter(char),
+    /// The decoded bytes are too short to include the checksum.
+    TooShort,
+    /// The checksum does not match.
+    InvalidChecksum,
+}
+
+impl fmt::Display for ParseBase58Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidCharacter(c) => write!(f, "Invalid character in Base58 string: {:?}", c),
+            Self::TooShort => f.write_str("Base58Check string is too short."),
+            Self::InvalidChecksum => f.write_str("Base58Check checksum does not match."),
+        }
+    }
+}
+
+impl Error for ParseBase58Error {}
+
+fn checksum(payload: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = Sha256::calculate(Sha256::calculate(payload).as_ref());
+    let mut ret = [0; CHECKSUM_LEN];
+    ret.copy_from_slice(&hash.as_ref()[..CHECKSUM_LEN]);
+    ret
+}
+
+/// Encodes `bytes` into Base58 string.
+pub fn encode(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+
+    // Digits in base 58, little endian.
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &b in &bytes[zeros..] {
+        let mut carry = b as u32;
+        for d in digits.iter_mut() {
+            carry += (*d as u32) << 8;
+            *d = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while 0 < carry {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let mut ret = String::with_capacity(zeros + digits.len());
+    (0..zeros).for_each(|_| ret.push(ALPHABET[0] as char));
+    digits
+        .iter()
+        .rev()
+        .for_each(|&d| ret.push(ALPHABET[d as usize] as char));
+    ret
+}
+
+/// Decodes Base58 string `s` .
+pub fn decode(s: &str) -> Result<Vec<u8>, ParseBase58Error> {
+    let zeros = s.bytes().take_while(|&c| c == ALPHABET[0]).count();
+
+    // Bytes in base 256, little endian.
+    let mut bytes: Vec<u8> = Vec::with_capacity(s.len() * 733 / 1000 + 1);
+    for c in s[zeros..].chars() {
+        let mut carry = match ALPHABET.iter().position(|&a| a as char == c) {
+            Some(d) => d as u32,
+            None => return Err(ParseBase58Error::InvalidCharacter(c)),
+        };
+        for b in bytes.iter_mut() {
+            carry += (*b as u32) * 58;
+            *b = carry as u8;
+            carry >>= 8;
+        }
+        while 0 < carry {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut ret = vec![0; zeros];
+    ret.extend(bytes.iter().rev());
+    Ok(ret)
+}
+
+/// Appends the checksum to `payload` and encodes it into Base58 string.
+pub fn encode_check(payload: &[u8]) -> String {
+    let mut bytes = Vec::with_capacity(payload.len() + CHECKSUM_LEN);
+    bytes.extend_from_slice(payload);
+    bytes.extend_from_slice(&checksum(payload));
+    encode(&bytes)
+}
+
+/// Decodes Base58Check string `s` , verifies the checksum, and returns the payload.
+pub fn decode_check(s: &str) -> Result<Vec<u8>, ParseBase58Error> {
+    let mut bytes = decode(s)?;
+    if bytes.len() < CHECKSUM_LEN {
+        return Err(ParseBase58Error::TooShort);
+    }
+
+    let payload_len = bytes.len() - CHECKSUM_LEN;
+    if checksum(&bytes[..payload_len]) != bytes[payload_len..] {
+        return Err(ParseBase58Error::InvalidChecksum);
+    }
+
+    bytes.truncate(payload_len);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let cases: &[(&[u8], &str)] = &[
+            (b"", ""),
+            (&[0], "1"),
+            (&[0, 0, 0x28, 0x7f, 0xb4, 0xcd], "11233QC4"),
+            (b"hello world", "StV1DL6CwTryKyV"),
+        ];
+
+        for &(bytes, s) in cases {
+            assert_eq!(s, encode(bytes));
+            assert_eq!(Ok(bytes.to_vec()), decode(s));
+        }
+
+        assert_eq!(Err(ParseBase58Error::InvalidCharacter('0')), decode("10"));
+    }
+
+    #[test]
+    fn check() {
+        // The address of the genesis coinbase of Bitcoin.
+        let s = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+        let payload = decode_check(s).unwrap();
+        assert_eq!(21, payload.len());
+        assert_eq!(0, payload[0]);
+        assert_eq!(s, encode_check(&payload));
+
+        assert_eq!(
+            Err(ParseBase58Error::InvalidChecksum),
+            decode_check("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb")
+        );
+        assert_eq!(Err(ParseBase58Error::TooShort), decode_check("11"));
+    }
+}
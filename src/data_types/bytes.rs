@@ -0,0 +1,253 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `bytes` defines struct `Bytes` .
+
+use super::{CAlloc, CVec};
+use core::hash::{Hash, Hasher};
+use core::ops::{Bound, Deref, RangeBounds};
+use counting_pointer::Asc;
+use std::borrow::Borrow;
+use std::fmt;
+
+/// `Accounted` owns the bytes allocated out of [`CAlloc`] , and increases/decreases the caching
+/// byte size as it is created/dropped.
+///
+/// [`CAlloc`]: crate::data_types::CAlloc
+struct Accounted<O>
+where
+    O: AsRef<[u8]>,
+{
+    owner: O,
+    len: usize,
+}
+
+impl<O> Accounted<O>
+where
+    O: AsRef<[u8]>,
+{
+    fn new(owner: O) -> Self {
+        let len = owner.as_ref().len();
+        mouse_cache_alloc::increase_cache_size(len);
+        Self { owner, len }
+    }
+}
+
+impl<O> Drop for Accounted<O>
+where
+    O: AsRef<[u8]>,
+{
+    fn drop(&mut self) {
+        mouse_cache_alloc::decrease_cache_size(self.len);
+    }
+}
+
+impl<O> AsRef<[u8]> for Accounted<O>
+where
+    O: AsRef<[u8]>,
+{
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.owner.as_ref()
+    }
+}
+
+/// `Bytes` is a reference counted view of immutable bytes.
+///
+/// `Bytes` takes the ownership of the buffer instead of copying it; e.g. `mouse_leveldb::Octets`
+/// returned from LevelDB. The clones and the slices share the same buffer, and it is released
+/// when the last one is dropped.
+///
+/// The byte size of the buffer is counted as the caching byte size while it is alive.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::Bytes;
+///
+/// let bytes = Bytes::from_owner(vec![0, 1, 2, 3, 4]);
+/// let slice = bytes.slice(1..3);
+///
+/// assert_eq!(&[1, 2], slice.as_ref());
+/// assert_eq!(&[0, 1, 2, 3, 4], bytes.as_ref());
+/// ```
+#[derive(Clone)]
+pub struct Bytes {
+    owner: Asc<dyn 'static + Sync + Send + AsRef<[u8]>, CAlloc>,
+    start: usize,
+    end: usize,
+}
+
+impl Bytes {
+    fn from_accounted<O>(owner: O) -> Self
+    where
+        O: 'static + Sync + Send + AsRef<[u8]>,
+    {
+        let end = owner.as_ref().len();
+
+        let asc = Asc::new(owner, CAlloc::default());
+        let (ptr, alloc) = Asc::into_raw_alloc(asc);
+        let ptr = ptr as *const (dyn 'static + Sync + Send + AsRef<[u8]>);
+        let owner = unsafe { Asc::from_raw_alloc(ptr, alloc) };
+
+        Self {
+            owner,
+            start: 0,
+            end,
+        }
+    }
+
+    /// Creates a new instance taking the ownership of `owner` without copying the bytes.
+    ///
+    /// `owner.as_ref()` must always return the same bytes.
+    ///
+    /// The length of the bytes is added to the caching byte size until the last instance
+    /// sharing `owner` is dropped.
+    pub fn from_owner<O>(owner: O) -> Self
+    where
+        O: 'static + Sync + Send + AsRef<[u8]>,
+    {
+        Self::from_accounted(Accounted::new(owner))
+    }
+
+    /// Returns a new instance sharing the buffer with `self` and referring to `range` of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn slice<R>(&self, range: R) -> Self
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end && end <= len, "slice index out of bounds");
+
+        Self {
+            owner: self.owner.clone(),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+}
+
+impl From<CVec<u8>> for Bytes {
+    /// Takes the ownership of `cvec` . ( `CVec` has already been counted as the caching byte
+    /// size.)
+    #[inline]
+    fn from(cvec: CVec<u8>) -> Self {
+        Self::from_accounted(cvec)
+    }
+}
+
+impl From<mouse_leveldb::Octets> for Bytes {
+    /// Takes the ownership of `octets` without copying.
+    #[inline]
+    fn from(octets: mouse_leveldb::Octets) -> Self {
+        Self::from_owner(octets)
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        let owner: &(dyn 'static + Sync + Send + AsRef<[u8]>) = &*self.owner;
+        &owner.as_ref()[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl Borrow<[u8]> for Bytes {
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.deref()
+    }
+}
+
+impl PartialEq for Bytes {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for Bytes {}
+
+impl Hash for Bytes {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_ref(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice() {
+        let bytes = Bytes::from_owner(vec![0, 1, 2, 3, 4, 5]);
+
+        let s = bytes.slice(1..5);
+        assert_eq!(&[1, 2, 3, 4], s.as_ref());
+
+        let s = s.slice(1..=2);
+        assert_eq!(&[2, 3], s.as_ref());
+
+        assert_eq!(true, s.slice(2..).is_empty());
+        assert_eq!(bytes, bytes.slice(..));
+    }
+
+    #[should_panic]
+    #[test]
+    fn slice_out_of_bounds() {
+        let bytes = Bytes::from_owner(vec![0, 1, 2]);
+        let s = bytes.slice(1..);
+        s.slice(..3);
+    }
+
+    #[test]
+    fn from_cvec() {
+        let cvec = CVec::from(&[1, 2, 3][..]);
+        let ptr = cvec.as_ptr();
+
+        let bytes = Bytes::from(cvec);
+        assert_eq!(ptr, bytes.as_ptr());
+    }
+}
@@ -0,0 +1,417 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `codec` provides the canonical binary encoding for the intrinsic data of [`Acid`] .
+//!
+//! The encoding starts with the header; i.e. [`AcidTypeTag`] followed by the version, and the
+//! fields follow it in the order. Each field is one of the followings.
+//!
+//! - unsigned integer: LEB128 in the shortest form.
+//! - signed integer: zigzag encoded, and then LEB128 in the shortest form.
+//! - byte string: the length as an unsigned integer followed by the bytes.
+//! - hash (e.g. [`Id`] ): the bytes without the length.
+//! - list of hashes: the number of the hashes as an unsigned integer followed by the hashes.
+//!
+//! The encoding does not depend on the platform, and each value has only one encoding; [`Decoder`]
+//! rejects the encoding not in the shortest form, and the trailing bytes.
+//!
+//! # Examples
+//!
+//! ```
+//! use mouse::data_types::codec::{Decoder, Encoder};
+//! use mouse::data_types::{CryptoHash, Id};
+//!
+//! let parents = [Id::calculate(b"a"), Id::calculate(b"b")];
+//!
+//! let mut encoder = Encoder::new(1, 0);
+//! encoder.u64(300).bytes(b"payload").ids(&parents);
+//! let encoded = encoder.finish();
+//!
+//! let mut decoder = Decoder::new(&encoded).unwrap();
+//! assert_eq!(1, decoder.tag());
+//! assert_eq!(0, decoder.version());
+//! assert_eq!(300, decoder.u64().unwrap());
+//! assert_eq!(b"payload", decoder.bytes().unwrap());
+//! assert_eq!(&parents[..], &decoder.ids::<Id>().unwrap()[..]);
+//! decoder.finish().unwrap();
+//! ```
+//!
+//! [`Acid`]: crate::data_types::Acid
+//! [`AcidTypeTag`]: crate::data_types::AcidTypeTag
+//! [`Id`]: crate::data_types::Id
+//! [`Decoder`]: self::Decoder
+
+use super::{AcidTypeTag, CryptoHash};
+use std::error;
+use std::fmt;
+
+/// The maximum length of the LEB128 encoding of `u64` .
+const MAX_VARINT_LEN: usize = 10;
+
+/// `Error` is the reason why [`Decoder`] fails.
+///
+/// [`Decoder`]: self::Decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The bytes end in the middle of a field.
+    UnexpectedEnd,
+    /// An integer is not encoded in the shortest form.
+    NonCanonical,
+    /// An integer is too large for the type.
+    Overflow,
+    /// Some bytes are left after the last field.
+    TrailingBytes,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnexpectedEnd => f.write_str("The encoded bytes end unexpectedly."),
+            Error::NonCanonical => f.write_str("The integer is not in the canonical form."),
+            Error::Overflow => f.write_str("The integer overflows."),
+            Error::TrailingBytes => f.write_str("Some bytes are left after the last field."),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// `Encode` is implemented by the types which [`Encoder`] can write.
+///
+/// A struct can implement it by encoding the fields in the order.
+///
+/// [`Encoder`]: self::Encoder
+pub trait Encode {
+    /// Writes `self` to `encoder` .
+    fn encode(&self, encoder: &mut Encoder);
+}
+
+/// `Decode` is implemented by the types which [`Decoder`] can read.
+///
+/// [`Decoder`]: self::Decoder
+pub trait Decode: Sized {
+    /// Reads an instance from `decoder` .
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error>;
+}
+
+/// `Encoder` builds the canonical encoding field by field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    /// Creates a new instance writing the header.
+    pub fn new(tag: AcidTypeTag, version: u8) -> Self {
+        Self {
+            buffer: vec![tag, version],
+        }
+    }
+
+    /// Writes an unsigned integer.
+    pub fn u64(&mut self, mut val: u64) -> &mut Self {
+        while 0x80 <= val {
+            self.buffer.push((val as u8) | 0x80);
+            val >>= 7;
+        }
+        self.buffer.push(val as u8);
+        self
+    }
+
+    /// Writes a signed integer.
+    pub fn i64(&mut self, val: i64) -> &mut Self {
+        self.u64(((val << 1) ^ (val >> 63)) as u64)
+    }
+
+    /// Writes a byte string.
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u64(bytes.len() as u64);
+        self.buffer.extend_from_slice(bytes);
+        self
+    }
+
+    /// Writes a hash.
+    pub fn hash<H: CryptoHash>(&mut self, hash: &H) -> &mut Self {
+        self.buffer.extend_from_slice(hash.as_ref());
+        self
+    }
+
+    /// Writes a list of hashes.
+    pub fn ids<H: CryptoHash>(&mut self, ids: &[H]) -> &mut Self {
+        self.u64(ids.len() as u64);
+        for id in ids {
+            self.hash(id);
+        }
+        self
+    }
+
+    /// Writes `val` .
+    pub fn encode<T: Encode + ?Sized>(&mut self, val: &T) -> &mut Self {
+        val.encode(self);
+        self
+    }
+
+    /// Consumes `self` and returns the encoded bytes.
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// `Decoder` reads the canonical encoding field by field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoder<'a> {
+    tag: AcidTypeTag,
+    version: u8,
+    rest: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    /// Creates a new instance reading the header.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < 2 {
+            return Err(Error::UnexpectedEnd);
+        }
+
+        Ok(Self {
+            tag: bytes[0],
+            version: bytes[1],
+            rest: &bytes[2..],
+        })
+    }
+
+    /// Returns the [`AcidTypeTag`] in the header.
+    ///
+    /// [`AcidTypeTag`]: crate::data_types::AcidTypeTag
+    pub fn tag(&self) -> AcidTypeTag {
+        self.tag
+    }
+
+    /// Returns the version in the header.
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.rest.len() < len {
+            return Err(Error::UnexpectedEnd);
+        }
+
+        let (ret, rest) = self.rest.split_at(len);
+        self.rest = rest;
+        Ok(ret)
+    }
+
+    /// Reads an unsigned integer.
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        let mut val: u64 = 0;
+
+        for i in 0..MAX_VARINT_LEN {
+            let byte = self.take(1)?[0];
+            let bits = (byte & 0x7f) as u64;
+
+            // The 10th byte can hold only 1 bit.
+            if i == MAX_VARINT_LEN - 1 && 1 < bits {
+                return Err(Error::Overflow);
+            }
+            val |= bits << (7 * i);
+
+            if byte & 0x80 == 0 {
+                // The last byte must not be 0 except for the first byte.
+                if byte == 0 && i != 0 {
+                    return Err(Error::NonCanonical);
+                }
+                return Ok(val);
+            }
+        }
+
+        Err(Error::Overflow)
+    }
+
+    /// Reads a signed integer.
+    pub fn i64(&mut self) -> Result<i64, Error> {
+        let val = self.u64()?;
+        Ok(((val >> 1) as i64) ^ -((val & 1) as i64))
+    }
+
+    /// Reads a byte string.
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u64()?;
+        if (self.rest.len() as u64) < len {
+            return Err(Error::UnexpectedEnd);
+        }
+        self.take(len as usize)
+    }
+
+    /// Reads a hash.
+    pub fn hash<H: CryptoHash>(&mut self) -> Result<H, Error> {
+        let bytes = self.take(H::LEN)?;
+        Ok(unsafe { H::copy_bytes(bytes) })
+    }
+
+    /// Reads a list of hashes.
+    pub fn ids<H: CryptoHash>(&mut self) -> Result<Vec<H>, Error> {
+        let count = self.u64()?;
+
+        // Not to allocate too much for the broken bytes.
+        if (self.rest.len() / H::LEN) < count as usize {
+            return Err(Error::UnexpectedEnd);
+        }
+
+        (0..count).map(|_| self.hash()).collect()
+    }
+
+    /// Reads an instance of `T` .
+    pub fn decode<T: Decode>(&mut self) -> Result<T, Error> {
+        T::decode(self)
+    }
+
+    /// Returns an error if some bytes are left.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.rest.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes)
+        }
+    }
+}
+
+impl Encode for u64 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.u64(*self);
+    }
+}
+
+impl Decode for u64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.u64()
+    }
+}
+
+impl Encode for i64 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.i64(*self);
+    }
+}
+
+impl Decode for i64 {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.i64()
+    }
+}
+
+impl Encode for [u8] {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.bytes(self);
+    }
+}
+
+impl Decode for Vec<u8> {
+    fn decode(decoder: &mut Decoder) -> Result<Self, Error> {
+        decoder.bytes().map(Vec::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::Id;
+
+    fn encode_u64(val: u64) -> Vec<u8> {
+        let mut encoder = Encoder::new(0, 0);
+        encoder.u64(val);
+        encoder.finish()[2..].to_vec()
+    }
+
+    fn decode_u64(bytes: &[u8]) -> Result<u64, Error> {
+        let mut encoded = vec![0, 0];
+        encoded.extend_from_slice(bytes);
+        let mut decoder = Decoder::new(&encoded)?;
+        let ret = decoder.u64()?;
+        decoder.finish()?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn varint() {
+        assert_eq!(vec![0x00], encode_u64(0));
+        assert_eq!(vec![0x7f], encode_u64(127));
+        assert_eq!(vec![0x80, 0x01], encode_u64(128));
+        assert_eq!(vec![0xac, 0x02], encode_u64(300));
+        assert_eq!(10, encode_u64(u64::MAX).len());
+
+        for &val in &[0, 1, 127, 128, 300, 1 << 35, u64::MAX - 1, u64::MAX] {
+            assert_eq!(Ok(val), decode_u64(&encode_u64(val)));
+        }
+    }
+
+    #[test]
+    fn varint_non_canonical() {
+        assert_eq!(Err(Error::NonCanonical), decode_u64(&[0x80, 0x00]));
+        assert_eq!(Err(Error::NonCanonical), decode_u64(&[0xff, 0x80, 0x00]));
+        assert_eq!(Err(Error::UnexpectedEnd), decode_u64(&[0x80]));
+        assert_eq!(Err(Error::TrailingBytes), decode_u64(&[0x01, 0x00]));
+
+        let mut overflow = vec![0xff; 9];
+        overflow.push(0x02);
+        assert_eq!(Err(Error::Overflow), decode_u64(&overflow));
+    }
+
+    #[test]
+    fn signed() {
+        for &val in &[0, 1, -1, 63, -64, 64, i64::MAX, i64::MIN] {
+            let mut encoder = Encoder::new(0, 0);
+            encoder.i64(val);
+            let encoded = encoder.finish();
+
+            let mut decoder = Decoder::new(&encoded).unwrap();
+            assert_eq!(Ok(val), decoder.i64());
+        }
+
+        let mut encoder = Encoder::new(0, 0);
+        encoder.i64(-1);
+        assert_eq!(vec![0, 0, 0x01], encoder.finish());
+    }
+
+    #[test]
+    fn bytes_and_ids() {
+        let ids = [Id::calculate(b"a"), Id::calculate(b"b")];
+
+        let mut encoder = Encoder::new(3, 1);
+        encoder.bytes(&[]).bytes(b"abc").ids(&ids).encode(&7_u64);
+        let encoded = encoder.finish();
+
+        let mut decoder = Decoder::new(&encoded).unwrap();
+        assert_eq!(3, decoder.tag());
+        assert_eq!(1, decoder.version());
+        assert_eq!(Ok(&b""[..]), decoder.bytes());
+        assert_eq!(Ok(&b"abc"[..]), decoder.bytes());
+        assert_eq!(Ok(ids.to_vec()), decoder.ids::<Id>());
+        assert_eq!(Ok(7), decoder.decode::<u64>());
+        assert_eq!(Ok(()), decoder.finish());
+
+        // Truncated
+        let truncated = &encoded[..encoded.len() - 2];
+        let mut decoder = Decoder::new(truncated).unwrap();
+        decoder.bytes().unwrap();
+        decoder.bytes().unwrap();
+        assert_eq!(Err(Error::UnexpectedEnd), decoder.ids::<Id>());
+    }
+
+    #[test]
+    fn header() {
+        assert_eq!(Err(Error::UnexpectedEnd), Decoder::new(&[1]));
+    }
+}
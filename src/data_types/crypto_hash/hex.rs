@@ -0,0 +1,138 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `hex` defines struct `HexDisplay` and function `parse_hex` .
+
+use core::fmt::{self, Debug, Display};
+use std::error::Error;
+
+/// Byte count of the stack buffer. `HexDisplay` formats at most `BUFFER_LEN / 2` bytes at once.
+const BUFFER_LEN: usize = 64;
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+/// `HexDisplay` is an adapter to format bytes as lower case hex string without heap allocation.
+///
+/// `HexDisplay` writes the hex characters into a stack buffer and passes the buffer to the
+/// formatter.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::crypto_hash::{HexDisplay, Sha256};
+/// use mouse::data_types::CryptoHash;
+///
+/// let bytes: &[u8] = &[0x01, 0xab];
+/// assert_eq!("01ab", format!("{}", HexDisplay::new(bytes)));
+///
+/// let hash = Sha256::zeroed();
+/// assert_eq!("0".repeat(64), hash.display_hex().to_string());
+/// ```
+#[derive(Clone, Copy)]
+pub struct HexDisplay<'a>(&'a [u8]);
+
+impl<'a> HexDisplay<'a> {
+    /// Creates a new instance to format `bytes` .
+    #[inline]
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl Display for HexDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut buffer = [0u8; BUFFER_LEN];
+
+        for chunk in self.0.chunks(BUFFER_LEN / 2) {
+            for (i, b) in chunk.iter().enumerate() {
+                buffer[2 * i] = HEX_CHARS[(b >> 4) as usize];
+                buffer[2 * i + 1] = HEX_CHARS[(b & 0x0f) as usize];
+            }
+
+            // `buffer` is filled with ASCII characters.
+            let s = unsafe { core::str::from_utf8_unchecked(&buffer[..2 * chunk.len()]) };
+            f.write_str(s)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for HexDisplay<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// `ParseHexError` is the error returned by [`parse_hex`] .
+///
+/// [`parse_hex`]: self::parse_hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseHexError {
+    /// The length of the string is not twice as long as the buffer.
+    InvalidLength(usize),
+    /// The string includes a character which is not a hex digit.
+    InvalidCharacter(char),
+}
+
+impl Display for ParseHexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => write!(f, "Invalid length of hex string: {}", len),
+            Self::InvalidCharacter(c) => write!(f, "Invalid character in hex string: {:?}", c),
+        }
+    }
+}
+
+impl Error for ParseHexError {}
+
+fn hex_value(c: u8) -> Result<u8, ParseHexError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(ParseHexError::InvalidCharacter(c as char)),
+    }
+}
+
+/// Parses hex string `s` (either lower case or upper case) and fills `buffer` .
+///
+/// The length of `s` must be twice as long as that of `buffer` .
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::crypto_hash::parse_hex;
+///
+/// let mut buffer = [0; 2];
+/// parse_hex("01aB", &mut buffer).unwrap();
+/// assert_eq!([0x01, 0xab], buffer);
+///
+/// assert!(parse_hex("01a", &mut buffer).is_err());
+/// assert!(parse_hex("01ag", &mut buffer).is_err());
+/// ```
+pub fn parse_hex(s: &str, buffer: &mut [u8]) -> Result<(), ParseHexError> {
+    if s.len() != 2 * buffer.len() {
+        return Err(ParseHexError::InvalidLength(s.len()));
+    }
+
+    for (b, pair) in buffer.iter_mut().zip(s.as_bytes().chunks(2)) {
+        *b = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+    }
+
+    Ok(())
+}
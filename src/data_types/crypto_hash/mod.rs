@@ -16,12 +16,14 @@
 
 //! `crypto_hash` defines traits and structs relating to cryptographic hash.
 
+mod hex;
 mod sha256;
 
 use core::hash::Hash;
 use core::mem::MaybeUninit;
 use std::borrow::Borrow;
 
+pub use hex::{parse_hex, HexDisplay, ParseHexError};
 pub use sha256::{Sha256, Sha256Hasher};
 
 /// Traits for wrapper of `[u8]` indicates crypto hash like 'sha256'.
@@ -61,8 +63,15 @@ pub trait CryptoHash:
     /// # Safety
     ///
     /// The behavior is undefined if `bytes.len` does not equal to `Self::LEN` .
+    ///
+    /// # Panics
+    ///
+    /// If feature "strict" is specified, panics instead of the undefined behavior.
     #[inline]
     unsafe fn copy_bytes(bytes: &[u8]) -> Self {
+        #[cfg(feature = "strict")]
+        assert_eq!(Self::LEN, bytes.len());
+
         // Assume the implementation is just a wrapper of '[u8]' and don't have any other property.
         let mut ret = MaybeUninit::uninit();
 
@@ -72,6 +81,17 @@ pub trait CryptoHash:
         ret.assume_init()
     }
 
+    /// Copies `bytes` and creates a new instance if `bytes.len` equals to `Self::LEN` ; otherwise
+    /// returns `None` .
+    #[inline]
+    fn try_copy_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == Self::LEN {
+            Some(unsafe { Self::copy_bytes(bytes) })
+        } else {
+            None
+        }
+    }
+
     /// Calculates crypto hash of `bytes` and returns a new instance.
     fn calculate(bytes: &[u8]) -> Self {
         <Self::Hasher as CryptoHasher>::calculate(bytes)
@@ -83,6 +103,16 @@ pub trait CryptoHash:
         Self::LEN
     }
 
+    /// Returns an adapter to format `self` as lower case hex string without heap allocation.
+    ///
+    /// See also [`HexDisplay`] .
+    ///
+    /// [`HexDisplay`]: self::HexDisplay
+    #[inline]
+    fn display_hex(&self) -> HexDisplay {
+        HexDisplay::new(self.as_ref())
+    }
+
     /// Provides a raw pointer to the wrapped `[u8]` .
     #[inline]
     fn as_ptr(&self) -> *const u8 {
@@ -16,9 +16,11 @@
 
 //! `sha256` defines struct `Sha256` and `Sha256Hasher` .
 
-use super::{CryptoHash, CryptoHasher};
+use super::{parse_hex, CryptoHash, CryptoHasher, ParseHexError};
+use core::fmt;
 use core::mem::MaybeUninit;
 use core::ops::{Deref, DerefMut};
+use core::str::FromStr;
 use crypto::digest::Digest;
 use std::borrow::Borrow;
 
@@ -26,10 +28,50 @@ const HASH_LEN: usize = 32;
 
 /// `Sha256` is a wrapper of `[u8; 32]` and implements [`CryptoHash`] .
 ///
+/// It is formatted and parsed as the lower case hex string of 64 characters. It hashes same to
+/// `[u8]` , so that a hash table keyed by `Sha256` can be looked up by the slice.
+///
 /// [`CryptoHash`]: crate::data_types::CryptoHash
-#[derive(Debug, Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::crypto_hash::Sha256;
+/// use mouse::data_types::CryptoHash;
+///
+/// let hash = Sha256::calculate(b"foo");
+/// let s = hash.to_string();
+/// assert_eq!(64, s.len());
+/// assert_eq!(hash, s.parse().unwrap());
+///
+/// let set: std::collections::HashSet<Sha256> = vec![hash].into_iter().collect();
+/// assert!(set.contains(hash.as_ref() as &[u8]));
+/// ```
+#[derive(Clone, Copy, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct Sha256([u8; HASH_LEN]);
 
+impl fmt::Display for Sha256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.display_hex(), f)
+    }
+}
+
+impl fmt::Debug for Sha256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sha256({})", self.display_hex())
+    }
+}
+
+impl FromStr for Sha256 {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut buffer = [0; HASH_LEN];
+        parse_hex(s, &mut buffer)?;
+        Ok(Self(buffer))
+    }
+}
+
 impl AsRef<[u8]> for Sha256 {
     #[inline]
     fn as_ref(&self) -> &[u8] {
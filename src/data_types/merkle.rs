@@ -0,0 +1,315 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `merkle` calculates the Merkle tree over [`CryptoHash`] s; e.g. the ids of the acids in a
+//! block.
+//!
+//! The tree is that of RFC 6962 (Certificate Transparency); a leaf is hashed with prefix 0x00,
+//! an internal node with prefix 0x01, and the tree of `n` leaves is split at the largest power of
+//! 2 less than `n` . No node is duplicated, so different leaves never make the same root.
+//!
+//! [`CryptoHash`]: crate::data_types::CryptoHash
+
+use super::{CryptoHash, CryptoHasher};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+fn leaf_hash<H: CryptoHash>(leaf: &H) -> H {
+    let mut hasher = H::Hasher::default();
+    hasher.write(&[LEAF_PREFIX]);
+    hasher.write(leaf.as_ref());
+    hasher.finish()
+}
+
+fn node_hash<H: CryptoHash>(left: &H, right: &H) -> H {
+    let mut hasher = H::Hasher::default();
+    hasher.write(&[NODE_PREFIX]);
+    hasher.write(left.as_ref());
+    hasher.write(right.as_ref());
+    hasher.finish()
+}
+
+/// Returns the largest power of 2 less than `n` .
+fn split_point(n: usize) -> usize {
+    debug_assert!(1 < n);
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// Calculates the Merkle root of `leaves` .
+///
+/// The root of no leaf is the hash of the empty bytes.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::merkle;
+/// use mouse::data_types::{CryptoHash, Id};
+///
+/// let ids = [Id::calculate(b"a"), Id::calculate(b"b"), Id::calculate(b"c")];
+/// let root = merkle::root(&ids);
+///
+/// let proof = merkle::prove(&ids, 2).unwrap();
+/// assert!(proof.verify(&ids[2], &root));
+/// ```
+pub fn root<H: CryptoHash>(leaves: &[H]) -> H {
+    match leaves.len() {
+        0 => H::calculate(&[]),
+        1 => leaf_hash(&leaves[0]),
+        n => {
+            let k = split_point(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// `Builder` calculates the Merkle root adding the leaves one by one.
+///
+/// It keeps only `O(log n)` hashes for `n` leaves.
+#[derive(Debug, Clone)]
+pub struct Builder<H> {
+    /// The roots of the perfect subtrees and the number of the leaves of each, in the order of
+    /// the leaves. The number of the leaves decreases strictly.
+    subtrees: Vec<(H, usize)>,
+    len: usize,
+}
+
+impl<H> Default for Builder<H> {
+    fn default() -> Self {
+        Self {
+            subtrees: Vec::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<H: CryptoHash> Builder<H> {
+    /// Creates a new empty instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of the leaves added.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no leaf is added.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `leaf` to the end.
+    pub fn push(&mut self, leaf: &H) {
+        let mut hash = leaf_hash(leaf);
+        let mut size = 1;
+
+        while let Some(&(last, last_size)) = self.subtrees.last() {
+            if last_size != size {
+                break;
+            }
+            self.subtrees.pop();
+            hash = node_hash(&last, &hash);
+            size *= 2;
+        }
+
+        self.subtrees.push((hash, size));
+        self.len += 1;
+    }
+
+    /// Returns the Merkle root of the leaves added so far.
+    ///
+    /// It is the same to function [`root`] .
+    ///
+    /// [`root`]: self::root
+    pub fn root(&self) -> H {
+        let mut it = self.subtrees.iter().rev();
+        match it.next() {
+            None => H::calculate(&[]),
+            Some(&(last, _)) => it.fold(last, |acc, (hash, _)| node_hash(hash, &acc)),
+        }
+    }
+}
+
+/// `Proof` is the inclusion proof of a leaf; i.e. the audit path of RFC 6962.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof<H> {
+    index: usize,
+    leaf_count: usize,
+    path: Vec<H>,
+}
+
+/// Creates the inclusion proof of `leaves[index]` , or returns `None` if `index` is out of
+/// range.
+pub fn prove<H: CryptoHash>(leaves: &[H], index: usize) -> Option<Proof<H>> {
+    if leaves.len() <= index {
+        return None;
+    }
+
+    let leaf_count = leaves.len();
+    let mut path = Vec::new();
+    let mut leaves = leaves;
+    let mut i = index;
+
+    // Collects from the root to the leaf, and reverses later.
+    while 1 < leaves.len() {
+        let k = split_point(leaves.len());
+        if i < k {
+            path.push(root(&leaves[k..]));
+            leaves = &leaves[..k];
+        } else {
+            path.push(root(&leaves[..k]));
+            leaves = &leaves[k..];
+            i -= k;
+        }
+    }
+    path.reverse();
+
+    Some(Proof {
+        index,
+        leaf_count,
+        path,
+    })
+}
+
+impl<H: CryptoHash> Proof<H> {
+    /// Returns the index of the leaf.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of the leaves of the tree.
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Provides a reference to the sibling hashes from the leaf to the root.
+    pub fn path(&self) -> &[H] {
+        &self.path
+    }
+
+    /// Returns `true` if `leaf` is at `self.index()` in the tree of `root` .
+    pub fn verify(&self, leaf: &H, root: &H) -> bool {
+        if self.leaf_count <= self.index {
+            return false;
+        }
+
+        let mut f = self.index;
+        let mut s = self.leaf_count - 1;
+        let mut hash = leaf_hash(leaf);
+
+        for sibling in self.path.iter() {
+            if s == 0 {
+                return false;
+            }
+
+            if f & 1 == 1 || f == s {
+                hash = node_hash(sibling, &hash);
+                if f & 1 == 0 {
+                    while f & 1 == 0 && f != 0 {
+                        f >>= 1;
+                        s >>= 1;
+                    }
+                }
+            } else {
+                hash = node_hash(&hash, sibling);
+            }
+
+            f >>= 1;
+            s >>= 1;
+        }
+
+        s == 0 && hash == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::Id;
+
+    fn leaves(n: usize) -> Vec<Id> {
+        (0..n).map(|i| Id::calculate(&i.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn root_() {
+        let l = leaves(3);
+        assert_eq!(Id::calculate(&[]), root::<Id>(&[]));
+        assert_eq!(leaf_hash(&l[0]), root(&l[..1]));
+
+        // RFC 6962 splits 3 leaves into 2 and 1.
+        let expected = node_hash(
+            &node_hash(&leaf_hash(&l[0]), &leaf_hash(&l[1])),
+            &leaf_hash(&l[2]),
+        );
+        assert_eq!(expected, root(&l));
+
+        // A leaf is distinguished from an internal node.
+        assert_ne!(root(&l[..2]), root(&[root(&l[..2])]));
+    }
+
+    #[test]
+    fn builder() {
+        let l = leaves(33);
+        let mut builder = Builder::new();
+        assert_eq!(root::<Id>(&[]), builder.root());
+
+        for (i, leaf) in l.iter().enumerate() {
+            builder.push(leaf);
+            assert_eq!(i + 1, builder.len());
+            assert_eq!(root(&l[..=i]), builder.root());
+        }
+    }
+
+    #[test]
+    fn prove_verify() {
+        for n in 1..=17 {
+            let l = leaves(n);
+            let r = root(&l);
+
+            for i in 0..n {
+                let proof = prove(&l, i).unwrap();
+                assert_eq!(true, proof.verify(&l[i], &r));
+
+                let other = (i + 1) % n;
+                if other != i {
+                    assert_eq!(false, proof.verify(&l[other], &r));
+                }
+                assert_eq!(false, proof.verify(&l[i], &Id::zeroed()));
+            }
+            assert_eq!(None, prove(&l, n));
+        }
+    }
+
+    #[test]
+    fn tampered_proof() {
+        let l = leaves(5);
+        let r = root(&l);
+        let mut proof = prove(&l, 3).unwrap();
+
+        proof.path[0] = Id::zeroed();
+        assert_eq!(false, proof.verify(&l[3], &r));
+
+        let mut proof = prove(&l, 3).unwrap();
+        proof.index = 2;
+        assert_eq!(false, proof.verify(&l[3], &r));
+    }
+}
@@ -19,20 +19,32 @@
 
 mod acid;
 mod acid_chain_relation;
+pub mod base58;
+mod bytes;
 mod chain_index;
+pub mod codec;
 pub mod crypto_hash;
+pub mod merkle;
 mod resource;
+pub mod signature;
 
 use crate::{Config, ModuleEnvironment};
 pub use acid::{Acid, CAcid, Id};
 pub use acid_chain_relation::AcidChainRelation;
+pub use bytes::Bytes;
 pub use chain_index::ChainIndex;
 use clap::App;
-use core::iter::IntoIterator;
-use core::ops::{Deref, DerefMut, Index, IndexMut};
+use core::cmp::Ordering;
+use core::hash::{Hash, Hasher};
+use core::iter::{FromIterator, IntoIterator};
+use core::ops::{Bound, Deref, DerefMut, Index, IndexMut, RangeBounds};
+use core::ptr;
 use core::slice::{Iter, IterMut, SliceIndex};
 pub use crypto_hash::{CryptoHash, CryptoHasher};
-pub use resource::{AssetValue, Resource, ResourceId, RESOURCE_ID_BUFFER_CAPACITY};
+pub use resource::{
+    AssetOverflow, AssetValue, Resource, ResourceId, ResourceIdBuf, ResourceIdTooLong,
+    RESOURCE_ID_BUFFER_CAPACITY,
+};
 use std::borrow::{Borrow, BorrowMut};
 use std::error::Error;
 
@@ -44,12 +56,14 @@ pub type BlockHeight = i64;
 /// `Environment` implements `ModuleEnvironment` .
 pub struct Environment {
     acid_deserializer: AcidDeserializer,
+    acid_deserializers: [Option<AcidDeserializer>; 256],
 }
 
 impl Default for Environment {
     fn default() -> Self {
         Self {
             acid_deserializer: default_acid_deserializer,
+            acid_deserializers: [None; 256],
         }
     }
 }
@@ -59,11 +73,11 @@ impl ModuleEnvironment for Environment {
         app
     }
 
-    unsafe fn check(&mut self, _: &Config) -> Result<(), Box<dyn Error>> {
+    unsafe fn check(&mut self, _: &Config) -> Result<(), crate::Error> {
         Ok(())
     }
 
-    unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
         Ok(())
     }
 }
@@ -90,17 +104,60 @@ impl Environment {
     pub fn set_acid_deserializer(&mut self, deserializer: AcidDeserializer) {
         self.acid_deserializer = deserializer;
     }
+
+    /// Registor `deserializer` for the intrinsic data whose [`AcidTypeTag`] is `tag` .
+    ///
+    /// If a deserializer has already been registered for `tag` , it is replaced.
+    /// The deserializer set by [`set_acid_deserializer`] is used for the tags that no deserializer
+    /// is registered for.
+    ///
+    /// See also function [`deserialize_acid`] .
+    ///
+    /// [`set_acid_deserializer`]: Self::set_acid_deserializer
+    /// [`deserialize_acid`]: crate::deserialize_acid
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{deserialize_acid, AcidDeserializer, Environment};
+    ///
+    /// let deserializer: AcidDeserializer = |_: &[u8]| Err(Box::from("tag 1"));
+    ///
+    /// let mut env = Environment::default();
+    /// env.register_acid_deserializer(1, deserializer);
+    ///
+    /// let e = deserialize_acid(&[1, 0], &env).err().unwrap();
+    /// assert_eq!("tag 1", e.to_string());
+    ///
+    /// let e = deserialize_acid(&[2, 0], &env).err().unwrap();
+    /// assert_ne!("tag 1", e.to_string());
+    /// ```
+    pub fn register_acid_deserializer(&mut self, tag: AcidTypeTag, deserializer: AcidDeserializer) {
+        self.acid_deserializers[tag as usize] = Some(deserializer);
+    }
 }
 
 /// Function type to deserialize `Acid` .
 pub type AcidDeserializer = fn(&[u8]) -> Result<CAcid, Box<dyn Error>>;
 
+/// `AcidTypeTag` identifies the type of [`Acid`] , and it is the first byte of the intrinsic data.
+///
+/// If the intrinsic data is encoded in DER, for example, it is the identifier octet.
+///
+/// See also method [`Environment::register_acid_deserializer`] .
+///
+/// [`Acid`]: crate::data_types::Acid
+pub type AcidTypeTag = u8;
+
 fn default_acid_deserializer(_: &[u8]) -> Result<CAcid, Box<dyn Error>> {
     Err(Box::from("Not specified how to deserialize 'Acid'."))
 }
 
 /// Deserializes `bytes` using deserializer registored to `env` .
 ///
+/// If a deserializer is registered for the [`AcidTypeTag`] of `bytes` (i.e. the first byte,) it
+/// is used; otherwise the deserializer set by [`Environment::set_acid_deserializer`] is used.
+///
 /// # Examples
 ///
 /// ```
@@ -114,7 +171,15 @@ fn default_acid_deserializer(_: &[u8]) -> Result<CAcid, Box<dyn Error>> {
 /// assert_eq!(true, deserialize_acid(&[], &env).is_err());
 /// ```
 pub fn deserialize_acid(bytes: &[u8], env: &Environment) -> Result<CAcid, Box<dyn Error>> {
-    (env.acid_deserializer)(bytes)
+    let registered = match bytes.first() {
+        None => None,
+        Some(&tag) => env.acid_deserializers[tag as usize],
+    };
+
+    match registered {
+        Some(deserializer) => deserializer(bytes),
+        None => (env.acid_deserializer)(bytes),
+    }
 }
 
 /// `CAlloc` implements `GlobalAlloc` and behaves like `std::alloc::System` except for that
@@ -130,13 +195,60 @@ pub use mouse_cache_alloc::MmapAlloc as CMmapAlloc;
 
 /// `CVec` behaves like `std::vec::Vec` except for the followings.
 ///
-/// - `CVec` does not implement methods to cost 'O(n)' CPU time on purpose.
+/// - `CVec` does not implement most of the methods to cost 'O(n)' CPU time on purpose. (The
+///   exceptions say so in the document.)
 /// - `CVec` uses [`CAlloc`] to allocate/deallocate heap memory.
-#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Default)]
 pub struct CVec<T> {
     buffer: mouse_containers::Vec<T, CAlloc>,
 }
 
+// The comparison and the hash are delegated to the slice, because the standard library
+// specializes them for `[u8]` (i.e. 'memcmp' and a single `Hasher::write` call) instead of the
+// element-wise loop.
+
+impl<T> PartialEq for CVec<T>
+where
+    T: PartialEq,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl<T> Eq for CVec<T> where T: Eq {}
+
+impl<T> PartialOrd for CVec<T>
+where
+    T: PartialOrd,
+{
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.as_ref().partial_cmp(other.as_ref())
+    }
+}
+
+impl<T> Ord for CVec<T>
+where
+    T: Ord,
+{
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_ref().cmp(other.as_ref())
+    }
+}
+
+impl<T> Hash for CVec<T>
+where
+    T: Hash,
+{
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
 impl<T> From<Vec<T>> for CVec<T> {
     fn from(vec: Vec<T>) -> Self {
         unsafe {
@@ -253,6 +365,135 @@ impl<'a, T> IntoIterator for &'a mut CVec<T> {
     }
 }
 
+impl<T> IntoIterator for CVec<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    /// Creates a consuming iterator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<String>::new();
+    /// cvec.push(String::from("a"));
+    /// cvec.push(String::from("b"));
+    ///
+    /// let v: Vec<String> = cvec.into_iter().collect();
+    /// assert_eq!(vec!["a", "b"], v);
+    /// ```
+    fn into_iter(mut self) -> Self::IntoIter {
+        let end = self.len();
+        unsafe {
+            // 'IntoIter' owns the elements. 'self' only owns the buffer.
+            self.set_len(0);
+        }
+
+        IntoIter {
+            vec: self,
+            current: 0,
+            end,
+        }
+    }
+}
+
+/// `IntoIter` is a consuming iterator of [`CVec`] .
+///
+/// [`CVec`]: self::CVec
+pub struct IntoIter<T> {
+    /// The length is always 0 and the elements are owned by `self` .
+    vec: CVec<T>,
+    current: usize,
+    end: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.current == self.end {
+            None
+        } else {
+            let ret = unsafe { ptr::read(self.vec.as_ptr().add(self.current)) };
+            self.current += 1;
+            Some(ret)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.current;
+        (n, Some(n))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.current == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { ptr::read(self.vec.as_ptr().add(self.end)) })
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> Drop for IntoIter<T> {
+    fn drop(&mut self) {
+        // Drops the elements not yielded yet, and then 'self.vec' deallocates the buffer.
+        self.for_each(drop);
+    }
+}
+
+impl<T> FromIterator<T> for CVec<T> {
+    /// Collects the elements into a new instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let cvec: CVec<u8> = (0..4).collect();
+    /// assert_eq!(&[0, 1, 2, 3], cvec.as_ref());
+    /// ```
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+impl<T> Extend<T> for CVec<T> {
+    /// Appends the elements to the end, reserving the lower bound of the size hint in advance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.push(0);
+    /// cvec.extend(1..3);
+    /// assert_eq!(&[0, 1, 2], cvec.as_ref());
+    /// ```
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        iter.for_each(|val| self.push(val));
+    }
+}
+
+impl<'a, T> Extend<&'a T> for CVec<T>
+where
+    T: 'a + Copy,
+{
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
 impl<T> CVec<T> {
     /// Clones and appends all the elements in `vals` to the end of `self` .
     ///
@@ -526,3 +767,373 @@ impl<T> CVec<T> {
         self.buffer.clear();
     }
 }
+
+impl<T> CVec<T> {
+    /// Inserts `val` at `index` , shifting all the elements after it to the right.
+    ///
+    /// This method costs 'O(n)' CPU time where 'n' is `self.len() - index` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.extend_from_slice(&[0, 1, 2]);
+    ///
+    /// cvec.insert(1, 5);
+    /// assert_eq!(&[0, 5, 1, 2], cvec.as_ref());
+    ///
+    /// cvec.insert(4, 6);
+    /// assert_eq!(&[0, 5, 1, 2, 6], cvec.as_ref());
+    /// ```
+    pub fn insert(&mut self, index: usize, val: T) {
+        let len = self.len();
+        assert!(index <= len, "insertion index out of bounds");
+
+        self.reserve(1);
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            ptr::copy(ptr, ptr.add(1), len - index);
+            ptr::write(ptr, val);
+            self.set_len(len + 1);
+        }
+    }
+
+    /// Removes and returns the element at `index` , shifting all the elements after it to the
+    /// left.
+    ///
+    /// This method costs 'O(n)' CPU time where 'n' is `self.len() - index` .
+    /// Use [`swap_remove`] if the order does not matter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// [`swap_remove`]: Self::swap_remove
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.extend_from_slice(&[0, 1, 2]);
+    ///
+    /// assert_eq!(1, cvec.remove(1));
+    /// assert_eq!(&[0, 2], cvec.as_ref());
+    /// ```
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "removal index out of bounds");
+
+        unsafe {
+            let ptr = self.as_mut_ptr().add(index);
+            let ret = ptr::read(ptr);
+            ptr::copy(ptr.add(1), ptr, len - index - 1);
+            self.set_len(len - 1);
+            ret
+        }
+    }
+
+    /// Removes and returns the element at `index` , replacing it with the last element.
+    ///
+    /// This method does not preserve the order, but costs only 'O(1)' CPU time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.extend_from_slice(&[0, 1, 2, 3]);
+    ///
+    /// assert_eq!(1, cvec.swap_remove(1));
+    /// assert_eq!(&[0, 3, 2], cvec.as_ref());
+    /// ```
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(index < len, "swap_remove index out of bounds");
+
+        unsafe {
+            let base = self.as_mut_ptr();
+            let ret = ptr::read(base.add(index));
+            ptr::copy(base.add(len - 1), base.add(index), 1);
+            self.set_len(len - 1);
+            ret
+        }
+    }
+
+    /// Retains only the elements that `f` returns `true` for, preserving the order.
+    ///
+    /// This method costs 'O(n)' CPU time where 'n' is the length.
+    ///
+    /// If `f` panics, the elements not visited yet are leaked. (They are not dropped.)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.extend_from_slice(&[0, 1, 2, 3, 4]);
+    ///
+    /// cvec.retain(|&v| v % 2 == 0);
+    /// assert_eq!(&[0, 2, 4], cvec.as_ref());
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let len = self.len();
+        let mut kept = 0;
+
+        unsafe {
+            // Not to drop any element twice even if 'f' panics.
+            self.set_len(0);
+            let base = self.as_mut_ptr();
+
+            for i in 0..len {
+                let ptr = base.add(i);
+                if f(&*ptr) {
+                    if kept != i {
+                        ptr::copy_nonoverlapping(ptr, base.add(kept), 1);
+                    }
+                    kept += 1;
+                } else {
+                    ptr::drop_in_place(ptr);
+                }
+            }
+
+            self.set_len(kept);
+        }
+    }
+
+    /// Removes the elements in `range` and returns them as an iterator.
+    ///
+    /// The elements after `range` are shifted to the left when the iterator is dropped, so it
+    /// costs 'O(n)' CPU time where 'n' is the length.
+    /// If the iterator is dropped before all the elements are consumed, the rest are dropped.
+    ///
+    /// If the iterator is leaked (e.g. `core::mem::forget` ), `self` loses the elements in and
+    /// after `range` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if the start of `range` is greater than the end, or if the end is greater than the
+    /// length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::CVec;
+    ///
+    /// let mut cvec = CVec::<u8>::new();
+    /// cvec.extend_from_slice(&[0, 1, 2, 3, 4]);
+    ///
+    /// let drained: Vec<u8> = cvec.drain(1..3).collect();
+    /// assert_eq!(vec![1, 2], drained);
+    /// assert_eq!(&[0, 3, 4], cvec.as_ref());
+    ///
+    /// cvec.drain(..);
+    /// assert_eq!(true, cvec.is_empty());
+    /// ```
+    pub fn drain<R>(&mut self, range: R) -> Drain<T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end, "drain start is greater than the end");
+        assert!(end <= len, "drain end is out of bounds");
+
+        unsafe {
+            // Not to expose the elements in and after 'range' while draining.
+            self.set_len(start);
+        }
+
+        Drain {
+            vec: self,
+            current: start,
+            end,
+            tail_start: end,
+            tail_len: len - end,
+        }
+    }
+}
+
+/// `Drain` is an iterator returned from method [`CVec::drain`] .
+///
+/// [`CVec::drain`]: CVec::drain
+pub struct Drain<'a, T> {
+    vec: &'a mut CVec<T>,
+    /// The index of the next element to yield.
+    current: usize,
+    /// The end of the elements not yielded yet.
+    end: usize,
+    /// The end of the range to drain; i.e. the start of the elements after the range.
+    tail_start: usize,
+    /// The number of the elements after the range.
+    tail_len: usize,
+}
+
+impl<T> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.current == self.end {
+            None
+        } else {
+            let ret = unsafe { ptr::read(self.vec.as_ptr().add(self.current)) };
+            self.current += 1;
+            Some(ret)
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.end - self.current;
+        (n, Some(n))
+    }
+}
+
+impl<T> DoubleEndedIterator for Drain<'_, T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.current == self.end {
+            None
+        } else {
+            self.end -= 1;
+            Some(unsafe { ptr::read(self.vec.as_ptr().add(self.end)) })
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        // Drops the elements not yielded yet.
+        self.for_each(drop);
+
+        unsafe {
+            // The length was set to the start of the range.
+            let start = self.vec.len();
+            let base = self.vec.as_mut_ptr();
+            ptr::copy(base.add(self.tail_start), base.add(start), self.tail_len);
+            self.vec.set_len(start + self.tail_len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn remove_drops_nothing_twice() {
+        let rc = Rc::new(());
+        let mut cvec = CVec::new();
+        (0..5).for_each(|_| cvec.push(rc.clone()));
+        assert_eq!(6, Rc::strong_count(&rc));
+
+        cvec.insert(2, rc.clone());
+        drop(cvec.remove(0));
+        drop(cvec.swap_remove(0));
+        assert_eq!(5, Rc::strong_count(&rc));
+
+        let mut i = 0;
+        cvec.retain(|_| {
+            i += 1;
+            i % 2 == 0
+        });
+        assert_eq!(2, cvec.len());
+        assert_eq!(3, Rc::strong_count(&rc));
+
+        drop(cvec);
+        assert_eq!(1, Rc::strong_count(&rc));
+    }
+
+    #[test]
+    fn drain_() {
+        let mut cvec = CVec::<u8>::new();
+        cvec.extend_from_slice(&[0, 1, 2, 3, 4, 5]);
+
+        {
+            let mut drain = cvec.drain(1..=4);
+            assert_eq!(4, drain.len());
+            assert_eq!(Some(1), drain.next());
+            assert_eq!(Some(4), drain.next_back());
+        }
+        assert_eq!(&[0, 5], cvec.as_ref());
+
+        let drained: Vec<u8> = cvec.drain(2..).collect();
+        assert_eq!(true, drained.is_empty());
+        assert_eq!(&[0, 5], cvec.as_ref());
+    }
+
+    #[test]
+    fn drain_drops_rest() {
+        let rc = Rc::new(());
+        let mut cvec = CVec::new();
+        (0..5).for_each(|_| cvec.push(rc.clone()));
+
+        let mut drain = cvec.drain(1..4);
+        drop(drain.next());
+        drop(drain);
+
+        assert_eq!(2, cvec.len());
+        assert_eq!(3, Rc::strong_count(&rc));
+    }
+
+    #[test]
+    fn into_iter_drops_rest() {
+        let rc = Rc::new(());
+        let cvec: CVec<Rc<()>> = (0..5).map(|_| rc.clone()).collect();
+        assert_eq!(6, Rc::strong_count(&rc));
+
+        let mut it = cvec.into_iter();
+        assert_eq!(5, it.len());
+        drop(it.next());
+        drop(it.next_back());
+        assert_eq!(4, Rc::strong_count(&rc));
+
+        drop(it);
+        assert_eq!(1, Rc::strong_count(&rc));
+    }
+
+    #[test]
+    fn extend_() {
+        let mut cvec = CVec::<u8>::new();
+        cvec.extend(&[0, 1]);
+        cvec.extend(vec![2, 3]);
+        cvec.extend(CVec::from(&[4, 5][..]));
+        assert_eq!(&[0, 1, 2, 3, 4, 5], cvec.as_ref());
+
+        let rev: CVec<u8> = cvec.into_iter().rev().collect();
+        assert_eq!(&[5, 4, 3, 2, 1, 0], rev.as_ref());
+    }
+
+    #[should_panic]
+    #[test]
+    fn insert_out_of_bounds() {
+        let mut cvec = CVec::<u8>::new();
+        cvec.insert(1, 0);
+    }
+}
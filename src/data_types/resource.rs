@@ -16,16 +16,43 @@
 
 //! `resource` defines struct `Resource` and relatings.
 
+use super::{base58, CVec};
 use core::hash::{Hash, Hasher};
 use core::mem::MaybeUninit;
+use std::error::Error;
 use std::fmt;
 
 /// The total buffer size of the `ResourceId` .
 pub const RESOURCE_ID_BUFFER_CAPACITY: usize = 118; // The total size of 'Resource' will be 128.
 
 /// Alias to estimate the Asset.
+///
+/// It is `i64` by default, or `i128` if feature "asset_value_128" is specified; e.g. to represent
+/// the token amount with 18 decimals.
+#[cfg(not(feature = "asset_value_128"))]
 pub type AssetValue = i64;
 
+/// Alias to estimate the Asset.
+///
+/// It is `i64` by default, or `i128` if feature "asset_value_128" is specified; e.g. to represent
+/// the token amount with 18 decimals.
+#[cfg(feature = "asset_value_128")]
+pub type AssetValue = i128;
+
+/// `AssetOverflow` is the error that an [`AssetValue`] overflows.
+///
+/// [`AssetValue`]: self::AssetValue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetOverflow;
+
+impl fmt::Display for AssetOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The asset value overflows.")
+    }
+}
+
+impl Error for AssetOverflow {}
+
 /// `ResourceId` is constituted of 'owner' and 'asset type', and identifies unique [`Resource`] .
 ///
 /// # Owner
@@ -75,6 +102,10 @@ impl ResourceId {
     /// The behavior is undefined if `owner.len() + asset_type.len()` is greater than
     /// [`RESOURCE_ID_BUFFER_CAPACITY`] .
     ///
+    /// # Panics
+    ///
+    /// If feature "strict" is specified, panics instead of the undefined behavior.
+    ///
     /// [`RESOURCE_ID_BUFFER_CAPACITY`]: self::RESOURCE_ID_BUFFER_CAPACITY
     ///
     /// # Examples
@@ -89,6 +120,9 @@ impl ResourceId {
     /// ```
     #[inline]
     pub unsafe fn new(owner: &[u8], asset_type: &[u8]) -> Self {
+        #[cfg(feature = "strict")]
+        assert!(owner.len() + asset_type.len() <= RESOURCE_ID_BUFFER_CAPACITY);
+        #[cfg(not(feature = "strict"))]
         debug_assert!(owner.len() + asset_type.len() <= RESOURCE_ID_BUFFER_CAPACITY);
 
         let mut ret: MaybeUninit<Self> = MaybeUninit::uninit();
@@ -105,8 +139,65 @@ impl ResourceId {
 
         ret.assume_init()
     }
+
+    /// Creates a new instance from `owner` and `asset_type` , or returns an error if
+    /// `owner.len() + asset_type.len()` is greater than [`RESOURCE_ID_BUFFER_CAPACITY`] .
+    ///
+    /// See also [`ResourceIdBuf`] for the longer ones.
+    ///
+    /// [`RESOURCE_ID_BUFFER_CAPACITY`]: self::RESOURCE_ID_BUFFER_CAPACITY
+    /// [`ResourceIdBuf`]: self::ResourceIdBuf
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{ResourceId, RESOURCE_ID_BUFFER_CAPACITY};
+    ///
+    /// let resource_id = ResourceId::try_new(&[1, 2, 3], &[]).unwrap();
+    /// assert_eq!(&[1, 2, 3], resource_id.owner());
+    ///
+    /// let owner = [0; RESOURCE_ID_BUFFER_CAPACITY];
+    /// assert!(ResourceId::try_new(&owner, &[0]).is_err());
+    /// ```
+    #[inline]
+    pub fn try_new(owner: &[u8], asset_type: &[u8]) -> Result<Self, ResourceIdTooLong> {
+        let len = owner.len() + asset_type.len();
+        if RESOURCE_ID_BUFFER_CAPACITY < len {
+            Err(ResourceIdTooLong { len })
+        } else {
+            Ok(unsafe { Self::new(owner, asset_type) })
+        }
+    }
+}
+
+/// `ResourceIdTooLong` is the error that the 'owner' and the 'asset type' are too long for
+/// [`ResourceId`] .
+///
+/// [`ResourceId`]: self::ResourceId
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceIdTooLong {
+    len: usize,
+}
+
+impl ResourceIdTooLong {
+    /// Returns the total length of the 'owner' and the 'asset type'.
+    pub fn total_len(&self) -> usize {
+        self.len
+    }
 }
 
+impl fmt::Display for ResourceIdTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The total length of the owner and the asset type is {}, greater than {}.",
+            self.len, RESOURCE_ID_BUFFER_CAPACITY
+        )
+    }
+}
+
+impl Error for ResourceIdTooLong {}
+
 impl fmt::Debug for ResourceId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ResourceId")
@@ -176,6 +267,166 @@ impl ResourceId {
             std::slice::from_raw_parts(ptr, self.asset_type_len as usize)
         }
     }
+
+    /// Encodes the 'owner' into Base58Check string; e.g. the address of Bitcoin.
+    ///
+    /// See also module [`base58`] .
+    ///
+    /// [`base58`]: crate::data_types::base58
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::ResourceId;
+    ///
+    /// let owner = &[0, 1, 2, 3];
+    /// let resource_id = unsafe { ResourceId::new(owner, &[]) };
+    ///
+    /// let address = resource_id.owner_base58check();
+    /// let decoded = ResourceId::from_base58check(&address, &[]).unwrap();
+    /// assert_eq!(resource_id, decoded);
+    /// ```
+    pub fn owner_base58check(&self) -> String {
+        base58::encode_check(self.owner())
+    }
+
+    /// Creates a new instance from the 'owner' encoded in Base58Check and `asset_type` .
+    ///
+    /// Returns an error if `owner` is not a valid Base58Check string, or if the total length is
+    /// greater than [`RESOURCE_ID_BUFFER_CAPACITY`] .
+    ///
+    /// [`RESOURCE_ID_BUFFER_CAPACITY`]: self::RESOURCE_ID_BUFFER_CAPACITY
+    pub fn from_base58check(owner: &str, asset_type: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let owner = base58::decode_check(owner)?;
+        let ret = Self::try_new(&owner, asset_type)?;
+        Ok(ret)
+    }
+}
+
+/// `ResourceIdBuf` is same to [`ResourceId`] except for that it accepts the 'owner' and the
+/// 'asset type' of any length.
+///
+/// It holds [`ResourceId`] (i.e. the stack buffer) if the total length is less than or equal to
+/// [`RESOURCE_ID_BUFFER_CAPACITY`] , or allocates [`CVec`] otherwise.
+/// The equality and the hash are same to [`ResourceId`] .
+///
+/// [`ResourceId`]: self::ResourceId
+/// [`RESOURCE_ID_BUFFER_CAPACITY`]: self::RESOURCE_ID_BUFFER_CAPACITY
+/// [`CVec`]: crate::data_types::CVec
+///
+/// # Examples
+///
+/// ```
+/// use mouse::data_types::{ResourceIdBuf, RESOURCE_ID_BUFFER_CAPACITY};
+///
+/// let short = ResourceIdBuf::new(&[1, 2, 3], &[4]);
+/// assert_eq!(true, short.as_resource_id().is_some());
+///
+/// let owner = [0; RESOURCE_ID_BUFFER_CAPACITY];
+/// let long = ResourceIdBuf::new(&owner, &[4]);
+/// assert_eq!(true, long.as_resource_id().is_none());
+/// assert_eq!(&owner[..], long.owner());
+/// assert_eq!(&[4], long.asset_type());
+/// ```
+#[derive(Clone)]
+pub struct ResourceIdBuf {
+    inner: ResourceIdBufInner,
+}
+
+#[derive(Clone)]
+enum ResourceIdBufInner {
+    /// Holds the 'owner' and the 'asset type' in the stack buffer.
+    Inline(ResourceId),
+    /// Holds the 'owner' followed by the 'asset type' in the heap.
+    Heap { buffer: CVec<u8>, owner_len: usize },
+}
+
+impl ResourceIdBuf {
+    /// Creates a new instance from `owner` and `asset_type` .
+    ///
+    /// This method does not allocate heap memory unless `owner.len() + asset_type.len()` is
+    /// greater than [`RESOURCE_ID_BUFFER_CAPACITY`] .
+    ///
+    /// [`RESOURCE_ID_BUFFER_CAPACITY`]: self::RESOURCE_ID_BUFFER_CAPACITY
+    pub fn new(owner: &[u8], asset_type: &[u8]) -> Self {
+        match ResourceId::try_new(owner, asset_type) {
+            Ok(resource_id) => Self::from(resource_id),
+            Err(_) => {
+                let mut buffer = CVec::new();
+                buffer.reserve(owner.len() + asset_type.len());
+                buffer.extend_from_slice(owner);
+                buffer.extend_from_slice(asset_type);
+                let inner = ResourceIdBufInner::Heap {
+                    buffer,
+                    owner_len: owner.len(),
+                };
+                Self { inner }
+            }
+        }
+    }
+
+    /// Provides a reference to the 'owner'.
+    pub fn owner(&self) -> &[u8] {
+        match &self.inner {
+            ResourceIdBufInner::Inline(resource_id) => resource_id.owner(),
+            ResourceIdBufInner::Heap { buffer, owner_len } => &buffer.as_ref()[..*owner_len],
+        }
+    }
+
+    /// Provides a reference to the 'asset_type'.
+    pub fn asset_type(&self) -> &[u8] {
+        match &self.inner {
+            ResourceIdBufInner::Inline(resource_id) => resource_id.asset_type(),
+            ResourceIdBufInner::Heap { buffer, owner_len } => &buffer.as_ref()[*owner_len..],
+        }
+    }
+
+    /// Provides a reference to the [`ResourceId`] if `self` holds it, or returns `None` .
+    ///
+    /// [`ResourceId`]: self::ResourceId
+    pub fn as_resource_id(&self) -> Option<&ResourceId> {
+        match &self.inner {
+            ResourceIdBufInner::Inline(resource_id) => Some(resource_id),
+            ResourceIdBufInner::Heap { .. } => None,
+        }
+    }
+}
+
+impl From<ResourceId> for ResourceIdBuf {
+    #[inline]
+    fn from(resource_id: ResourceId) -> Self {
+        let inner = ResourceIdBufInner::Inline(resource_id);
+        Self { inner }
+    }
+}
+
+impl fmt::Debug for ResourceIdBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResourceIdBuf")
+            .field("owner", &self.owner())
+            .field("asset_type", &self.asset_type())
+            .finish()
+    }
+}
+
+impl PartialEq<Self> for ResourceIdBuf {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.owner() == other.owner() && self.asset_type() == other.asset_type()
+    }
+}
+
+impl Eq for ResourceIdBuf {}
+
+impl Hash for ResourceIdBuf {
+    #[inline]
+    fn hash<H>(&self, hasher: &mut H)
+    where
+        H: Hasher,
+    {
+        self.owner().hash(hasher);
+        self.asset_type().hash(hasher);
+    }
 }
 
 /// `Resource` is constituted of `ResourceId` and the number of how much asset.
@@ -309,6 +560,9 @@ impl Resource {
 
     /// Increases owning value by `value` .
     ///
+    /// This method panics on overflow in the debug build, and wraps around in the release build.
+    /// Use [`checked_deposit`] or [`saturating_deposit`] if `value` is not trusted.
+    ///
     /// The following sentences have the same effect on `resource` .
     ///
     /// - `resource.deposit(10)`
@@ -317,6 +571,8 @@ impl Resource {
     /// See also [`withdraw`] .
     ///
     /// [`withdraw`]: Self::withdraw
+    /// [`checked_deposit`]: Self::checked_deposit
+    /// [`saturating_deposit`]: Self::saturating_deposit
     ///
     /// # Examples
     ///
@@ -341,6 +597,9 @@ impl Resource {
 
     /// Decreases owning value by `value` .
     ///
+    /// This method panics on overflow in the debug build, and wraps around in the release build.
+    /// Use [`checked_withdraw`] or [`saturating_withdraw`] if `value` is not trusted.
+    ///
     /// The following sentences have the same effect on `resource` .
     ///
     /// - `resource.deposit(10)`
@@ -349,6 +608,8 @@ impl Resource {
     /// See also [`deposit`] .
     ///
     /// [`deposit`]: Self::deposit
+    /// [`checked_withdraw`]: Self::checked_withdraw
+    /// [`saturating_withdraw`]: Self::saturating_withdraw
     ///
     /// # Examples
     ///
@@ -370,6 +631,92 @@ impl Resource {
     pub fn withdraw(&mut self, value: AssetValue) {
         self.value_ -= value;
     }
+
+    /// Increases owning value by `value` , or returns an error without any change if it
+    /// overflows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{AssetOverflow, AssetValue, Resource, ResourceId};
+    ///
+    /// let id = unsafe { ResourceId::new(&[1, 2, 3], &[]) };
+    /// let mut resource = Resource::new(&id, 5);
+    ///
+    /// assert_eq!(Ok(()), resource.checked_deposit(7));
+    /// assert_eq!(12, resource.value());
+    ///
+    /// assert_eq!(Err(AssetOverflow), resource.checked_deposit(AssetValue::MAX));
+    /// assert_eq!(12, resource.value());
+    /// ```
+    #[inline]
+    pub fn checked_deposit(&mut self, value: AssetValue) -> Result<(), AssetOverflow> {
+        self.value_ = self.value_.checked_add(value).ok_or(AssetOverflow)?;
+        Ok(())
+    }
+
+    /// Decreases owning value by `value` , or returns an error without any change if it
+    /// overflows.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{AssetOverflow, AssetValue, Resource, ResourceId};
+    ///
+    /// let id = unsafe { ResourceId::new(&[1, 2, 3], &[]) };
+    /// let mut resource = Resource::new(&id, -5);
+    ///
+    /// assert_eq!(Ok(()), resource.checked_withdraw(7));
+    /// assert_eq!(-12, resource.value());
+    ///
+    /// assert_eq!(Err(AssetOverflow), resource.checked_withdraw(AssetValue::MAX));
+    /// assert_eq!(-12, resource.value());
+    /// ```
+    #[inline]
+    pub fn checked_withdraw(&mut self, value: AssetValue) -> Result<(), AssetOverflow> {
+        self.value_ = self.value_.checked_sub(value).ok_or(AssetOverflow)?;
+        Ok(())
+    }
+
+    /// Increases owning value by `value` , saturating at the bound of [`AssetValue`] .
+    ///
+    /// [`AssetValue`]: self::AssetValue
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{AssetValue, Resource, ResourceId};
+    ///
+    /// let id = unsafe { ResourceId::new(&[1, 2, 3], &[]) };
+    /// let mut resource = Resource::new(&id, 5);
+    ///
+    /// resource.saturating_deposit(AssetValue::MAX);
+    /// assert_eq!(AssetValue::MAX, resource.value());
+    /// ```
+    #[inline]
+    pub fn saturating_deposit(&mut self, value: AssetValue) {
+        self.value_ = self.value_.saturating_add(value);
+    }
+
+    /// Decreases owning value by `value` , saturating at the bound of [`AssetValue`] .
+    ///
+    /// [`AssetValue`]: self::AssetValue
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::data_types::{AssetValue, Resource, ResourceId};
+    ///
+    /// let id = unsafe { ResourceId::new(&[1, 2, 3], &[]) };
+    /// let mut resource = Resource::new(&id, -5);
+    ///
+    /// resource.saturating_withdraw(AssetValue::MAX);
+    /// assert_eq!(AssetValue::MIN, resource.value());
+    /// ```
+    #[inline]
+    pub fn saturating_withdraw(&mut self, value: AssetValue) {
+        self.value_ = self.value_.saturating_sub(value);
+    }
 }
 
 #[cfg(test)]
@@ -383,9 +730,49 @@ mod tests {
         assert_eq!(0, size_of::<ResourceId>() % 8);
     }
 
+    #[test]
+    fn resource_id_buf() {
+        let owner = [1; RESOURCE_ID_BUFFER_CAPACITY];
+
+        for &len in &[
+            0,
+            1,
+            RESOURCE_ID_BUFFER_CAPACITY - 1,
+            RESOURCE_ID_BUFFER_CAPACITY,
+        ] {
+            let inline = ResourceIdBuf::new(&owner[..len], &[2]);
+            let resource_id = ResourceId::try_new(&owner[..len], &[2]);
+            assert_eq!(resource_id.ok().as_ref(), inline.as_resource_id());
+            assert_eq!(&owner[..len], inline.owner());
+            assert_eq!(&[2], inline.asset_type());
+        }
+
+        let heap = ResourceIdBuf::new(&owner, &[2, 3]);
+        assert_eq!(None, heap.as_resource_id());
+        assert_eq!(&owner[..], heap.owner());
+        assert_eq!(&[2, 3], heap.asset_type());
+        assert_eq!(heap, ResourceIdBuf::new(&owner, &[2, 3]));
+        assert_ne!(heap, ResourceIdBuf::new(&owner, &[2]));
+
+        assert_eq!(
+            Err(ResourceIdTooLong {
+                len: RESOURCE_ID_BUFFER_CAPACITY + 2
+            }),
+            ResourceId::try_new(&owner, &[2, 3])
+        );
+    }
+
+    #[cfg(not(feature = "asset_value_128"))]
     #[test]
     fn resource_size() {
         // No special reason to '128', but I feel like setting a round number.
         assert_eq!(128, size_of::<Resource>());
     }
+
+    #[cfg(feature = "asset_value_128")]
+    #[test]
+    fn resource_size() {
+        assert_eq!(0, size_of::<Resource>() % 16);
+        assert!(size_of::<Resource>() <= 144);
+    }
 }
@@ -0,0 +1,144 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `ed25519` defines struct `Ed25519PublicKey` and `Ed25519SecretKey` .
+
+use super::{zeroize, Signer, Verifier};
+use crypto::ed25519;
+use std::fmt;
+
+const PUBLIC_KEY_LEN: usize = 32;
+const SEED_LEN: usize = 32;
+const SIGNATURE_LEN: usize = 64;
+
+/// `Ed25519PublicKey` is a public key of Ed25519 and implements [`Verifier`] .
+///
+/// [`Verifier`]: super::Verifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ed25519PublicKey([u8; PUBLIC_KEY_LEN]);
+
+impl Ed25519PublicKey {
+    /// Creates a new instance from the encoded public key, or returns `None` if the length of
+    /// `bytes` is not 32.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() == PUBLIC_KEY_LEN {
+            let mut key = [0; PUBLIC_KEY_LEN];
+            key.copy_from_slice(bytes);
+            Some(Self(key))
+        } else {
+            None
+        }
+    }
+}
+
+impl Verifier for Ed25519PublicKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        signature.len() == SIGNATURE_LEN && ed25519::verify(message, &self.0, signature)
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// `Ed25519SecretKey` is a secret key of Ed25519 and implements [`Signer`] .
+///
+/// The secret is overwritten with 0 when dropped.
+///
+/// [`Signer`]: super::Signer
+#[derive(Clone)]
+pub struct Ed25519SecretKey {
+    /// The expanded secret key. (The seed followed by the public key.)
+    secret: [u8; 64],
+    public: [u8; PUBLIC_KEY_LEN],
+}
+
+impl Drop for Ed25519SecretKey {
+    fn drop(&mut self) {
+        zeroize(&mut self.secret);
+    }
+}
+
+impl fmt::Debug for Ed25519SecretKey {
+    /// Does not show the secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ed25519SecretKey")
+            .field("public", &self.public)
+            .finish()
+    }
+}
+
+impl Ed25519SecretKey {
+    /// Creates a new instance from the 32 bytes seed.
+    ///
+    /// The seed should be generated by a cryptographically secure random number generator.
+    pub fn from_seed(seed: &[u8; SEED_LEN]) -> Self {
+        let (secret, public) = ed25519::keypair(seed);
+        Self { secret, public }
+    }
+}
+
+impl Signer for Ed25519SecretKey {
+    type Verifier = Ed25519PublicKey;
+
+    fn public_key(&self) -> Ed25519PublicKey {
+        Ed25519PublicKey(self.public)
+    }
+
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        ed25519::signature(message, &self.secret).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rfc8032_test1() {
+        let mut seed = [0; SEED_LEN];
+        seed.copy_from_slice(&from_hex(
+            "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+        ));
+        let public = from_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let signature = from_hex(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+
+        let secret = Ed25519SecretKey::from_seed(&seed);
+        assert_eq!(&public[..], secret.public_key().as_bytes());
+        assert_eq!(signature, secret.sign(b""));
+
+        let verifier = Ed25519PublicKey::from_bytes(&public).unwrap();
+        assert_eq!(true, verifier.verify(b"", &signature));
+        assert_eq!(false, verifier.verify(b"x", &signature));
+        assert_eq!(false, verifier.verify(b"", &signature[1..]));
+    }
+
+    #[test]
+    fn from_bytes() {
+        assert_eq!(None, Ed25519PublicKey::from_bytes(&[0; 31]));
+        assert_eq!(true, Ed25519PublicKey::from_bytes(&[0; 32]).is_some());
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `signature` defines traits and structs relating to digital signature.
+//!
+//! Ed25519 is always available. secp256k1 (ECDSA) is available only if feature "secp256k1" is
+//! specified.
+//!
+//! An `Acid` implementation usually stores the public key of the owner and the signature in the
+//! extrinsic data, and verifies the signature over the intrinsic data.
+//!
+//! # Examples
+//!
+//! ```
+//! use mouse::data_types::signature::{Ed25519SecretKey, Signer, Verifier};
+//!
+//! let secret = Ed25519SecretKey::from_seed(&[1; 32]);
+//! let public = secret.public_key();
+//!
+//! let intrinsic = b"intrinsic data";
+//! let signature = secret.sign(intrinsic);
+//! assert!(public.verify(intrinsic, &signature));
+//! ```
+
+mod ed25519;
+#[cfg(feature = "secp256k1")]
+mod secp256k1;
+
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+pub use ed25519::{Ed25519PublicKey, Ed25519SecretKey};
+#[cfg(feature = "secp256k1")]
+pub use secp256k1::{Secp256k1PublicKey, Secp256k1SecretKey};
+
+/// Overwrites `bytes` with 0 not to leave the secret in the memory.
+///
+/// The writes are volatile so that the compiler does not omit them even if `bytes` is not read
+/// any more.
+pub(crate) fn zeroize(bytes: &mut [u8]) {
+    for b in bytes.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    atomic::compiler_fence(Ordering::SeqCst);
+}
+
+/// `Verifier` is a public key to verify the signatures.
+pub trait Verifier {
+    /// Returns `true` if `signature` is a valid signature of `message` by the owner of `self` .
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+
+    /// Provides a reference to the encoded public key.
+    fn as_bytes(&self) -> &[u8];
+}
+
+/// `Signer` is a secret key to sign the messages.
+pub trait Signer {
+    /// The type of the public key.
+    type Verifier: Verifier;
+
+    /// Returns the public key corresponding to `self` .
+    fn public_key(&self) -> Self::Verifier;
+
+    /// Signs `message` and returns the signature.
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zeroize_() {
+        let mut bytes = [0xab; 32];
+        zeroize(&mut bytes[1..]);
+        assert_eq!(0xab, bytes[0]);
+        assert_eq!([0; 31], bytes[1..]);
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `secp256k1` defines struct `Secp256k1PublicKey` and `Secp256k1SecretKey` .
+//!
+//! The signature is ECDSA over SHA-256 of the message, and it is encoded in the 64 bytes compact
+//! format; i.e. 'r' followed by 's' .
+
+use super::{Signer, Verifier};
+use core::convert::TryFrom;
+use k256::ecdsa::signature::{Signer as _, Verifier as _};
+use k256::ecdsa::{Signature, SigningKey, VerifyingKey};
+use std::fmt;
+
+/// `Secp256k1PublicKey` is a public key of secp256k1 and implements [`Verifier`] .
+///
+/// [`Verifier`]: super::Verifier
+#[derive(Clone)]
+pub struct Secp256k1PublicKey {
+    key: VerifyingKey,
+    /// The compressed SEC1 encoding.
+    bytes: Vec<u8>,
+}
+
+impl fmt::Debug for Secp256k1PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Secp256k1PublicKey")
+            .field(&self.bytes)
+            .finish()
+    }
+}
+
+impl PartialEq for Secp256k1PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes == other.bytes
+    }
+}
+
+impl Eq for Secp256k1PublicKey {}
+
+impl Secp256k1PublicKey {
+    /// Creates a new instance from the SEC1 encoded public key (compressed or uncompressed), or
+    /// returns `None` if `bytes` is not a valid public key.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let key = VerifyingKey::from_sec1_bytes(bytes).ok()?;
+        Some(Self::from(key))
+    }
+}
+
+impl From<VerifyingKey> for Secp256k1PublicKey {
+    fn from(key: VerifyingKey) -> Self {
+        let bytes = key.to_bytes().to_vec();
+        Self { key, bytes }
+    }
+}
+
+impl Verifier for Secp256k1PublicKey {
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match Signature::try_from(signature) {
+            Ok(signature) => self.key.verify(message, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+/// `Secp256k1SecretKey` is a secret key of secp256k1 and implements [`Signer`] .
+///
+/// [`Signer`]: super::Signer
+#[derive(Clone)]
+pub struct Secp256k1SecretKey {
+    key: SigningKey,
+}
+
+impl fmt::Debug for Secp256k1SecretKey {
+    /// Does not show the secret.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secp256k1SecretKey").finish()
+    }
+}
+
+impl Secp256k1SecretKey {
+    /// Creates a new instance from the 32 bytes secret scalar, or returns `None` if `bytes` is
+    /// not a valid secret key.
+    ///
+    /// The secret should be generated by a cryptographically secure random number generator.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let key = SigningKey::from_bytes(bytes).ok()?;
+        Some(Self { key })
+    }
+}
+
+impl Signer for Secp256k1SecretKey {
+    type Verifier = Secp256k1PublicKey;
+
+    fn public_key(&self) -> Secp256k1PublicKey {
+        Secp256k1PublicKey::from(self.key.verify_key())
+    }
+
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let signature: Signature = self.key.sign(message);
+        signature.as_ref().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_verify() {
+        let secret = Secp256k1SecretKey::from_bytes(&[1; 32]).unwrap();
+        let public = secret.public_key();
+        assert_eq!(33, public.as_bytes().len());
+
+        let signature = secret.sign(b"message");
+        assert_eq!(64, signature.len());
+        assert_eq!(true, public.verify(b"message", &signature));
+        assert_eq!(false, public.verify(b"massage", &signature));
+        assert_eq!(false, public.verify(b"message", &signature[1..]));
+
+        let decoded = Secp256k1PublicKey::from_bytes(public.as_bytes()).unwrap();
+        assert_eq!(public, decoded);
+    }
+
+    #[test]
+    fn invalid_keys() {
+        assert_eq!(true, Secp256k1SecretKey::from_bytes(&[0; 32]).is_none());
+        assert_eq!(true, Secp256k1PublicKey::from_bytes(&[0; 10]).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `error` defines enum `Error` .
+//! `error` is independent from other modules.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+/// `Error` is the error type of the module APIs; i.e. `ModuleEnvironment` , the KVS and the RDB.
+///
+/// The variants tell where the error comes from, and each variant except for `Config` and
+/// `NotImplemented` wraps the original error. (Call `source()` to get it.) `Error` is `Send`
+/// and `Sync` , so it can be passed to another thread.
+///
+/// `Error` converts into `Box<dyn std::error::Error>` , and vice versa. (The latter is regarded
+/// as [`Error::Other`] . `Box<dyn std::error::Error>` is not `Send` , so only the message is
+/// kept.) `std::io::Error` is converted into [`Error::Io`] .
+///
+/// # Examples
+///
+/// ```
+/// use mouse::Error;
+/// use std::error::Error as _;
+///
+/// let e = Error::NotImplemented("foo");
+/// assert_eq!(true, e.source().is_none());
+///
+/// let e: Box<dyn std::error::Error> = Box::from("bar");
+/// let e = Error::from(e);
+/// assert_eq!(true, e.source().is_some());
+/// assert_eq!("bar", e.to_string());
+/// ```
+///
+/// [`Error::Other`]: Self::Other
+/// [`Error::Io`]: Self::Io
+#[derive(Debug)]
+pub enum Error {
+    /// The error of the KVS.
+    Kvs(Box<dyn error::Error + Send + Sync>),
+    /// The error of the RDB.
+    Rdb(Box<dyn error::Error + Send + Sync>),
+    /// The error of the cache.
+    Cache(Box<dyn error::Error + Send + Sync>),
+    /// The stored data is broken; e.g. the checksum of the KVS data does not match.
+    ///
+    /// Retrying does not help unlike the other errors of the KVS or the RDB.
+    Corrupt(Box<dyn error::Error + Send + Sync>),
+    /// The I/O error other than the KVS and the RDB.
+    Io(io::Error),
+    /// The arguments are invalid.
+    Config(String),
+    /// The method is not implemented.
+    NotImplemented(&'static str),
+    /// Any other error.
+    Other(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Kvs(e) => write!(f, "KVS error: {}", e),
+            Self::Rdb(e) => write!(f, "RDB error: {}", e),
+            Self::Cache(e) => write!(f, "Cache error: {}", e),
+            Self::Corrupt(e) => write!(f, "Corrupt data: {}", e),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Config(msg) => f.write_str(msg),
+            Self::NotImplemented(name) => write!(f, "'{}' is not implemented yet.", name),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Kvs(e) | Self::Rdb(e) | Self::Cache(e) | Self::Corrupt(e) | Self::Other(e) => {
+                Some(e.as_ref())
+            }
+            Self::Io(e) => Some(e),
+            Self::Config(_) | Self::NotImplemented(_) => None,
+        }
+    }
+}
+
+impl From<Box<dyn error::Error + Send + Sync>> for Error {
+    fn from(e: Box<dyn error::Error + Send + Sync>) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<Box<dyn error::Error>> for Error {
+    fn from(e: Box<dyn error::Error>) -> Self {
+        Self::Other(Box::from(e.to_string()))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Error {
+    /// Creates `Error::Kvs` wrapping `e` .
+    pub fn kvs<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Self::Kvs(e.into())
+    }
+
+    /// Creates `Error::Rdb` wrapping `e` .
+    pub fn rdb<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Self::Rdb(e.into())
+    }
+
+    /// Creates `Error::Cache` wrapping `e` .
+    pub fn cache<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Self::Cache(e.into())
+    }
+
+    /// Creates `Error::Corrupt` wrapping `e` .
+    pub fn corrupt<E>(e: E) -> Self
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Self::Corrupt(e.into())
+    }
+
+    /// Returns `true` if `self` is `Error::Corrupt` .
+    pub fn is_corrupt(&self) -> bool {
+        matches!(self, Self::Corrupt(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn display() {
+        assert_eq!("RDB error: foo", Error::rdb("foo").to_string());
+        assert_eq!("bad", Error::Config(String::from("bad")).to_string());
+        assert_eq!(
+            "'foo' is not implemented yet.",
+            Error::NotImplemented("foo").to_string()
+        );
+    }
+
+    #[test]
+    fn into_box() {
+        let e: Box<dyn error::Error> = Box::new(Error::kvs("foo"));
+        let e = e.downcast::<Error>().unwrap();
+        match *e {
+            Error::Kvs(ref inner) => assert_eq!("foo", inner.to_string()),
+            _ => panic!("Unexpected variant"),
+        }
+        assert_eq!(true, e.source().is_some());
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Error>();
+    }
+
+    #[test]
+    fn io() {
+        let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "foo"));
+        match &e {
+            Error::Io(inner) => assert_eq!(io::ErrorKind::NotFound, inner.kind()),
+            _ => panic!("Unexpected variant"),
+        }
+        assert_eq!("I/O error: foo", e.to_string());
+        assert_eq!(true, e.source().is_some());
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `events` notifies the subscribers of the changes of the main chain and the mempool.
+//! `events` depends on module `data_types` .
+//!
+//! [`Bus`] sends each [`Event`] to every [`Subscription`] through a bounded channel. The event is
+//! dropped for the subscription whose channel is full, so that a slow subscriber never blocks the
+//! publisher. (See also method [`Subscription::lost`] .)
+//!
+//! [`Bus`]: self::Bus
+//! [`Event`]: self::Event
+//! [`Subscription`]: self::Subscription
+//! [`Subscription::lost`]: self::Subscription::lost
+
+use crate::data_types::{ChainIndex, Id};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// `Event` is what [`Bus`] publishes.
+///
+/// [`Bus`]: self::Bus
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The block is appended to the main chain.
+    BlockConnected(ChainIndex),
+    /// The block is removed from the tip of the main chain; e.g. by a reorg. The ids are of the
+    /// acids which belonged to the block and are moved back to the mempool.
+    BlockDisconnected(ChainIndex, Arc<[Id]>),
+    /// The acid is accepted to the mempool.
+    AcidAccepted(Id),
+    /// The acid is recorded as invalid.
+    AcidInvalidated(Id),
+}
+
+struct Subscriber {
+    sender: SyncSender<Event>,
+    lost: Arc<AtomicU64>,
+}
+
+/// `Bus` delivers [`Event`] to the subscriptions.
+///
+/// [`Event`]: self::Event
+#[derive(Default)]
+pub struct Bus {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl Bus {
+    /// Creates a new [`Subscription`] which buffers at most `capacity` events. (`capacity` is
+    /// regarded as 1 if it is 0.)
+    ///
+    /// [`Subscription`]: self::Subscription
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+        let lost = Arc::new(AtomicU64::new(0));
+
+        let subscriber = Subscriber {
+            sender,
+            lost: lost.clone(),
+        };
+        self.subscribers.lock().unwrap().push(subscriber);
+
+        Subscription { receiver, lost }
+    }
+
+    /// Sends `event` to every [`Subscription`] , and returns the number of the subscriptions which
+    /// buffered it.
+    ///
+    /// The dropped subscriptions are removed.
+    ///
+    /// [`Subscription`]: self::Subscription
+    pub fn publish(&self, event: Event) -> usize {
+        let mut ret = 0;
+
+        // Holding the lock so that every subscription receives the events in the same order.
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(
+            |subscriber| match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => {
+                    ret += 1;
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    subscriber.lost.fetch_add(1, Ordering::Relaxed);
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        );
+
+        ret
+    }
+
+    /// Returns the number of the subscriptions. (The dropped one is counted until the next
+    /// [`publish`] .)
+    ///
+    /// [`publish`]: Self::publish
+    pub fn subscription_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+}
+
+/// `Subscription` receives [`Event`] from [`Bus`] . It is unsubscribed when dropped.
+///
+/// [`Event`]: self::Event
+/// [`Bus`]: self::Bus
+pub struct Subscription {
+    receiver: Receiver<Event>,
+    lost: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Returns the oldest buffered event if any, or `None` without blocking.
+    pub fn try_recv(&self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Blocks at most `timeout` until an event arrives, and returns it, or `None` on timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the number of the events dropped because the buffer was full.
+    pub fn lost(&self) -> u64 {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+
+    fn id(n: u8) -> Id {
+        let mut id = Id::zeroed();
+        id[0] = n;
+        id
+    }
+
+    #[test]
+    fn publish_() {
+        let bus = Bus::default();
+        assert_eq!(0, bus.publish(Event::AcidAccepted(id(1))));
+
+        let first = bus.subscribe(10);
+        let second = bus.subscribe(10);
+        let block = ChainIndex::new(1, &id(2));
+        assert_eq!(2, bus.publish(Event::AcidAccepted(id(1))));
+        assert_eq!(2, bus.publish(Event::BlockConnected(block)));
+
+        for subscription in &[first, second] {
+            assert_eq!(Some(Event::AcidAccepted(id(1))), subscription.try_recv());
+            assert_eq!(
+                Some(Event::BlockConnected(block)),
+                subscription.recv_timeout(Duration::from_millis(1))
+            );
+            assert_eq!(None, subscription.try_recv());
+            assert_eq!(0, subscription.lost());
+        }
+    }
+
+    #[test]
+    fn bounded() {
+        let bus = Bus::default();
+        let subscription = bus.subscribe(2);
+
+        for i in 0..5 {
+            bus.publish(Event::AcidInvalidated(id(i)));
+        }
+        assert_eq!(3, subscription.lost());
+        assert_eq!(Some(Event::AcidInvalidated(id(0))), subscription.try_recv());
+        assert_eq!(Some(Event::AcidInvalidated(id(1))), subscription.try_recv());
+        assert_eq!(None, subscription.try_recv());
+    }
+
+    #[test]
+    fn unsubscribe() {
+        let bus = Bus::default();
+        let subscription = bus.subscribe(1);
+        assert_eq!(1, bus.subscription_count());
+
+        drop(subscription);
+        assert_eq!(0, bus.publish(Event::AcidAccepted(id(1))));
+        assert_eq!(0, bus.subscription_count());
+    }
+}
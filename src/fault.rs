@@ -0,0 +1,242 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `fault` injects failures and delays into the storage layers to test the crash consistency.
+//! `fault` is independent from other modules.
+//!
+//! The storage layers call [`hit`] at each [`Point`] . The registry is programmable only if
+//! feature "fault_injection" is specified; otherwise, [`hit`] always succeeds and the functions
+//! to program the registry are not compiled.
+//!
+//! The registry is global, so the tests programming it should not run in parallel with the
+//! other tests touching the same [`Point`] .
+//!
+//! [`hit`]: self::hit
+//! [`Point`]: self::Point
+
+use std::error::Error;
+use std::fmt;
+
+/// `Point` is where a failure or a delay can be injected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Point {
+    /// Before the KVS writes the extrinsic batch.
+    KvsExtrinsicWrite,
+    /// Before the KVS writes the intrinsic batch. (i.e. after the extrinsic batch is written.)
+    KvsIntrinsicWrite,
+    /// Before the RDB commits the transaction.
+    RdbCommit,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Point::KvsExtrinsicWrite => f.write_str("kvs_extrinsic_write"),
+            Point::KvsIntrinsicWrite => f.write_str("kvs_intrinsic_write"),
+            Point::RdbCommit => f.write_str("rdb_commit"),
+        }
+    }
+}
+
+/// `Injected` is the error that [`hit`] returns when the failure is programmed.
+///
+/// [`hit`]: self::hit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Injected {
+    point: Point,
+}
+
+impl Injected {
+    /// Provides the point where the failure is injected.
+    pub fn point(&self) -> Point {
+        self.point
+    }
+}
+
+impl fmt::Display for Injected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Injected failure at '{}'", self.point)
+    }
+}
+
+impl Error for Injected {}
+
+/// Sleeps if a delay is programmed for `point` , and returns an error if a failure is programmed
+/// for this hit.
+#[inline]
+pub fn hit(point: Point) -> Result<(), Injected> {
+    imp::hit(point)
+}
+
+/// Makes the `n` th [`hit`] at `point` from now fail. (`n` starts from 1.)
+///
+/// The other hits succeed. This function overwrites the failure programmed before for `point` .
+///
+/// [`hit`]: self::hit
+#[cfg(feature = "fault_injection")]
+pub fn fail_nth(point: Point, n: usize) {
+    imp::registry().fail_nth(point, n)
+}
+
+/// Makes every [`hit`] at `point` sleep for `delay` before returning.
+///
+/// [`hit`]: self::hit
+#[cfg(feature = "fault_injection")]
+pub fn delay(point: Point, delay: std::time::Duration) {
+    imp::registry().delay(point, delay)
+}
+
+/// Returns the number of the [`hit`] s at `point` since the last [`clear`] .
+///
+/// [`hit`]: self::hit
+/// [`clear`]: self::clear
+#[cfg(feature = "fault_injection")]
+pub fn hits(point: Point) -> usize {
+    imp::registry().hits(point)
+}
+
+/// Discards all the programmed failures and delays, and resets the counts.
+#[cfg(feature = "fault_injection")]
+pub fn clear() {
+    imp::registry().clear()
+}
+
+#[cfg(feature = "fault_injection")]
+mod imp {
+    use super::{Injected, Point};
+    use std::collections::HashMap;
+    use std::sync::{Mutex, Once};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Rule {
+        hits: usize,
+        /// The hit number (not the remaining count) to fail.
+        fail_at: Option<usize>,
+        delay: Option<Duration>,
+    }
+
+    #[derive(Default)]
+    pub struct Registry {
+        rules: Mutex<HashMap<Point, Rule>>,
+    }
+
+    impl Registry {
+        pub fn fail_nth(&self, point: Point, n: usize) {
+            let mut rules = self.rules.lock().unwrap();
+            let rule = rules.entry(point).or_default();
+            rule.fail_at = Some(rule.hits + n);
+        }
+
+        pub fn delay(&self, point: Point, delay: Duration) {
+            let mut rules = self.rules.lock().unwrap();
+            rules.entry(point).or_default().delay = Some(delay);
+        }
+
+        pub fn hits(&self, point: Point) -> usize {
+            let rules = self.rules.lock().unwrap();
+            rules.get(&point).map(|rule| rule.hits).unwrap_or(0)
+        }
+
+        pub fn clear(&self) {
+            self.rules.lock().unwrap().clear();
+        }
+
+        pub fn hit(&self, point: Point) -> Result<(), Injected> {
+            let (delay, fails) = {
+                let mut rules = self.rules.lock().unwrap();
+                let rule = rules.entry(point).or_default();
+                rule.hits += 1;
+                (rule.delay, rule.fail_at == Some(rule.hits))
+            };
+
+            // Sleep without the lock.
+            if let Some(delay) = delay {
+                std::thread::sleep(delay);
+            }
+
+            if fails {
+                warn!("Injected failure at '{}'", point);
+                Err(Injected { point })
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    pub fn registry() -> &'static Registry {
+        static INIT: Once = Once::new();
+        static mut REGISTRY: *const Registry = core::ptr::null();
+
+        unsafe {
+            INIT.call_once(|| REGISTRY = Box::into_raw(Box::new(Registry::default())));
+            &*REGISTRY
+        }
+    }
+
+    pub fn hit(point: Point) -> Result<(), Injected> {
+        registry().hit(point)
+    }
+}
+
+#[cfg(not(feature = "fault_injection"))]
+mod imp {
+    use super::{Injected, Point};
+
+    #[inline]
+    pub fn hit(_point: Point) -> Result<(), Injected> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "fault_injection"))]
+mod tests {
+    use super::imp::Registry;
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn fail_nth_() {
+        // Use a local registry not to break the other tests.
+        let registry = Registry::default();
+        let point = Point::KvsIntrinsicWrite;
+
+        registry.fail_nth(point, 2);
+        assert_eq!(Ok(()), registry.hit(point));
+        assert_eq!(Err(Injected { point }), registry.hit(point));
+        assert_eq!(Ok(()), registry.hit(point));
+        assert_eq!(3, registry.hits(point));
+        assert_eq!(0, registry.hits(Point::RdbCommit));
+
+        registry.fail_nth(point, 1);
+        assert_eq!(true, registry.hit(point).is_err());
+
+        registry.clear();
+        assert_eq!(0, registry.hits(point));
+        assert_eq!(Ok(()), registry.hit(point));
+    }
+
+    #[test]
+    fn delay_() {
+        let registry = Registry::default();
+        let point = Point::RdbCommit;
+
+        registry.delay(point, Duration::from_millis(10));
+        let start = Instant::now();
+        assert_eq!(Ok(()), registry.hit(point));
+        assert!(Duration::from_millis(10) <= start.elapsed());
+    }
+}
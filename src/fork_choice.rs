@@ -0,0 +1,491 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `fork_choice` decides whether a side branch should replace the main chain.
+//! `fork_choice` depends on module `data_types` and `rdb` .
+//!
+//! The headers of the blocks are stored in RDB table "block_headers", (see module
+//! [`rdb::block_headers`] ,) and function [`branch`] follows them from a tip back to the main
+//! chain. [`ForkChoice`] compares the [`Branch`] with the main chain above the fork point.
+//!
+//! The rule is chosen by '--fork-choice' ([`LongestChain`] or [`HeaviestWork`] ,) or set by the
+//! application with method [`GlobalEnvironment::set_fork_choice`] .
+//!
+//! This module only decides. Reverting the main chain to the fork point and committing the side
+//! branch are up to the caller.
+//!
+//! # Checkpoints
+//!
+//! '--checkpoint height:id' declares that the block at `height` must be `id` . (It can be
+//! specified multiple times.) [`Environment::allows`] refuses a branch replacing a checkpoint
+//! with another block, and function [`finalize_checkpoints`] makes the main chain immutable up
+//! to the highest checkpoint it reaches. (See also function [`main_chain::finalize`] .)
+//!
+//! [`rdb::block_headers`]: crate::rdb::block_headers
+//! [`branch`]: self::branch
+//! [`ForkChoice`]: self::ForkChoice
+//! [`Branch`]: self::Branch
+//! [`LongestChain`]: self::LongestChain
+//! [`HeaviestWork`]: self::HeaviestWork
+//! [`GlobalEnvironment::set_fork_choice`]: crate::GlobalEnvironment::set_fork_choice
+//! [`Environment::allows`]: self::Environment::allows
+//! [`finalize_checkpoints`]: self::finalize_checkpoints
+//! [`main_chain::finalize`]: crate::rdb::main_chain::finalize
+
+use crate::data_types::{BlockHeight, CryptoHash, Id};
+use crate::rdb::block_headers::{self, BlockHeader};
+use crate::rdb::{main_chain, Master, Slave};
+use crate::{Config, Error, ModuleEnvironment};
+use clap::{App, Arg};
+use std::collections::BTreeMap;
+
+const DEFAULT_RULE: &'static str = "longest";
+
+/// `Branch` is a side branch and the main chain above the fork point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    /// The height of the last block shared by the main chain and the side branch.
+    pub fork_height: BlockHeight,
+    /// The headers of the main chain above the fork point, ordered by the height.
+    pub main: Vec<BlockHeader>,
+    /// The headers of the side branch above the fork point, ordered by the height.
+    pub side: Vec<BlockHeader>,
+}
+
+impl Branch {
+    /// Returns the total work of `main` .
+    pub fn main_work(&self) -> i128 {
+        self.main.iter().map(|h| h.work as i128).sum()
+    }
+
+    /// Returns the total work of `side` .
+    pub fn side_work(&self) -> i128 {
+        self.side.iter().map(|h| h.work as i128).sum()
+    }
+}
+
+/// `ForkChoice` is the rule to choose the main chain.
+pub trait ForkChoice: Send + Sync {
+    /// Returns `true` if the side branch of `branch` should replace the main chain.
+    ///
+    /// It should return `false` if they are equally good, so that the main chain does not
+    /// switch back and forth.
+    fn prefers(&self, branch: &Branch) -> bool;
+}
+
+/// `LongestChain` prefers the branch with more blocks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LongestChain;
+
+impl ForkChoice for LongestChain {
+    fn prefers(&self, branch: &Branch) -> bool {
+        branch.main.len() < branch.side.len()
+    }
+}
+
+/// `HeaviestWork` prefers the branch with more total work.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaviestWork;
+
+impl ForkChoice for HeaviestWork {
+    fn prefers(&self, branch: &Branch) -> bool {
+        branch.main_work() < branch.side_work()
+    }
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --fork-choice
+/// - --checkpoint
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --fork-choice: longest
+/// - --checkpoint: (not specified)
+pub struct Environment {
+    builtin: Box<dyn ForkChoice>,
+    custom: Option<Box<dyn ForkChoice>>,
+    checkpoints: BTreeMap<BlockHeight, Id>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            builtin: Box::new(LongestChain),
+            custom: None,
+            checkpoints: BTreeMap::new(),
+        }
+    }
+}
+
+/// Parses `s` formatted as 'height:id' where 'id' is a hex string.
+fn parse_checkpoint(s: &str) -> Result<(BlockHeight, Id), String> {
+    let mut it = s.splitn(2, ':');
+    let (height, id) = match (it.next(), it.next()) {
+        (Some(height), Some(id)) => (height, id),
+        _ => return Err(String::from("expected 'height:id'")),
+    };
+
+    let height: BlockHeight = height.parse().map_err(|e| format!("{}", e))?;
+    if height < 0 {
+        return Err(format!("negative height {}", height));
+    }
+    let id: Id = id.parse().map_err(|e| format!("{}", e))?;
+
+    Ok((height, id))
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.args(&[
+            Arg::with_name("fork_choice")
+                .help(
+                    "The rule to choose the main chain among the branches.
+'longest' prefers more blocks, and 'heaviest' prefers more total work.
+It is ignored if the application sets its own rule.",
+                )
+                .possible_values(&["longest", "heaviest"])
+                .long("--fork-choice")
+                .default_value(DEFAULT_RULE)
+                .takes_value(true),
+            Arg::with_name("checkpoint")
+                .help(
+                    "Declares that the block at the height must be the id. (e.g. '100:<hex id>')
+The main chain is never reverted below the highest checkpoint that it reaches.
+It can be specified multiple times.",
+                )
+                .long("--checkpoint")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), Error> {
+        let rule = config.args().value_of("fork_choice").unwrap();
+        self.builtin = match rule {
+            "longest" => Box::new(LongestChain),
+            "heaviest" => Box::new(HeaviestWork),
+            _ => {
+                let msg = format!("Bad parameter for '--fork-choice': {}", rule);
+                return Err(Error::Config(msg));
+            }
+        };
+
+        self.checkpoints.clear();
+        for s in config.args().values_of("checkpoint").into_iter().flatten() {
+            let (height, id) = parse_checkpoint(s).map_err(|e| {
+                let msg = format!("Failed to parse '--checkpoint' {}: {}", s, e);
+                Error::Config(msg)
+            })?;
+
+            match self.checkpoints.insert(height, id) {
+                Some(prev) if prev != id => {
+                    let msg = format!("Conflicting '--checkpoint' at height {}", height);
+                    return Err(Error::Config(msg));
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Sets `rule` to be used instead of '--fork-choice'.
+    pub fn set_rule(&mut self, rule: Box<dyn ForkChoice>) {
+        self.custom = Some(rule);
+    }
+
+    /// Provides a reference to the rule in use.
+    pub fn rule(&self) -> &dyn ForkChoice {
+        match &self.custom {
+            Some(rule) => &**rule,
+            None => &*self.builtin,
+        }
+    }
+
+    /// Provides a reference to the checkpoints. ('--checkpoint')
+    pub fn checkpoints(&self) -> &BTreeMap<BlockHeight, Id> {
+        &self.checkpoints
+    }
+
+    /// Returns `false` if switching to the side branch of `branch` would revert a checkpoint, or
+    /// would put another block at the height of a checkpoint; otherwise, returns `true` .
+    pub fn allows(&self, branch: &Branch) -> bool {
+        let main_tip = branch.fork_height + branch.main.len() as BlockHeight;
+        let side_tip = branch.fork_height + branch.side.len() as BlockHeight;
+
+        for (&height, id) in self.checkpoints.range(branch.fork_height + 1..) {
+            if main_tip < height && side_tip < height {
+                break;
+            }
+
+            let index = (height - branch.fork_height - 1) as usize;
+            match branch.side.get(index) {
+                Some(header) if header.id == *id => (),
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+/// Verifies the main chain against the checkpoints, and finalizes the main chain up to the
+/// highest checkpoint that it reaches. Returns the finalized height if any, or `None` .
+///
+/// # Error
+///
+/// Errors with `Error::Config` if a block in the main chain conflicts with a checkpoint.
+pub fn finalize_checkpoints<S>(
+    env: &Environment,
+    session: &mut S,
+) -> Result<Option<BlockHeight>, Error>
+where
+    S: Master,
+{
+    let heights = env.checkpoints.keys();
+    let chain = main_chain::fetch(heights, session)?;
+
+    for (height, id) in chain.iter() {
+        if env.checkpoints.get(height) != Some(id) {
+            let msg = format!(
+                "The block at height {} is {}, which conflicts with '--checkpoint'",
+                height,
+                id.display_hex()
+            );
+            return Err(Error::Config(msg));
+        }
+    }
+
+    match chain.keys().next_back() {
+        None => Ok(None),
+        Some(&height) => {
+            main_chain::finalize(height, session)?;
+            Ok(Some(height))
+        }
+    }
+}
+
+/// Returns `true` if the block at `height` is in the main chain and never reverted; i.e. it is
+/// finalized, or it is not higher than a checkpoint that the main chain has reached.
+pub fn is_final<S>(height: BlockHeight, env: &Environment, session: &mut S) -> Result<bool, Error>
+where
+    S: Slave,
+{
+    if main_chain::fetch_one(height, session)?.is_none() {
+        return Ok(false);
+    }
+
+    if let Some(finalized) = main_chain::finalized_height(session)? {
+        if height <= finalized {
+            return Ok(true);
+        }
+    }
+
+    for (&h, id) in env.checkpoints.range(height..) {
+        if main_chain::fetch_one(h, session)? == Some(*id) {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Follows the headers from `tip` back to the main chain, and returns the [`Branch`] .
+///
+/// The headers of the main chain blocks are fetched from RDB table "block_headers" as well; the
+/// block whose header is not stored is regarded to have work 1.
+///
+/// Returns `None` if `tip` is in the main chain, if any header on the way is not stored, (i.e.
+/// `tip` is an orphan,) if the heights are not contiguous, or if the fork point is lower than
+/// the finalized height. (The finalized blocks cannot be reverted.)
+///
+/// [`Branch`]: self::Branch
+pub fn branch<S>(tip: &BlockHeader, session: &mut S) -> Result<Option<Branch>, Error>
+where
+    S: Slave,
+{
+    if main_chain::find_by_id(&tip.id, session)?.is_some() {
+        return Ok(None);
+    }
+
+    let mut side = vec![*tip];
+    let fork = loop {
+        let last = *side.last().unwrap();
+
+        if let Some(fork) = main_chain::find_by_id(&last.parent, session)? {
+            if fork.height() + 1 != last.height {
+                return Ok(None);
+            }
+            break fork;
+        }
+
+        match block_headers::fetch(&last.parent, session)? {
+            Some(header) if header.height + 1 == last.height => side.push(header),
+            _ => return Ok(None),
+        }
+    };
+    side.reverse();
+
+    if let Some(finalized) = main_chain::finalized_height(session)? {
+        if fork.height() < finalized {
+            return Ok(None);
+        }
+    }
+
+    let chain = main_chain::fetch_asc(fork.height() + 1, u32::MAX, session)?;
+    let mut main = Vec::with_capacity(chain.as_ref().len());
+    let mut parent = *fork.id();
+    for chain_index in chain.as_ref() {
+        let header = match block_headers::fetch(chain_index.id(), session)? {
+            Some(header) => header,
+            None => BlockHeader {
+                id: *chain_index.id(),
+                parent,
+                height: chain_index.height(),
+                work: 1,
+            },
+        };
+        parent = header.id;
+        main.push(header);
+    }
+
+    Ok(Some(Branch {
+        fork_height: fork.height(),
+        main,
+        side,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Id {
+        let mut id = Id::zeroed();
+        id[0] = n;
+        id
+    }
+
+    fn headers(works: &[i64]) -> Vec<BlockHeader> {
+        works
+            .iter()
+            .enumerate()
+            .map(|(i, &work)| BlockHeader {
+                id: Id::zeroed(),
+                parent: Id::zeroed(),
+                height: i as BlockHeight + 1,
+                work,
+            })
+            .collect()
+    }
+
+    fn branch(main: &[i64], side: &[i64]) -> Branch {
+        Branch {
+            fork_height: 0,
+            main: headers(main),
+            side: headers(side),
+        }
+    }
+
+    #[test]
+    fn longest_chain() {
+        assert_eq!(true, LongestChain.prefers(&branch(&[1], &[1, 1])));
+        assert_eq!(true, LongestChain.prefers(&branch(&[], &[1])));
+        assert_eq!(false, LongestChain.prefers(&branch(&[1, 1], &[1, 1])));
+        assert_eq!(false, LongestChain.prefers(&branch(&[9, 9], &[1])));
+    }
+
+    #[test]
+    fn heaviest_work() {
+        assert_eq!(true, HeaviestWork.prefers(&branch(&[1, 1], &[3])));
+        assert_eq!(false, HeaviestWork.prefers(&branch(&[1, 2], &[3])));
+        assert_eq!(false, HeaviestWork.prefers(&branch(&[5], &[1, 1, 1])));
+
+        let max = branch(&[i64::MAX, i64::MAX], &[i64::MAX, i64::MAX, 1]);
+        assert_eq!(true, HeaviestWork.prefers(&max));
+    }
+
+    #[test]
+    fn environment_rule() {
+        struct Never;
+        impl ForkChoice for Never {
+            fn prefers(&self, _: &Branch) -> bool {
+                false
+            }
+        }
+
+        let mut env = Environment::default();
+        assert_eq!(true, env.rule().prefers(&branch(&[1], &[1, 1])));
+
+        env.set_rule(Box::new(Never));
+        assert_eq!(false, env.rule().prefers(&branch(&[1], &[1, 1])));
+    }
+
+    #[test]
+    fn parse_checkpoint_() {
+        let hex = "01".repeat(32);
+        let mut expected = Id::zeroed();
+        expected.iter_mut().for_each(|b| *b = 1);
+
+        assert_eq!(Ok((10, expected)), parse_checkpoint(&format!("10:{}", hex)));
+        assert_eq!(true, parse_checkpoint(&hex).is_err());
+        assert_eq!(true, parse_checkpoint(&format!("-1:{}", hex)).is_err());
+        assert_eq!(true, parse_checkpoint("10:0101").is_err());
+    }
+
+    #[test]
+    fn allows() {
+        let mut env = Environment::default();
+        env.checkpoints.insert(3, id(3));
+
+        // Main chain: 1, 2, 3 (checkpoint), 4
+        let mut branch = Branch {
+            fork_height: 2,
+            main: headers(&[1, 1]),
+            side: headers(&[1, 1, 1]),
+        };
+
+        // The side branch replaces the checkpoint.
+        assert_eq!(false, env.allows(&branch));
+
+        // The side branch has the checkpoint.
+        branch.side[0].id = id(3);
+        assert_eq!(true, env.allows(&branch));
+
+        // The fork point is above the checkpoint.
+        branch.fork_height = 3;
+        branch.side[0].id = id(4);
+        assert_eq!(true, env.allows(&branch));
+
+        // Neither reaches the checkpoint.
+        env.checkpoints.insert(10, id(10));
+        assert_eq!(true, env.allows(&branch));
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `fuzzing` provides the entry points of the fuzz targets for the on-disk formats.
+//! `fuzzing` depends on module `data_types` and `kvs` .
+//!
+//! This module is available only if feature "fuzzing" is specified.
+//!
+//! Each function takes arbitrary bytes and panics if it finds a broken invariant. The fuzz
+//! targets in directory 'fuzz' (for cargo-fuzz) call them, and the unit tests run them with the
+//! regression corpus checked in 'fuzz/corpus/{target name}'.
+//!
+//! Run the fuzz targets like the following.
+//!
+//! ```sh
+//! cargo +nightly fuzz run kvs_checksum
+//! ```
+
+use crate::data_types;
+use crate::kvs::{checksum, dump};
+
+/// Verifies the checksum trailer of the KVS value.
+///
+/// - `data` as the stored value is accepted only if the trailer is correct.
+/// - `data` as the value is always restored after the trailer is appended.
+pub fn kvs_checksum(data: &[u8]) {
+    if let Some(value) = checksum::strip(data) {
+        assert_eq!(data, &checksum::append(value)[..]);
+    }
+
+    let stored = checksum::append(data);
+    assert_eq!(Some(data), checksum::strip(&stored));
+}
+
+/// Reads `data` as the stream that [`kvs::export`] writes.
+///
+/// The rows read successfully must be written back into the same bytes.
+///
+/// [`kvs::export`]: crate::kvs::export
+pub fn kvs_dump(data: &[u8]) {
+    let mut reader = data;
+    if dump::read_header(&mut reader).is_err() {
+        return;
+    }
+
+    let mut rewritten = Vec::with_capacity(data.len());
+    dump::write_header(&mut rewritten).unwrap();
+
+    loop {
+        let consumed = data.len() - reader.len();
+        match dump::read_row(&mut reader) {
+            Ok(Some((id, intrinsic, extrinsic))) => {
+                dump::write_row(&id, &intrinsic, &extrinsic, &mut rewritten).unwrap();
+                assert_eq!(&data[..data.len() - reader.len()], &rewritten[..]);
+            }
+            Ok(None) => {
+                assert_eq!(data.len(), consumed);
+                break;
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+/// Deserializes `data` with the deserializer registered to `env` .
+///
+/// The intrinsic data of the deserialized acid must be same to `data` .
+///
+/// The application registers the deserializers to `env` and calls this function from its own
+/// fuzz target.
+pub fn acid_intrinsic(data: &[u8], env: &data_types::Environment) {
+    if let Ok(acid) = data_types::deserialize_acid(data, env) {
+        assert_eq!(data, &acid.intrinsic()[..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Calls `f` with each file in 'fuzz/corpus/{target}', and returns the number of the files.
+    fn run_corpus(target: &str, f: fn(&[u8])) -> usize {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "fuzz", "corpus", target]
+            .iter()
+            .collect();
+
+        let mut count = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            f(&data);
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn kvs_checksum_corpus() {
+        assert_ne!(0, run_corpus("kvs_checksum", kvs_checksum));
+    }
+
+    #[test]
+    fn kvs_dump_corpus() {
+        assert_ne!(0, run_corpus("kvs_dump", kvs_dump));
+    }
+
+    #[test]
+    fn acid_intrinsic_() {
+        let env = data_types::Environment::default();
+        acid_intrinsic(b"", &env);
+        acid_intrinsic(b"mouse", &env);
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `health` aggregates the health checks into a report.
+//! `health` is independent from other modules.
+//!
+//! The applications register the named callbacks to [`Registry`] to report the health of their
+//! own subsystems.
+//!
+//! [`Registry`]: self::Registry
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+/// `Status` is the result of a health check.
+///
+/// The order is from the healthiest to the worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Status {
+    /// Working normally.
+    Ok,
+    /// Working, but with some problem.
+    Degraded,
+    /// Not working.
+    Failed,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Ok => f.write_str("ok"),
+            Status::Degraded => f.write_str("degraded"),
+            Status::Failed => f.write_str("failed"),
+        }
+    }
+}
+
+/// `Check` is what a health check callback returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// The result.
+    pub status: Status,
+    /// The human readable details. (Can be empty.)
+    pub details: String,
+}
+
+impl Check {
+    /// Creates a new instance with [`Status::Ok`] and empty details.
+    pub fn ok() -> Self {
+        Self {
+            status: Status::Ok,
+            details: String::new(),
+        }
+    }
+
+    /// Creates a new instance with [`Status::Degraded`] .
+    pub fn degraded<S: Into<String>>(details: S) -> Self {
+        Self {
+            status: Status::Degraded,
+            details: details.into(),
+        }
+    }
+
+    /// Creates a new instance with [`Status::Failed`] .
+    pub fn failed<S: Into<String>>(details: S) -> Self {
+        Self {
+            status: Status::Failed,
+            details: details.into(),
+        }
+    }
+}
+
+/// `Entry` is the named result in [`Report`] .
+///
+/// [`Report`]: self::Report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the health check.
+    pub name: String,
+    /// The result.
+    pub check: Check,
+}
+
+/// `Report` is the results of all the health checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The results in the order of the registration.
+    pub entries: Vec<Entry>,
+}
+
+impl Report {
+    /// Returns the worst status in `self` , or [`Status::Ok`] if `self` is empty.
+    pub fn status(&self) -> Status {
+        self.entries
+            .iter()
+            .map(|entry| entry.check.status)
+            .max()
+            .unwrap_or(Status::Ok)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.status())?;
+        for entry in self.entries.iter() {
+            write!(f, "\n{}: {}", entry.name, entry.check.status)?;
+            if !entry.check.details.is_empty() {
+                write!(f, " ({})", entry.check.details)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+type Callback = Arc<dyn Fn() -> Check + Send + Sync>;
+
+/// `Registry` stores the named health check callbacks.
+#[derive(Default)]
+pub struct Registry {
+    callbacks: Mutex<Vec<(String, Callback)>>,
+}
+
+impl Registry {
+    /// Registers `callback` as `name` .
+    ///
+    /// If another callback is registered as `name` , it is replaced keeping the order.
+    pub fn register<F>(&self, name: &str, callback: F)
+    where
+        F: 'static + Fn() -> Check + Send + Sync,
+    {
+        let callback: Callback = Arc::new(callback);
+        let mut callbacks = self.callbacks.lock().unwrap();
+
+        match callbacks.iter_mut().find(|(n, _)| n == name) {
+            Some((_, c)) => *c = callback,
+            None => callbacks.push((String::from(name), callback)),
+        }
+    }
+
+    /// Removes the callback registered as `name` , and returns `true` if found.
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut callbacks = self.callbacks.lock().unwrap();
+        let len = callbacks.len();
+        callbacks.retain(|(n, _)| n != name);
+        callbacks.len() != len
+    }
+
+    /// Calls each callback and appends the result to `report` .
+    ///
+    /// A callback which panics is regarded as [`Status::Failed`] .
+    pub fn run(&self, report: &mut Report) {
+        // Not to hold the lock while calling the callbacks, which may take long.
+        let callbacks: Vec<(String, Callback)> = self.callbacks.lock().unwrap().clone();
+
+        for (name, callback) in callbacks {
+            let check = match panic::catch_unwind(AssertUnwindSafe(|| callback())) {
+                Ok(check) => check,
+                Err(_) => Check::failed("The health check panicked."),
+            };
+            report.entries.push(Entry { name, check });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_() {
+        let registry = Registry::default();
+        registry.register("foo", || Check::ok());
+        registry.register("bar", || Check::degraded("slow"));
+        registry.register("baz", || panic!("baz"));
+
+        let mut report = Report::default();
+        registry.run(&mut report);
+
+        let names: Vec<&str> = report.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["foo", "bar", "baz"], names);
+        assert_eq!(Check::degraded("slow"), report.entries[1].check);
+        assert_eq!(Status::Failed, report.entries[2].check.status);
+        assert_eq!(Status::Failed, report.status());
+    }
+
+    #[test]
+    fn register_replaces() {
+        let registry = Registry::default();
+        registry.register("foo", || Check::failed("foo"));
+        registry.register("bar", || Check::ok());
+        registry.register("foo", || Check::ok());
+
+        let mut report = Report::default();
+        registry.run(&mut report);
+        assert_eq!(2, report.entries.len());
+        assert_eq!("foo", report.entries[0].name);
+        assert_eq!(Status::Ok, report.status());
+    }
+
+    #[test]
+    fn unregister_() {
+        let registry = Registry::default();
+        registry.register("foo", || Check::ok());
+
+        assert_eq!(true, registry.unregister("foo"));
+        assert_eq!(false, registry.unregister("foo"));
+
+        let mut report = Report::default();
+        registry.run(&mut report);
+        assert_eq!(Report::default(), report);
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `import` bootstraps the main chain from the data of a foreign chain; e.g. the block files of
+//! Bitcoin.
+//! `import` depends on module `data_types` , `rdb` , and `storage` .
+//!
+//! The application implements [`ImportAdapter`] for the foreign format, and function [`import`]
+//! commits each block via [`storage::commit_block`] .
+//!
+//! # Resume
+//!
+//! The blocks already in the main chain are skipped, so [`import`] can be called again with the
+//! same data after it is interrupted.
+//!
+//! [`ImportAdapter`]: self::ImportAdapter
+//! [`import`]: self::import
+//! [`storage::commit_block`]: crate::storage::commit_block
+
+use crate::data_types::{BlockHeight, CAcid, ChainIndex, CryptoHash};
+use crate::{rdb, GlobalEnvironment};
+use std::error::Error;
+
+/// `ImportedBlock` is a block mapped from a foreign record.
+pub struct ImportedBlock {
+    /// The height of the block in the main chain.
+    pub height: BlockHeight,
+    /// The block itself.
+    pub block: CAcid,
+    /// The acids belonging to the block except for `block` .
+    pub acids: Vec<CAcid>,
+}
+
+/// `ImportAdapter` reads the records of a foreign chain in the order of the height and maps them
+/// to the acids.
+pub trait ImportAdapter {
+    /// The record in the foreign format.
+    type Record;
+
+    /// Reads the next record and returns it, or returns `None` if no record is left.
+    fn next_record(&mut self) -> Result<Option<Self::Record>, Box<dyn Error>>;
+
+    /// Maps `record` into the block.
+    fn to_block(&mut self, record: Self::Record) -> Result<ImportedBlock, Box<dyn Error>>;
+}
+
+/// `Summary` is the result of function [`import`] .
+///
+/// [`import`]: self::import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// The number of the blocks committed.
+    pub imported: u64,
+    /// The number of the blocks skipped because they are already in the main chain.
+    pub skipped: u64,
+}
+
+/// Commits all the blocks that `adapter` provides, and returns how many blocks are committed.
+///
+/// The blocks already in the main chain are skipped. If another block is in the main chain at
+/// the same height, this function fails.
+///
+/// `progress` is called with the height every time a block is committed or skipped.
+pub fn import<A, P>(
+    adapter: &mut A,
+    env: &GlobalEnvironment,
+    mut progress: P,
+) -> Result<Summary, Box<dyn Error>>
+where
+    A: ImportAdapter,
+    P: FnMut(BlockHeight),
+{
+    let mut summary = Summary::default();
+
+    while let Some(record) = adapter.next_record()? {
+        let ImportedBlock {
+            height,
+            block,
+            acids,
+        } = adapter.to_block(record)?;
+        let chain_index = ChainIndex::new(height, block.id());
+
+        let committed = {
+            let mut session = rdb::slave(&env.rdb);
+            rdb::main_chain::fetch_one(height, &mut session)?
+        };
+
+        match committed {
+            Some(id) if id == *block.id() => summary.skipped += 1,
+            Some(id) => {
+                let msg = format!(
+                    "Failed to import block {}: block {} is at height {}",
+                    block.id().display_hex(),
+                    id.display_hex(),
+                    height
+                );
+                return Err(Box::from(msg));
+            }
+            None => {
+                let mut all = Vec::with_capacity(acids.len() + 1);
+                all.push(block);
+                all.extend(acids);
+                crate::commit_block(&chain_index, &all, false, env)?;
+                summary.imported += 1;
+            }
+        }
+
+        progress(height);
+    }
+
+    info!(
+        "Imported {} blocks. ({} blocks were already in the main chain.)",
+        summary.imported, summary.skipped
+    );
+    Ok(summary)
+}
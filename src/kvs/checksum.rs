@@ -0,0 +1,107 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `checksum` appends and verifies the CRC-32 trailer of the values stored in the KVS.
+
+use core::convert::TryFrom;
+
+/// The byte size of the trailer.
+pub const TRAILER_LEN: usize = 4;
+
+/// CRC-32 (IEEE 802.3) lookup table.
+static TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+/// Calculates CRC-32 of `bytes` .
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let crc = bytes.iter().fold(!0, |crc: u32, &b| {
+        TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+/// Returns `value` followed by the checksum trailer.
+pub fn append(value: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(value.len() + TRAILER_LEN);
+    ret.extend_from_slice(value);
+    ret.extend_from_slice(&crc32(value).to_le_bytes());
+    ret
+}
+
+/// Verifies the trailer of `stored` and returns the value without the trailer, or `None` if the
+/// trailer does not match.
+pub fn strip(stored: &[u8]) -> Option<&[u8]> {
+    if stored.len() < TRAILER_LEN {
+        return None;
+    }
+
+    let (value, trailer) = stored.split_at(stored.len() - TRAILER_LEN);
+    let trailer = u32::from_le_bytes(<[u8; TRAILER_LEN]>::try_from(trailer).unwrap());
+
+    if crc32(value) == trailer {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xcbf43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn append_strip() {
+        for value in &[&b""[..], b"a", b"mouse"] {
+            let stored = append(value);
+            assert_eq!(value.len() + TRAILER_LEN, stored.len());
+            assert_eq!(Some(*value), strip(&stored));
+
+            for i in 0..stored.len() {
+                let mut broken = stored.clone();
+                broken[i] ^= 1;
+                assert_eq!(None, strip(&broken));
+            }
+            assert_eq!(None, strip(&stored[..stored.len() - 1]));
+        }
+    }
+}
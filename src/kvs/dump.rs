@@ -0,0 +1,273 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `dump` exports the KVS data into a byte stream and imports it.
+
+use super::{fetch, put, Environment, ReadQuery, WriteQuery};
+use crate::data_types::{CryptoHash, Id};
+use crate::kvs;
+use crate::rdb::{self, Slave};
+use crate::Error;
+use core::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+/// The header of the exported stream. (The last byte is the format version.)
+const MAGIC: &'static [u8] = b"MOUSEKVS\x01";
+
+/// The number of the ids fetched from the RDB at once.
+const EXPORT_CHUNK: u32 = 1024;
+
+/// The number of the rows imported before waiting for the writes.
+const IMPORT_CHUNK: usize = 1024;
+
+fn write_bytes<W: Write>(bytes: &[u8], writer: &mut W) -> io::Result<()> {
+    let len = u32::try_from(bytes.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too large data to export"))?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as u64;
+
+    // Not to allocate the length before the data arrives; the length can be broken.
+    let mut ret = Vec::new();
+    reader.take(len).read_to_end(&mut ret)?;
+    if (ret.len() as u64) < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(ret)
+}
+
+/// Reads the next `Id` , or returns `None` if `reader` reaches the end.
+fn read_id<R: Read>(reader: &mut R) -> io::Result<Option<Id>> {
+    let mut id = Id::zeroed();
+
+    let mut filled = 0;
+    while filled < id.len() {
+        match reader.read(&mut id[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(Some(id))
+}
+
+/// Writes the header of the stream.
+pub fn write_header<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(MAGIC)
+}
+
+/// Reads the header of the stream and returns an error if the format is unknown.
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<()> {
+    let mut magic = vec![0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic == MAGIC {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "unknown format"))
+    }
+}
+
+/// Writes a row of `id` , `intrinsic` , and `extrinsic` .
+pub fn write_row<W: Write>(
+    id: &Id,
+    intrinsic: &[u8],
+    extrinsic: &[u8],
+    writer: &mut W,
+) -> io::Result<()> {
+    writer.write_all(id.as_ref())?;
+    write_bytes(intrinsic, writer)?;
+    write_bytes(extrinsic, writer)
+}
+
+/// Reads the next row, i.e. (id, intrinsic, extrinsic), or returns `None` if `reader` reaches
+/// the end.
+pub fn read_row<R: Read>(reader: &mut R) -> io::Result<Option<(Id, Vec<u8>, Vec<u8>)>> {
+    match read_id(reader)? {
+        None => Ok(None),
+        Some(id) => {
+            let intrinsic = read_bytes(reader)?;
+            let extrinsic = read_bytes(reader)?;
+            Ok(Some((id, intrinsic, extrinsic)))
+        }
+    }
+}
+
+/// Writes all the KVS data of the acids in RDB table "acids" into `writer` , and returns the
+/// number of the exported rows.
+///
+/// The KVS cannot enumerate the data, so this function follows RDB table "acids"; i.e. the data
+/// not in the table is not exported. The id in the table but not in the KVS is skipped with a
+/// warning log.
+///
+/// `progress` is called with the number of the exported rows every time a row is exported.
+///
+/// # Format
+///
+/// The stream starts with "MOUSEKVS\x01" followed by the rows. Each row is the id, the length of
+/// the intrinsic data as a 4 bytes little endian integer, the intrinsic data, the length of the
+/// extrinsic data, and the extrinsic data.
+pub fn export<W, S, P>(
+    mut writer: W,
+    env: &Environment,
+    session: &mut S,
+    mut progress: P,
+) -> Result<u64, Error>
+where
+    W: Write,
+    S: Slave,
+    P: FnMut(u64),
+{
+    write_header(&mut writer)?;
+
+    let mut exported = 0;
+    let mut min_seq = None;
+
+    loop {
+        let ids = rdb::acids::fetch_ids(min_seq, EXPORT_CHUNK, session)?;
+        let ids = ids.as_ref();
+
+        for (_, id) in ids {
+            let mut query = fetch(id, env);
+            let row = match query.wait() {
+                Ok(Some(row)) => row,
+                Ok(None) => {
+                    warn!("Acid {} is not in the KVS; skipped.", id.display_hex());
+                    continue;
+                }
+                Err(e) => return Err(Error::from(kvs::Error::clone(&e))),
+            };
+
+            write_row(id, &row.intrinsic, &row.extrinsic, &mut writer)?;
+
+            exported += 1;
+            progress(exported);
+        }
+
+        match ids.last() {
+            Some((seq, _)) if ids.len() == EXPORT_CHUNK as usize => min_seq = Some(seq + 1),
+            _ => break,
+        }
+    }
+
+    writer.flush()?;
+    Ok(exported)
+}
+
+/// Puts the data exported by [`export`] into the KVS, and returns the number of the imported rows.
+///
+/// The existing data with the same id is overwritten. Note that this function does not touch the
+/// RDB.
+///
+/// `progress` is called with the number of the imported rows every time the writes are
+/// completed.
+///
+/// [`export`]: self::export
+pub fn import<R, P>(mut reader: R, env: &Environment, mut progress: P) -> Result<u64, Error>
+where
+    R: Read,
+    P: FnMut(u64),
+{
+    read_header(&mut reader)
+        .map_err(|e| Error::kvs(format!("Failed to import the KVS data: {}", e)))?;
+
+    let mut imported = 0;
+    let mut queries = Vec::with_capacity(IMPORT_CHUNK);
+
+    loop {
+        let row = read_row(&mut reader)?;
+        let is_end = row.is_none();
+        if let Some((id, intrinsic, extrinsic)) = row {
+            queries.push(put(&id, &intrinsic, &extrinsic, env));
+        }
+
+        if queries.len() == IMPORT_CHUNK || (is_end && !queries.is_empty()) {
+            for query in queries.iter_mut() {
+                query
+                    .wait()
+                    .map_err(|e| Error::from(kvs::Error::clone(&e)))?;
+            }
+
+            imported += queries.len() as u64;
+            queries.clear();
+            progress(imported);
+        }
+
+        if is_end {
+            return Ok(imported);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_write_bytes() {
+        let mut buffer = Vec::new();
+        write_bytes(b"", &mut buffer).unwrap();
+        write_bytes(b"mouse", &mut buffer).unwrap();
+
+        let mut reader = &buffer[..];
+        assert_eq!(b"".to_vec(), read_bytes(&mut reader).unwrap());
+        assert_eq!(b"mouse".to_vec(), read_bytes(&mut reader).unwrap());
+        assert_eq!(true, read_bytes(&mut reader).is_err());
+
+        // The length is broken.
+        let mut reader: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x00];
+        assert_eq!(true, read_bytes(&mut reader).is_err());
+    }
+
+    #[test]
+    fn read_id_() {
+        let id = Id::calculate(b"mouse");
+
+        let mut reader: &[u8] = id.as_ref();
+        assert_eq!(Some(id), read_id(&mut reader).unwrap());
+        assert_eq!(None, read_id(&mut reader).unwrap());
+
+        let mut reader = &id.as_ref()[1..];
+        assert_eq!(true, read_id(&mut reader).is_err());
+    }
+
+    #[test]
+    fn read_write_row() {
+        let id = Id::calculate(b"mouse");
+
+        let mut buffer = Vec::new();
+        write_header(&mut buffer).unwrap();
+        write_row(&id, b"intrinsic", b"", &mut buffer).unwrap();
+
+        let mut reader = &buffer[..];
+        read_header(&mut reader).unwrap();
+        assert_eq!(
+            Some((id, b"intrinsic".to_vec(), Vec::new())),
+            read_row(&mut reader).unwrap()
+        );
+        assert_eq!(None, read_row(&mut reader).unwrap());
+
+        let mut reader = &buffer[1..];
+        assert_eq!(true, read_header(&mut reader).is_err());
+    }
+}
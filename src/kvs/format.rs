@@ -0,0 +1,125 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `format` compares the format of the data stored in the KVS with the one that the options
+//! specify.
+
+use super::leveldb::stored_format;
+use super::Environment;
+use crate::data_types::CryptoHash;
+use crate::rdb::{self, Slave};
+use crate::Error;
+use std::fmt;
+
+/// The number of the ids fetched from the RDB at once.
+const CHUNK: u32 = 1024;
+
+/// `FormatState` is the number of the rows in the KVS to be converted for the options
+/// '--kvs-verify-checksums' and '--kvs-dedup-extrinsic-min-bytes' .
+///
+/// The KVS data is converted by exporting it and importing it into a new KVS with the new
+/// options. (See also function [`export`] and [`import`] .)
+///
+/// [`export`]: crate::kvs::export
+/// [`import`]: crate::kvs::import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatState {
+    /// Whether '--kvs-verify-checksums' is specified.
+    pub verify_checksums: bool,
+    /// Whether '--kvs-dedup-extrinsic-min-bytes' is specified.
+    pub dedup: bool,
+    /// The number of the inspected rows.
+    pub rows: u64,
+    /// The number of the rows stored without the checksum trailer if `verify_checksums` is
+    /// `true` , or with it if `false` .
+    pub checksum_conversions: u64,
+    /// The number of the rows whose extrinsic data is not tagged if `dedup` is `true` , or is
+    /// tagged if `false` . (The rows without the extrinsic data are not counted.)
+    pub dedup_conversions: u64,
+}
+
+impl FormatState {
+    /// Returns `true` if no row is to be converted.
+    pub fn is_empty(&self) -> bool {
+        self.checksum_conversions == 0 && self.dedup_conversions == 0
+    }
+}
+
+impl fmt::Display for FormatState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled = |b| if b { "enabled" } else { "disabled" };
+        write!(
+            f,
+            "Would convert {} of {} KVS rows for '--kvs-verify-checksums' ({}) and {} rows for \
+             '--kvs-dedup-extrinsic-min-bytes' ({})",
+            self.checksum_conversions,
+            self.rows,
+            enabled(self.verify_checksums),
+            self.dedup_conversions,
+            enabled(self.dedup)
+        )
+    }
+}
+
+/// Inspects the KVS data of the acids in RDB table "acids" , and returns the number of the rows
+/// to be converted for the current options without changing anything.
+///
+/// The KVS cannot enumerate the data, so this function follows RDB table "acids" as function
+/// [`export`] does. The format of each row is told from the data itself, so the counts are
+/// estimates. (See also function `stored_format` .)
+///
+/// [`export`]: crate::kvs::export
+pub fn format_state<S>(env: &Environment, session: &mut S) -> Result<FormatState, Error>
+where
+    S: Slave,
+{
+    let mut ret = FormatState {
+        verify_checksums: env.verify_checksums(),
+        dedup: env.is_dedup_enabled(),
+        ..FormatState::default()
+    };
+    let mut min_seq = None;
+
+    loop {
+        let ids = rdb::acids::fetch_ids(min_seq, CHUNK, session)?;
+        let ids = ids.as_ref();
+
+        for (_, id) in ids {
+            let format = match stored_format(id, env)? {
+                Some(format) => format,
+                None => {
+                    warn!("Acid {} is not in the KVS; skipped.", id.display_hex());
+                    continue;
+                }
+            };
+
+            ret.rows += 1;
+            if format.checksum != ret.verify_checksums {
+                ret.checksum_conversions += 1;
+            }
+            if format.dedup.map_or(false, |dedup| dedup != ret.dedup) {
+                ret.dedup_conversions += 1;
+            }
+        }
+
+        match ids.last() {
+            Some((seq, _)) if ids.len() == CHUNK as usize => min_seq = Some(seq + 1),
+            _ => break,
+        }
+    }
+
+    Ok(ret)
+}
@@ -14,16 +14,36 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::{ReadQuery, Row, WriteQuery};
-use crate::data_types::{Acid, Id};
-use crate::{Config, ModuleEnvironment};
+use super::checksum;
+use super::prefetch::Pool;
+use super::speculative::Speculation;
+use super::{CorruptRow, Error, ReadQuery, Row, WriteQuery};
+use crate::data_types::{self, Acid, CryptoHash, Id};
+use crate::retry::Backoff;
+use crate::runtime::{self, WorkerGroup};
+use crate::{cache, fault, Config, ModuleEnvironment};
 use clap::{App, Arg};
 use counting_pointer::Asc;
 use spin_sync::Mutex;
 use std::borrow::Cow;
-use std::error::Error;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
 use std::ffi::CString;
-use std::path::PathBuf;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const DEFAULT_FLUSH_INTERVAL_MS: &'static str = "10";
+const DEFAULT_PREFETCH_THREADS: &'static str = "4";
+const DEFAULT_SPECULATIVE_PREFETCH_DEPTH: &'static str = "0";
+const DEFAULT_SPECULATIVE_PREFETCH_BUDGET: &'static str = "256";
+const DEFAULT_OPEN_ATTEMPTS: &'static str = "1";
+const DEFAULT_OPEN_RETRY_DELAY_MS: &'static str = "100";
 
 struct Db {
     intrinsic: mouse_leveldb::Database,
@@ -40,16 +60,23 @@ impl Default for Db {
 }
 
 impl Db {
-    pub fn open(&mut self, path: &PathBuf) -> Result<(), Box<dyn Error>> {
+    /// Opens the databases retrying with `backoff` .
+    pub fn open(
+        &mut self,
+        path: &PathBuf,
+        backoff: &Backoff,
+    ) -> Result<(), Box<dyn error::Error + Send + Sync>> {
         let mut path = path.clone();
         {
             path.push("intrinsic");
             let path = path.to_string_lossy().into_owned().into_bytes();
             let path = CString::new(path).or_else(|e| {
-                let err: Box<dyn Error> = Box::from(format!("Failed to open KVS: {}", e));
+                let err: Box<dyn error::Error + Send + Sync> =
+                    Box::from(format!("Failed to open KVS: {}", e));
                 Err(err)
             })?;
-            self.intrinsic.open(&path)?;
+            let db = &mut self.intrinsic;
+            backoff.retry("open the intrinsic KVS", || db.open(&path))?;
         }
 
         {
@@ -57,10 +84,12 @@ impl Db {
             path.push("extrinsic");
             let path = path.to_string_lossy().into_owned().into_bytes();
             let path = CString::new(path).or_else(|e| {
-                let err: Box<dyn Error> = Box::from(format!("Failed to open KVS: {}", e));
+                let err: Box<dyn error::Error + Send + Sync> =
+                    Box::from(format!("Failed to open KVS: {}", e));
                 Err(err)
             })?;
-            self.extrinsic.open(&path)?;
+            let db = &mut self.extrinsic;
+            backoff.retry("open the extrinsic KVS", || db.open(&path))?;
         }
 
         Ok(())
@@ -71,6 +100,9 @@ struct WriteBatch {
     results: Vec<Asc<Mutex<PutResult>>>,
     intrinsic: mouse_leveldb::WriteBatch,
     extrinsic: mouse_leveldb::WriteBatch,
+    /// The extrinsic values which the reference counting reads before they are flushed.
+    /// (See [`Shared::read_extrinsic`] .)
+    pending: HashMap<Vec<u8>, Vec<u8>>,
 }
 
 impl Default for WriteBatch {
@@ -79,6 +111,7 @@ impl Default for WriteBatch {
             results: Vec::new(),
             intrinsic: mouse_leveldb::WriteBatch::new(),
             extrinsic: mouse_leveldb::WriteBatch::new(),
+            pending: HashMap::new(),
         }
     }
 }
@@ -101,27 +134,52 @@ impl WriteBatch {
         self.results.len()
     }
 
-    pub fn put(&mut self, id: &Id, intrinsic: &[u8], extrinsic: &[u8]) -> Asc<Mutex<PutResult>> {
+    pub fn put(&mut self, id: &[u8], intrinsic: &[u8], extrinsic: &[u8]) -> Asc<Mutex<PutResult>> {
         if !intrinsic.is_empty() {
-            self.intrinsic.put(id.as_ref(), intrinsic);
+            self.intrinsic.put(id, intrinsic);
         }
         if !extrinsic.is_empty() {
-            self.extrinsic.put(id.as_ref(), extrinsic);
+            self.extrinsic.put(id, extrinsic);
         }
 
+        self.result()
+    }
+
+    /// Returns the result to be set when `self` is flushed next time without putting anything.
+    pub fn result(&mut self) -> Asc<Mutex<PutResult>> {
         let result = Asc::from(Mutex::new(PutResult::NotYet));
         self.results.push(result.clone());
 
         result
     }
 
+    /// Puts `extrinsic` into the extrinsic batch without a result.
+    pub fn put_extrinsic(&mut self, key: &[u8], extrinsic: &[u8]) {
+        self.extrinsic.put(key, extrinsic);
+    }
+
+    /// Same to [`put_extrinsic`] except for that [`Shared::read_extrinsic`] reads `extrinsic`
+    /// before `self` is flushed.
+    ///
+    /// [`put_extrinsic`]: Self::put_extrinsic
+    pub fn put_tracked(&mut self, key: &[u8], extrinsic: &[u8]) {
+        self.extrinsic.put(key, extrinsic);
+        self.pending.insert(key.to_vec(), extrinsic.to_vec());
+    }
+
     pub fn flush(&mut self, db: &Db) {
         // Flush extrinsic batch
         {
+            if let Err(e) = fault::hit(fault::Point::KvsExtrinsicWrite) {
+                self.set_error(Error::from(e));
+                self.clear();
+                return;
+            }
+
             let db = &db.extrinsic;
             let res = mouse_leveldb::write(db, &mut self.extrinsic);
             if let Err(e) = res {
-                self.set_error(e);
+                self.set_error(backend_error(e));
                 self.clear();
                 return;
             }
@@ -129,10 +187,16 @@ impl WriteBatch {
 
         // Flush intrinsic batch
         {
+            if let Err(e) = fault::hit(fault::Point::KvsIntrinsicWrite) {
+                self.set_error(Error::from(e));
+                self.clear();
+                return;
+            }
+
             let db = &db.intrinsic;
             let res = mouse_leveldb::write(db, &mut self.intrinsic);
             if let Err(e) = res {
-                self.set_error(e);
+                self.set_error(backend_error(e));
                 self.clear();
                 return;
             }
@@ -147,8 +211,8 @@ impl WriteBatch {
         self.clear();
     }
 
-    fn set_error(&mut self, e: mouse_leveldb::Error) {
-        let e = Asc::from(e);
+    fn set_error(&mut self, e: Error) {
+        let e = Arc::new(e);
 
         for r in &self.results {
             let mut r = r.lock().unwrap();
@@ -160,17 +224,269 @@ impl WriteBatch {
         self.results.clear();
         self.intrinsic.clear();
         self.extrinsic.clear();
+        self.pending.clear();
+    }
+}
+
+/// `Shared` is the part of `Environment` shared with the flusher thread.
+#[derive(Default)]
+struct Shared {
+    db: Db,
+    max_write_queries: usize,
+    verify_checksums: bool,
+    /// The min byte size of the extrinsic data to be shared, or `None` to disable it.
+    dedup_min_bytes: Option<usize>,
+    write_batch: std::sync::Mutex<WriteBatch>,
+    /// Notified when the batch reaches '--max-write-kvs-queries' or when the flusher should stop.
+    filled: Condvar,
+    /// Notified when the batch is flushed.
+    flushed: Condvar,
+    is_stopping: AtomicBool,
+}
+
+impl Shared {
+    /// Returns `stored` without the checksum trailer if '--kvs-verify-checksums' is specified, or
+    /// `stored` itself.
+    ///
+    /// The checksum must have been verified.
+    fn strip_checksum<'a>(&self, stored: &'a [u8]) -> &'a [u8] {
+        if self.verify_checksums && !stored.is_empty() {
+            &stored[..stored.len() - checksum::TRAILER_LEN]
+        } else {
+            stored
+        }
+    }
+
+    /// Returns the value to be stored as the extrinsic data of an acid, and the key and the value
+    /// of the shared extrinsic data if `extrinsic` is large enough to be shared.
+    fn encode_extrinsic<'a>(
+        &self,
+        extrinsic: &'a [u8],
+    ) -> (Cow<'a, [u8]>, Option<(Vec<u8>, Vec<u8>)>) {
+        let min_bytes = match self.dedup_min_bytes {
+            Some(min_bytes) if !extrinsic.is_empty() => min_bytes,
+            _ => return (with_checksum(extrinsic, self.verify_checksums), None),
+        };
+
+        let inline = tagged(EXTRINSIC_INLINE, extrinsic);
+        let inline = with_checksum(&inline, self.verify_checksums).into_owned();
+        if extrinsic.len() < min_bytes {
+            return (Cow::Owned(inline), None);
+        }
+
+        let hash = Id::calculate(extrinsic);
+        let pointer = tagged(EXTRINSIC_POINTER, hash.as_ref());
+        let pointer = with_checksum(&pointer, self.verify_checksums).into_owned();
+        (Cow::Owned(pointer), Some((blob_key(hash.as_ref()), inline)))
+    }
+
+    /// Returns the extrinsic value stored with `key` including the one in `batch` not flushed
+    /// yet, or `None` if nothing is stored.
+    fn read_extrinsic(&self, key: &[u8], batch: &WriteBatch) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(value) = batch.pending.get(key) {
+            return Ok(Some(value.clone()));
+        }
+
+        let value = mouse_leveldb::get(&self.db.extrinsic, key).map_err(backend_error)?;
+        let value: &[u8] = value.as_ref();
+        if value.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(value.to_vec()))
+        }
+    }
+
+    /// Returns the hash of the shared extrinsic data if `stored` is the pointer to it, or `None` .
+    ///
+    /// Broken `stored` is regarded as not a pointer, so that the shared extrinsic data is leaked
+    /// rather than deleted while it is referred to.
+    fn pointer(&self, stored: &[u8]) -> Option<Vec<u8>> {
+        let value = if self.verify_checksums {
+            checksum::strip(stored)?
+        } else {
+            stored
+        };
+
+        match value.split_first() {
+            Some((&EXTRINSIC_POINTER, hash)) => Some(hash.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of the acids referring to the shared extrinsic data of `hash` .
+    fn references(&self, hash: &[u8], batch: &WriteBatch) -> Result<u64, Error> {
+        let key = count_key(hash);
+        let stored = match self.read_extrinsic(&key, batch)? {
+            None => return Ok(0),
+            Some(stored) => stored,
+        };
+
+        let value = if self.verify_checksums {
+            checksum::strip(&stored)
+        } else {
+            Some(&stored[..])
+        };
+        match value.and_then(|value| <[u8; 8]>::try_from(value).ok()) {
+            Some(bytes) => Ok(u64::from_le_bytes(bytes)),
+            None => Err(Error::from(CorruptRow::new(&key, "extrinsic"))),
+        }
+    }
+
+    /// Puts `count` as the number of the acids referring to the shared extrinsic data of `hash`
+    /// into `batch` .
+    fn set_references(&self, hash: &[u8], count: u64, batch: &mut WriteBatch) {
+        let value = with_checksum(&count.to_le_bytes(), self.verify_checksums).into_owned();
+        batch.put_tracked(&count_key(hash), &value);
+    }
+
+    /// Moves the reference of `id` to the shared extrinsic data which `stored` points to, if
+    /// any.
+    ///
+    /// The counts are not changed if `id` already refers to it, so that putting the same acid
+    /// twice (e.g. to redo the storage journal) is idempotent. The shared extrinsic data which
+    /// `id` referred to before is not deleted even if the count reaches 0.
+    fn move_reference(
+        &self,
+        id: &[u8],
+        stored: &[u8],
+        batch: &mut WriteBatch,
+    ) -> Result<(), Error> {
+        let old = self.read_extrinsic(id, batch)?;
+        let old = old.and_then(|old| self.pointer(&old));
+        let new = self.pointer(stored);
+
+        if old != new {
+            let old_count = match &old {
+                None => 0,
+                Some(hash) => self.references(hash, batch)?,
+            };
+            let new_count = match &new {
+                None => 0,
+                Some(hash) => self.references(hash, batch)?,
+            };
+
+            // Both counts are read before writing either, not to update only one of them.
+            if let Some(hash) = old {
+                self.set_references(&hash, old_count.saturating_sub(1), batch);
+            }
+            if let Some(hash) = new {
+                self.set_references(&hash, new_count + 1, batch);
+            }
+        }
+
+        batch.pending.insert(id.to_vec(), stored.to_vec());
+        Ok(())
+    }
+}
+
+/// Tag of the extrinsic data stored as it is.
+const EXTRINSIC_INLINE: u8 = 0;
+/// Tag of the extrinsic data which is the hash of the shared extrinsic data.
+const EXTRINSIC_POINTER: u8 = 1;
+/// The prefix of the key of the shared extrinsic data.
+const BLOB_KEY_PREFIX: u8 = 0xff;
+/// The prefix of the key of the number of the acids referring to the shared extrinsic data.
+const COUNT_KEY_PREFIX: u8 = 0xfe;
+/// The byte length of the keys of the shared extrinsic data and of the reference counts.
+///
+/// The acid data is stored in the same key space, so the id must be shorter than this not to
+/// collide with these keys. (See [`check_id`] .)
+///
+/// [`check_id`]: self::check_id
+const SHARED_KEY_LEN: usize = 1 + Id::LEN;
+
+/// Returns an error if `id` is too long to be stored in the KVS; i.e. unless `id` is shorter
+/// than [`SHARED_KEY_LEN`] .
+///
+/// [`SHARED_KEY_LEN`]: self::SHARED_KEY_LEN
+fn check_id(id: &[u8]) -> Result<(), Error> {
+    if id.len() < SHARED_KEY_LEN {
+        Ok(())
+    } else {
+        Err(Error::IdTooLong(id.len()))
+    }
+}
+
+fn tagged(tag: u8, payload: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(payload.len() + 1);
+    ret.push(tag);
+    ret.extend_from_slice(payload);
+    ret
+}
+
+fn blob_key(hash: &[u8]) -> Vec<u8> {
+    tagged(BLOB_KEY_PREFIX, hash)
+}
+
+fn count_key(hash: &[u8]) -> Vec<u8> {
+    tagged(COUNT_KEY_PREFIX, hash)
+}
+
+/// Flushes the batch every time it reaches '--max-write-kvs-queries' or `interval` elapses until
+/// `Shared.is_stopping` is set.
+fn flush_loop(shared: &Shared, interval: Duration) {
+    let mut batch = shared.write_batch.lock().unwrap();
+    loop {
+        let is_stopping = shared.is_stopping.load(Ordering::Acquire);
+
+        if !is_stopping && batch.len() < shared.max_write_queries {
+            batch = shared.filled.wait_timeout(batch, interval).unwrap().0;
+        }
+
+        if 0 < batch.len() {
+            batch.flush(&shared.db);
+            shared.flushed.notify_all();
+        }
+
+        if is_stopping {
+            return;
+        }
     }
 }
 
 /// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --kvs-db-path
+/// - --max-write-kvs-queries
+/// - --kvs-flush-interval-ms
+/// - --kvs-prefetch-threads
+/// - --kvs-speculative-prefetch-depth
+/// - --kvs-speculative-prefetch-budget
+/// - --kvs-verify-checksums
+/// - --kvs-dedup-extrinsic-min-bytes
+/// - --kvs-open-attempts
+/// - --kvs-open-retry-delay-ms
 #[derive(Default)]
 pub struct Environment {
     db_path: PathBuf,
-    db: Db,
+    open_backoff: Backoff,
+    flush_interval: Duration,
+    shared: Arc<Shared>,
+    flusher: Option<JoinHandle<()>>,
 
-    max_write_queries: usize,
-    write_batch: std::sync::Mutex<WriteBatch>,
+    prefetch_threads: usize,
+    prefetcher: Pool,
+    speculation: Speculation,
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        if let Some(flusher) = self.flusher.take() {
+            self.shared.is_stopping.store(true, Ordering::Release);
+            {
+                let _batch = self.shared.write_batch.lock().unwrap();
+                self.shared.filled.notify_all();
+            }
+
+            if flusher.join().is_err() {
+                error!("The KVS flusher thread panicked.");
+            }
+        }
+    }
 }
 
 impl ModuleEnvironment for Environment {
@@ -186,123 +502,536 @@ impl ModuleEnvironment for Environment {
                 .long("--max-write-kvs-queries")
                 .default_value("128")
                 .takes_value(true),
+            Arg::with_name("KVS_FLUSH_INTERVAL_MS")
+                .help(
+                    "The max interval in milliseconds to flush the writing kvs queries.
+The writing kvs queries are flushed in the caller thread if 0 is specified.",
+                )
+                .long("--kvs-flush-interval-ms")
+                .default_value(DEFAULT_FLUSH_INTERVAL_MS)
+                .takes_value(true),
+            Arg::with_name("KVS_PREFETCH_THREADS")
+                .help("The number of the threads to fetch the acids from the KVS in advance.")
+                .long("--kvs-prefetch-threads")
+                .default_value(DEFAULT_PREFETCH_THREADS)
+                .takes_value(true),
+            Arg::with_name("KVS_SPECULATIVE_PREFETCH_DEPTH")
+                .help(
+                    "How many generations of the parents are prefetched at idle time after an acid
+is fetched. (The speculative prefetch is disabled by default.)",
+                )
+                .long("--kvs-speculative-prefetch-depth")
+                .default_value(DEFAULT_SPECULATIVE_PREFETCH_DEPTH)
+                .takes_value(true),
+            Arg::with_name("KVS_SPECULATIVE_PREFETCH_BUDGET")
+                .help("The max number of the acids waiting for the speculative prefetch.")
+                .long("--kvs-speculative-prefetch-budget")
+                .default_value(DEFAULT_SPECULATIVE_PREFETCH_BUDGET)
+                .takes_value(true),
+            Arg::with_name("KVS_VERIFY_CHECKSUMS")
+                .help(
+                    "Stores the CRC-32 checksum with the KVS data, and verifies it on fetch.
+The KVS must be always used with or always without this flag.",
+                )
+                .long("--kvs-verify-checksums")
+                .takes_value(false),
+            Arg::with_name("KVS_DEDUP_EXTRINSIC_MIN_BYTES")
+                .help(
+                    "Stores the same extrinsic data only once if it is as large as this value.
+(Disabled by default.) The KVS must be always used with or always without this option.",
+                )
+                .long("--kvs-dedup-extrinsic-min-bytes")
+                .takes_value(true),
+            Arg::with_name("KVS_OPEN_ATTEMPTS")
+                .help("The max number of the attempts to open the KVS at the startup.")
+                .long("--kvs-open-attempts")
+                .default_value(DEFAULT_OPEN_ATTEMPTS)
+                .takes_value(true),
+            Arg::with_name("KVS_OPEN_RETRY_DELAY_MS")
+                .help(
+                    "The delay in milliseconds before the 2nd attempt to open the KVS.
+The delay is doubled for each following attempt.",
+                )
+                .long("--kvs-open-retry-delay-ms")
+                .default_value(DEFAULT_OPEN_RETRY_DELAY_MS)
+                .takes_value(true),
         ])
     }
 
-    unsafe fn check(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
         let db_path = config.args().value_of("PATH_TO_KVS_DB_DIR").unwrap();
         self.db_path = PathBuf::from(db_path);
 
         let max_write_queries = config.args().value_of("MAX_WRITE_KVS_QUERIES").unwrap();
-        self.max_write_queries = max_write_queries.parse().map_err(|e| {
-            Box::<dyn Error>::from(format!(
+        self.shared_mut().max_write_queries = max_write_queries.parse().map_err(|e| {
+            crate::Error::Config(format!(
                 "Failed to parse argument '--max-write-kvs-queries': {}",
                 e
             ))
         })?;
 
+        let flush_interval = config.args().value_of("KVS_FLUSH_INTERVAL_MS").unwrap();
+        let flush_interval = flush_interval.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-flush-interval-ms': {}",
+                e
+            ))
+        })?;
+        self.flush_interval = Duration::from_millis(flush_interval);
+
+        let prefetch_threads = config.args().value_of("KVS_PREFETCH_THREADS").unwrap();
+        self.prefetch_threads = prefetch_threads.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-prefetch-threads': {}",
+                e
+            ))
+        })?;
+
+        let depth = config
+            .args()
+            .value_of("KVS_SPECULATIVE_PREFETCH_DEPTH")
+            .unwrap();
+        let depth = depth.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-speculative-prefetch-depth': {}",
+                e
+            ))
+        })?;
+
+        let budget = config
+            .args()
+            .value_of("KVS_SPECULATIVE_PREFETCH_BUDGET")
+            .unwrap();
+        let budget = budget.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-speculative-prefetch-budget': {}",
+                e
+            ))
+        })?;
+        self.speculation = Speculation::new(depth, budget);
+
+        self.shared_mut().verify_checksums = config.args().is_present("KVS_VERIFY_CHECKSUMS");
+
+        if let Some(min_bytes) = config.args().value_of("KVS_DEDUP_EXTRINSIC_MIN_BYTES") {
+            let min_bytes = crate::byte_size::parse(min_bytes).map_err(|e| {
+                crate::Error::Config(format!(
+                    "Failed to parse argument '--kvs-dedup-extrinsic-min-bytes': {}",
+                    e
+                ))
+            })?;
+            self.shared_mut().dedup_min_bytes = Some(min_bytes);
+        }
+
+        let attempts = config.args().value_of("KVS_OPEN_ATTEMPTS").unwrap();
+        let attempts = attempts.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-open-attempts': {}",
+                e
+            ))
+        })?;
+
+        let delay = config.args().value_of("KVS_OPEN_RETRY_DELAY_MS").unwrap();
+        let delay = delay.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--kvs-open-retry-delay-ms': {}",
+                e
+            ))
+        })?;
+        self.open_backoff = Backoff::new(attempts, Duration::from_millis(delay));
+
         Ok(())
     }
 
-    unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
-        self.db.open(&self.db_path)?;
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        let db_path = self.db_path.clone();
+        let backoff = self.open_backoff;
+        let shared = self.shared_mut();
+        shared
+            .db
+            .open(&db_path, &backoff)
+            .map_err(crate::Error::kvs)?;
 
-        let mut write_batch = self.write_batch.lock().unwrap();
-        write_batch.init(self.max_write_queries);
+        let mut write_batch = shared.write_batch.lock().unwrap();
+        write_batch.init(shared.max_write_queries);
 
         Ok(())
     }
 }
 
+impl Environment {
+    /// Provides a reference to the path to the KVS database directory. ('--kvs-db-path')
+    pub fn db_path(&self) -> &Path {
+        &self.db_path
+    }
+
+    /// Returns `true` if '--kvs-verify-checksums' is specified.
+    pub fn verify_checksums(&self) -> bool {
+        self.shared.verify_checksums
+    }
+
+    /// Returns `true` if '--kvs-dedup-extrinsic-min-bytes' is specified.
+    pub fn is_dedup_enabled(&self) -> bool {
+        self.shared.dedup_min_bytes.is_some()
+    }
+
+    fn shared_mut(&mut self) -> &mut Shared {
+        Arc::get_mut(&mut self.shared).expect("The KVS flusher thread has already started.")
+    }
+
+    /// Starts the thread to flush the writing queries in the background.
+    ///
+    /// After this method is called, [`WriteQuery::wait`] blocks till the flusher thread flushes
+    /// the query; otherwise, the caller thread flushes the queries.
+    ///
+    /// This method does nothing if '--kvs-flush-interval-ms' is 0 or if the flusher thread has
+    /// already started.
+    ///
+    /// [`WriteQuery::wait`]: crate::kvs::WriteQuery::wait
+    pub fn start_flusher(&mut self, runtime_env: &runtime::Environment) -> io::Result<()> {
+        if self.flush_interval == Duration::from_millis(0) || self.flusher.is_some() {
+            return Ok(());
+        }
+
+        let shared = self.shared.clone();
+        let interval = self.flush_interval;
+        let flusher = runtime::spawn(WorkerGroup::Flush, runtime_env, move || {
+            flush_loop(&shared, interval)
+        })?;
+
+        self.flusher = Some(flusher);
+        Ok(())
+    }
+
+    /// Starts '--kvs-prefetch-threads' threads for function [`prefetch`] .
+    ///
+    /// If this method is not called, [`prefetch`] fetches the acids in the caller thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the prefetch threads have already started.
+    ///
+    /// [`prefetch`]: crate::kvs::prefetch
+    pub fn start_prefetchers(&mut self, runtime_env: &runtime::Environment) -> io::Result<()> {
+        assert_eq!(true, self.prefetcher.is_empty());
+        self.prefetcher = Pool::start(self.prefetch_threads, runtime_env)?;
+        Ok(())
+    }
+}
+
+/// Converts the error of LevelDB into [`Error`] .
+///
+/// [`Error`]: super::Error
+fn backend_error(e: mouse_leveldb::Error) -> Error {
+    Error::Backend(e.to_string())
+}
+
 enum FetchResult {
     NotYet,
     NotFound,
     Found(mouse_leveldb::Octets, mouse_leveldb::Octets),
-    Err(mouse_leveldb::Error),
+    /// The data fetched on a prefetch thread. (See [`Pending`] .)
+    Copied(Vec<u8>, Vec<u8>),
+    Err(Arc<Error>),
+}
+
+/// Fetches the data of `id` from the KVS.
+fn do_fetch(id: &[u8], shared: &Shared) -> FetchResult {
+    let corrupt = |column| {
+        let e = Error::from(CorruptRow::new(id, column));
+        FetchResult::Err(Arc::new(e))
+    };
+    let backend = |e| FetchResult::Err(Arc::new(backend_error(e)));
+
+    let intrinsic_db = &shared.db.intrinsic;
+    let intrinsic = match mouse_leveldb::get(intrinsic_db, id) {
+        Ok(octets) => octets,
+        Err(e) => return backend(e),
+    };
+
+    if intrinsic.as_ref().is_empty() {
+        return FetchResult::NotFound;
+    }
+
+    let extrinsic_db = &shared.db.extrinsic;
+    let extrinsic = match mouse_leveldb::get(extrinsic_db, id) {
+        Ok(octets) => octets,
+        Err(e) => return backend(e),
+    };
+
+    if shared.verify_checksums {
+        if checksum::strip(intrinsic.as_ref()).is_none() {
+            return corrupt("intrinsic");
+        }
+
+        let extrinsic: &[u8] = extrinsic.as_ref();
+        if !extrinsic.is_empty() && checksum::strip(extrinsic).is_none() {
+            return corrupt("extrinsic");
+        }
+    }
+
+    if shared.dedup_min_bytes.is_none() || extrinsic.as_ref().is_empty() {
+        return FetchResult::Found(intrinsic, extrinsic);
+    }
+
+    // Follow the pointer to the shared extrinsic data if necessary.
+    let pointer = match shared.strip_checksum(extrinsic.as_ref()).split_first() {
+        Some((&EXTRINSIC_INLINE, _)) => return FetchResult::Found(intrinsic, extrinsic),
+        Some((&EXTRINSIC_POINTER, hash)) => blob_key(hash),
+        _ => return corrupt("extrinsic"),
+    };
+
+    let blob = match mouse_leveldb::get(extrinsic_db, &pointer) {
+        Ok(octets) => octets,
+        Err(e) => return backend(e),
+    };
+
+    let blob_bytes: &[u8] = blob.as_ref();
+    if blob_bytes.is_empty()
+        || (shared.verify_checksums && checksum::strip(blob_bytes).is_none())
+        || shared.strip_checksum(blob_bytes).first() != Some(&EXTRINSIC_INLINE)
+    {
+        return corrupt("extrinsic");
+    }
+
+    FetchResult::Found(intrinsic, blob)
+}
+
+/// The result of [`Pending`] ; the intrinsic and the extrinsic data if found.
+type PendingResult = Result<Option<(Vec<u8>, Vec<u8>)>, Arc<Error>>;
+
+/// `Pending` is the fetch running on a prefetch thread for `ReadQuery::wait_timeout` .
+#[derive(Default)]
+struct Pending {
+    result: std::sync::Mutex<Option<PendingResult>>,
+    finished: Condvar,
+}
+
+/// Fetches the data of `id` from the KVS, and copies it to send it to another thread.
+fn fetch_owned(id: &[u8], shared: &Shared) -> PendingResult {
+    match do_fetch(id, shared) {
+        FetchResult::NotFound => Ok(None),
+        FetchResult::Found(intrinsic, extrinsic) => Ok(Some((
+            intrinsic.as_ref().to_vec(),
+            extrinsic.as_ref().to_vec(),
+        ))),
+        FetchResult::Err(e) => Err(e),
+        _ => panic!("Program never comes here."),
+    }
+}
+
+/// Returns the error that a prefetch thread panicked.
+fn panicked() -> Arc<Error> {
+    let msg = String::from("The KVS prefetch thread panicked.");
+    Arc::new(Error::Backend(msg))
+}
+
+impl From<PendingResult> for FetchResult {
+    fn from(result: PendingResult) -> Self {
+        match result {
+            Ok(None) => FetchResult::NotFound,
+            Ok(Some((intrinsic, extrinsic))) => FetchResult::Copied(intrinsic, extrinsic),
+            Err(e) => FetchResult::Err(e),
+        }
+    }
 }
 
-struct FetchQuery<'a> {
+impl Pending {
+    /// Starts to fetch `id` on a prefetch thread of `env` .
+    fn start(id: &[u8], env: &Environment) -> Arc<Self> {
+        let ret = Arc::new(Self::default());
+
+        let pending = ret.clone();
+        let id = id.to_vec();
+        let shared = env.shared.clone();
+        env.prefetcher.spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| fetch_owned(&id, &shared)))
+                .unwrap_or_else(|_| Err(panicked()));
+
+            *pending.result.lock().unwrap() = Some(result);
+            pending.finished.notify_all();
+        });
+
+        ret
+    }
+
+    fn is_finished(&self) -> bool {
+        self.result.lock().unwrap().is_some()
+    }
+
+    fn error(&self) -> Option<Arc<Error>> {
+        match &*self.result.lock().unwrap() {
+            Some(Err(e)) => Some(e.clone()),
+            _ => None,
+        }
+    }
+
+    /// Blocks till the fetch finishes and takes the result, or returns `None` if `timeout`
+    /// passes before that. (`None` timeout means no limit.)
+    fn take(&self, timeout: Option<Duration>) -> Option<PendingResult> {
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut result = self.result.lock().unwrap();
+
+        loop {
+            if let Some(r) = result.take() {
+                return Some(r);
+            }
+
+            result = match deadline {
+                None => self.finished.wait(result).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return None;
+                    }
+                    self.finished
+                        .wait_timeout(result, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+    }
+}
+
+struct FetchQuery<'a, H> {
     env: &'a Environment,
-    id: Id,
+    id: H,
     result: FetchResult,
+    pending: Option<Arc<Pending>>,
 }
 
-impl<'a> FetchQuery<'a> {
-    pub fn new(id: &Id, env: &'a Environment) -> Self {
+impl<'a, H> FetchQuery<'a, H>
+where
+    H: CryptoHash,
+{
+    pub fn new(id: &H, env: &'a Environment) -> Self {
+        let result = match check_id(id.as_ref()) {
+            Ok(()) => FetchResult::NotYet,
+            Err(e) => FetchResult::Err(Arc::new(e)),
+        };
+
         Self {
             id: *id,
             env,
-            result: FetchResult::NotYet,
+            result,
+            pending: None,
         }
     }
 
-    fn do_fetch(&self) -> FetchResult {
-        let intrinsic_db = &self.env.db.intrinsic;
-        let intrinsic = match mouse_leveldb::get(intrinsic_db, self.id.as_ref()) {
-            Ok(octets) => octets,
-            Err(e) => return FetchResult::Err(e),
-        };
+    /// Fetches the data unless finished, and returns `true` , or returns `false` if `timeout`
+    /// passes before the fetch finishes.
+    ///
+    /// The fetch runs on a prefetch thread only if `timeout` is specified and if the prefetch
+    /// threads are started; otherwise, it runs in the caller thread ignoring `timeout` .
+    fn finish(&mut self, timeout: Option<Duration>) -> bool {
+        match self.result {
+            FetchResult::NotYet => (),
+            _ => return true,
+        }
 
-        if intrinsic.as_ref().is_empty() {
-            return FetchResult::NotFound;
+        if self.pending.is_none() {
+            if timeout.is_none() || self.env.prefetcher.is_empty() {
+                self.result = do_fetch(self.id.as_ref(), &self.env.shared);
+                return true;
+            }
+            self.pending = Some(Pending::start(self.id.as_ref(), self.env));
         }
 
-        let extrinsic_db = &self.env.db.extrinsic;
-        let extrinsic = match mouse_leveldb::get(extrinsic_db, self.id.as_ref()) {
-            Ok(octets) => octets,
-            Err(e) => return FetchResult::Err(e),
+        let result = match self.pending.as_ref().unwrap().take(timeout) {
+            None => return false,
+            Some(r) => r,
+        };
+
+        self.pending = None;
+        self.result = FetchResult::from(result);
+        true
+    }
+
+    /// Returns the finished result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the fetch has not finished yet.
+    fn row(&self) -> Result<Option<Row>, Arc<Error>> {
+        let (intrinsic, extrinsic): (&[u8], &[u8]) = match &self.result {
+            FetchResult::NotYet => panic!("Program never comes here."),
+            FetchResult::NotFound => return Ok(None),
+            FetchResult::Found(intrinsic, extrinsic) => (intrinsic.as_ref(), extrinsic.as_ref()),
+            FetchResult::Copied(intrinsic, extrinsic) => (intrinsic, extrinsic),
+            FetchResult::Err(e) => return Err(e.clone()),
         };
 
-        FetchResult::Found(intrinsic, extrinsic)
+        // The checksums and the tag have already been verified.
+        let shared = &self.env.shared;
+        let intrinsic = shared.strip_checksum(intrinsic);
+        let mut extrinsic = shared.strip_checksum(extrinsic);
+        if shared.dedup_min_bytes.is_some() && !extrinsic.is_empty() {
+            extrinsic = &extrinsic[1..];
+        }
+
+        let row = Row {
+            intrinsic: Cow::Borrowed(intrinsic),
+            extrinsic: Cow::Borrowed(extrinsic),
+        };
+        Ok(Some(row))
     }
 }
 
-impl ReadQuery for FetchQuery<'_> {
+impl<H> ReadQuery for FetchQuery<'_, H>
+where
+    H: CryptoHash,
+{
     fn is_finished(&self) -> bool {
         match self.result {
-            FetchResult::NotYet => false,
+            FetchResult::NotYet => self.pending.as_ref().map_or(false, |p| p.is_finished()),
             _ => true,
         }
     }
 
-    fn wait(&mut self) -> Result<Option<Row>, &dyn Error> {
-        if !self.is_finished() {
-            self.result = self.do_fetch();
-        }
+    fn wait(&mut self) -> Result<Option<Row>, Arc<Error>> {
+        self.finish(None);
+        self.row()
+    }
 
-        match &self.result {
-            FetchResult::NotYet => panic!("Program never comes here."),
-            FetchResult::NotFound => Ok(None),
-            FetchResult::Found(intrinsic, extrinsic) => {
-                let intrinsic: &[u8] = intrinsic.as_ref();
-                let extrinsic: &[u8] = extrinsic.as_ref();
-                let row = Row {
-                    intrinsic: Cow::Borrowed(intrinsic),
-                    extrinsic: Cow::Borrowed(extrinsic),
-                };
-                Ok(Some(row))
-            }
-            FetchResult::Err(e) => Err(e),
+    fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<Option<Row>>, Arc<Error>> {
+        if self.finish(Some(timeout)) {
+            self.row().map(Some)
+        } else {
+            Ok(None)
         }
     }
 
-    fn error(&self) -> Option<&dyn Error> {
+    fn error(&self) -> Option<Arc<Error>> {
         match &self.result {
-            FetchResult::Err(e) => Some(e),
+            FetchResult::Err(e) => Some(e.clone()),
+            FetchResult::NotYet => self.pending.as_ref().and_then(|p| p.error()),
             _ => None,
         }
     }
 }
 
 /// Returns a new `ReadQuery`
-pub fn fetch<'a>(id: &Id, env: &'a Environment) -> impl ReadQuery + 'a {
+pub fn fetch<'a, H>(id: &H, env: &'a Environment) -> impl ReadQuery + 'a
+where
+    H: 'a + CryptoHash,
+{
     FetchQuery::new(id, env)
 }
 
 enum PutResult {
     NotYet,
     Succeeded,
-    Error(Asc<mouse_leveldb::Error>),
+    Error(Arc<Error>),
+}
+
+/// Returns `value` followed by the checksum if `enabled` is `true` and `value` is not empty, or
+/// `value` itself.
+fn with_checksum(value: &[u8], enabled: bool) -> Cow<[u8]> {
+    if enabled && !value.is_empty() {
+        Cow::Owned(checksum::append(value))
+    } else {
+        Cow::Borrowed(value)
+    }
 }
 
 struct PutQuery<'a> {
@@ -311,16 +1040,92 @@ struct PutQuery<'a> {
 }
 
 impl<'a> PutQuery<'a> {
-    pub fn new(id: &Id, intrinsic: &[u8], extrinsic: &[u8], env: &'a Environment) -> Self {
-        let mut batch = env.write_batch.lock().unwrap();
-        let result = batch.put(id, intrinsic, extrinsic);
+    pub fn new(id: &[u8], intrinsic: &[u8], extrinsic: &[u8], env: &'a Environment) -> Self {
+        if let Err(e) = check_id(id) {
+            let result = Asc::from(Mutex::new(PutResult::Error(Arc::new(e))));
+            return Self { env, result };
+        }
+
+        let shared = &env.shared;
+        let intrinsic = with_checksum(intrinsic, shared.verify_checksums);
+        let (extrinsic, blob) = shared.encode_extrinsic(extrinsic);
 
-        if batch.len() == env.max_write_queries {
-            batch.flush(&env.db);
+        let mut batch = shared.write_batch.lock().unwrap();
+        if shared.dedup_min_bytes.is_some() && !extrinsic.is_empty() {
+            if let Err(e) = shared.move_reference(id, &extrinsic, &mut batch) {
+                let result = Asc::from(Mutex::new(PutResult::Error(Arc::new(e))));
+                return Self { env, result };
+            }
+        }
+        if let Some((key, value)) = blob {
+            batch.put_extrinsic(&key, &value);
+        }
+        let result = batch.put(id, &intrinsic, &extrinsic);
+
+        if shared.max_write_queries <= batch.len() {
+            if env.flusher.is_some() {
+                shared.filled.notify_one();
+            } else {
+                batch.flush(&shared.db);
+            }
         }
 
         Self { env, result }
     }
+
+    /// Blocks till the query finishes and returns `true` , or returns `false` if `timeout`
+    /// passes before that. (`None` timeout means no limit.)
+    ///
+    /// If the flusher thread is not started, this method flushes the batch in the caller thread
+    /// ignoring `timeout` .
+    fn finish(&self, timeout: Option<Duration>) -> bool {
+        if self.is_finished() {
+            return true;
+        }
+
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let shared = &self.env.shared;
+        let mut batch = shared.write_batch.lock().unwrap();
+        if self.env.flusher.is_none() {
+            if !self.is_finished() {
+                batch.flush(&shared.db);
+            }
+            return true;
+        }
+
+        // The result is set while the flusher thread holds the lock.
+        while !self.is_finished() {
+            batch = match deadline {
+                None => shared.flushed.wait(batch).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if deadline <= now {
+                        return false;
+                    }
+                    shared
+                        .flushed
+                        .wait_timeout(batch, deadline - now)
+                        .unwrap()
+                        .0
+                }
+            };
+        }
+
+        true
+    }
+
+    /// Returns the finished result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the query has not finished yet.
+    fn outcome(&self) -> Result<(), Arc<Error>> {
+        match &*self.result.lock().unwrap() {
+            PutResult::NotYet => panic!("Never comes here."),
+            PutResult::Succeeded => Ok(()),
+            PutResult::Error(e) => Err(e.clone()),
+        }
+    }
 }
 
 impl WriteQuery for PutQuery<'_> {
@@ -331,33 +1136,54 @@ impl WriteQuery for PutQuery<'_> {
         }
     }
 
-    fn wait(&mut self) -> Result<(), &dyn Error> {
-        if !self.is_finished() {
-            let mut batch = self.env.write_batch.lock().unwrap();
-            if !self.is_finished() {
-                batch.flush(&self.env.db);
-            }
-        }
+    fn wait(&mut self) -> Result<(), Arc<Error>> {
+        self.finish(None);
+        self.outcome()
+    }
 
-        match &*self.result.lock().unwrap() {
-            PutResult::NotYet => panic!("Never comes here."),
-            PutResult::Succeeded => Ok(()),
-            PutResult::Error(e) => unsafe { Err(&*Asc::as_ptr(e)) },
+    fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<()>, Arc<Error>> {
+        if self.finish(Some(timeout)) {
+            self.outcome().map(Some)
+        } else {
+            Ok(None)
         }
     }
 
-    fn error(&self) -> Option<&dyn Error> {
+    fn error(&self) -> Option<Arc<Error>> {
         match &*self.result.lock().unwrap() {
-            PutResult::Error(e) => unsafe { Some(&*Asc::as_ptr(e)) },
+            PutResult::Error(e) => Some(e.clone()),
             _ => None,
         }
     }
 }
 
+/// Returns the number of the writing queries waiting for being flushed.
+pub fn pending_writes(env: &Environment) -> usize {
+    env.shared.write_batch.lock().unwrap().len()
+}
+
+/// Returns a new `WriteQuery` to put `intrinsic` and `extrinsic` as the data of `id` .
+///
+/// Empty `intrinsic` or `extrinsic` is not put, i.e. the current data is left as it is.
+pub fn put<'a, H>(
+    id: &H,
+    intrinsic: &[u8],
+    extrinsic: &[u8],
+    env: &'a Environment,
+) -> impl WriteQuery + 'a
+where
+    H: CryptoHash,
+{
+    PutQuery::new(id.as_ref(), intrinsic, extrinsic, env)
+}
+
 /// Returns a new `WriteQuery` to put both the intrinsic data and extrinsic data of `acid` .
-pub fn insert<'a>(acid: &dyn Acid, env: &'a Environment) -> impl WriteQuery + 'a {
+pub fn insert<'a, H>(acid: &dyn Acid<H>, env: &'a Environment) -> impl WriteQuery + 'a
+where
+    H: CryptoHash,
+{
     PutQuery::new(
-        acid.id(),
+        acid.id().as_ref(),
         acid.intrinsic().as_ref(),
         acid.extrinsic().as_ref(),
         env,
@@ -369,6 +1195,449 @@ pub fn insert<'a>(acid: &dyn Acid, env: &'a Environment) -> impl WriteQuery + 'a
 /// Note that the acid cannot be fetched before the intrinsic data is stored, too.
 /// This method is called only when the user is sure that the intrinsic data is already stored
 /// to the KVS, and when the user want to update the extrinsic data.
-pub fn update<'a>(acid: &dyn Acid, env: &'a Environment) -> impl WriteQuery + 'a {
-    PutQuery::new(acid.id(), &[], acid.extrinsic().as_ref(), env)
+pub fn update<'a, H>(acid: &dyn Acid<H>, env: &'a Environment) -> impl WriteQuery + 'a
+where
+    H: CryptoHash,
+{
+    PutQuery::new(acid.id().as_ref(), &[], acid.extrinsic().as_ref(), env)
+}
+
+/// `StoredFormat` is the format of the data of an acid stored in the KVS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredFormat {
+    /// Whether the data has the checksum trailer. (See '--kvs-verify-checksums'.)
+    pub checksum: bool,
+    /// Whether the extrinsic data is tagged for the shared extrinsic data, or `None` if the
+    /// extrinsic data is empty. (See '--kvs-dedup-extrinsic-min-bytes'.)
+    pub dedup: Option<bool>,
+}
+
+/// Reads the data of `id` as it is stored, and returns the format, or returns `None` if the KVS
+/// does not have the data of `id` .
+///
+/// The KVS does not record the format, so it is told from the data itself. The data is regarded
+/// to have the checksum trailer if the trailer of the intrinsic data matches. The extrinsic data
+/// is regarded as tagged if it starts with the tag of the inline data, or if it is as long as the
+/// pointer and starts with the tag of the pointer. The data stored in the other format can be
+/// misjudged by chance.
+pub fn stored_format(id: &Id, env: &Environment) -> Result<Option<StoredFormat>, Error> {
+    let db = &env.shared.db;
+
+    let intrinsic = mouse_leveldb::get(&db.intrinsic, id.as_ref()).map_err(backend_error)?;
+    let intrinsic: &[u8] = intrinsic.as_ref();
+    if intrinsic.is_empty() {
+        return Ok(None);
+    }
+    let checksum = checksum::strip(intrinsic).is_some();
+
+    let extrinsic = mouse_leveldb::get(&db.extrinsic, id.as_ref()).map_err(backend_error)?;
+    let extrinsic: &[u8] = extrinsic.as_ref();
+    let extrinsic = match checksum::strip(extrinsic) {
+        Some(value) if checksum => value,
+        _ => extrinsic,
+    };
+    let dedup = match extrinsic.split_first() {
+        None => None,
+        Some((&EXTRINSIC_INLINE, _)) => Some(true),
+        Some((&EXTRINSIC_POINTER, hash)) => Some(hash.len() == Id::LEN),
+        Some(_) => Some(false),
+    };
+
+    Ok(Some(StoredFormat { checksum, dedup }))
+}
+
+/// Releases the references of `ids` to the shared extrinsic data, and returns the keys that
+/// nothing refers to any more; i.e. the keys of the shared extrinsic data and of their reference
+/// counts. (See '--kvs-dedup-extrinsic-min-bytes'.)
+///
+/// The extrinsic data of `ids` is replaced with empty data and flushed before this function
+/// returns, so releasing the same ids again releases nothing. The KVS does not provide the API to
+/// delete yet; the application deletes `ids` and the returned keys from the extrinsic KVS.
+///
+/// The returned keys must be deleted before the same extrinsic data is put again, or the new
+/// acid refers to the deleted data. This function returns nothing if
+/// '--kvs-dedup-extrinsic-min-bytes' is not specified.
+pub fn release_extrinsic(ids: &[Id], env: &Environment) -> Result<Vec<Vec<u8>>, Error> {
+    let shared = &env.shared;
+    if shared.dedup_min_bytes.is_none() {
+        return Ok(Vec::new());
+    }
+
+    let released = tagged(EXTRINSIC_INLINE, &[]);
+    let released = with_checksum(&released, shared.verify_checksums).into_owned();
+
+    let mut ret = Vec::new();
+    let mut batch = shared.write_batch.lock().unwrap();
+    for id in ids {
+        let stored = shared.read_extrinsic(id.as_ref(), &batch)?;
+        let hash = match stored.and_then(|stored| shared.pointer(&stored)) {
+            None => continue,
+            Some(hash) => hash,
+        };
+
+        let count = shared.references(&hash, &batch)?.saturating_sub(1);
+        batch.put_tracked(id.as_ref(), &released);
+        shared.set_references(&hash, count, &mut batch);
+        if count == 0 {
+            ret.push(blob_key(&hash));
+            ret.push(count_key(&hash));
+        }
+    }
+
+    // Flush in the caller thread not to return the keys before the counts are stored.
+    let result = batch.result();
+    batch.flush(&shared.db);
+    shared.flushed.notify_all();
+    drop(batch);
+
+    let result = result.lock().unwrap();
+    match &*result {
+        PutResult::Error(e) => Err(Error::clone(e)),
+        _ => Ok(ret),
+    }
+}
+
+/// Fetches the acids with `ids` from the KVS in parallel, and caches them; i.e. [`cache::find`]
+/// hits after this function returns.
+///
+/// This function skips the ids that are already cached (including the cache of 'Not Found'.)
+/// If the acid is not stored in the KVS, this function calls [`cache::not_found`] instead.
+///
+/// The acids are fetched on '--kvs-prefetch-threads' threads. (See also method
+/// [`Environment::start_prefetchers`] .) This function blocks till all of them finish, and
+/// returns the number of the acids newly cached. A failure to fetch is logged and skipped.
+///
+/// [`cache::find`]: crate::cache::find
+/// [`cache::not_found`]: crate::cache::not_found
+/// [`Environment::start_prefetchers`]: self::Environment::start_prefetchers
+pub fn prefetch(
+    ids: &[Id],
+    env: &Environment,
+    data_types_env: &data_types::Environment,
+    cache_env: &cache::Environment,
+) -> usize {
+    let ids: Vec<(Id, u32)> = ids.iter().map(|id| (*id, 0)).collect();
+    prefetch_with_depth(&ids, env, data_types_env, cache_env)
+}
+
+/// Queues the parents of `acid` for function [`prefetch_speculative`] if
+/// '--kvs-speculative-prefetch-depth' is greater than 0, and returns the number of the queued
+/// ids.
+///
+/// [`prefetch_speculative`]: self::prefetch_speculative
+pub fn schedule_parents(acid: &dyn Acid, env: &Environment) -> usize {
+    env.speculation.schedule(acid, 1)
+}
+
+/// Takes at most `limit` ids queued by [`schedule_parents`] , and prefetches them as
+/// [`prefetch`] does. The parents of the fetched acids are queued in turn, till the depth reaches
+/// '--kvs-speculative-prefetch-depth'.
+///
+/// The parents of an acid are likely to be requested after the acid is, so the application calls
+/// this function at idle time to reduce the latency of the following lookups.
+///
+/// Returns the number of the acids newly cached.
+///
+/// [`schedule_parents`]: self::schedule_parents
+/// [`prefetch`]: self::prefetch
+pub fn prefetch_speculative(
+    limit: usize,
+    env: &Environment,
+    data_types_env: &data_types::Environment,
+    cache_env: &cache::Environment,
+) -> usize {
+    let ids = env.speculation.take(limit);
+    prefetch_with_depth(&ids, env, data_types_env, cache_env)
+}
+
+/// Prefetches each id of `ids` , and queues the parents with the depth + 1.
+///
+/// Only the KVS is read on the prefetch threads; the acids are deserialized and cached in the
+/// caller thread.
+fn prefetch_with_depth(
+    ids: &[(Id, u32)],
+    env: &Environment,
+    data_types_env: &data_types::Environment,
+    cache_env: &cache::Environment,
+) -> usize {
+    let ids: Vec<&(Id, u32)> = ids
+        .iter()
+        .filter(|(id, _)| match cache::is_cached(id, cache_env) {
+            cache::CacheState::Lost => true,
+            _ => false,
+        })
+        .collect();
+
+    let jobs = ids
+        .iter()
+        .map(|(id, _)| {
+            let id = id.as_ref().to_vec();
+            let shared = env.shared.clone();
+            Box::new(move || fetch_owned(&id, &shared)) as Box<dyn FnOnce() -> PendingResult + Send>
+        })
+        .collect();
+    let results = env.prefetcher.run_all(jobs);
+
+    let mut fetched = 0;
+    for ((id, depth), result) in ids.into_iter().zip(results) {
+        let mut query = FetchQuery::new(id, env);
+        query.result = FetchResult::from(result.unwrap_or_else(|| Err(panicked())));
+
+        let intrinsic = match query.row() {
+            Ok(Some(row)) => row.intrinsic,
+            Ok(None) => {
+                cache::not_found(*id, cache_env);
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to prefetch acid {}: {}", id.display_hex(), e);
+                continue;
+            }
+        };
+
+        let acid = match data_types::deserialize_acid(intrinsic.as_ref(), data_types_env) {
+            Ok(acid) => acid,
+            Err(e) => {
+                warn!("Failed to prefetch acid {}: {}", id.display_hex(), e);
+                continue;
+            }
+        };
+
+        env.speculation.schedule(&*acid, depth + 1);
+        match cache::insert(acid, cache_env) {
+            Ok(_) => fetched += 1,
+            Err(e) => warn!(
+                "Failed to cache prefetched acid {}: {}",
+                id.display_hex(),
+                e
+            ),
+        }
+    }
+
+    fetched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTRINSIC: &'static [u8] = b"intrinsic";
+    const SHORT: &'static [u8] = b"short";
+    const LONG: &'static [u8] = b"long extrinsic";
+
+    /// Opens a new KVS in a temporary directory. The extrinsic data as long as 8 bytes is shared.
+    fn open(name: &str, verify_checksums: bool) -> Environment {
+        let path = format!(
+            "mouse-kvs-{}-{}-{}",
+            name,
+            verify_checksums,
+            std::process::id()
+        );
+        let path = std::env::temp_dir().join(path);
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        let mut env = Environment::default();
+        env.db_path = path;
+        {
+            let shared = env.shared_mut();
+            shared.max_write_queries = 16;
+            shared.verify_checksums = verify_checksums;
+            shared.dedup_min_bytes = Some(8);
+        }
+        unsafe { env.init().unwrap() };
+
+        env
+    }
+
+    fn id(seed: u8) -> Id {
+        Id::calculate(&[seed])
+    }
+
+    fn fetched(id: &Id, env: &Environment) -> Result<Option<(Vec<u8>, Vec<u8>)>, Arc<Error>> {
+        let mut query = fetch(id, env);
+        let ret = query
+            .wait()
+            .map(|row| row.map(|row| (row.intrinsic.to_vec(), row.extrinsic.to_vec())));
+        ret
+    }
+
+    fn stored(key: &[u8], env: &Environment) -> Vec<u8> {
+        let value = mouse_leveldb::get(&env.shared.db.extrinsic, key).unwrap();
+        value.as_ref().to_vec()
+    }
+
+    fn references(hash: &[u8], env: &Environment) -> u64 {
+        let batch = env.shared.write_batch.lock().unwrap();
+        env.shared.references(hash, &batch).unwrap()
+    }
+
+    /// Puts the raw values into the KVS bypassing the encoding.
+    fn put_raw(id: &[u8], intrinsic: &[u8], extrinsic: &[u8], env: &Environment) {
+        let shared = &env.shared;
+        let mut batch = shared.write_batch.lock().unwrap();
+        if intrinsic.is_empty() {
+            batch.put_extrinsic(id, extrinsic);
+        } else {
+            batch.put(id, intrinsic, extrinsic);
+        }
+        batch.flush(&shared.db);
+    }
+
+    #[test]
+    fn inline_and_pointer() {
+        for &verify_checksums in &[false, true] {
+            let env = open("inline", verify_checksums);
+
+            put(&id(1), INTRINSIC, SHORT, &env).wait().unwrap();
+            put(&id(2), INTRINSIC, LONG, &env).wait().unwrap();
+
+            let expected = Some((INTRINSIC.to_vec(), SHORT.to_vec()));
+            assert_eq!(expected, fetched(&id(1), &env).unwrap());
+            let expected = Some((INTRINSIC.to_vec(), LONG.to_vec()));
+            assert_eq!(expected, fetched(&id(2), &env).unwrap());
+            assert_eq!(None, fetched(&id(3), &env).unwrap());
+
+            // Only the long extrinsic data is shared.
+            let short = Id::calculate(SHORT);
+            assert_eq!(true, stored(&blob_key(short.as_ref()), &env).is_empty());
+            let long = Id::calculate(LONG);
+            assert_eq!(false, stored(&blob_key(long.as_ref()), &env).is_empty());
+            assert_eq!(1, references(long.as_ref(), &env));
+
+            // Update the extrinsic data from the pointer to the inline data.
+            put(&id(2), &[], SHORT, &env).wait().unwrap();
+            let expected = Some((INTRINSIC.to_vec(), SHORT.to_vec()));
+            assert_eq!(expected, fetched(&id(2), &env).unwrap());
+            assert_eq!(0, references(long.as_ref(), &env));
+        }
+    }
+
+    #[test]
+    fn broken_pointer() {
+        for &verify_checksums in &[false, true] {
+            let env = open("broken", verify_checksums);
+            let hash = Id::calculate(LONG);
+            let intrinsic = with_checksum(INTRINSIC, verify_checksums).into_owned();
+
+            // The shared extrinsic data is missing.
+            let pointer = tagged(EXTRINSIC_POINTER, hash.as_ref());
+            let pointer = with_checksum(&pointer, verify_checksums).into_owned();
+            put_raw(id(1).as_ref(), &intrinsic, &pointer, &env);
+            assert_eq!(true, fetched(&id(1), &env).is_err());
+
+            // The shared extrinsic data is not tagged as inline.
+            let blob = tagged(EXTRINSIC_POINTER, LONG);
+            let blob = with_checksum(&blob, verify_checksums).into_owned();
+            put_raw(&blob_key(hash.as_ref()), &[], &blob, &env);
+            assert_eq!(true, fetched(&id(1), &env).is_err());
+
+            // The tag of the extrinsic data is unknown.
+            let unknown = tagged(2, hash.as_ref());
+            let unknown = with_checksum(&unknown, verify_checksums).into_owned();
+            put_raw(id(2).as_ref(), &intrinsic, &unknown, &env);
+            assert_eq!(true, fetched(&id(2), &env).is_err());
+
+            // The checksum of the shared extrinsic data is wrong.
+            if verify_checksums {
+                let mut blob = checksum::append(&tagged(EXTRINSIC_INLINE, LONG));
+                blob[1] ^= 0xff;
+                put_raw(&blob_key(hash.as_ref()), &[], &blob, &env);
+                assert_eq!(true, fetched(&id(1), &env).is_err());
+            }
+
+            // Repair the shared extrinsic data.
+            let blob = tagged(EXTRINSIC_INLINE, LONG);
+            let blob = with_checksum(&blob, verify_checksums).into_owned();
+            put_raw(&blob_key(hash.as_ref()), &[], &blob, &env);
+            let expected = Some((INTRINSIC.to_vec(), LONG.to_vec()));
+            assert_eq!(expected, fetched(&id(1), &env).unwrap());
+        }
+    }
+
+    #[test]
+    fn release_extrinsic_() {
+        for &verify_checksums in &[false, true] {
+            let env = open("release", verify_checksums);
+            let hash = Id::calculate(LONG);
+
+            put(&id(1), INTRINSIC, LONG, &env).wait().unwrap();
+            put(&id(2), INTRINSIC, LONG, &env).wait().unwrap();
+            // Putting the same acid again does not change the count.
+            put(&id(1), INTRINSIC, LONG, &env).wait().unwrap();
+            assert_eq!(2, references(hash.as_ref(), &env));
+
+            assert_eq!(true, release_extrinsic(&[id(1)], &env).unwrap().is_empty());
+            assert_eq!(1, references(hash.as_ref(), &env));
+
+            // Releasing twice does nothing.
+            assert_eq!(true, release_extrinsic(&[id(1)], &env).unwrap().is_empty());
+            assert_eq!(1, references(hash.as_ref(), &env));
+
+            let expected = vec![blob_key(hash.as_ref()), count_key(hash.as_ref())];
+            assert_eq!(expected, release_extrinsic(&[id(2)], &env).unwrap());
+            assert_eq!(0, references(hash.as_ref(), &env));
+
+            // The released acid has no extrinsic data.
+            let expected = Some((INTRINSIC.to_vec(), Vec::new()));
+            assert_eq!(expected, fetched(&id(2), &env).unwrap());
+
+            // Neither inline data nor unknown ids are released.
+            put(&id(3), INTRINSIC, SHORT, &env).wait().unwrap();
+            let ids = [id(3), id(4)];
+            assert_eq!(true, release_extrinsic(&ids, &env).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn stored_format_() {
+        for &verify_checksums in &[false, true] {
+            let env = open("format", verify_checksums);
+
+            put(&id(1), INTRINSIC, SHORT, &env).wait().unwrap();
+            put(&id(2), INTRINSIC, LONG, &env).wait().unwrap();
+            put(&id(3), INTRINSIC, &[], &env).wait().unwrap();
+            put_raw(id(4).as_ref(), INTRINSIC, SHORT, &env);
+
+            let format = |checksum, dedup| Some(StoredFormat { checksum, dedup });
+            let expected = format(verify_checksums, Some(true));
+            assert_eq!(expected, stored_format(&id(1), &env).unwrap());
+            assert_eq!(expected, stored_format(&id(2), &env).unwrap());
+            let expected = format(verify_checksums, None);
+            assert_eq!(expected, stored_format(&id(3), &env).unwrap());
+
+            // Stored without the checksum trailer nor the tag.
+            let expected = format(false, Some(false));
+            assert_eq!(expected, stored_format(&id(4), &env).unwrap());
+            assert_eq!(None, stored_format(&id(5), &env).unwrap());
+        }
+    }
+
+    #[test]
+    fn too_long_id() {
+        let env = open("too-long", false);
+        let hash = Id::calculate(LONG);
+        put(&id(1), INTRINSIC, LONG, &env).wait().unwrap();
+
+        // The id as long as the key of the shared extrinsic data is rejected.
+        let key = blob_key(hash.as_ref());
+        let e = PutQuery::new(&key, INTRINSIC, SHORT, &env)
+            .wait()
+            .unwrap_err();
+        assert_eq!(Error::IdTooLong(SHARED_KEY_LEN), *e);
+        assert_eq!(
+            Error::IdTooLong(SHARED_KEY_LEN),
+            check_id(&key).unwrap_err()
+        );
+        assert_eq!(
+            Error::IdTooLong(Id::LEN + 2),
+            check_id(&vec![0; Id::LEN + 2]).unwrap_err()
+        );
+        assert_eq!(true, check_id(id(1).as_ref()).is_ok());
+
+        // The shared extrinsic data is left as it is.
+        let expected = Some((INTRINSIC.to_vec(), LONG.to_vec()));
+        assert_eq!(expected, fetched(&id(1), &env).unwrap());
+        assert_eq!(1, references(hash.as_ref(), &env));
+    }
 }
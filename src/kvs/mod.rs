@@ -16,11 +16,27 @@
 
 //! 'kvs' module
 
+pub(crate) mod checksum;
+pub(crate) mod dump;
+mod format;
 mod leveldb;
+mod prefetch;
+mod speculative;
 
-pub use leveldb::{fetch, insert, update, Environment};
+use crate::data_types::crypto_hash::HexDisplay;
+use crate::data_types::{self, CAcid, Id};
+use crate::fault;
+pub use dump::{export, import};
+pub use format::{format_state, FormatState};
+pub use leveldb::{
+    fetch, insert, pending_writes, prefetch, prefetch_speculative, put, release_extrinsic,
+    schedule_parents, update, Environment,
+};
 use std::borrow::Cow;
-use std::error::Error;
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Trait for query to the KVS to insert or to update.
 ///
@@ -33,13 +49,25 @@ pub trait WriteQuery {
 
     /// Starts query if not yet, and blocks till the query finished.
     /// If the query has already finished, returns immediately.
-    fn wait(&mut self) -> Result<(), &dyn Error>;
+    ///
+    /// The error is shared by all the queries which failed together, and it can be sent to
+    /// another thread.
+    fn wait(&mut self) -> Result<(), Arc<Error>>;
+
+    /// Same to [`wait`] except for that this method gives up blocking after `timeout` passes and
+    /// returns `Ok(None)` .
+    ///
+    /// The query is not cancelled even if it times out; call this method or [`wait`] again to
+    /// wait for it more.
+    ///
+    /// [`wait`]: Self::wait
+    fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<()>, Arc<Error>>;
 
     /// Returns error if `self` has finished, and if the query was failed; otherwise, returns
     /// `None`
     ///
     /// This method does not block.
-    fn error(&self) -> Option<&dyn Error>;
+    fn error(&self) -> Option<Arc<Error>>;
 }
 
 /// `Row` represents data stored in the KVS.
@@ -50,6 +78,122 @@ pub struct Row<'a> {
     pub extrinsic: Cow<'a, [u8]>,
 }
 
+/// `CorruptRow` is the error that the data stored in the KVS does not match the checksum.
+///
+/// The checksum is verified only if '--kvs-verify-checksums' is specified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptRow {
+    id: Vec<u8>,
+    column: &'static str,
+}
+
+impl CorruptRow {
+    /// Creates a new instance.
+    ///
+    /// `column` is either "intrinsic" or "extrinsic".
+    pub fn new(id: &[u8], column: &'static str) -> Self {
+        Self {
+            id: id.to_vec(),
+            column,
+        }
+    }
+
+    /// Returns the id of the broken data.
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Returns which data is broken; "intrinsic" or "extrinsic".
+    pub fn column(&self) -> &'static str {
+        self.column
+    }
+}
+
+impl fmt::Display for CorruptRow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The {} data of {} in the KVS is corrupt: checksum mismatch",
+            self.column,
+            HexDisplay::new(&self.id)
+        )
+    }
+}
+
+impl error::Error for CorruptRow {}
+
+/// `Error` is the error of [`ReadQuery`] and [`WriteQuery`] .
+///
+/// [`ReadQuery`]: self::ReadQuery
+/// [`WriteQuery`]: self::WriteQuery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The backend (i.e. LevelDB) failed. It holds the message of the backend error.
+    Backend(String),
+    /// The stored data does not match the checksum.
+    Corrupt(CorruptRow),
+    /// The failure is injected. (See module [`fault`] .)
+    ///
+    /// [`fault`]: crate::fault
+    Injected(fault::Injected),
+    /// The id is too long to be stored in the KVS. It holds the byte length of the id.
+    ///
+    /// The id must be shorter than the keys of the shared extrinsic data, which are 1 byte longer
+    /// than [`Id`] . (See '--kvs-dedup-extrinsic-min-bytes'.)
+    ///
+    /// [`Id`]: crate::data_types::Id
+    IdTooLong(usize),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Backend(msg) => f.write_str(msg),
+            Self::Corrupt(e) => e.fmt(f),
+            Self::Injected(e) => e.fmt(f),
+            Self::IdTooLong(len) => write!(f, "The id of {} bytes is too long for the KVS", len),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Backend(_) => None,
+            Self::IdTooLong(_) => None,
+            Self::Corrupt(e) => Some(e),
+            Self::Injected(e) => Some(e),
+        }
+    }
+}
+
+impl From<CorruptRow> for Error {
+    fn from(e: CorruptRow) -> Self {
+        Self::Corrupt(e)
+    }
+}
+
+impl From<fault::Injected> for Error {
+    fn from(e: fault::Injected) -> Self {
+        Self::Injected(e)
+    }
+}
+
+/// [`Error::Corrupt`] is converted into [`crate::Error::Corrupt`] , and the others are into
+/// [`crate::Error::Kvs`] .
+///
+/// [`Error::Corrupt`]: self::Error::Corrupt
+/// [`crate::Error::Corrupt`]: crate::Error::Corrupt
+/// [`crate::Error::Kvs`]: crate::Error::Kvs
+impl From<Error> for crate::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Corrupt(e) => Self::corrupt(e),
+            e => Self::kvs(e),
+        }
+    }
+}
+
 /// Trait for query to the KVS to fetch.
 ///
 /// It depends on the implementation whether the constructor starts the query or not.
@@ -64,11 +208,89 @@ pub trait ReadQuery {
     ///
     /// This method returns `Row` if the data is found, or `None` if the query succeeded but no
     /// such data is stored in the KVS.
-    fn wait(&mut self) -> Result<Option<Row>, &dyn Error>;
+    fn wait(&mut self) -> Result<Option<Row>, Arc<Error>>;
+
+    /// Same to [`wait`] except for that this method gives up blocking after `timeout` passes and
+    /// returns `Ok(None)` .
+    ///
+    /// The query is not cancelled even if it times out; call this method or [`wait`] again to
+    /// wait for it more.
+    ///
+    /// [`wait`]: Self::wait
+    fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<Option<Row>>, Arc<Error>>;
 
     /// Returns error if `self` has finished, and if the query was failed; otherwise, returns
     /// `None`
     ///
     /// This method does not block.
-    fn error(&self) -> Option<&dyn Error>;
+    fn error(&self) -> Option<Arc<Error>>;
+}
+
+/// Fetches the intrinsic data of `id` from the KVS, and deserializes it using the deserializer
+/// registored to `data_types_env` .
+///
+/// Returns `None` if no such data is stored in the KVS.
+/// Fails if the data does not match the checksum. (See also [`CorruptRow`] .)
+///
+/// Note that the extrinsic data is not used, because [`Acid`] must be deserialized only from the
+/// intrinsic data.
+///
+/// See also function [`deserialize_acid`] .
+///
+/// [`Acid`]: crate::data_types::Acid
+/// [`deserialize_acid`]: crate::data_types::deserialize_acid
+/// [`CorruptRow`]: self::CorruptRow
+pub fn fetch_acid(
+    id: &Id,
+    env: &Environment,
+    data_types_env: &data_types::Environment,
+) -> Result<Option<CAcid>, crate::Error> {
+    let mut query = fetch(id, env);
+    match query.wait() {
+        Ok(None) => Ok(None),
+        Ok(Some(row)) => {
+            let acid = data_types::deserialize_acid(row.intrinsic.as_ref(), data_types_env)?;
+            Ok(Some(acid))
+        }
+        Err(e) => Err(crate::Error::from(Error::clone(&e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+    use std::thread;
+
+    #[test]
+    fn error_() {
+        let e = Arc::new(Error::from(CorruptRow::new(&[0xab], "intrinsic")));
+        assert_eq!(
+            "The intrinsic data of ab in the KVS is corrupt: checksum mismatch",
+            e.to_string()
+        );
+        assert_eq!(true, e.source().is_some());
+
+        // The error can be sent to another thread.
+        let cloned = e.clone();
+        let handle = thread::spawn(move || cloned.to_string());
+        assert_eq!(e.to_string(), handle.join().unwrap());
+
+        let e = Error::Backend(String::from("IO error"));
+        assert_eq!("IO error", e.to_string());
+        assert_eq!(true, e.source().is_none());
+    }
+
+    #[test]
+    fn into_crate_error() {
+        let e = Error::from(CorruptRow::new(&[0xab], "extrinsic"));
+        assert_eq!(true, crate::Error::from(e).is_corrupt());
+
+        let e = crate::Error::from(Error::Backend(String::from("IO error")));
+        assert_eq!(false, e.is_corrupt());
+        match e {
+            crate::Error::Kvs(_) => (),
+            _ => panic!("Unexpected variant"),
+        }
+    }
 }
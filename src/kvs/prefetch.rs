@@ -0,0 +1,202 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `prefetch` provides the worker threads to fetch the acids from the KVS in parallel.
+//!
+//! The threads only read the KVS; they never wait for another job, so a job never waits for the
+//! same pool. (This is why the fetches do not use the shared worker threads. See module
+//! [`workers`] .)
+//!
+//! [`workers`]: crate::workers
+
+use crate::runtime::{self, WorkerGroup};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// `Pool` is a fixed number of the worker threads.
+#[derive(Default)]
+pub struct Pool {
+    sender: Option<Mutex<Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        // The workers finish when the channel is closed.
+        self.sender = None;
+
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("A KVS prefetch thread panicked.");
+            }
+        }
+    }
+}
+
+impl Pool {
+    /// Returns `true` if no worker thread is started.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// Starts `threads` worker threads in `WorkerGroup::Prefetch` .
+    ///
+    /// If `threads` is 0, the jobs are run in the caller thread.
+    pub fn start(threads: usize, runtime_env: &runtime::Environment) -> io::Result<Self> {
+        let mut ret = Self::default();
+        if threads == 0 {
+            return Ok(ret);
+        }
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        ret.sender = Some(Mutex::new(sender));
+
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            let worker = runtime::spawn(WorkerGroup::Prefetch, runtime_env, move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            })?;
+            ret.workers.push(worker);
+        }
+
+        Ok(ret)
+    }
+
+    /// Runs `job` on a worker thread without blocking, or runs it in the caller thread if no
+    /// worker thread is started.
+    ///
+    /// A panic in `job` is caught and discarded.
+    pub fn spawn<F>(&self, job: F)
+    where
+        F: 'static + FnOnce() + Send,
+    {
+        let job: Job = Box::new(move || {
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        });
+
+        match &self.sender {
+            None => job(),
+            Some(sender) => {
+                if let Err(mpsc::SendError(job)) = sender.lock().unwrap().send(job) {
+                    // The workers have already finished. (Never comes here as long as `self` is
+                    // alive.)
+                    job();
+                }
+            }
+        }
+    }
+
+    /// Runs `jobs` on the worker threads, blocks till all of them finish, and returns the results
+    /// in the same order as `jobs` . The result is `None` if the job panicked.
+    ///
+    /// The jobs own everything they use, so they can outlive this call safely; e.g. if the caller
+    /// unwinds before they finish.
+    pub fn run_all<T>(&self, jobs: Vec<Box<dyn FnOnce() -> T + Send>>) -> Vec<Option<T>>
+    where
+        T: 'static + Send,
+    {
+        let mut ret: Vec<Option<T>> = jobs.iter().map(|_| None).collect();
+        let (sender, receiver) = mpsc::channel();
+
+        for (i, job) in jobs.into_iter().enumerate() {
+            let sender = sender.clone();
+            self.spawn(move || {
+                let result = job();
+                // The receiver is dropped only if the caller has unwound.
+                let _ = sender.send((i, result));
+            });
+        }
+
+        // Each job drops the sender when it finishes, or when it panics, so the loop ends after
+        // all of them.
+        drop(sender);
+        for (i, result) in receiver {
+            ret[i] = Some(result);
+        }
+
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(n: usize) -> Vec<Box<dyn FnOnce() -> usize + Send>> {
+        (0..n)
+            .map(|i| {
+                Box::new(move || {
+                    if i == 3 {
+                        panic!("Job {} panicked.", i);
+                    }
+                    i * 2
+                }) as Box<dyn FnOnce() -> usize + Send>
+            })
+            .collect()
+    }
+
+    fn expected(n: usize) -> Vec<Option<usize>> {
+        (0..n)
+            .map(|i| if i == 3 { None } else { Some(i * 2) })
+            .collect()
+    }
+
+    #[test]
+    fn run_all_() {
+        let pool = Pool::start(4, &runtime::Environment::default()).unwrap();
+        assert_eq!(false, pool.is_empty());
+        assert_eq!(expected(100), pool.run_all(jobs(100)));
+        assert_eq!(Vec::<Option<usize>>::new(), pool.run_all(jobs(0)));
+    }
+
+    #[test]
+    fn run_all_in_caller_thread() {
+        // No worker thread is started.
+        let pool = Pool::default();
+        assert_eq!(true, pool.is_empty());
+        assert_eq!(expected(10), pool.run_all(jobs(10)));
+
+        let pool = Pool::start(0, &runtime::Environment::default()).unwrap();
+        assert_eq!(true, pool.is_empty());
+        assert_eq!(expected(10), pool.run_all(jobs(10)));
+    }
+
+    #[test]
+    fn run_all_unwind() {
+        let pool = Pool::start(2, &runtime::Environment::default()).unwrap();
+
+        // Poison the lock of the sender.
+        let poison = || {
+            let _sender = pool.sender.as_ref().unwrap().lock().unwrap();
+            panic!("Poison the lock.");
+        };
+        assert_eq!(true, panic::catch_unwind(AssertUnwindSafe(poison)).is_err());
+
+        // `run_all` fails to queue the jobs and unwinds without blocking.
+        let result = panic::catch_unwind(AssertUnwindSafe(|| pool.run_all(jobs(10))));
+        assert_eq!(true, result.is_err());
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `speculative` queues the ids of the acids probably requested next; i.e. the parents of the
+//! acids fetched recently.
+
+use crate::data_types::{Acid, Id};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// `Speculation` is the bounded queue of the ids to prefetch at idle time.
+///
+/// Each id has the depth; i.e. the distance from the acid fetched on demand. The depth of the
+/// parents of the acid fetched on demand is 1.
+#[derive(Default)]
+pub struct Speculation {
+    queue: Mutex<VecDeque<(Id, u32)>>,
+    max_depth: u32,
+    budget: usize,
+}
+
+impl Speculation {
+    /// Creates a new instance to queue the ids whose depth is `max_depth` or less, and at most
+    /// `budget` ids at once.
+    pub fn new(max_depth: u32, budget: usize) -> Self {
+        Self {
+            queue: Default::default(),
+            max_depth,
+            budget,
+        }
+    }
+
+    /// Queues the parents of `acid` with `depth` , and returns the number of the queued ids.
+    ///
+    /// The parents are ignored if `depth` is greater than the max depth, or if the queue is full.
+    pub fn schedule(&self, acid: &dyn Acid, depth: u32) -> usize {
+        if self.max_depth < depth {
+            return 0;
+        }
+
+        let mut queue = self.queue.lock().unwrap();
+        let mut ret = 0;
+
+        for i in 0..acid.parent_count() {
+            if self.budget <= queue.len() {
+                break;
+            }
+
+            let parent = acid.parent(i).unwrap();
+            if queue.iter().all(|(id, _)| *id != parent) {
+                queue.push_back((parent, depth));
+                ret += 1;
+            }
+        }
+
+        ret
+    }
+
+    /// Takes at most `limit` ids in the queued order.
+    pub fn take(&self, limit: usize) -> Vec<(Id, u32)> {
+        let mut queue = self.queue.lock().unwrap();
+        let len = limit.min(queue.len());
+        queue.drain(..len).collect()
+    }
+
+    /// Returns the number of the queued ids.
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+    use crate::test_utils::{GeneratedAcid, Rng};
+
+    fn id(i: u8) -> Id {
+        let mut ret = Id::zeroed();
+        ret[0] = i;
+        ret
+    }
+
+    #[test]
+    fn schedule_and_take() {
+        let mut rng = Rng::new(1);
+        let acid = GeneratedAcid::new(vec![id(1), id(2), id(3)], 8, &mut rng);
+        let other = GeneratedAcid::new(vec![id(3), id(4)], 8, &mut rng);
+
+        let speculation = Speculation::new(2, 4);
+        assert_eq!(0, speculation.schedule(&acid, 3));
+        assert_eq!(3, speculation.schedule(&acid, 1));
+
+        // id(3) is queued already, and the queue is full after id(4).
+        assert_eq!(1, speculation.schedule(&other, 2));
+        assert_eq!(0, speculation.schedule(&other, 2));
+        assert_eq!(4, speculation.len());
+
+        assert_eq!(vec![(id(1), 1), (id(2), 1)], speculation.take(2));
+        assert_eq!(vec![(id(3), 1), (id(4), 2)], speculation.take(10));
+        assert_eq!(0, speculation.len());
+    }
+}
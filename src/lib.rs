@@ -21,19 +21,58 @@
 #[macro_use]
 extern crate log;
 
+pub mod admission;
+pub mod block_builder;
+pub mod byte_size;
 pub mod cache;
+pub mod clock;
+pub mod coin_selection;
+mod config_file;
 pub mod data_types;
+mod error;
+pub mod events;
+pub mod fault;
+pub mod fork_choice;
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub mod fuzzing;
+pub mod health;
+pub mod import;
 pub mod kvs;
+pub mod listen;
 mod logger;
+pub mod mempool;
+pub mod ownership;
+pub mod profile;
+pub mod prune;
 pub mod rdb;
+pub mod reconcile;
+pub mod replay;
+pub mod retention;
+pub mod retry;
+pub mod runtime;
+pub mod shutdown;
+pub mod snapshot;
+pub mod storage;
 #[cfg(test)]
 mod stub;
+pub mod sync;
+#[cfg(any(test, feature = "test_utils"))]
+pub mod test_utils;
+pub mod wallet;
+pub mod workers;
 
-use clap::{App, ArgMatches};
-use data_types::CAcid;
-use std::error::Error;
+pub use error::Error;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use data_types::{Acid, CAcid, ChainIndex, CryptoHash, Id, ResourceId};
+use shutdown::ShutdownHandle;
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
-use std::os::raw::c_int;
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 /// `Config` is a wrapper of [`clap::ArgMatches<'static>`] .
 ///
@@ -45,14 +84,32 @@ use std::os::raw::c_int;
 pub struct Config {
     args_: ArgMatches<'static>,
     name_: String,
+    handlers_: HashMap<String, SubcommandHandler>,
 }
 
+/// `SubcommandHandler` is a function to run a subcommand.
+///
+/// It is called by function [`run`] with the initialized [`GlobalEnvironment`] and the arguments
+/// of the subcommand.
+///
+/// [`run`]: crate::run
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub type SubcommandHandler =
+    fn(&GlobalEnvironment, &ArgMatches<'static>) -> Result<(), Box<dyn std::error::Error>>;
+
+/// The name of the built-in subcommand to run the node. (Same to that no subcommand is given.)
+const RUN_SUBCOMMAND: &'static str = "run";
+
 impl Config {
     /// Parses the argument and creates a new instance.
     ///
     /// This function parses arguments for `Mouse` by default.
     /// If user want to add some arguments, set them to `app` .
     ///
+    /// If '--config' is specified, the arguments in the TOML file are merged into the command
+    /// line arguments. (The command line arguments take precedence.) Each key of the file is the
+    /// long name of an argument, and the keys in table `[foo]` are prefixed by "foo-".
+    ///
     /// # Examples
     ///
     /// ```no_run
@@ -78,20 +135,120 @@ impl Config {
     /// let config = Config::new(app);
     /// ```
     pub fn new(app: App<'static, 'static>) -> Self {
+        Self::with_subcommands(app, Vec::new())
+    }
+
+    /// Parses the argument with `subcommands` and creates a new instance.
+    ///
+    /// Each element of `subcommands` is a pair of the subcommand definition and the handler.
+    /// Function [`run`] calls the handler of the given subcommand instead of waiting for the
+    /// signal. Built-in subcommand "run" is added as well, which behaves as if no subcommand is
+    /// given.
+    ///
+    /// The arguments for `Mouse` belong to the top level command; i.e. they are placed before
+    /// the subcommand.
+    ///
+    /// [`run`]: crate::run
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # #[macro_use] extern crate clap;
+    ///
+    /// use clap::{App, ArgMatches, SubCommand};
+    /// use mouse::{Config, GlobalEnvironment, SubcommandHandler};
+    /// use std::error::Error;
+    ///
+    /// fn reindex(_env: &GlobalEnvironment, _args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    ///     Ok(())
+    /// }
+    ///
+    /// // Initialize app
+    /// let app = App::new(crate_name!())
+    ///     .version(crate_version!())
+    ///     .about(crate_description!());
+    ///
+    /// // Add subcommand 'reindex'
+    /// let reindex_app = SubCommand::with_name("reindex").about("Rebuilds the index.");
+    ///
+    /// // Creates 'Config'.
+    /// let handler: SubcommandHandler = reindex;
+    /// let config = Config::with_subcommands(app, vec![(reindex_app, handler)]);
+    /// ```
+    pub fn with_subcommands(
+        app: App<'static, 'static>,
+        subcommands: Vec<(App<'static, 'static>, SubcommandHandler)>,
+    ) -> Self {
         let name = String::from(app.get_name());
 
+        let mut handlers = HashMap::with_capacity(subcommands.len());
+        let mut app = app;
+        if !subcommands.is_empty() {
+            app = app.subcommand(
+                SubCommand::with_name(RUN_SUBCOMMAND).about("Runs the node. (Default.)"),
+            );
+        }
+        for (subcommand, handler) in subcommands {
+            handlers.insert(String::from(subcommand.get_name()), handler);
+            app = app.subcommand(subcommand);
+        }
+
         let app = logger::Environment::args(app);
+        let app = runtime::Environment::args(app);
+        let app = workers::Environment::args(app);
+        let app = clock::Environment::args(app);
+        let app = admission::Environment::args(app);
         let app = data_types::Environment::args(app);
         let app = cache::Environment::args(app);
         let app = kvs::Environment::args(app);
         let app = rdb::Environment::args(app);
+        let app = storage::Environment::args(app);
+        let app = retention::Environment::args(app);
+        let app = prune::Environment::args(app);
+        let app = fork_choice::Environment::args(app);
+        let app = mempool::Environment::args(app);
+        let app = wallet::Environment::args(app);
+
+        let app = app.arg(
+            Arg::with_name(config_file::CONFIG_ARG)
+                .help(
+                    "Path to the TOML file to load the arguments from.
+Each key is the long name of an argument. The command line arguments take precedence.",
+                )
+                .long(config_file::CONFIG_ARG)
+                .takes_value(true),
+        );
+
+        let args = match config_file::merge(std::env::args_os().collect()) {
+            Ok(args) => args,
+            Err(e) => {
+                clap::Error::with_description(&e.to_string(), clap::ErrorKind::InvalidValue).exit()
+            }
+        };
 
         Config {
-            args_: app.get_matches(),
+            args_: app.get_matches_from(args),
             name_: name,
+            handlers_: handlers,
         }
     }
 
+    /// Returns the name and the arguments of the given subcommand, or `None` if no subcommand is
+    /// given.
+    pub fn subcommand(&self) -> Option<(&str, &ArgMatches<'static>)> {
+        match self.args_.subcommand() {
+            (name, Some(args)) => Some((name, args)),
+            _ => None,
+        }
+    }
+
+    /// Returns the handler of the given subcommand, or `None` if no subcommand, or built-in
+    /// subcommand "run" is given.
+    fn subcommand_handler(&self) -> Option<(SubcommandHandler, &ArgMatches<'static>)> {
+        let (name, args) = self.subcommand()?;
+        self.handlers_.get(name).map(|&handler| (handler, args))
+    }
+
     /// Provides a reference to the wrapped value.
     ///
     /// # Examples
@@ -153,8 +310,68 @@ impl Config {
 }
 
 /// Initializes mouse, starts to listen to the user requests, and waits for the signal.
-#[cfg(unix)]
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+/// (On Windows, waits for the console control event such as Ctrl+C instead.)
+///
+/// This function is same to [`run_with`] with the default [`GlobalEnvironment`] .
+///
+/// [`run_with`]: crate::run_with
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    run_with(config, GlobalEnvironment::default())
+}
+
+/// Initializes `environment` , starts to listen to the user requests, and waits for the signal.
+///
+/// The modules registered to `environment` by [`GlobalEnvironment::register`] are checked and
+/// initialized after the built-in modules.
+///
+/// If a subcommand registered by [`Config::with_subcommands`] is given, calls the handler with
+/// the initialized [`GlobalEnvironment`] and returns the result instead of waiting for the
+/// signal.
+///
+/// [`GlobalEnvironment::register`]: crate::GlobalEnvironment::register
+/// [`Config::with_subcommands`]: crate::Config::with_subcommands
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub fn run_with(
+    config: Config,
+    environment: GlobalEnvironment,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_until(config, environment, &ShutdownHandle::new())
+}
+
+/// Starts [`run`] in a new thread, and returns the handle to stop it and the `JoinHandle` of the
+/// thread.
+///
+/// The node stops when [`ShutdownHandle::shutdown`] is called, as well as when the signal comes.
+///
+/// [`run`]: crate::run
+/// [`ShutdownHandle::shutdown`]: crate::shutdown::ShutdownHandle::shutdown
+pub fn start(
+    config: Config,
+) -> io::Result<(
+    ShutdownHandle,
+    JoinHandle<Result<(), Box<dyn std::error::Error + Send + Sync>>>,
+)> {
+    let handle = ShutdownHandle::new();
+
+    let join_handle = {
+        let handle = handle.clone();
+        thread::Builder::new()
+            .name(String::from("mouse"))
+            .spawn(move || {
+                let environment = GlobalEnvironment::default();
+                run_until(config, environment, &handle).map_err(|e| Box::from(e.to_string()))
+            })?
+    };
+
+    Ok((handle, join_handle))
+}
+
+fn run_until(
+    config: Config,
+    mut environment: GlobalEnvironment,
+    handle: &ShutdownHandle,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Open log.
     // 'logger' is a special module and excluded from 'GlobalEnvironment'.
     let mut logger = logger::Environment::default();
@@ -162,24 +379,31 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     unsafe { logger.init() }?;
 
     // Log has opend here.
-    let log_error = |e| {
+    fn log_error<E: Display>(e: E) -> E {
         error!("{}", e);
         e
-    };
+    }
 
     {
-        let mut environment = GlobalEnvironment::default();
         unsafe { environment.check(&config).map_err(log_error) }?;
         unsafe { environment.init().map_err(log_error) }?;
 
-        unsafe {
-            if sigwait_() != 0 {
-                let msg = errno::errno().to_string();
-                error!("{}", &msg);
-                return Err(Box::from(msg));
+        if environment.rdb.is_migrate_dry_run() {
+            let report = environment.pending_migrations().map_err(log_error)?;
+            for line in report.to_string().lines() {
+                info!("{}", line);
             }
+            return Ok(());
+        }
+
+        if let Some((handler, args)) = config.subcommand_handler() {
+            return handler(&environment, args).map_err(log_error);
         }
 
+        handle
+            .wait()
+            .map_err(|e| log_error(Box::<dyn std::error::Error>::from(e)))?;
+
         // 'environment' is dropped here.
     }
 
@@ -188,15 +412,6 @@ pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
     // 'logger' is dropped here.
 }
 
-#[link(name = "mouse_signal")]
-extern "C" {
-    /// Waits for signals 'SIGHUP' or 'SIGTERM' or 'SIGINT' and returns `0` on success, or `1`.
-    ///
-    /// 'errno' will be set on error.
-    #[cfg(unix)]
-    fn sigwait_() -> c_int;
-}
-
 /// `ModuleEnvironment` represents a set of the followings for each module.
 ///
 /// - Connection to the outside of the process, DataBase connection, socket to listen to the user
@@ -216,7 +431,7 @@ pub trait ModuleEnvironment: Default {
     /// The behavior is undefined if called after method [`init`] is called.
     ///
     /// [`init`]: Self::init
-    unsafe fn check(&mut self, _config: &Config) -> Result<(), Box<dyn Error>> {
+    unsafe fn check(&mut self, _config: &Config) -> Result<(), Error> {
         panic!("Not implemented yet.");
     }
 
@@ -226,11 +441,60 @@ pub trait ModuleEnvironment: Default {
     /// # Safety
     ///
     /// The behavior is undefined if this method is called twice or more than twice.
-    unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    unsafe fn init(&mut self) -> Result<(), Error> {
         panic!("Not implemented yet.");
     }
 }
 
+/// `DynModuleEnvironment` is an object safe version of [`ModuleEnvironment`] to register the
+/// user defined modules to [`GlobalEnvironment`] .
+///
+/// Every `ModuleEnvironment` implementing `Any` implements this trait.
+///
+/// [`ModuleEnvironment`]: crate::ModuleEnvironment
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub trait DynModuleEnvironment: Any {
+    /// Same to [`ModuleEnvironment::check`] .
+    ///
+    /// (The name is different not to conflict with `ModuleEnvironment` .)
+    ///
+    /// # Safety
+    ///
+    /// See [`ModuleEnvironment::check`] .
+    ///
+    /// [`ModuleEnvironment::check`]: crate::ModuleEnvironment::check
+    unsafe fn check_dyn(&mut self, config: &Config) -> Result<(), Error>;
+
+    /// Same to [`ModuleEnvironment::init`] .
+    ///
+    /// # Safety
+    ///
+    /// See [`ModuleEnvironment::init`] .
+    ///
+    /// [`ModuleEnvironment::init`]: crate::ModuleEnvironment::init
+    unsafe fn init_dyn(&mut self) -> Result<(), Error>;
+
+    /// Provides a reference to `self` as `Any` .
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> DynModuleEnvironment for T
+where
+    T: ModuleEnvironment + Any,
+{
+    unsafe fn check_dyn(&mut self, config: &Config) -> Result<(), Error> {
+        ModuleEnvironment::check(self, config)
+    }
+
+    unsafe fn init_dyn(&mut self) -> Result<(), Error> {
+        ModuleEnvironment::init(self)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 /// A set of `ModuleEnvironment` instances for all the module.
 #[derive(Default)]
 pub struct GlobalEnvironment {
@@ -240,13 +504,195 @@ pub struct GlobalEnvironment {
     // !!
     // !! See Rust-RFC 1857 for details.
     // !! https://github.com/rust-lang/rfcs/blob/master/text/1857-stabilize-drop-order.md
+    // !!
+    // !! The user defined modules are dropped in the reverse order of the registration before
+    // !! the others. (See 'Drop' implementation.)
+    modules: Vec<Box<dyn DynModuleEnvironment>>,
+    health: health::Registry,
+    events: events::Bus,
+    ownership: Option<Box<dyn ownership::OwnershipVerifier>>,
+    wallet: wallet::Environment,
+    mempool: mempool::Environment,
+    fork_choice: fork_choice::Environment,
+    prune: prune::Environment,
+    retention: retention::Environment,
+    storage: storage::Environment,
     rdb: rdb::Environment,
     kvs: kvs::Environment,
     cache: cache::Environment,
     data_types: data_types::Environment,
+    admission: admission::Environment,
+    clock: clock::Environment,
+    workers: workers::Environment,
+    runtime: runtime::Environment,
+}
+
+impl Drop for GlobalEnvironment {
+    fn drop(&mut self) {
+        while let Some(module) = self.modules.pop() {
+            drop(module);
+        }
+    }
 }
 
 impl GlobalEnvironment {
+    /// Registers the user defined module `module` .
+    ///
+    /// The registered modules are checked and initialized after the built-in modules in the
+    /// order of the registration, and dropped before the built-in modules in the reverse order.
+    ///
+    /// The arguments that `module` requests must be added to `App` before [`Config`] is created.
+    ///
+    /// [`Config`]: crate::Config
+    ///
+    /// # Safety
+    ///
+    /// The behavior is undefined if this method is called after method [`check`] is called.
+    ///
+    /// [`check`]: Self::check
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::{Config, Error, GlobalEnvironment, ModuleEnvironment};
+    ///
+    /// #[derive(Default)]
+    /// struct Foo;
+    ///
+    /// impl ModuleEnvironment for Foo {
+    ///     unsafe fn check(&mut self, _config: &Config) -> Result<(), Error> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     unsafe fn init(&mut self) -> Result<(), Error> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut env = GlobalEnvironment::default();
+    /// unsafe { env.register(Box::new(Foo)) };
+    /// assert_eq!(true, env.module::<Foo>().is_some());
+    /// ```
+    pub unsafe fn register(&mut self, module: Box<dyn DynModuleEnvironment>) {
+        self.modules.push(module);
+    }
+
+    /// Provides a reference to the first registered module of type `T` if any.
+    pub fn module<T>(&self) -> Option<&T>
+    where
+        T: DynModuleEnvironment,
+    {
+        self.modules
+            .iter()
+            .find_map(|module| module.as_any().downcast_ref::<T>())
+    }
+
+    /// Registers the health check `callback` as `name` .
+    ///
+    /// Unlike [`register`] , this method can be called at any time; e.g. when the application
+    /// starts a subsystem.
+    ///
+    /// See also function [`health_report`] .
+    ///
+    /// [`register`]: Self::register
+    /// [`health_report`]: crate::health_report
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use mouse::health::Check;
+    ///
+    /// let env = GlobalEnvironment::default();
+    /// env.register_health_check("foo", || Check::degraded("foo is slow"));
+    /// ```
+    pub fn register_health_check<F>(&self, name: &str, callback: F)
+    where
+        F: 'static + Fn() -> health::Check + Send + Sync,
+    {
+        self.health.register(name, callback);
+    }
+
+    /// Removes the health check registered as `name` , and returns `true` if found.
+    pub fn unregister_health_check(&self, name: &str) -> bool {
+        self.health.unregister(name)
+    }
+
+    /// Subscribes the events of the main chain and the mempool buffering at most `capacity`
+    /// events.
+    ///
+    /// Like [`register_health_check`] , this method can be called at any time. See also module
+    /// [`events`] .
+    ///
+    /// [`register_health_check`]: Self::register_health_check
+    /// [`events`]: crate::events
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    ///
+    /// let env = GlobalEnvironment::default();
+    /// let subscription = env.subscribe_events(1024);
+    /// assert_eq!(None, subscription.try_recv());
+    /// ```
+    pub fn subscribe_events(&self, capacity: usize) -> events::Subscription {
+        self.events.subscribe(capacity)
+    }
+
+    /// Sends `event` to the subscriptions, and returns the number of the subscriptions which
+    /// buffered it.
+    ///
+    /// Functions [`commit_block`] , [`revert_block`] , [`add_pending_acid`] , and
+    /// [`mark_invalid_acid`] publish the events by themselves. The application publishes the
+    /// others; e.g. [`events::Event::BlockDisconnected`] when it reverts the main chain without
+    /// [`revert_block`] .
+    ///
+    /// [`commit_block`]: crate::commit_block
+    /// [`revert_block`]: crate::revert_block
+    /// [`add_pending_acid`]: crate::add_pending_acid
+    /// [`mark_invalid_acid`]: crate::mark_invalid_acid
+    /// [`events::Event::BlockDisconnected`]: crate::events::Event::BlockDisconnected
+    pub fn publish_event(&self, event: events::Event) -> usize {
+        self.events.publish(event)
+    }
+
+    /// Registers `f` to be called every `interval` on the worker threads, and returns the id to
+    /// unregister it.
+    ///
+    /// Like [`register_health_check`] , this method can be called at any time. The registered
+    /// tasks start to run after method [`init`] is called, and stop when `self` is dropped.
+    ///
+    /// See also method [`workers::Environment::schedule`] .
+    ///
+    /// [`register_health_check`]: Self::register_health_check
+    /// [`init`]: Self::init
+    /// [`workers::Environment::schedule`]: crate::workers::Environment::schedule
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use std::time::Duration;
+    ///
+    /// let env = GlobalEnvironment::default();
+    /// let id = env.schedule("foo", Duration::from_secs(60), || ());
+    /// assert_eq!(true, env.unschedule(id));
+    /// ```
+    pub fn schedule<F>(&self, name: &str, interval: Duration, f: F) -> u64
+    where
+        F: 'static + Fn() + Send + Sync,
+    {
+        self.workers.schedule(name, interval, f)
+    }
+
+    /// Unregisters the task registered by method [`schedule`] , and returns `true` if found.
+    ///
+    /// [`schedule`]: Self::schedule
+    pub fn unschedule(&self, id: u64) -> bool {
+        self.workers.unschedule(id)
+    }
+
     /// Calls method [`ModuleEnvironment.check`] for each property.
     ///
     /// # Safety
@@ -255,28 +701,144 @@ impl GlobalEnvironment {
     ///
     /// [`init`]: Self::init
     /// [`ModuleEnvironment.check`]: crate::ModuleEnvironment::check
-    pub unsafe fn check(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+    pub unsafe fn check(&mut self, config: &Config) -> Result<(), Error> {
+        self.runtime.check(config)?;
+        self.workers.check(config)?;
+        self.clock.check(config)?;
+        self.admission.check(config)?;
         self.data_types.check(config)?;
         self.cache.check(config)?;
         self.kvs.check(config)?;
         self.rdb.check(config)?;
+        self.storage.check(config)?;
+        self.retention.check(config)?;
+        self.prune.check(config)?;
+        self.fork_choice.check(config)?;
+        self.mempool.check(config)?;
+        self.wallet.check(config)?;
+
+        for module in self.modules.iter_mut() {
+            module.check_dyn(config)?;
+        }
 
         Ok(())
     }
 
     /// Calls method [`ModuleEnvironment.init`] for each property.
     ///
+    /// This method also starts the worker threads (see module [`workers`] ,) and the KVS flusher
+    /// and prefetch threads, completes the block commit interrupted by a crash if any, (see also
+    /// function [`storage::recover`] ,) warms up the cache if '--cache-warmup-count' is
+    /// specified, and finalizes the main chain up to the checkpoints. (See also function
+    /// [`fork_choice::finalize_checkpoints`] .)
+    ///
+    /// If '--migrate-dry-run' is specified, this method only opens the KVS and the RDB, and
+    /// changes nothing.
+    /// (See also method [`pending_migrations`] .)
+    ///
     /// # Safety
     ///
     /// The behavior is undefined if this method is called twice or more than twice.
     ///
     /// [`ModuleEnvironment.init`]: crate::ModuleEnvironment::init
-    pub unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    /// [`workers`]: crate::workers
+    /// [`storage::recover`]: crate::storage::recover
+    /// [`fork_choice::finalize_checkpoints`]: crate::fork_choice::finalize_checkpoints
+    /// [`pending_migrations`]: Self::pending_migrations
+    pub unsafe fn init(&mut self) -> Result<(), Error> {
+        // Only open the KVS and the RDB to report the pending migrations.
+        if self.rdb.is_migrate_dry_run() {
+            self.kvs.init()?;
+            return self.rdb.init();
+        }
+
+        self.runtime.init()?;
+        self.workers.init()?;
+        self.workers.start(&self.runtime)?;
+        self.clock.init()?;
+        self.admission.init()?;
         self.data_types.init()?;
         self.cache.init()?;
         self.kvs.init()?;
+        self.kvs.start_flusher(&self.runtime).map_err(Error::kvs)?;
+        self.kvs
+            .start_prefetchers(&self.runtime)
+            .map_err(Error::kvs)?;
         self.rdb.init()?;
+        self.storage.init()?;
+        storage::recover(&self.storage, &self.kvs, &self.rdb)?;
+        self.warm_up_cache().map_err(Error::cache)?;
+        self.retention.init()?;
+        self.prune.init()?;
+        self.fork_choice.init()?;
+        fork_choice::finalize_checkpoints(&self.fork_choice, &mut rdb::master(&self.rdb))?;
+        self.mempool.init()?;
+        self.wallet.init()?;
+
+        for module in self.modules.iter_mut() {
+            module.init_dyn()?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the RDB schema objects which are not created yet, and the number of the KVS rows
+    /// to be converted for the current options.
+    ///
+    /// [`run`] writes the report into the log and exits if '--migrate-dry-run' is specified.
+    ///
+    /// See also function [`storage::pending_migrations`] .
+    ///
+    /// [`run`]: crate::run
+    /// [`storage::pending_migrations`]: crate::storage::pending_migrations
+    pub fn pending_migrations(&self) -> Result<storage::MigrationReport, Error> {
+        storage::pending_migrations(&self.kvs, &self.rdb)
+    }
+
+    /// Fetches the most recent '--cache-warmup-count' blocks in the main chain from the KVS, and
+    /// caches them.
+    ///
+    /// Warming up is an optimization; it stops with a warning if a block fails to be fetched or
+    /// cached (e.g. the hard limit is exceeded) and the blocks cached until then are kept.
+    fn warm_up_cache(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let count = self.cache.warmup_count();
+        if count == 0 {
+            return Ok(());
+        }
+
+        let chain = {
+            let mut session = rdb::slave(&self.rdb);
+            rdb::main_chain::fetch_desc(data_types::BlockHeight::MAX, count, &mut session)?
+        };
 
+        let mut cached = 0;
+        for chain_index in chain.as_ref() {
+            let id = chain_index.id();
+            let acid = match fetch_acid(id, self) {
+                Ok(Some(acid)) => acid,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!(
+                        "Stopped warming up the cache at {}: {}",
+                        id.display_hex(),
+                        e
+                    );
+                    break;
+                }
+            };
+
+            if let Err(e) = cache::insert(acid, &self.cache) {
+                warn!(
+                    "Stopped warming up the cache at {}: {}",
+                    id.display_hex(),
+                    e
+                );
+                break;
+            }
+            cached += 1;
+        }
+
+        info!("Warmed up the cache with {} blocks.", cached);
         Ok(())
     }
 
@@ -299,6 +861,91 @@ impl GlobalEnvironment {
     pub fn set_acid_deserializer(&mut self, deserializer: data_types::AcidDeserializer) {
         self.data_types.set_acid_deserializer(deserializer);
     }
+
+    /// Register `deserializer` to `self` for the intrinsic data whose type tag is `tag` .
+    ///
+    /// See also method [`register_acid_deserializer`] of `data_types::Environment` .
+    ///
+    /// [`register_acid_deserializer`]: crate::data_types::Environment::register_acid_deserializer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use mouse::data_types::AcidDeserializer;
+    ///
+    /// let deserializer: AcidDeserializer = |_| Err(Box::from("foo"));
+    /// let mut env = GlobalEnvironment::default();
+    /// env.register_acid_deserializer(1, deserializer);
+    ///
+    /// assert_eq!(true, mouse::deserialize_acid(&[1], &env).is_err());
+    /// ```
+    pub fn register_acid_deserializer(
+        &mut self,
+        tag: data_types::AcidTypeTag,
+        deserializer: data_types::AcidDeserializer,
+    ) {
+        self.data_types
+            .register_acid_deserializer(tag, deserializer);
+    }
+
+    /// Register `prioritizer` to `self` to order the pending acids in the mempool.
+    ///
+    /// See also method [`set_prioritizer`] of `mempool::Environment` .
+    ///
+    /// [`set_prioritizer`]: crate::mempool::Environment::set_prioritizer
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use mouse::mempool::Prioritizer;
+    ///
+    /// let prioritizer: Prioritizer = |_| 0;
+    /// let mut env = GlobalEnvironment::default();
+    /// env.set_mempool_prioritizer(prioritizer);
+    /// ```
+    pub fn set_mempool_prioritizer(&mut self, prioritizer: mempool::Prioritizer) {
+        self.mempool.set_prioritizer(prioritizer);
+    }
+
+    /// Register `rule` to `self` to choose the main chain instead of '--fork-choice'.
+    ///
+    /// See also module [`fork_choice`] .
+    ///
+    /// [`fork_choice`]: crate::fork_choice
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use mouse::fork_choice::HeaviestWork;
+    ///
+    /// let mut env = GlobalEnvironment::default();
+    /// env.set_fork_choice(Box::new(HeaviestWork));
+    /// ```
+    pub fn set_fork_choice(&mut self, rule: Box<dyn fork_choice::ForkChoice>) {
+        self.fork_choice.set_rule(rule);
+    }
+
+    /// Register `verifier` to `self` to verify the spending condition of the resources.
+    ///
+    /// See also module [`ownership`] .
+    ///
+    /// [`ownership`]: crate::ownership
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::GlobalEnvironment;
+    /// use mouse::ownership::Ed25519Owner;
+    ///
+    /// let mut env = GlobalEnvironment::default();
+    /// env.set_ownership_verifier(Box::new(Ed25519Owner));
+    /// ```
+    pub fn set_ownership_verifier(&mut self, verifier: Box<dyn ownership::OwnershipVerifier>) {
+        self.ownership = Some(verifier);
+    }
 }
 
 /// Deserializes `bytes` using deserializer registored to `env` .
@@ -316,10 +963,828 @@ impl GlobalEnvironment {
 ///
 /// assert_eq!(true, mouse::deserialize_acid(&[], &env).is_err());
 /// ```
-pub fn deserialize_acid(bytes: &[u8], env: &GlobalEnvironment) -> Result<CAcid, Box<dyn Error>> {
+pub fn deserialize_acid(
+    bytes: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<CAcid, Box<dyn std::error::Error>> {
     data_types::deserialize_acid(bytes, &env.data_types)
 }
 
+/// Fetches the intrinsic data of `id` from the KVS and deserializes it using deserializer
+/// registored to `env` .
+///
+/// Returns `None` if no such data is stored in the KVS.
+///
+/// The parents of the fetched acid are queued for [`prefetch_speculative`] if
+/// '--kvs-speculative-prefetch-depth' is greater than 0.
+///
+/// See also function [`kvs::fetch_acid`] .
+///
+/// [`prefetch_speculative`]: crate::prefetch_speculative
+/// [`kvs::fetch_acid`]: crate::kvs::fetch_acid
+pub fn fetch_acid(
+    id: &Id,
+    env: &GlobalEnvironment,
+) -> Result<Option<CAcid>, Box<dyn std::error::Error>> {
+    let acid = kvs::fetch_acid(id, &env.kvs, &env.data_types)?;
+    if let Some(acid) = acid.as_ref() {
+        kvs::schedule_parents(&**acid, &env.kvs);
+    }
+    Ok(acid)
+}
+
+/// Returns the current time adjusted by the clock skew against the other nodes.
+///
+/// See also function [`clock::now`] .
+///
+/// [`clock::now`]: crate::clock::now
+pub fn now(env: &GlobalEnvironment) -> clock::Timestamp {
+    clock::now(&env.clock)
+}
+
+/// Reports that a peer node said the current time is `peer_now` to estimate the clock skew.
+///
+/// See also function [`clock::observe_peer_time`] .
+///
+/// [`clock::observe_peer_time`]: crate::clock::observe_peer_time
+pub fn observe_peer_time(peer_now: clock::Timestamp, env: &GlobalEnvironment) {
+    clock::observe_peer_time(peer_now, &env.clock)
+}
+
+/// Returns an error if `timestamp` is too far in the future.
+///
+/// See also function [`clock::check_timestamp`] .
+///
+/// [`clock::check_timestamp`]: crate::clock::check_timestamp
+pub fn check_timestamp(
+    timestamp: clock::Timestamp,
+    env: &GlobalEnvironment,
+) -> Result<(), Box<dyn std::error::Error>> {
+    clock::check_timestamp(timestamp, &env.clock)
+}
+
+/// Returns the current resource usage for the admission control.
+///
+/// The free disk space is the less one of the KVS and the RDB. It is `None` if it is not
+/// available on the platform.
+pub fn admission_metrics(env: &GlobalEnvironment) -> admission::Metrics {
+    let disk_free = [env.kvs.db_path(), env.rdb.data_path()]
+        .iter()
+        .filter_map(|path| admission::disk_free(path).ok())
+        .min();
+
+    admission::Metrics {
+        disk_free,
+        kvs_queue_depth: kvs::pending_writes(&env.kvs),
+        memory_used: cache::cache_using_byte_size(),
+    }
+}
+
+/// Returns the health of the node.
+///
+/// The report starts with the built-in checks "admission", which is [`health::Status::Degraded`]
+/// if the new pending acids are rejected now, and "cache_integrity", which is degraded if
+/// [`cache::integrity::verify`] has found any anomaly. The checks registered by
+/// [`GlobalEnvironment::register_health_check`] follow them.
+///
+/// [`health::Status::Degraded`]: crate::health::Status::Degraded
+/// [`cache::integrity::verify`]: crate::cache::integrity::verify
+/// [`GlobalEnvironment::register_health_check`]: crate::GlobalEnvironment::register_health_check
+pub fn health_report(env: &GlobalEnvironment) -> health::Report {
+    let mut report = health::Report::default();
+
+    let admission = match admission::check(&admission_metrics(env), &env.admission) {
+        Ok(()) => health::Check::ok(),
+        Err(rejection) => health::Check::degraded(rejection.to_string()),
+    };
+    report.entries.push(health::Entry {
+        name: String::from("admission"),
+        check: admission,
+    });
+    report.entries.push(health::Entry {
+        name: String::from("cache_integrity"),
+        check: cache::integrity::health(&env.cache),
+    });
+
+    env.health.run(&mut report);
+    report
+}
+
+/// Deserializes `bytes` of a new pending acid that `source` (e.g. a peer) sent, unless the
+/// admission control refuses it before the expensive validation.
+///
+/// `bytes` is refused if `source` sends too many acids, (see function [`admission::throttle`] ,)
+/// if `bytes` is larger than '--max-acid-bytes', or if the acid is already in the mempool or in
+/// the cache. The error is [`admission::Rejection`] then, so that the caller can tell the reason
+/// to the peer by `downcast_ref` .
+///
+/// The returned acid is not validated yet. Pass it to function [`add_pending_acid`] .
+///
+/// [`admission::throttle`]: crate::admission::throttle
+/// [`admission::Rejection`]: crate::admission::Rejection
+/// [`add_pending_acid`]: crate::add_pending_acid
+pub fn admit_acid(
+    source: &str,
+    bytes: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<CAcid, Box<dyn std::error::Error>> {
+    admission::throttle(source, &env.admission)?;
+    admission::check_size(bytes.len(), &env.admission)?;
+
+    let acid = deserialize_acid(bytes, env)?;
+
+    let is_cached = match cache::is_cached(acid.id(), &env.cache) {
+        cache::CacheState::Cached => true,
+        _ => false,
+    };
+    if is_cached || mempool::contains(acid.id(), &env.mempool) {
+        debug!(
+            "Rejected acid {} from {}: already known.",
+            acid.id().display_hex(),
+            source
+        );
+        return Err(Box::new(admission::Rejection::Duplicated));
+    }
+
+    Ok(acid)
+}
+
+/// Adds `acid` to the mempool unless the admission control rejects it, and returns the result of
+/// function [`mempool::add`] .
+///
+/// [`events::Event::AcidAccepted`] is published if `acid` is added.
+///
+/// If `acid` is invalid or recorded as invalid in the RDB, or if the [`OwnershipVerifier`]
+/// refuses it, this function returns `false` without adding it. The invalid `acid` is recorded so
+/// that it is refused after the restart as well. (See also module [`rdb::invalid_acids`] .)
+///
+/// The error of the admission control is [`admission::Rejection`] , so that the caller can tell
+/// the reason to the peer by `downcast_ref` .
+///
+/// [`mempool::add`]: crate::mempool::add
+/// [`events::Event::AcidAccepted`]: crate::events::Event::AcidAccepted
+/// [`OwnershipVerifier`]: crate::ownership::OwnershipVerifier
+/// [`rdb::invalid_acids`]: crate::rdb::invalid_acids
+/// [`admission::Rejection`]: crate::admission::Rejection
+pub fn add_pending_acid(
+    acid: CAcid,
+    env: &GlobalEnvironment,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if acid.is_invalid() {
+        mark_invalid_acid(&*acid, env)?;
+        return Ok(false);
+    }
+
+    let is_invalid = {
+        let mut session = rdb::slave(&env.rdb);
+        rdb::invalid_acids::is_invalid(acid.id(), &mut session)?
+    };
+    if is_invalid {
+        debug!(
+            "Refused pending acid {}: it is recorded as invalid.",
+            acid.id().display_hex()
+        );
+        return Ok(false);
+    }
+
+    if let Some(verifier) = env.ownership.as_ref() {
+        if let Err(e) = ownership::verify(&*acid, &**verifier) {
+            record_invalid(acid.id(), &e.to_string(), env)?;
+            return Ok(false);
+        }
+    }
+
+    let metrics = admission_metrics(env);
+    if let Err(rejection) = admission::check(&metrics, &env.admission) {
+        debug!(
+            "Rejected pending acid {}: {}",
+            acid.id().display_hex(),
+            rejection
+        );
+        return Err(Box::new(rejection));
+    }
+
+    let id = *acid.id();
+    let is_added = mempool::add(acid, &env.mempool);
+    if is_added {
+        env.events.publish(events::Event::AcidAccepted(id));
+    }
+    Ok(is_added)
+}
+
+/// Records `acid` as invalid in the RDB with [`Acid::invalid_reason`] , and removes it from the
+/// mempool.
+///
+/// [`events::Event::AcidInvalidated`] is published if `acid` is recorded newly.
+///
+/// See also function [`rdb::invalid_acids::mark_invalid`] .
+///
+/// [`Acid::invalid_reason`]: crate::data_types::Acid::invalid_reason
+/// [`events::Event::AcidInvalidated`]: crate::events::Event::AcidInvalidated
+/// [`rdb::invalid_acids::mark_invalid`]: crate::rdb::invalid_acids::mark_invalid
+pub fn mark_invalid_acid(
+    acid: &dyn Acid,
+    env: &GlobalEnvironment,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reason = acid
+        .invalid_reason()
+        .map(|e| e.to_string())
+        .unwrap_or_default();
+    record_invalid(acid.id(), &reason, env)
+}
+
+fn record_invalid(
+    id: &Id,
+    reason: &str,
+    env: &GlobalEnvironment,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut session = rdb::master(&env.rdb);
+    if rdb::invalid_acids::mark_invalid(id, reason, &mut session)? {
+        debug!("Recorded acid {} as invalid: {}", id.display_hex(), reason);
+        env.events.publish(events::Event::AcidInvalidated(*id));
+    }
+    drop(session);
+
+    mempool::remove(id, &env.mempool);
+    Ok(())
+}
+
+/// Fetches the acids with `ids` from the KVS in parallel and caches them.
+///
+/// See also function [`kvs::prefetch`] .
+///
+/// [`kvs::prefetch`]: crate::kvs::prefetch
+pub fn prefetch(ids: &[Id], env: &GlobalEnvironment) -> usize {
+    kvs::prefetch(ids, &env.kvs, &env.data_types, &env.cache)
+}
+
+/// Prefetches at most `limit` parents of the acids fetched recently, and caches them.
+///
+/// The application calls this function at idle time. See also function
+/// [`kvs::prefetch_speculative`] .
+///
+/// [`kvs::prefetch_speculative`]: crate::kvs::prefetch_speculative
+pub fn prefetch_speculative(limit: usize, env: &GlobalEnvironment) -> usize {
+    kvs::prefetch_speculative(limit, &env.kvs, &env.data_types, &env.cache)
+}
+
+/// Assembles a candidate block from at most `max_count` pending acids in the RDB mempool, and
+/// returns the template for a miner or a validator to seal.
+///
+/// The acids are taken in the order of the priority, (see function
+/// [`rdb::acids::fetch_mempool_by_priority`] ,) and added while the total byte size of the
+/// intrinsic data is at most `max_bytes` . An acid is skipped if it is not found in the KVS, if it
+/// is invalid, if any balance would be less than 0, or if any parent is neither in the main chain
+/// nor in the template. A parent always precedes the children in the template.
+///
+/// This function changes nothing. See also module [`block_builder`] .
+///
+/// [`rdb::acids::fetch_mempool_by_priority`]: crate::rdb::acids::fetch_mempool_by_priority
+/// [`block_builder`]: crate::block_builder
+pub fn build_block_template(
+    max_count: u32,
+    max_bytes: usize,
+    env: &GlobalEnvironment,
+) -> Result<block_builder::Template, Box<dyn std::error::Error>> {
+    let mut session = rdb::slave(&env.rdb);
+    let ids = rdb::acids::fetch_mempool_by_priority(max_count, &mut session)?;
+    let ids = ids.as_ref();
+
+    let mut acids = Vec::with_capacity(ids.len());
+    for (_, id) in ids {
+        let acid = match cache::find(id, &env.cache) {
+            cache::CacheFindResult::Hit(acid) => Some(acid),
+            cache::CacheFindResult::Fault => None,
+            cache::CacheFindResult::Lost => fetch_acid(id, env)?,
+        };
+
+        match acid {
+            Some(acid) if !acid.is_invalid() => acids.push(acid),
+            Some(_) => debug!("Skipped pending acid {}: invalid.", id.display_hex()),
+            None => warn!("Skipped pending acid {}: not found.", id.display_hex()),
+        }
+    }
+
+    // The parents which are not in the main chain.
+    let parents: HashSet<Id> = acids
+        .iter()
+        .flat_map(|acid| (0..acid.parent_count()).filter_map(move |i| acid.parent(i)))
+        .collect();
+    let states = rdb::acids::fetch_state(parents.iter(), &mut session)?;
+    let mut pending: HashSet<Id> = parents
+        .into_iter()
+        .filter(|id| match states.get(id) {
+            Some(Some(_)) => false,
+            _ => true,
+        })
+        .collect();
+    pending.extend(acids.iter().map(|acid| *acid.id()));
+
+    let resource_ids: HashSet<ResourceId> = acids
+        .iter()
+        .flat_map(|acid| (0..acid.resource_count()).filter_map(move |i| acid.resource(i)))
+        .map(|resource| *resource.id())
+        .collect();
+    let balances = rdb::resources::fetch(resource_ids.iter(), &mut session)?;
+
+    let mut builder = block_builder::Builder::new(max_bytes, balances, pending);
+    loop {
+        // Retry the deferred acids as long as any acid is added.
+        let count = builder.count();
+        acids.retain(|acid| builder.push_acid(&**acid) == block_builder::Outcome::Deferred);
+        if acids.is_empty() || builder.count() == count {
+            break;
+        }
+    }
+
+    let template = builder.finish();
+    debug!(
+        "Built a block template of {} acids ({} bytes) out of {} pending acids.",
+        template.ids.len(),
+        template.byte_size,
+        ids.len()
+    );
+    Ok(template)
+}
+
+/// Writes the cache elements into `writer` to debug the memory usage, and returns the number of
+/// the written elements.
+///
+/// The application calls this function from its RPC or CLI. See also function [`cache::dump`] .
+///
+/// [`cache::dump`]: crate::cache::dump
+pub fn dump_cache<W>(writer: W, env: &GlobalEnvironment) -> std::io::Result<usize>
+where
+    W: std::io::Write,
+{
+    cache::dump(writer, &env.cache)
+}
+
+/// Queues `f` to run on a worker thread shared by the modules, and returns the handle to join it.
+///
+/// See also function [`workers::spawn`] .
+///
+/// [`workers::spawn`]: crate::workers::spawn
+pub fn spawn_task<F, T>(f: F, env: &GlobalEnvironment) -> workers::Task<T>
+where
+    F: 'static + FnOnce() -> T + Send,
+    T: 'static + Send,
+{
+    workers::spawn(f, &env.workers)
+}
+
+/// Writes all the KVS data of the acids in RDB table "acids" into `writer` , and returns the
+/// number of the exported rows.
+///
+/// See also function [`kvs::export`] .
+///
+/// [`kvs::export`]: crate::kvs::export
+pub fn export_kvs<W, P>(writer: W, env: &GlobalEnvironment, progress: P) -> Result<u64, Error>
+where
+    W: std::io::Write,
+    P: FnMut(u64),
+{
+    let mut session = rdb::slave(&env.rdb);
+    kvs::export(writer, &env.kvs, &mut session, progress)
+}
+
+/// Puts the data exported by [`export_kvs`] into the KVS, and returns the number of the imported
+/// rows.
+///
+/// See also function [`kvs::import`] .
+///
+/// [`export_kvs`]: self::export_kvs
+/// [`kvs::import`]: crate::kvs::import
+pub fn import_kvs<R, P>(reader: R, env: &GlobalEnvironment, progress: P) -> Result<u64, Error>
+where
+    R: std::io::Read,
+    P: FnMut(u64),
+{
+    kvs::import(reader, &env.kvs, progress)
+}
+
+/// Writes the snapshot of the chain state at the tip into `writer` , and returns the summary.
+///
+/// See also function [`snapshot::export`] .
+///
+/// [`snapshot::export`]: crate::snapshot::export
+pub fn export_snapshot<W>(writer: W, env: &GlobalEnvironment) -> Result<snapshot::Summary, Error>
+where
+    W: std::io::Write,
+{
+    let mut session = rdb::slave(&env.rdb);
+    snapshot::export(writer, &mut session)
+}
+
+/// Bootstraps the RDB from the snapshot written by [`export_snapshot`] , and returns the summary.
+///
+/// If `expected` is not `None` , fails unless the digest of the snapshot equals to it.
+/// Call [`import_kvs`] as well to fetch the acids in the snapshot.
+///
+/// See also function [`snapshot::import`] .
+///
+/// [`export_snapshot`]: self::export_snapshot
+/// [`import_kvs`]: self::import_kvs
+/// [`snapshot::import`]: crate::snapshot::import
+pub fn import_snapshot<R>(
+    reader: R,
+    expected: Option<&Id>,
+    env: &GlobalEnvironment,
+) -> Result<snapshot::Summary, Error>
+where
+    R: std::io::Read,
+{
+    let mut session = rdb::master(&env.rdb);
+    snapshot::import(reader, expected, &mut session)
+}
+
+/// Stores `acids` into the KVS and appends `chain_index` to the main chain in the RDB in a
+/// crash-recoverable way, and returns the performed mutations.
+///
+/// If `dry_run` is `true` , writes nothing and returns the mutations that would be performed.
+///
+/// Fails and writes nothing if `chain_index` conflicts with '--checkpoint', or if the
+/// [`OwnershipVerifier`] refuses any of `acids` . (The error is [`ownership::OwnershipError`]
+/// then.) The main chain is finalized up to `chain_index` in the same RDB transaction if it is a
+/// checkpoint.
+///
+/// [`events::Event::BlockConnected`] is published unless `dry_run` is `true` .
+///
+/// See also function [`storage::commit_block`] .
+///
+/// [`OwnershipVerifier`]: crate::ownership::OwnershipVerifier
+/// [`ownership::OwnershipError`]: crate::ownership::OwnershipError
+/// [`events::Event::BlockConnected`]: crate::events::Event::BlockConnected
+/// [`storage::commit_block`]: crate::storage::commit_block
+pub fn commit_block(
+    chain_index: &ChainIndex,
+    acids: &[CAcid],
+    dry_run: bool,
+    env: &GlobalEnvironment,
+) -> Result<storage::CommitPlan, Box<dyn std::error::Error>> {
+    let checkpoint = env.fork_choice.checkpoints().get(&chain_index.height());
+    if let Some(id) = checkpoint {
+        if id != chain_index.id() {
+            let msg = format!(
+                "Block {} at height {} conflicts with '--checkpoint'",
+                chain_index.id().display_hex(),
+                chain_index.height()
+            );
+            return Err(Box::from(msg));
+        }
+    }
+
+    if let Some(verifier) = env.ownership.as_ref() {
+        for acid in acids {
+            ownership::verify(&**acid, &**verifier)?;
+        }
+    }
+
+    let plan = storage::commit_block(
+        chain_index,
+        acids,
+        checkpoint.is_some(),
+        dry_run,
+        &env.storage,
+        &env.kvs,
+        &env.rdb,
+    )?;
+
+    if !dry_run {
+        env.events
+            .publish(events::Event::BlockConnected(*chain_index));
+    }
+
+    Ok(plan)
+}
+
+/// Stores `header` into RDB table "block_headers", and returns the [`Branch`] ending at `header`
+/// if the fork choice rule prefers it to the main chain, or `None` .
+///
+/// The caller is expected to revert the main chain to `fork_height` of the returned branch, (see
+/// function [`revert_block`] ,) and to commit the blocks in `side` . Nothing is returned if
+/// `header` is in the main chain, or if any ancestor of `header` is not stored yet. (Call this
+/// function again for the descendants after the missing headers arrive.)
+///
+/// See also module [`fork_choice`] .
+///
+/// [`Branch`]: crate::fork_choice::Branch
+/// [`revert_block`]: crate::revert_block
+/// [`fork_choice`]: crate::fork_choice
+pub fn add_block_header(
+    header: &rdb::block_headers::BlockHeader,
+    env: &GlobalEnvironment,
+) -> Result<Option<fork_choice::Branch>, Error> {
+    let mut session = rdb::master(&env.rdb);
+    rdb::block_headers::insert(header, &mut session)?;
+
+    let branch = fork_choice::branch(header, &mut session)?;
+    Ok(branch
+        .filter(|branch| env.fork_choice.allows(branch) && env.fork_choice.rule().prefers(branch)))
+}
+
+/// Removes the tip from the main chain, and returns it if any, or `None` if the main chain is
+/// empty.
+///
+/// The acids belonging to the reverted block are moved back to the mempool in the same RDB
+/// transaction. (See function [`rdb::revert_block`] .) Then, the cache elements of the block and
+/// the acids are invalidated, (see function [`cache::invalidate`] ,) and
+/// [`events::Event::BlockDisconnected`] is published with the ids of the acids. The caller
+/// reverting the main chain to `fork_height` of a [`Branch`] calls this function for each block
+/// above it.
+///
+/// Fails if the tip is finalized.
+///
+/// [`rdb::revert_block`]: crate::rdb::revert_block
+/// [`cache::invalidate`]: crate::cache::invalidate
+/// [`events::Event::BlockDisconnected`]: crate::events::Event::BlockDisconnected
+/// [`Branch`]: crate::fork_choice::Branch
+pub fn revert_block(env: &GlobalEnvironment) -> Result<Option<ChainIndex>, Error> {
+    let (tip, acid_ids) = {
+        let mut session = rdb::master(&env.rdb);
+        match rdb::revert_block(&mut session)? {
+            None => return Ok(None),
+            Some(reverted) => reverted,
+        }
+    };
+
+    let ids = core::iter::once(tip.id()).chain(acid_ids.iter());
+    cache::invalidate(ids, &env.cache);
+    env.events
+        .publish(events::Event::BlockDisconnected(tip, acid_ids.into()));
+
+    Ok(Some(tip))
+}
+
+/// Returns `true` if the block at `height` is in the main chain and never reverted.
+///
+/// A block is final if it is finalized, (see function [`rdb::main_chain::finalize`] ,) or if
+/// it is not higher than a checkpoint ('--checkpoint') that the main chain has reached.
+///
+/// [`rdb::main_chain::finalize`]: crate::rdb::main_chain::finalize
+pub fn is_final(height: data_types::BlockHeight, env: &GlobalEnvironment) -> Result<bool, Error> {
+    let mut session = rdb::slave(&env.rdb);
+    fork_choice::is_final(height, &env.fork_choice, &mut session)
+}
+
+/// Applies the retention policy at the current tip, and returns the plan.
+///
+/// See also function [`retention::apply`] .
+///
+/// [`retention::apply`]: crate::retention::apply
+pub fn apply_retention(
+    env: &GlobalEnvironment,
+) -> Result<retention::Plan, Box<dyn std::error::Error>> {
+    let tip_height = {
+        let mut session = rdb::slave(&env.rdb);
+        let tip = rdb::main_chain::fetch_desc(data_types::BlockHeight::MAX, 1, &mut session)?;
+        match tip.as_ref().first() {
+            None => return Ok(retention::Plan::default()),
+            Some(chain_index) => chain_index.height(),
+        }
+    };
+
+    retention::apply(tip_height, &env.retention, &env.rdb)
+}
+
+/// Deletes the acid data buried deeper than '--prune-keep-blocks' from the current tip with
+/// `delete` , and returns the result if anything is pruned.
+///
+/// `delete` takes the ids of the acids and the keys of the shared extrinsic data that nothing
+/// refers to any more. (See function [`kvs::release_extrinsic`] .)
+///
+/// This function does nothing if '--prune-keep-blocks' is not specified. The application is
+/// expected to call this function periodically; e.g. with method
+/// [`GlobalEnvironment::schedule`] .
+///
+/// See also function [`prune::apply`] .
+///
+/// [`kvs::release_extrinsic`]: crate::kvs::release_extrinsic
+/// [`GlobalEnvironment::schedule`]: crate::GlobalEnvironment::schedule
+/// [`prune::apply`]: crate::prune::apply
+pub fn prune_block_data<F>(
+    env: &GlobalEnvironment,
+    mut delete: F,
+) -> Result<Option<prune::Pruned>, Box<dyn std::error::Error>>
+where
+    F: FnMut(&[Id], &[Vec<u8>]) -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+{
+    if env.prune.keep_blocks().is_none() {
+        return Ok(None);
+    }
+
+    let tip_height = {
+        let mut session = rdb::slave(&env.rdb);
+        let tip = rdb::main_chain::fetch_desc(data_types::BlockHeight::MAX, 1, &mut session)?;
+        match tip.as_ref().first() {
+            None => return Ok(None),
+            Some(chain_index) => chain_index.height(),
+        }
+    };
+
+    let kvs_env = &env.kvs;
+    let pruned = prune::apply(tip_height, &env.prune, &env.rdb, |ids| {
+        let blobs = kvs::release_extrinsic(ids, kvs_env)?;
+        delete(ids, &blobs)
+    })?;
+    Ok(pruned)
+}
+
+/// Removes the pending acids older than '--mempool-max-age-secs' from the mempool and from RDB
+/// table "acids", and returns the number of the acids removed from the mempool.
+///
+/// This function does nothing if '--mempool-max-age-secs' is not specified. The application
+/// is expected to call this function periodically.
+///
+/// See also function [`mempool::expire`] and [`rdb::acids::purge_mempool_older_than`] .
+///
+/// [`mempool::expire`]: crate::mempool::expire
+/// [`rdb::acids::purge_mempool_older_than`]: crate::rdb::acids::purge_mempool_older_than
+pub fn expire_pending_acids(env: &GlobalEnvironment) -> Result<usize, Box<dyn std::error::Error>> {
+    let age = match env.mempool.max_age() {
+        None => return Ok(0),
+        Some(age) => age,
+    };
+
+    let expired = mempool::expire(age, &env.mempool);
+
+    let mut session = rdb::master(&env.rdb);
+    let purged = rdb::acids::purge_mempool_older_than(age, &mut session)?;
+    debug!(
+        "Purged {} pending acids older than {} seconds from the RDB.",
+        purged,
+        age.as_secs()
+    );
+
+    Ok(expired)
+}
+
+/// Creates a read-only view of the RDB pinned at the current tip.
+///
+/// See also function [`storage::pin`] .
+///
+/// [`storage::pin`]: crate::storage::pin
+pub fn pin_view(
+    env: &GlobalEnvironment,
+) -> Result<storage::PinnedView<impl '_ + rdb::Slave>, Box<dyn std::error::Error>> {
+    storage::pin(&env.storage, &env.rdb)
+}
+
+/// Returns the keys in the wallet.
+///
+/// See also function [`wallet::list`] .
+///
+/// [`wallet::list`]: crate::wallet::list
+pub fn list_keys(env: &GlobalEnvironment) -> Vec<wallet::KeyInfo> {
+    wallet::list(&env.wallet)
+}
+
+/// Generates a new key named `label` in the wallet encrypted with `passphrase` , and returns it.
+///
+/// See also function [`wallet::create`] .
+///
+/// [`wallet::create`]: crate::wallet::create
+pub fn create_key(
+    label: &str,
+    passphrase: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<wallet::KeyInfo, Error> {
+    wallet::create(label, passphrase, &env.wallet)
+}
+
+/// Stores the key generated from `seed` as `label` in the wallet encrypted with `passphrase` ,
+/// and returns it.
+///
+/// See also function [`wallet::import`] .
+///
+/// [`wallet::import`]: crate::wallet::import
+pub fn import_key(
+    label: &str,
+    seed: &[u8; 32],
+    passphrase: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<wallet::KeyInfo, Error> {
+    wallet::import(label, seed, passphrase, &env.wallet)
+}
+
+/// Restores the keys of `account` from `mnemonic` into the wallet encrypted with `passphrase` ,
+/// and returns the restored ones.
+///
+/// The keys are derived at "m/44'/`coin_type`'/`account`'/0'/index'" for index 0, 1, 2, ... until
+/// `gap_limit` consecutive owners without any resource are found. Then, the keys up to the last
+/// owner with any resource (or the first key if none) are stored as
+/// "hd-`coin_type`-`account`-index". The keys already in the wallet are skipped.
+///
+/// Fails if `coin_type` or `account` is not less than [`wallet::hd::HARDENED`] , or if the
+/// index reaches it.
+///
+/// The application should assign an `account` for each asset type.
+///
+/// See also module [`wallet::hd`] .
+///
+/// [`wallet::hd::HARDENED`]: crate::wallet::hd::HARDENED
+/// [`wallet::hd`]: crate::wallet::hd
+pub fn restore_keys(
+    mnemonic: &str,
+    mnemonic_passphrase: &str,
+    coin_type: u32,
+    account: u32,
+    gap_limit: u32,
+    passphrase: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<Vec<wallet::KeyInfo>, Error> {
+    use data_types::signature::Ed25519SecretKey;
+    use wallet::hd::{self, DerivationPath};
+
+    let seed = hd::seed_from_mnemonic(mnemonic, mnemonic_passphrase)?;
+
+    let mut keys = Vec::new();
+    let mut last_used = 0;
+    let mut gap = 0;
+    {
+        let mut session = rdb::slave(&env.rdb);
+        while gap < gap_limit.max(1) {
+            let index = keys.len() as u32;
+            let path = DerivationPath::bip44(coin_type, account, index)
+                .map_err(|e| Error::Other(Box::new(e)))?;
+            let key = hd::derive(&seed, &path)?;
+            let owner = Ed25519SecretKey::from_seed(&key)
+                .public_key()
+                .as_bytes()
+                .to_vec();
+
+            if rdb::resources::fetch_by_owner(&owner, &mut session)?.is_empty() {
+                gap += 1;
+            } else {
+                last_used = index;
+                gap = 0;
+            }
+            keys.push((key, owner));
+        }
+    }
+
+    let existing = wallet::list(&env.wallet);
+    let mut ret = Vec::new();
+    for (index, (key, owner)) in keys.iter().enumerate().take(last_used as usize + 1) {
+        if existing.iter().any(|k| &k.owner == owner) {
+            debug!("Skipped restoring key {}: already in the wallet.", index);
+            continue;
+        }
+
+        let label = format!("hd-{}-{}-{}", coin_type, account, index);
+        ret.push(wallet::import(&label, key, passphrase, &env.wallet)?);
+    }
+
+    info!("Restored {} keys from the mnemonic.", ret.len());
+    Ok(ret)
+}
+
+/// Selects the resources of `asset_type` owned by any of `owners` whose total value is at least
+/// `target` , or returns `None` if the total of all of them is less than `target` .
+///
+/// This function changes nothing. See also module [`coin_selection`] .
+///
+/// [`coin_selection`]: crate::coin_selection
+pub fn select_resources<'a, I>(
+    owners: I,
+    asset_type: &[u8],
+    target: data_types::AssetValue,
+    strategy: coin_selection::Strategy,
+    env: &GlobalEnvironment,
+) -> Result<Option<coin_selection::Selection>, Error>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut session = rdb::slave(&env.rdb);
+
+    let mut candidates = Vec::new();
+    for owner in owners {
+        let resources = rdb::resources::fetch_by_owner(owner, &mut session)?;
+        candidates.extend(
+            resources
+                .into_iter()
+                .filter(|(id, _)| id.asset_type() == asset_type),
+        );
+    }
+
+    Ok(coin_selection::select(&candidates, target, strategy))
+}
+
+/// Signs `intrinsic` with the key in the wallet whose owner is `owner` , and returns the
+/// signature.
+///
+/// See also function [`wallet::sign`] .
+///
+/// [`wallet::sign`]: crate::wallet::sign
+pub fn sign_intrinsic(
+    owner: &[u8],
+    intrinsic: &[u8],
+    passphrase: &[u8],
+    env: &GlobalEnvironment,
+) -> Result<Vec<u8>, Error> {
+    wallet::sign(owner, intrinsic, passphrase, &env.wallet)
+}
+
 /// `NotImplementedError` implements `std::error::Error` for default functions and so on.
 #[derive(Debug, Clone, Copy)]
 struct NotImplementedError;
@@ -330,4 +1795,4 @@ impl Display for NotImplementedError {
     }
 }
 
-impl Error for NotImplementedError {}
+impl std::error::Error for NotImplementedError {}
@@ -0,0 +1,258 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `listen` creates the TCP listeners, taking over the sockets passed by the service manager if
+//! any. (Socket activation of systemd.)
+//! `listen` is independent from other modules.
+//!
+//! If environment variables 'LISTEN_PID' and 'LISTEN_FDS' are set for this process, function
+//! [`bind`] returns the passed sockets instead of binding. The sockets are matched by
+//! 'LISTEN_FDNAMES' (i.e. 'FileDescriptorName=' of the socket unit) if it is set, or handed in
+//! the order otherwise. If no socket is left, [`bind`] binds the address as usual.
+//!
+//! The socket activation is available only on unix.
+//!
+//! [`bind`]: self::bind
+
+use std::io;
+use std::net::{TcpListener, ToSocketAddrs};
+
+/// The first file descriptor passed by the service manager. ('SD_LISTEN_FDS_START')
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Parses the environment variables and returns the passed file descriptors and the names.
+///
+/// Returns an empty `Vec` if `listen_pid` is not `pid` ; i.e. the variables are for another
+/// process.
+#[cfg(unix)]
+fn parse(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+    pid: u32,
+) -> Vec<(i32, Option<String>)> {
+    match listen_pid.and_then(|s| s.parse::<u32>().ok()) {
+        Some(p) if p == pid => (),
+        _ => return Vec::new(),
+    }
+
+    let count = match listen_fds.and_then(|s| s.parse::<i32>().ok()) {
+        Some(n) if 0 < n => n,
+        _ => return Vec::new(),
+    };
+
+    let mut names = listen_fdnames.map(|s| s.split(':'));
+    (0..count)
+        .map(|i| {
+            let name = names.as_mut().and_then(|it| it.next()).map(String::from);
+            (LISTEN_FDS_START + i, name)
+        })
+        .collect()
+}
+
+/// Returns the TCP listener named `name` passed by the service manager if any, or binds `addr` .
+///
+/// If the service manager does not name the sockets, `name` is ignored and the sockets are
+/// returned in the order.
+///
+/// # Error
+///
+/// Fails if the passed socket is not a listening stream socket, or if binding `addr` fails.
+///
+/// # Examples
+///
+/// ```no_run
+/// let listener = mouse::listen::bind("rpc", "127.0.0.1:8080").unwrap();
+/// ```
+pub fn bind<A: ToSocketAddrs>(name: &str, addr: A) -> io::Result<TcpListener> {
+    match imp::take(name)? {
+        Some(listener) => {
+            info!(
+                "Took over the listener '{}' from the service manager.",
+                name
+            );
+            Ok(listener)
+        }
+        None => TcpListener::bind(addr),
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::parse;
+    use std::env;
+    use std::io;
+    use std::mem;
+    use std::net::TcpListener;
+    use std::os::raw::{c_int, c_void};
+    use std::os::unix::io::FromRawFd;
+    use std::process;
+    use std::sync::Mutex;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    mod consts {
+        use std::os::raw::c_int;
+        pub const SOL_SOCKET: c_int = 1;
+        pub const SO_TYPE: c_int = 3;
+        pub const SO_ACCEPTCONN: c_int = 30;
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    mod consts {
+        use std::os::raw::c_int;
+        pub const SOL_SOCKET: c_int = 0xffff;
+        pub const SO_TYPE: c_int = 0x1008;
+        pub const SO_ACCEPTCONN: c_int = 0x0002;
+    }
+
+    use consts::*;
+    const SOCK_STREAM: c_int = 1;
+
+    extern "C" {
+        fn getsockopt(
+            sockfd: c_int,
+            level: c_int,
+            optname: c_int,
+            optval: *mut c_void,
+            optlen: *mut u32,
+        ) -> c_int;
+    }
+
+    /// The file descriptors not taken yet, or `None` before the environment variables are
+    /// parsed.
+    static PASSED: Mutex<Option<Vec<(i32, Option<String>)>>> = Mutex::new(None);
+
+    /// Parses the environment variables.
+    ///
+    /// The variables are left as they are because 'env::remove_var' is not thread safe. The
+    /// child processes ignore them anyway because 'LISTEN_PID' does not match.
+    fn load() -> Vec<(i32, Option<String>)> {
+        let listen_pid = env::var("LISTEN_PID").ok();
+        let listen_fds = env::var("LISTEN_FDS").ok();
+        let listen_fdnames = env::var("LISTEN_FDNAMES").ok();
+        parse(
+            listen_pid.as_deref(),
+            listen_fds.as_deref(),
+            listen_fdnames.as_deref(),
+            process::id(),
+        )
+    }
+
+    fn sockopt(fd: i32, optname: c_int) -> io::Result<c_int> {
+        let mut val: c_int = 0;
+        let mut len = mem::size_of::<c_int>() as u32;
+        let optval = &mut val as *mut c_int as *mut c_void;
+
+        if unsafe { getsockopt(fd, SOL_SOCKET, optname, optval, &mut len) } == 0 {
+            Ok(val)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Returns `Err` unless `fd` is a listening stream socket.
+    pub fn check(fd: i32, name: &str) -> io::Result<()> {
+        if sockopt(fd, SO_TYPE)? == SOCK_STREAM && sockopt(fd, SO_ACCEPTCONN)? != 0 {
+            Ok(())
+        } else {
+            let msg = format!(
+                "The file descriptor {} passed as '{}' is not a listening stream socket.",
+                fd, name
+            );
+            Err(io::Error::new(io::ErrorKind::InvalidInput, msg))
+        }
+    }
+
+    pub fn take(name: &str) -> io::Result<Option<TcpListener>> {
+        let mut passed = PASSED.lock().unwrap();
+        let fds = passed.get_or_insert_with(load);
+
+        let is_named = fds.iter().any(|(_, n)| n.is_some());
+        let index = if is_named {
+            match fds.iter().position(|(_, n)| n.as_deref() == Some(name)) {
+                None => return Ok(None),
+                Some(i) => i,
+            }
+        } else if fds.is_empty() {
+            return Ok(None);
+        } else {
+            0
+        };
+
+        let (fd, _) = fds.remove(index);
+        check(fd, name)?;
+        Ok(Some(unsafe { TcpListener::from_raw_fd(fd) }))
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::net::TcpListener;
+
+    pub fn take(_name: &str) -> io::Result<Option<TcpListener>> {
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_() {
+        assert_eq!(
+            Vec::<(i32, Option<String>)>::new(),
+            parse(None, None, None, 10)
+        );
+        assert_eq!(
+            Vec::<(i32, Option<String>)>::new(),
+            parse(Some("11"), Some("2"), None, 10)
+        );
+        assert_eq!(
+            Vec::<(i32, Option<String>)>::new(),
+            parse(Some("10"), Some("0"), None, 10)
+        );
+
+        assert_eq!(
+            vec![(3, None), (4, None)],
+            parse(Some("10"), Some("2"), None, 10)
+        );
+        assert_eq!(
+            vec![
+                (3, Some(String::from("rpc"))),
+                (4, Some(String::from("p2p")))
+            ],
+            parse(Some("10"), Some("2"), Some("rpc:p2p"), 10)
+        );
+    }
+
+    #[test]
+    fn check_() {
+        use std::net::{TcpStream, UdpSocket};
+        use std::os::unix::io::AsRawFd;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert_eq!(true, imp::check(listener.as_raw_fd(), "tcp").is_ok());
+
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        assert_eq!(true, imp::check(stream.as_raw_fd(), "stream").is_err());
+
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert_eq!(true, imp::check(socket.as_raw_fd(), "udp").is_err());
+    }
+}
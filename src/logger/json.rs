@@ -0,0 +1,203 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `json` formats each log record as a line of JSON object.
+//!
+//! The object has keys "timestamp" (RFC 3339 in UTC), "level", "target", "message", and the key
+//! value fields of the record.
+
+use log::kv::{self, Key, Value, Visitor};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Appends `s` to `buf` as a JSON string literal.
+fn push_str(s: &str, buf: &mut String) {
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(buf, "\\u{:04x}", c as u32);
+            }
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+/// Formats `ms` milliseconds since the UNIX epoch as RFC 3339 in UTC.
+fn format_timestamp(ms: u64) -> String {
+    let secs = ms / 1000;
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Converts the days since 1970-01-01 into the civil date.
+    // (http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        ms % 1000
+    )
+}
+
+struct FieldWriter<'a> {
+    buf: &'a mut String,
+}
+
+impl<'kvs> Visitor<'kvs> for FieldWriter<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.buf.push(',');
+        push_str(key.as_str(), self.buf);
+        self.buf.push(':');
+        push_str(&value.to_string(), self.buf);
+        Ok(())
+    }
+}
+
+/// Formats `record` as a JSON object without the trailing new line.
+pub fn format(record: &Record, timestamp_ms: u64) -> String {
+    let mut buf = String::new();
+
+    buf.push_str("{\"timestamp\":");
+    push_str(&format_timestamp(timestamp_ms), &mut buf);
+    buf.push_str(",\"level\":");
+    push_str(record.level().as_str(), &mut buf);
+    buf.push_str(",\"target\":");
+    push_str(record.target(), &mut buf);
+    buf.push_str(",\"message\":");
+    push_str(&record.args().to_string(), &mut buf);
+
+    let _ = record
+        .key_values()
+        .visit(&mut FieldWriter { buf: &mut buf });
+
+    buf.push('}');
+    buf
+}
+
+/// `JsonLogger` writes each log record to `W` as a line of JSON object.
+pub struct JsonLogger<W> {
+    level: LevelFilter,
+    writer: Mutex<W>,
+}
+
+impl<W> JsonLogger<W>
+where
+    W: 'static + Write + Send,
+{
+    /// Creates a new instance.
+    pub fn new(level: LevelFilter, writer: W) -> Self {
+        Self {
+            level,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Sets `self` as the global logger.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(self))
+    }
+}
+
+impl<W> Log for JsonLogger<W>
+where
+    W: Write + Send,
+{
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let line = format(record, now);
+
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writeln!(writer, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.writer.lock().unwrap().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn push_str_() {
+        let mut buf = String::new();
+        push_str("a\"b\\c\nd\u{1}", &mut buf);
+        assert_eq!(r#""a\"b\\c\nd\u0001""#, buf);
+    }
+
+    #[test]
+    fn format_timestamp_() {
+        assert_eq!("1970-01-01T00:00:00.000Z", format_timestamp(0));
+        assert_eq!("2000-02-29T12:34:56.789Z", format_timestamp(951827696789));
+        assert_eq!("2021-12-31T23:59:59.999Z", format_timestamp(1640995199999));
+    }
+
+    #[test]
+    fn format_() {
+        let fields = ("height", 3);
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("mouse::storage")
+            .args(format_args!("committed \"block\""))
+            .key_values(&fields)
+            .build();
+
+        assert_eq!(
+            concat!(
+                r#"{"timestamp":"1970-01-01T00:00:01.000Z","level":"INFO","#,
+                r#""target":"mouse::storage","message":"committed \"block\"","height":"3"}"#
+            ),
+            format(&record, 1000)
+        );
+    }
+}
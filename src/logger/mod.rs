@@ -14,8 +14,14 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-#[cfg(feature = "term_logger")]
+pub mod json;
+#[cfg(all(unix, feature = "syslog_logger"))]
+mod syslog_logger;
+#[cfg(all(feature = "term_logger", not(all(unix, feature = "syslog_logger"))))]
 mod term_logger;
 
-#[cfg(feature = "term_logger")]
+// "syslog_logger" takes precedence over "term_logger" because the latter is a default feature.
+#[cfg(all(unix, feature = "syslog_logger"))]
+pub use syslog_logger::Environment;
+#[cfg(all(feature = "term_logger", not(all(unix, feature = "syslog_logger"))))]
 pub use term_logger::Environment;
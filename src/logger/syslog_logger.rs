@@ -0,0 +1,210 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `syslog_logger` sends each log record to the local syslog socket in the RFC 3164 format.
+
+use crate::{Config, ModuleEnvironment};
+use clap::{App, Arg};
+use core::result::Result;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::os::unix::net::UnixDatagram;
+use std::process;
+
+/// The path to the local syslog socket.
+const SYSLOG_SOCKET: &'static str = "/dev/log";
+
+/// The name and the code of each facility.
+const FACILITIES: &[(&'static str, u8)] = &[
+    ("kern", 0),
+    ("user", 1),
+    ("mail", 2),
+    ("daemon", 3),
+    ("auth", 4),
+    ("syslog", 5),
+    ("lpr", 6),
+    ("news", 7),
+    ("uucp", 8),
+    ("cron", 9),
+    ("authpriv", 10),
+    ("ftp", 11),
+    ("local0", 16),
+    ("local1", 17),
+    ("local2", 18),
+    ("local3", 19),
+    ("local4", 20),
+    ("local5", 21),
+    ("local6", 22),
+    ("local7", 23),
+];
+
+/// `Environment` implements `ModuleEnvironment` .
+///
+/// The ident of the messages is [`Config::name`] .
+///
+/// [`Config::name`]: crate::Config::name
+pub struct Environment {
+    level: LevelFilter,
+    facility: u8,
+    ident: String,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Warn,
+            facility: 3,
+            ident: String::from(env!("CARGO_PKG_NAME")),
+        }
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        let facilities: Vec<&'static str> = FACILITIES.iter().map(|(name, _)| *name).collect();
+
+        app.args(&[
+            Arg::with_name("log_level")
+                .possible_values(&["TRACE", "DEBUG", "INFO", "WARN", "ERROR"])
+                .long("log-level")
+                .default_value("WARN")
+                .takes_value(true),
+            Arg::with_name("log_facility")
+                .help("The syslog facility to send the logs as.")
+                .possible_values(&facilities)
+                .long("log-facility")
+                .default_value("daemon")
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        match config.args().value_of("log_level").unwrap() {
+            "TRACE" => self.level = LevelFilter::Trace,
+            "DEBUG" => self.level = LevelFilter::Debug,
+            "INFO" => self.level = LevelFilter::Info,
+            "WARN" => self.level = LevelFilter::Warn,
+            "ERROR" => self.level = LevelFilter::Error,
+            arg => {
+                let msg = format!("Bad parameter for '--log-level': {}", arg);
+                return Err(crate::Error::Config(msg));
+            }
+        }
+
+        let facility = config.args().value_of("log_facility").unwrap();
+        match FACILITIES.iter().find(|(name, _)| *name == facility) {
+            Some((_, code)) => self.facility = *code,
+            None => {
+                let msg = format!("Bad parameter for '--log-facility': {}", facility);
+                return Err(crate::Error::Config(msg));
+            }
+        }
+
+        self.ident = String::from(config.name());
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        let socket = UnixDatagram::unbound()
+            .and_then(|s| s.connect(SYSLOG_SOCKET).map(|_| s))
+            .map_err(|e| {
+                let msg = format!("Failed to open log '{}': {}", SYSLOG_SOCKET, e);
+                crate::Error::Other(Box::from(msg))
+            })?;
+
+        let logger = SyslogLogger {
+            level: self.level,
+            facility: self.facility,
+            ident: self.ident.clone(),
+            socket,
+        };
+
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(logger)).map_err(|e| {
+            let msg = format!("Failed to open log: {}", e);
+            crate::Error::Other(Box::from(msg))
+        })
+    }
+}
+
+/// Returns the syslog severity corresponding to `level` .
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Formats `record` as a syslog message without the timestamp and the hostname.
+/// (The local syslog daemon adds them.)
+fn format(record: &Record, facility: u8, ident: &str, pid: u32) -> String {
+    let priority = facility as u32 * 8 + severity(record.level()) as u32;
+    format!("<{}>{}[{}]: {}", priority, ident, pid, record.args())
+}
+
+struct SyslogLogger {
+    level: LevelFilter,
+    facility: u8,
+    ident: String,
+    socket: UnixDatagram,
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let msg = format(record, self.facility, &self.ident, process::id());
+        // Nowhere to report the failure.
+        let _ = self.socket.send(msg.as_bytes());
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_() {
+        let record = Record::builder()
+            .level(Level::Warn)
+            .args(format_args!("disk is almost full"))
+            .build();
+
+        // daemon (3) * 8 + warning (4)
+        assert_eq!(
+            "<28>mouse[42]: disk is almost full",
+            format(&record, 3, "mouse", 42)
+        );
+
+        let record = Record::builder()
+            .level(Level::Trace)
+            .args(format_args!("foo"))
+            .build();
+
+        // local7 (23) * 8 + debug (7)
+        assert_eq!("<191>node[1]: foo", format(&record, 23, "node", 1));
+    }
+}
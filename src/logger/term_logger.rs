@@ -14,38 +14,47 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
+use super::json::JsonLogger;
 use crate::{Config, ModuleEnvironment};
 use clap::{App, Arg};
 use core::result::Result;
 use log::LevelFilter;
 use simplelog::{TermLogger, TerminalMode};
-use std::error::Error;
+use std::io;
 
 /// `Environment` implements `ModuleEnvironment` .
 pub struct Environment {
     level: LevelFilter,
+    is_json: bool,
 }
 
 impl Default for Environment {
     fn default() -> Self {
         Self {
             level: LevelFilter::Warn,
+            is_json: false,
         }
     }
 }
 
 impl ModuleEnvironment for Environment {
     fn args(app: App<'static, 'static>) -> App<'static, 'static> {
-        app.arg(
+        app.args(&[
             Arg::with_name("log_level")
                 .possible_values(&["TRACE", "DEBUG", "INFO", "WARN", "ERROR"])
                 .long("log-level")
                 .default_value("WARN")
                 .takes_value(true),
-        )
+            Arg::with_name("log_format")
+                .help("'json' writes each log as a line of JSON object.")
+                .possible_values(&["text", "json"])
+                .long("log-format")
+                .default_value("text")
+                .takes_value(true),
+        ])
     }
 
-    unsafe fn check(&mut self, config: &Config) -> Result<(), Box<dyn Error>> {
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
         match config.args().value_of("log_level").unwrap() {
             "TRACE" => self.level = LevelFilter::Trace,
             "DEBUG" => self.level = LevelFilter::Debug,
@@ -54,17 +63,27 @@ impl ModuleEnvironment for Environment {
             "Error" => self.level = LevelFilter::Error,
             arg => {
                 let msg = format!("Bad parameter for '--log-level': {}", arg);
-                return Err(Box::from(msg));
+                return Err(crate::Error::Config(msg));
             }
         }
 
+        self.is_json = config.args().value_of("log_format") == Some("json");
+
         Ok(())
     }
 
-    unsafe fn init(&mut self) -> Result<(), Box<dyn Error>> {
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        if self.is_json {
+            let logger = JsonLogger::new(self.level, io::stdout());
+            return logger.init().map_err(|e| {
+                let msg = format!("Failed to open log: {}", e);
+                crate::Error::Other(Box::from(msg))
+            });
+        }
+
         TermLogger::init(self.level, Default::default(), TerminalMode::Stdout).map_err(|e| {
             let msg = format!("Failed to open log: {}", e);
-            Box::from(msg)
+            crate::Error::Other(Box::from(msg))
         })
     }
 }
@@ -0,0 +1,552 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `mempool` holds the pending acids (acids not mined yet) in memory.
+//! `mempool` depends on module `data_types` and `rdb` .
+
+use crate::data_types::{Acid, CAcid, CryptoHash, Id, ResourceId};
+use crate::rdb::{self, Master};
+use crate::{Config, ModuleEnvironment};
+use clap::{App, Arg};
+use core::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MAX_BYTES: &'static str = "64MiB";
+
+/// `Priority` is the order of the pending acids; the greater, the earlier.
+pub type Priority = i64;
+
+/// Function type to calculate the [`Priority`] of the pending acid.
+///
+/// [`Priority`]: self::Priority
+pub type Prioritizer = fn(&dyn Acid) -> Priority;
+
+fn default_prioritizer(_: &dyn Acid) -> Priority {
+    0
+}
+
+/// Returns the byte size that `acid` is regarded to consume in the mempool.
+fn byte_size(acid: &dyn Acid) -> usize {
+    acid.intrinsic().len() + acid.extrinsic().len()
+}
+
+struct Element {
+    acid: CAcid,
+    priority: Priority,
+    seq: u64,
+    byte_size: usize,
+    received_at: Instant,
+}
+
+/// The key to order the pending acids.
+///
+/// Higher priority comes first, and the acid added earlier comes first if the priorities are same.
+type OrderKey = (Reverse<Priority>, u64, Id);
+
+#[derive(Default)]
+struct Pool {
+    elements: HashMap<Id, Element>,
+    order: BTreeSet<OrderKey>,
+    byte_size: usize,
+    next_seq: u64,
+}
+
+impl Pool {
+    pub fn insert(&mut self, acid: CAcid, priority: Priority) -> bool {
+        let id = *acid.id();
+        if self.elements.contains_key(&id) {
+            return false;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        let byte_size = byte_size(&*acid);
+        self.byte_size += byte_size;
+        self.order.insert((Reverse(priority), seq, id));
+
+        let element = Element {
+            acid,
+            priority,
+            seq,
+            byte_size,
+            received_at: Instant::now(),
+        };
+        self.elements.insert(id, element);
+
+        true
+    }
+
+    pub fn remove(&mut self, id: &Id) -> Option<CAcid> {
+        let element = self.elements.remove(id)?;
+        self.order
+            .remove(&(Reverse(element.priority), element.seq, *id));
+        self.byte_size -= element.byte_size;
+
+        Some(element.acid)
+    }
+
+    /// Removes the lowest priority element and returns the id if any, or `None` .
+    pub fn pop_lowest(&mut self) -> Option<Id> {
+        let id = match self.order.iter().next_back() {
+            None => return None,
+            Some((_, _, id)) => *id,
+        };
+
+        self.remove(&id);
+        Some(id)
+    }
+
+    /// Removes the elements added `age` or longer ago and returns the ids.
+    pub fn expire(&mut self, age: Duration) -> Vec<Id> {
+        let expired: Vec<Id> = self
+            .elements
+            .iter()
+            .filter(|(_, element)| age <= element.received_at.elapsed())
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired.iter() {
+            self.remove(id);
+        }
+        expired
+    }
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --mempool-max-bytes
+/// - --mempool-max-age-secs
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --mempool-max-bytes: 64MiB (= 67108864 bytes)
+/// - --mempool-max-age-secs: (not specified; i.e. the pending acids never expire)
+pub struct Environment {
+    max_bytes: usize,
+    max_age: Option<Duration>,
+    prioritizer: Prioritizer,
+    pool: Mutex<Pool>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            max_bytes: crate::byte_size::parse(DEFAULT_MAX_BYTES).unwrap(),
+            max_age: None,
+            prioritizer: default_prioritizer,
+            pool: Default::default(),
+        }
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.args(&[
+            Arg::with_name("mempool_max_bytes")
+                .help(
+                    "The max byte size of the pending acids.
+The lowest priority acid is evicted when the total size exceeds this value.
+Units KB, MB, GB, TB, KiB, MiB, GiB and TiB are accepted. (e.g. '64MB', '1GiB')",
+                )
+                .long("--mempool-max-bytes")
+                .default_value(DEFAULT_MAX_BYTES)
+                .takes_value(true),
+            Arg::with_name("mempool_max_age_secs")
+                .help(
+                    "The pending acids older than this seconds are removed by the expiration.
+(The pending acids never expire by default.)",
+                )
+                .long("--mempool-max-age-secs")
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let max_bytes = config.args().value_of("mempool_max_bytes").unwrap();
+        self.max_bytes = crate::byte_size::parse(max_bytes).map_err(|e| {
+            let msg = format!("Failed to parse '--mempool-max-bytes': {}", e);
+            crate::Error::Config(msg)
+        })?;
+
+        if let Some(max_age) = config.args().value_of("mempool_max_age_secs") {
+            let max_age = max_age.parse().map_err(|e| {
+                let msg = format!("Failed to parse '--mempool-max-age-secs': {}", e);
+                crate::Error::Config(msg)
+            })?;
+            self.max_age = Some(Duration::from_secs(max_age));
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Registor `prioritizer` to `self` .
+    ///
+    /// The default prioritizer always returns 0, i.e. the pending acids are ordered by the time
+    /// when they are added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::mempool::{Environment, Prioritizer};
+    ///
+    /// let prioritizer: Prioritizer = |acid| acid.intrinsic().len() as i64;
+    ///
+    /// let mut env = Environment::default();
+    /// env.set_prioritizer(prioritizer);
+    /// ```
+    pub fn set_prioritizer(&mut self, prioritizer: Prioritizer) {
+        self.prioritizer = prioritizer;
+    }
+
+    /// Returns the max age of the pending acids if '--mempool-max-age-secs' is specified, or
+    /// `None` .
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+}
+
+/// Adds `acid` to the mempool if not added yet, and returns `true` if `acid` is added newly and
+/// is still in the mempool; otherwise returns `false` .
+///
+/// If the total byte size exceeds '--mempool-max-bytes', the lowest priority acids are evicted.
+/// (`acid` itself can be evicted.)
+pub fn add(acid: CAcid, env: &Environment) -> bool {
+    let id = *acid.id();
+    let priority = (env.prioritizer)(&*acid);
+
+    let mut pool = env.pool.lock().unwrap();
+    if !pool.insert(acid, priority) {
+        return false;
+    }
+
+    let mut is_evicted = false;
+    while env.max_bytes < pool.byte_size {
+        match pool.pop_lowest() {
+            None => break,
+            Some(evicted) => {
+                debug!(
+                    "Evicted pending acid {} from mempool.",
+                    evicted.display_hex()
+                );
+                is_evicted |= evicted == id;
+            }
+        }
+    }
+
+    !is_evicted
+}
+
+/// Removes the acid with `id` from the mempool and returns it if any, or `None` .
+pub fn remove(id: &Id, env: &Environment) -> Option<CAcid> {
+    let mut pool = env.pool.lock().unwrap();
+    pool.remove(id)
+}
+
+/// Removes the acids added to the mempool `age` or longer ago, and returns the number of the
+/// removed acids.
+///
+/// See also function [`rdb::acids::purge_mempool_older_than`] to expire RDB table "acids".
+///
+/// [`rdb::acids::purge_mempool_older_than`]: crate::rdb::acids::purge_mempool_older_than
+pub fn expire(age: Duration, env: &Environment) -> usize {
+    let mut pool = env.pool.lock().unwrap();
+    let expired = pool.expire(age);
+
+    for id in expired.iter() {
+        debug!("Expired pending acid {} from mempool.", id.display_hex());
+    }
+    expired.len()
+}
+
+/// Returns `true` if the acid with `id` is in the mempool, or `false` .
+pub fn contains(id: &Id, env: &Environment) -> bool {
+    let pool = env.pool.lock().unwrap();
+    pool.elements.contains_key(id)
+}
+
+/// Returns the number of the pending acids.
+pub fn len(env: &Environment) -> usize {
+    let pool = env.pool.lock().unwrap();
+    pool.elements.len()
+}
+
+/// Returns the total byte size of the pending acids.
+pub fn using_byte_size(env: &Environment) -> usize {
+    let pool = env.pool.lock().unwrap();
+    pool.byte_size
+}
+
+/// Returns at most `limit` pending acids in order of the priority.
+///
+/// Higher priority acid comes first, and the acid added earlier comes first if the priorities
+/// are same.
+pub fn pending(limit: usize, env: &Environment) -> Vec<CAcid> {
+    let pool = env.pool.lock().unwrap();
+    pool.order
+        .iter()
+        .take(limit)
+        .map(|(_, _, id)| pool.elements[id].acid.clone())
+        .collect()
+}
+
+/// Union-find over `0..len` .
+///
+/// The root of each set is always the smallest index in the set.
+struct DisjointSet(Vec<usize>);
+
+impl DisjointSet {
+    pub fn new(len: usize) -> Self {
+        Self((0..len).collect())
+    }
+
+    pub fn root(&mut self, mut i: usize) -> usize {
+        while self.0[i] != i {
+            // Path halving
+            self.0[i] = self.0[self.0[i]];
+            i = self.0[i];
+        }
+        i
+    }
+
+    pub fn union(&mut self, a: usize, b: usize) {
+        let a = self.root(a);
+        let b = self.root(b);
+        if a < b {
+            self.0[b] = a;
+        } else if b < a {
+            self.0[a] = b;
+        }
+    }
+}
+
+/// Partitions `0..resource_ids.len()` into the groups so that the elements sharing some
+/// `ResourceId` belong to the same group.
+///
+/// Each group is sorted in ascending order, and the groups are sorted by the first element.
+fn conflict_group_indices(resource_ids: &[Vec<ResourceId>]) -> Vec<Vec<usize>> {
+    let mut set = DisjointSet::new(resource_ids.len());
+
+    // The first index using each ResourceId.
+    let mut users: HashMap<&ResourceId, usize> = HashMap::new();
+    for (i, ids) in resource_ids.iter().enumerate() {
+        for id in ids {
+            match users.entry(id) {
+                Entry::Occupied(e) => set.union(*e.get(), i),
+                Entry::Vacant(e) => {
+                    e.insert(i);
+                }
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_positions: HashMap<usize, usize> = HashMap::new();
+    for i in 0..resource_ids.len() {
+        let root = set.root(i);
+        let position = *group_positions.entry(root).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[position].push(i);
+    }
+
+    groups
+}
+
+/// Takes at most `limit` pending acids in order of the priority (as [`pending`] does,) partitions
+/// them into the conflict groups, and returns the groups.
+///
+/// Acids sharing some [`ResourceId`] belong to the same group (transitively,) so acids in
+/// different groups touch disjoint resources, and the groups can be validated and applied in
+/// parallel.
+///
+/// Acids in each group are ordered by the priority, and the groups are ordered by the priority of
+/// the first acid.
+///
+/// [`pending`]: self::pending
+/// [`ResourceId`]: crate::data_types::ResourceId
+pub fn conflict_groups(limit: usize, env: &Environment) -> Vec<Vec<CAcid>> {
+    let acids = pending(limit, env);
+
+    let resource_ids: Vec<Vec<ResourceId>> = acids
+        .iter()
+        .map(|acid| {
+            (0..acid.resource_count())
+                .map(|i| *acid.resource(i).unwrap().id())
+                .collect()
+        })
+        .collect();
+
+    conflict_group_indices(&resource_ids)
+        .iter()
+        .map(|group| group.iter().map(|&i| acids[i].clone()).collect())
+        .collect()
+}
+
+/// Synchronizes the mempool with RDB table "acids", and returns the number of acids removed from
+/// the mempool.
+///
+/// - The pending acids that are not stored in RDB table "acids" yet are stored as in mempool.
+/// - The pending acids that belong to a Block in the main chain are removed from the mempool.
+///
+/// See also module [`rdb::acids`] .
+///
+/// [`rdb::acids`]: crate::rdb::acids
+pub fn sync<S>(env: &Environment, session: &mut S) -> Result<usize, Box<dyn Error>>
+where
+    S: Master,
+{
+    let mut pool = env.pool.lock().unwrap();
+
+    let ids: Vec<Id> = pool.elements.keys().cloned().collect();
+    let states = rdb::acids::fetch_state(ids.iter(), session)?;
+
+    let unknowns = ids.iter().filter(|id| !states.contains_key(*id));
+    rdb::acids::accept_to_mempool(unknowns, session)?;
+
+    let mut ret = 0;
+    for (id, state) in states.iter() {
+        if state.is_some() {
+            pool.remove(id);
+            ret += 1;
+        }
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub::Blob;
+
+    fn blob(i: u8) -> CAcid {
+        let bytes: &[u8] = &[i];
+        CAcid::from(Blob::from(bytes))
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let env = Environment::default();
+
+        assert_eq!(true, add(blob(1), &env));
+        assert_eq!(false, add(blob(1), &env));
+        assert_eq!(true, add(blob(2), &env));
+        assert_eq!(2, len(&env));
+
+        assert_eq!(true, remove(blob(1).id(), &env).is_some());
+        assert_eq!(false, remove(blob(1).id(), &env).is_some());
+        assert_eq!(false, contains(blob(1).id(), &env));
+        assert_eq!(true, contains(blob(2).id(), &env));
+        assert_eq!(byte_size(&*blob(2)), using_byte_size(&env));
+    }
+
+    #[test]
+    fn expire_() {
+        let env = Environment::default();
+        add(blob(1), &env);
+        add(blob(2), &env);
+
+        assert_eq!(0, expire(Duration::from_secs(3600), &env));
+        assert_eq!(2, len(&env));
+
+        assert_eq!(2, expire(Duration::from_secs(0), &env));
+        assert_eq!(0, len(&env));
+        assert_eq!(0, using_byte_size(&env));
+    }
+
+    #[test]
+    fn order() {
+        let mut env = Environment::default();
+        env.set_prioritizer(|acid| acid.intrinsic().last().cloned().unwrap() as Priority);
+
+        for i in &[2, 1, 3] {
+            add(blob(*i), &env);
+        }
+
+        let ids: Vec<Id> = pending(10, &env).iter().map(|acid| *acid.id()).collect();
+        assert_eq!(vec![*blob(3).id(), *blob(2).id(), *blob(1).id()], ids);
+
+        let ids: Vec<Id> = pending(1, &env).iter().map(|acid| *acid.id()).collect();
+        assert_eq!(vec![*blob(3).id()], ids);
+    }
+
+    #[test]
+    fn partition_conflict_groups() {
+        let resource_id = |owner: u8| unsafe { ResourceId::new(&[owner], &[]) };
+
+        let resource_ids = vec![
+            vec![resource_id(1)],
+            vec![resource_id(2)],
+            vec![],
+            vec![resource_id(3), resource_id(2)],
+            vec![resource_id(3)],
+            vec![resource_id(4), resource_id(1)],
+        ];
+
+        let groups = conflict_group_indices(&resource_ids);
+        assert_eq!(vec![vec![0, 5], vec![1, 3, 4], vec![2]], groups);
+    }
+
+    #[test]
+    fn conflict_groups_without_resources() {
+        let env = Environment::default();
+        for i in 0..3 {
+            add(blob(i), &env);
+        }
+
+        // Blob touches no resource.
+        let groups = conflict_groups(10, &env);
+        assert_eq!(3, groups.len());
+        assert_eq!(true, groups.iter().all(|group| group.len() == 1));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut env = Environment::default();
+        env.set_prioritizer(|acid| acid.intrinsic().last().cloned().unwrap() as Priority);
+        env.max_bytes = byte_size(&*blob(0)) * 2;
+
+        assert_eq!(true, add(blob(2), &env));
+        assert_eq!(true, add(blob(3), &env));
+
+        // The lowest priority acid is evicted.
+        assert_eq!(false, add(blob(1), &env));
+        assert_eq!(true, add(blob(4), &env));
+
+        assert_eq!(2, len(&env));
+        assert_eq!(false, contains(blob(2).id(), &env));
+        assert_eq!(true, contains(blob(3).id(), &env));
+        assert_eq!(true, contains(blob(4).id(), &env));
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `ownership` verifies that an acid may consume the resources; e.g. by the signature or the
+//! script of the owner.
+//! `ownership` depends on module `data_types` .
+//!
+//! The owner is opaque bytes for `Mouse` . [`OwnershipVerifier`] set by method
+//! [`GlobalEnvironment::set_ownership_verifier`] gives it the meaning, and is called for each
+//! resource that an acid consumes (i.e. whose value is negative) with [`Acid::witness`] . Nothing
+//! is verified if no verifier is set.
+//!
+//! [`OwnershipVerifier`]: self::OwnershipVerifier
+//! [`GlobalEnvironment::set_ownership_verifier`]: crate::GlobalEnvironment::set_ownership_verifier
+//! [`Acid::witness`]: crate::data_types::Acid::witness
+
+use crate::data_types::signature::{Ed25519PublicKey, Verifier};
+use crate::data_types::{Acid, ResourceId};
+use std::error::Error;
+use std::fmt;
+
+/// `OwnershipVerifier` is the spending condition of the resources.
+pub trait OwnershipVerifier: Send + Sync {
+    /// Returns `Ok(())` if `witness` proves that the acid with `intrinsic` data may consume the
+    /// resource of `owner` , or the reason why not.
+    ///
+    /// `witness` is empty if [`Acid::witness`] returns `None` .
+    ///
+    /// [`Acid::witness`]: crate::data_types::Acid::witness
+    fn verify(&self, owner: &[u8], intrinsic: &[u8], witness: &[u8]) -> Result<(), String>;
+}
+
+/// `Ed25519Owner` regards the owner as an Ed25519 public key, and the witness as the signature of
+/// the intrinsic data. (See also module [`wallet`] .)
+///
+/// [`wallet`]: crate::wallet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ed25519Owner;
+
+impl OwnershipVerifier for Ed25519Owner {
+    fn verify(&self, owner: &[u8], intrinsic: &[u8], witness: &[u8]) -> Result<(), String> {
+        let public = Ed25519PublicKey::from_bytes(owner)
+            .ok_or_else(|| String::from("The owner is not an Ed25519 public key"))?;
+
+        if public.verify(intrinsic, witness) {
+            Ok(())
+        } else {
+            Err(String::from("Bad signature"))
+        }
+    }
+}
+
+/// `OwnershipError` is the error that [`OwnershipVerifier`] refuses a resource.
+///
+/// [`OwnershipVerifier`]: self::OwnershipVerifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnershipError {
+    resource_id: ResourceId,
+    reason: String,
+}
+
+impl OwnershipError {
+    /// Provides a reference to the refused [`ResourceId`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    pub fn resource_id(&self) -> &ResourceId {
+        &self.resource_id
+    }
+
+    /// Provides a reference to the reason that [`OwnershipVerifier`] returned.
+    ///
+    /// [`OwnershipVerifier`]: self::OwnershipVerifier
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl fmt::Display for OwnershipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to verify the ownership of {}: {}",
+            self.resource_id.owner_base58check(),
+            self.reason
+        )
+    }
+}
+
+impl Error for OwnershipError {}
+
+/// Calls `verifier` for each resource that `acid` consumes, and returns the first error if any.
+pub fn verify(acid: &dyn Acid, verifier: &dyn OwnershipVerifier) -> Result<(), OwnershipError> {
+    let intrinsic = acid.intrinsic();
+
+    for i in 0..acid.resource_count() {
+        let resource = match acid.resource(i) {
+            Some(resource) if resource.value() < 0 => resource,
+            _ => continue,
+        };
+
+        let witness = acid.witness(i).unwrap_or_default();
+        if let Err(reason) = verifier.verify(resource.owner(), &intrinsic, &witness) {
+            return Err(OwnershipError {
+                resource_id: *resource.id(),
+                reason,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::signature::{Ed25519SecretKey, Signer};
+    use crate::data_types::{CryptoHash, Id, Resource};
+    use std::any::TypeId;
+    use std::borrow::Cow;
+
+    struct Spend {
+        id: Id,
+        resources: Vec<Resource>,
+        witnesses: Vec<Vec<u8>>,
+    }
+
+    impl Acid for Spend {
+        fn id(&self) -> &Id {
+            &self.id
+        }
+
+        fn intrinsic(&self) -> Cow<[u8]> {
+            Cow::Borrowed(b"intrinsic")
+        }
+
+        fn extrinsic(&self) -> Cow<[u8]> {
+            Cow::default()
+        }
+
+        fn parent_count(&self) -> usize {
+            0
+        }
+
+        fn parent(&self, _: usize) -> Option<Id> {
+            None
+        }
+
+        fn resource_count(&self) -> usize {
+            self.resources.len()
+        }
+
+        fn resource(&self, index: usize) -> Option<Resource> {
+            self.resources.get(index).copied()
+        }
+
+        fn witness(&self, index: usize) -> Option<Cow<[u8]>> {
+            self.witnesses.get(index).map(|w| Cow::Borrowed(&w[..]))
+        }
+
+        fn is_traceable(&self) -> bool {
+            true
+        }
+
+        fn set_traceable(&self) -> bool {
+            false
+        }
+
+        fn is_invalid(&self) -> bool {
+            false
+        }
+
+        fn invalid_reason(&self) -> Option<&dyn Error> {
+            None
+        }
+
+        unsafe fn merge(&self, _other: &dyn Acid) -> bool {
+            false
+        }
+
+        fn type_id(&self) -> TypeId {
+            TypeId::of::<Self>()
+        }
+    }
+
+    fn resource(owner: &[u8], value: i64) -> Resource {
+        let id = unsafe { ResourceId::new(owner, b"asset") };
+        Resource::new(&id, value as _)
+    }
+
+    #[test]
+    fn ed25519() {
+        let secret = Ed25519SecretKey::from_seed(&[1; 32]);
+        let owner = secret.public_key().as_bytes().to_vec();
+        let signature = secret.sign(b"intrinsic");
+
+        let mut acid = Spend {
+            id: Id::zeroed(),
+            resources: vec![resource(&owner, -3), resource(&[9; 32], 3)],
+            witnesses: vec![signature.clone()],
+        };
+        assert_eq!(Ok(()), verify(&acid, &Ed25519Owner));
+
+        // No witness.
+        acid.witnesses.clear();
+        let err = verify(&acid, &Ed25519Owner).unwrap_err();
+        assert_eq!(&owner[..], err.resource_id().owner());
+
+        // Bad signature.
+        let mut forged = signature;
+        forged[0] ^= 1;
+        acid.witnesses.push(forged);
+        assert_eq!(true, verify(&acid, &Ed25519Owner).is_err());
+
+        // The owner is not a public key.
+        acid.resources[0] = resource(&[1, 2, 3], -3);
+        assert_eq!(true, verify(&acid, &Ed25519Owner).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `profile` measures the elapsed time of each phase and dumps it in the folded stack format,
+//! which flamegraph tools (e.g. 'flamegraph.pl' or 'inferno-flamegraph') accept.
+//! `profile` is independent from other modules.
+//!
+//! The measurement is enabled only if feature "profiling" is specified; otherwise, [`scope`] does
+//! nothing and [`dump_folded`] writes nothing.
+//!
+//! Each thread has a stack of the [`Scope`] s. When the outermost [`Scope`] (e.g. the one for
+//! each block) is dropped, the timings of the thread are aggregated into the global table.
+//! Each thread logs the summary of the outermost phases at the debug level at most once per
+//! [`LOG_INTERVAL`] , because some phases (e.g. "cache_insert") are too frequent to log each.
+//!
+//! [`scope`]: self::scope
+//! [`dump_folded`]: self::dump_folded
+//! [`Scope`]: self::Scope
+//! [`LOG_INTERVAL`]: self::LOG_INTERVAL
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// The minimum interval for each thread to log the summary.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Starts to measure phase `name` until the returned value is dropped.
+///
+/// If another `Scope` is alive on the current thread, phase `name` is regarded as the child of
+/// the innermost one.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::profile;
+///
+/// {
+///     let _block = profile::scope("commit_block");
+///     {
+///         let _kvs = profile::scope("kvs_write");
+///         // Write to the KVS.
+///     }
+/// }
+///
+/// let mut folded = Vec::new();
+/// profile::dump_folded(&mut folded).unwrap();
+///
+/// if cfg!(feature = "profiling") {
+///     let folded = String::from_utf8(folded).unwrap();
+///     assert!(folded.contains("commit_block;kvs_write "));
+/// }
+/// ```
+#[inline]
+pub fn scope(name: &'static str) -> Scope {
+    Scope::new(name)
+}
+
+/// Writes the aggregated timings to `w` in the folded stack format.
+///
+/// Each line is the semicolon separated phase names followed by a space and the self time in
+/// microseconds; i.e. the time spent in the phase excluding the child phases.
+///
+/// Only the timings of the finished outermost [`Scope`] s are written.
+///
+/// [`Scope`]: self::Scope
+pub fn dump_folded<W: Write>(w: &mut W) -> io::Result<()> {
+    imp::dump_folded(w)
+}
+
+/// Discards the aggregated timings.
+pub fn reset() {
+    imp::reset()
+}
+
+/// `Scope` measures the elapsed time of a phase while it is alive.
+///
+/// See function [`scope`] for details.
+///
+/// [`scope`]: self::scope
+#[must_use = "The phase finishes when `Scope` is dropped."]
+pub struct Scope {
+    _priv: (),
+}
+
+impl Scope {
+    fn new(name: &'static str) -> Self {
+        imp::enter(name);
+        Self { _priv: () }
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        imp::leave();
+    }
+}
+
+#[cfg(feature = "profiling")]
+mod imp {
+    use super::LOG_INTERVAL;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::io::{self, Write};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    struct Frame {
+        name: &'static str,
+        start: Instant,
+        children: Duration,
+    }
+
+    #[derive(Default)]
+    struct ThreadProfile {
+        stack: Vec<Frame>,
+        /// Self time of each folded stack since the outermost frame started.
+        folded: HashMap<String, Duration>,
+        /// The count and the total time of each outermost phase since `logged` .
+        summary: HashMap<&'static str, (usize, Duration)>,
+        /// When the summary is logged last, or `None` before the first outermost frame finishes.
+        logged: Option<Instant>,
+    }
+
+    thread_local! {
+        static PROFILE: RefCell<ThreadProfile> = RefCell::new(ThreadProfile::default());
+    }
+
+    /// The aggregated self time of each folded stack of all the threads, or `None` before
+    /// anything is aggregated.
+    static AGGREGATED: Mutex<Option<HashMap<String, Duration>>> = Mutex::new(None);
+
+    pub fn enter(name: &'static str) {
+        PROFILE.with(|profile| {
+            profile.borrow_mut().stack.push(Frame {
+                name,
+                start: Instant::now(),
+                children: Duration::default(),
+            });
+        });
+    }
+
+    pub fn leave() {
+        PROFILE.with(|profile| {
+            let mut profile = profile.borrow_mut();
+
+            let key = profile
+                .stack
+                .iter()
+                .map(|frame| frame.name)
+                .collect::<Vec<_>>()
+                .join(";");
+            let frame = profile.stack.pop().unwrap();
+            let elapsed = frame.start.elapsed();
+
+            *profile.folded.entry(key).or_default() += elapsed - frame.children.min(elapsed);
+
+            match profile.stack.last_mut() {
+                Some(parent) => parent.children += elapsed,
+                None => flush(&mut profile, frame.name, elapsed),
+            }
+        });
+    }
+
+    fn flush(profile: &mut ThreadProfile, name: &'static str, elapsed: Duration) {
+        {
+            let mut aggregated = AGGREGATED.lock().unwrap();
+            let aggregated = aggregated.get_or_insert_with(HashMap::new);
+            for (key, duration) in profile.folded.drain() {
+                *aggregated.entry(key).or_default() += duration;
+            }
+        }
+
+        let (count, total) = profile.summary.entry(name).or_default();
+        *count += 1;
+        *total += elapsed;
+
+        let now = Instant::now();
+        match profile.logged {
+            None => profile.logged = Some(now),
+            Some(logged) if LOG_INTERVAL <= now - logged => {
+                let mut phases: Vec<_> = profile.summary.drain().collect();
+                phases.sort();
+                for (name, (count, total)) in phases {
+                    debug!(
+                        "Profiled '{}' {} times in {:?} in total.",
+                        name, count, total
+                    );
+                }
+                profile.logged = Some(now);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn dump_folded<W: Write>(w: &mut W) -> io::Result<()> {
+        let aggregated = AGGREGATED.lock().unwrap();
+        let mut lines: Vec<_> = aggregated.iter().flatten().collect();
+        lines.sort();
+
+        for (key, duration) in lines {
+            writeln!(w, "{} {}", key, duration.as_micros())?;
+        }
+        Ok(())
+    }
+
+    pub fn reset() {
+        *AGGREGATED.lock().unwrap() = None;
+    }
+}
+
+#[cfg(not(feature = "profiling"))]
+mod imp {
+    use std::io::{self, Write};
+
+    #[inline]
+    pub fn enter(_name: &'static str) {}
+
+    #[inline]
+    pub fn leave() {}
+
+    pub fn dump_folded<W: Write>(_w: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub fn reset() {}
+}
@@ -0,0 +1,203 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `prune` deletes the block data (i.e. the intrinsic and the extrinsic data of the acids)
+//! buried deeper than '--prune-keep-blocks' to save the storage. The main chain and the
+//! balances in the RDB are kept.
+//! `prune` depends on module `data_types` and `rdb` .
+//!
+//! The height that the block data is pruned below (= 'prune horizon') is recorded in RDB table
+//! "acids_pruning", so that the pruning resumes from there after the restart. (See also
+//! function [`rdb::acids::pruned_height`] .)
+//!
+//! The KVS does not provide the API to delete yet, so function [`apply`] takes the function to
+//! delete the data. (Function [`prune_block_data`] passes the keys of the shared extrinsic data
+//! to be deleted as well.)
+//!
+//! [`rdb::acids::pruned_height`]: crate::rdb::acids::pruned_height
+//! [`apply`]: self::apply
+//! [`prune_block_data`]: crate::prune_block_data
+
+use crate::data_types::{BlockHeight, Id};
+use crate::{rdb, Config, Error, ModuleEnvironment};
+use clap::{App, Arg};
+
+/// The number of the blocks to prune at once.
+const BATCH_BLOCKS: BlockHeight = 100;
+
+/// `Pruned` is the result of function [`apply`] .
+///
+/// [`apply`]: self::apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pruned {
+    /// The prune horizon after the pruning.
+    pub below: BlockHeight,
+    /// The number of the acids whose data is deleted.
+    pub acids: usize,
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --prune-keep-blocks
+///
+/// # Default
+///
+/// Nothing is specified by default; i.e. the block data is kept forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Environment {
+    keep_blocks: Option<BlockHeight>,
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.arg(
+            Arg::with_name("prune_keep_blocks")
+                .help(
+                    "The number of the recent blocks to keep the acid data for.
+The older acid data is deleted while the main chain and the balances are kept.",
+                )
+                .long("--prune-keep-blocks")
+                .takes_value(true),
+        )
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), Error> {
+        if let Some(keep_blocks) = config.args().value_of("prune_keep_blocks") {
+            let keep_blocks: BlockHeight = keep_blocks.parse().map_err(|e| {
+                let msg = format!("Failed to parse '--prune-keep-blocks': {}", e);
+                Error::Config(msg)
+            })?;
+            if keep_blocks <= 0 {
+                let msg = "'--prune-keep-blocks' must be greater than 0";
+                return Err(Error::Config(String::from(msg)));
+            }
+            self.keep_blocks = Some(keep_blocks);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Returns the number of the recent blocks to keep the data for if '--prune-keep-blocks' is
+    /// specified, or `None` .
+    pub fn keep_blocks(&self) -> Option<BlockHeight> {
+        self.keep_blocks
+    }
+}
+
+/// Returns the height that the block data should be pruned below when the tip is at
+/// `tip_height` , or `None` if nothing is to be pruned.
+pub fn horizon(tip_height: BlockHeight, env: &Environment) -> Option<BlockHeight> {
+    let keep_blocks = env.keep_blocks?;
+    match tip_height.checked_sub(keep_blocks) {
+        Some(h) if 0 <= h => Some(h + 1),
+        _ => None,
+    }
+}
+
+/// Prunes the block data below [`horizon`] , and returns the result if anything is pruned, or
+/// `None` .
+///
+/// `delete` is called with the ids of the acids in the order of the height, a batch of blocks at
+/// a time. The prune horizon is recorded after each call succeeds, so that the next call
+/// resumes from there if `delete` fails.
+///
+/// [`horizon`]: self::horizon
+pub fn apply<F>(
+    tip_height: BlockHeight,
+    env: &Environment,
+    rdb_env: &rdb::Environment,
+    mut delete: F,
+) -> Result<Option<Pruned>, Error>
+where
+    F: FnMut(&[Id]) -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+{
+    let horizon = match horizon(tip_height, env) {
+        None => return Ok(None),
+        Some(h) => h,
+    };
+
+    let mut from = {
+        let mut session = rdb::slave(rdb_env);
+        match rdb::acids::pruned_height(&mut session)? {
+            Some(h) => h,
+            None => {
+                let first = rdb::main_chain::fetch_asc(BlockHeight::MIN, 1, &mut session)?;
+                match first.as_ref().first() {
+                    None => return Ok(None),
+                    Some(chain_index) => chain_index.height(),
+                }
+            }
+        }
+    };
+
+    if horizon <= from {
+        return Ok(None);
+    }
+
+    let mut acids = 0;
+    while from < horizon {
+        let to = horizon.min(from.saturating_add(BATCH_BLOCKS));
+
+        let ids = {
+            let mut session = rdb::slave(rdb_env);
+            rdb::acids::fetch_by_chain_height(from, to, &mut session)?
+        };
+        delete(&ids).map_err(Error::kvs)?;
+        acids += ids.len();
+
+        let mut session = rdb::master(rdb_env);
+        rdb::acids::set_pruned_height(to, &mut session)?;
+        from = to;
+    }
+
+    info!(
+        "Pruned the data of {} acids below height {}.",
+        acids, horizon
+    );
+    Ok(Some(Pruned {
+        below: horizon,
+        acids,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn horizon_() {
+        let env = Environment::default();
+        assert_eq!(None, horizon(100, &env));
+
+        let env = Environment {
+            keep_blocks: Some(10),
+        };
+        assert_eq!(Some(91), horizon(100, &env));
+        assert_eq!(Some(1), horizon(10, &env));
+        assert_eq!(None, horizon(9, &env));
+        assert_eq!(None, horizon(BlockHeight::MIN, &env));
+    }
+}
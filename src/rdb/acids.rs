@@ -22,17 +22,25 @@
 //! - seq: integer, auto increment (or sequence)
 //! - id: binary string to store [`Id`], unique, not null
 //! - chain_height: integer, default null
+//! - priority: integer, default null
+//! - received_at: integer to store the system time in milliseconds when the acid is accepted to
+//!   mempool, default null
 //!
 //! Note that `chain_height` stores the height of the Blockchain including the [`Acid`] .
 //! If it is none, the [`Acid`] is not mined yet and in mempool.
 //!
+//! Table "acids_pruning" stores the height that the block data (i.e. the intrinsic and the
+//! extrinsic data of the acids) is pruned below. See also module [`prune`] .
+//!
+//! [`prune`]: crate::prune
 //! [`Acid`]: crate::data_types::Acid
 
 use super::{sqlite3, Master, Slave};
-use crate::data_types::{ChainIndex, Id};
+use crate::data_types::{BlockHeight, ChainIndex, Id};
+use crate::Error;
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::error::Error;
+use std::time::Duration;
 
 /// Inserts each [`Id`] of `acids` with NULL "chain_height" into RDB table "acids" if the [`Id`] is
 /// not in the table yet.
@@ -41,10 +49,10 @@ use std::error::Error;
 /// This function execute like the following SQL for each id in `acids` .
 /// (It depends on the implementation. The real SQL may be different.)
 ///
-/// INSERT INTO acids (id) VALUES (`id`) ON CONFLICT DO NOTHING
+/// INSERT INTO acids (id, received_at) VALUES (`id`, now) ON CONFLICT DO NOTHING
 ///
 /// [`Id`]: crate::data_types::Id
-pub fn accept_to_mempool<I, S, A>(acids: I, session: &mut S) -> Result<(), Box<dyn Error>>
+pub fn accept_to_mempool<I, S, A>(acids: I, session: &mut S) -> Result<(), Error>
 where
     I: Iterator<Item = A>,
     S: Master,
@@ -52,7 +60,33 @@ where
 {
     match sqlite3::acids::accept_to_mempool(acids, session) {
         Ok(()) => Ok(()),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Same to [`accept_to_mempool`] except for that "priority" is set as well.
+///
+/// `acids` is an iterator of ([`Id`] , priority) or a reference to it. If the [`Id`] is already
+/// in mempool, the priority is overwritten. The [`Id`] which is not in mempool is ignored.
+///
+/// This function execute like the following SQL for each (id, priority) in `acids` .
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// INSERT INTO acids (id, priority, received_at) VALUES (`id`, `priority`, now)
+///     ON CONFLICT (id) DO UPDATE SET priority = `priority` WHERE chain_height IS NULL
+///
+/// [`accept_to_mempool`]: self::accept_to_mempool
+/// [`Id`]: crate::data_types::Id
+pub fn accept_to_mempool_with_priority<I, S, B, A>(acids: I, session: &mut S) -> Result<(), Error>
+where
+    I: Iterator<Item = B>,
+    S: Master,
+    B: Borrow<(A, i64)>,
+    A: Borrow<Id>,
+{
+    match sqlite3::acids::accept_to_mempool_with_priority(acids, session) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -67,11 +101,12 @@ where
 /// # Safety
 ///
 /// The behavior is undefined if `chain_index` is not in the "main_chain".
+/// (If feature "strict" is specified, this function fails instead.)
 pub unsafe fn mempool_to_chain<I, S, A>(
     chain_index: &ChainIndex,
     acids: I,
     session: &mut S,
-) -> Result<usize, Box<dyn Error>>
+) -> Result<usize, Error>
 where
     I: Iterator<Item = A>,
     S: Master,
@@ -79,7 +114,7 @@ where
 {
     match sqlite3::acids::mempool_to_chain(chain_index, acids, session) {
         Ok(n) => Ok(n),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -90,19 +125,38 @@ where
 ///
 /// UPDATE acids SET chain_height = NULL WHERE chain_height = `chain_index.height()`
 ///
+/// Fails if `chain_index` is finalized. (See also [`main_chain::finalize`] .)
+///
+/// [`main_chain::finalize`]: crate::rdb::main_chain::finalize
+///
 /// # Safety
 ///
 /// The behavior is undefined if `chain_index` is not in the "main_chain".
-pub unsafe fn chain_to_mempool<S>(
-    chain_index: &ChainIndex,
-    session: &mut S,
-) -> Result<usize, Box<dyn Error>>
+/// (If feature "strict" is specified, this function fails instead.)
+pub unsafe fn chain_to_mempool<S>(chain_index: &ChainIndex, session: &mut S) -> Result<usize, Error>
 where
     S: Master,
 {
     match sqlite3::acids::chain_to_mempool(chain_index, session) {
         Ok(n) => Ok(n),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Deletes the acids in mempool received more than `age` ago, and returns the number of the
+/// deleted acids.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// DELETE FROM acids WHERE chain_height IS NULL AND received_at < (now - `age`)
+pub fn purge_mempool_older_than<S>(age: Duration, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    match sqlite3::acids::purge_mempool_older_than(age, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -127,7 +181,7 @@ where
 pub fn fetch_state<I, S, A>(
     acids: I,
     session: &mut S,
-) -> Result<HashMap<Id, Option<ChainIndex>>, Box<dyn Error>>
+) -> Result<HashMap<Id, Option<ChainIndex>>, Error>
 where
     I: Iterator<Item = A>,
     S: Slave,
@@ -135,7 +189,7 @@ where
 {
     match sqlite3::acids::fetch_state(acids, session) {
         Ok(m) => Ok(m),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -156,12 +210,166 @@ pub fn fetch_mempool<S>(
     min_seq: Option<i64>,
     limit: u32,
     session: &mut S,
-) -> Result<impl AsRef<[(i64, Id)]>, Box<dyn Error>>
+) -> Result<impl AsRef<[(i64, Id)]>, Error>
 where
     S: Slave,
 {
     match sqlite3::acids::fetch_mempool(min_seq, limit, session) {
         Ok(s) => Ok(s),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches at most `limit` number of [`Acid`] from mempool in order of the priority desc, and
+/// returns a slice of `(record sequence number, the id of the acid)` .
+///
+/// The acids with the same priority are ordered by the record sequence number, and those without
+/// the priority follow all the others.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT seq, id FROM acids
+///     WHERE chain_height IS NULL ORDER BY priority DESC, seq ASC LIMIT `limit`
+///
+/// [`Acid`]: crate::data_types::Acid
+pub fn fetch_mempool_by_priority<S>(
+    limit: u32,
+    session: &mut S,
+) -> Result<impl AsRef<[(i64, Id)]>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::acids::fetch_mempool_by_priority(limit, session) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches at most `limit` number of [`Id`] in order of the record sequence number regardless of
+/// whether it is in mempool or not, and returns a slice of `(record sequence number, the id)` .
+///
+/// If `min_seq` is not `None` , this method ignores the records whose sequence number is less
+/// than `min_seq` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT seq, id FROM acids WHERE seq >= `min_seq` ORDER BY seq ASC LIMIT `limit`
+///
+/// [`Id`]: crate::data_types::Id
+pub fn fetch_ids<S>(
+    min_seq: Option<i64>,
+    limit: u32,
+    session: &mut S,
+) -> Result<impl AsRef<[(i64, Id)]>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::acids::fetch_ids(min_seq, limit, session) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the ids of the acids whose "chain_height" is greater than or equals to `min_height`
+/// and less than `max_height` , ordered by "chain_height".
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT id FROM acids WHERE chain_height >= `min_height` AND chain_height < `max_height`
+///     ORDER BY chain_height ASC, seq ASC
+pub fn fetch_by_chain_height<S>(
+    min_height: BlockHeight,
+    max_height: BlockHeight,
+    session: &mut S,
+) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::acids::fetch_by_chain_height(min_height, max_height, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns the height that the block data is pruned below if any, or `None` .
+pub fn pruned_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::acids::pruned_height(session) {
+        Ok(h) => Ok(h),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Records in RDB table "acids_pruning" that the block data is pruned below `height` , and
+/// returns `true` if the height is changed.
+///
+/// Does nothing and returns `false` if `height` is less than or equals to the recorded height.
+/// (The height never decreases.)
+pub fn set_pruned_height<S>(height: BlockHeight, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    match sqlite3::acids::set_pruned_height(height, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches at most `limit` acids in the main chain whose "chain_height" is less than or equals to
+/// `max_height` in order of the record sequence number, and returns a vector of `(record sequence
+/// number, chain_height, the id)` .
+///
+/// If `min_seq` is not `None` , this method ignores the records whose sequence number is less
+/// than `min_seq` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT seq, chain_height, id FROM acids WHERE seq >= `min_seq`
+///     AND chain_height <= `max_height` ORDER BY seq ASC LIMIT `limit`
+pub fn fetch_chain<S>(
+    min_seq: Option<i64>,
+    max_height: BlockHeight,
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(i64, BlockHeight, Id)>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::acids::fetch_chain(min_seq, max_height, limit, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Inserts each `(chain_height, id)` of `acids` into RDB table "acids" as in the main chain, and
+/// returns the number of the inserted or the changed rows.
+///
+/// The acid in the mempool is moved into the chain, and the one already in the chain is not
+/// changed. Unlike [`mempool_to_chain`] , this function does not check RDB table "main_chain";
+/// it is used to restore the table from a snapshot. (See also module [`snapshot`] .)
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// INSERT INTO acids (id, chain_height) VALUES (`id`, `chain_height`)
+///     ON CONFLICT(id) DO UPDATE SET chain_height = `chain_height` WHERE chain_height IS NULL
+///
+/// [`mempool_to_chain`]: self::mempool_to_chain
+/// [`snapshot`]: crate::snapshot
+pub fn insert_chain<I, S, B>(acids: I, session: &mut S) -> Result<usize, Error>
+where
+    I: Iterator<Item = B>,
+    S: Master,
+    B: Borrow<(BlockHeight, Id)>,
+{
+    match sqlite3::acids::insert_chain(acids, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
@@ -0,0 +1,131 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! This module provides functions to manipulate RDB table "block_headers" to track the blocks
+//! on the side branches as well as those in the main chain.
+//!
+//! Table "block_headers" has following columns.
+//! (It depends on the implementation. the real schema can be different.)
+//!
+//! - id: binary string to store [`Id`], primary key
+//! - parent: binary string to store [`Id`] of the previous block, not null, indexed
+//! - height: integer, not null
+//! - work: integer not less than 0, not null
+//!
+//! A header whose id is not in RDB table "main_chain" is on a side branch.
+//! See also module [`fork_choice`] .
+//!
+//! [`Id`]: crate::data_types::Id
+//! [`fork_choice`]: crate::fork_choice
+
+use super::{sqlite3, Master, Slave};
+use crate::data_types::{BlockHeight, Id};
+use crate::Error;
+
+/// `BlockHeader` is a row of RDB table "block_headers".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    /// The id of the block.
+    pub id: Id,
+    /// The id of the previous block.
+    pub parent: Id,
+    /// The height of the block.
+    pub height: BlockHeight,
+    /// The work (or the weight) that the block contributes to the chain. It is up to the
+    /// application; e.g. the expected number of the hashes to mine the block.
+    pub work: i64,
+}
+
+/// Inserts `header` , and returns `true` if it is inserted newly, or `false` if the id has
+/// already been.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// INSERT INTO block_headers (id, parent, height, work)
+///     VALUES (`header.id`, `header.parent`, `header.height`, `header.work`)
+///     ON CONFLICT DO NOTHING
+///
+/// # Error
+///
+/// Errors if `header.work` is less than 0.
+pub fn insert<S>(header: &BlockHeader, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    match sqlite3::block_headers::insert(header, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the header whose id is `id` if any, or `None` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT id, parent, height, work FROM block_headers WHERE id = `id`
+pub fn fetch<S>(id: &Id, session: &mut S) -> Result<Option<BlockHeader>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::block_headers::fetch(id, session) {
+        Ok(h) => Ok(h),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the tips of the side branches; i.e. the headers which are not in RDB table
+/// "main_chain" and have no child, ordered by the height desc.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT id, parent, height, work FROM block_headers AS h
+///     WHERE h.id NOT IN (SELECT id FROM main_chain)
+///     AND NOT EXISTS (SELECT 1 FROM block_headers WHERE parent = h.id)
+///     ORDER BY height DESC, id ASC
+pub fn fetch_side_tips<S>(session: &mut S) -> Result<Vec<BlockHeader>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::block_headers::fetch_side_tips(session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Deletes the headers on the side branches whose height is less than or equals to `height` ,
+/// and returns the number of the deleted rows.
+///
+/// It is typically called with the finalized height, because such a branch can never replace
+/// the main chain. (See also function [`main_chain::finalize`] .)
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// DELETE FROM block_headers WHERE height <= `height` AND id NOT IN (SELECT id FROM main_chain)
+///
+/// [`main_chain::finalize`]: super::main_chain::finalize
+pub fn prune_side<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    match sqlite3::block_headers::prune_side(height, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! This module provides functions to manipulate RDB table "invalid_acids" to remember the acids
+//! found to be invalid, so that they are not validated again after the restart.
+//!
+//! Table "invalid_acids" has following columns.
+//! (It depends on the implementation. the real schema can be different.)
+//!
+//! - id: binary string to store [`Id`], primary key
+//! - reason: string, not null
+//!
+//! [`Id`]: crate::data_types::Id
+
+use super::{sqlite3, Master, Slave};
+use crate::data_types::Id;
+use crate::Error;
+
+/// Records that the acid with `id` is invalid for `reason` , and returns `true` if it is recorded
+/// newly, or `false` if it has already been.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// INSERT INTO invalid_acids (id, reason) VALUES (`id`, `reason`) ON CONFLICT DO NOTHING
+pub fn mark_invalid<S>(id: &Id, reason: &str, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    match sqlite3::invalid_acids::mark_invalid(id, reason, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns `true` if the acid with `id` is recorded as invalid, or `false` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT COUNT(*) FROM invalid_acids WHERE id = `id`
+pub fn is_invalid<S>(id: &Id, session: &mut S) -> Result<bool, Error>
+where
+    S: Slave,
+{
+    match sqlite3::invalid_acids::is_invalid(id, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns the reason why the acid with `id` is recorded as invalid if any, or `None` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT reason FROM invalid_acids WHERE id = `id`
+pub fn invalid_reason<S>(id: &Id, session: &mut S) -> Result<Option<String>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::invalid_acids::invalid_reason(id, session) {
+        Ok(r) => Ok(r),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Removes the record of the acid with `id` , and returns `true` if it was recorded, or `false` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// DELETE FROM invalid_acids WHERE id = `id`
+pub fn unmark_invalid<S>(id: &Id, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    match sqlite3::invalid_acids::unmark_invalid(id, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
@@ -22,14 +22,20 @@
 //! - height: integer, unique, not null
 //! - id: binary string to store [`Id`], unique, not null
 //!
+//! # Finality
+//!
+//! The blocks whose height is less than or equals to the 'finalized height' are finalized.
+//! Function [`pop`] refuses to revert the finalized blocks.
+//!
+//! [`pop`]: self::pop
 //! [`ChainIndex`]: crate::data_types::ChainIndex
 //! [`Id`]: crate::data_types::Id
 
 use super::{sqlite3, Master, Slave};
 use crate::data_types::{BlockHeight, ChainIndex, Id};
+use crate::Error;
 use std::borrow::Borrow;
 use std::collections::BTreeMap;
-use std::error::Error;
 
 /// Insert `chain_index` into RDB table "main_chain".
 ///
@@ -43,7 +49,7 @@ use std::error::Error;
 /// This method does not sanitize at all except for the table constraint.
 /// (i.e. The height and the id of the `chain_index` is unique in "main_chain" if this method
 /// success.)
-pub fn push<S>(chain_index: &ChainIndex, session: &mut S) -> Result<(), Box<dyn Error>>
+pub fn push<S>(chain_index: &ChainIndex, session: &mut S) -> Result<(), Error>
 where
     S: Master,
 {
@@ -58,7 +64,9 @@ where
 /// (It depends on the implementation. The real SQL may be different.)
 ///
 /// DELETE FROM main_chain ORDER BY height DESC LIMIT 1
-pub fn pop<S>(session: &mut S) -> Result<(), Box<dyn Error>>
+///
+/// Fails if the heighest record is finalized.
+pub fn pop<S>(session: &mut S) -> Result<(), Error>
 where
     S: Master,
 {
@@ -66,16 +74,50 @@ where
     Ok(())
 }
 
+/// Marks the blocks in "main_chain" whose height is less than or equals to `height` as finalized,
+/// and returns `true` if the finalized height is changed.
+///
+/// Does nothing and returns `false` if `height` is not in "main_chain", or if `height` is less
+/// than or equals to the current finalized height. (The finalized height never decreases.)
+pub fn finalize<S>(height: BlockHeight, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    match sqlite3::main_chain::finalize(height, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns the finalized height if any block is finalized, or `None` .
+pub fn finalized_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::main_chain::finalized_height(session) {
+        Ok(h) => Ok(h),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns `true` if `chain_index` is in "main_chain" and finalized, or `false` .
+pub fn is_final<S>(chain_index: &ChainIndex, session: &mut S) -> Result<bool, Error>
+where
+    S: Slave,
+{
+    match sqlite3::main_chain::is_final(chain_index, session) {
+        Ok(b) => Ok(b),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
 /// Fetches records corresponding to `heights` from "main_chain".
 ///
 /// This function execute like the following SQL for each `h` in `heights`.
 /// (It depends on the implementation. The real SQL may be different.)
 ///
 /// SELECT id FROM main_chain WHERE height = `h`
-pub fn fetch<I, S, H>(
-    heights: I,
-    session: &mut S,
-) -> Result<BTreeMap<BlockHeight, Id>, Box<dyn Error>>
+pub fn fetch<I, S, H>(heights: I, session: &mut S) -> Result<BTreeMap<BlockHeight, Id>, Error>
 where
     I: Iterator<Item = H>,
     H: Borrow<BlockHeight>,
@@ -83,7 +125,7 @@ where
 {
     match sqlite3::main_chain::fetch(heights, session) {
         Ok(m) => Ok(m),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -94,13 +136,29 @@ where
 /// (It depends on the implementation. The real SQL may be different.)
 ///
 /// SELECT id FROM main_chain WHERE height = `height`
-pub fn fetch_one<S>(height: BlockHeight, session: &mut S) -> Result<Option<Id>, Box<dyn Error>>
+pub fn fetch_one<S>(height: BlockHeight, session: &mut S) -> Result<Option<Id>, Error>
 where
     S: Slave,
 {
     match sqlite3::main_chain::fetch_one(height, session) {
         Ok(id) => Ok(id),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches a record corresponding to `id` from "main_chain" and returns it if found, or `None` .
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT height, id FROM main_chain WHERE id = `id`
+pub fn find_by_id<S>(id: &Id, session: &mut S) -> Result<Option<ChainIndex>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::main_chain::find_by_id(id, session) {
+        Ok(chain_index) => Ok(chain_index),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -118,13 +176,13 @@ pub fn fetch_asc<S>(
     min_height: BlockHeight,
     limit: u32,
     session: &mut S,
-) -> Result<impl AsRef<[ChainIndex]>, Box<dyn Error>>
+) -> Result<impl AsRef<[ChainIndex]>, Error>
 where
     S: Slave,
 {
     match sqlite3::main_chain::fetch_asc(min_height, limit, session) {
         Ok(r) => Ok(r),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -142,12 +200,12 @@ pub fn fetch_desc<S>(
     max_height: BlockHeight,
     limit: u32,
     session: &mut S,
-) -> Result<impl AsRef<[ChainIndex]>, Box<dyn Error>>
+) -> Result<impl AsRef<[ChainIndex]>, Error>
 where
     S: Slave,
 {
     match sqlite3::main_chain::fetch_desc(max_height, limit, session) {
         Ok(r) => Ok(r),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
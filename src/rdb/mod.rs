@@ -17,12 +17,227 @@
 //! 'rdb' module
 
 pub mod acids;
+pub mod block_headers;
+pub mod invalid_acids;
 pub mod main_chain;
+pub mod parents;
 pub mod resources;
+pub mod sql;
 mod sqlite3;
 
 pub use sqlite3::{Environment, Error};
 
+use crate::data_types::{AssetValue, BlockHeight, ChainIndex, CryptoHash, Id, ResourceId};
+use std::borrow::Borrow;
+
+/// `PendingMigration` is a schema object which is not created in the RDB yet.
+///
+/// See also function [`pending_migrations`] .
+///
+/// [`pending_migrations`]: self::pending_migrations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMigration {
+    /// The type of the schema object; i.e. "table", "index", or "trigger".
+    pub kind: &'static str,
+    /// The name of the schema object.
+    pub name: &'static str,
+    /// The table that the schema object belongs to.
+    pub table: &'static str,
+    /// The number of the rows in `table` which the migration would touch. (0 if `table` does not
+    /// exist yet.)
+    pub rows: i64,
+}
+
+/// Returns the schema objects that [`Environment`] would create at `init()` without changing
+/// anything.
+///
+/// [`Environment`]: self::Environment
+pub fn pending_migrations<S>(session: &mut S) -> Result<Vec<PendingMigration>, crate::Error>
+where
+    S: Slave,
+{
+    match sqlite3::migration::pending_migrations(session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(crate::Error::rdb(e)),
+    }
+}
+
+/// Defragments the RDB and updates the statistics for the query planner.
+///
+/// This function execute like the following SQL.
+///
+/// ```sql
+/// VACUUM;
+/// ANALYZE;
+/// ```
+///
+/// It can take long time for a large database, and the other threads cannot use the RDB
+/// meanwhile.
+///
+/// # Panics
+///
+/// Panics if `session` is in a transaction.
+pub fn maintain<S>(session: &mut S) -> Result<(), crate::Error>
+where
+    S: Master,
+{
+    match sqlite3::maintain(session) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(crate::Error::rdb(e)),
+    }
+}
+
+/// Appends `chain_index` to RDB table "main_chain", moves `acid_ids` from the mempool into
+/// `chain_index` , and applies `balance_deltas` as the balances at `chain_index` atomically.
+///
+/// `balance_deltas` is an iterator of ([`ResourceId`] , [`AssetValue`] ) or a reference to it.
+/// (See also function [`resources::update_balance_at`] .)
+///
+/// If `session` is not in a transaction, this function starts one, and commits it on success or
+/// rolls it back on failure. Otherwise, this function runs in the transaction, and the caller
+/// should roll it back on failure.
+///
+/// # Errors
+///
+/// Fails and changes nothing if
+///
+/// - the height of `chain_index` is not next to the tip of "main_chain" (unless "main_chain" is
+///   empty),
+/// - any acid in `acid_ids` is not in the mempool, or
+/// - any balance would be less than 0.
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+/// [`AssetValue`]: crate::data_types::AssetValue
+/// [`resources::update_balance_at`]: self::resources::update_balance_at
+pub fn append_block<I, A, J, B, R, V, S>(
+    chain_index: &ChainIndex,
+    acid_ids: I,
+    balance_deltas: J,
+    session: &mut S,
+) -> Result<(), crate::Error>
+where
+    I: Iterator<Item = A> + Clone,
+    A: Borrow<Id>,
+    J: Iterator<Item = B> + Clone,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+    S: Master,
+{
+    if session.is_transaction() {
+        return do_append_block(chain_index, acid_ids, balance_deltas, session);
+    }
+
+    session.begin_transaction()?;
+    match do_append_block(chain_index, acid_ids, balance_deltas, session) {
+        Ok(()) => session.commit(),
+        Err(e) => {
+            session.rollback()?;
+            Err(e)
+        }
+    }
+}
+
+fn do_append_block<I, A, J, B, R, V, S>(
+    chain_index: &ChainIndex,
+    acid_ids: I,
+    balance_deltas: J,
+    session: &mut S,
+) -> Result<(), crate::Error>
+where
+    I: Iterator<Item = A> + Clone,
+    A: Borrow<Id>,
+    J: Iterator<Item = B> + Clone,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+    S: Master,
+{
+    let height = chain_index.height();
+    let tip = main_chain::fetch_desc(BlockHeight::MAX, 1, session)?;
+    if let Some(tip) = tip.as_ref().first() {
+        if height != tip.height() + 1 {
+            let msg = format!(
+                "Failed to append block {}: height {} is not next to the tip {}",
+                chain_index.id().display_hex(),
+                height,
+                tip.height()
+            );
+            return Err(crate::Error::rdb(msg));
+        }
+    }
+
+    main_chain::push(chain_index, session)?;
+
+    let expected = acid_ids.clone().count();
+    let moved = unsafe { acids::mempool_to_chain(chain_index, acid_ids, session)? };
+    if moved != expected {
+        let msg = format!(
+            "Failed to append block {}: {} of {} acids are not in the mempool",
+            chain_index.id().display_hex(),
+            expected - moved,
+            expected
+        );
+        return Err(crate::Error::rdb(msg));
+    }
+
+    resources::update_balance_at(balance_deltas, height, session)
+}
+
+/// Removes the tip from RDB table "main_chain", and moves the acids belonging to it back to the
+/// mempool atomically.
+///
+/// Returns the removed tip and the ids of the moved acids, or `None` if "main_chain" is empty.
+///
+/// If `session` is not in a transaction, this function starts one, and commits it on success or
+/// rolls it back on failure. Otherwise, this function runs in the transaction, and the caller
+/// should roll it back on failure.
+///
+/// # Errors
+///
+/// Fails and changes nothing if the tip is finalized. (See also function
+/// [`main_chain::finalize`] .)
+///
+/// [`main_chain::finalize`]: self::main_chain::finalize
+pub fn revert_block<S>(session: &mut S) -> Result<Option<(ChainIndex, Vec<Id>)>, crate::Error>
+where
+    S: Master,
+{
+    if session.is_transaction() {
+        return do_revert_block(session);
+    }
+
+    session.begin_transaction()?;
+    match do_revert_block(session) {
+        Ok(reverted) => {
+            session.commit()?;
+            Ok(reverted)
+        }
+        Err(e) => {
+            session.rollback()?;
+            Err(e)
+        }
+    }
+}
+
+fn do_revert_block<S>(session: &mut S) -> Result<Option<(ChainIndex, Vec<Id>)>, crate::Error>
+where
+    S: Master,
+{
+    let tip = main_chain::fetch_desc(BlockHeight::MAX, 1, session)?;
+    let tip = match tip.as_ref().first() {
+        None => return Ok(None),
+        Some(chain_index) => *chain_index,
+    };
+
+    let height = tip.height();
+    let acid_ids = acids::fetch_by_chain_height(height, height + 1, session)?;
+    unsafe { acids::chain_to_mempool(&tip, session)? };
+    main_chain::pop(session)?;
+
+    Ok(Some((tip, acid_ids)))
+}
+
 /// `Session` represents a session to the RDB.
 pub trait Session {
     /// Returns `true` if the current session is in transaction.
@@ -33,21 +248,21 @@ pub trait Session {
     /// # Panics
     ///
     /// Panics if `self` is in transaction.
-    fn begin_transaction(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn begin_transaction(&mut self) -> Result<(), crate::Error>;
 
     /// Commits transaction.
     ///
     /// # Panics
     ///
     /// Panics if `self` is not in transaction.
-    fn commit(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn commit(&mut self) -> Result<(), crate::Error>;
 
     /// Rollback transaction.
     ///
     /// # Panics
     ///
     /// Panics if `self` is not in transaction.
-    fn rollback(&mut self) -> Result<(), Box<dyn std::error::Error>>;
+    fn rollback(&mut self) -> Result<(), crate::Error>;
 }
 
 /// Represents a session to a slave RDB.
@@ -75,3 +290,107 @@ pub fn master<'a>(env: &'a Environment) -> impl 'a + Master {
 pub fn slave<'a>(env: &'a Environment) -> impl 'a + Slave {
     sqlite3::slave(env)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env() -> Environment {
+        let env = Environment::default();
+        {
+            let mut session = master(&env);
+            sqlite3::create_table(&mut session).unwrap();
+        }
+        env
+    }
+
+    fn id(n: u8) -> Id {
+        let mut id = Id::zeroed();
+        id[0] = n;
+        id
+    }
+
+    #[test]
+    fn append_block_() {
+        let env = env();
+        let mut session = master(&env);
+        let resource_id = unsafe { ResourceId::new(b"owner", b"asset") };
+        let no_deltas: [(ResourceId, AssetValue); 0] = [];
+
+        acids::accept_to_mempool([id(1), id(2), id(3)].iter(), &mut session).unwrap();
+
+        let first = ChainIndex::new(1, &id(1));
+        let deltas = [(resource_id, 10)];
+        append_block(&first, [id(1), id(2)].iter(), deltas.iter(), &mut session).unwrap();
+        assert_eq!(Some(id(1)), main_chain::fetch_one(1, &mut session).unwrap());
+        assert_eq!(false, session.is_transaction());
+
+        // Not contiguous.
+        let third = ChainIndex::new(3, &id(3));
+        let res = append_block(&third, [id(3)].iter(), no_deltas.iter(), &mut session);
+        assert_eq!(true, res.is_err());
+
+        // id(2) is not in the mempool any longer.
+        let second = ChainIndex::new(2, &id(3));
+        let res = append_block(&second, [id(2)].iter(), no_deltas.iter(), &mut session);
+        assert_eq!(true, res.is_err());
+
+        // The balance would be negative.
+        let deltas = [(resource_id, -11)];
+        let res = append_block(&second, [id(3)].iter(), deltas.iter(), &mut session);
+        assert_eq!(true, res.is_err());
+        assert_eq!(None, main_chain::fetch_one(2, &mut session).unwrap());
+
+        let deltas = [(resource_id, -10)];
+        append_block(&second, [id(3)].iter(), deltas.iter(), &mut session).unwrap();
+        assert_eq!(Some(id(3)), main_chain::fetch_one(2, &mut session).unwrap());
+    }
+
+    #[test]
+    fn revert_block_() {
+        let env = env();
+        let mut session = master(&env);
+        let no_deltas: [(ResourceId, AssetValue); 0] = [];
+
+        assert_eq!(None, revert_block(&mut session).unwrap());
+
+        acids::accept_to_mempool([id(1), id(2), id(3)].iter(), &mut session).unwrap();
+        let first = ChainIndex::new(1, &id(1));
+        append_block(&first, [id(1)].iter(), no_deltas.iter(), &mut session).unwrap();
+        let second = ChainIndex::new(2, &id(2));
+        append_block(
+            &second,
+            [id(2), id(3)].iter(),
+            no_deltas.iter(),
+            &mut session,
+        )
+        .unwrap();
+
+        let (tip, mut reverted) = revert_block(&mut session).unwrap().unwrap();
+        reverted.sort();
+        assert_eq!(second, tip);
+        assert_eq!(vec![id(2), id(3)], reverted);
+        assert_eq!(false, session.is_transaction());
+        assert_eq!(None, main_chain::fetch_one(2, &mut session).unwrap());
+
+        // The acids are in the mempool again.
+        let states = acids::fetch_state([id(1), id(2), id(3)].iter(), &mut session).unwrap();
+        assert_eq!(Some(&Some(first)), states.get(&id(1)));
+        assert_eq!(Some(&None), states.get(&id(2)));
+        assert_eq!(Some(&None), states.get(&id(3)));
+
+        // They do not look mined in the next block at the same height.
+        let other = ChainIndex::new(2, &id(4));
+        append_block(&other, [id(2)].iter(), no_deltas.iter(), &mut session).unwrap();
+        let states = acids::fetch_state([id(2), id(3)].iter(), &mut session).unwrap();
+        assert_eq!(Some(&Some(other)), states.get(&id(2)));
+        assert_eq!(Some(&None), states.get(&id(3)));
+
+        // The finalized tip is not reverted.
+        assert_eq!(true, main_chain::finalize(2, &mut session).unwrap());
+        assert_eq!(true, revert_block(&mut session).is_err());
+        assert_eq!(Some(id(4)), main_chain::fetch_one(2, &mut session).unwrap());
+        let states = acids::fetch_state([id(2)].iter(), &mut session).unwrap();
+        assert_eq!(Some(&Some(other)), states.get(&id(2)));
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! This module provides functions to manipulate RDB table "parents" to look up the acids by the
+//! parent.
+//!
+//! Table "parents" has following columns.
+//! (It depends on the implementation. the real schema can be different.)
+//!
+//! - child_id: binary string to store [`Id`], not null
+//! - parent_id: binary string to store [`Id`], not null, indexed
+//! - idx: integer, the index of the parent, not null
+//!
+//! (child_id, idx) is the primary key.
+//!
+//! [`Id`]: crate::data_types::Id
+
+use super::{sqlite3, Master, Slave};
+use crate::data_types::Id;
+use crate::Error;
+use std::borrow::Borrow;
+
+/// Inserts the parents of each acid in `acids` , and returns the number of the inserted rows.
+///
+/// Each element of `acids` is a pair of the id of the acid and the ids of the parents in the
+/// order. The rows already in the table are ignored.
+///
+/// This function execute like the following SQL for each parent of each acid.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// INSERT INTO parents (child_id, parent_id, idx) VALUES (`child`, `parent`, `idx`)
+///     ON CONFLICT DO NOTHING
+pub fn insert<I, C, P, A, S>(acids: I, session: &mut S) -> Result<usize, Error>
+where
+    I: Iterator<Item = (C, P)>,
+    C: Borrow<Id>,
+    P: IntoIterator<Item = A>,
+    A: Borrow<Id>,
+    S: Master,
+{
+    match sqlite3::parents::insert(acids, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the ids of the acids which have `parent_id` as a parent in order of the id.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT DISTINCT child_id FROM parents WHERE parent_id = `parent_id` ORDER BY child_id
+pub fn children_of<S>(parent_id: &Id, session: &mut S) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::parents::children_of(parent_id, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the ids of the parents of `child_id` in the order.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT parent_id FROM parents WHERE child_id = `child_id` ORDER BY idx ASC
+pub fn parents_of<S>(child_id: &Id, session: &mut S) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::parents::parents_of(child_id, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
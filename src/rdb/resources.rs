@@ -26,13 +26,111 @@
 //! - asset_type: binary string to store the asset type of [`ResourceId`] .
 //! - value: The number of the asset to be depositted.
 //!
+//! Table "resources_history" stores the balance of each [`ResourceId`] at each height that
+//! [`update_balance_at`] is called with. It has the same columns as "resources" and "height".
+//!
+//! [`update_balance_at`]: self::update_balance_at
 //! [`ResourceId`]: crate::data_types::ResourceId
 
 use super::{sqlite3, Master, Slave};
-use crate::data_types::{AssetValue, ResourceId};
+use crate::data_types::{AssetOverflow, AssetValue, BlockHeight, ResourceId};
+use crate::Error;
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::error::Error;
+
+/// Fetches all the [`ResourceId`] whose owner is `owner` and the depositted value, ordered by
+/// the asset type.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT asset_type, value FROM resources WHERE owner = `owner` ORDER BY asset_type
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+pub fn fetch_by_owner<S>(
+    owner: &[u8],
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::resources::fetch_by_owner(owner, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns the sum of the depositted value of `asset_type` in RDB table "resources".
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT SUM(value) FROM resources WHERE asset_type = `asset_type`
+///
+/// # Error
+///
+/// Errors if the sum overflows.
+pub fn total_supply<S>(asset_type: &[u8], session: &mut S) -> Result<AssetValue, Error>
+where
+    S: Slave,
+{
+    match sqlite3::resources::total_supply(asset_type, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches at most `limit` [`ResourceId`] whose asset type is `asset_type` and the depositted
+/// value, ordered by the value desc.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT owner, value FROM resources WHERE asset_type = `asset_type`
+/// ORDER BY value DESC, owner ASC LIMIT `limit`
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+pub fn top_owners<S>(
+    asset_type: &[u8],
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::resources::top_owners(asset_type, limit, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches at most `limit` [`ResourceId`] and the depositted value ordered by the owner and the
+/// asset type.
+///
+/// If `after` is not `None` , this function ignores the [`ResourceId`] less than or equals to
+/// `after` ; i.e. pass the last one of the previous result to fetch the next.
+///
+/// This function execute like the following SQL.
+/// (It depends on the implementation. The real SQL may be different.)
+///
+/// SELECT owner, asset_type, value FROM resources
+/// WHERE (owner, asset_type) > (`after.owner`, `after.asset_type`)
+/// ORDER BY owner ASC, asset_type ASC LIMIT `limit`
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+pub fn fetch_all<S>(
+    after: Option<&ResourceId>,
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::resources::fetch_all(after, limit, session) {
+        Ok(v) => Ok(v),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
 
 /// Upadtes the asset value in RDB table "resources".
 ///
@@ -46,9 +144,12 @@ use std::error::Error;
 ///
 /// Errors if any [`AssetValue`] is less than 0.
 ///
+/// Errors with `Error::Rdb` wrapping [`AssetOverflow`] if any [`AssetValue`] overflows.
+///
 /// [`ResourceId`]: crate::data_types::ResourceId
 /// [`AssetValue`]: crate::data_types::AssetValue
-pub fn update_balance<I, S, B, R, V>(balances: I, session: &mut S) -> Result<(), Box<dyn Error>>
+/// [`AssetOverflow`]: crate::data_types::AssetOverflow
+pub fn update_balance<I, S, B, R, V>(balances: I, session: &mut S) -> Result<(), Error>
 where
     I: Iterator<Item = B> + Clone,
     S: Master,
@@ -58,7 +159,102 @@ where
 {
     match sqlite3::resources::update_balance(balances, session) {
         Ok(_) => Ok(()),
-        Err(e) => Err(Box::new(e)),
+        Err(e) if e.is_too_big() => Err(Error::rdb(AssetOverflow)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Same to [`update_balance`] except for that the balances after the update are recorded in RDB
+/// table "resources_history" as those at `height` .
+///
+/// Call this function in the same transaction as the block at `height` is pushed into
+/// "main_chain".
+///
+/// If '--rdb-resources-history-depth' is specified, the history older than the depth is pruned
+/// at the same time. (See also function [`prune_history`] .)
+///
+/// [`update_balance`]: self::update_balance
+/// [`prune_history`]: self::prune_history
+pub fn update_balance_at<I, S, B, R, V>(
+    balances: I,
+    height: BlockHeight,
+    session: &mut S,
+) -> Result<(), Error>
+where
+    I: Iterator<Item = B> + Clone,
+    S: Master,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+{
+    match sqlite3::resources::update_balance_at(balances, height, session) {
+        Ok(_) => Ok(()),
+        Err(e) if e.is_too_big() => Err(Error::rdb(AssetOverflow)),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Fetches the depositted value of each [`ResourceId`] in `resource_ids` at `height` from RDB
+/// table "resources_history".
+///
+/// The returned value does not has the [`ResourceId`] as the key if the corresponding value is 0.
+///
+/// Returns `None` if the history at `height` is already pruned.
+pub fn fetch_at_height<I, S, R>(
+    resource_ids: I,
+    height: BlockHeight,
+    session: &mut S,
+) -> Result<Option<HashMap<ResourceId, AssetValue>>, Error>
+where
+    I: Iterator<Item = R>,
+    S: Slave,
+    R: Borrow<ResourceId>,
+{
+    match sqlite3::resources::fetch_at_height(resource_ids, height, session) {
+        Ok(m) => Ok(m),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Deletes the history which is not necessary to fetch the balance at `height` or later, and
+/// returns the number of the deleted rows.
+///
+/// After this function is called, [`fetch_at_height`] returns `None` for the height less than
+/// `height` . Does nothing if `height` is less than or equals to the height already pruned.
+///
+/// [`fetch_at_height`]: self::fetch_at_height
+pub fn prune_history<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    match sqlite3::resources::prune_history(height, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Deletes the history whose height is greater than `height` , and returns the number of the
+/// deleted rows.
+///
+/// Call this function in the same transaction as the blocks are popped from "main_chain".
+pub fn truncate_history<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    match sqlite3::resources::truncate_history(height, session) {
+        Ok(n) => Ok(n),
+        Err(e) => Err(Error::rdb(e)),
+    }
+}
+
+/// Returns the height that the history is pruned below if any, or `None` .
+pub fn pruned_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    match sqlite3::resources::pruned_height(session) {
+        Ok(h) => Ok(h),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
 
@@ -68,7 +264,7 @@ where
 pub fn fetch<I, S, R>(
     resource_ids: I,
     session: &mut S,
-) -> Result<HashMap<ResourceId, AssetValue>, Box<dyn Error>>
+) -> Result<HashMap<ResourceId, AssetValue>, Error>
 where
     I: Iterator<Item = R>,
     S: Slave,
@@ -76,6 +272,6 @@ where
 {
     match sqlite3::resources::fetch(resource_ids, session) {
         Ok(m) => Ok(m),
-        Err(e) => Err(Box::new(e)),
+        Err(e) => Err(Error::rdb(e)),
     }
 }
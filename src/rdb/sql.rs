@@ -0,0 +1,270 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! This module provides a generic SQL API for the application to own tables in the RDB.
+//!
+//! The application should not touch the tables that `mouse` owns (e.g. "main_chain", "acids",
+//! and so on), and should name its tables with a prefix not to conflict with them.
+//!
+//! [`Statement`] borrows the session while it is alive, and the bound values while it is alive,
+//! so neither can be dropped before the statement is.
+//!
+//! Function [`prepare`] accepts only the statements which do not modify the database, so that
+//! a slave session cannot write even if it shares the connection with the master session.
+//! (e.g. '--rdb-read-connections' is 0.) Use function [`prepare_write`] with a master session to
+//! modify the database.
+//!
+//! Note that the SQL dialect depends on the implementation. (It is SQLite for now.)
+//!
+//! # Examples
+//!
+//! ```
+//! use mouse::rdb::{self, sql};
+//!
+//! let env = rdb::Environment::default();
+//! let mut session = rdb::master(&env);
+//!
+//! sql::prepare_write("CREATE TABLE app_names(name TEXT)", &mut session)
+//!     .unwrap()
+//!     .step()
+//!     .unwrap();
+//!
+//! let name = String::from("foo");
+//! let mut stmt = sql::prepare_write("INSERT INTO app_names VALUES(?1)", &mut session).unwrap();
+//! stmt.bind_text(1, &name).unwrap();
+//! stmt.step().unwrap();
+//! drop(stmt);
+//!
+//! let mut stmt = sql::prepare("SELECT name FROM app_names", &mut session).unwrap();
+//! let names = stmt
+//!     .query_map(|row| Ok(row.column_text(0)?.map(String::from)))
+//!     .unwrap();
+//! assert_eq!(vec![Some(name.clone())], names);
+//! ```
+//!
+//! [`Statement`]: self::Statement
+//! [`prepare`]: self::prepare
+//! [`prepare_write`]: self::prepare_write
+
+use super::{sqlite3, Error, Master, Slave};
+
+/// `Statement` is a prepared SQL statement.
+///
+/// It is finalized when dropped.
+pub struct Statement<'a> {
+    stmt: sqlite3::Stmt<'a>,
+}
+
+/// Prepares `sql` on the connection of `session` , and returns the statement.
+///
+/// Returns `Err` ("SQLITE_READONLY") if `sql` may modify the database. (See function
+/// [`prepare_write`] .)
+///
+/// [`prepare_write`]: self::prepare_write
+pub fn prepare<'a, S>(sql: &'a str, session: &'a mut S) -> Result<Statement<'a>, Error>
+where
+    S: Slave,
+{
+    let stmt = sqlite3::prepare(sql, session)?;
+    if stmt.is_readonly() {
+        Ok(Statement { stmt })
+    } else {
+        Err(Error::READONLY)
+    }
+}
+
+/// Prepares `sql` on the connection of master `session` , and returns the statement.
+///
+/// Unlike function [`prepare`] , `sql` may modify the database.
+///
+/// [`prepare`]: self::prepare
+pub fn prepare_write<'a, S>(sql: &'a str, session: &'a mut S) -> Result<Statement<'a>, Error>
+where
+    S: Master,
+{
+    sqlite3::prepare(sql, session).map(|stmt| Statement { stmt })
+}
+
+impl<'a> Statement<'a> {
+    /// Executes `self` , and returns `true` if a row is returned, or `false` if finished.
+    ///
+    /// Call this method again to fetch the next row.
+    pub fn step(&mut self) -> Result<bool, Error> {
+        self.stmt.step()
+    }
+
+    /// Resets `self` to execute again. The bound values are kept.
+    pub fn reset(&mut self) {
+        self.stmt.reset()
+    }
+
+    /// Binds integer `val` to the parameter at `index` . (`index` starts at 1.)
+    pub fn bind_int(&mut self, index: usize, val: i64) -> Result<(), Error> {
+        self.stmt.bind_int(index, val)
+    }
+
+    /// Binds binary `val` to the parameter at `index` . (`index` starts at 1.)
+    ///
+    /// `val` is not copied, so it must outlive `self` .
+    pub fn bind_blob(&mut self, index: usize, val: &'a [u8]) -> Result<(), Error> {
+        self.stmt.bind_blob(index, val)
+    }
+
+    /// Binds text `val` to the parameter at `index` . (`index` starts at 1.)
+    ///
+    /// `val` is not copied, so it must outlive `self` .
+    pub fn bind_text(&mut self, index: usize, val: &'a str) -> Result<(), Error> {
+        self.stmt.bind_text(index, val)
+    }
+
+    /// Binds NULL to the parameter at `index` . (`index` starts at 1.)
+    pub fn bind_null(&mut self, index: usize) -> Result<(), Error> {
+        self.stmt.bind_null(index)
+    }
+
+    /// Returns the integer at column `index` of the current row, or `None` if it is NULL.
+    /// (`index` starts at 0.)
+    ///
+    /// Returns `Err` ("SQLITE_MISMATCH") if the value is neither NULL nor an integer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not return `true` , or if `index` is out of range.
+    ///
+    /// [`step`]: Self::step
+    pub fn column_int(&mut self, index: usize) -> Result<Option<i64>, Error> {
+        self.stmt.try_column_int(index)
+    }
+
+    /// Returns the binary at column `index` of the current row, or `None` if it is NULL.
+    /// (`index` starts at 0.)
+    ///
+    /// Returns `Err` ("SQLITE_MISMATCH") if the value is neither NULL nor a binary.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not return `true` , or if `index` is out of range.
+    ///
+    /// [`step`]: Self::step
+    pub fn column_blob(&mut self, index: usize) -> Result<Option<&[u8]>, Error> {
+        self.stmt.try_column_blob(index)
+    }
+
+    /// Same to [`column_blob`] except for that the value is copied.
+    ///
+    /// [`column_blob`]: Self::column_blob
+    pub fn column_blob_owned(&mut self, index: usize) -> Result<Option<Vec<u8>>, Error> {
+        self.column_blob(index).map(|val| val.map(<[u8]>::to_vec))
+    }
+
+    /// Returns the text at column `index` of the current row, or `None` if it is NULL.
+    /// (`index` starts at 0.)
+    ///
+    /// Returns `Err` ("SQLITE_MISMATCH") if the value is neither NULL nor a text, or if it is not
+    /// a valid UTF-8.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not return `true` , or if `index` is out of range.
+    ///
+    /// [`step`]: Self::step
+    pub fn column_text(&mut self, index: usize) -> Result<Option<&str>, Error> {
+        self.stmt.try_column_text(index)
+    }
+
+    /// Executes `self` to the end, and collects the result of `f` for each row.
+    pub fn query_map<T, F>(&mut self, mut f: F) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&mut Self) -> Result<T, Error>,
+    {
+        let mut ret = Vec::new();
+        while self.step()? {
+            match f(self) {
+                Ok(t) => ret.push(t),
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Returns the number of the rows that the last execution on the connection modified.
+    pub fn last_changes(&self) -> usize {
+        self.stmt.last_changes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::{self, Environment};
+
+    fn env() -> Environment {
+        let env = Environment::default();
+        {
+            let mut session = rdb::master(&env);
+            let sql = "CREATE TABLE app_values(i INTEGER, b BLOB, t TEXT)";
+            prepare_write(sql, &mut session).unwrap().step().unwrap();
+            let sql = "INSERT INTO app_values VALUES(1, x'01', 'a')";
+            prepare_write(sql, &mut session).unwrap().step().unwrap();
+        }
+        env
+    }
+
+    #[test]
+    fn column_mismatch() {
+        let env = env();
+        let mut session = rdb::slave(&env);
+        let mut stmt = prepare("SELECT i, b, t FROM app_values", &mut session).unwrap();
+        assert_eq!(Ok(true), stmt.step());
+
+        assert_eq!(Ok(Some(1)), stmt.column_int(0));
+        assert_eq!(Ok(Some(vec![1])), stmt.column_blob_owned(1));
+        assert_eq!(Ok(Some("a")), stmt.column_text(2));
+
+        assert_eq!(true, stmt.column_int(2).unwrap_err().is_mismatch());
+        assert_eq!(true, stmt.column_blob(0).unwrap_err().is_mismatch());
+        assert_eq!(true, stmt.column_blob_owned(2).unwrap_err().is_mismatch());
+        assert_eq!(true, stmt.column_text(1).unwrap_err().is_mismatch());
+    }
+
+    #[test]
+    fn prepare_readonly() {
+        let env = env();
+
+        // The slave session shares the connection with the master because
+        // '--rdb-read-connections' is 0, but it still cannot write.
+        let mut session = rdb::slave(&env);
+        for sql in &[
+            "INSERT INTO app_values VALUES(2, NULL, NULL)",
+            "DELETE FROM app_values",
+            "DROP TABLE app_values",
+        ] {
+            match prepare(sql, &mut session) {
+                Err(e) => assert_eq!(true, e.is_readonly()),
+                Ok(_) => panic!("'{}' is prepared on a slave session.", sql),
+            }
+        }
+        drop(session);
+
+        let mut session = rdb::master(&env);
+        let sql = "SELECT COUNT(*) FROM app_values";
+        let mut stmt = prepare(sql, &mut session).unwrap();
+        assert_eq!(Ok(vec![Some(1)]), stmt.query_map(|stmt| stmt.column_int(0)));
+    }
+}
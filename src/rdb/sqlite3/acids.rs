@@ -15,9 +15,14 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::{Error, Master, Slave, Sqlite3Session};
-use crate::data_types::{ChainIndex, CryptoHash, Id};
+use crate::clock;
+use crate::data_types::{BlockHeight, ChainIndex, Id};
 use std::borrow::Borrow;
 use std::collections::HashMap;
+use std::time::Duration;
+
+#[cfg(feature = "strict")]
+use super::SQLITE_CONSTRAINT_FOREIGNKEY;
 
 /// Make sure to create table "acids".
 ///
@@ -33,13 +38,40 @@ where
         const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS acids(
         seq INTEGER PRIMARY KEY,
         id BLOB UNIQUE NOT NULL,
-        chain_height INTEGER DEFAULT NULL
+        chain_height INTEGER DEFAULT NULL,
+        priority INTEGER DEFAULT NULL,
+        received_at INTEGER DEFAULT NULL
         )"#;
 
         let mut stmt = session.con.stmt_once(SQL)?;
         stmt.step()?;
     }
 
+    // Add column priority to the table created by the older version.
+    if !has_column("priority", session)? {
+        const SQL: &'static str = r#"ALTER TABLE acids ADD COLUMN priority INTEGER DEFAULT NULL"#;
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Add column received_at to the table created by the older version.
+    // The acids already in mempool are regarded to be received now.
+    if !has_column("received_at", session)? {
+        {
+            const SQL: &'static str =
+                r#"ALTER TABLE acids ADD COLUMN received_at INTEGER DEFAULT NULL"#;
+            let mut stmt = session.con.stmt_once(SQL)?;
+            stmt.step()?;
+        }
+        {
+            const SQL: &'static str =
+                r#"UPDATE acids SET received_at = ?1 WHERE chain_height IS NULL"#;
+            let mut stmt = session.con.stmt_once(SQL)?;
+            stmt.bind_int(1, clock::system_now())?;
+            stmt.step()?;
+        }
+    }
+
     // Create index for column chain_height.
     {
         const SQL: &'static str =
@@ -49,13 +81,72 @@ where
         stmt.step()?;
     }
 
+    // Create index to fetch mempool by the priority.
+    {
+        const SQL: &'static str = r#"CREATE INDEX IF NOT EXISTS mempool_priority_
+            ON acids(priority DESC, seq ASC) WHERE chain_height IS NULL"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Create index to purge mempool by the age.
+    {
+        const SQL: &'static str = r#"CREATE INDEX IF NOT EXISTS mempool_received_at_
+            ON acids(received_at) WHERE chain_height IS NULL"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Refuse to move the acids in the finalized blocks to mempool.
+    // (Table "main_chain_finality" is created by 'main_chain::create_table()'.)
+    {
+        const SQL: &'static str = r#"CREATE TRIGGER IF NOT EXISTS keep_finalized_acids_
+            BEFORE UPDATE OF chain_height ON acids
+            WHEN OLD.chain_height <= (SELECT height FROM main_chain_finality WHERE id = 0)
+            BEGIN
+                SELECT RAISE(ABORT, 'finalized acid cannot be reverted');
+            END"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Table "acids_pruning" has at most 1 row to store the height that the block data is pruned
+    // below.
+    {
+        const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS acids_pruning(
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            height INTEGER NOT NULL
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
     Ok(())
 }
 
+/// Returns `true` if table "acids" has column `name` .
+fn has_column(name: &'static str, session: &mut Sqlite3Session) -> Result<bool, Error> {
+    // 'name' is a constant; it need not be escaped.
+    let sql = format!(
+        "SELECT COUNT(*) FROM pragma_table_info('acids') WHERE name = '{}'",
+        name
+    );
+
+    let mut stmt = session.con.stmt_once(&sql)?;
+    stmt.step()?;
+    Ok(stmt.column_int(0).unwrap_or(0) != 0)
+}
+
 /// Inserts each [`Id`] of `acids` with NULL "chain_height" into RDB table "acids" if the [`Id`] is
 /// not in the table yet.
 /// (NULL "chain_height" represents mempool.)
 ///
+/// "received_at" is set to the current system time in milliseconds.
+///
 /// [`Id`]: crate::data_types::Id
 pub fn accept_to_mempool<I, S, A>(acids: I, session: &mut S) -> Result<(), Error>
 where
@@ -65,12 +156,47 @@ where
 {
     let session = Sqlite3Session::as_sqlite3_session(session);
 
-    const SQL: &'static str = r#"INSERT INTO acids (id) VALUES (?1) ON CONFLICT DO NOTHING"#;
+    const SQL: &'static str =
+        r#"INSERT INTO acids (id, received_at) VALUES (?1, ?2) ON CONFLICT DO NOTHING"#;
     let stmt = session.con.stmt(SQL)?;
+    let now = clock::system_now();
 
     for id in acids {
         let id = id.borrow();
         stmt.bind_blob(1, id.as_ref())?;
+        stmt.bind_int(2, now)?;
+        stmt.step()?;
+    }
+
+    Ok(())
+}
+
+/// Same to [`accept_to_mempool`] except for that "priority" is set as well.
+///
+/// `acids` is an iterator of ([`Id`] , priority) or a reference to it. If the [`Id`] is already
+/// in mempool, the priority is overwritten. The [`Id`] which is not in mempool is ignored.
+///
+/// [`accept_to_mempool`]: self::accept_to_mempool
+/// [`Id`]: crate::data_types::Id
+pub fn accept_to_mempool_with_priority<I, S, B, A>(acids: I, session: &mut S) -> Result<(), Error>
+where
+    I: Iterator<Item = B>,
+    S: Master,
+    B: Borrow<(A, i64)>,
+    A: Borrow<Id>,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"INSERT INTO acids (id, priority, received_at) VALUES (?1, ?2, ?3)
+        ON CONFLICT (id) DO UPDATE SET priority = excluded.priority WHERE chain_height IS NULL"#;
+    let stmt = session.con.stmt(SQL)?;
+    let now = clock::system_now();
+
+    for b in acids {
+        let (id, priority) = b.borrow();
+        stmt.bind_blob(1, id.borrow().as_ref())?;
+        stmt.bind_int(2, *priority)?;
+        stmt.bind_int(3, now)?;
         stmt.step()?;
     }
 
@@ -83,6 +209,7 @@ where
 /// # Safety
 ///
 /// The behavior is undefined if `chain_index` is not in the "main_chain".
+/// (If feature "strict" is specified, this function fails instead.)
 pub unsafe fn mempool_to_chain<I, S, A>(
     chain_index: &ChainIndex,
     acids: I,
@@ -95,6 +222,9 @@ where
 {
     let session = Sqlite3Session::as_sqlite3_session(session);
 
+    #[cfg(feature = "strict")]
+    check_main_chain(chain_index, session)?;
+
     const SQL: &'static str =
         r#"UPDATE acids SET chain_height = ?1 WHERE id = ?2 AND chain_height IS NULL"#;
     let stmt = session.con.stmt(SQL)?;
@@ -115,15 +245,21 @@ where
 
 /// Moves acids included in `chain_index` to mempool, and returns the number of acids to be moved.
 ///
+/// Fails if `chain_index` is finalized.
+///
 /// # Safety
 ///
 /// The behavior is undefined if `chain_index` is not in the "main_chain".
+/// (If feature "strict" is specified, this function fails instead.)
 pub unsafe fn chain_to_mempool<S>(chain_index: &ChainIndex, session: &mut S) -> Result<usize, Error>
 where
     S: Master,
 {
     let session = Sqlite3Session::as_sqlite3_session(session);
 
+    #[cfg(feature = "strict")]
+    check_main_chain(chain_index, session)?;
+
     const SQL: &'static str = r#"UPDATE acids SET chain_height = NULL WHERE chain_height = ?1"#;
     let stmt = session.con.stmt(SQL)?;
 
@@ -133,6 +269,44 @@ where
     Ok(stmt.last_changes())
 }
 
+/// Returns an error unless `chain_index` is in the "main_chain".
+#[cfg(feature = "strict")]
+fn check_main_chain(chain_index: &ChainIndex, session: &mut Sqlite3Session) -> Result<(), Error> {
+    const SQL: &'static str = r#"SELECT id FROM main_chain WHERE height = ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, chain_index.height())?;
+
+    let is_found = stmt.step()? && stmt.column_hash::<Id>(0)? == Some(*chain_index.id());
+    stmt.reset();
+
+    if is_found {
+        Ok(())
+    } else {
+        Err(Error::new(SQLITE_CONSTRAINT_FOREIGNKEY))
+    }
+}
+
+/// Deletes the acids in mempool received more than `age` ago, and returns the number of the
+/// deleted acids.
+///
+/// The acids whose "received_at" is NULL are never deleted.
+pub fn purge_mempool_older_than<S>(age: Duration, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str =
+        r#"DELETE FROM acids WHERE chain_height IS NULL AND received_at < ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    let age = age.as_millis().min(clock::Timestamp::MAX as u128) as clock::Timestamp;
+    stmt.bind_int(1, clock::system_now().saturating_sub(age))?;
+    stmt.step()?;
+
+    Ok(stmt.last_changes())
+}
+
 /// Fetches the state of each acid in `acids` .
 ///
 /// For each [`Id`] in `acids` ,
@@ -170,13 +344,12 @@ where
         stmt.bind_blob(1, id.as_ref())?;
         if stmt.step()? {
             let height = stmt.column_int(0);
-            match stmt.column_blob(1) {
+            match stmt.column_hash::<Id>(1)? {
                 None => {
                     ret.insert(*id, None);
                 }
                 Some(id_) => {
                     let height = height.unwrap();
-                    let id_ = unsafe { Id::copy_bytes(id_) };
                     ret.insert(*id, Some(ChainIndex::new(height, &id_)));
                 }
             }
@@ -215,16 +388,202 @@ where
 
     while stmt.step()? {
         let seq = stmt.column_int(0).unwrap();
-        let id = unsafe { Id::copy_bytes(stmt.column_blob(1).unwrap()) };
+        let id = stmt.column_hash::<Id>(1)?.unwrap();
+        ret.push((seq, id));
+    }
+
+    Ok(ret)
+}
+
+/// Fetches at most `limit` number of [`Acid`] from mempool in order of the priority desc, and
+/// returns a slice of `(record sequence number, the id of the acid)` .
+///
+/// The acids with the same priority are ordered by the record sequence number, and those without
+/// the priority follow all the others.
+///
+/// [`Acid`]: crate::data_types::Acid
+pub fn fetch_mempool_by_priority<S>(
+    limit: u32,
+    session: &mut S,
+) -> Result<impl AsRef<[(i64, Id)]>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT seq, id FROM acids
+    WHERE chain_height IS NULL ORDER BY priority DESC, seq ASC LIMIT ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, limit as i64)?;
+
+    let mut ret = Vec::with_capacity(limit as usize);
+
+    while stmt.step()? {
+        let seq = stmt.column_int(0).unwrap();
+        let id = stmt.column_hash::<Id>(1)?.unwrap();
+        ret.push((seq, id));
+    }
+
+    Ok(ret)
+}
+
+/// Fetches at most `limit` number of [`Id`] in order of the record sequence number regardless of
+/// "chain_height", and returns a slice of `(record sequence number, the id)` .
+///
+/// If `min_seq` is not `None` , this method ignores the records whose sequence number is less
+/// than `min_seq` .
+///
+/// [`Id`]: crate::data_types::Id
+pub fn fetch_ids<S>(
+    min_seq: Option<i64>,
+    limit: u32,
+    session: &mut S,
+) -> Result<impl AsRef<[(i64, Id)]>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT seq, id FROM acids
+    WHERE seq >= ?1 ORDER BY seq ASC LIMIT ?2"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    let min_seq = min_seq.unwrap_or(0);
+    stmt.bind_int(1, min_seq)?;
+    stmt.bind_int(2, limit as i64)?;
+
+    let mut ret = Vec::with_capacity(limit as usize);
+
+    while stmt.step()? {
+        let seq = stmt.column_int(0).unwrap();
+        let id = stmt.column_hash::<Id>(1)?.unwrap();
         ret.push((seq, id));
     }
 
     Ok(ret)
 }
 
+/// Fetches the ids of the acids whose "chain_height" is greater than or equals to `min_height`
+/// and less than `max_height` , ordered by "chain_height" and the record sequence number.
+pub fn fetch_by_chain_height<S>(
+    min_height: BlockHeight,
+    max_height: BlockHeight,
+    session: &mut S,
+) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT id FROM acids
+    WHERE chain_height >= ?1 AND chain_height < ?2 ORDER BY chain_height ASC, seq ASC"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, min_height)?;
+    stmt.bind_int(2, max_height)?;
+
+    stmt.query_map(|stmt| Ok(stmt.column_hash::<Id>(0)?.unwrap()))
+}
+
+/// Returns the height that the block data is pruned below if any, or `None` .
+pub fn pruned_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT height FROM acids_pruning WHERE id = 0"#;
+    let stmt = session.con.stmt(SQL)?;
+    if stmt.step()? {
+        Ok(stmt.column_int(0))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Records that the block data is pruned below `height` , and returns `true` if the height is
+/// changed. (The height never decreases.)
+pub fn set_pruned_height<S>(height: BlockHeight, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"INSERT INTO acids_pruning (id, height) VALUES (0, ?1)
+        ON CONFLICT(id) DO UPDATE SET height = excluded.height WHERE excluded.height > height"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, height)?;
+    stmt.step()?;
+
+    Ok(0 < stmt.last_changes())
+}
+
+/// Fetches at most `limit` acids whose "chain_height" is less than or equals to `max_height` in
+/// order of the record sequence number, and returns a vector of `(record sequence number,
+/// chain_height, the id)` .
+///
+/// If `min_seq` is not `None` , this method ignores the records whose sequence number is less
+/// than `min_seq` .
+pub fn fetch_chain<S>(
+    min_seq: Option<i64>,
+    max_height: BlockHeight,
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(i64, BlockHeight, Id)>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT seq, chain_height, id FROM acids
+    WHERE seq >= ?1 AND chain_height <= ?2 ORDER BY seq ASC LIMIT ?3"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, min_seq.unwrap_or(0))?;
+    stmt.bind_int(2, max_height)?;
+    stmt.bind_int(3, limit as i64)?;
+
+    stmt.query_map(|stmt| {
+        let seq = stmt.column_int(0).unwrap();
+        let height = stmt.column_int(1).unwrap();
+        let id = stmt.column_hash::<Id>(2)?.unwrap();
+        Ok((seq, height, id))
+    })
+}
+
+/// Inserts each `(chain_height, id)` of `acids` into table "acids", and returns the number of
+/// the inserted or the changed rows.
+///
+/// The acid in the mempool is moved into the chain, and the one already in the chain is not
+/// changed.
+pub fn insert_chain<I, S, B>(acids: I, session: &mut S) -> Result<usize, Error>
+where
+    I: Iterator<Item = B>,
+    S: Master,
+    B: Borrow<(BlockHeight, Id)>,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"INSERT INTO acids (id, chain_height) VALUES (?1, ?2)
+        ON CONFLICT(id) DO UPDATE SET chain_height = excluded.chain_height
+        WHERE chain_height IS NULL"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    let mut ret = 0;
+    for acid in acids {
+        let (height, id) = acid.borrow();
+        stmt.bind_blob(1, id.as_ref())?;
+        stmt.bind_int(2, *height)?;
+        stmt.step()?;
+
+        ret += stmt.last_changes();
+    }
+
+    Ok(ret)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_types::CryptoHash;
     use crate::rdb::sqlite3::{main_chain, master, Environment};
 
     const ACID_COUNT: usize = 10;
@@ -302,6 +661,7 @@ mod tests {
         let mut session = master(&env);
 
         let chain_index = ChainIndex::new(1, &Id::zeroed());
+        main_chain::push(&chain_index, &mut session).unwrap();
         assert_eq!(Ok(1), unsafe {
             mempool_to_chain(&chain_index, ids()[0..1].iter(), &mut session)
         });
@@ -320,6 +680,7 @@ mod tests {
         let env = filled_table();
         let mut session = master(&env);
         let chain_index = ChainIndex::new(1, &Id::zeroed());
+        main_chain::push(&chain_index, &mut session).unwrap();
 
         assert_eq!(Ok(0), unsafe {
             chain_to_mempool(&chain_index, &mut session)
@@ -331,6 +692,28 @@ mod tests {
         });
     }
 
+    #[cfg(feature = "strict")]
+    #[test]
+    fn strict_main_chain() {
+        let env = filled_table();
+        let mut session = master(&env);
+
+        let chain_index = ChainIndex::new(1, &Id::zeroed());
+        assert_eq!(true, unsafe {
+            mempool_to_chain(&chain_index, ids().iter(), &mut session).is_err()
+        });
+        assert_eq!(true, unsafe {
+            chain_to_mempool(&chain_index, &mut session).is_err()
+        });
+
+        let mut other = Id::zeroed();
+        other[0] = 0xff;
+        main_chain::push(&ChainIndex::new(1, &other), &mut session).unwrap();
+        assert_eq!(true, unsafe {
+            mempool_to_chain(&chain_index, ids().iter(), &mut session).is_err()
+        });
+    }
+
     #[test]
     fn fetch_state_from_empty_table() {
         let env = empty_table();
@@ -363,4 +746,148 @@ mod tests {
             assert_eq!(None, fetched[id]);
         }
     }
+
+    #[test]
+    fn fetch_ids_() {
+        let env = filled_table();
+        let mut session = master(&env);
+
+        let chain_index = ChainIndex::new(1, &Id::zeroed());
+        main_chain::push(&chain_index, &mut session).unwrap();
+        unsafe { mempool_to_chain(&chain_index, ids()[0..5].iter(), &mut session).unwrap() };
+
+        let fetched = fetch_ids(None, ACID_COUNT as u32, &mut session).unwrap();
+        let fetched: Vec<Id> = fetched.as_ref().iter().map(|(_, id)| *id).collect();
+        assert_eq!(ids(), fetched);
+
+        let fetched = fetch_ids(None, 3, &mut session).unwrap();
+        assert_eq!(3, fetched.as_ref().len());
+
+        let next = fetched.as_ref()[2].0 + 1;
+        let fetched = fetch_ids(Some(next), ACID_COUNT as u32, &mut session).unwrap();
+        let fetched: Vec<Id> = fetched.as_ref().iter().map(|(_, id)| *id).collect();
+        assert_eq!(&ids()[3..], &fetched[..]);
+    }
+
+    #[test]
+    fn purge_mempool_older_than_() {
+        let env = filled_table();
+        let mut session = master(&env);
+        let ids = ids();
+
+        // Nothing is old enough.
+        let purged = purge_mempool_older_than(Duration::from_secs(3600), &mut session).unwrap();
+        assert_eq!(0, purged);
+
+        // Pretend ids[0..4] were received 2 hours ago, and ids[3] is mined.
+        {
+            let session = Sqlite3Session::as_sqlite3_session(&mut session);
+            let sql = format!(
+                "UPDATE acids SET received_at = {} WHERE seq <= 4",
+                clock::system_now() - 2 * 3600 * 1000
+            );
+            let mut stmt = session.con.stmt_once(&sql).unwrap();
+            stmt.step().unwrap();
+        }
+        let chain_index = ChainIndex::new(1, &Id::zeroed());
+        main_chain::push(&chain_index, &mut session).unwrap();
+        unsafe { mempool_to_chain(&chain_index, ids[3..4].iter(), &mut session).unwrap() };
+
+        let purged = purge_mempool_older_than(Duration::from_secs(3600), &mut session).unwrap();
+        assert_eq!(3, purged);
+
+        let states = fetch_state(ids.iter(), &mut session).unwrap();
+        assert_eq!(ACID_COUNT - 3, states.len());
+        assert_eq!(false, ids[0..3].iter().any(|id| states.contains_key(id)));
+        assert_eq!(Some(&Some(chain_index)), states.get(&ids[3]));
+    }
+
+    #[test]
+    fn fetch_mempool_by_priority_() {
+        let env = filled_table();
+        let mut session = master(&env);
+        let ids = ids();
+
+        // Without priority, ordered by the sequence number.
+        let fetched = fetch_mempool_by_priority(3, &mut session).unwrap();
+        let fetched: Vec<Id> = fetched.as_ref().iter().map(|(_, id)| *id).collect();
+        assert_eq!(&ids[0..3], &fetched[..]);
+
+        // ids[5] and ids[7] with priority 10, ids[2] with priority 20.
+        let priorities = [(ids[5], 10), (ids[2], 20), (ids[7], 10)];
+        accept_to_mempool_with_priority(priorities.iter(), &mut session).unwrap();
+
+        // Mining ids[7].
+        let chain_index = ChainIndex::new(1, &Id::zeroed());
+        main_chain::push(&chain_index, &mut session).unwrap();
+        unsafe { mempool_to_chain(&chain_index, ids[7..8].iter(), &mut session).unwrap() };
+
+        // The priority of a mined acid is not changed.
+        accept_to_mempool_with_priority([(ids[7], 30)].iter(), &mut session).unwrap();
+
+        let fetched = fetch_mempool_by_priority(4, &mut session).unwrap();
+        let fetched: Vec<Id> = fetched.as_ref().iter().map(|(_, id)| *id).collect();
+        assert_eq!(vec![ids[2], ids[5], ids[0], ids[1]], fetched);
+    }
+
+    #[test]
+    fn pruning() {
+        let env = filled_table();
+        let mut session = master(&env);
+        let ids = ids();
+
+        for height in 1..=3 {
+            let chain_index = ChainIndex::new(height, &ids[height as usize]);
+            main_chain::push(&chain_index, &mut session).unwrap();
+            let acids = ids[(height as usize * 2)..(height as usize * 2 + 2)].iter();
+            unsafe { mempool_to_chain(&chain_index, acids, &mut session).unwrap() };
+        }
+
+        assert_eq!(
+            &ids[2..6],
+            &fetch_by_chain_height(1, 3, &mut session).unwrap()[..]
+        );
+        assert_eq!(
+            true,
+            fetch_by_chain_height(4, 9, &mut session)
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(None, pruned_height(&mut session).unwrap());
+        assert_eq!(true, set_pruned_height(2, &mut session).unwrap());
+        assert_eq!(false, set_pruned_height(1, &mut session).unwrap());
+        assert_eq!(false, set_pruned_height(2, &mut session).unwrap());
+        assert_eq!(Some(2), pruned_height(&mut session).unwrap());
+    }
+
+    #[test]
+    fn chain() {
+        let env = empty_table();
+        let mut session = master(&env);
+        let ids = ids();
+
+        // ids[0] is in the mempool, and ids[1] is in the chain already.
+        accept_to_mempool(ids[0..2].iter(), &mut session).unwrap();
+        assert_eq!(1, insert_chain([(1, ids[1])].iter(), &mut session).unwrap());
+
+        let acids = [(1, ids[0]), (1, ids[1]), (2, ids[2]), (3, ids[3])];
+        assert_eq!(3, insert_chain(acids.iter(), &mut session).unwrap());
+        assert_eq!(
+            0,
+            fetch_mempool(None, 10, &mut session)
+                .unwrap()
+                .as_ref()
+                .len()
+        );
+
+        let fetched = fetch_chain(None, 2, 10, &mut session).unwrap();
+        let fetched: Vec<(BlockHeight, Id)> = fetched.iter().map(|&(_, h, id)| (h, id)).collect();
+        assert_eq!(vec![(1, ids[0]), (1, ids[1]), (2, ids[2])], fetched);
+
+        let first = fetch_chain(None, 3, 1, &mut session).unwrap();
+        assert_eq!(1, first.len());
+        let rest = fetch_chain(Some(first[0].0 + 1), 3, 10, &mut session).unwrap();
+        assert_eq!(3, rest.len());
+    }
 }
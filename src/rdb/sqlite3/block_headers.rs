@@ -0,0 +1,229 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Error, Master, Slave, Sqlite3Session, Stmt};
+use crate::data_types::{BlockHeight, Id};
+use crate::rdb::block_headers::BlockHeader;
+
+/// Make sure to create table "block_headers".
+///
+/// This method does nothing if the table is.
+pub fn create_table<S>(session: &mut S) -> Result<(), Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    {
+        const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS block_headers(
+            id BLOB PRIMARY KEY,
+            parent BLOB NOT NULL,
+            height INTEGER NOT NULL,
+            work INTEGER NOT NULL CHECK (work >= 0)
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Create index to look up the children.
+    {
+        const SQL: &'static str =
+            r#"CREATE INDEX IF NOT EXISTS block_parent_ ON block_headers(parent)"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    Ok(())
+}
+
+fn read_header(stmt: &mut Stmt) -> Result<BlockHeader, Error> {
+    Ok(BlockHeader {
+        id: stmt.column_hash::<Id>(0)?.unwrap(),
+        parent: stmt.column_hash::<Id>(1)?.unwrap(),
+        height: stmt.column_int(2).unwrap(),
+        work: stmt.column_int(3).unwrap(),
+    })
+}
+
+/// Inserts `header` , and returns `true` if it is inserted newly, or `false` if the id has
+/// already been.
+pub fn insert<S>(header: &BlockHeader, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"INSERT INTO block_headers (id, parent, height, work)
+        VALUES (?1, ?2, ?3, ?4) ON CONFLICT DO NOTHING"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, header.id.as_ref())?;
+    stmt.bind_blob(2, header.parent.as_ref())?;
+    stmt.bind_int(3, header.height)?;
+    stmt.bind_int(4, header.work)?;
+    stmt.step()?;
+
+    Ok(stmt.last_changes() == 1)
+}
+
+/// Fetches the header whose id is `id` if any, or `None` .
+pub fn fetch<S>(id: &Id, session: &mut S) -> Result<Option<BlockHeader>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT id, parent, height, work FROM block_headers WHERE id = ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, id.as_ref())?;
+
+    if stmt.step()? {
+        let header = read_header(stmt)?;
+        Ok(Some(header))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Fetches the headers which are not in RDB table "main_chain" and have no child, ordered by the
+/// height desc and the id.
+pub fn fetch_side_tips<S>(session: &mut S) -> Result<Vec<BlockHeader>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT id, parent, height, work FROM block_headers AS h
+        WHERE NOT EXISTS (SELECT 1 FROM main_chain WHERE main_chain.id = h.id)
+        AND NOT EXISTS (SELECT 1 FROM block_headers AS c WHERE c.parent = h.id)
+        ORDER BY height DESC, id ASC"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.query_map(read_header)
+}
+
+/// Deletes the headers which are not in RDB table "main_chain" and whose height is less than or
+/// equals to `height` , and returns the number of the deleted rows.
+pub fn prune_side<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"DELETE FROM block_headers WHERE height <= ?1
+        AND id NOT IN (SELECT id FROM main_chain)"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, height)?;
+    stmt.step()?;
+
+    Ok(stmt.last_changes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::{ChainIndex, CryptoHash};
+    use crate::rdb::sqlite3::{main_chain, master, Environment};
+
+    fn empty_table() -> Environment {
+        let env = Environment::default();
+        {
+            let mut session = master(&env);
+            main_chain::create_table(&mut session).unwrap();
+            create_table(&mut session).unwrap();
+        }
+        env
+    }
+
+    fn id(i: u8) -> Id {
+        let mut ret = Id::zeroed();
+        ret[0] = i;
+        ret
+    }
+
+    fn header(i: u8, parent: u8, height: BlockHeight) -> BlockHeader {
+        BlockHeader {
+            id: id(i),
+            parent: id(parent),
+            height,
+            work: i as i64,
+        }
+    }
+
+    #[test]
+    fn create_table_() {
+        let env = Environment::default();
+        let mut session = master(&env);
+
+        assert_eq!(true, create_table(&mut session).is_ok());
+        assert_eq!(true, create_table(&mut session).is_ok());
+    }
+
+    #[test]
+    fn insert_and_fetch() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        assert_eq!(true, insert(&header(1, 0, 1), &mut session).unwrap());
+        assert_eq!(false, insert(&header(1, 0, 1), &mut session).unwrap());
+
+        assert_eq!(Some(header(1, 0, 1)), fetch(&id(1), &mut session).unwrap());
+        assert_eq!(None, fetch(&id(2), &mut session).unwrap());
+
+        let mut bad = header(2, 1, 2);
+        bad.work = -1;
+        assert_eq!(true, insert(&bad, &mut session).is_err());
+    }
+
+    #[test]
+    fn side_tips() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        // Main chain: 1 <- 2 <- 3
+        // Side branch: 2 <- 4 <- 5, 2 <- 6
+        for h in &[
+            header(1, 0, 1),
+            header(2, 1, 2),
+            header(3, 2, 3),
+            header(4, 2, 3),
+            header(5, 4, 4),
+            header(6, 2, 3),
+        ] {
+            insert(h, &mut session).unwrap();
+        }
+        for i in 1..=3 {
+            main_chain::push(&ChainIndex::new(i as i64, &id(i)), &mut session).unwrap();
+        }
+
+        assert_eq!(
+            vec![header(5, 4, 4), header(6, 2, 3)],
+            fetch_side_tips(&mut session).unwrap()
+        );
+
+        assert_eq!(2, prune_side(3, &mut session).unwrap());
+        assert_eq!(None, fetch(&id(4), &mut session).unwrap());
+        assert_eq!(Some(header(3, 2, 3)), fetch(&id(3), &mut session).unwrap());
+
+        // id(5) is an orphan now.
+        assert_eq!(
+            vec![header(5, 4, 4)],
+            fetch_side_tips(&mut session).unwrap()
+        );
+    }
+}
@@ -15,41 +15,30 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::{
-    sqlite3, sqlite3_close, sqlite3_open_v2, Error, Stmt, SQLITE_OPEN_MEMORY, SQLITE_OPEN_NOMUTEX,
-    SQLITE_OPEN_READWRITE,
+    sqlite3, sqlite3_close, sqlite3_open_v2, Error, Stmt, SQLITE_OPEN_CREATE, SQLITE_OPEN_MEMORY,
+    SQLITE_OPEN_NOMUTEX, SQLITE_OPEN_READONLY, SQLITE_OPEN_READWRITE,
 };
 use core::convert::TryFrom;
 use core::ptr;
-use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::os::raw::{c_char, c_int};
 use std::path::Path;
+use std::time::Duration;
 
-/// New type of `&'static str` , which is compared by the address.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Sql(*const u8);
-
-#[cfg(test)]
-mod sql_tests {
-    use super::*;
-
-    #[test]
-    fn equality() {
-        const A: &'static str = "aa";
-        const B: &'static str = "ab";
-        const C: &'static str = "a";
-
-        assert_ne!(Sql(A.as_ptr()), Sql(B.as_ptr()));
-        assert_ne!(Sql(A.as_ptr()), Sql(C.as_ptr()));
-    }
-}
+/// The max number of the [`Stmt`] instances that each [`Connection`] caches.
+const STMT_CACHE_CAPACITY: usize = 256;
 
 /// Wrapper of C struct [`sqlite3`]
 ///
 /// [`sqlite3`]: https://www.sqlite.org/c3ref/sqlite3.html
 pub struct Connection {
     raw: *mut sqlite3,
-    stmts: HashMap<Sql, Stmt<'static>>,
+    /// The cached statements keyed by the SQL, and when each one was used last.
+    stmts: HashMap<String, (Stmt<'static>, u64)>,
+    stmt_capacity: usize,
+    clock: u64,
+    busy_timeout: Duration,
 }
 
 unsafe impl Send for Connection {}
@@ -65,24 +54,56 @@ impl TryFrom<&Path> for Connection {
     type Error = Box<dyn std::error::Error>;
 
     fn try_from(filename: &Path) -> Result<Self, Self::Error> {
+        const FLAGS: c_int = SQLITE_OPEN_READWRITE | SQLITE_OPEN_CREATE | SQLITE_OPEN_NOMUTEX;
+        Self::open(filename, FLAGS)
+    }
+}
+
+impl Connection {
+    /// Opens the database file `filename` in `flags` and returns a new instance.
+    fn open(filename: &Path, flags: c_int) -> Result<Self, Box<dyn std::error::Error>> {
         let filename = CString::new(filename.to_string_lossy().as_bytes()).map_err(Box::new)?;
         let mut raw: *mut sqlite3 = ptr::null_mut();
-        const FLAGS: c_int = SQLITE_OPEN_READWRITE | SQLITE_OPEN_MEMORY | SQLITE_OPEN_NOMUTEX;
         const ZVFS: *const c_char = ptr::null();
 
-        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut raw, FLAGS, ZVFS) };
+        let code = unsafe { sqlite3_open_v2(filename.as_ptr(), &mut raw, flags, ZVFS) };
         match Error::new(code) {
             Error::OK => Ok(Self {
                 raw,
                 stmts: Default::default(),
+                stmt_capacity: STMT_CACHE_CAPACITY,
+                clock: 0,
+                busy_timeout: Duration::default(),
             }),
-            e => Err(Box::new(e)),
+            e => {
+                // sqlite3_open_v2() allocates the handle even if it fails.
+                unsafe { sqlite3_close(raw) };
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Opens the database file `filename` read-only and returns a new instance.
+    pub fn open_read_only(filename: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        const FLAGS: c_int = SQLITE_OPEN_READONLY | SQLITE_OPEN_NOMUTEX;
+        Self::open(filename, FLAGS)
+    }
+
+    /// Sets how long the statements of `self` retry while another connection is locking the
+    /// database.
+    ///
+    /// See also method [`Stmt::step`] .
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+        for (stmt, _) in self.stmts.values_mut() {
+            stmt.set_busy_timeout(timeout);
         }
     }
-}
 
-impl Connection {
     /// Opens in-memory database and returns a new instance.
+    ///
+    /// The database is discarded when the instance is dropped. It is for the tests and for the
+    /// placeholder until the database file is opened.
     pub fn open_memory_db() -> Result<Self, Error> {
         let filename: *const c_char = "memory_db".as_ptr() as *const c_char;
         let mut raw: *mut sqlite3 = ptr::null_mut();
@@ -94,6 +115,9 @@ impl Connection {
             Error::OK => Ok(Self {
                 raw,
                 stmts: Default::default(),
+                stmt_capacity: STMT_CACHE_CAPACITY,
+                clock: 0,
+                busy_timeout: Duration::default(),
             }),
             e => Err(e),
         }
@@ -101,21 +125,58 @@ impl Connection {
 
     /// Creates [`Stmt`] instance.
     pub fn stmt_once<'a>(&'a mut self, sql: &'a str) -> Result<Stmt<'a>, Error> {
-        Stmt::new(sql, unsafe { &mut *self.raw })
+        let mut stmt = Stmt::new(sql, unsafe { &mut *self.raw })?;
+        stmt.set_busy_timeout(self.busy_timeout);
+        Ok(stmt)
     }
 
     /// Creates and caches [`Stmt`] if not cached and provides a reference to the cached instance.
+    ///
+    /// See also method [`stmt_cached`] .
+    ///
+    /// [`stmt_cached`]: Self::stmt_cached
     pub fn stmt(&mut self, sql: &'static str) -> Result<&mut Stmt<'static>, Error> {
-        match self.stmts.entry(Sql(sql.as_ptr())) {
-            Entry::Occupied(o) => {
-                let stmt = o.into_mut();
-                stmt.clear();
-                Ok(stmt)
-            }
-            Entry::Vacant(v) => {
-                let stmt = Stmt::new(sql, unsafe { &mut *self.raw })?;
-                Ok(v.insert(stmt))
+        self.stmt_cached(sql)
+    }
+
+    /// Creates and caches [`Stmt`] if not cached and provides a reference to the cached instance.
+    ///
+    /// The statements are cached by the content of `sql` , so the same SQL built dynamically
+    /// reuses the cached one. If the cache is full, the least recently used one is finalized.
+    pub fn stmt_cached(&mut self, sql: &str) -> Result<&mut Stmt<'static>, Error> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        if !self.stmts.contains_key(sql) {
+            // sqlite3_prepare_v2() copies the SQL, so the statement does not borrow 'sql'.
+            let mut stmt = unsafe {
+                let sql: &'static str = &*(sql as *const str);
+                Stmt::new(sql, &mut *self.raw)?
+            };
+            stmt.set_busy_timeout(self.busy_timeout);
+
+            if self.stmt_capacity <= self.stmts.len() {
+                self.evict_lru();
             }
+            self.stmts.insert(String::from(sql), (stmt, clock));
+        }
+
+        let (stmt, last_used) = self.stmts.get_mut(sql).unwrap();
+        *last_used = clock;
+        stmt.clear();
+        Ok(stmt)
+    }
+
+    /// Finalizes the least recently used statement.
+    fn evict_lru(&mut self) {
+        let lru = self
+            .stmts
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(sql, _)| sql.clone());
+
+        if let Some(sql) = lru {
+            self.stmts.remove(&sql);
         }
     }
 }
@@ -128,4 +189,67 @@ mod connection_tests {
     fn memory_db_constructor() {
         assert_eq!(true, Connection::open_memory_db().is_ok());
     }
+
+    #[test]
+    fn step_retries_while_busy() {
+        use std::thread;
+
+        let path = std::env::temp_dir().join(format!("mouse-rdb-busy-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut locker = Connection::try_from(path.as_path()).unwrap();
+        locker.stmt("BEGIN EXCLUSIVE").unwrap().step().unwrap();
+
+        let mut con = Connection::try_from(path.as_path()).unwrap();
+        con.set_busy_timeout(Duration::from_millis(20));
+        let e = con.stmt("BEGIN EXCLUSIVE").unwrap().step().unwrap_err();
+        assert_eq!(true, e.is_busy());
+
+        con.set_busy_timeout(Duration::from_secs(10));
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            locker.stmt("COMMIT").unwrap().step().unwrap();
+        });
+        assert_eq!(Ok(false), con.stmt("BEGIN EXCLUSIVE").unwrap().step());
+        handle.join().unwrap();
+
+        drop(con);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn stmt_cached_by_content() {
+        let mut con = Connection::open_memory_db().unwrap();
+
+        let sql = String::from("SELECT 1");
+        let a = con.stmt_cached(&sql).unwrap() as *const Stmt;
+        let b = con.stmt_cached(&format!("SELECT {}", 1)).unwrap() as *const Stmt;
+        let c = con.stmt("SELECT 1").unwrap() as *const Stmt;
+
+        assert_eq!(1, con.stmts.len());
+        assert_eq!(a, b);
+        assert_eq!(a, c);
+    }
+
+    #[test]
+    fn stmt_cache_evicts_lru() {
+        let mut con = Connection::open_memory_db().unwrap();
+        con.stmt_capacity = 2;
+
+        con.stmt_cached("SELECT 1").unwrap();
+        con.stmt_cached("SELECT 2").unwrap();
+        con.stmt_cached("SELECT 1").unwrap();
+        con.stmt_cached("SELECT 3").unwrap();
+
+        assert_eq!(2, con.stmts.len());
+        assert_eq!(true, con.stmts.contains_key("SELECT 1"));
+        assert_eq!(false, con.stmts.contains_key("SELECT 2"));
+        assert_eq!(true, con.stmts.contains_key("SELECT 3"));
+    }
+
+    #[test]
+    fn read_only_constructor() {
+        let path = Path::new("/nonexistent/mouse-rdb");
+        assert_eq!(true, Connection::open_read_only(path).is_err());
+    }
 }
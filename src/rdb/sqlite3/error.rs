@@ -14,7 +14,10 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::{SQLITE_DONE, SQLITE_OK, SQLITE_ROW};
+use super::{
+    SQLITE_BUSY, SQLITE_CONSTRAINT, SQLITE_CORRUPT, SQLITE_DONE, SQLITE_LOCKED, SQLITE_MISMATCH,
+    SQLITE_NOTADB, SQLITE_OK, SQLITE_READONLY, SQLITE_ROW, SQLITE_TOOBIG,
+};
 use std::ffi::CStr;
 use std::fmt;
 use std::os::raw::{c_char, c_int};
@@ -32,11 +35,61 @@ impl Error {
     pub const ROW: Error = Error { code: SQLITE_ROW };
     /// Wrapper of C "SQLITE_DONE".
     pub const DONE: Error = Error { code: SQLITE_DONE };
+    /// Wrapper of C "SQLITE_READONLY".
+    pub const READONLY: Error = Error {
+        code: SQLITE_READONLY,
+    };
 
     /// Creates a new instance.
     pub const fn new(code: c_int) -> Self {
         Self { code }
     }
+
+    /// Returns the (extended) result code.
+    pub fn code(&self) -> c_int {
+        self.code
+    }
+
+    /// Returns the primary result code; i.e. the least significant 8 bits of the extended one.
+    pub fn primary_code(&self) -> c_int {
+        self.code & 0xff
+    }
+
+    /// Returns `true` if the database is locked by another connection; i.e. "SQLITE_BUSY" or
+    /// "SQLITE_LOCKED". The operation may succeed if it is retried later.
+    pub fn is_busy(&self) -> bool {
+        let code = self.primary_code();
+        code == SQLITE_BUSY || code == SQLITE_LOCKED
+    }
+
+    /// Returns `true` if a constraint is violated; i.e. "SQLITE_CONSTRAINT" .
+    pub fn is_constraint(&self) -> bool {
+        self.primary_code() == SQLITE_CONSTRAINT
+    }
+
+    /// Returns `true` if the database file is broken; i.e. "SQLITE_CORRUPT" or "SQLITE_NOTADB" .
+    pub fn is_corrupt(&self) -> bool {
+        let code = self.primary_code();
+        code == SQLITE_CORRUPT || code == SQLITE_NOTADB
+    }
+
+    /// Returns `true` if a value is too big to store; i.e. "SQLITE_TOOBIG" .
+    ///
+    /// This crate returns it also if an integer overflows.
+    pub fn is_too_big(&self) -> bool {
+        self.primary_code() == SQLITE_TOOBIG
+    }
+
+    /// Returns `true` if a value is of an unexpected type; i.e. "SQLITE_MISMATCH" .
+    pub fn is_mismatch(&self) -> bool {
+        self.primary_code() == SQLITE_MISMATCH
+    }
+
+    /// Returns `true` if the statement tries to modify the database on a read-only session;
+    /// i.e. "SQLITE_READONLY" .
+    pub fn is_readonly(&self) -> bool {
+        self.primary_code() == SQLITE_READONLY
+    }
 }
 
 impl fmt::Display for Error {
@@ -55,3 +108,27 @@ impl std::error::Error for Error {}
 extern "C" {
     fn sqlite3_errstr(code: c_int) -> *const c_char;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::sqlite3::{SQLITE_CONSTRAINT_CHECK, SQLITE_RANGE};
+
+    #[test]
+    fn classification() {
+        assert_eq!(true, Error::new(SQLITE_BUSY).is_busy());
+        assert_eq!(true, Error::new(SQLITE_LOCKED).is_busy());
+        assert_eq!(false, Error::new(SQLITE_RANGE).is_busy());
+
+        assert_eq!(true, Error::new(SQLITE_CONSTRAINT).is_constraint());
+        assert_eq!(true, Error::new(SQLITE_CONSTRAINT_CHECK).is_constraint());
+        assert_eq!(false, Error::new(SQLITE_BUSY).is_constraint());
+
+        assert_eq!(true, Error::new(SQLITE_CORRUPT).is_corrupt());
+        assert_eq!(true, Error::new(SQLITE_NOTADB).is_corrupt());
+        assert_eq!(false, Error::OK.is_corrupt());
+
+        assert_eq!(true, Error::new(SQLITE_TOOBIG).is_too_big());
+        assert_eq!(false, Error::new(SQLITE_RANGE).is_too_big());
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Error, Master, Slave, Sqlite3Session};
+use crate::data_types::Id;
+
+/// Make sure to create table "invalid_acids".
+///
+/// This method does nothing if the table is.
+pub fn create_table<S>(session: &mut S) -> Result<(), Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    // The reason is stored as UTF-8 bytes.
+    const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS invalid_acids(
+        id BLOB PRIMARY KEY,
+        reason BLOB NOT NULL
+    )"#;
+
+    let mut stmt = session.con.stmt_once(SQL)?;
+    stmt.step()?;
+
+    Ok(())
+}
+
+/// Records that the acid with `id` is invalid for `reason` , and returns `true` if it is recorded
+/// newly, or `false` if it has already been.
+///
+/// The reason is not overwritten if `id` has already been recorded.
+pub fn mark_invalid<S>(id: &Id, reason: &str, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str =
+        r#"INSERT INTO invalid_acids (id, reason) VALUES (?1, ?2) ON CONFLICT DO NOTHING"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, id.as_ref())?;
+    stmt.bind_blob(2, reason.as_bytes())?;
+    stmt.step()?;
+
+    Ok(stmt.last_changes() == 1)
+}
+
+/// Returns `true` if the acid with `id` is recorded as invalid, or `false` .
+pub fn is_invalid<S>(id: &Id, session: &mut S) -> Result<bool, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT COUNT(*) FROM invalid_acids WHERE id = ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, id.as_ref())?;
+    stmt.step()?;
+
+    Ok(stmt.column_int(0).unwrap_or(0) != 0)
+}
+
+/// Returns the reason why the acid with `id` is recorded as invalid if any, or `None` .
+pub fn invalid_reason<S>(id: &Id, session: &mut S) -> Result<Option<String>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT reason FROM invalid_acids WHERE id = ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, id.as_ref())?;
+    if !stmt.step()? {
+        return Ok(None);
+    }
+
+    let reason = stmt.column_blob(0).unwrap_or(&[]);
+    Ok(Some(String::from_utf8_lossy(reason).into_owned()))
+}
+
+/// Removes the record of the acid with `id` , and returns `true` if it was recorded, or `false` .
+pub fn unmark_invalid<S>(id: &Id, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"DELETE FROM invalid_acids WHERE id = ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, id.as_ref())?;
+    stmt.step()?;
+
+    Ok(stmt.last_changes() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+    use crate::rdb::sqlite3::{master, Environment};
+
+    fn empty_table() -> Environment {
+        let env = Environment::default();
+        {
+            let mut session = master(&env);
+            create_table(&mut session).unwrap();
+        }
+        env
+    }
+
+    fn id(i: u8) -> Id {
+        let mut ret = Id::zeroed();
+        ret[0] = i;
+        ret
+    }
+
+    #[test]
+    fn create_table_() {
+        let env = Environment::default();
+        let mut session = master(&env);
+
+        assert_eq!(true, create_table(&mut session).is_ok());
+        assert_eq!(true, create_table(&mut session).is_ok());
+    }
+
+    #[test]
+    fn mark_invalid_() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        assert_eq!(false, is_invalid(&id(1), &mut session).unwrap());
+        assert_eq!(None, invalid_reason(&id(1), &mut session).unwrap());
+
+        assert_eq!(
+            true,
+            mark_invalid(&id(1), "bad signature", &mut session).unwrap()
+        );
+        assert_eq!(
+            false,
+            mark_invalid(&id(1), "double spend", &mut session).unwrap()
+        );
+        assert_eq!(true, mark_invalid(&id(2), "", &mut session).unwrap());
+
+        assert_eq!(true, is_invalid(&id(1), &mut session).unwrap());
+        assert_eq!(
+            Some(String::from("bad signature")),
+            invalid_reason(&id(1), &mut session).unwrap()
+        );
+        assert_eq!(
+            Some(String::new()),
+            invalid_reason(&id(2), &mut session).unwrap()
+        );
+        assert_eq!(false, is_invalid(&id(3), &mut session).unwrap());
+
+        assert_eq!(true, unmark_invalid(&id(1), &mut session).unwrap());
+        assert_eq!(false, unmark_invalid(&id(1), &mut session).unwrap());
+        assert_eq!(false, is_invalid(&id(1), &mut session).unwrap());
+    }
+}
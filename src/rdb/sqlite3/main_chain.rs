@@ -15,25 +15,52 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::{Error, Master, Slave, Sqlite3Session};
-use crate::data_types::{BlockHeight, ChainIndex, CryptoHash, Id};
+use crate::data_types::{BlockHeight, ChainIndex, Id};
 use std::borrow::Borrow;
 use std::collections::BTreeMap;
 
-/// Make sure to create table "main_chain".
+/// Make sure to create table "main_chain" and "main_chain_finality".
 ///
 /// This method does nothing if the table is.
 pub fn create_table<S>(session: &mut S) -> Result<(), Error>
 where
     S: Master,
 {
-    const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS main_chain(
-        height INTEGER PRIMARY KEY,
-        id BLOB UNIQUE NOT NULL
-    )"#;
-
     let session = Sqlite3Session::as_sqlite3_session(session);
-    let mut stmt = session.con.stmt_once(SQL)?;
-    stmt.step()?;
+
+    {
+        const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS main_chain(
+            height INTEGER PRIMARY KEY,
+            id BLOB UNIQUE NOT NULL
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Table "main_chain_finality" has at most 1 row to store the finalized height.
+    {
+        const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS main_chain_finality(
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            height INTEGER NOT NULL
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Refuse to revert the finalized blocks.
+    {
+        const SQL: &'static str = r#"CREATE TRIGGER IF NOT EXISTS keep_finalized_main_chain_
+            BEFORE DELETE ON main_chain
+            WHEN OLD.height <= (SELECT height FROM main_chain_finality WHERE id = 0)
+            BEGIN
+                SELECT RAISE(ABORT, 'finalized block cannot be reverted');
+            END"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
 
     Ok(())
 }
@@ -62,6 +89,8 @@ where
 
 /// Delete the heighest record in the "main_chain" if "main_chain" is not empty;
 /// otherwise, does nothing.
+///
+/// Fails if the heighest record is finalized.
 pub fn pop<S>(session: &mut S) -> Result<(), Error>
 where
     S: Master,
@@ -74,6 +103,61 @@ where
     Ok(())
 }
 
+/// Marks the blocks in "main_chain" whose height is less than or equals to `height` as finalized,
+/// and returns `true` if the finalized height is changed.
+///
+/// Does nothing and returns `false` if `height` is not in "main_chain", or if `height` is less
+/// than or equals to the current finalized height. (The finalized height never decreases.)
+pub fn finalize<S>(height: BlockHeight, session: &mut S) -> Result<bool, Error>
+where
+    S: Master,
+{
+    const SQL: &'static str = r#"INSERT INTO main_chain_finality (id, height)
+        SELECT 0, height FROM main_chain WHERE height = ?1
+        ON CONFLICT(id) DO UPDATE SET height = excluded.height WHERE excluded.height > height"#;
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, height)?;
+    stmt.step()?;
+
+    Ok(0 < stmt.last_changes())
+}
+
+/// Returns the finalized height if any block is finalized, or `None` .
+pub fn finalized_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    const SQL: &'static str = r#"SELECT height FROM main_chain_finality WHERE id = 0"#;
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    let stmt = session.con.stmt(SQL)?;
+    if stmt.step()? {
+        let height = stmt.column_int(0).unwrap();
+        Ok(Some(height))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Returns `true` if `chain_index` is in "main_chain" and finalized, or `false` .
+pub fn is_final<S>(chain_index: &ChainIndex, session: &mut S) -> Result<bool, Error>
+where
+    S: Slave,
+{
+    const SQL: &'static str = r#"SELECT 1 FROM main_chain, main_chain_finality
+        WHERE main_chain.height = ?1 AND main_chain.id = ?2
+        AND main_chain_finality.id = 0 AND main_chain.height <= main_chain_finality.height"#;
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, chain_index.height())?;
+    stmt.bind_blob(2, chain_index.id().as_ref())?;
+
+    stmt.step()
+}
+
 /// Fetches records corresponding to `heights` from "main_chain".
 pub fn fetch<I, S, H>(heights: I, session: &mut S) -> Result<BTreeMap<BlockHeight, Id>, Error>
 where
@@ -90,7 +174,7 @@ where
         let h = *h.borrow();
         stmt.bind_int(1, h)?;
         if stmt.step()? {
-            let id = unsafe { Id::copy_bytes(stmt.column_blob(0).unwrap()) };
+            let id = stmt.column_hash::<Id>(0)?.unwrap();
             ret.insert(h, id);
         }
     }
@@ -111,13 +195,34 @@ where
     stmt.bind_int(1, height)?;
 
     if stmt.step()? {
-        let id = unsafe { Id::copy_bytes(stmt.column_blob(0).unwrap()) };
+        let id = stmt.column_hash::<Id>(0)?.unwrap();
         Ok(Some(id))
     } else {
         Ok(None)
     }
 }
 
+/// Fetches a record corresponding to `id` from "main_chain" and returns it if found, or `None` .
+///
+/// Column "id" is unique, so it is indexed and this function does not scan the table.
+pub fn find_by_id<S>(id: &Id, session: &mut S) -> Result<Option<ChainIndex>, Error>
+where
+    S: Slave,
+{
+    const SQL: &'static str = r#"SELECT height FROM main_chain WHERE id = ?1"#;
+    let session = Sqlite3Session::as_sqlite3_session(session);
+    let stmt = session.con.stmt(SQL)?;
+
+    stmt.bind_blob(1, id.as_ref())?;
+
+    if stmt.step()? {
+        let height = stmt.column_int(0).unwrap();
+        Ok(Some(ChainIndex::new(height, id)))
+    } else {
+        Ok(None)
+    }
+}
+
 /// Fetches at most `limit` records, whose height is greater than or equals to `min_height` order
 /// by the height from RDB table "main_chain".
 ///
@@ -141,7 +246,7 @@ where
     let mut ret = Vec::with_capacity(limit as usize);
     while stmt.step()? {
         let height = stmt.column_int(0).unwrap();
-        let id = unsafe { Id::copy_bytes(stmt.column_blob(1).unwrap()) };
+        let id = stmt.column_hash::<Id>(1)?.unwrap();
         ret.push(ChainIndex::new(height, &id));
     }
     Ok(ret)
@@ -170,7 +275,7 @@ where
     let mut ret = Vec::with_capacity(limit as usize);
     while stmt.step()? {
         let height = stmt.column_int(0).unwrap();
-        let id = unsafe { Id::copy_bytes(stmt.column_blob(1).unwrap()) };
+        let id = stmt.column_hash::<Id>(1)?.unwrap();
         ret.push(ChainIndex::new(height, &id));
     }
     Ok(ret)
@@ -179,6 +284,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data_types::CryptoHash;
     use crate::rdb::sqlite3::{master, slave, Environment};
 
     const CHAIN_LEN: usize = 10;
@@ -279,6 +385,55 @@ mod tests {
         assert_eq!(true, pop(&mut session).is_ok());
     }
 
+    #[test]
+    fn finalize_() {
+        let env = filled_table();
+        let mut session = master(&env);
+
+        assert_eq!(None, finalized_height(&mut session).unwrap());
+        for c in main_chain() {
+            assert_eq!(false, is_final(&c, &mut session).unwrap());
+        }
+
+        // Not in "main_chain"
+        assert_eq!(false, finalize(MAX_CHAIN_HEIGHT + 1, &mut session).unwrap());
+        assert_eq!(None, finalized_height(&mut session).unwrap());
+
+        assert_eq!(true, finalize(3, &mut session).unwrap());
+        assert_eq!(Some(3), finalized_height(&mut session).unwrap());
+
+        // The finalized height never decreases.
+        assert_eq!(false, finalize(3, &mut session).unwrap());
+        assert_eq!(false, finalize(2, &mut session).unwrap());
+        assert_eq!(Some(3), finalized_height(&mut session).unwrap());
+
+        assert_eq!(true, finalize(5, &mut session).unwrap());
+        assert_eq!(Some(5), finalized_height(&mut session).unwrap());
+
+        for c in main_chain() {
+            let expected = c.height() <= 5;
+            assert_eq!(expected, is_final(&c, &mut session).unwrap());
+        }
+
+        // Not in "main_chain"
+        let c = ChainIndex::new(1, &Id::zeroed());
+        assert_eq!(false, is_final(&c, &mut session).unwrap());
+    }
+
+    #[test]
+    fn pop_finalized() {
+        let env = filled_table();
+        let mut session = master(&env);
+
+        finalize(MAX_CHAIN_HEIGHT - 1, &mut session).unwrap();
+
+        assert_eq!(true, pop(&mut session).is_ok());
+        assert_eq!(false, pop(&mut session).is_ok());
+
+        let fetched = fetch_one(MAX_CHAIN_HEIGHT - 1, &mut session);
+        assert_eq!(true, fetched.unwrap().is_some());
+    }
+
     #[test]
     fn fetch_from_empty() {
         let env = empty_table();
@@ -423,6 +578,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn find_by_id_() {
+        let env = filled_table();
+        let mut session = slave(&env);
+
+        for (i, id) in ids().iter().enumerate() {
+            let expected = ChainIndex::new(i as BlockHeight + 1, id);
+            assert_eq!(Ok(Some(expected)), find_by_id(id, &mut session));
+        }
+
+        let mut id = Id::zeroed();
+        id[0] = 0xff;
+        assert_eq!(Ok(None), find_by_id(&id, &mut session));
+    }
+
     #[test]
     fn fetch_asc_from_empty_table() {
         let env = empty_table();
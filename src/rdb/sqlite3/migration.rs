@@ -0,0 +1,132 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `migration` reports the schema objects that function [`create_table`] would create.
+//!
+//! [`create_table`]: super::create_table
+
+use super::{Error, Slave, Sqlite3Session};
+use crate::rdb::PendingMigration;
+
+/// The schema objects that function `create_table` creates; (type, name, table.)
+/// Type "column" is the column added to the table created by the older version.
+///
+/// The order is same to that `create_table` creates them.
+const SCHEMA: [(&'static str, &'static str, &'static str); 21] = [
+    ("table", "main_chain", "main_chain"),
+    ("table", "main_chain_finality", "main_chain_finality"),
+    ("trigger", "keep_finalized_main_chain_", "main_chain"),
+    ("table", "acids", "acids"),
+    ("column", "priority", "acids"),
+    ("column", "received_at", "acids"),
+    ("index", "chain_height_", "acids"),
+    ("index", "mempool_priority_", "acids"),
+    ("index", "mempool_received_at_", "acids"),
+    ("trigger", "keep_finalized_acids_", "acids"),
+    ("table", "acids_pruning", "acids_pruning"),
+    ("table", "resources", "resources"),
+    ("trigger", "cleanup_resources", "resources"),
+    ("index", "asset_type_value_", "resources"),
+    ("table", "resources_history", "resources_history"),
+    (
+        "table",
+        "resources_history_pruning",
+        "resources_history_pruning",
+    ),
+    ("table", "invalid_acids", "invalid_acids"),
+    ("table", "parents", "parents"),
+    ("index", "parent_id_", "parents"),
+    ("table", "block_headers", "block_headers"),
+    ("index", "block_parent_", "block_headers"),
+];
+
+fn exists(
+    kind: &str,
+    name: &str,
+    table: &str,
+    session: &mut Sqlite3Session,
+) -> Result<bool, Error> {
+    // The names are the constants above; they need not be escaped.
+    let sql = match kind {
+        "column" => format!(
+            "SELECT COUNT(*) FROM pragma_table_info('{}') WHERE name = '{}'",
+            table, name
+        ),
+        _ => format!(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = '{}' AND name = '{}'",
+            kind, name
+        ),
+    };
+    let mut stmt = session.con.stmt_once(&sql)?;
+    stmt.step()?;
+    Ok(stmt.column_int(0).unwrap_or(0) != 0)
+}
+
+fn count_rows(table: &str, session: &mut Sqlite3Session) -> Result<i64, Error> {
+    let sql = format!("SELECT COUNT(*) FROM {}", table);
+    let mut stmt = session.con.stmt_once(&sql)?;
+    stmt.step()?;
+    Ok(stmt.column_int(0).unwrap_or(0))
+}
+
+/// Returns the schema objects which are not created yet without changing anything.
+pub fn pending_migrations<S>(session: &mut S) -> Result<Vec<PendingMigration>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+    let mut ret = Vec::new();
+
+    for &(kind, name, table) in SCHEMA.iter() {
+        if exists(kind, name, table, session)? {
+            continue;
+        }
+
+        let rows = if exists("table", table, table, session)? {
+            count_rows(table, session)?
+        } else {
+            0
+        };
+
+        ret.push(PendingMigration {
+            kind,
+            name,
+            table,
+            rows,
+        });
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rdb::sqlite3::{create_table, master, Environment};
+
+    #[test]
+    fn pending_migrations_() {
+        let env = Environment::default();
+        let mut session = master(&env);
+
+        let pendings = pending_migrations(&mut session).unwrap();
+        assert_eq!(SCHEMA.len(), pendings.len());
+        assert_eq!(true, pendings.iter().all(|p| p.rows == 0));
+
+        create_table(&mut session).unwrap();
+        assert_eq!(true, pending_migrations(&mut session).unwrap().is_empty());
+    }
+}
@@ -15,105 +15,288 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod acids;
+pub mod block_headers;
 mod connection;
 mod error;
+pub mod invalid_acids;
 pub mod main_chain;
+pub mod migration;
+pub mod parents;
+mod read_pool;
 pub mod resources;
+mod session_queue;
 mod stmt;
 
 use super::{Master, Session, Slave};
-use crate::{Config, ModuleEnvironment};
+use crate::retry::Backoff;
+use crate::{fault, Config, ModuleEnvironment};
 use clap::{App, Arg};
 use core::cell::Cell;
 use core::convert::TryFrom;
 use std::os::raw::{c_char, c_int, c_void};
-use std::path::PathBuf;
-use std::sync::{Condvar, Mutex};
-use std::thread::{self, ThreadId};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use connection::Connection;
 pub use error::Error;
-use stmt::Stmt;
+use read_pool::ReadPool;
+use session_queue::SessionQueue;
+pub(crate) use stmt::Stmt;
 
 // libsqlite3 error constants
 // https://www.sqlite.org/draft/rescode.html
 const SQLITE_OK: c_int = 0;
+const SQLITE_BUSY: c_int = 5;
+const SQLITE_LOCKED: c_int = 6;
+const SQLITE_READONLY: c_int = 8;
+const SQLITE_CORRUPT: c_int = 11;
 const SQLITE_TOOBIG: c_int = 18;
+const SQLITE_CONSTRAINT: c_int = 19;
+const SQLITE_MISMATCH: c_int = 20;
 const SQLITE_RANGE: c_int = 25;
+const SQLITE_NOTADB: c_int = 26;
 const SQLITE_DONE: c_int = 101;
 const SQLITE_ROW: c_int = 100;
 const SQLITE_CONSTRAINT_CHECK: c_int = 275;
+#[allow(dead_code)]
+const SQLITE_CONSTRAINT_FOREIGNKEY: c_int = 787;
 
 // Constants for column type
 // https://www.sqlite.org/draft/c3ref/c_blob.html
 const SQLITE_INTEGER: c_int = 1;
+const SQLITE_TEXT: c_int = 3;
 const SQLITE_BLOB: c_int = 4;
 const SQLITE_NULL: c_int = 5;
 
 // Constants for sqlite3_open_v2()
 // https://www.sqlite.org/draft/c3ref/c_open_autoproxy.html
+const SQLITE_OPEN_READONLY: c_int = 0x00000001;
 const SQLITE_OPEN_READWRITE: c_int = 0x00000002;
+const SQLITE_OPEN_CREATE: c_int = 0x00000004;
 const SQLITE_OPEN_MEMORY: c_int = 0x00000080;
 const SQLITE_OPEN_NOMUTEX: c_int = 0x00008000;
 
+const DEFAULT_OPEN_ATTEMPTS: &'static str = "1";
+const DEFAULT_OPEN_RETRY_DELAY_MS: &'static str = "100";
+const DEFAULT_READ_CONNECTIONS: &'static str = "0";
+const DEFAULT_BUSY_TIMEOUT_MS: &'static str = "5000";
+
 /// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// The database file at '--rdb-data-path' is opened (and created if not exists) in method `init`
+/// with WAL journaling. The default instance uses an in-memory database until `init` is called;
+/// it is only for the tests.
 pub struct Environment {
     data_path: PathBuf,
-    session_owner: (Mutex<Option<ThreadId>>, Condvar),
+    open_backoff: Backoff,
+    migrate_dry_run: bool,
+    resources_history_depth: Option<i64>,
+    session_queue: SessionQueue,
     connection: Cell<Connection>,
+    read_connections: usize,
+    read_pool: ReadPool,
+    busy_timeout: Duration,
 }
 
 impl Default for Environment {
     fn default() -> Self {
         Self {
             data_path: PathBuf::default(),
-            session_owner: Default::default(),
+            open_backoff: Backoff::default(),
+            migrate_dry_run: false,
+            resources_history_depth: None,
+            session_queue: Default::default(),
             connection: Cell::new(Connection::open_memory_db().unwrap()),
+            read_connections: 0,
+            read_pool: ReadPool::default(),
+            busy_timeout: Duration::default(),
         }
     }
 }
 
 impl ModuleEnvironment for Environment {
     fn args(app: App<'static, 'static>) -> App<'static, 'static> {
-        app.arg(
+        app.args(&[
             Arg::with_name("PATH_TO_RDB_DATA_DIR")
                 .help("Path to the RDB database directory.")
                 .long("--rdb-data-path")
                 .required(true)
                 .takes_value(true),
-        )
+            Arg::with_name("MIGRATE_DRY_RUN")
+                .help(
+                    "Reports the pending RDB schema migrations and the KVS data to be converted for
+the KVS options, and exits without applying them.",
+                )
+                .long("--migrate-dry-run")
+                .takes_value(false),
+            Arg::with_name("RDB_PRIORITIZE_MASTER")
+                .help(
+                    "The threads waiting for a master session go ahead of those waiting for a slave
+session. (By default, the sessions are handed in the arrival order.)",
+                )
+                .long("--rdb-prioritize-master")
+                .takes_value(false),
+            Arg::with_name("RDB_OPEN_ATTEMPTS")
+                .help("The max number of the attempts to open the RDB at the startup.")
+                .long("--rdb-open-attempts")
+                .default_value(DEFAULT_OPEN_ATTEMPTS)
+                .takes_value(true),
+            Arg::with_name("RDB_OPEN_RETRY_DELAY_MS")
+                .help(
+                    "The delay in milliseconds before the 2nd attempt to open the RDB.
+The delay is doubled for each following attempt.",
+                )
+                .long("--rdb-open-retry-delay-ms")
+                .default_value(DEFAULT_OPEN_RETRY_DELAY_MS)
+                .takes_value(true),
+            Arg::with_name("RDB_RESOURCES_HISTORY_DEPTH")
+                .help(
+                    "The number of the recent blocks to keep the balance history for.
+(The history is kept forever by default.)",
+                )
+                .long("--rdb-resources-history-depth")
+                .takes_value(true),
+            Arg::with_name("RDB_READ_CONNECTIONS")
+                .help(
+                    "The number of the read-only connections for the slave sessions.
+If it is 0, the slave sessions share the connection with the master sessions.",
+                )
+                .long("--rdb-read-connections")
+                .default_value(DEFAULT_READ_CONNECTIONS)
+                .takes_value(true),
+            Arg::with_name("RDB_BUSY_TIMEOUT_MS")
+                .help(
+                    "How long in milliseconds to retry an SQL statement while the database is locked
+by another connection.",
+                )
+                .long("--rdb-busy-timeout-ms")
+                .default_value(DEFAULT_BUSY_TIMEOUT_MS)
+                .takes_value(true),
+        ])
     }
 
-    unsafe fn check(&mut self, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
         let data_path = config.args().value_of("PATH_TO_RDB_DATA_DIR").unwrap();
         self.data_path = PathBuf::from(data_path);
+        self.migrate_dry_run = config.args().is_present("MIGRATE_DRY_RUN");
+        let prioritize_master = config.args().is_present("RDB_PRIORITIZE_MASTER");
+        self.session_queue.set_prioritize_master(prioritize_master);
+
+        let attempts = config.args().value_of("RDB_OPEN_ATTEMPTS").unwrap();
+        let attempts = attempts.parse().map_err(|e| {
+            let msg = format!("Failed to parse argument '--rdb-open-attempts': {}", e);
+            crate::Error::Config(msg)
+        })?;
+
+        let delay = config.args().value_of("RDB_OPEN_RETRY_DELAY_MS").unwrap();
+        let delay = delay.parse().map_err(|e| {
+            let msg = format!(
+                "Failed to parse argument '--rdb-open-retry-delay-ms': {}",
+                e
+            );
+            crate::Error::Config(msg)
+        })?;
+        self.open_backoff = Backoff::new(attempts, Duration::from_millis(delay));
+
+        if let Some(depth) = config.args().value_of("RDB_RESOURCES_HISTORY_DEPTH") {
+            let depth = depth.parse().map_err(|e| {
+                let msg = format!(
+                    "Failed to parse argument '--rdb-resources-history-depth': {}",
+                    e
+                );
+                crate::Error::Config(msg)
+            })?;
+            self.resources_history_depth = Some(depth);
+        }
+
+        let read_connections = config.args().value_of("RDB_READ_CONNECTIONS").unwrap();
+        self.read_connections = read_connections.parse().map_err(|e| {
+            let msg = format!("Failed to parse argument '--rdb-read-connections': {}", e);
+            crate::Error::Config(msg)
+        })?;
+
+        let busy_timeout = config.args().value_of("RDB_BUSY_TIMEOUT_MS").unwrap();
+        let busy_timeout = busy_timeout.parse().map_err(|e| {
+            let msg = format!("Failed to parse argument '--rdb-busy-timeout-ms': {}", e);
+            crate::Error::Config(msg)
+        })?;
+        self.busy_timeout = Duration::from_millis(busy_timeout);
 
         Ok(())
     }
 
-    unsafe fn init(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.connection = Cell::new(Connection::try_from(self.data_path.as_ref())?);
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        let path = self.data_path.as_path();
+        let connection = self
+            .open_backoff
+            .retry("open the RDB", || Connection::try_from(path))
+            .map_err(crate::Error::rdb)?;
+        self.connection = Cell::new(connection);
+        self.connection
+            .get_mut()
+            .set_busy_timeout(self.busy_timeout);
+
+        if !self.migrate_dry_run {
+            enable_wal(self.connection.get_mut()).map_err(crate::Error::rdb)?;
+
+            let mut session = master(self);
+            create_table(&mut session).map_err(crate::Error::rdb)?;
+        }
 
-        let mut session = master(self);
-        create_table(&mut session)?;
+        if 0 < self.read_connections {
+            let mut connections = Vec::with_capacity(self.read_connections);
+            for _ in 0..self.read_connections {
+                let mut connection = Connection::open_read_only(path).map_err(crate::Error::rdb)?;
+                connection.set_busy_timeout(self.busy_timeout);
+                connections.push(connection);
+            }
+            self.read_pool = ReadPool::new(connections);
+        }
 
         Ok(())
     }
 }
 
+impl Environment {
+    /// Provides a reference to the path to the RDB database. ('--rdb-data-path')
+    pub fn data_path(&self) -> &Path {
+        &self.data_path
+    }
+
+    /// Returns `true` if '--migrate-dry-run' is specified.
+    ///
+    /// If so, method `init` only opens the database and does not create any table.
+    pub fn is_migrate_dry_run(&self) -> bool {
+        self.migrate_dry_run
+    }
+
+    /// Returns the number of the read-only connections for the slave sessions.
+    /// ('--rdb-read-connections')
+    pub fn read_connections(&self) -> usize {
+        self.read_connections
+    }
+}
+
 /// Blocks while another thread is using the connection, and creates a new [`Master`] session.
 ///
+/// The sessions are handed in the arrival order, except for that the master sessions go ahead
+/// if '--rdb-prioritize-master' is specified.
+///
 /// # Panics
 ///
 /// Panics if the current thread owns another `Session` instance.
 ///
 /// [`Master`]: crate::rdb::Master
 pub fn master<'a>(env: &'a Environment) -> impl 'a + Master {
-    Sqlite3Session::new(env)
+    Sqlite3Session::new_master(env)
 }
 
 /// Blocks while another thread is using the connection, and creates a new [`Slave`] session.
 ///
+/// The sessions are handed in the arrival order.
+/// If '--rdb-read-connections' is greater than 0, the session uses one of the read-only
+/// connections instead, and it does not wait for the master sessions.
+///
 /// # Panics
 ///
 /// Panics if the current thread owns another `Session` instance.
@@ -123,14 +306,68 @@ pub fn slave<'a>(env: &'a Environment) -> impl 'a + Slave {
     Sqlite3Session::new(env)
 }
 
+/// Changes the journal mode of `con` into WAL, so that the readers do not block the writer.
+///
+/// The journal mode is persistent in the database file.
+fn enable_wal(con: &mut Connection) -> Result<(), Error> {
+    {
+        let mut stmt = con.stmt_once("PRAGMA journal_mode = WAL")?;
+        stmt.step()?;
+    }
+
+    let mut stmt =
+        con.stmt_once("SELECT COUNT(*) FROM pragma_journal_mode WHERE journal_mode = 'wal'")?;
+    stmt.step()?;
+    if stmt.column_int(0) != Some(1) {
+        warn!("Failed to change the RDB journal mode into WAL.");
+    }
+
+    Ok(())
+}
+
 /// Creates RDB tables if not exists.
-pub fn create_table<S>(session: &mut S) -> Result<(), Box<dyn std::error::Error>>
+pub fn create_table<S>(session: &mut S) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
 where
     S: Master,
 {
     main_chain::create_table(session)?;
     acids::create_table(session)?;
     resources::create_table(session)?;
+    invalid_acids::create_table(session)?;
+    parents::create_table(session)?;
+    block_headers::create_table(session)?;
+
+    Ok(())
+}
+
+/// Prepares `sql` on the connection of `session` , and returns the statement.
+///
+/// The statement is not cached, and it is finalized when dropped.
+pub(crate) fn prepare<'a, S>(sql: &'a str, session: &'a mut S) -> Result<Stmt<'a>, Error>
+where
+    S: Session,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+    session.con.stmt_once(sql)
+}
+
+/// Runs "VACUUM" to defragment the database file, and "ANALYZE" to update the statistics for
+/// the query planner.
+///
+/// # Panics
+///
+/// Panics if `session` is in a transaction. ("VACUUM" cannot run in a transaction.)
+pub fn maintain<S>(session: &mut S) -> Result<(), Error>
+where
+    S: Master,
+{
+    assert_eq!(false, session.is_transaction());
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    for sql in &["VACUUM", "ANALYZE"] {
+        let mut stmt = session.con.stmt_once(sql)?;
+        stmt.step()?;
+    }
 
     Ok(())
 }
@@ -144,6 +381,8 @@ pub enum sqlite3 {}
 struct Sqlite3Session<'a> {
     env: &'a Environment,
     con: &'a mut Connection,
+    /// The index of the read-only connection, or `None` if `con` is the master connection.
+    reader: Option<usize>,
     is_transaction_: bool,
 }
 
@@ -154,46 +393,66 @@ impl Drop for Sqlite3Session<'_> {
         // Ignore the error.
         let _ = self.do_rollback();
 
-        let (mtx, cond) = &self.env.session_owner;
-        let mut guard = mtx.lock().unwrap();
-        *guard = None;
-        cond.notify_one();
+        match self.reader {
+            None => self.env.session_queue.release(),
+            Some(index) => self.env.read_pool.release(index),
+        }
     }
 }
 
 impl<'a> Sqlite3Session<'a> {
-    /// Blocks while another thread is using the connection, and creates a new instance.
+    /// Blocks while another thread is using the connection, and creates a new instance as a
+    /// slave session.
     ///
     /// # Panics
     ///
     /// Panics if the current thread is using another instance.
     pub fn new(env: &'a Environment) -> Self {
-        // Acquiring the ownership of the session.
-        {
-            let (mtx, cond) = &env.session_owner;
-            let mut guard = mtx.lock().unwrap();
-            let current_id = Some(thread::current().id());
-
-            // Some thread is using the connection.
-            if guard.is_some() {
-                if *guard == current_id {
-                    // It is the current thread itself that is using the connection.
-                    drop(guard);
-                    panic!("One thread tries to acqiure 2 RDB sessions.");
-                } else {
-                    // Another thread is using the connection.
-                    while {
-                        guard = cond.wait(guard).unwrap();
-                        guard.is_some()
-                    } {}
-                }
-            }
-            *guard = current_id;
+        if env.read_pool.is_empty() {
+            return Self::acquire(env, false);
+        }
+
+        if env.session_queue.is_owner() {
+            panic!("One thread tries to acqiure 2 RDB sessions.");
+        }
+        let index = env.read_pool.acquire();
+
+        let mut ret = Self {
+            env,
+            con: unsafe { &mut *env.read_pool.connection(index) },
+            reader: Some(index),
+            is_transaction_: false,
+        };
+
+        // For just in case.
+        // do_rollback() returns an error if no transaction is not started.
+        // ignore the error.
+        let _ = ret.do_rollback();
+        ret
+    }
+
+    /// Blocks while another thread is using the connection, and creates a new instance as a
+    /// master session.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread is using another instance.
+    pub fn new_master(env: &'a Environment) -> Self {
+        Self::acquire(env, true)
+    }
+
+    fn acquire(env: &'a Environment, is_master: bool) -> Self {
+        if env.read_pool.is_owner() {
+            panic!("One thread tries to acqiure 2 RDB sessions.");
         }
 
+        // Acquiring the ownership of the session.
+        env.session_queue.acquire(is_master);
+
         let mut ret = Self {
             env,
             con: unsafe { &mut *env.connection.as_ptr() },
+            reader: None,
             is_transaction_: false,
         };
 
@@ -210,30 +469,31 @@ impl Session for Sqlite3Session<'_> {
         self.is_transaction_
     }
 
-    fn begin_transaction(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn begin_transaction(&mut self) -> Result<(), crate::Error> {
         assert_eq!(false, self.is_transaction_);
         // The compiler can't assume the type to use map_err().
         match self.do_begin_transaction() {
             Ok(()) => Ok(()),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(crate::Error::rdb(e)),
         }
     }
 
-    fn commit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn commit(&mut self) -> Result<(), crate::Error> {
         assert_eq!(true, self.is_transaction_);
+        fault::hit(fault::Point::RdbCommit).map_err(crate::Error::rdb)?;
         // The compiler can't assume the type to use map_err().
         match self.do_commit() {
             Ok(()) => Ok(()),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(crate::Error::rdb(e)),
         }
     }
 
-    fn rollback(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    fn rollback(&mut self) -> Result<(), crate::Error> {
         assert_eq!(true, self.is_transaction_);
         // The compiler can't assume the type to use map_err().
         match self.do_rollback() {
             Ok(()) => Ok(()),
-            Err(e) => Err(Box::new(e)),
+            Err(e) => Err(crate::Error::rdb(e)),
         }
     }
 }
@@ -302,6 +562,7 @@ extern "C" {
     ) -> c_int;
     fn sqlite3_finalize(pstmt: *mut sqlite3_stmt) -> c_int;
     fn sqlite3_column_count(pstmt: *mut sqlite3_stmt) -> c_int;
+    fn sqlite3_stmt_readonly(pstmt: *mut sqlite3_stmt) -> c_int;
 
     fn sqlite3_db_handle(pstmt: *mut sqlite3_stmt) -> *mut sqlite3;
 
@@ -318,11 +579,19 @@ extern "C" {
         vlen: c_int,
         destructor: *const c_void,
     ) -> c_int;
+    fn sqlite3_bind_text(
+        pstmt: *mut sqlite3_stmt,
+        index: c_int,
+        pval: *const c_char,
+        vlen: c_int,
+        destructor: *const c_void,
+    ) -> c_int;
     fn sqlite3_bind_null(pstmt: *mut sqlite3_stmt, index: c_int) -> c_int;
 
     fn sqlite3_column_type(pstmt: *mut sqlite3_stmt, icol: c_int) -> c_int;
     fn sqlite3_column_int64(pstmt: *mut sqlite3_stmt, icol: c_int) -> i64;
     fn sqlite3_column_blob(pstmt: *mut sqlite3_stmt, icol: c_int) -> *const c_void;
+    fn sqlite3_column_text(pstmt: *mut sqlite3_stmt, icol: c_int) -> *const u8;
     fn sqlite3_column_bytes(pstmt: *mut sqlite3_stmt, icol: c_int) -> c_int;
 }
 
@@ -336,6 +605,15 @@ mod tests {
         let _ = Sqlite3Session::new(&env);
     }
 
+    #[test]
+    fn maintain_() {
+        let env = Environment::default();
+        let mut session = master(&env);
+        create_table(&mut session).unwrap();
+
+        assert_eq!(true, maintain(&mut session).is_ok());
+    }
+
     #[should_panic]
     #[test]
     fn construct_twice() {
@@ -343,4 +621,111 @@ mod tests {
         let _1st = Sqlite3Session::new(&env);
         let _2nd = Sqlite3Session::new(&env);
     }
+
+    /// Removes the database file and the WAL files.
+    fn remove_db(path: &Path) {
+        for suffix in &["", "-wal", "-shm"] {
+            let mut path = path.as_os_str().to_os_string();
+            path.push(suffix);
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn read_connections() {
+        use crate::data_types::{ChainIndex, CryptoHash, Id};
+
+        let path = std::env::temp_dir().join(format!("mouse-rdb-{}", std::process::id()));
+        remove_db(&path);
+
+        let mut env = Environment::default();
+        env.data_path = path.clone();
+        env.read_connections = 2;
+        unsafe { env.init().unwrap() };
+
+        let chain_index = ChainIndex::new(1, &Id::zeroed());
+        {
+            let mut session = master(&env);
+            main_chain::push(&chain_index, &mut session).unwrap();
+        }
+
+        // Another thread can read while the current thread owns a slave session.
+        let mut session = slave(&env);
+        assert_eq!(
+            Ok(Some(Id::zeroed())),
+            main_chain::fetch_one(1, &mut session)
+        );
+        {
+            let env = &env as *const Environment as usize;
+            std::thread::spawn(move || {
+                let env = unsafe { &*(env as *const Environment) };
+                let mut session = slave(env);
+                assert_eq!(
+                    Ok(Some(Id::zeroed())),
+                    main_chain::fetch_one(1, &mut session)
+                );
+            })
+            .join()
+            .unwrap();
+        }
+        drop(session);
+        drop(env);
+
+        remove_db(&path);
+    }
+
+    #[test]
+    fn persistent() {
+        use crate::data_types::{ChainIndex, CryptoHash, Id};
+
+        let path = std::env::temp_dir().join(format!("mouse-rdb-p-{}", std::process::id()));
+        remove_db(&path);
+
+        let open = || {
+            let mut env = Environment::default();
+            env.data_path = path.clone();
+            unsafe { env.init().unwrap() };
+            env
+        };
+
+        {
+            let env = open();
+            let mut session = master(&env);
+            main_chain::push(&ChainIndex::new(1, &Id::zeroed()), &mut session).unwrap();
+        }
+
+        {
+            let env = open();
+            let mut session = slave(&env);
+            assert_eq!(
+                Ok(Some(Id::zeroed())),
+                main_chain::fetch_one(1, &mut session)
+            );
+
+            let session = Sqlite3Session::as_sqlite3_session(&mut session);
+            let mut stmt = session
+                .con
+                .stmt_once("SELECT COUNT(*) FROM pragma_journal_mode WHERE journal_mode = 'wal'")
+                .unwrap();
+            assert_eq!(Ok(true), stmt.step());
+            assert_eq!(Some(1), stmt.column_int(0));
+        }
+
+        remove_db(&path);
+    }
+
+    #[should_panic]
+    #[test]
+    fn construct_master_with_reader() {
+        let path = std::env::temp_dir().join(format!("mouse-rdb-m-{}", std::process::id()));
+        remove_db(&path);
+
+        let mut env = Environment::default();
+        env.data_path = path;
+        env.read_connections = 1;
+        unsafe { env.init().unwrap() };
+
+        let _1st = slave(&env);
+        let _2nd = master(&env);
+    }
 }
@@ -0,0 +1,185 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{Error, Master, Slave, Sqlite3Session};
+use crate::data_types::Id;
+use std::borrow::Borrow;
+
+/// Make sure to create table "parents".
+///
+/// This method does nothing if the table is.
+pub fn create_table<S>(session: &mut S) -> Result<(), Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    {
+        const SQL: &'static str = r#"CREATE TABLE IF NOT EXISTS parents(
+            child_id BLOB NOT NULL,
+            parent_id BLOB NOT NULL,
+            idx INTEGER NOT NULL,
+            PRIMARY KEY (child_id, idx)
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Create index to look up the children.
+    {
+        const SQL: &'static str = r#"CREATE INDEX IF NOT EXISTS parent_id_ ON parents(parent_id)"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    Ok(())
+}
+
+/// Inserts the parents of each acid in `acids` , and returns the number of the inserted rows.
+///
+/// Each element of `acids` is a pair of the id of the acid and the ids of the parents in the
+/// order. The rows already in the table are ignored.
+pub fn insert<I, C, P, A, S>(acids: I, session: &mut S) -> Result<usize, Error>
+where
+    I: Iterator<Item = (C, P)>,
+    C: Borrow<Id>,
+    P: IntoIterator<Item = A>,
+    A: Borrow<Id>,
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"INSERT INTO parents (child_id, parent_id, idx) VALUES (?1, ?2, ?3)
+        ON CONFLICT DO NOTHING"#;
+    let stmt = session.con.stmt(SQL)?;
+
+    let mut ret = 0;
+
+    for (child, parents) in acids {
+        let child = child.borrow();
+        for (idx, parent) in parents.into_iter().enumerate() {
+            stmt.bind_blob(1, child.as_ref())?;
+            stmt.bind_blob(2, parent.borrow().as_ref())?;
+            stmt.bind_int(3, idx as i64)?;
+            stmt.step()?;
+
+            ret += stmt.last_changes();
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Fetches the ids of the acids which have `parent_id` as a parent in order of the id.
+pub fn children_of<S>(parent_id: &Id, session: &mut S) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str =
+        r#"SELECT DISTINCT child_id FROM parents WHERE parent_id = ?1 ORDER BY child_id"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, parent_id.as_ref())?;
+
+    let mut ret = Vec::new();
+    while stmt.step()? {
+        ret.push(stmt.column_hash::<Id>(0)?.unwrap());
+    }
+
+    Ok(ret)
+}
+
+/// Fetches the ids of the parents of `child_id` in the order.
+pub fn parents_of<S>(child_id: &Id, session: &mut S) -> Result<Vec<Id>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str =
+        r#"SELECT parent_id FROM parents WHERE child_id = ?1 ORDER BY idx ASC"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, child_id.as_ref())?;
+
+    let mut ret = Vec::new();
+    while stmt.step()? {
+        ret.push(stmt.column_hash::<Id>(0)?.unwrap());
+    }
+
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+    use crate::rdb::sqlite3::{master, Environment};
+
+    fn empty_table() -> Environment {
+        let env = Environment::default();
+        {
+            let mut session = master(&env);
+            create_table(&mut session).unwrap();
+        }
+        env
+    }
+
+    fn id(i: u8) -> Id {
+        let mut ret = Id::zeroed();
+        ret[0] = i;
+        ret
+    }
+
+    #[test]
+    fn create_table_() {
+        let env = Environment::default();
+        let mut session = master(&env);
+
+        assert_eq!(true, create_table(&mut session).is_ok());
+        assert_eq!(true, create_table(&mut session).is_ok());
+    }
+
+    #[test]
+    fn insert_and_fetch() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        // id(3) -> [id(1), id(2)], id(4) -> [id(1)], id(5) -> []
+        let acids = vec![
+            (id(3), vec![id(1), id(2)]),
+            (id(4), vec![id(1)]),
+            (id(5), vec![]),
+        ];
+        assert_eq!(3, insert(acids.iter().cloned(), &mut session).unwrap());
+        assert_eq!(0, insert(acids.iter().cloned(), &mut session).unwrap());
+
+        assert_eq!(
+            vec![id(3), id(4)],
+            children_of(&id(1), &mut session).unwrap()
+        );
+        assert_eq!(vec![id(3)], children_of(&id(2), &mut session).unwrap());
+        assert_eq!(Vec::<Id>::new(), children_of(&id(3), &mut session).unwrap());
+
+        assert_eq!(
+            vec![id(1), id(2)],
+            parents_of(&id(3), &mut session).unwrap()
+        );
+        assert_eq!(Vec::<Id>::new(), parents_of(&id(5), &mut session).unwrap());
+    }
+}
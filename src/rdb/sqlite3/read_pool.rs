@@ -0,0 +1,151 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `read_pool` hands the read-only connections to the slave sessions.
+
+use super::connection::Connection;
+use core::cell::Cell;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+/// `ReadPool` is a pool of the read-only connections.
+///
+/// Each connection is owned by at most one thread at the same time, and the threads wait for an
+/// idle connection if all the connections are in use.
+#[derive(Default)]
+pub struct ReadPool {
+    connections: Vec<Cell<Connection>>,
+    /// The owner of each connection.
+    owners: Mutex<Vec<Option<ThreadId>>>,
+    cond: Condvar,
+}
+
+impl ReadPool {
+    /// Creates a new instance with `connections` .
+    pub fn new(connections: Vec<Connection>) -> Self {
+        let owners = Mutex::new(vec![None; connections.len()]);
+        Self {
+            connections: connections.into_iter().map(Cell::new).collect(),
+            owners,
+            cond: Condvar::new(),
+        }
+    }
+
+    /// Returns `true` if `self` has no connection; i.e. the slave sessions share the connection
+    /// with the master sessions.
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    /// Returns `true` if the current thread owns a connection of `self` .
+    pub fn is_owner(&self) -> bool {
+        let current_id = Some(thread::current().id());
+        self.owners.lock().unwrap().contains(&current_id)
+    }
+
+    /// Blocks until a connection is idle, and makes the current thread the owner of it.
+    /// Returns the index of the connection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self` is empty, or if the current thread owns another connection.
+    pub fn acquire(&self) -> usize {
+        assert_eq!(false, self.is_empty());
+
+        let current_id = Some(thread::current().id());
+        let mut owners = self.owners.lock().unwrap();
+
+        if owners.contains(&current_id) {
+            drop(owners);
+            panic!("One thread tries to acqiure 2 RDB sessions.");
+        }
+
+        loop {
+            if let Some(index) = owners.iter().position(Option::is_none) {
+                owners[index] = current_id;
+                return index;
+            }
+            owners = self.cond.wait(owners).unwrap();
+        }
+    }
+
+    /// Provides a pointer to the connection at `index` .
+    pub fn connection(&self, index: usize) -> *mut Connection {
+        self.connections[index].as_ptr()
+    }
+
+    /// Releases the connection at `index` and wakes up a waiting thread.
+    pub fn release(&self, index: usize) {
+        let mut owners = self.owners.lock().unwrap();
+        owners[index] = None;
+        self.cond.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn pool(size: usize) -> ReadPool {
+        let connections = (0..size)
+            .map(|_| Connection::open_memory_db().unwrap())
+            .collect();
+        ReadPool::new(connections)
+    }
+
+    #[test]
+    fn acquire_release() {
+        let pool = pool(2);
+        assert_eq!(false, pool.is_owner());
+
+        let index = pool.acquire();
+        assert_eq!(true, pool.is_owner());
+
+        pool.release(index);
+        assert_eq!(false, pool.is_owner());
+    }
+
+    #[should_panic]
+    #[test]
+    fn acquire_twice() {
+        let pool = pool(2);
+        pool.acquire();
+        pool.acquire();
+    }
+
+    #[test]
+    fn acquire_in_parallel() {
+        struct Shared(ReadPool);
+        unsafe impl Send for Shared {}
+        unsafe impl Sync for Shared {}
+
+        let pool = Arc::new(Shared(pool(2)));
+        let held = pool.0.acquire();
+
+        let other = pool.clone();
+        let index = thread::spawn(move || {
+            let index = other.0.acquire();
+            other.0.release(index);
+            index
+        })
+        .join()
+        .unwrap();
+
+        assert_ne!(held, index);
+        pool.0.release(held);
+    }
+}
@@ -14,11 +14,88 @@
 // You should have received a copy of the GNU General Public License
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::{Error, Master, Slave, Sqlite3Session, SQLITE_CONSTRAINT_CHECK};
-use crate::data_types::{AssetValue, ResourceId};
+use super::{Error, Master, Slave, Sqlite3Session, Stmt, SQLITE_CONSTRAINT_CHECK, SQLITE_TOOBIG};
+use crate::data_types::{AssetValue, BlockHeight, ResourceId, RESOURCE_ID_BUFFER_CAPACITY};
 use std::borrow::Borrow;
 use std::collections::HashMap;
 
+// 'AssetValue' is stored as INTEGER by default.
+//
+// If feature "asset_value_128" is specified, it is stored as 16 bytes BLOB instead; the big
+// endian with the sign bit flipped, so that the comparison of the BLOBs (i.e. 'memcmp') agrees
+// with that of the integers. SQLite cannot calculate such BLOBs, so the values are calculated
+// in Rust.
+
+/// The SQL type of the column to store [`AssetValue`] .
+#[cfg(not(feature = "asset_value_128"))]
+macro_rules! value_type {
+    () => {
+        "INTEGER"
+    };
+}
+
+/// The SQL literal of [`AssetValue`] 0.
+#[cfg(not(feature = "asset_value_128"))]
+macro_rules! value_zero {
+    () => {
+        "0"
+    };
+}
+
+#[cfg(feature = "asset_value_128")]
+macro_rules! value_type {
+    () => {
+        "BLOB"
+    };
+}
+
+#[cfg(feature = "asset_value_128")]
+macro_rules! value_zero {
+    () => {
+        "X'80000000000000000000000000000000'"
+    };
+}
+
+/// [`AssetValue`] encoded to store in RDB.
+#[cfg(not(feature = "asset_value_128"))]
+type EncodedValue = i64;
+
+#[cfg(feature = "asset_value_128")]
+type EncodedValue = [u8; 16];
+
+#[cfg(not(feature = "asset_value_128"))]
+fn encode_value(value: AssetValue) -> EncodedValue {
+    value
+}
+
+#[cfg(feature = "asset_value_128")]
+fn encode_value(value: AssetValue) -> EncodedValue {
+    ((value as u128) ^ (1 << 127)).to_be_bytes()
+}
+
+#[cfg(not(feature = "asset_value_128"))]
+fn bind_value(stmt: &mut Stmt, index: usize, value: &EncodedValue) -> Result<(), Error> {
+    stmt.bind_int(index, *value)
+}
+
+#[cfg(feature = "asset_value_128")]
+fn bind_value(stmt: &mut Stmt, index: usize, value: &EncodedValue) -> Result<(), Error> {
+    stmt.bind_blob(index, value)
+}
+
+#[cfg(not(feature = "asset_value_128"))]
+fn column_value(stmt: &mut Stmt, index: usize) -> Option<AssetValue> {
+    stmt.column_int(index)
+}
+
+#[cfg(feature = "asset_value_128")]
+fn column_value(stmt: &mut Stmt, index: usize) -> Option<AssetValue> {
+    let bytes = stmt.column_blob(index)?;
+    let mut buffer = [0; 16];
+    buffer.copy_from_slice(bytes);
+    Some((u128::from_be_bytes(buffer) ^ (1 << 127)) as AssetValue)
+}
+
 /// Make sure to create table "resources".
 ///
 /// This method does nothing if the table is.
@@ -30,14 +107,20 @@ where
 
     // Creating table
     {
-        const SQL: &'static str = r#"
+        const SQL: &'static str = concat!(
+            r#"
         CREATE TABLE IF NOT EXISTS resources(
             owner BLOB NOT NULL,
             asset_type BLOB NOT NULL,
-            value INTEGER NOT NULL,
+            value "#,
+            value_type!(),
+            r#" NOT NULL,
             CONSTRAINT resource_id_ PRIMARY KEY(owner, asset_type),
-            CONSTRAINT value_ CHECK (value >= 0)
-        )"#;
+            CONSTRAINT value_ CHECK (value >= "#,
+            value_zero!(),
+            r#")
+        )"#
+        );
 
         let mut stmt = session.con.stmt_once(SQL)?;
         stmt.step()?;
@@ -45,20 +128,66 @@ where
 
     // Creating trigger to cleanup
     {
-        const SQL: &'static str = r#"
+        const SQL: &'static str = concat!(
+            r#"
         CREATE TRIGGER IF NOT EXISTS cleanup_resources
             AFTER UPDATE OF value ON resources
             FOR EACH ROW
-            WHEN NEW.value = 0
+            WHEN NEW.value = "#,
+            value_zero!(),
+            r#"
             BEGIN
                 DELETE FROM resources WHERE owner = old.owner AND asset_type = old.asset_type;
             END
+        "#
+        );
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Creating index for the aggregation by the asset type
+    {
+        const SQL: &'static str = r#"
+        CREATE INDEX IF NOT EXISTS asset_type_value_ ON resources(asset_type, value)
         "#;
 
         let mut stmt = session.con.stmt_once(SQL)?;
         stmt.step()?;
     }
 
+    // Creating table to store the balance history
+    {
+        const SQL: &'static str = concat!(
+            r#"
+        CREATE TABLE IF NOT EXISTS resources_history(
+            owner BLOB NOT NULL,
+            asset_type BLOB NOT NULL,
+            height INTEGER NOT NULL,
+            value "#,
+            value_type!(),
+            r#" NOT NULL,
+            CONSTRAINT resources_history_ PRIMARY KEY(owner, asset_type, height)
+        )"#
+        );
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
+    // Table "resources_history_pruning" has at most 1 row to store the height that the history
+    // is pruned below.
+    {
+        const SQL: &'static str = r#"
+        CREATE TABLE IF NOT EXISTS resources_history_pruning(
+            id INTEGER PRIMARY KEY CHECK (id = 0),
+            height INTEGER NOT NULL
+        )"#;
+
+        let mut stmt = session.con.stmt_once(SQL)?;
+        stmt.step()?;
+    }
+
     Ok(())
 }
 
@@ -74,6 +203,9 @@ where
 ///
 /// Errors if any [`AssetValue`] is less than 0.
 ///
+/// Errors with "SQLITE_TOOBIG" if any [`AssetValue`] overflows. (SQLite converts the integer
+/// into the floating point number on overflow, so it is checked explicitly.)
+///
 /// [`ResourceId`]: crate::data_types::ResourceId
 /// [`AssetValue`]: crate::data_types::AssetValue
 pub fn update_balance<I, S, B, R, V>(balances: I, session: &mut S) -> Result<(), Error>
@@ -85,12 +217,24 @@ where
     V: Borrow<AssetValue>,
 {
     let session = Sqlite3Session::as_sqlite3_session(session);
+    update_value(balances, session)
+}
 
+#[cfg(not(feature = "asset_value_128"))]
+fn update_value<I, B, R, V>(balances: I, session: &mut Sqlite3Session) -> Result<(), Error>
+where
+    I: Iterator<Item = B> + Clone,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+{
     // Depositting
     {
+        // The value is positive, so the right side of the WHERE clause never overflows.
         const SQL: &'static str = r#"
         INSERT INTO resources (owner, asset_type, value) VALUES(?1, ?2, ?3)
-            ON CONFLICT (owner, asset_type) DO UPDATE set value = value + ?3;
+            ON CONFLICT (owner, asset_type) DO UPDATE set value = value + ?3
+            WHERE value <= 9223372036854775807 - ?3;
         "#;
         let stmt = session.con.stmt(SQL)?;
         for b in balances.clone() {
@@ -101,8 +245,14 @@ where
             }
             stmt.bind_blob(1, resource_id.borrow().owner())?;
             stmt.bind_blob(2, resource_id.borrow().asset_type())?;
-            stmt.bind_int(3, *value.borrow())?;
+            let value = encode_value(*value.borrow());
+            bind_value(stmt, 3, &value)?;
             stmt.step()?;
+
+            // The WHERE clause prevents from the update if the value overflows.
+            if stmt.last_changes() == 0 {
+                return Err(Error::new(SQLITE_TOOBIG));
+            }
         }
     }
 
@@ -121,7 +271,8 @@ where
             }
             stmt.bind_blob(1, resource_id.borrow().owner())?;
             stmt.bind_blob(2, resource_id.borrow().asset_type())?;
-            stmt.bind_int(3, *value.borrow())?;
+            let value = encode_value(*value.borrow());
+            bind_value(stmt, 3, &value)?;
             stmt.step()?;
 
             // UPDATE SQL does nothing if no such ResourceId is in the table.
@@ -135,6 +286,249 @@ where
     Ok(())
 }
 
+#[cfg(feature = "asset_value_128")]
+fn update_value<I, B, R, V>(balances: I, session: &mut Sqlite3Session) -> Result<(), Error>
+where
+    I: Iterator<Item = B> + Clone,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+{
+    // Depositting at first, and withdrawing next.
+    let deposits = balances.clone().filter(|b| 0 < *b.borrow().1.borrow());
+    let withdrawals = balances.filter(|b| *b.borrow().1.borrow() < 0);
+
+    for b in deposits.chain(withdrawals) {
+        let (resource_id, value) = b.borrow();
+        let resource_id = resource_id.borrow();
+
+        let current = {
+            const SQL: &'static str = r#"
+            SELECT value FROM resources WHERE owner = ?1 AND asset_type = ?2;
+            "#;
+            let stmt = session.con.stmt(SQL)?;
+            stmt.bind_blob(1, resource_id.owner())?;
+            stmt.bind_blob(2, resource_id.asset_type())?;
+            if stmt.step()? {
+                column_value(stmt, 0).unwrap()
+            } else {
+                0
+            }
+        };
+
+        let updated = match current.checked_add(*value.borrow()) {
+            None => return Err(Error::new(SQLITE_TOOBIG)),
+            Some(v) if v < 0 => return Err(Error::new(SQLITE_CONSTRAINT_CHECK)),
+            Some(v) => v,
+        };
+
+        if updated == 0 {
+            const SQL: &'static str = r#"
+            DELETE FROM resources WHERE owner = ?1 AND asset_type = ?2;
+            "#;
+            let stmt = session.con.stmt(SQL)?;
+            stmt.bind_blob(1, resource_id.owner())?;
+            stmt.bind_blob(2, resource_id.asset_type())?;
+            stmt.step()?;
+        } else {
+            const SQL: &'static str = r#"
+            INSERT OR REPLACE INTO resources (owner, asset_type, value) VALUES(?1, ?2, ?3);
+            "#;
+            let stmt = session.con.stmt(SQL)?;
+            let updated = encode_value(updated);
+            stmt.bind_blob(1, resource_id.owner())?;
+            stmt.bind_blob(2, resource_id.asset_type())?;
+            bind_value(stmt, 3, &updated)?;
+            stmt.step()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Same to [`update_balance`] except for that the balances after the update are recorded in
+/// table "resources_history" as those at `height` .
+///
+/// If '--rdb-resources-history-depth' is specified, the history older than the depth is pruned
+/// at the same time.
+///
+/// [`update_balance`]: self::update_balance
+pub fn update_balance_at<I, S, B, R, V>(
+    balances: I,
+    height: BlockHeight,
+    session: &mut S,
+) -> Result<(), Error>
+where
+    I: Iterator<Item = B> + Clone,
+    S: Master,
+    B: Borrow<(R, V)>,
+    R: Borrow<ResourceId>,
+    V: Borrow<AssetValue>,
+{
+    update_balance(balances.clone(), session)?;
+
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    {
+        // The trigger deletes the row from "resources" if the value is 0.
+        const SQL: &'static str = concat!(
+            r#"
+        INSERT OR REPLACE INTO resources_history (owner, asset_type, height, value)
+            VALUES(?1, ?2, ?3,
+                IFNULL((SELECT value FROM resources WHERE owner = ?1 AND asset_type = ?2), "#,
+            value_zero!(),
+            r#"))
+        "#
+        );
+        let stmt = session.con.stmt(SQL)?;
+        for b in balances {
+            let (resource_id, _) = b.borrow();
+            stmt.bind_blob(1, resource_id.borrow().owner())?;
+            stmt.bind_blob(2, resource_id.borrow().asset_type())?;
+            stmt.bind_int(3, height)?;
+            stmt.step()?;
+        }
+    }
+
+    if let Some(depth) = session.env.resources_history_depth {
+        if depth < height {
+            prune_history(height - depth, session)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the balance of each [`ResourceId`] in `resource_ids` at `height` from table
+/// "resources_history".
+///
+/// The returned value does not has the [`ResourceId`] as the key if the corresponding value is 0.
+///
+/// Returns `None` if the history at `height` is pruned.
+pub fn fetch_at_height<I, S, R>(
+    resource_ids: I,
+    height: BlockHeight,
+    session: &mut S,
+) -> Result<Option<HashMap<ResourceId, AssetValue>>, Error>
+where
+    I: Iterator<Item = R>,
+    S: Slave,
+    R: Borrow<ResourceId>,
+{
+    if let Some(pruned) = pruned_height(session)? {
+        if height < pruned {
+            return Ok(None);
+        }
+    }
+
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"
+    SELECT value FROM resources_history WHERE owner = ?1 AND asset_type = ?2 AND height <= ?3
+        ORDER BY height DESC LIMIT 1
+    "#;
+    let stmt = session.con.stmt(SQL)?;
+
+    let mut ret = match resource_ids.size_hint() {
+        (n, None) => HashMap::with_capacity(n),
+        (_, Some(n)) => HashMap::with_capacity(n),
+    };
+
+    for resource_id in resource_ids {
+        let resource_id = resource_id.borrow();
+        stmt.bind_blob(1, resource_id.owner())?;
+        stmt.bind_blob(2, resource_id.asset_type())?;
+        stmt.bind_int(3, height)?;
+        if stmt.step()? {
+            let value = column_value(stmt, 0).unwrap();
+            if value != 0 {
+                ret.insert(*resource_id, value);
+            }
+        }
+    }
+
+    Ok(Some(ret))
+}
+
+/// Deletes the history which is not necessary to fetch the balance at `height` or later, and
+/// returns the number of the deleted rows.
+///
+/// After this function is called, [`fetch_at_height`] returns `None` for the height less than
+/// `height` . Does nothing if `height` is less than or equals to the height already pruned.
+///
+/// [`fetch_at_height`]: self::fetch_at_height
+pub fn prune_history<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    if let Some(pruned) = pruned_height(session)? {
+        if height <= pruned {
+            return Ok(0);
+        }
+    }
+
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    // Keeps the latest row at or below 'height' of each ResourceId.
+    let deleted = {
+        const SQL: &'static str = r#"
+        DELETE FROM resources_history WHERE height < ?1 AND EXISTS (
+            SELECT 1 FROM resources_history AS newer
+                WHERE newer.owner = resources_history.owner
+                AND newer.asset_type = resources_history.asset_type
+                AND newer.height > resources_history.height AND newer.height <= ?1
+        )"#;
+        let stmt = session.con.stmt(SQL)?;
+        stmt.bind_int(1, height)?;
+        stmt.step()?;
+        stmt.last_changes()
+    };
+
+    {
+        const SQL: &'static str = r#"
+        INSERT OR REPLACE INTO resources_history_pruning (id, height) VALUES (0, ?1)
+        "#;
+        let stmt = session.con.stmt(SQL)?;
+        stmt.bind_int(1, height)?;
+        stmt.step()?;
+    }
+
+    Ok(deleted)
+}
+
+/// Deletes the history whose height is greater than `height` , and returns the number of the
+/// deleted rows.
+///
+/// Call this function when the blocks are popped from the main chain.
+pub fn truncate_history<S>(height: BlockHeight, session: &mut S) -> Result<usize, Error>
+where
+    S: Master,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"DELETE FROM resources_history WHERE height > ?1"#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_int(1, height)?;
+    stmt.step()?;
+    Ok(stmt.last_changes())
+}
+
+/// Returns the height that the history is pruned below if any, or `None` .
+pub fn pruned_height<S>(session: &mut S) -> Result<Option<BlockHeight>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"SELECT height FROM resources_history_pruning WHERE id = 0"#;
+    let stmt = session.con.stmt(SQL)?;
+    if stmt.step()? {
+        Ok(stmt.column_int(0))
+    } else {
+        Ok(None)
+    }
+}
+
 /// Fetches the depositted value of each [`ResourceId`] in `resource_ids` .
 ///
 /// The returned value does not has the [`ResourceId`] as the key if the corresponding value is 0.
@@ -164,7 +558,7 @@ where
         stmt.bind_blob(1, resource_id.owner())?;
         stmt.bind_blob(2, resource_id.asset_type())?;
         if stmt.step()? {
-            let value = stmt.column_int(0).unwrap();
+            let value = column_value(stmt, 0).unwrap();
             debug_assert_eq!(true, value > 0);
             ret.insert(*resource_id, value);
         }
@@ -173,6 +567,165 @@ where
     Ok(ret)
 }
 
+/// Fetches all the [`ResourceId`] whose owner is `owner` and the depositted value, ordered by
+/// the asset type.
+pub fn fetch_by_owner<S>(
+    owner: &[u8],
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    // The primary key (owner, asset_type) works as the index on owner.
+    const SQL: &'static str = r#"
+    SELECT asset_type, value FROM resources WHERE owner = ?1 ORDER BY asset_type;
+    "#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, owner)?;
+
+    let mut ret = Vec::new();
+    while stmt.step()? {
+        let value = column_value(stmt, 1).unwrap();
+        let asset_type = stmt.column_blob(0).unwrap_or(&[]);
+        if RESOURCE_ID_BUFFER_CAPACITY < owner.len() + asset_type.len() {
+            return Err(Error::new(SQLITE_TOOBIG));
+        }
+
+        let resource_id = unsafe { ResourceId::new(owner, asset_type) };
+        ret.push((resource_id, value));
+    }
+
+    Ok(ret)
+}
+
+/// Returns the sum of the depositted value of `asset_type` .
+pub fn total_supply<S>(asset_type: &[u8], session: &mut S) -> Result<AssetValue, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    // SUM() fails if the result overflows.
+    #[cfg(not(feature = "asset_value_128"))]
+    const SQL: &'static str = r#"
+    SELECT IFNULL(SUM(value), 0) FROM resources WHERE asset_type = ?1;
+    "#;
+
+    #[cfg(feature = "asset_value_128")]
+    const SQL: &'static str = r#"
+    SELECT value FROM resources WHERE asset_type = ?1;
+    "#;
+
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, asset_type)?;
+    sum_values(stmt)
+}
+
+#[cfg(not(feature = "asset_value_128"))]
+fn sum_values(stmt: &mut Stmt) -> Result<AssetValue, Error> {
+    stmt.step()?;
+    Ok(stmt.column_int(0).unwrap_or(0))
+}
+
+#[cfg(feature = "asset_value_128")]
+fn sum_values(stmt: &mut Stmt) -> Result<AssetValue, Error> {
+    let mut ret: AssetValue = 0;
+    while stmt.step()? {
+        let value = column_value(stmt, 0).unwrap();
+        ret = ret.checked_add(value).ok_or(Error::new(SQLITE_TOOBIG))?;
+    }
+    Ok(ret)
+}
+
+/// Fetches at most `limit` [`ResourceId`] whose asset type is `asset_type` and the depositted
+/// value, ordered by the value desc.
+///
+/// The owners with the same value are ordered by the owner.
+pub fn top_owners<S>(
+    asset_type: &[u8],
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"
+    SELECT owner, value FROM resources WHERE asset_type = ?1
+        ORDER BY value DESC, owner ASC LIMIT ?2;
+    "#;
+    let stmt = session.con.stmt(SQL)?;
+    stmt.bind_blob(1, asset_type)?;
+    stmt.bind_int(2, limit as i64)?;
+
+    let mut ret = Vec::with_capacity(limit as usize);
+    while stmt.step()? {
+        let value = column_value(stmt, 1).unwrap();
+        let owner = stmt.column_blob(0).unwrap_or(&[]);
+        if RESOURCE_ID_BUFFER_CAPACITY < owner.len() + asset_type.len() {
+            return Err(Error::new(SQLITE_TOOBIG));
+        }
+
+        let resource_id = unsafe { ResourceId::new(owner, asset_type) };
+        ret.push((resource_id, value));
+    }
+
+    Ok(ret)
+}
+
+/// Fetches at most `limit` [`ResourceId`] and the depositted value ordered by the owner and the
+/// asset type.
+///
+/// If `after` is not `None` , this method ignores the [`ResourceId`] less than or equals to
+/// `after` .
+pub fn fetch_all<S>(
+    after: Option<&ResourceId>,
+    limit: u32,
+    session: &mut S,
+) -> Result<Vec<(ResourceId, AssetValue)>, Error>
+where
+    S: Slave,
+{
+    let session = Sqlite3Session::as_sqlite3_session(session);
+
+    const SQL: &'static str = r#"
+    SELECT owner, asset_type, value FROM resources
+        WHERE ?1 IS NULL OR owner > ?1 OR (owner = ?1 AND asset_type > ?2)
+        ORDER BY owner ASC, asset_type ASC LIMIT ?3;
+    "#;
+    let stmt = session.con.stmt(SQL)?;
+
+    match after {
+        None => {
+            stmt.bind_null(1)?;
+            stmt.bind_null(2)?;
+        }
+        Some(after) => {
+            stmt.bind_blob(1, after.owner())?;
+            stmt.bind_blob(2, after.asset_type())?;
+        }
+    }
+    stmt.bind_int(3, limit as i64)?;
+
+    let mut ret = Vec::with_capacity(limit as usize);
+    while stmt.step()? {
+        let value = column_value(stmt, 2).unwrap();
+        let owner = stmt.column_blob_owned(0).unwrap_or_default();
+        let asset_type = stmt.column_blob(1).unwrap_or(&[]);
+        if RESOURCE_ID_BUFFER_CAPACITY < owner.len() + asset_type.len() {
+            return Err(Error::new(SQLITE_TOOBIG));
+        }
+
+        let resource_id = unsafe { ResourceId::new(&owner, asset_type) };
+        ret.push((resource_id, value));
+    }
+
+    Ok(ret)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -268,6 +821,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn update_balance_overflow() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        let resource_id = balances()[1].0;
+        update_balance([(resource_id, AssetValue::MAX - 1)].iter(), &mut session).unwrap();
+        update_balance([(resource_id, 1)].iter(), &mut session).unwrap();
+
+        let e = update_balance([(resource_id, 1)].iter(), &mut session).unwrap_err();
+        assert_eq!(true, e.is_too_big());
+
+        let fetched = fetch([resource_id].iter(), &mut session).unwrap();
+        assert_eq!(Some(&AssetValue::MAX), fetched.get(&resource_id));
+    }
+
     #[test]
     fn fetch_from_empty_table() {
         let env = empty_table();
@@ -313,4 +882,163 @@ mod tests {
             assert_eq!(*v - 1, fetched[k]);
         }
     }
+
+    #[test]
+    fn fetch_by_owner_() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        let owner: &[u8] = &[1, 2];
+        let resource_ids: Vec<ResourceId> = [&[3u8] as &[u8], &[0], &[1, 2]]
+            .iter()
+            .map(|asset_type| unsafe { ResourceId::new(owner, asset_type) })
+            .collect();
+        assert_eq!(0, fetch_by_owner(owner, &mut session).unwrap().len());
+
+        let deposits = resource_ids.iter().map(|r| (r, 5));
+        update_balance(
+            deposits.chain(balances().iter().map(|(r, v)| (r, *v))),
+            &mut session,
+        )
+        .unwrap();
+
+        let fetched = fetch_by_owner(owner, &mut session).unwrap();
+        let asset_types: Vec<&[u8]> = fetched.iter().map(|(r, _)| r.asset_type()).collect();
+        assert_eq!(vec![&[0u8] as &[u8], &[1, 2], &[3]], asset_types);
+        assert_eq!(
+            true,
+            fetched.iter().all(|(r, v)| r.owner() == owner && *v == 5)
+        );
+
+        assert_eq!(0, fetch_by_owner(&[9, 9, 9], &mut session).unwrap().len());
+    }
+
+    #[test]
+    fn total_supply_() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        let asset_type = balances()[3].0.asset_type().to_vec();
+        assert_eq!(0, total_supply(&asset_type, &mut session).unwrap());
+
+        update_balance(balances().iter(), &mut session).unwrap();
+        // Each asset type is held by only one owner in balances().
+        assert_eq!(3, total_supply(&asset_type, &mut session).unwrap());
+
+        let another = unsafe { ResourceId::new(&[100], &asset_type) };
+        update_balance([(another, 10)].iter(), &mut session).unwrap();
+        assert_eq!(13, total_supply(&asset_type, &mut session).unwrap());
+    }
+
+    #[test]
+    fn top_owners_() {
+        let env = empty_table();
+        let mut session = master(&env);
+
+        let asset_type: &[u8] = &[7];
+        let holdings: Vec<(ResourceId, AssetValue)> = [(&[3u8], 5), (&[1], 9), (&[2], 5)]
+            .iter()
+            .map(|(owner, v)| (unsafe { ResourceId::new(*owner, asset_type) }, *v))
+            .collect();
+        update_balance(holdings.iter(), &mut session).unwrap();
+
+        let top = top_owners(asset_type, 10, &mut session).unwrap();
+        let owners: Vec<&[u8]> = top.iter().map(|(r, _)| r.owner()).collect();
+        let values: Vec<AssetValue> = top.iter().map(|(_, v)| *v).collect();
+        assert_eq!(vec![&[1u8] as &[u8], &[2], &[3]], owners);
+        assert_eq!(vec![9, 5, 5], values);
+
+        assert_eq!(2, top_owners(asset_type, 2, &mut session).unwrap().len());
+        assert_eq!(0, top_owners(&[8], 2, &mut session).unwrap().len());
+    }
+
+    #[test]
+    fn fetch_all_() {
+        let env = empty_table();
+        let mut session = master(&env);
+        assert_eq!(0, fetch_all(None, 10, &mut session).unwrap().len());
+
+        // balances()[0] is 0 and not stored.
+        update_balance(balances().iter(), &mut session).unwrap();
+
+        let mut fetched = Vec::new();
+        let mut after = None;
+        loop {
+            let chunk = fetch_all(after.as_ref(), 3, &mut session).unwrap();
+            after = chunk.last().map(|(r, _)| *r);
+            fetched.extend(chunk.iter().copied());
+            if chunk.len() < 3 {
+                break;
+            }
+        }
+
+        assert_eq!(&balances()[1..], &fetched[..]);
+    }
+
+    #[test]
+    fn fetch_at_height_() {
+        let env = empty_table();
+        let mut session = master(&env);
+        let ids = || balances().into_iter().map(|(k, _)| k);
+
+        // height 1: deposit i; height 2: withdraw 1; height 4: deposit 1 to the 2nd one.
+        update_balance_at(balances().iter(), 1, &mut session).unwrap();
+        update_balance_at(
+            balances().iter().skip(1).map(|(k, _)| (k, -1)),
+            2,
+            &mut session,
+        )
+        .unwrap();
+        update_balance_at(balances().iter().skip(1).take(1), 4, &mut session).unwrap();
+
+        assert_eq!(
+            Some(HashMap::new()),
+            fetch_at_height(ids(), 0, &mut session).unwrap()
+        );
+
+        let at1 = fetch_at_height(ids(), 1, &mut session).unwrap().unwrap();
+        assert_eq!(balances().len() - 1, at1.len());
+
+        let at3 = fetch_at_height(ids(), 3, &mut session).unwrap().unwrap();
+        assert_eq!(balances().len() - 2, at3.len());
+        assert_eq!(None, at3.get(&balances()[1].0));
+        assert_eq!(Some(&1), at3.get(&balances()[2].0));
+
+        let at4 = fetch_at_height(ids(), 4, &mut session).unwrap().unwrap();
+        assert_eq!(Some(&1), at4.get(&balances()[1].0));
+        assert_eq!(fetch(ids(), &mut session).unwrap(), at4);
+
+        // Truncating height 4.
+        assert_eq!(1, truncate_history(3, &mut session).unwrap());
+        let at4 = fetch_at_height(ids(), 4, &mut session).unwrap().unwrap();
+        assert_eq!(at3, at4);
+    }
+
+    #[test]
+    fn prune_history_() {
+        let env = empty_table();
+        let mut session = master(&env);
+        let ids = || balances().into_iter().map(|(k, _)| k);
+
+        update_balance_at(balances().iter(), 1, &mut session).unwrap();
+        update_balance_at(
+            balances().iter().skip(2).map(|(k, _)| (k, -1)),
+            2,
+            &mut session,
+        )
+        .unwrap();
+        let at2 = fetch_at_height(ids(), 2, &mut session).unwrap();
+
+        assert_eq!(None, pruned_height(&mut session).unwrap());
+        // Rows at height 1 are deleted except for those of the first two ResourceIds.
+        assert_eq!(
+            balances().len() - 2,
+            prune_history(2, &mut session).unwrap()
+        );
+        assert_eq!(Some(2), pruned_height(&mut session).unwrap());
+        assert_eq!(0, prune_history(1, &mut session).unwrap());
+
+        assert_eq!(None, fetch_at_height(ids(), 1, &mut session).unwrap());
+        assert_eq!(at2, fetch_at_height(ids(), 2, &mut session).unwrap());
+    }
 }
@@ -0,0 +1,169 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `session_queue` hands the ownership of the connection to the waiting threads in order.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, ThreadId};
+
+#[derive(Default)]
+struct State {
+    owner: Option<ThreadId>,
+    /// The tickets of the waiting threads in the arrival order, and whether each is for a master
+    /// session.
+    waiting: VecDeque<(u64, bool)>,
+    next_ticket: u64,
+}
+
+impl State {
+    /// Returns the ticket to be served next.
+    ///
+    /// The oldest master is preferred if `prioritize_master` is `true` ; otherwise, the oldest
+    /// one.
+    fn next(&self, prioritize_master: bool) -> Option<u64> {
+        let master = if prioritize_master {
+            self.waiting.iter().find(|(_, is_master)| *is_master)
+        } else {
+            None
+        };
+
+        master.or(self.waiting.front()).map(|&(ticket, _)| ticket)
+    }
+}
+
+/// `SessionQueue` is a FIFO queue to acquire the connection.
+///
+/// Each thread takes a ticket on arrival and waits until the ticket is served, so that a thread
+/// is never overtaken by the threads arriving later. If `prioritize_master` is `true` , the
+/// threads acquiring a master session overtake those acquiring a slave session.
+#[derive(Default)]
+pub struct SessionQueue {
+    state: Mutex<State>,
+    cond: Condvar,
+    prioritize_master: bool,
+}
+
+impl SessionQueue {
+    /// Sets whether the master sessions overtake the slave sessions.
+    pub fn set_prioritize_master(&mut self, prioritize_master: bool) {
+        self.prioritize_master = prioritize_master;
+    }
+
+    /// Returns `true` if the current thread is the owner.
+    pub fn is_owner(&self) -> bool {
+        let current_id = Some(thread::current().id());
+        self.state.lock().unwrap().owner == current_id
+    }
+
+    /// Blocks until the ticket of the current thread is served, and makes the current thread the
+    /// owner.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the current thread is the owner.
+    pub fn acquire(&self, is_master: bool) {
+        let current_id = Some(thread::current().id());
+        let mut state = self.state.lock().unwrap();
+
+        if state.owner == current_id {
+            drop(state);
+            panic!("One thread tries to acqiure 2 RDB sessions.");
+        }
+
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.waiting.push_back((ticket, is_master));
+
+        while state.owner.is_some() || state.next(self.prioritize_master) != Some(ticket) {
+            state = self.cond.wait(state).unwrap();
+        }
+
+        state.waiting.retain(|&(t, _)| t != ticket);
+        state.owner = current_id;
+    }
+
+    /// Releases the ownership and wakes up the waiting threads.
+    pub fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.owner = None;
+
+        // Only the thread with the next ticket can go ahead, but it is unknown which thread is
+        // waiting for it.
+        self.cond.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(waiting: &[(u64, bool)]) -> State {
+        State {
+            owner: None,
+            waiting: waiting.iter().cloned().collect(),
+            next_ticket: 0,
+        }
+    }
+
+    #[test]
+    fn next() {
+        assert_eq!(None, state(&[]).next(false));
+        assert_eq!(None, state(&[]).next(true));
+
+        let s = state(&[(3, false), (4, true), (5, true)]);
+        assert_eq!(Some(3), s.next(false));
+        assert_eq!(Some(4), s.next(true));
+
+        let s = state(&[(3, false), (4, false)]);
+        assert_eq!(Some(3), s.next(true));
+    }
+
+    #[test]
+    fn acquire_in_order() {
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let queue = Arc::new(SessionQueue::default());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        queue.acquire(false);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let queue = queue.clone();
+                let order = order.clone();
+                let handle = thread::spawn(move || {
+                    queue.acquire(false);
+                    order.lock().unwrap().push(i);
+                    queue.release();
+                });
+
+                // Wait for the thread to take the ticket.
+                while queue.state.lock().unwrap().waiting.len() <= i {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                handle
+            })
+            .collect();
+
+        queue.release();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(vec![0, 1, 2, 3], *order.lock().unwrap());
+    }
+}
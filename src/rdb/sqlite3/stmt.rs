@@ -15,16 +15,23 @@
 // along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::{
-    sqlite3, sqlite3_bind_blob, sqlite3_bind_int64, sqlite3_bind_null, sqlite3_changes,
-    sqlite3_clear_bindings, sqlite3_column_blob, sqlite3_column_bytes, sqlite3_column_count,
-    sqlite3_column_int64, sqlite3_column_type, sqlite3_db_handle, sqlite3_finalize,
-    sqlite3_prepare_v2, sqlite3_reset, sqlite3_step, sqlite3_stmt, Error, SQLITE_BLOB,
-    SQLITE_INTEGER, SQLITE_NULL, SQLITE_RANGE, SQLITE_TOOBIG,
+    sqlite3, sqlite3_bind_blob, sqlite3_bind_int64, sqlite3_bind_null, sqlite3_bind_text,
+    sqlite3_changes, sqlite3_clear_bindings, sqlite3_column_blob, sqlite3_column_bytes,
+    sqlite3_column_count, sqlite3_column_int64, sqlite3_column_text, sqlite3_column_type,
+    sqlite3_db_handle, sqlite3_finalize, sqlite3_prepare_v2, sqlite3_reset, sqlite3_step,
+    sqlite3_stmt, sqlite3_stmt_readonly, Error, SQLITE_BLOB, SQLITE_INTEGER, SQLITE_MISMATCH,
+    SQLITE_NULL, SQLITE_RANGE, SQLITE_TEXT, SQLITE_TOOBIG,
 };
+use crate::data_types::CryptoHash;
 use core::convert::TryFrom;
 use core::marker::PhantomData;
 use core::ptr;
 use std::os::raw::{c_char, c_int, c_void};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The max delay between the retries of [`Stmt::step`] while the database is locked.
+const MAX_BUSY_DELAY: Duration = Duration::from_millis(100);
 
 /// Wrapper of C [`sqlite3_stmt`] .
 ///
@@ -33,6 +40,7 @@ pub struct Stmt<'a> {
     raw: *mut sqlite3_stmt,
     column_count: c_int,
     is_row: bool,
+    busy_timeout: Duration,
     _con: PhantomData<&'a mut sqlite3>,
     _sql: PhantomData<&'a str>,
 }
@@ -60,6 +68,7 @@ impl<'a> Stmt<'a> {
                     raw,
                     column_count,
                     is_row: false,
+                    busy_timeout: Duration::default(),
                     _con: PhantomData,
                     _sql: PhantomData,
                 })
@@ -105,26 +114,53 @@ impl Stmt<'_> {
     ///
     /// Otherwise, i.e. [`sqlite3_step`] failed, calls [`reset`] and returns `Err` .
     ///
+    /// If the database is locked by another connection (i.e. [`Error::is_busy`] ) before any row
+    /// is returned, retries with the exponential backoff until the busy timeout is over.
+    ///
     /// [`reset`]: Self::reset
     /// [`sqlite3_step`]: https://www.sqlite.org/c3ref/step.html
     pub fn step(&mut self) -> Result<bool, Error> {
-        let code = unsafe { sqlite3_step(self.raw) };
-        match Error::new(code) {
-            Error::DONE => {
-                self.reset();
-                Ok(false)
-            }
-            Error::ROW => {
-                self.is_row = true;
-                Ok(true)
-            }
-            e => {
-                self.reset();
-                Err(e)
+        let mut started: Option<Instant> = None;
+        let mut delay = Duration::from_millis(1);
+
+        loop {
+            let code = unsafe { sqlite3_step(self.raw) };
+            match Error::new(code) {
+                Error::DONE => {
+                    self.reset();
+                    return Ok(false);
+                }
+                Error::ROW => {
+                    self.is_row = true;
+                    return Ok(true);
+                }
+                e if e.is_busy() && !self.is_row => {
+                    let elapsed = started.get_or_insert_with(Instant::now).elapsed();
+                    if self.busy_timeout <= elapsed {
+                        self.reset();
+                        return Err(e);
+                    }
+
+                    self.reset();
+                    thread::sleep(delay.min(self.busy_timeout - elapsed));
+                    delay = (delay * 2).min(MAX_BUSY_DELAY);
+                }
+                e => {
+                    self.reset();
+                    return Err(e);
+                }
             }
         }
     }
 
+    /// Sets how long method [`step`] retries while the database is locked by another
+    /// connection.
+    ///
+    /// [`step`]: Self::step
+    pub fn set_busy_timeout(&mut self, timeout: Duration) {
+        self.busy_timeout = timeout;
+    }
+
     /// Wrapper of C function [`sqlite3_bind_int64`] .
     ///
     /// Calls method [`reset`] if necessary, and calls [`sqlite3_bind_int64`] .
@@ -180,6 +216,34 @@ impl Stmt<'_> {
         }
     }
 
+    /// Wrapper of C function [`sqlite3_bind_text`] .
+    ///
+    /// Calls method [`reset`] if necessary, and calls [`sqlite3_bind_text`] .
+    /// Note that `index` starts at 1, not 0.
+    ///
+    /// [`reset`]: Self::reset
+    /// [`sqlite3_bind_text`]: https://www.sqlite.org/c3ref/bind_blob.html
+    pub fn bind_text<'a, 'b>(&'a mut self, index: usize, val: &'b str) -> Result<(), Error>
+    where
+        'b: 'a,
+    {
+        // self.reset() was not called after self.step() returns true.
+        if self.is_row {
+            self.reset();
+        }
+
+        let index = c_int::try_from(index).or(Err(Error::new(SQLITE_RANGE)))?;
+        let ptr = val.as_ptr() as *const c_char;
+        let len = c_int::try_from(val.len()).or(Err(Error::new(SQLITE_TOOBIG)))?;
+        const DESTRUCTOR: *const c_void = core::ptr::null();
+
+        let code = unsafe { sqlite3_bind_text(self.raw, index, ptr, len, DESTRUCTOR) };
+        match Error::new(code) {
+            Error::OK => Ok(()),
+            e => Err(e),
+        }
+    }
+
     /// Wrapper of C function [`sqlite3_bind_null`] .
     ///
     /// Calls method [`reset`] if necessary, and calls [`sqlite3_bind_null`] .
@@ -271,13 +335,178 @@ impl Stmt<'_> {
                 SQLITE_BLOB => {
                     let ptr = sqlite3_column_blob(self.raw, index) as *const u8;
                     let len = sqlite3_column_bytes(self.raw, index) as usize;
-                    Some(core::slice::from_raw_parts(ptr, len))
+                    // sqlite3_column_blob() returns NULL for a zero-length blob.
+                    if ptr.is_null() {
+                        Some(&[])
+                    } else {
+                        Some(core::slice::from_raw_parts(ptr, len))
+                    }
                 }
                 _ => panic!("Bad column type"),
             }
         }
     }
 
+    /// Returns `Err` if the type of the value at column `index` is neither Null nor `expected` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// [`step`]: Self::step
+    fn check_column_type(&self, index: usize, expected: c_int) -> Result<(), Error> {
+        assert_eq!(true, self.is_row);
+        assert!(index < (self.column_count as usize));
+
+        match unsafe { sqlite3_column_type(self.raw, index as c_int) } {
+            SQLITE_NULL => Ok(()),
+            t if t == expected => Ok(()),
+            _ => Err(Error::new(SQLITE_MISMATCH)),
+        }
+    }
+
+    /// Same to method [`column_int`] except for that this method returns `Err` instead of
+    /// panicking if the column value type is neither Null nor Integer.
+    ///
+    /// [`column_int`]: Self::column_int
+    pub fn try_column_int(&mut self, index: usize) -> Result<Option<i64>, Error> {
+        self.check_column_type(index, SQLITE_INTEGER)?;
+        Ok(self.column_int(index))
+    }
+
+    /// Same to method [`column_blob`] except for that this method returns `Err` instead of
+    /// panicking if the column value type is neither Null nor Blob.
+    ///
+    /// [`column_blob`]: Self::column_blob
+    pub fn try_column_blob(&mut self, index: usize) -> Result<Option<&[u8]>, Error> {
+        self.check_column_type(index, SQLITE_BLOB)?;
+        Ok(self.column_blob(index))
+    }
+
+    /// Same to method [`column_text`] except for that this method returns `Err` instead of
+    /// panicking if the column value type is neither Null nor Text.
+    ///
+    /// [`column_text`]: Self::column_text
+    pub fn try_column_text(&mut self, index: usize) -> Result<Option<&str>, Error> {
+        self.check_column_type(index, SQLITE_TEXT)?;
+        self.column_text(index)
+    }
+
+    /// Calls method [`column_blob`] and copies the value into a new `Vec` .
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions to [`column_blob`] .
+    ///
+    /// [`column_blob`]: Self::column_blob
+    pub fn column_blob_owned(&mut self, index: usize) -> Option<Vec<u8>> {
+        self.column_blob(index).map(<[u8]>::to_vec)
+    }
+
+    /// Wrapper of C function [`sqlite3_column_type`] , [`sqlite3_column_text`] , and
+    /// [`sqlite3_column_bytes`] .
+    ///
+    /// This method calls [`sqlite3_column_type`] first.
+    ///
+    /// If the value type is Null, returns `None` , or if the value type is Text, calls
+    /// [`sqlite3_column_text`] and [`sqlite3_column_bytes`] and returns the result.
+    /// Returns `Err` if the value is not a valid UTF-8.
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the previous [`step`] did not returns `true` or [`step`] did not called.
+    ///
+    /// Panics if `index` is out of range.
+    ///
+    /// Panics if the column value type is neither Null nor Text.
+    ///
+    /// [`step`]: Self::step
+    /// [`sqlite3_column_type`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_text`]: https://www.sqlite.org/c3ref/column_blob.html
+    /// [`sqlite3_column_bytes`]: https://www.sqlite.org/c3ref/column_blob.html
+    pub fn column_text(&mut self, index: usize) -> Result<Option<&str>, Error> {
+        assert_eq!(true, self.is_row);
+        assert!(index < (self.column_count as usize));
+
+        let index = index as c_int;
+        let bytes = unsafe {
+            match sqlite3_column_type(self.raw, index) {
+                SQLITE_NULL => return Ok(None),
+                SQLITE_TEXT => {
+                    // sqlite3_column_text() must be called before sqlite3_column_bytes().
+                    let ptr = sqlite3_column_text(self.raw, index);
+                    let len = sqlite3_column_bytes(self.raw, index) as usize;
+                    if ptr.is_null() {
+                        &[]
+                    } else {
+                        core::slice::from_raw_parts(ptr, len)
+                    }
+                }
+                _ => panic!("Bad column type"),
+            }
+        };
+
+        match core::str::from_utf8(bytes) {
+            Ok(s) => Ok(Some(s)),
+            Err(_) => Err(Error::new(SQLITE_MISMATCH)),
+        }
+    }
+
+    /// Calls method [`step`] until it returns `false` , and collects the result of `f` for each
+    /// row.
+    ///
+    /// `f` takes `self` at each row to read the columns.
+    ///
+    /// [`step`]: Self::step
+    pub fn query_map<T, F>(&mut self, mut f: F) -> Result<Vec<T>, Error>
+    where
+        F: FnMut(&mut Self) -> Result<T, Error>,
+    {
+        let mut ret = Vec::new();
+        while self.step()? {
+            match f(self) {
+                Ok(t) => ret.push(t),
+                Err(e) => {
+                    self.reset();
+                    return Err(e);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Calls method [`column_blob`] and copies the value into a new [`CryptoHash`] instance.
+    ///
+    /// Returns `None` if the value type is Null, or returns `Err` if the byte length of the value
+    /// does not equal to `H::LEN` . (i.e. The value was stored with another hash type.)
+    ///
+    /// Note that `index` starts at 0, not 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions to [`column_blob`] .
+    ///
+    /// [`column_blob`]: Self::column_blob
+    /// [`CryptoHash`]: crate::data_types::CryptoHash
+    pub fn column_hash<H>(&mut self, index: usize) -> Result<Option<H>, Error>
+    where
+        H: CryptoHash,
+    {
+        match self.column_blob(index) {
+            None => Ok(None),
+            Some(bytes) => match H::try_copy_bytes(bytes) {
+                None => Err(Error::new(SQLITE_MISMATCH)),
+                Some(h) => Ok(Some(h)),
+            },
+        }
+    }
+
     /// Returns the number of rows the last SQL execution via the DB connection modified.
     pub fn last_changes(&self) -> usize {
         unsafe {
@@ -285,4 +514,79 @@ impl Stmt<'_> {
             sqlite3_changes(pdb) as usize
         }
     }
+
+    /// Wrapper of C function [`sqlite3_stmt_readonly`] .
+    ///
+    /// Returns `true` if `self` makes no direct change to the database.
+    ///
+    /// [`sqlite3_stmt_readonly`]: https://www.sqlite.org/c3ref/stmt_readonly.html
+    pub fn is_readonly(&self) -> bool {
+        unsafe { sqlite3_stmt_readonly(self.raw) != 0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::connection::Connection;
+    use super::*;
+
+    #[test]
+    fn text_and_owned_blob() {
+        let mut con = Connection::open_memory_db().unwrap();
+        {
+            let mut stmt = con
+                .stmt_once("CREATE TABLE t(name TEXT, data BLOB)")
+                .unwrap();
+            stmt.step().unwrap();
+        }
+
+        let stmt = con.stmt("INSERT INTO t VALUES(?1, ?2)").unwrap();
+        for (name, data) in &[("foo", &b"1"[..]), ("bar", &b""[..])] {
+            stmt.bind_text(1, name).unwrap();
+            stmt.bind_blob(2, data).unwrap();
+            assert_eq!(Ok(false), stmt.step());
+        }
+        stmt.bind_null(1).unwrap();
+        stmt.bind_null(2).unwrap();
+        assert_eq!(Ok(false), stmt.step());
+
+        let stmt = con.stmt("SELECT name, data FROM t ORDER BY rowid").unwrap();
+        let rows = stmt
+            .query_map(|stmt| {
+                let name = stmt.column_text(0)?.map(String::from);
+                Ok((name, stmt.column_blob_owned(1)))
+            })
+            .unwrap();
+        assert_eq!(
+            vec![
+                (Some(String::from("foo")), Some(b"1".to_vec())),
+                (Some(String::from("bar")), Some(Vec::new())),
+                (None, None),
+            ],
+            rows
+        );
+
+        let stmt = con.stmt("SELECT data FROM t WHERE name = ?1").unwrap();
+        stmt.bind_text(1, "bar").unwrap();
+        assert_eq!(Ok(1), stmt.query_map(|_| Ok(())).map(|v| v.len()));
+    }
+
+    #[test]
+    fn try_column() {
+        let mut con = Connection::open_memory_db().unwrap();
+        let mut stmt = con.stmt_once("SELECT 1, x'01', 'a', NULL").unwrap();
+        assert_eq!(true, stmt.is_readonly());
+        assert_eq!(Ok(true), stmt.step());
+
+        assert_eq!(Ok(Some(1)), stmt.try_column_int(0));
+        assert_eq!(Ok(Some(&b"\x01"[..])), stmt.try_column_blob(1));
+        assert_eq!(Ok(Some("a")), stmt.try_column_text(2));
+        assert_eq!(Ok(None), stmt.try_column_int(3));
+        assert_eq!(Ok(None), stmt.try_column_blob(3));
+        assert_eq!(Ok(None), stmt.try_column_text(3));
+
+        assert_eq!(true, stmt.try_column_int(1).unwrap_err().is_mismatch());
+        assert_eq!(true, stmt.try_column_blob(2).unwrap_err().is_mismatch());
+        assert_eq!(true, stmt.try_column_text(0).unwrap_err().is_mismatch());
+    }
 }
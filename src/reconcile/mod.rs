@@ -0,0 +1,181 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `reconcile` compares the balances stored in the local RDB with those of a reference node.
+//! `reconcile` depends on module `data_types` and `rdb` .
+//!
+//! `Mouse` does not know how to talk with the reference node; the user implements
+//! [`BalanceSource`] for it.
+//!
+//! [`BalanceSource`]: self::BalanceSource
+
+use crate::data_types::{AssetValue, BlockHeight, ResourceId};
+use crate::rdb::{self, Slave};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// `BalanceSource` provides the balances of a reference node.
+pub trait BalanceSource {
+    /// Fetches the balance of each [`ResourceId`] in `resource_ids` , and returns the height of the
+    /// reference node and the balances.
+    ///
+    /// The returned value need not have the [`ResourceId`] as the key if the balance is 0.
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    fn fetch_balances(
+        &self,
+        resource_ids: &[ResourceId],
+    ) -> Result<(BlockHeight, HashMap<ResourceId, AssetValue>), Box<dyn Error>>;
+}
+
+/// `Mismatch` represents a [`ResourceId`] whose balance differs between the local node and the
+/// reference node.
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The [`ResourceId`] whose balance differs.
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    pub resource_id: ResourceId,
+    /// The balance stored in the local RDB.
+    pub local: AssetValue,
+    /// The balance of the reference node.
+    pub remote: AssetValue,
+}
+
+/// `Report` is the result of function [`reconcile`] .
+///
+/// [`reconcile`]: self::reconcile
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The height of the local main chain, or `None` if the main chain is empty.
+    pub local_height: Option<BlockHeight>,
+    /// The height of the reference node.
+    pub remote_height: BlockHeight,
+    /// The number of the compared [`ResourceId`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    pub checked: usize,
+    /// The [`ResourceId`] s whose balance differs in the order of `resource_ids` passed to
+    /// [`reconcile`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    /// [`reconcile`]: self::reconcile
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Report {
+    /// Returns `true` if the both heights are same, i.e. the mismatches are meaningful.
+    pub fn is_same_height(&self) -> bool {
+        self.local_height == Some(self.remote_height)
+    }
+}
+
+fn diff(
+    resource_ids: &[ResourceId],
+    local: &HashMap<ResourceId, AssetValue>,
+    remote: &HashMap<ResourceId, AssetValue>,
+) -> Vec<Mismatch> {
+    resource_ids
+        .iter()
+        .filter_map(|resource_id| {
+            let local = local.get(resource_id).cloned().unwrap_or(0);
+            let remote = remote.get(resource_id).cloned().unwrap_or(0);
+            if local == remote {
+                None
+            } else {
+                Some(Mismatch {
+                    resource_id: *resource_id,
+                    local,
+                    remote,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Fetches the balances of `resource_ids` from both the local RDB and `source` , and reports the
+/// mismatches.
+///
+/// `resource_ids` is the sample to be compared. Comparing all the resources could be too heavy
+/// for the reference node.
+///
+/// The balances are compared even if the heights are different; see [`Report::is_same_height`]
+/// before trusting the mismatches.
+///
+/// [`Report::is_same_height`]: self::Report::is_same_height
+pub fn reconcile<S, B>(
+    resource_ids: &[ResourceId],
+    source: &B,
+    session: &mut S,
+) -> Result<Report, Box<dyn Error>>
+where
+    S: Slave,
+    B: BalanceSource + ?Sized,
+{
+    let local_height = rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, session)?
+        .as_ref()
+        .first()
+        .map(|chain_index| chain_index.height());
+    let local = rdb::resources::fetch(resource_ids.iter(), session)?;
+
+    let (remote_height, remote) = source.fetch_balances(resource_ids)?;
+
+    Ok(Report {
+        local_height,
+        remote_height,
+        checked: resource_ids.len(),
+        mismatches: diff(resource_ids, &local, &remote),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource_id(owner: u8) -> ResourceId {
+        unsafe { ResourceId::new(&[owner], &[]) }
+    }
+
+    #[test]
+    fn diff_() {
+        let resource_ids: Vec<ResourceId> = (0..4).map(resource_id).collect();
+
+        let mut local = HashMap::new();
+        local.insert(resource_id(1), 10);
+        local.insert(resource_id(2), 20);
+
+        let mut remote = HashMap::new();
+        remote.insert(resource_id(1), 10);
+        remote.insert(resource_id(2), 21);
+        remote.insert(resource_id(3), 30);
+
+        let expected = vec![
+            Mismatch {
+                resource_id: resource_id(2),
+                local: 20,
+                remote: 21,
+            },
+            Mismatch {
+                resource_id: resource_id(3),
+                local: 0,
+                remote: 30,
+            },
+        ];
+        assert_eq!(expected, diff(&resource_ids, &local, &remote));
+    }
+}
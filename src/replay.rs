@@ -0,0 +1,525 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `replay` records the workload of a node as an anonymized trace, and replays it against
+//! another node to compare the configurations.
+//! `replay` depends on module `data_types` , and function [`replay`] depends on `test_utils` as
+//! well.
+//!
+//! # Trace format
+//!
+//! A trace is a text, and each line is an operation; the offset from the start in microseconds,
+//! the kind, and the arguments separated by a space. The lines starting with '#' are ignored.
+//!
+//! - "fetch n": fetches the acid added by the n-th "insert" (counted from 0), or "fetch -" for
+//!   an acid not added in the trace.
+//! - "insert bytes": adds a pending acid of `bytes` bytes intrinsic data.
+//! - "apply acids bytes": commits a block of `acids` acids, each of them is `bytes` bytes.
+//!
+//! No id nor data is recorded, so a trace can be shared without exposing the production data.
+//!
+//! Function [`replay`] is available only if feature "test_utils" is specified, because it
+//! generates the acids by module `test_utils` .
+//!
+//! [`replay`]: self::replay
+
+use crate::data_types::{Acid, CAcid, Id};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// `Op` is an anonymized operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Fetches the acid added by the n-th [`Op::Insert`] , or `None` if the acid was not added
+    /// in the trace.
+    Fetch(Option<u64>),
+    /// Adds a pending acid of the bytes.
+    Insert(usize),
+    /// Commits a block of the acids, each of them is the bytes.
+    Apply {
+        /// The number of the acids.
+        acids: usize,
+        /// The bytes of each acid.
+        bytes: usize,
+    },
+}
+
+impl Op {
+    /// Returns the kind of `self` .
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Op::Fetch(_) => "fetch",
+            Op::Insert(_) => "insert",
+            Op::Apply { .. } => "apply",
+        }
+    }
+}
+
+/// `Trace` is the recorded operations with the offset from the start.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    ops: Vec<(Duration, Op)>,
+}
+
+impl Trace {
+    /// Provides a reference to the operations in the order of the offset.
+    pub fn ops(&self) -> &[(Duration, Op)] {
+        &self.ops
+    }
+
+    /// Writes `self` into `w` in the trace format.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (offset, op) in self.ops.iter() {
+            write!(w, "{} {}", offset.as_micros(), op.kind())?;
+            match op {
+                Op::Fetch(None) => writeln!(w, " -")?,
+                Op::Fetch(Some(n)) => writeln!(w, " {}", n)?,
+                Op::Insert(bytes) => writeln!(w, " {}", bytes)?,
+                Op::Apply { acids, bytes } => writeln!(w, " {} {}", acids, bytes)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a trace from `r` .
+    pub fn read<R: BufRead>(r: R) -> Result<Self, Box<dyn Error>> {
+        let mut ops = Vec::new();
+
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let op = parse_line(line).map_err(|e| {
+                let msg = format!("Failed to parse the trace at line {}: {}", i + 1, e);
+                Box::<dyn Error>::from(msg)
+            })?;
+            ops.push(op);
+        }
+
+        Ok(Self { ops })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(Duration, Op), Box<dyn Error>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let offset = Duration::from_micros(words[0].parse()?);
+
+    let op = match &words[1..] {
+        ["fetch", "-"] => Op::Fetch(None),
+        ["fetch", n] => Op::Fetch(Some(n.parse()?)),
+        ["insert", bytes] => Op::Insert(bytes.parse()?),
+        ["apply", acids, bytes] => Op::Apply {
+            acids: acids.parse()?,
+            bytes: bytes.parse()?,
+        },
+        _ => return Err(Box::from(format!("unknown operation '{}'", line))),
+    };
+
+    Ok((offset, op))
+}
+
+/// `Recorder` records the operations into a [`Trace`] .
+///
+/// The ids are replaced with the order of the insertion, and the data is replaced with the
+/// length.
+///
+/// [`Trace`]: self::Trace
+pub struct Recorder {
+    start: Instant,
+    state: Mutex<RecorderState>,
+}
+
+#[derive(Default)]
+struct RecorderState {
+    ops: Vec<(Duration, Op)>,
+    insert_count: u64,
+    /// The order of the first insertion of each id.
+    inserted: HashMap<Id, u64>,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    /// Creates a new instance starting now.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            state: Mutex::default(),
+        }
+    }
+
+    fn push(&self, state: &mut RecorderState, op: Op) {
+        state.ops.push((self.start.elapsed(), op));
+    }
+
+    /// Records that `id` is fetched.
+    pub fn record_fetch(&self, id: &Id) {
+        let mut state = self.state.lock().unwrap();
+        let n = state.inserted.get(id).copied();
+        self.push(&mut state, Op::Fetch(n));
+    }
+
+    /// Records that `acid` is added as a pending acid.
+    pub fn record_insert(&self, acid: &dyn Acid) {
+        let mut state = self.state.lock().unwrap();
+        let n = state.insert_count;
+        state.insert_count += 1;
+        state.inserted.entry(*acid.id()).or_insert(n);
+        self.push(&mut state, Op::Insert(acid.intrinsic().len()));
+    }
+
+    /// Records that a block of `acids` is committed.
+    pub fn record_apply(&self, acids: &[CAcid]) {
+        let bytes = match acids.len() {
+            0 => 0,
+            n => {
+                acids
+                    .iter()
+                    .map(|acid| acid.intrinsic().len())
+                    .sum::<usize>()
+                    / n
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let op = Op::Apply {
+            acids: acids.len(),
+            bytes,
+        };
+        self.push(&mut state, op);
+    }
+
+    /// Returns the recorded trace.
+    pub fn finish(self) -> Trace {
+        let mut ops = self.state.into_inner().unwrap().ops;
+        ops.sort_by_key(|(offset, _)| *offset);
+        Trace { ops }
+    }
+}
+
+/// `Stats` is the latencies of one kind of the operations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    latencies: Vec<Duration>,
+}
+
+impl Stats {
+    fn push(&mut self, latency: Duration) {
+        let index = match self.latencies.binary_search(&latency) {
+            Ok(i) => i,
+            Err(i) => i,
+        };
+        self.latencies.insert(index, latency);
+    }
+
+    /// Returns the number of the operations.
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Returns the mean of the latencies, or 0 if empty.
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::default(),
+            n => self.latencies.iter().sum::<Duration>() / n as u32,
+        }
+    }
+
+    /// Returns the `p` percentile of the latencies, or 0 if empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is greater than 100.
+    pub fn percentile(&self, p: u32) -> Duration {
+        assert!(p <= 100);
+        match self.count() {
+            0 => Duration::default(),
+            n => self.latencies[(n - 1) * p as usize / 100],
+        }
+    }
+}
+
+/// `Report` is the result of function [`replay`] .
+///
+/// [`replay`]: self::replay
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The wall time to replay the trace.
+    pub elapsed: Duration,
+    /// The latencies of each kind of the operations.
+    pub stats: HashMap<&'static str, Stats>,
+}
+
+impl Report {
+    /// Returns the number of the operations per second.
+    pub fn throughput(&self) -> f64 {
+        let count: usize = self.stats.values().map(Stats::count).sum();
+        match self.elapsed.as_secs_f64() {
+            secs if secs == 0.0 => 0.0,
+            secs => count as f64 / secs,
+        }
+    }
+
+    /// Returns the comparison of `self` against `baseline` ; e.g. the report with the current
+    /// configuration.
+    pub fn compare<'a>(&'a self, baseline: &'a Report) -> Comparison<'a> {
+        Comparison {
+            baseline,
+            candidate: self,
+        }
+    }
+}
+
+/// `Comparison` displays the throughput and the latency deltas between 2 [`Report`] s.
+///
+/// [`Report`]: self::Report
+pub struct Comparison<'a> {
+    baseline: &'a Report,
+    candidate: &'a Report,
+}
+
+impl fmt::Display for Comparison<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delta = |base: f64, cand: f64| {
+            if base == 0.0 {
+                0.0
+            } else {
+                (cand - base) / base * 100.0
+            }
+        };
+
+        let base = self.baseline.throughput();
+        let cand = self.candidate.throughput();
+        writeln!(
+            f,
+            "throughput: {:.1} -> {:.1} ops/s ({:+.1}%)",
+            base,
+            cand,
+            delta(base, cand)
+        )?;
+
+        let mut kinds: Vec<_> = self.baseline.stats.keys().collect();
+        kinds.sort();
+        let empty = Stats::default();
+        for kind in kinds {
+            let base = &self.baseline.stats[kind];
+            let cand = self.candidate.stats.get(kind).unwrap_or(&empty);
+            for &p in &[50, 99] {
+                let b = base.percentile(p);
+                let c = cand.percentile(p);
+                writeln!(
+                    f,
+                    "{} p{}: {:?} -> {:?} ({:+.1}%)",
+                    kind,
+                    p,
+                    b,
+                    c,
+                    delta(b.as_secs_f64(), c.as_secs_f64())
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `Pace` is how fast function [`replay`] issues the operations.
+///
+/// [`replay`]: self::replay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pace {
+    /// Issues each operation at the recorded offset.
+    Recorded,
+    /// Issues the operations one after another.
+    AsFastAsPossible,
+}
+
+/// Replays `trace` against the node of `env` , and returns the latencies.
+///
+/// The acids are generated by [`test_utils`] with `seed` , so the same seed replays the same
+/// data. The blocks are committed on the tip of the main chain.
+///
+/// `env` should be a test node; the generated acids are stored in it.
+///
+/// [`test_utils`]: crate::test_utils
+#[cfg(any(test, feature = "test_utils"))]
+pub fn replay(
+    trace: &Trace,
+    pace: Pace,
+    seed: u64,
+    env: &crate::GlobalEnvironment,
+) -> Result<Report, Box<dyn Error>> {
+    use crate::data_types::{BlockHeight, ChainIndex, CryptoHash};
+    use crate::rdb;
+    use crate::test_utils::{GeneratedAcid, Rng};
+
+    let mut rng = Rng::new(seed);
+    let mut inserted: Vec<Id> = Vec::new();
+    let mut report = Report::default();
+
+    let (mut height, mut prev) = {
+        let mut session = rdb::slave(&env.rdb);
+        let tip = rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, &mut session)?;
+        match tip.as_ref().first() {
+            Some(c) => (c.height(), Some(*c.id())),
+            None => (0, None),
+        }
+    };
+
+    let start = Instant::now();
+    for &(offset, op) in trace.ops() {
+        if pace == Pace::Recorded {
+            if let Some(wait) = offset.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+
+        let op_start = Instant::now();
+        match op {
+            Op::Fetch(n) => {
+                let id = match n.and_then(|n| inserted.get(n as usize)) {
+                    Some(id) => *id,
+                    None => Id::calculate(&rng.next_u64().to_le_bytes()),
+                };
+                crate::fetch_acid(&id, env)?;
+            }
+            Op::Insert(bytes) => {
+                let acid = CAcid::from(GeneratedAcid::new(Vec::new(), bytes, &mut rng));
+                inserted.push(*acid.id());
+                crate::add_pending_acid(acid, env)?;
+            }
+            Op::Apply { acids, bytes } => {
+                let mut all: Vec<CAcid> = (0..acids.saturating_sub(1))
+                    .map(|_| CAcid::from(GeneratedAcid::new(Vec::new(), bytes, &mut rng)))
+                    .collect();
+                let parents = prev
+                    .into_iter()
+                    .chain(all.iter().map(|a| *a.id()))
+                    .collect();
+                let block = CAcid::from(GeneratedAcid::new(parents, bytes, &mut rng));
+
+                height += 1;
+                prev = Some(*block.id());
+                let chain_index = ChainIndex::new(height, block.id());
+                all.insert(0, block);
+                crate::commit_block(&chain_index, &all, false, env)?;
+            }
+        }
+        report
+            .stats
+            .entry(op.kind())
+            .or_default()
+            .push(op_start.elapsed());
+    }
+
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::CryptoHash;
+    use crate::test_utils::{GeneratedAcid, Rng};
+
+    #[test]
+    fn write_read() {
+        let trace = Trace {
+            ops: vec![
+                (Duration::from_micros(0), Op::Insert(100)),
+                (Duration::from_micros(5), Op::Fetch(Some(0))),
+                (Duration::from_micros(7), Op::Fetch(None)),
+                (
+                    Duration::from_micros(12),
+                    Op::Apply {
+                        acids: 3,
+                        bytes: 40,
+                    },
+                ),
+            ],
+        };
+
+        let mut buffer = Vec::new();
+        trace.write(&mut buffer).unwrap();
+        assert_eq!(
+            "0 insert 100\n5 fetch 0\n7 fetch -\n12 apply 3 40\n",
+            String::from_utf8(buffer.clone()).unwrap()
+        );
+        assert_eq!(trace, Trace::read(&buffer[..]).unwrap());
+
+        assert_eq!(
+            true,
+            Trace::read(&b"# comment\n\n"[..]).unwrap().ops().is_empty()
+        );
+        assert_eq!(true, Trace::read(&b"3 delete 1\n"[..]).is_err());
+        assert_eq!(true, Trace::read(&b"x insert 1\n"[..]).is_err());
+    }
+
+    #[test]
+    fn recorder_anonymizes() {
+        let mut rng = Rng::new(1);
+        let a = CAcid::from(GeneratedAcid::new(Vec::new(), 10, &mut rng));
+        let b = CAcid::from(GeneratedAcid::new(Vec::new(), 20, &mut rng));
+
+        let recorder = Recorder::new();
+        recorder.record_insert(&*a);
+        recorder.record_insert(&*b);
+        recorder.record_fetch(b.id());
+        recorder.record_fetch(&Id::zeroed());
+        recorder.record_apply(&[a, b]);
+
+        let ops: Vec<Op> = recorder.finish().ops().iter().map(|(_, op)| *op).collect();
+        assert_eq!(
+            vec![
+                Op::Insert(10),
+                Op::Insert(20),
+                Op::Fetch(Some(1)),
+                Op::Fetch(None),
+                Op::Apply {
+                    acids: 2,
+                    bytes: 15
+                },
+            ],
+            ops
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::default();
+        assert_eq!(Duration::default(), stats.percentile(50));
+
+        for ms in &[5, 1, 3, 2, 4] {
+            stats.push(Duration::from_millis(*ms));
+        }
+        assert_eq!(5, stats.count());
+        assert_eq!(Duration::from_millis(3), stats.mean());
+        assert_eq!(Duration::from_millis(1), stats.percentile(0));
+        assert_eq!(Duration::from_millis(3), stats.percentile(50));
+        assert_eq!(Duration::from_millis(5), stats.percentile(100));
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `retention` derives what to keep, to archive, and to delete from a single retention policy.
+//! `retention` depends on module `data_types` and `rdb` .
+//!
+//! The policy is given by the following arguments in the number of the blocks from the tip, and
+//! validated at `check()` .
+//!
+//! - --retention-hot-blocks: the blocks kept in the fast storage
+//! - --retention-archive-after: the blocks older than this are archived
+//! - --retention-delete-after: the blocks older than this are deleted
+//! - --retention-undo-blocks: the blocks which can be undone; i.e. the balance history is kept
+//!
+//! Function [`plan`] converts the policy into the heights, and function [`apply`] drives the
+//! subsystems. For now, only the balance history in the RDB is pruned; the archival and the
+//! deletion are reported in [`Plan`] for the application to perform.
+//!
+//! [`plan`]: self::plan
+//! [`apply`]: self::apply
+//! [`Plan`]: self::Plan
+
+use crate::data_types::BlockHeight;
+use crate::{rdb, Config, ModuleEnvironment};
+use clap::{App, Arg};
+use std::error::Error;
+
+/// `Plan` is the heights derived from the retention policy at a tip.
+///
+/// Each field is `None` if the corresponding argument is not specified, or if the chain is not
+/// long enough; i.e. nothing is to be done.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// The blocks at this height or higher should be kept in the fast storage.
+    pub hot_from: Option<BlockHeight>,
+    /// The blocks lower than this height should be archived.
+    pub archive_below: Option<BlockHeight>,
+    /// The blocks lower than this height should be deleted.
+    pub delete_below: Option<BlockHeight>,
+    /// The balance history lower than this height should be pruned.
+    pub undo_below: Option<BlockHeight>,
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --retention-hot-blocks
+/// - --retention-archive-after
+/// - --retention-delete-after
+/// - --retention-undo-blocks
+///
+/// # Default
+///
+/// Nothing is specified by default; i.e. everything is kept forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Environment {
+    hot_blocks: Option<BlockHeight>,
+    archive_after: Option<BlockHeight>,
+    delete_after: Option<BlockHeight>,
+    undo_blocks: Option<BlockHeight>,
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.args(&[
+            Arg::with_name("retention_hot_blocks")
+                .help("The number of the recent blocks to keep in the fast storage.")
+                .long("--retention-hot-blocks")
+                .takes_value(true),
+            Arg::with_name("retention_archive_after")
+                .help(
+                    "The blocks older than this number of the blocks from the tip are archived.
+It must not be less than '--retention-hot-blocks'.",
+                )
+                .long("--retention-archive-after")
+                .takes_value(true),
+            Arg::with_name("retention_delete_after")
+                .help(
+                    "The blocks older than this number of the blocks from the tip are deleted.
+It must be greater than '--retention-archive-after', and must not be less than the others.",
+                )
+                .long("--retention-delete-after")
+                .takes_value(true),
+            Arg::with_name("retention_undo_blocks")
+                .help("The number of the recent blocks to keep the balance history for.")
+                .long("--retention-undo-blocks")
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let parse = |name: &str, long: &str| -> Result<Option<BlockHeight>, crate::Error> {
+            match config.args().value_of(name) {
+                None => Ok(None),
+                Some(s) => match s.parse::<BlockHeight>() {
+                    Ok(n) if 0 <= n => Ok(Some(n)),
+                    Ok(n) => {
+                        let msg = format!("Failed to parse '{}': negative value {}", long, n);
+                        Err(crate::Error::Config(msg))
+                    }
+                    Err(e) => {
+                        let msg = format!("Failed to parse '{}': {}", long, e);
+                        Err(crate::Error::Config(msg))
+                    }
+                },
+            }
+        };
+
+        self.hot_blocks = parse("retention_hot_blocks", "--retention-hot-blocks")?;
+        self.archive_after = parse("retention_archive_after", "--retention-archive-after")?;
+        self.delete_after = parse("retention_delete_after", "--retention-delete-after")?;
+        self.undo_blocks = parse("retention_undo_blocks", "--retention-undo-blocks")?;
+
+        self.validate().map_err(crate::Error::Config)
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Returns an error if the policy is inconsistent.
+    fn validate(&self) -> Result<(), String> {
+        let le = |a: Option<BlockHeight>, b: Option<BlockHeight>| match (a, b) {
+            (Some(a), Some(b)) => a <= b,
+            _ => true,
+        };
+
+        if !le(self.hot_blocks, self.archive_after) {
+            return Err(String::from(
+                "'--retention-archive-after' is less than '--retention-hot-blocks'.",
+            ));
+        }
+
+        if let (Some(archive), Some(delete)) = (self.archive_after, self.delete_after) {
+            if delete <= archive {
+                return Err(String::from(
+                    "'--retention-delete-after' must be greater than '--retention-archive-after'.",
+                ));
+            }
+        }
+
+        if !le(self.hot_blocks, self.delete_after) {
+            return Err(String::from(
+                "'--retention-delete-after' is less than '--retention-hot-blocks'.",
+            ));
+        }
+
+        if !le(self.undo_blocks, self.delete_after) {
+            return Err(String::from(
+                "'--retention-delete-after' is less than '--retention-undo-blocks'; \
+                 the balance history would outlive the blocks.",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts the retention policy into the heights at `tip_height` .
+pub fn plan(tip_height: BlockHeight, env: &Environment) -> Plan {
+    // The lowest height of the most recent 'n' blocks; i.e. the blocks lower than it is older.
+    let from = |n: Option<BlockHeight>| match n {
+        Some(n) if n < tip_height => Some(tip_height - n + 1),
+        _ => None,
+    };
+
+    Plan {
+        hot_from: from(env.hot_blocks),
+        archive_below: from(env.archive_after),
+        delete_below: from(env.delete_after),
+        undo_below: from(env.undo_blocks),
+    }
+}
+
+/// Calls [`plan`] and prunes the balance history in the RDB, and returns the plan.
+///
+/// See also function [`rdb::resources::prune_history`] .
+///
+/// [`plan`]: self::plan
+/// [`rdb::resources::prune_history`]: crate::rdb::resources::prune_history
+pub fn apply(
+    tip_height: BlockHeight,
+    env: &Environment,
+    rdb_env: &rdb::Environment,
+) -> Result<Plan, Box<dyn Error>> {
+    let plan = plan(tip_height, env);
+
+    if let Some(height) = plan.undo_below {
+        let mut session = rdb::master(rdb_env);
+        let pruned = rdb::resources::prune_history(height, &mut session)?;
+        debug!(
+            "Pruned {} rows of the balance history below height {}.",
+            pruned, height
+        );
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(hot: i64, archive: i64, delete: i64, undo: i64) -> Environment {
+        let f = |n| if n < 0 { None } else { Some(n) };
+        Environment {
+            hot_blocks: f(hot),
+            archive_after: f(archive),
+            delete_after: f(delete),
+            undo_blocks: f(undo),
+        }
+    }
+
+    #[test]
+    fn validate_() {
+        assert_eq!(true, env(-1, -1, -1, -1).validate().is_ok());
+        assert_eq!(true, env(10, 100, 1000, 50).validate().is_ok());
+        assert_eq!(true, env(10, 10, -1, 5000).validate().is_ok());
+
+        assert_eq!(false, env(100, 10, -1, -1).validate().is_ok());
+        assert_eq!(false, env(-1, 100, 100, -1).validate().is_ok());
+        assert_eq!(false, env(100, -1, 10, -1).validate().is_ok());
+        assert_eq!(false, env(-1, -1, 10, 100).validate().is_ok());
+    }
+
+    #[test]
+    fn plan_() {
+        let env = env(10, 100, 1000, 50);
+
+        assert_eq!(Plan::default(), plan(10, &env));
+        assert_eq!(
+            Plan {
+                hot_from: Some(91),
+                archive_below: None,
+                delete_below: None,
+                undo_below: Some(51),
+            },
+            plan(100, &env)
+        );
+        assert_eq!(
+            Plan {
+                hot_from: Some(1991),
+                archive_below: Some(1901),
+                delete_below: Some(1001),
+                undo_below: Some(1951),
+            },
+            plan(2000, &env)
+        );
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `retry` retries a fallible operation with the exponential backoff.
+//! `retry` is independent from other modules.
+
+use std::error::Error;
+use std::fmt::Display;
+use std::thread;
+use std::time::Duration;
+
+/// `Backoff` is the policy to retry an operation.
+///
+/// The delay before the 2nd attempt is `initial_delay` , and it is doubled every time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    attempts: u32,
+    initial_delay: Duration,
+}
+
+impl Default for Backoff {
+    /// Tries only once.
+    fn default() -> Self {
+        Self::new(1, Duration::from_millis(0))
+    }
+}
+
+impl Backoff {
+    /// Creates a new instance to try at most `attempts` times.
+    ///
+    /// 0 `attempts` is regarded as 1.
+    pub fn new(attempts: u32, initial_delay: Duration) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            initial_delay,
+        }
+    }
+
+    /// Calls `f` until it succeeds or until it fails `attempts` times, and returns the result.
+    ///
+    /// Each failure is logged at the warning level. The error after the exhaustion includes all
+    /// the errors.
+    ///
+    /// `what` is the description of the operation to log; e.g. "open KVS".
+    pub fn retry<T, E, F>(&self, what: &str, mut f: F) -> Result<T, Box<dyn Error + Send + Sync>>
+    where
+        E: Display,
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut errors = Vec::new();
+        let mut delay = self.initial_delay;
+
+        for attempt in 1..=self.attempts {
+            match f() {
+                Ok(t) => return Ok(t),
+                Err(e) => {
+                    warn!(
+                        "Failed to {} (attempt {}/{}): {}",
+                        what, attempt, self.attempts, e
+                    );
+                    errors.push(format!("{}: {}", attempt, e));
+                }
+            }
+
+            if attempt < self.attempts {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+
+        let msg = format!(
+            "Failed to {} after {} attempts ({})",
+            what,
+            self.attempts,
+            errors.join("; ")
+        );
+        Err(Box::from(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_succeeds() {
+        let backoff = Backoff::new(3, Duration::from_millis(0));
+
+        let mut count = 0;
+        let res = backoff.retry("test", || {
+            count += 1;
+            if count < 3 {
+                Err("foo")
+            } else {
+                Ok(count)
+            }
+        });
+        assert_eq!(3, res.unwrap());
+    }
+
+    #[test]
+    fn retry_exhausted() {
+        let backoff = Backoff::new(2, Duration::from_millis(0));
+
+        let mut count = 0;
+        let res: Result<(), _> = backoff.retry("test", || {
+            count += 1;
+            Err(format!("error {}", count))
+        });
+
+        assert_eq!(2, count);
+        let msg = res.unwrap_err().to_string();
+        assert_eq!(
+            "Failed to test after 2 attempts (1: error 1; 2: error 2)",
+            msg
+        );
+    }
+
+    #[test]
+    fn default_tries_once() {
+        let mut count = 0;
+        let _: Result<(), _> = Backoff::default().retry("test", || {
+            count += 1;
+            Err("foo")
+        });
+        assert_eq!(1, count);
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `runtime` spawns the worker threads with the name, the CPU affinity, and the priority
+//! configured for each [`WorkerGroup`] .
+//! `runtime` is independent from other modules.
+//!
+//! The CPU affinity and the priority are supported only on Linux so far. On other platforms, they
+//! are ignored with a warning log.
+//!
+//! [`WorkerGroup`]: self::WorkerGroup
+
+mod os;
+
+use crate::{Config, ModuleEnvironment};
+use clap::{App, Arg};
+use std::error::Error;
+use std::io;
+use std::thread::{self, JoinHandle};
+
+const DEFAULT_THREAD_NAME_PREFIX: &'static str = "mouse";
+
+/// `WorkerGroup` is the kind of the worker threads sharing the same settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkerGroup {
+    /// Threads to flush the writes to the KVS.
+    Flush,
+    /// Threads to validate the acids.
+    Validation,
+    /// Threads to communicate with other nodes.
+    Network,
+    /// Threads to fetch the acids from the KVS in advance.
+    Prefetch,
+    /// Threads to run the tasks shared by the modules. (See module [`workers`] .)
+    ///
+    /// [`workers`]: crate::workers
+    Worker,
+}
+
+impl WorkerGroup {
+    /// Returns the name of `self` , which is a part of the thread name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Flush => "flush",
+            Self::Validation => "validation",
+            Self::Network => "network",
+            Self::Prefetch => "prefetch",
+            Self::Worker => "worker",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::Flush => 0,
+            Self::Validation => 1,
+            Self::Network => 2,
+            Self::Prefetch => 3,
+            Self::Worker => 4,
+        }
+    }
+}
+
+/// Argument names for each `WorkerGroup` in the order of `WorkerGroup::index()` .
+///
+/// (name of affinity, long of affinity, name of nice, long of nice)
+static GROUP_ARGS: [(&'static str, &'static str, &'static str, &'static str); 5] = [
+    (
+        "flush_thread_affinity",
+        "--flush-thread-affinity",
+        "flush_thread_nice",
+        "--flush-thread-nice",
+    ),
+    (
+        "validation_thread_affinity",
+        "--validation-thread-affinity",
+        "validation_thread_nice",
+        "--validation-thread-nice",
+    ),
+    (
+        "network_thread_affinity",
+        "--network-thread-affinity",
+        "network_thread_nice",
+        "--network-thread-nice",
+    ),
+    (
+        "prefetch_thread_affinity",
+        "--prefetch-thread-affinity",
+        "prefetch_thread_nice",
+        "--prefetch-thread-nice",
+    ),
+    (
+        "worker_thread_affinity",
+        "--worker-thread-affinity",
+        "worker_thread_nice",
+        "--worker-thread-nice",
+    ),
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ThreadSettings {
+    /// CPU ids to run the thread. Empty means no restriction.
+    affinity: Vec<usize>,
+    /// Nice value of the thread. `None` means to inherit the spawning thread.
+    nice: Option<i32>,
+}
+
+impl ThreadSettings {
+    /// Applies `self` to the current thread.
+    ///
+    /// Logs a warning and continues on failure.
+    fn apply(&self) {
+        if !self.affinity.is_empty() {
+            if let Err(e) = os::set_affinity(&self.affinity) {
+                warn!("Failed to set CPU affinity {:?}: {}", self.affinity, e);
+            }
+        }
+
+        if let Some(nice) = self.nice {
+            if let Err(e) = os::set_nice(nice) {
+                warn!("Failed to set nice value {}: {}", nice, e);
+            }
+        }
+    }
+}
+
+/// Parses comma separated CPU ids like "0,2,3".
+fn parse_affinity(s: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    s.split(',')
+        .map(|cpu| {
+            let cpu: usize = cpu.trim().parse()?;
+            if os::MAX_CPUS <= cpu {
+                let msg = format!("CPU id must be less than {}: {}", os::MAX_CPUS, cpu);
+                Err(Box::<dyn Error>::from(msg))
+            } else {
+                Ok(cpu)
+            }
+        })
+        .collect()
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --thread-name-prefix
+/// - --flush-thread-affinity
+/// - --flush-thread-nice
+/// - --validation-thread-affinity
+/// - --validation-thread-nice
+/// - --network-thread-affinity
+/// - --network-thread-nice
+/// - --prefetch-thread-affinity
+/// - --prefetch-thread-nice
+/// - --worker-thread-affinity
+/// - --worker-thread-nice
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --thread-name-prefix: mouse
+/// - The others: (not specified; i.e. the OS default)
+pub struct Environment {
+    thread_name_prefix: String,
+    settings: [ThreadSettings; 5],
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            thread_name_prefix: String::from(DEFAULT_THREAD_NAME_PREFIX),
+            settings: Default::default(),
+        }
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        let app = app.arg(
+            Arg::with_name("thread_name_prefix")
+                .help("The prefix of the worker thread names.")
+                .long("--thread-name-prefix")
+                .default_value(DEFAULT_THREAD_NAME_PREFIX)
+                .takes_value(true),
+        );
+
+        GROUP_ARGS.iter().fold(
+            app,
+            |app, &(affinity_name, affinity_long, nice_name, nice_long)| {
+                app.args(&[
+                    Arg::with_name(affinity_name)
+                        .help("Comma separated CPU ids to run the worker threads. (Linux only)")
+                        .long(affinity_long)
+                        .takes_value(true),
+                    Arg::with_name(nice_name)
+                        .help("The nice value of the worker threads. (Linux only)")
+                        .long(nice_long)
+                        .allow_hyphen_values(true)
+                        .takes_value(true),
+                ])
+            },
+        )
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let prefix = config.args().value_of("thread_name_prefix").unwrap();
+        self.thread_name_prefix = String::from(prefix);
+
+        for (i, (affinity_name, affinity_long, nice_name, nice_long)) in
+            GROUP_ARGS.iter().enumerate()
+        {
+            if let Some(affinity) = config.args().value_of(affinity_name) {
+                self.settings[i].affinity = parse_affinity(affinity).map_err(|e| {
+                    let msg = format!("Failed to parse '{}': {}", affinity_long, e);
+                    crate::Error::Config(msg)
+                })?;
+            }
+
+            if let Some(nice) = config.args().value_of(nice_name) {
+                let nice = nice.parse().map_err(|e| {
+                    let msg = format!("Failed to parse '{}': {}", nice_long, e);
+                    crate::Error::Config(msg)
+                })?;
+                self.settings[i].nice = Some(nice);
+            }
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+/// Spawns a new thread to run `f` with the settings for `group` .
+///
+/// The thread is named as "{--thread-name-prefix}-{group name}".
+/// The CPU affinity and the priority are applied before `f` is called; if it fails (e.g. the
+/// platform does not support it,) a warning is logged and `f` is called anyway.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::runtime::{spawn, Environment, WorkerGroup};
+///
+/// let env = Environment::default();
+/// let handle = spawn(WorkerGroup::Flush, &env, || {
+///     std::thread::current().name().map(String::from)
+/// })
+/// .unwrap();
+///
+/// assert_eq!(Some("mouse-flush".to_string()), handle.join().unwrap());
+/// ```
+pub fn spawn<F, T>(group: WorkerGroup, env: &Environment, f: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let settings = env.settings[group.index()].clone();
+    let name = format!("{}-{}", env.thread_name_prefix, group.name());
+
+    thread::Builder::new().name(name).spawn(move || {
+        settings.apply();
+        f()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_affinity_() {
+        assert_eq!(vec![0], parse_affinity("0").unwrap());
+        assert_eq!(vec![0, 2, 3], parse_affinity("0, 2,3").unwrap());
+
+        assert_eq!(true, parse_affinity("").is_err());
+        assert_eq!(true, parse_affinity("0,a").is_err());
+        assert_eq!(true, parse_affinity(&os::MAX_CPUS.to_string()).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Platform dependent functions to set the CPU affinity and the priority of the current thread.
+
+use std::io;
+
+/// The number of CPUs 'cpu_set_t' can hold. (= CPU_SETSIZE)
+pub const MAX_CPUS: usize = 1024;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::MAX_CPUS;
+    use core::mem::size_of;
+    use std::io;
+    use std::os::raw::{c_int, c_uint};
+
+    const BITS: usize = 8 * size_of::<u64>();
+
+    /// Same layout as C struct 'cpu_set_t'.
+    #[repr(C)]
+    struct CpuSet([u64; MAX_CPUS / BITS]);
+
+    const PRIO_PROCESS: c_int = 0;
+
+    extern "C" {
+        fn sched_setaffinity(pid: c_int, cpusetsize: usize, mask: *const CpuSet) -> c_int;
+        fn setpriority(which: c_int, who: c_uint, prio: c_int) -> c_int;
+    }
+
+    pub fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+        let mut set = CpuSet([0; MAX_CPUS / BITS]);
+        for &cpu in cpus {
+            set.0[cpu / BITS] |= 1 << (cpu % BITS);
+        }
+
+        // pid 0 means the current thread.
+        match unsafe { sched_setaffinity(0, size_of::<CpuSet>(), &set) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    pub fn set_nice(nice: i32) -> io::Result<()> {
+        // On Linux, the nice value is a per-thread attribute, and 'who = 0' means the current
+        // thread.
+        match unsafe { setpriority(PRIO_PROCESS, 0, nice) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(io::ErrorKind::Other, "Not supported on this platform.")
+    }
+
+    pub fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn set_nice(_nice: i32) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+/// Makes the current thread run only on `cpus` .
+///
+/// # Panics
+///
+/// Panics if any element of `cpus` is greater than or equals to `MAX_CPUS` .
+pub fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    assert_eq!(true, cpus.iter().all(|&cpu| cpu < MAX_CPUS));
+    imp::set_affinity(cpus)
+}
+
+/// Sets the nice value of the current thread.
+pub fn set_nice(nice: i32) -> io::Result<()> {
+    imp::set_nice(nice)
+}
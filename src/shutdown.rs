@@ -0,0 +1,184 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `shutdown` provides [`ShutdownHandle`] to request to stop the node.
+//! `shutdown` is independent from other modules.
+//!
+//! The request comes from method [`ShutdownHandle::shutdown`] or from the OS.
+//! On unix, the request from the OS is signal 'SIGHUP', 'SIGINT', or 'SIGTERM'. On Windows, it is
+//! the console control event; i.e. 'Ctrl+C', 'Ctrl+Break', closing the console, logging off, or
+//! shutting down the system.
+//!
+//! [`ShutdownHandle`]: self::ShutdownHandle
+//! [`ShutdownHandle::shutdown`]: self::ShutdownHandle::shutdown
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// The interval to poll the request from the OS.
+///
+/// The signal handler cannot notify a `Condvar` safely, so [`ShutdownHandle::wait`] polls the
+/// flag that the handler sets.
+///
+/// [`ShutdownHandle::wait`]: self::ShutdownHandle::wait
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Set by the OS handler.
+static REQUESTED_BY_OS: AtomicBool = AtomicBool::new(false);
+
+/// `ShutdownHandle` is a cloneable handle to request the node to stop, and to wait for the
+/// request.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::shutdown::ShutdownHandle;
+/// use std::thread;
+///
+/// let handle = ShutdownHandle::new();
+///
+/// let waiter = {
+///     let handle = handle.clone();
+///     thread::spawn(move || handle.wait())
+/// };
+///
+/// handle.shutdown();
+/// waiter.join().unwrap().unwrap();
+/// assert_eq!(true, handle.is_shutdown());
+/// ```
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl ShutdownHandle {
+    /// Creates a new instance.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests to stop and wakes up the threads waiting for `self` or the clones.
+    pub fn shutdown(&self) {
+        let (mtx, cond) = &*self.inner;
+        *mtx.lock().unwrap() = true;
+        cond.notify_all();
+    }
+
+    /// Returns `true` if [`shutdown`] is called or if the OS requested to stop.
+    ///
+    /// [`shutdown`]: Self::shutdown
+    pub fn is_shutdown(&self) -> bool {
+        let (mtx, _) = &*self.inner;
+        *mtx.lock().unwrap() || REQUESTED_BY_OS.load(Ordering::Acquire)
+    }
+
+    /// Blocks until [`shutdown`] is called or until the OS requests to stop.
+    ///
+    /// This method installs the OS handler at first.
+    ///
+    /// [`shutdown`]: Self::shutdown
+    pub fn wait(&self) -> io::Result<()> {
+        imp::install()?;
+
+        let (mtx, cond) = &*self.inner;
+        let mut requested = mtx.lock().unwrap();
+        while !*requested && !REQUESTED_BY_OS.load(Ordering::Acquire) {
+            requested = cond.wait_timeout(requested, POLL_INTERVAL).unwrap().0;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::REQUESTED_BY_OS;
+    use std::io;
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGHUP: c_int = 1;
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    /// The return value of 'signal()' on error.
+    const SIG_ERR: usize = !0;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn handler(_signum: c_int) {
+        // Only the async-signal-safe operation is allowed here.
+        REQUESTED_BY_OS.store(true, Ordering::Release);
+    }
+
+    pub fn install() -> io::Result<()> {
+        for &signum in &[SIGHUP, SIGINT, SIGTERM] {
+            let f: extern "C" fn(c_int) = handler;
+            if unsafe { signal(signum, f as usize) } == SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::REQUESTED_BY_OS;
+    use std::io;
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    type HandlerRoutine = unsafe extern "system" fn(u32) -> c_int;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: Option<HandlerRoutine>, add: c_int) -> c_int;
+    }
+
+    unsafe extern "system" fn handler(_ctrl_type: u32) -> c_int {
+        REQUESTED_BY_OS.store(true, Ordering::Release);
+
+        // Returns TRUE not to call the default handler which terminates the process at once.
+        1
+    }
+
+    pub fn install() -> io::Result<()> {
+        if unsafe { SetConsoleCtrlHandler(Some(handler), 1) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shutdown_before_wait() {
+        let handle = ShutdownHandle::new();
+        assert_eq!(false, handle.is_shutdown());
+
+        handle.clone().shutdown();
+        assert_eq!(true, handle.is_shutdown());
+        assert_eq!(true, handle.wait().is_ok());
+    }
+}
@@ -0,0 +1,499 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `snapshot` exports the chain state at the tip into a byte stream, and bootstraps a fresh node
+//! from it without replaying the blocks.
+//! `snapshot` depends on module `data_types` and `rdb` .
+//!
+//! The snapshot consists of the tip of the main chain, the balances in RDB table "resources",
+//! and the ids of the acids in the main chain with "chain_height". It does not include the
+//! intrinsic and the extrinsic data of the acids; see function [`kvs::export`] for them.
+//!
+//! # Format
+//!
+//! The stream starts with "MOUSESNP\x01" and the byte size of [`AssetValue`] , followed by 3
+//! sections.
+//!
+//! - The tip: the height as an 8 bytes little endian integer and the id.
+//! - The balances: the chunks of the rows. Each chunk is the number of the rows as a 4 bytes
+//!   little endian integer and the rows, and an empty chunk terminates the section. Each row is
+//!   the length of the owner as 1 byte, the owner, the length of the asset type, the asset type,
+//!   and the value as a little endian integer.
+//! - The acids: the chunks of the rows as well. Each row is "chain_height" and the id.
+//!
+//! # Integrity
+//!
+//! Each section is followed by the SHA-256 of the section, and [`Summary::digest`] is the SHA-256
+//! of them. Compare the digest with the one published by a trusted node before bootstrapping.
+//!
+//! [`kvs::export`]: crate::kvs::export
+//! [`AssetValue`]: crate::data_types::AssetValue
+//! [`Summary::digest`]: self::Summary::digest
+
+use crate::data_types::crypto_hash::Sha256Hasher;
+use crate::data_types::{
+    AssetValue, BlockHeight, ChainIndex, CryptoHash, CryptoHasher, Id, ResourceId,
+    RESOURCE_ID_BUFFER_CAPACITY,
+};
+use crate::rdb::{self, Master, Session, Slave};
+use crate::Error;
+use core::convert::TryFrom;
+use core::mem::{self, size_of};
+use std::io::{self, Read, Write};
+
+/// The header of the snapshot. (The last byte is the format version.)
+const MAGIC: &'static [u8] = b"MOUSESNP\x01";
+
+/// The number of the rows fetched from the RDB at once.
+const CHUNK: u32 = 1024;
+
+/// `Summary` is the result of function [`export`] and [`import`] .
+///
+/// [`export`]: self::export
+/// [`import`]: self::import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    /// The tip of the main chain that the snapshot is taken at.
+    pub tip: ChainIndex,
+    /// The number of the balances.
+    pub resources: u64,
+    /// The number of the acids.
+    pub acids: u64,
+    /// The SHA-256 of the hashes of the sections.
+    pub digest: Id,
+}
+
+/// `Hashing` calculates the SHA-256 of the bytes written into or read from `inner` .
+struct Hashing<T> {
+    inner: T,
+    hasher: Sha256Hasher,
+}
+
+impl<T> Hashing<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            hasher: Sha256Hasher::default(),
+        }
+    }
+
+    /// Returns the hash of the bytes since the last call, and resets the hasher.
+    fn take_hash(&mut self) -> Id {
+        mem::take(&mut self.hasher).finish()
+    }
+}
+
+impl<W> Write for Hashing<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        CryptoHasher::write(&mut self.hasher, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R> Read for Hashing<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        CryptoHasher::write(&mut self.hasher, &buf[..n]);
+        Ok(n)
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writes the hash of the section into the inner stream, and appends it to `digest` .
+fn end_section<W: Write>(writer: &mut Hashing<W>, digest: &mut Sha256Hasher) -> io::Result<()> {
+    let hash = writer.take_hash();
+    CryptoHasher::write(digest, hash.as_ref());
+    writer.inner.write_all(hash.as_ref())
+}
+
+/// Reads the hash of the section from the inner stream, and appends it to `digest` if it
+/// matches.
+fn verify_section<R: Read>(reader: &mut Hashing<R>, digest: &mut Sha256Hasher) -> io::Result<()> {
+    let hash = reader.take_hash();
+    let mut expected = Id::zeroed();
+    reader.inner.read_exact(&mut expected)?;
+
+    if hash == expected {
+        CryptoHasher::write(digest, hash.as_ref());
+        Ok(())
+    } else {
+        Err(invalid_data("the hash of the section does not match"))
+    }
+}
+
+fn write_count<W: Write>(count: usize, writer: &mut W) -> io::Result<()> {
+    let count = u32::try_from(count).map_err(|_| invalid_data("too many rows in a chunk"))?;
+    writer.write_all(&count.to_le_bytes())
+}
+
+fn read_count<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn write_height<W: Write>(height: BlockHeight, id: &Id, writer: &mut W) -> io::Result<()> {
+    writer.write_all(&height.to_le_bytes())?;
+    writer.write_all(id.as_ref())
+}
+
+fn read_height<R: Read>(reader: &mut R) -> io::Result<(BlockHeight, Id)> {
+    let mut height = [0; size_of::<BlockHeight>()];
+    reader.read_exact(&mut height)?;
+    let mut id = Id::zeroed();
+    reader.read_exact(&mut id)?;
+    Ok((BlockHeight::from_le_bytes(height), id))
+}
+
+fn write_resource<W: Write>(
+    resource_id: &ResourceId,
+    value: AssetValue,
+    writer: &mut W,
+) -> io::Result<()> {
+    for bytes in &[resource_id.owner(), resource_id.asset_type()] {
+        // The total length is less than RESOURCE_ID_BUFFER_CAPACITY.
+        writer.write_all(&[bytes.len() as u8])?;
+        writer.write_all(bytes)?;
+    }
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn read_resource<R: Read>(reader: &mut R) -> io::Result<(ResourceId, AssetValue)> {
+    let mut buffer = [0; RESOURCE_ID_BUFFER_CAPACITY];
+    let mut lens = [0; 2];
+    let mut filled = 0;
+
+    for len in lens.iter_mut() {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        *len = byte[0] as usize;
+        if RESOURCE_ID_BUFFER_CAPACITY < filled + *len {
+            return Err(invalid_data("too long resource id"));
+        }
+        reader.read_exact(&mut buffer[filled..(filled + *len)])?;
+        filled += *len;
+    }
+
+    let mut value = [0; size_of::<AssetValue>()];
+    reader.read_exact(&mut value)?;
+
+    let (owner, asset_type) = buffer[..filled].split_at(lens[0]);
+    let resource_id = unsafe { ResourceId::new(owner, asset_type) };
+    Ok((resource_id, AssetValue::from_le_bytes(value)))
+}
+
+/// Writes the snapshot at the tip of the main chain into `writer` , and returns the summary.
+///
+/// If `session` is not in a transaction, this function reads in a transaction so that no block
+/// is committed meanwhile. Otherwise, the caller should make sure of it.
+///
+/// # Error
+///
+/// Errors if the main chain is empty.
+pub fn export<W, S>(writer: W, session: &mut S) -> Result<Summary, Error>
+where
+    W: Write,
+    S: Slave,
+{
+    if session.is_transaction() {
+        return do_export(writer, session);
+    }
+
+    session.begin_transaction()?;
+    let ret = do_export(writer, session);
+    // Nothing is written.
+    session.rollback()?;
+    ret
+}
+
+fn do_export<W, S>(writer: W, session: &mut S) -> Result<Summary, Error>
+where
+    W: Write,
+    S: Slave,
+{
+    let tip = match rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, session)?
+        .as_ref()
+        .first()
+    {
+        Some(&tip) => tip,
+        None => {
+            let msg = "Failed to export the snapshot: the main chain is empty";
+            return Err(Error::rdb(msg));
+        }
+    };
+
+    let mut writer = Hashing::new(writer);
+    let mut digest = Sha256Hasher::default();
+    writer.inner.write_all(MAGIC)?;
+    writer.inner.write_all(&[size_of::<AssetValue>() as u8])?;
+
+    write_height(tip.height(), tip.id(), &mut writer)?;
+    end_section(&mut writer, &mut digest)?;
+
+    let mut resources = 0;
+    let mut after = None;
+    loop {
+        let chunk = rdb::resources::fetch_all(after.as_ref(), CHUNK, session)?;
+        write_count(chunk.len(), &mut writer)?;
+        for (resource_id, value) in chunk.iter() {
+            write_resource(resource_id, *value, &mut writer)?;
+        }
+
+        resources += chunk.len() as u64;
+        match chunk.last() {
+            None => break,
+            Some(&(resource_id, _)) => after = Some(resource_id),
+        }
+    }
+    end_section(&mut writer, &mut digest)?;
+
+    let mut acids = 0;
+    let mut min_seq = None;
+    loop {
+        let chunk = rdb::acids::fetch_chain(min_seq, tip.height(), CHUNK, session)?;
+        write_count(chunk.len(), &mut writer)?;
+        for (_, height, id) in chunk.iter() {
+            write_height(*height, id, &mut writer)?;
+        }
+
+        acids += chunk.len() as u64;
+        match chunk.last() {
+            None => break,
+            Some(&(seq, _, _)) => min_seq = Some(seq + 1),
+        }
+    }
+    end_section(&mut writer, &mut digest)?;
+
+    writer.flush()?;
+    Ok(Summary {
+        tip,
+        resources,
+        acids,
+        digest: digest.finish(),
+    })
+}
+
+/// Bootstraps the RDB from the snapshot written by [`export`] , and returns the summary.
+///
+/// The main chain is finalized at the tip of the snapshot, because the balances before it are
+/// unknown. The balance history is pruned below the tip for the same reason. (See also function
+/// [`rdb::resources::fetch_at_height`] .)
+///
+/// If `expected` is not `None` , the import fails unless [`Summary::digest`] equals to it.
+///
+/// If `session` is not in a transaction, this function starts one, and commits it on success or
+/// rolls it back on failure. Otherwise, this function runs in the transaction, and the caller
+/// should roll it back on failure.
+///
+/// # Error
+///
+/// Fails and changes nothing if RDB table "main_chain" is not empty, if the snapshot is broken,
+/// or if the digest does not match `expected` .
+///
+/// [`export`]: self::export
+/// [`rdb::resources::fetch_at_height`]: crate::rdb::resources::fetch_at_height
+/// [`Summary::digest`]: self::Summary::digest
+pub fn import<R, S>(reader: R, expected: Option<&Id>, session: &mut S) -> Result<Summary, Error>
+where
+    R: Read,
+    S: Master,
+{
+    if session.is_transaction() {
+        return do_import(reader, expected, session);
+    }
+
+    session.begin_transaction()?;
+    match do_import(reader, expected, session) {
+        Ok(summary) => {
+            session.commit()?;
+            Ok(summary)
+        }
+        Err(e) => {
+            session.rollback()?;
+            Err(e)
+        }
+    }
+}
+
+fn do_import<R, S>(reader: R, expected: Option<&Id>, session: &mut S) -> Result<Summary, Error>
+where
+    R: Read,
+    S: Master,
+{
+    let tip = rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, session)?;
+    if !tip.as_ref().is_empty() {
+        let msg = "Failed to import the snapshot: the main chain is not empty";
+        return Err(Error::rdb(msg));
+    }
+
+    let mut reader = Hashing::new(reader);
+    let mut digest = Sha256Hasher::default();
+    {
+        let mut header = vec![0; MAGIC.len() + 1];
+        reader.inner.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC
+            || header[MAGIC.len()] as usize != size_of::<AssetValue>()
+        {
+            return Err(Error::from(invalid_data("unknown format")));
+        }
+    }
+
+    let (height, id) = read_height(&mut reader)?;
+    verify_section(&mut reader, &mut digest)?;
+    let tip = ChainIndex::new(height, &id);
+    rdb::main_chain::push(&tip, session)?;
+
+    let mut resources = 0;
+    loop {
+        let count = read_count(&mut reader)?;
+        if count == 0 {
+            break;
+        }
+
+        let mut chunk = Vec::with_capacity(count.min(CHUNK) as usize);
+        for _ in 0..count {
+            chunk.push(read_resource(&mut reader)?);
+        }
+        rdb::resources::update_balance_at(chunk.iter(), height, session)?;
+        resources += chunk.len() as u64;
+    }
+    verify_section(&mut reader, &mut digest)?;
+
+    let mut acids = 0;
+    loop {
+        let count = read_count(&mut reader)?;
+        if count == 0 {
+            break;
+        }
+
+        let mut chunk = Vec::with_capacity(count.min(CHUNK) as usize);
+        for _ in 0..count {
+            let row = read_height(&mut reader)?;
+            if height < row.0 {
+                return Err(Error::from(invalid_data("the acid is higher than the tip")));
+            }
+            chunk.push(row);
+        }
+        rdb::acids::insert_chain(chunk.iter(), session)?;
+        acids += chunk.len() as u64;
+    }
+    verify_section(&mut reader, &mut digest)?;
+
+    if reader.inner.read(&mut [0])? != 0 {
+        return Err(Error::from(invalid_data(
+            "unexpected data after the snapshot",
+        )));
+    }
+
+    let digest = digest.finish();
+    if let Some(expected) = expected {
+        if expected != &digest {
+            let msg = format!(
+                "Failed to import the snapshot: the digest is {} but {} is expected",
+                digest.display_hex(),
+                expected.display_hex()
+            );
+            return Err(Error::Other(Box::from(msg)));
+        }
+    }
+
+    rdb::main_chain::finalize(height, session)?;
+    rdb::resources::prune_history(height, session)?;
+
+    info!(
+        "Imported the snapshot at height {}: {} balances and {} acids.",
+        height, resources, acids
+    );
+    Ok(Summary {
+        tip,
+        resources,
+        acids,
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn section() {
+        let mut buffer = Vec::new();
+        let mut digest = Sha256Hasher::default();
+        {
+            let mut writer = Hashing::new(&mut buffer);
+            write_count(1, &mut writer).unwrap();
+            end_section(&mut writer, &mut digest).unwrap();
+            write_count(0, &mut writer).unwrap();
+            end_section(&mut writer, &mut digest).unwrap();
+        }
+        let digest = digest.finish();
+        assert_eq!(2 * (4 + Id::zeroed().len()), buffer.len());
+
+        let mut verified = Sha256Hasher::default();
+        let mut reader = Hashing::new(&buffer[..]);
+        assert_eq!(1, read_count(&mut reader).unwrap());
+        verify_section(&mut reader, &mut verified).unwrap();
+        assert_eq!(0, read_count(&mut reader).unwrap());
+        verify_section(&mut reader, &mut verified).unwrap();
+        assert_eq!(digest, verified.finish());
+
+        // Broken
+        buffer[0] = 2;
+        let mut reader = Hashing::new(&buffer[..]);
+        assert_eq!(2, read_count(&mut reader).unwrap());
+        assert_eq!(
+            true,
+            verify_section(&mut reader, &mut Sha256Hasher::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn read_write_row() {
+        let resource_id = unsafe { ResourceId::new(b"owner", b"asset") };
+        let id = Id::calculate(b"mouse");
+
+        let mut buffer = Vec::new();
+        write_resource(&resource_id, 7, &mut buffer).unwrap();
+        write_height(3, &id, &mut buffer).unwrap();
+
+        let mut reader = &buffer[..];
+        assert_eq!((resource_id, 7), read_resource(&mut reader).unwrap());
+        assert_eq!((3, id), read_height(&mut reader).unwrap());
+        assert_eq!(true, reader.is_empty());
+
+        // Truncated
+        assert_eq!(true, read_resource(&mut &buffer[..8]).is_err());
+
+        // The length of the owner is broken.
+        buffer[0] = 0xff;
+        assert_eq!(true, read_resource(&mut &buffer[..]).is_err());
+    }
+}
@@ -0,0 +1,720 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `storage` coordinates the writes spanning module `kvs` and `rdb` .
+//! `storage` depends on module `data_types` , `kvs` , and `rdb` .
+//!
+//! # Journal
+//!
+//! [`commit_block`] writes everything to be stored into the journal file before writing to the
+//! KVS and the RDB, and removes the journal after both of them are done.
+//! All the writes are idempotent, so [`recover`] can redo the journal if the process crashed on
+//! the way.
+//!
+//! # Pinned view
+//!
+//! [`pin`] creates [`PinnedView`] to read the RDB consistently at the tip when it is created.
+//!
+//! [`commit_block`]: self::commit_block
+//! [`recover`]: self::recover
+//! [`pin`]: self::pin
+//! [`PinnedView`]: self::PinnedView
+
+mod view;
+
+use crate::data_types::{BlockHeight, CAcid, ChainIndex, CryptoHash, Id};
+use crate::kvs::WriteQuery;
+use crate::rdb::Session;
+use crate::{kvs, profile, rdb, Config, ModuleEnvironment};
+use clap::{App, Arg};
+use core::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+pub use view::{pin, PinnedView};
+
+/// The file name of the journal if '--storage-journal-path' is not specified.
+pub const JOURNAL_FILE_NAME: &'static str = "storage.journal";
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --storage-journal-path
+///
+/// '--storage-journal-path' is optional. It is [`JOURNAL_FILE_NAME`] under the RDB data
+/// directory ('--rdb-data-path') by default, or under the KVS directory if the RDB does not have
+/// the data directory.
+///
+/// [`JOURNAL_FILE_NAME`]: self::JOURNAL_FILE_NAME
+#[derive(Default)]
+pub struct Environment {
+    journal_path: PathBuf,
+    /// Serializes [`commit_block`] and [`recover`] because they share the journal file.
+    ///
+    /// [`commit_block`]: self::commit_block
+    /// [`recover`]: self::recover
+    journal_lock: Mutex<()>,
+    /// The number of the journaled commits applied to the RDB so far.
+    journal_position: AtomicU64,
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.arg(
+            Arg::with_name("PATH_TO_STORAGE_JOURNAL")
+                .help(
+                    "Path to the journal file to make the writes crash-recoverable.
+('storage.journal' under '--rdb-data-path' by default.)",
+                )
+                .long("--storage-journal-path")
+                .takes_value(true),
+        )
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let args = config.args();
+        self.journal_path = match args.value_of("PATH_TO_STORAGE_JOURNAL") {
+            Some(journal_path) => PathBuf::from(journal_path),
+            None => {
+                let dir = args
+                    .value_of("PATH_TO_RDB_DATA_DIR")
+                    .or_else(|| args.value_of("PATH_TO_KVS_DB_DIR"))
+                    .ok_or_else(|| {
+                        let msg = "'--storage-journal-path' is required because neither the RDB \
+                                   nor the KVS has the data directory";
+                        crate::Error::Config(String::from(msg))
+                    })?;
+                PathBuf::from(dir).join(JOURNAL_FILE_NAME)
+            }
+        };
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        // Recovery requires module 'kvs' and 'rdb'.
+        // 'GlobalEnvironment' calls function 'recover()' after they are initialized.
+        Ok(())
+    }
+}
+
+/// Everything [`commit_block`] writes.
+///
+/// [`commit_block`]: self::commit_block
+#[derive(Debug, PartialEq, Eq)]
+struct Record {
+    chain_index: ChainIndex,
+    /// Id, intrinsic data, and extrinsic data of each acid.
+    acids: Vec<(Id, Vec<u8>, Vec<u8>)>,
+    /// The ids of the parents of each acid. (Empty if the journal was written by the older
+    /// version.)
+    parents: Vec<Vec<Id>>,
+    /// Whether to finalize the main chain up to `chain_index` ; i.e. `chain_index` is a
+    /// checkpoint. (`false` if the journal was written by the older version.)
+    finalize: bool,
+}
+
+impl Record {
+    pub fn new(chain_index: &ChainIndex, acids: &[CAcid], finalize: bool) -> Self {
+        let parents = acids
+            .iter()
+            .map(|acid| {
+                (0..acid.parent_count())
+                    .map(|i| acid.parent(i).unwrap())
+                    .collect()
+            })
+            .collect();
+
+        let acids = acids
+            .iter()
+            .map(|acid| {
+                let intrinsic = acid.intrinsic().into_owned();
+                let extrinsic = acid.extrinsic().into_owned();
+                (*acid.id(), intrinsic, extrinsic)
+            })
+            .collect();
+
+        Self {
+            chain_index: *chain_index,
+            acids,
+            parents,
+            finalize,
+        }
+    }
+
+    /// Serializes `self` .
+    ///
+    /// # Format
+    ///
+    /// - height: 8 bytes little endian
+    /// - block id: `Id::LEN` bytes
+    /// - acid count: 4 bytes little endian
+    /// - for each acid:
+    ///   - id: `Id::LEN` bytes
+    ///   - intrinsic length: 4 bytes little endian
+    ///   - intrinsic
+    ///   - extrinsic length: 4 bytes little endian
+    ///   - extrinsic
+    /// - for each acid: (omitted by the older version)
+    ///   - parent count: 4 bytes little endian
+    ///   - parent ids: `Id::LEN` bytes for each
+    /// - finalize: 1 byte; 1 if `true` , or 0 (omitted by the older version)
+    /// - checksum: `Id::LEN` bytes (the hash of all the bytes above)
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+
+        ret.extend_from_slice(&self.chain_index.height().to_le_bytes());
+        ret.extend_from_slice(self.chain_index.id().as_ref());
+        ret.extend_from_slice(&(self.acids.len() as u32).to_le_bytes());
+
+        for (id, intrinsic, extrinsic) in &self.acids {
+            ret.extend_from_slice(id.as_ref());
+            ret.extend_from_slice(&(intrinsic.len() as u32).to_le_bytes());
+            ret.extend_from_slice(intrinsic);
+            ret.extend_from_slice(&(extrinsic.len() as u32).to_le_bytes());
+            ret.extend_from_slice(extrinsic);
+        }
+
+        for parents in &self.parents {
+            ret.extend_from_slice(&(parents.len() as u32).to_le_bytes());
+            for parent in parents {
+                ret.extend_from_slice(parent.as_ref());
+            }
+        }
+
+        ret.push(self.finalize as u8);
+
+        let checksum = Id::calculate(&ret);
+        ret.extend_from_slice(checksum.as_ref());
+
+        ret
+    }
+
+    /// Deserializes `bytes` and returns the result if `bytes` is well-formed; otherwise returns
+    /// `None` .
+    ///
+    /// A journal could be broken if the process crashed while writing it.
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Id::LEN {
+            return None;
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - Id::LEN);
+        if Id::calculate(body).as_ref() != checksum {
+            return None;
+        }
+
+        let mut reader = body;
+        let height = <[u8; 8]>::try_from(take(&mut reader, 8)?).ok()?;
+        let height = BlockHeight::from_le_bytes(height);
+        let block_id = Id::try_copy_bytes(take(&mut reader, Id::LEN)?)?;
+        if height <= 0 {
+            return None;
+        }
+        let chain_index = ChainIndex::new(height, &block_id);
+
+        let count = take_u32(&mut reader)?;
+        let mut acids = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let id = Id::try_copy_bytes(take(&mut reader, Id::LEN)?)?;
+            let len = take_u32(&mut reader)? as usize;
+            let intrinsic = take(&mut reader, len)?.to_vec();
+            let len = take_u32(&mut reader)? as usize;
+            let extrinsic = take(&mut reader, len)?.to_vec();
+            acids.push((id, intrinsic, extrinsic));
+        }
+
+        // The parents of an acid take 4 bytes or more, so the last 1 byte is 'finalize' .
+        let mut parents = Vec::new();
+        if 1 < reader.len() {
+            parents.reserve(count as usize);
+            for _ in 0..count {
+                let parent_count = take_u32(&mut reader)?;
+                let mut ids = Vec::with_capacity(parent_count.min(1024) as usize);
+                for _ in 0..parent_count {
+                    ids.push(Id::try_copy_bytes(take(&mut reader, Id::LEN)?)?);
+                }
+                parents.push(ids);
+            }
+        }
+
+        let finalize = match reader {
+            [] | [0] => false,
+            [1] => true,
+            _ => return None,
+        };
+
+        Some(Self {
+            chain_index,
+            acids,
+            parents,
+            finalize,
+        })
+    }
+}
+
+/// Splits the first `len` bytes from `reader` and returns them if `reader` is long enough.
+fn take<'a>(reader: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if reader.len() < len {
+        None
+    } else {
+        let (ret, rest) = reader.split_at(len);
+        *reader = rest;
+        Some(ret)
+    }
+}
+
+fn take_u32(reader: &mut &[u8]) -> Option<u32> {
+    let bytes = take(reader, 4)?;
+    Some(u32::from_le_bytes(<[u8; 4]>::try_from(bytes).ok()?))
+}
+
+fn write_journal(bytes: &[u8], env: &Environment) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&env.journal_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+fn read_journal(env: &Environment) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let mut file = match File::open(&env.journal_path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Box::new(e)),
+    };
+
+    let mut ret = Vec::new();
+    file.read_to_end(&mut ret)?;
+    Ok(Some(ret))
+}
+
+fn remove_journal(env: &Environment) -> Result<(), Box<dyn Error>> {
+    match fs::remove_file(&env.journal_path) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// `CommitPlan` reports the mutations [`commit_block`] performs (or would perform in dry-run
+/// mode.)
+///
+/// [`commit_block`]: self::commit_block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitPlan {
+    /// The block to be committed.
+    pub chain_index: ChainIndex,
+    /// `true` if `chain_index` is already in RDB table "main_chain", i.e. the RDB is not changed.
+    pub is_committed: bool,
+    /// The number of the acids to be put into the KVS.
+    pub kvs_puts: usize,
+    /// The ids of the acids to be inserted into RDB table "acids" newly.
+    pub new_acids: Vec<Id>,
+    /// The ids of the acids to be moved from mempool to `chain_index` .
+    pub mined_acids: Vec<Id>,
+}
+
+/// Writes `record` to the KVS and the RDB, and returns what is written.
+///
+/// If `dry_run` is `true` , this function executes only the reading queries and returns what
+/// would be written.
+///
+/// Each step is idempotent so that this function can be called again for the same `record` .
+fn apply(
+    record: &Record,
+    dry_run: bool,
+    env: &Environment,
+    kvs_env: &kvs::Environment,
+    rdb_env: &rdb::Environment,
+) -> Result<CommitPlan, Box<dyn Error>> {
+    // Write to the KVS first.
+    if !dry_run {
+        let _profile = profile::scope("kvs_write");
+        let mut queries: Vec<_> = record
+            .acids
+            .iter()
+            .map(|(id, intrinsic, extrinsic)| kvs::put(id, intrinsic, extrinsic, kvs_env))
+            .collect();
+
+        for query in queries.iter_mut() {
+            query.wait().map_err(|e| kvs::Error::clone(&e))?;
+        }
+    }
+
+    // Then, update the RDB in a transaction.
+    let _profile = profile::scope("rdb_update");
+    let mut session = rdb::master(rdb_env);
+    session.begin_transaction()?;
+
+    let result = (|| -> Result<CommitPlan, Box<dyn Error>> {
+        let chain_index = &record.chain_index;
+        let mut plan = CommitPlan {
+            chain_index: *chain_index,
+            is_committed: false,
+            kvs_puts: record.acids.len(),
+            new_acids: Vec::new(),
+            mined_acids: Vec::new(),
+        };
+
+        match rdb::main_chain::fetch_one(chain_index.height(), &mut session)? {
+            Some(id) if id == *chain_index.id() => {
+                plan.is_committed = true;
+                return Ok(plan);
+            }
+            Some(_) => {
+                let msg = format!(
+                    "Failed to commit block {}: another block is at height {}",
+                    chain_index.id().display_hex(),
+                    chain_index.height()
+                );
+                return Err(Box::<dyn Error>::from(msg));
+            }
+            None => (),
+        }
+
+        let ids = record.acids.iter().map(|(id, _, _)| id);
+        let states = rdb::acids::fetch_state(ids.clone(), &mut session)?;
+        for id in ids.clone() {
+            match states.get(id) {
+                None => {
+                    plan.new_acids.push(*id);
+                    plan.mined_acids.push(*id);
+                }
+                Some(None) => plan.mined_acids.push(*id),
+                Some(Some(_)) => (),
+            }
+        }
+
+        if !dry_run {
+            rdb::acids::accept_to_mempool(ids.clone(), &mut session)?;
+            let parents = ids.clone().zip(record.parents.iter());
+            rdb::parents::insert(parents, &mut session)?;
+            rdb::main_chain::push(chain_index, &mut session)?;
+            unsafe { rdb::acids::mempool_to_chain(chain_index, ids, &mut session)? };
+            if record.finalize {
+                rdb::main_chain::finalize(chain_index.height(), &mut session)?;
+            }
+        }
+
+        Ok(plan)
+    })();
+
+    match result {
+        Ok(plan) if !dry_run => {
+            session.commit()?;
+            // Count up before releasing 'session' so that the PinnedView created after that
+            // sees the new position.
+            env.journal_position.fetch_add(1, Ordering::AcqRel);
+            Ok(plan)
+        }
+        Ok(plan) => {
+            session.rollback()?;
+            Ok(plan)
+        }
+        Err(e) => {
+            session.rollback()?;
+            Err(e)
+        }
+    }
+}
+
+/// Stores `acids` into the KVS, appends `chain_index` to RDB table "main_chain", and makes
+/// `acids` belong to `chain_index` in RDB table "acids".
+///
+/// `acids` should include the block itself, i.e. the acid whose id equals to `chain_index.id()` .
+///
+/// The writes are journaled, so the KVS and the RDB are kept consistent even if the process
+/// crashes on the way; [`recover`] completes the interrupted commit.
+///
+/// This function does nothing if `chain_index` is already in RDB table "main_chain" (except for
+/// the KVS writes,) and fails if another block is at the same height.
+///
+/// If `finalize` is `true` , this function also finalizes the main chain up to `chain_index` in
+/// the same RDB transaction. (See also function [`rdb::main_chain::finalize`] .)
+///
+/// Returns the mutations performed. If `dry_run` is `true` , this function writes nothing (not
+/// even the journal,) and returns the mutations that would be performed.
+///
+/// # Panics
+///
+/// Panics if the current thread owns another RDB `Session` instance.
+///
+/// [`recover`]: self::recover
+/// [`rdb::main_chain::finalize`]: crate::rdb::main_chain::finalize
+pub fn commit_block(
+    chain_index: &ChainIndex,
+    acids: &[CAcid],
+    finalize: bool,
+    dry_run: bool,
+    env: &Environment,
+    kvs_env: &kvs::Environment,
+    rdb_env: &rdb::Environment,
+) -> Result<CommitPlan, Box<dyn Error>> {
+    let _lock = env.journal_lock.lock().unwrap();
+    let _profile = profile::scope("commit_block");
+
+    let record = Record::new(chain_index, acids, finalize);
+    if dry_run {
+        return apply(&record, true, env, kvs_env, rdb_env);
+    }
+
+    {
+        let _profile = profile::scope("journal_write");
+        write_journal(&record.serialize(), env)?;
+    }
+    let plan = apply(&record, false, env, kvs_env, rdb_env)?;
+    remove_journal(env)?;
+
+    Ok(plan)
+}
+
+/// Completes the [`commit_block`] interrupted by a crash if any, and returns `true` if something
+/// is recovered.
+///
+/// A broken journal (i.e. the process crashed while writing the journal) is discarded, because
+/// nothing was written to the KVS nor to the RDB at that time.
+///
+/// [`GlobalEnvironment`] calls this function at `init()` .
+///
+/// [`commit_block`]: self::commit_block
+/// [`GlobalEnvironment`]: crate::GlobalEnvironment
+pub fn recover(
+    env: &Environment,
+    kvs_env: &kvs::Environment,
+    rdb_env: &rdb::Environment,
+) -> Result<bool, Box<dyn Error>> {
+    let _lock = env.journal_lock.lock().unwrap();
+
+    let bytes = match read_journal(env)? {
+        None => return Ok(false),
+        Some(bytes) => bytes,
+    };
+
+    let ret = match Record::deserialize(&bytes) {
+        None => {
+            warn!("Discarded the broken storage journal.");
+            false
+        }
+        Some(record) => {
+            apply(&record, false, env, kvs_env, rdb_env)?;
+            info!(
+                "Recovered the commit of block {}.",
+                record.chain_index.id().display_hex()
+            );
+            true
+        }
+    };
+
+    remove_journal(env)?;
+    Ok(ret)
+}
+
+/// `MigrationReport` is what '--migrate-dry-run' reports; the RDB schema objects to be created,
+/// and the KVS data to be converted.
+///
+/// See also function [`pending_migrations`] .
+///
+/// [`pending_migrations`]: self::pending_migrations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The RDB schema objects which are not created yet.
+    pub rdb: Vec<rdb::PendingMigration>,
+    /// The number of the KVS rows to be converted for the current options.
+    pub kvs: kvs::FormatState,
+}
+
+impl MigrationReport {
+    /// Returns `true` if nothing is to be migrated.
+    pub fn is_empty(&self) -> bool {
+        self.rdb.is_empty() && self.kvs.is_empty()
+    }
+}
+
+/// Writes a line for each RDB schema object, and a line for the KVS data.
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.rdb.is_empty() {
+            writeln!(f, "No pending RDB migration.")?;
+        }
+        for p in &self.rdb {
+            writeln!(
+                f,
+                "Would create {} '{}' on table '{}' ({} rows)",
+                p.kind, p.name, p.table, p.rows
+            )?;
+        }
+        write!(f, "{}", self.kvs)
+    }
+}
+
+/// Returns the RDB schema objects that `rdb_env` would create at `init()` , and the number of the
+/// KVS rows to be converted for the options of `kvs_env` without changing anything.
+///
+/// `kvs_env` and `rdb_env` must have been initialized. (The KVS does not convert its data at
+/// `init()` ; the data is converted by exporting and importing it into a new KVS.)
+///
+/// See also function [`rdb::pending_migrations`] and [`kvs::format_state`] .
+///
+/// [`rdb::pending_migrations`]: crate::rdb::pending_migrations
+/// [`kvs::format_state`]: crate::kvs::format_state
+pub fn pending_migrations(
+    kvs_env: &kvs::Environment,
+    rdb_env: &rdb::Environment,
+) -> Result<MigrationReport, crate::Error> {
+    let mut session = rdb::slave(rdb_env);
+    let rdb = rdb::pending_migrations(&mut session)?;
+
+    // The KVS is inspected following RDB table "acids" ; nothing to inspect if it does not exist.
+    let kvs = if rdb.iter().any(|p| p.kind == "table" && p.name == "acids") {
+        kvs::FormatState {
+            verify_checksums: kvs_env.verify_checksums(),
+            dedup: kvs_env.is_dedup_enabled(),
+            ..kvs::FormatState::default()
+        }
+    } else {
+        kvs::format_state(kvs_env, &mut session)?
+    };
+
+    Ok(MigrationReport { rdb, kvs })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> Record {
+        let acids = vec![
+            (Id::calculate(&[1]), vec![1, 2, 3], vec![]),
+            (Id::calculate(&[2]), vec![4], vec![5, 6]),
+        ];
+        Record {
+            chain_index: ChainIndex::new(3, &Id::calculate(&[1])),
+            acids,
+            parents: vec![vec![Id::calculate(&[2])], vec![]],
+            finalize: true,
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let record = record();
+        let bytes = record.serialize();
+        assert_eq!(Some(record), Record::deserialize(&bytes));
+    }
+
+    #[test]
+    fn deserialize_without_parents() {
+        let mut record = record();
+        record.parents.clear();
+
+        let bytes = record.serialize();
+        assert_eq!(Some(record), Record::deserialize(&bytes));
+    }
+
+    #[test]
+    fn deserialize_without_finalize() {
+        let mut record = record();
+        let mut bytes = record.serialize();
+
+        // Remove 'finalize' and the checksum, and append the checksum again.
+        bytes.truncate(bytes.len() - Id::LEN - 1);
+        let checksum = Id::calculate(&bytes);
+        bytes.extend_from_slice(checksum.as_ref());
+
+        record.finalize = false;
+        assert_eq!(Some(record), Record::deserialize(&bytes));
+    }
+
+    #[test]
+    fn deserialize_broken() {
+        let bytes = record().serialize();
+
+        for i in 0..bytes.len() {
+            assert_eq!(None, Record::deserialize(&bytes[..i]));
+        }
+
+        let mut bytes = bytes;
+        bytes[0] ^= 1;
+        assert_eq!(None, Record::deserialize(&bytes));
+    }
+
+    #[test]
+    fn commit_checkpoint() {
+        let mut env = Environment::default();
+        let journal = format!("mouse-storage-checkpoint-{}", std::process::id());
+        env.journal_path = std::env::temp_dir().join(journal);
+        // No acid is committed, so the KVS is not used.
+        let kvs_env = kvs::Environment::default();
+        let mut rdb_env = rdb::Environment::default();
+        unsafe { rdb_env.init().unwrap() };
+
+        let first = ChainIndex::new(1, &Id::calculate(&[1]));
+        commit_block(&first, &[], false, false, &env, &kvs_env, &rdb_env).unwrap();
+        let second = ChainIndex::new(2, &Id::calculate(&[2]));
+        commit_block(&second, &[], true, false, &env, &kvs_env, &rdb_env).unwrap();
+
+        // The checkpoint is finalized as soon as it is committed.
+        let mut session = rdb::master(&rdb_env);
+        assert_eq!(
+            true,
+            rdb::main_chain::is_final(&second, &mut session).unwrap()
+        );
+        assert_eq!(true, rdb::revert_block(&mut session).is_err());
+        let tip = rdb::main_chain::fetch_one(2, &mut session).unwrap();
+        assert_eq!(Some(*second.id()), tip);
+    }
+
+    #[test]
+    fn migration_report() {
+        let mut report = MigrationReport {
+            rdb: Vec::new(),
+            kvs: kvs::FormatState::default(),
+        };
+        assert_eq!(true, report.is_empty());
+        assert_eq!(2, report.to_string().lines().count());
+
+        report.kvs.rows = 3;
+        report.kvs.checksum_conversions = 3;
+        assert_eq!(false, report.is_empty());
+
+        report.rdb.push(rdb::PendingMigration {
+            kind: "index",
+            name: "parent_id_",
+            table: "parents",
+            rows: 5,
+        });
+        let report = report.to_string();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(
+            "Would create index 'parent_id_' on table 'parents' (5 rows)",
+            lines[0]
+        );
+        assert_eq!(true, lines[1].starts_with("Would convert 3 of 3 KVS rows"));
+    }
+}
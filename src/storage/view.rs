@@ -0,0 +1,178 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::Environment;
+use crate::data_types::{AssetValue, BlockHeight, ChainIndex, Id, ResourceId};
+use crate::rdb::{self, Session, Slave};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::Ordering;
+
+/// `PinnedView` is a read-only view of the RDB pinned at the tip when it is created.
+///
+/// `PinnedView` owns an RDB session in a transaction until dropped, so no block is committed
+/// meanwhile and all the reads through it see the same state.
+/// Do not keep it longer than necessary; [`commit_block`] blocks while it is alive.
+///
+/// [`commit_block`]: super::commit_block
+pub struct PinnedView<S> {
+    session: S,
+    tip: Option<ChainIndex>,
+    journal_position: u64,
+}
+
+/// Blocks while another thread is using the RDB, and creates a new [`PinnedView`] .
+///
+/// # Panics
+///
+/// Panics if the current thread owns another RDB `Session` instance.
+///
+/// [`PinnedView`]: self::PinnedView
+pub fn pin<'a>(
+    env: &Environment,
+    rdb_env: &'a rdb::Environment,
+) -> Result<PinnedView<impl 'a + Slave>, Box<dyn Error>> {
+    let mut session = rdb::slave(rdb_env);
+    session.begin_transaction()?;
+
+    // [`commit_block`] counts up the position before releasing the session.
+    let journal_position = env.journal_position.load(Ordering::Acquire);
+    let tip = rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, &mut session)?
+        .as_ref()
+        .first()
+        .copied();
+
+    Ok(PinnedView {
+        session,
+        tip,
+        journal_position,
+    })
+}
+
+impl<S> PinnedView<S>
+where
+    S: Slave,
+{
+    /// Returns the tip of the main chain when `self` was created, or `None` if the main chain was
+    /// empty.
+    pub fn tip(&self) -> Option<&ChainIndex> {
+        self.tip.as_ref()
+    }
+
+    /// Returns the number of the blocks committed via the journal before `self` was created.
+    ///
+    /// Two views with the same position see the same state.
+    pub fn journal_position(&self) -> u64 {
+        self.journal_position
+    }
+
+    /// Returns the height of the tip, or 0 if the main chain was empty.
+    fn tip_height(&self) -> BlockHeight {
+        self.tip.as_ref().map(|c| c.height()).unwrap_or(0)
+    }
+
+    /// Fetches the id of the block at `height` in the main chain.
+    ///
+    /// See also function [`rdb::main_chain::fetch_one`] .
+    ///
+    /// [`rdb::main_chain::fetch_one`]: crate::rdb::main_chain::fetch_one
+    pub fn fetch_block(&mut self, height: BlockHeight) -> Result<Option<Id>, Box<dyn Error>> {
+        if self.tip_height() < height {
+            return Ok(None);
+        }
+        rdb::main_chain::fetch_one(height, &mut self.session).map_err(Box::from)
+    }
+
+    /// Fetches at most `limit` blocks whose height is greater than or equals to `min_height` order
+    /// by the height.
+    ///
+    /// See also function [`rdb::main_chain::fetch_asc`] .
+    ///
+    /// [`rdb::main_chain::fetch_asc`]: crate::rdb::main_chain::fetch_asc
+    pub fn fetch_blocks_asc(
+        &mut self,
+        min_height: BlockHeight,
+        limit: u32,
+    ) -> Result<Vec<ChainIndex>, Box<dyn Error>> {
+        let tip_height = self.tip_height();
+        let blocks = rdb::main_chain::fetch_asc(min_height, limit, &mut self.session)?;
+        let ret = blocks
+            .as_ref()
+            .iter()
+            .take_while(|c| c.height() <= tip_height)
+            .copied()
+            .collect();
+        Ok(ret)
+    }
+
+    /// Fetches at most `limit` blocks whose height is less than or equals to `max_height` order
+    /// by the height desc.
+    ///
+    /// See also function [`rdb::main_chain::fetch_desc`] .
+    ///
+    /// [`rdb::main_chain::fetch_desc`]: crate::rdb::main_chain::fetch_desc
+    pub fn fetch_blocks_desc(
+        &mut self,
+        max_height: BlockHeight,
+        limit: u32,
+    ) -> Result<Vec<ChainIndex>, Box<dyn Error>> {
+        let max_height = max_height.min(self.tip_height());
+        let blocks = rdb::main_chain::fetch_desc(max_height, limit, &mut self.session)?;
+        Ok(blocks.as_ref().to_vec())
+    }
+
+    /// Fetches the balance of each [`ResourceId`] in `resource_ids` at the tip.
+    ///
+    /// See also function [`rdb::resources::fetch`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    /// [`rdb::resources::fetch`]: crate::rdb::resources::fetch
+    pub fn fetch_resources<I, R>(
+        &mut self,
+        resource_ids: I,
+    ) -> Result<HashMap<ResourceId, AssetValue>, Box<dyn Error>>
+    where
+        I: Iterator<Item = R>,
+        R: Borrow<ResourceId>,
+    {
+        rdb::resources::fetch(resource_ids, &mut self.session).map_err(Box::from)
+    }
+
+    /// Fetches the balance of each [`ResourceId`] in `resource_ids` at `height` .
+    ///
+    /// Returns `None` if `height` is greater than the tip, or if the history at `height` is
+    /// pruned.
+    ///
+    /// See also function [`rdb::resources::fetch_at_height`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    /// [`rdb::resources::fetch_at_height`]: crate::rdb::resources::fetch_at_height
+    pub fn fetch_resources_at<I, R>(
+        &mut self,
+        resource_ids: I,
+        height: BlockHeight,
+    ) -> Result<Option<HashMap<ResourceId, AssetValue>>, Box<dyn Error>>
+    where
+        I: Iterator<Item = R>,
+        R: Borrow<ResourceId>,
+    {
+        if self.tip_height() < height {
+            return Ok(None);
+        }
+        rdb::resources::fetch_at_height(resource_ids, height, &mut self.session).map_err(Box::from)
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `sync` downloads the main chain from a peer; i.e. the initial block download.
+//! `sync` depends on module `data_types` , `rdb` , and `storage` .
+//!
+//! `mouse` does not have the p2p layer, so the application implements [`BlockSource`] on its
+//! own. [`Syncer`] is a state machine repeating the following steps till the peer has no more
+//! block.
+//!
+//! 1. Requests the headers above the main chain by a range of the heights, verifies that they
+//!    continue from the main chain, and stores them into RDB table "block_headers".
+//! 1. Requests the body (i.e. the acids) of each header, and commits them in the order of the
+//!    height via [`storage::commit_block`] .
+//!
+//! This is the same to function [`import::import`] except for that the blocks are requested
+//! from a peer.
+//!
+//! # Resume
+//!
+//! [`Syncer`] starts from the tip of the main chain, so it resumes from the last committed block
+//! after it is interrupted.
+//!
+//! # Forks
+//!
+//! [`Syncer`] does not resolve a fork; it fails if the headers do not continue from the main
+//! chain. See function [`add_block_header`] to switch the main chain.
+//!
+//! [`BlockSource`]: self::BlockSource
+//! [`Syncer`]: self::Syncer
+//! [`storage::commit_block`]: crate::storage::commit_block
+//! [`import::import`]: crate::import::import
+//! [`add_block_header`]: crate::add_block_header
+
+use crate::data_types::{BlockHeight, CAcid, ChainIndex, CryptoHash, Id};
+use crate::rdb::{self, block_headers::BlockHeader};
+use crate::GlobalEnvironment;
+use std::collections::VecDeque;
+use std::error::Error;
+
+/// `BlockSource` is a peer to download the main chain from.
+pub trait BlockSource {
+    /// Returns the headers of the main chain of the peer from height `from` in the order of the
+    /// height, at most `limit` ones.
+    ///
+    /// Returns an empty `Vec` if the peer has no block at `from` .
+    fn headers(
+        &mut self,
+        from: BlockHeight,
+        limit: usize,
+    ) -> Result<Vec<BlockHeader>, Box<dyn Error>>;
+
+    /// Returns the block of `header` and the acids belonging to it except for the block.
+    fn body(&mut self, header: &BlockHeader) -> Result<(CAcid, Vec<CAcid>), Box<dyn Error>>;
+}
+
+/// `State` is the state of [`Syncer`] .
+///
+/// [`Syncer`]: self::Syncer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Requesting the headers.
+    Headers,
+    /// Requesting the bodies of the headers and committing them.
+    Bodies,
+    /// The peer has no more block.
+    Done,
+}
+
+/// `Progress` is the result of method [`Syncer::step`] .
+///
+/// [`Syncer::step`]: self::Syncer::step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The state after the step.
+    pub state: State,
+    /// The height of the last committed block, or 0 if the main chain is empty.
+    pub committed: BlockHeight,
+    /// The height of the last header received, which is not less than `committed` .
+    pub target: BlockHeight,
+}
+
+/// `Syncer` is the state machine of the initial block download.
+///
+/// See the module document for details.
+pub struct Syncer {
+    state: State,
+    /// The number of the headers to request at once.
+    batch: usize,
+    /// The height of the last committed block.
+    committed: BlockHeight,
+    /// The id of the last committed block, or `None` if the main chain is empty.
+    tip: Option<Id>,
+    /// The headers received but not committed yet.
+    headers: VecDeque<BlockHeader>,
+}
+
+impl Syncer {
+    /// Creates a new instance starting from the tip of the main chain, which requests `batch`
+    /// headers at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is 0.
+    pub fn new(batch: usize, env: &GlobalEnvironment) -> Result<Self, Box<dyn Error>> {
+        assert!(0 < batch);
+
+        let tip = {
+            let mut session = rdb::slave(&env.rdb);
+            rdb::main_chain::fetch_desc(BlockHeight::MAX, 1, &mut session)?
+        };
+        let (committed, tip) = match tip.as_ref().first() {
+            None => (0, None),
+            Some(chain_index) => (chain_index.height(), Some(*chain_index.id())),
+        };
+
+        Ok(Self {
+            state: State::Headers,
+            batch,
+            committed,
+            tip,
+            headers: VecDeque::new(),
+        })
+    }
+
+    /// Returns the current progress.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            state: self.state,
+            committed: self.committed,
+            target: self.headers.back().map_or(self.committed, |h| h.height),
+        }
+    }
+
+    /// Runs the next step, and returns the progress after that.
+    ///
+    /// In state [`State::Headers`] , requests at most `batch` headers. In state
+    /// [`State::Bodies`] , requests the body of a header and commits it. Does nothing in state
+    /// [`State::Done`] .
+    ///
+    /// The state is kept if this method fails, so the caller can retry it. (e.g. with another
+    /// peer.)
+    ///
+    /// [`State::Headers`]: self::State::Headers
+    /// [`State::Bodies`]: self::State::Bodies
+    /// [`State::Done`]: self::State::Done
+    pub fn step<B>(
+        &mut self,
+        source: &mut B,
+        env: &GlobalEnvironment,
+    ) -> Result<Progress, Box<dyn Error>>
+    where
+        B: BlockSource,
+    {
+        match self.state {
+            State::Headers => self.request_headers(source, env)?,
+            State::Bodies => self.commit_next(source, env)?,
+            State::Done => (),
+        }
+
+        Ok(self.progress())
+    }
+
+    fn request_headers<B>(
+        &mut self,
+        source: &mut B,
+        env: &GlobalEnvironment,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        B: BlockSource,
+    {
+        let headers = source.headers(self.committed + 1, self.batch)?;
+        if headers.is_empty() {
+            self.state = State::Done;
+            return Ok(());
+        }
+
+        check_continuity(self.committed, self.tip.as_ref(), &headers)?;
+
+        let mut session = rdb::master(&env.rdb);
+        for header in headers.iter() {
+            rdb::block_headers::insert(header, &mut session)?;
+        }
+
+        self.headers.extend(headers);
+        self.state = State::Bodies;
+        Ok(())
+    }
+
+    fn commit_next<B>(
+        &mut self,
+        source: &mut B,
+        env: &GlobalEnvironment,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        B: BlockSource,
+    {
+        let header = match self.headers.front() {
+            None => {
+                self.state = State::Headers;
+                return Ok(());
+            }
+            Some(header) => *header,
+        };
+
+        // The main chain may be changed by another thread after the headers are verified.
+        let tip = {
+            let mut session = rdb::slave(&env.rdb);
+            rdb::main_chain::fetch_one(self.committed, &mut session)?
+        };
+        if tip != self.tip {
+            let msg = format!(
+                "Failed to sync block {}: the main chain is changed at height {}",
+                header.id.display_hex(),
+                self.committed
+            );
+            return Err(Box::from(msg));
+        }
+
+        let (block, acids) = source.body(&header)?;
+        if *block.id() != header.id {
+            let msg = format!(
+                "Failed to sync block {}: block {} is received instead",
+                header.id.display_hex(),
+                block.id().display_hex()
+            );
+            return Err(Box::from(msg));
+        }
+
+        let mut all = Vec::with_capacity(acids.len() + 1);
+        all.push(block);
+        all.extend(acids);
+        let chain_index = ChainIndex::new(header.height, &header.id);
+        crate::commit_block(&chain_index, &all, false, env)?;
+
+        self.headers.pop_front();
+        self.committed = header.height;
+        self.tip = Some(header.id);
+        if self.headers.is_empty() {
+            self.state = State::Headers;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `Err` unless `headers` continues from the block at height `committed` whose id is
+/// `tip` . (`tip` is `None` if the main chain is empty.)
+fn check_continuity(
+    committed: BlockHeight,
+    tip: Option<&Id>,
+    headers: &[BlockHeader],
+) -> Result<(), Box<dyn Error>> {
+    let mut height = committed;
+    let mut parent = tip;
+
+    for header in headers {
+        if header.height != height + 1 {
+            let msg = format!(
+                "Header {} is at height {} while {} is expected",
+                header.id.display_hex(),
+                header.height,
+                height + 1
+            );
+            return Err(Box::from(msg));
+        }
+
+        // The genesis block has no parent.
+        if let Some(parent) = parent {
+            if header.parent != *parent {
+                let msg = format!(
+                    "Header {} does not continue from block {}",
+                    header.id.display_hex(),
+                    parent.display_hex()
+                );
+                return Err(Box::from(msg));
+            }
+        }
+
+        height = header.height;
+        parent = Some(&header.id);
+    }
+
+    Ok(())
+}
+
+/// Runs `syncer` till the peer has no more block, and returns the height of the last committed
+/// block.
+///
+/// `progress` is called after each step.
+pub fn run<B, P>(
+    syncer: &mut Syncer,
+    source: &mut B,
+    env: &GlobalEnvironment,
+    mut progress: P,
+) -> Result<BlockHeight, Box<dyn Error>>
+where
+    B: BlockSource,
+    P: FnMut(&Progress),
+{
+    loop {
+        let p = syncer.step(source, env)?;
+        progress(&p);
+
+        if p.state == State::Done {
+            info!("Synchronized the main chain up to height {}.", p.committed);
+            return Ok(p.committed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u8) -> Id {
+        let mut id = Id::zeroed();
+        id[0] = n;
+        id
+    }
+
+    fn header(height: BlockHeight, parent: u8, n: u8) -> BlockHeader {
+        BlockHeader {
+            id: id(n),
+            parent: id(parent),
+            height,
+            work: 1,
+        }
+    }
+
+    #[test]
+    fn check_continuity_() {
+        // From the genesis
+        let headers = [header(1, 0, 1), header(2, 1, 2), header(3, 2, 3)];
+        assert_eq!(true, check_continuity(0, None, &headers).is_ok());
+        assert_eq!(true, check_continuity(0, None, &[]).is_ok());
+
+        // From the tip
+        let headers = [header(4, 3, 4), header(5, 4, 5)];
+        assert_eq!(true, check_continuity(3, Some(&id(3)), &headers).is_ok());
+
+        // The first header does not continue from the tip.
+        assert_eq!(true, check_continuity(3, Some(&id(9)), &headers).is_err());
+        assert_eq!(true, check_continuity(2, Some(&id(3)), &headers).is_err());
+
+        // A gap between the headers
+        let headers = [header(4, 3, 4), header(6, 4, 6)];
+        assert_eq!(true, check_continuity(3, Some(&id(3)), &headers).is_err());
+
+        // A header does not refer to the previous one.
+        let headers = [header(4, 3, 4), header(5, 9, 5)];
+        assert_eq!(true, check_continuity(3, Some(&id(3)), &headers).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `test_utils` generates the pseudo random data for the benchmarks and the tests.
+//! `test_utils` depends on module `data_types` .
+//!
+//! This module is available only if feature "test_utils" is specified.
+//!
+//! All the generators take [`Rng`] , and the same seed always generates the same data regardless
+//! of the platform, so that the results are comparable across the versions.
+//!
+//! [`Rng`]: self::Rng
+
+use crate::data_types::{
+    Acid, AssetValue, BlockHeight, CAcid, ChainIndex, CryptoHash, Id, Resource, ResourceId,
+};
+use core::any::TypeId;
+use std::borrow::Cow;
+use std::error::Error;
+
+/// `Rng` is a deterministic pseudo random number generator. (SplitMix64)
+///
+/// It is NOT cryptographically secure.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Creates a new instance with `seed` .
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in `[min, max]` .
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is less than `min` .
+    pub fn range(&mut self, min: u64, max: u64) -> u64 {
+        assert!(min <= max);
+        match (max - min).checked_add(1) {
+            None => self.next_u64(),
+            Some(n) => min + self.next_u64() % n,
+        }
+    }
+
+    /// Fills `buf` with the random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// `GeneratedAcid` implements `Acid` for the generated data.
+///
+/// The intrinsic data is the ids of the parents followed by the random payload, and the id is
+/// the hash of the intrinsic data. It is always traceable and valid.
+pub struct GeneratedAcid {
+    id_: Id,
+    intrinsic_: Vec<u8>,
+    parents_: Vec<Id>,
+}
+
+impl GeneratedAcid {
+    /// Creates a new instance with `parents` and `payload_len` bytes random payload.
+    pub fn new(parents: Vec<Id>, payload_len: usize, rng: &mut Rng) -> Self {
+        let mut intrinsic_ = Vec::with_capacity(parents.len() * Id::LEN + payload_len);
+        for parent in parents.iter() {
+            intrinsic_.extend_from_slice(parent.as_ref());
+        }
+
+        let offset = intrinsic_.len();
+        intrinsic_.resize(offset + payload_len, 0);
+        rng.fill(&mut intrinsic_[offset..]);
+
+        Self {
+            id_: Id::calculate(&intrinsic_),
+            intrinsic_,
+            parents_: parents,
+        }
+    }
+}
+
+impl Acid for GeneratedAcid {
+    fn id(&self) -> &Id {
+        &self.id_
+    }
+
+    fn intrinsic(&self) -> Cow<[u8]> {
+        Cow::Borrowed(&self.intrinsic_)
+    }
+
+    fn extrinsic(&self) -> Cow<[u8]> {
+        Cow::default()
+    }
+
+    fn parent_count(&self) -> usize {
+        self.parents_.len()
+    }
+
+    fn parent(&self, index: usize) -> Option<Id> {
+        self.parents_.get(index).copied()
+    }
+
+    fn resource_count(&self) -> usize {
+        0
+    }
+
+    fn resource(&self, _: usize) -> Option<Resource> {
+        None
+    }
+
+    fn is_traceable(&self) -> bool {
+        true
+    }
+
+    fn set_traceable(&self) -> bool {
+        false
+    }
+
+    fn is_invalid(&self) -> bool {
+        false
+    }
+
+    fn invalid_reason(&self) -> Option<&dyn Error> {
+        None
+    }
+
+    unsafe fn merge(&self, _other: &dyn Acid) -> bool {
+        false
+    }
+
+    fn type_id(&self) -> TypeId {
+        TypeId::of::<Self>()
+    }
+}
+
+/// Generates `count` acids in the topological order; i.e. the parents of each acid precede it.
+///
+/// Each acid has at most `max_fan_in` parents chosen from the preceding acids, and
+/// `payload_len` bytes random payload.
+pub fn acids(count: usize, max_fan_in: usize, payload_len: usize, rng: &mut Rng) -> Vec<CAcid> {
+    let mut ret: Vec<CAcid> = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let fan_in = rng.range(0, max_fan_in.min(ret.len()) as u64) as usize;
+        let mut parents = Vec::with_capacity(fan_in);
+        while parents.len() < fan_in {
+            let parent = *ret[rng.range(0, ret.len() as u64 - 1) as usize].id();
+            if !parents.contains(&parent) {
+                parents.push(parent);
+            }
+        }
+
+        ret.push(CAcid::from(GeneratedAcid::new(parents, payload_len, rng)));
+    }
+
+    ret
+}
+
+/// Generates `count` blocks starting at height 1.
+///
+/// Each block has a random number of the acids in `[min_acids, max_acids]` , generated by
+/// function [`acids`] . The block itself is the first element of the acids, and its parents are
+/// the previous block and the other acids.
+///
+/// [`acids`]: self::acids
+pub fn blocks(
+    count: usize,
+    min_acids: usize,
+    max_acids: usize,
+    max_fan_in: usize,
+    payload_len: usize,
+    rng: &mut Rng,
+) -> Vec<(ChainIndex, Vec<CAcid>)> {
+    let mut ret: Vec<(ChainIndex, Vec<CAcid>)> = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let acid_count = rng.range(min_acids as u64, max_acids as u64) as usize;
+        let mut block_acids = acids(acid_count, max_fan_in, payload_len, rng);
+
+        let mut parents = Vec::with_capacity(acid_count + 1);
+        if let Some((prev, _)) = ret.last() {
+            parents.push(*prev.id());
+        }
+        parents.extend(block_acids.iter().map(|acid| *acid.id()));
+
+        let block = CAcid::from(GeneratedAcid::new(parents, payload_len, rng));
+        let chain_index = ChainIndex::new((i + 1) as BlockHeight, block.id());
+        block_acids.insert(0, block);
+
+        ret.push((chain_index, block_acids));
+    }
+
+    ret
+}
+
+/// `Distribution` is the distribution of the generated balances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// Every value in `[1, max]` is equally likely.
+    Uniform {
+        /// The max value.
+        max: AssetValue,
+    },
+    /// The value in `[1, max / 2^k]` is chosen uniformly, where `k` is 0 with probability 1/2,
+    /// 1 with 1/4, and so on; i.e. the smaller balance is the more likely.
+    Skewed {
+        /// The max value.
+        max: AssetValue,
+    },
+}
+
+/// Generates `count` [`ResourceId`] and the balance of each.
+///
+/// Each [`ResourceId`] has `owner_len` bytes random owner and one of the `asset_types` asset
+/// types named "asset0", "asset1", and so on.
+///
+/// # Panics
+///
+/// Panics if `asset_types` is 0, or if the max of `distribution` is less than 1.
+///
+/// [`ResourceId`]: crate::data_types::ResourceId
+pub fn balances(
+    count: usize,
+    owner_len: usize,
+    asset_types: usize,
+    distribution: Distribution,
+    rng: &mut Rng,
+) -> Vec<(ResourceId, AssetValue)> {
+    assert!(0 < asset_types);
+
+    let mut ret = Vec::with_capacity(count);
+    let mut owner = vec![0; owner_len];
+
+    for _ in 0..count {
+        rng.fill(&mut owner);
+        let asset_type = format!("asset{}", rng.range(0, asset_types as u64 - 1));
+        let resource_id = unsafe { ResourceId::new(&owner, asset_type.as_bytes()) };
+
+        let value = match distribution {
+            Distribution::Uniform { max } => {
+                assert!(0 < max);
+                rng.range(1, max as u64)
+            }
+            Distribution::Skewed { max } => {
+                assert!(0 < max);
+                let k = rng.next_u64().trailing_zeros();
+                let max = ((max as u64) >> k.min(63)).max(1);
+                rng.range(1, max)
+            }
+        };
+
+        ret.push((resource_id, value as AssetValue));
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rng_is_deterministic() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        for _ in 0..100 {
+            let x = a.next_u64();
+            assert_eq!(x, b.next_u64());
+            assert_ne!(x, c.next_u64());
+        }
+
+        // The first output of SplitMix64 with seed 0.
+        assert_eq!(0xe220a8397b1dcdaf, Rng::new(0).next_u64());
+    }
+
+    #[test]
+    fn range_() {
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            let x = rng.range(3, 5);
+            assert_eq!(true, 3 <= x && x <= 5);
+        }
+        assert_eq!(7, rng.range(7, 7));
+        rng.range(0, u64::MAX);
+    }
+
+    #[test]
+    fn acids_() {
+        let generated = acids(100, 3, 16, &mut Rng::new(1));
+        assert_eq!(100, generated.len());
+
+        for (i, acid) in generated.iter().enumerate() {
+            assert_eq!(true, acid.parent_count() <= 3);
+            for j in 0..acid.parent_count() {
+                let parent = acid.parent(j).unwrap();
+                assert_eq!(true, generated[..i].iter().any(|a| *a.id() == parent));
+            }
+        }
+
+        let regenerated = acids(100, 3, 16, &mut Rng::new(1));
+        assert_eq!(
+            true,
+            generated
+                .iter()
+                .zip(regenerated.iter())
+                .all(|(a, b)| a.id() == b.id())
+        );
+    }
+
+    #[test]
+    fn blocks_() {
+        let generated = blocks(10, 1, 5, 2, 8, &mut Rng::new(1));
+        assert_eq!(10, generated.len());
+
+        for (i, (chain_index, acids)) in generated.iter().enumerate() {
+            assert_eq!((i + 1) as BlockHeight, chain_index.height());
+            assert_eq!(chain_index.id(), acids[0].id());
+            assert_eq!(true, 2 <= acids.len() && acids.len() <= 6);
+            if 0 < i {
+                assert_eq!(Some(*generated[i - 1].0.id()), acids[0].parent(0));
+            }
+        }
+    }
+
+    #[test]
+    fn balances_() {
+        for &distribution in &[
+            Distribution::Uniform { max: 100 },
+            Distribution::Skewed { max: 100 },
+        ] {
+            let generated = balances(100, 20, 3, distribution, &mut Rng::new(1));
+            assert_eq!(100, generated.len());
+            for (resource_id, value) in generated.iter() {
+                assert_eq!(20, resource_id.owner().len());
+                assert_eq!(true, 0 < *value && *value <= 100);
+            }
+        }
+    }
+}
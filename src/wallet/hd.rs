@@ -0,0 +1,328 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `hd` derives the Ed25519 keys hierarchically from a seed after SLIP-0010, so that all the keys
+//! can be restored from a mnemonic.
+//!
+//! Ed25519 does not allow the non-hardened derivation. [`DerivationPath`] accepts the
+//! non-hardened indexes, however, [`derive`] rejects them.
+//!
+//! [`DerivationPath`]: self::DerivationPath
+//! [`derive`]: self::derive
+
+use crate::Error;
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::sha2::Sha512;
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The indexes greater than or equals to `HARDENED` are hardened.
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// The purpose of BIP44.
+const PURPOSE: u32 = 44;
+
+/// The number of the PBKDF2 rounds of BIP39.
+const MNEMONIC_ROUNDS: u32 = 2048;
+
+/// The minimum and the maximum byte size of the seed that SLIP-0010 allows.
+const MIN_SEED_LEN: usize = 16;
+const MAX_SEED_LEN: usize = 64;
+
+/// `DerivationPath` is a sequence of the child indexes from the master key like
+/// "m/44'/0'/0'/0'/0'".
+///
+/// The index followed by `'` or `h` is hardened.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// Creates a new instance of BIP44 style "m/44'/coin_type'/account'/0'/index'".
+    ///
+    /// All the indexes are hardened because Ed25519 supports only the hardened derivation. The
+    /// application should assign an `account` for each asset type.
+    ///
+    /// # Error
+    ///
+    /// Errors if any of `coin_type` , `account` , or `index` is greater than or equals to
+    /// [`HARDENED`] .
+    ///
+    /// [`HARDENED`]: self::HARDENED
+    pub fn bip44(coin_type: u32, account: u32, index: u32) -> Result<Self, InvalidDerivationPath> {
+        if HARDENED <= coin_type || HARDENED <= account || HARDENED <= index {
+            let path = format!("m/44'/{}'/{}'/0'/{}'", coin_type, account, index);
+            return Err(InvalidDerivationPath { path });
+        }
+
+        Ok(Self(vec![
+            PURPOSE | HARDENED,
+            coin_type | HARDENED,
+            account | HARDENED,
+            HARDENED,
+            index | HARDENED,
+        ]))
+    }
+
+    /// Returns the child indexes.
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+
+    /// Returns whether all the indexes are hardened or not.
+    pub fn is_hardened(&self) -> bool {
+        self.0.iter().all(|&i| HARDENED <= i)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("m")?;
+        for &i in &self.0 {
+            if HARDENED <= i {
+                write!(f, "/{}'", i - HARDENED)?;
+            } else {
+                write!(f, "/{}", i)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = InvalidDerivationPath;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || InvalidDerivationPath {
+            path: String::from(s),
+        };
+
+        let mut components = s.split('/');
+        if components.next() != Some("m") {
+            return Err(err());
+        }
+
+        let mut indexes = Vec::new();
+        for component in components {
+            let (number, hardened) = match component.strip_suffix(|c| c == '\'' || c == 'h') {
+                Some(number) => (number, HARDENED),
+                None => (component, 0),
+            };
+
+            // Rejecting the sign.
+            if !number.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(err());
+            }
+
+            match number.parse::<u32>() {
+                Ok(i) if i < HARDENED => indexes.push(i | hardened),
+                _ => return Err(err()),
+            }
+        }
+
+        Ok(Self(indexes))
+    }
+}
+
+/// `InvalidDerivationPath` is the error that a string is not a [`DerivationPath`] .
+///
+/// [`DerivationPath`]: self::DerivationPath
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidDerivationPath {
+    path: String,
+}
+
+impl fmt::Display for InvalidDerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid derivation path '{}'", self.path)
+    }
+}
+
+impl error::Error for InvalidDerivationPath {}
+
+/// Creates the seed from `mnemonic` and `passphrase` after BIP39.
+///
+/// The words of `mnemonic` are separated by a white space. Neither the wordlist nor the checksum
+/// is checked.
+///
+/// # Error
+///
+/// Errors if `mnemonic` or `passphrase` includes a non-ASCII character, because they are not
+/// normalized. (BIP39 requires NFKD, which does not change the ASCII strings.)
+pub fn seed_from_mnemonic(mnemonic: &str, passphrase: &str) -> Result<[u8; 64], Error> {
+    if !mnemonic.is_ascii() || !passphrase.is_ascii() {
+        let msg = "Only the ASCII mnemonic and passphrase are supported";
+        return Err(Error::Other(Box::from(msg)));
+    }
+
+    let mnemonic = mnemonic.split_whitespace().collect::<Vec<_>>().join(" ");
+    let salt = format!("mnemonic{}", passphrase);
+
+    let mut mac = Hmac::new(Sha512::new(), mnemonic.as_bytes());
+    let mut ret = [0; 64];
+    pbkdf2(&mut mac, salt.as_bytes(), MNEMONIC_ROUNDS, &mut ret);
+    Ok(ret)
+}
+
+/// Derives the Ed25519 secret key (i.e. the 32 bytes seed of [`Ed25519SecretKey`] ) at `path`
+/// from `seed` .
+///
+/// # Error
+///
+/// Errors if the length of `seed` is not between 16 and 64, or if `path` includes a non-hardened
+/// index.
+///
+/// [`Ed25519SecretKey`]: crate::data_types::signature::Ed25519SecretKey
+pub fn derive(seed: &[u8], path: &DerivationPath) -> Result<[u8; 32], Error> {
+    if seed.len() < MIN_SEED_LEN || MAX_SEED_LEN < seed.len() {
+        let msg = format!("The seed length {} is out of range", seed.len());
+        return Err(Error::Other(Box::from(msg)));
+    }
+    if !path.is_hardened() {
+        let msg = format!("Ed25519 does not support non-hardened path '{}'", path);
+        return Err(Error::Other(Box::from(msg)));
+    }
+
+    let (mut key, mut chain_code) = split(hmac_sha512(b"ed25519 seed", seed));
+
+    for &i in path.indexes() {
+        let mut data = [0; 37];
+        data[1..33].copy_from_slice(&key);
+        data[33..].copy_from_slice(&i.to_be_bytes());
+
+        let (k, c) = split(hmac_sha512(&chain_code, &data));
+        key = k;
+        chain_code = c;
+    }
+
+    Ok(key)
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::new(Sha512::new(), key);
+    mac.input(data);
+
+    let mut ret = [0; 64];
+    mac.raw_result(&mut ret);
+    ret
+}
+
+/// Splits the HMAC into the key (the left half) and the chain code (the right half).
+fn split(hmac: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut key = [0; 32];
+    let mut chain_code = [0; 32];
+    key.copy_from_slice(&hmac[..32]);
+    chain_code.copy_from_slice(&hmac[32..]);
+    (key, chain_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::crypto_hash::parse_hex;
+
+    fn hex<T: Default + AsMut<[u8]>>(s: &str) -> T {
+        let mut ret = T::default();
+        parse_hex(s, ret.as_mut()).unwrap();
+        ret
+    }
+
+    #[test]
+    fn parse_path() {
+        let path: DerivationPath = "m/44'/1h/2".parse().unwrap();
+        assert_eq!(&[44 | HARDENED, 1 | HARDENED, 2], path.indexes());
+        assert_eq!(false, path.is_hardened());
+        assert_eq!("m/44'/1'/2", path.to_string());
+
+        assert_eq!(Ok(DerivationPath::default()), "m".parse());
+        assert_eq!(
+            Ok(DerivationPath::bip44(1, 2, 3).unwrap()),
+            "m/44'/1'/2'/0'/3'".parse()
+        );
+
+        for s in &[
+            "",
+            "m/",
+            "44'/0'",
+            "m/-1",
+            "m/+1",
+            "m/1''",
+            "m/2147483648",
+            "M/0",
+        ] {
+            assert_eq!(true, s.parse::<DerivationPath>().is_err());
+        }
+    }
+
+    #[test]
+    fn bip44_() {
+        let path = DerivationPath::bip44(0, HARDENED - 1, 0).unwrap();
+        let expected = [44 | HARDENED, HARDENED, u32::MAX, HARDENED, HARDENED];
+        assert_eq!(&expected, path.indexes());
+
+        assert_eq!(true, DerivationPath::bip44(HARDENED, 0, 0).is_err());
+        assert_eq!(true, DerivationPath::bip44(0, HARDENED, 0).is_err());
+        assert_eq!(true, DerivationPath::bip44(0, 0, HARDENED).is_err());
+        assert_eq!(true, DerivationPath::bip44(0, 0, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn derive_() {
+        // Test vector 1 for ed25519 of SLIP-0010.
+        let seed: [u8; 16] = hex("000102030405060708090a0b0c0d0e0f");
+
+        let path = "m".parse().unwrap();
+        let expected: [u8; 32] =
+            hex("2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+        assert_eq!(expected, derive(&seed, &path).unwrap());
+
+        let path = "m/0'".parse().unwrap();
+        let expected: [u8; 32] =
+            hex("68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+        assert_eq!(expected, derive(&seed, &path).unwrap());
+
+        let path = "m/0'/1'".parse().unwrap();
+        let expected: [u8; 32] =
+            hex("b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2");
+        assert_eq!(expected, derive(&seed, &path).unwrap());
+
+        assert_eq!(true, derive(&seed, &"m/0'/1".parse().unwrap()).is_err());
+        assert_eq!(true, derive(&seed[..15], &path).is_err());
+    }
+
+    #[test]
+    fn mnemonic() {
+        // Test vector of BIP39.
+        let mnemonic = "abandon abandon abandon abandon abandon abandon \
+                        abandon abandon abandon abandon abandon about";
+        let seed = seed_from_mnemonic(mnemonic, "TREZOR").unwrap();
+        let expected = "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e5349553\
+                        1f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04";
+        assert_eq!(&hex::<[u8; 32]>(&expected[..64]), &seed[..32]);
+        assert_eq!(&hex::<[u8; 32]>(&expected[64..]), &seed[32..]);
+
+        // The white spaces are normalized.
+        let spaced = format!("  {}\n", mnemonic.replace(' ', "   "));
+        assert_eq!(
+            &seed[..],
+            &seed_from_mnemonic(&spaced, "TREZOR").unwrap()[..]
+        );
+
+        assert_eq!(true, seed_from_mnemonic(mnemonic, "pässword").is_err());
+    }
+}
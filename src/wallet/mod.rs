@@ -0,0 +1,571 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `wallet` stores the Ed25519 secret keys encrypted with the passphrase in the file specified
+//! by '--wallet-path', and signs the intrinsic data with them.
+//! `wallet` depends on module `data_types` .
+//!
+//! The owner of [`ResourceId`] that a key holds is the public key. (See also method
+//! [`KeyInfo::address`] .)
+//!
+//! # Encryption
+//!
+//! Each secret key (i.e. the 32 bytes seed) is encrypted with ChaCha20-Poly1305. The encryption
+//! key is derived from the passphrase by PBKDF2-HMAC-SHA256 with the random salt of each secret
+//! key, and the public key is authenticated as the associated data. The passphrase is never
+//! stored.
+//!
+//! The seeds, the salts, and the nonces are generated by the random number generator of the OS.
+//! The decrypted seeds and the encryption keys are overwritten with 0 after used.
+//!
+//! # Format
+//!
+//! The wallet file is a text file with a line for each key. A line is the label, the number of
+//! the PBKDF2 rounds, the public key, the salt, the nonce, the encrypted seed, and the tag
+//! separated by a space. (The binary ones are in hex.)
+//!
+//! # Hierarchical Deterministic Keys
+//!
+//! Module [`hd`] derives the keys from a mnemonic, and function [`import_derived`] stores them.
+//! The wallet file does not remember the mnemonic nor the derivation path.
+//!
+//! [`ResourceId`]: crate::data_types::ResourceId
+//! [`KeyInfo::address`]: self::KeyInfo::address
+//! [`hd`]: self::hd
+//! [`import_derived`]: self::import_derived
+
+pub mod hd;
+
+use crate::data_types::base58;
+use crate::data_types::crypto_hash::{parse_hex, HexDisplay};
+use crate::data_types::signature::{zeroize, Ed25519SecretKey, Signer, Verifier};
+use crate::{Config, Error, ModuleEnvironment};
+use clap::{App, Arg};
+use crypto::aead::{AeadDecryptor, AeadEncryptor};
+use crypto::chacha20poly1305::ChaCha20Poly1305;
+use crypto::hmac::Hmac;
+use crypto::pbkdf2::pbkdf2;
+use crypto::sha2::Sha256;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const SEED_LEN: usize = 32;
+const PUBLIC_KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 8;
+const TAG_LEN: usize = 16;
+
+/// The number of the PBKDF2 rounds for the new keys.
+#[cfg(not(test))]
+const PBKDF2_ROUNDS: u32 = 100_000;
+#[cfg(test)]
+const PBKDF2_ROUNDS: u32 = 16;
+
+/// The min number of the PBKDF2 rounds that the wallet file accepts. A line with fewer rounds is
+/// regarded as broken.
+const MIN_PBKDF2_ROUNDS: u32 = PBKDF2_ROUNDS;
+
+/// `KeyInfo` is the public information of a key in the wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyInfo {
+    /// The name of the key.
+    pub label: String,
+    /// The public key, which is the owner of [`ResourceId`] .
+    ///
+    /// [`ResourceId`]: crate::data_types::ResourceId
+    pub owner: Vec<u8>,
+}
+
+impl KeyInfo {
+    /// Returns `owner` encoded in Base58Check.
+    ///
+    /// See also method [`ResourceId::owner_base58check`] .
+    ///
+    /// [`ResourceId::owner_base58check`]: crate::data_types::ResourceId::owner_base58check
+    pub fn address(&self) -> String {
+        base58::encode_check(&self.owner)
+    }
+}
+
+/// `Entry` is a line of the wallet file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    label: String,
+    rounds: u32,
+    owner: [u8; PUBLIC_KEY_LEN],
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN],
+    encrypted: [u8; SEED_LEN],
+    tag: [u8; TAG_LEN],
+}
+
+impl Entry {
+    /// Encrypts `seed` with `passphrase` and creates a new instance.
+    fn seal(label: &str, seed: &[u8; SEED_LEN], passphrase: &[u8]) -> Result<Self, Error> {
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        fill_random(&mut salt)?;
+        fill_random(&mut nonce)?;
+
+        let public = Ed25519SecretKey::from_seed(seed).public_key();
+        let mut owner = [0; PUBLIC_KEY_LEN];
+        owner.copy_from_slice(public.as_bytes());
+
+        let mut key = derive_key(passphrase, &salt, PBKDF2_ROUNDS);
+        let mut cipher = ChaCha20Poly1305::new(&key, &nonce, &owner);
+        zeroize(&mut key);
+        let mut encrypted = [0; SEED_LEN];
+        let mut tag = [0; TAG_LEN];
+        cipher.encrypt(seed, &mut encrypted, &mut tag);
+
+        Ok(Self {
+            label: String::from(label),
+            rounds: PBKDF2_ROUNDS,
+            owner,
+            salt,
+            nonce,
+            encrypted,
+            tag,
+        })
+    }
+
+    /// Decrypts the seed with `passphrase` , or returns `None` if `passphrase` is wrong.
+    ///
+    /// The caller should overwrite the returned seed with 0 after used.
+    fn open(&self, passphrase: &[u8]) -> Option<[u8; SEED_LEN]> {
+        let mut key = derive_key(passphrase, &self.salt, self.rounds);
+        let mut cipher = ChaCha20Poly1305::new(&key, &self.nonce, &self.owner);
+        zeroize(&mut key);
+        let mut seed = [0; SEED_LEN];
+
+        if cipher.decrypt(&self.encrypted, &mut seed, &self.tag) {
+            Some(seed)
+        } else {
+            zeroize(&mut seed);
+            None
+        }
+    }
+
+    fn info(&self) -> KeyInfo {
+        KeyInfo {
+            label: self.label.clone(),
+            owner: self.owner.to_vec(),
+        }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{} {} {} {} {} {} {}\n",
+            self.label,
+            self.rounds,
+            HexDisplay::new(&self.owner),
+            HexDisplay::new(&self.salt),
+            HexDisplay::new(&self.nonce),
+            HexDisplay::new(&self.encrypted),
+            HexDisplay::new(&self.tag)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut columns = line.split(' ');
+        let label = columns.next().filter(|label| is_valid_label(label))?;
+        let rounds = columns.next()?.parse().ok()?;
+        if rounds < MIN_PBKDF2_ROUNDS {
+            return None;
+        }
+
+        let mut ret = Self {
+            label: String::from(label),
+            rounds,
+            owner: [0; PUBLIC_KEY_LEN],
+            salt: [0; SALT_LEN],
+            nonce: [0; NONCE_LEN],
+            encrypted: [0; SEED_LEN],
+            tag: [0; TAG_LEN],
+        };
+
+        parse_hex(columns.next()?, &mut ret.owner).ok()?;
+        parse_hex(columns.next()?, &mut ret.salt).ok()?;
+        parse_hex(columns.next()?, &mut ret.nonce).ok()?;
+        parse_hex(columns.next()?, &mut ret.encrypted).ok()?;
+        parse_hex(columns.next()?, &mut ret.tag).ok()?;
+
+        match columns.next() {
+            None => Some(ret),
+            Some(_) => None,
+        }
+    }
+}
+
+fn derive_key(passphrase: &[u8], salt: &[u8], rounds: u32) -> [u8; 32] {
+    let mut mac = Hmac::new(Sha256::new(), passphrase);
+    let mut ret = [0; 32];
+    pbkdf2(&mut mac, salt, rounds, &mut ret);
+    ret
+}
+
+/// Fills `buffer` with the random bytes that the OS generates. (e.g. "getrandom" on Linux, or
+/// "BCryptGenRandom" on Windows.)
+fn fill_random(buffer: &mut [u8]) -> Result<(), Error> {
+    getrandom::getrandom(buffer).map_err(|e| {
+        let msg = format!("Failed to generate random bytes: {}", e);
+        Error::Other(Box::from(msg))
+    })
+}
+
+fn is_valid_label(label: &str) -> bool {
+    !label.is_empty() && !label.chars().any(char::is_whitespace)
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --wallet-path
+///
+/// # Default
+///
+/// Nothing is specified by default; i.e. the wallet is disabled.
+#[derive(Default)]
+pub struct Environment {
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.arg(
+            Arg::with_name("wallet_path")
+                .help(
+                    "Path to the file to store the encrypted secret keys.
+The wallet is disabled by default.",
+                )
+                .long("--wallet-path")
+                .takes_value(true),
+        )
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), Error> {
+        self.path = config.args().value_of("wallet_path").map(PathBuf::from);
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), Error> {
+        let path = match self.path.as_ref() {
+            None => return Ok(()),
+            Some(path) => path,
+        };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                let msg = format!("Failed to read the wallet '{}': {}", path.display(), e);
+                return Err(Error::Config(msg));
+            }
+        };
+
+        let mut entries = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            match Entry::from_line(line) {
+                Some(entry) => entries.push(entry),
+                None => {
+                    let msg = format!(
+                        "The wallet '{}' is broken at line {}",
+                        path.display(),
+                        i + 1
+                    );
+                    return Err(Error::Config(msg));
+                }
+            }
+        }
+
+        info!("Loaded {} keys from the wallet.", entries.len());
+        *self.entries.get_mut().unwrap() = entries;
+        Ok(())
+    }
+}
+
+impl Environment {
+    fn path(&self) -> Result<&PathBuf, Error> {
+        self.path
+            .as_ref()
+            .ok_or_else(|| Error::Config(String::from("'--wallet-path' is not specified")))
+    }
+
+    /// Appends `entry` to the wallet file.
+    fn add(&self, entry: Entry) -> Result<KeyInfo, Error> {
+        let path = self.path()?;
+        let mut entries = self.entries.lock().unwrap();
+
+        if entries.iter().any(|e| e.label == entry.label) {
+            let msg = format!("Key '{}' is already in the wallet", entry.label);
+            return Err(Error::Other(Box::from(msg)));
+        }
+        if let Some(e) = entries.iter().find(|e| e.owner == entry.owner) {
+            let msg = format!("The key is already in the wallet as '{}'", e.label);
+            return Err(Error::Other(Box::from(msg)));
+        }
+
+        // Writing into another file and renaming it not to break the wallet on the way.
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&tmp_path)?;
+        for e in entries.iter().chain(Some(&entry)) {
+            file.write_all(e.to_line().as_bytes())?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+
+        let ret = entry.info();
+        entries.push(entry);
+        Ok(ret)
+    }
+}
+
+/// Returns the keys in the wallet in the order of the creation.
+pub fn list(env: &Environment) -> Vec<KeyInfo> {
+    let entries = env.entries.lock().unwrap();
+    entries.iter().map(Entry::info).collect()
+}
+
+/// Generates a new key named `label` , stores it encrypted with `passphrase` , and returns it.
+///
+/// # Error
+///
+/// Errors if '--wallet-path' is not specified, if `label` is empty or includes a white space, or
+/// if `label` is already in the wallet.
+pub fn create(label: &str, passphrase: &[u8], env: &Environment) -> Result<KeyInfo, Error> {
+    let mut seed = [0; SEED_LEN];
+    let ret = fill_random(&mut seed).and_then(|_| import(label, &seed, passphrase, env));
+    zeroize(&mut seed);
+    ret
+}
+
+/// Stores the key generated from `seed` as `label` encrypted with `passphrase` , and returns it.
+///
+/// # Error
+///
+/// Errors if '--wallet-path' is not specified, if `label` is empty or includes a white space, or
+/// if `label` or the key is already in the wallet.
+pub fn import(
+    label: &str,
+    seed: &[u8; SEED_LEN],
+    passphrase: &[u8],
+    env: &Environment,
+) -> Result<KeyInfo, Error> {
+    if !is_valid_label(label) {
+        let msg = format!("Invalid key label '{}'", label);
+        return Err(Error::Other(Box::from(msg)));
+    }
+
+    let entry = Entry::seal(label, seed, passphrase)?;
+    let ret = env.add(entry)?;
+    info!("Added key '{}' to the wallet.", label);
+    Ok(ret)
+}
+
+/// Derives the key at `path` from `seed` (See function [`hd::derive`] ,) stores it as `label`
+/// encrypted with `passphrase` , and returns it.
+///
+/// # Error
+///
+/// Errors if function [`hd::derive`] fails, or under the same conditions as function
+/// [`import`] .
+///
+/// [`hd::derive`]: self::hd::derive
+/// [`import`]: self::import
+pub fn import_derived(
+    label: &str,
+    seed: &[u8],
+    path: &hd::DerivationPath,
+    passphrase: &[u8],
+    env: &Environment,
+) -> Result<KeyInfo, Error> {
+    let mut key = hd::derive(seed, path)?;
+    let ret = import(label, &key, passphrase, env);
+    zeroize(&mut key);
+    ret
+}
+
+/// Signs `message` (usually the intrinsic data of an acid) with the key whose owner is `owner` ,
+/// and returns the signature.
+///
+/// # Error
+///
+/// Errors if `owner` is not in the wallet, or if `passphrase` is wrong.
+pub fn sign(
+    owner: &[u8],
+    message: &[u8],
+    passphrase: &[u8],
+    env: &Environment,
+) -> Result<Vec<u8>, Error> {
+    let entry = {
+        let entries = env.entries.lock().unwrap();
+        match entries.iter().find(|e| &e.owner[..] == owner) {
+            Some(entry) => entry.clone(),
+            None => {
+                let msg = format!("Owner {} is not in the wallet", base58::encode_check(owner));
+                return Err(Error::Other(Box::from(msg)));
+            }
+        }
+    };
+
+    let mut seed = entry.open(passphrase).ok_or_else(|| {
+        let msg = format!("Wrong passphrase for key '{}'", entry.label);
+        Error::Other(Box::from(msg))
+    })?;
+    let secret = Ed25519SecretKey::from_seed(&seed);
+    zeroize(&mut seed);
+
+    // `secret` is overwritten with 0 when dropped.
+    Ok(secret.sign(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_types::signature::Ed25519PublicKey;
+
+    fn env(name: &str) -> Environment {
+        let path =
+            std::env::temp_dir().join(format!("mouse-wallet-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        Environment {
+            path: Some(path),
+            entries: Mutex::default(),
+        }
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let seed = [1; SEED_LEN];
+        let entry = Entry::seal("alice", &seed, b"passphrase").unwrap();
+
+        assert_eq!(Some(seed), entry.open(b"passphrase"));
+        assert_eq!(None, entry.open(b"wrong"));
+        assert_eq!(
+            Ed25519SecretKey::from_seed(&seed).public_key().as_bytes(),
+            &entry.owner[..]
+        );
+
+        // The public key is authenticated.
+        let mut forged = entry.clone();
+        forged.owner[0] ^= 1;
+        assert_eq!(None, forged.open(b"passphrase"));
+    }
+
+    #[test]
+    fn line() {
+        let entry = Entry::seal("alice", &[1; SEED_LEN], b"passphrase").unwrap();
+        let line = entry.to_line();
+        assert_eq!(Some(entry), Entry::from_line(line.trim_end()));
+
+        assert_eq!(None, Entry::from_line(""));
+        assert_eq!(None, Entry::from_line(&line.trim_end()[1..]));
+        assert_eq!(None, Entry::from_line(&format!("{} 00", line.trim_end())));
+
+        // Too few rounds
+        let mut weak = entry.clone();
+        weak.rounds = MIN_PBKDF2_ROUNDS - 1;
+        assert_eq!(None, Entry::from_line(weak.to_line().trim_end()));
+        weak.rounds = 0;
+        assert_eq!(None, Entry::from_line(weak.to_line().trim_end()));
+    }
+
+    #[test]
+    fn fill_random_() {
+        let mut a = [0; SEED_LEN];
+        let mut b = [0; SEED_LEN];
+        fill_random(&mut a).unwrap();
+        fill_random(&mut b).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn import_and_sign() {
+        let env = env("import");
+        let seed = [2; SEED_LEN];
+
+        let key = import("alice", &seed, b"passphrase", &env).unwrap();
+        assert_eq!(vec![key.clone()], list(&env));
+        assert_eq!(true, import("alice", &[3; SEED_LEN], b"", &env).is_err());
+        assert_eq!(true, import("bob", &seed, b"", &env).is_err());
+        assert_eq!(
+            true,
+            import("bad label", &[3; SEED_LEN], b"", &env).is_err()
+        );
+
+        let signature = sign(&key.owner, b"intrinsic", b"passphrase", &env).unwrap();
+        let public = Ed25519PublicKey::from_bytes(&key.owner).unwrap();
+        assert_eq!(true, public.verify(b"intrinsic", &signature));
+        assert_eq!(
+            true,
+            sign(&key.owner, b"intrinsic", b"wrong", &env).is_err()
+        );
+        assert_eq!(
+            true,
+            sign(&[0; 32], b"intrinsic", b"passphrase", &env).is_err()
+        );
+
+        // Loading the file again.
+        let mut loaded = Environment {
+            path: env.path.clone(),
+            entries: Mutex::default(),
+        };
+        unsafe { loaded.init().unwrap() };
+        assert_eq!(list(&env), list(&loaded));
+
+        fs::remove_file(env.path.as_ref().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn import_derived_() {
+        let env = env("derived");
+        let seed = [4; 16];
+        let path = hd::DerivationPath::bip44(0, 1, 2).unwrap();
+
+        let key = import_derived("alice", &seed, &path, b"passphrase", &env).unwrap();
+        let expected = Ed25519SecretKey::from_seed(&hd::derive(&seed, &path).unwrap());
+        assert_eq!(expected.public_key().as_bytes(), &key.owner[..]);
+
+        let path = "m/44'/0'/1'/0'/2".parse().unwrap();
+        assert_eq!(
+            true,
+            import_derived("bob", &seed, &path, b"passphrase", &env).is_err()
+        );
+
+        fs::remove_file(env.path.as_ref().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn disabled() {
+        let env = Environment::default();
+        assert_eq!(true, create("alice", b"passphrase", &env).is_err());
+        assert_eq!(true, list(&env).is_empty());
+    }
+}
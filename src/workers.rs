@@ -0,0 +1,578 @@
+// Copyright 2021 Shin Yoshida
+//
+// This file is part of Mouse.
+//
+// Mouse is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License.
+//
+// Mouse is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Mouse.  If not, see <https://www.gnu.org/licenses/>.
+
+//! `workers` provides the worker threads shared by the modules, so that each module does not
+//! need to spawn threads of its own.
+//! `workers` depends on module `runtime` .
+//!
+//! The tasks are queued in a bounded queue, and run in the FIFO order. Function [`spawn`] blocks
+//! while the queue is full.
+//!
+//! `workers` also runs the recurring tasks registered by method [`Environment::schedule`] ; e.g.
+//! the maintenance jobs of the other modules.
+//!
+//! # Dedicated threads
+//!
+//! Module `kvs` keeps the threads of its own instead of the worker threads.
+//!
+//! - The flusher thread runs a loop for the whole lifetime. It would occupy a worker thread
+//!   forever, and a full queue must not delay the flush.
+//! - The prefetch threads run the fetches that the caller blocks for; e.g. function
+//!   [`kvs::prefetch`] and `ReadQuery::wait_timeout` . The caller can be a task on a worker thread
+//!   itself, so queuing the fetches to the worker threads could dead lock when all the worker
+//!   threads wait for them. Besides, [`spawn`] blocks while the queue is full, which
+//!   `ReadQuery::wait_timeout` must not.
+//!
+//! [`spawn`]: self::spawn
+//! [`Environment::schedule`]: self::Environment::schedule
+//! [`kvs::prefetch`]: crate::kvs::prefetch
+
+use crate::runtime::{self, WorkerGroup};
+use crate::{Config, ModuleEnvironment};
+use clap::{App, Arg};
+use std::any::Any;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const DEFAULT_WORKER_THREADS: &'static str = "4";
+const DEFAULT_WORKER_QUEUE_SIZE: &'static str = "1024";
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// `Periodic` is a recurring task registered by method [`Environment::schedule`] .
+///
+/// [`Environment::schedule`]: self::Environment::schedule
+struct Periodic {
+    id: u64,
+    name: String,
+    interval: Duration,
+    next: Instant,
+    f: Arc<dyn Fn() + Send + Sync>,
+    /// `true` while the last run is queued or running, so that the runs never overlap.
+    is_running: Arc<AtomicBool>,
+}
+
+impl Periodic {
+    /// Returns the job to run `self` once.
+    fn job(&self) -> Job {
+        let name = self.name.clone();
+        let f = self.f.clone();
+        let is_running = self.is_running.clone();
+
+        Box::new(move || {
+            if panic::catch_unwind(AssertUnwindSafe(|| f())).is_err() {
+                error!("Scheduled task '{}' panicked.", name);
+            }
+            is_running.store(false, Ordering::Release);
+        })
+    }
+}
+
+#[derive(Default)]
+struct TimetableState {
+    tasks: Vec<Periodic>,
+    next_id: u64,
+    is_stopping: bool,
+}
+
+/// `Timetable` is the recurring tasks shared with the timer thread.
+#[derive(Default)]
+struct Timetable {
+    state: Mutex<TimetableState>,
+    /// Notified when a task is registered or when the timer thread should stop.
+    changed: Condvar,
+}
+
+/// Queues the recurring tasks in `timetable` when they are due till the timer stops.
+///
+/// The tasks run on the worker threads if `sender` is `Some` , or in the timer thread.
+fn timer_loop(timetable: &Timetable, sender: Option<SyncSender<Job>>) {
+    let mut state = timetable.state.lock().unwrap();
+
+    loop {
+        if state.is_stopping {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut jobs = Vec::new();
+        for task in state.tasks.iter_mut().filter(|task| task.next <= now) {
+            // Skip the runs missed while the timer is late.
+            while task.next <= now {
+                task.next += task.interval;
+            }
+
+            if task.is_running.swap(true, Ordering::AcqRel) {
+                debug!(
+                    "Skipped scheduled task '{}': the last run is not finished.",
+                    task.name
+                );
+            } else {
+                jobs.push((task.name.clone(), task.is_running.clone(), task.job()));
+            }
+        }
+
+        if !jobs.is_empty() {
+            drop(state);
+            for (name, is_running, job) in jobs {
+                match &sender {
+                    None => job(),
+                    Some(sender) => {
+                        // The workers never finish while the timer is running, so the error
+                        // means that the queue is full.
+                        if sender.try_send(job).is_err() {
+                            warn!("Skipped scheduled task '{}': the queue is full.", name);
+                            is_running.store(false, Ordering::Release);
+                        }
+                    }
+                }
+            }
+            state = timetable.state.lock().unwrap();
+            continue;
+        }
+
+        let next = state.tasks.iter().map(|task| task.next).min();
+        state = match next {
+            None => timetable.changed.wait(state).unwrap(),
+            Some(next) => {
+                let timeout = next.saturating_duration_since(now);
+                timetable.changed.wait_timeout(state, timeout).unwrap().0
+            }
+        };
+    }
+}
+
+/// `Task` is the handle to join a task spawned by function [`spawn`] .
+///
+/// Dropping `Task` detaches the task; i.e. the task keeps running but the result is discarded.
+///
+/// [`spawn`]: self::spawn
+pub struct Task<T> {
+    receiver: Receiver<thread::Result<T>>,
+}
+
+impl<T> Task<T> {
+    /// Blocks till the task finishes, and returns the result.
+    ///
+    /// Like `std::thread::JoinHandle::join` , this method returns the panic payload if the task
+    /// panicked.
+    pub fn join(self) -> thread::Result<T> {
+        match self.receiver.recv() {
+            Ok(result) => result,
+            // The task was dropped without running; i.e. `Environment` has been dropped.
+            Err(_) => Err(Box::new("The task was dropped before running.") as Box<dyn Any + Send>),
+        }
+    }
+
+    /// Returns the result if the task has finished, or `None` without blocking.
+    pub fn try_join(&self) -> Option<thread::Result<T>> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// `Environment` implements `ModuleEnvironment` for this module.
+///
+/// # Arguments
+///
+/// `Environment` requests the following arguments.
+///
+/// - --worker-threads
+/// - --worker-queue-size
+///
+/// # Default
+///
+/// The `Default` implementation assumes the following arguments.
+///
+/// - --worker-threads: 4
+/// - --worker-queue-size: 1024
+///
+/// Note that the worker threads are started by method [`start`] , not by `init()` . Before then,
+/// the tasks run in the caller thread.
+///
+/// [`start`]: Self::start
+pub struct Environment {
+    threads: usize,
+    queue_size: usize,
+    sender: Option<Mutex<SyncSender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+    timetable: Arc<Timetable>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            threads: DEFAULT_WORKER_THREADS.parse().unwrap(),
+            queue_size: DEFAULT_WORKER_QUEUE_SIZE.parse().unwrap(),
+            sender: None,
+            workers: Vec::new(),
+            timetable: Arc::default(),
+            timer: None,
+        }
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        // Stop the timer first, because it holds the sender as well.
+        if let Some(timer) = self.timer.take() {
+            self.timetable.state.lock().unwrap().is_stopping = true;
+            self.timetable.changed.notify_all();
+
+            if timer.join().is_err() {
+                error!("The timer thread panicked.");
+            }
+        }
+
+        // The workers finish the queued tasks, and then finish when the channel is closed.
+        self.sender = None;
+
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("A worker thread panicked.");
+            }
+        }
+    }
+}
+
+impl ModuleEnvironment for Environment {
+    fn args(app: App<'static, 'static>) -> App<'static, 'static> {
+        app.args(&[
+            Arg::with_name("WORKER_THREADS")
+                .help(
+                    "The number of the worker threads shared by the modules. (0 runs the tasks in
+the caller thread.)",
+                )
+                .long("--worker-threads")
+                .default_value(DEFAULT_WORKER_THREADS)
+                .takes_value(true),
+            Arg::with_name("WORKER_QUEUE_SIZE")
+                .help("The max number of the tasks waiting for the worker threads.")
+                .long("--worker-queue-size")
+                .default_value(DEFAULT_WORKER_QUEUE_SIZE)
+                .takes_value(true),
+        ])
+    }
+
+    unsafe fn check(&mut self, config: &Config) -> Result<(), crate::Error> {
+        let threads = config.args().value_of("WORKER_THREADS").unwrap();
+        self.threads = threads.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--worker-threads': {}",
+                e
+            ))
+        })?;
+
+        let queue_size = config.args().value_of("WORKER_QUEUE_SIZE").unwrap();
+        self.queue_size = queue_size.parse().map_err(|e| {
+            crate::Error::Config(format!(
+                "Failed to parse argument '--worker-queue-size': {}",
+                e
+            ))
+        })?;
+        if self.queue_size == 0 {
+            let msg = "'--worker-queue-size' must be greater than 0.";
+            return Err(crate::Error::Config(String::from(msg)));
+        }
+
+        Ok(())
+    }
+
+    unsafe fn init(&mut self) -> Result<(), crate::Error> {
+        Ok(())
+    }
+}
+
+impl Environment {
+    /// Starts '--worker-threads' threads in `WorkerGroup::Worker` , and the timer thread to run
+    /// the tasks registered by method [`schedule`] .
+    ///
+    /// # Panics
+    ///
+    /// Panics if the worker threads have already started.
+    ///
+    /// [`schedule`]: Self::schedule
+    pub fn start(&mut self, runtime_env: &runtime::Environment) -> io::Result<()> {
+        assert_eq!(true, self.timer.is_none());
+        self.start_workers(runtime_env)?;
+
+        let timetable = self.timetable.clone();
+        let sender = self.sender.as_ref().map(|s| s.lock().unwrap().clone());
+        let timer = runtime::spawn(WorkerGroup::Worker, runtime_env, move || {
+            timer_loop(&timetable, sender)
+        })?;
+        self.timer = Some(timer);
+
+        Ok(())
+    }
+
+    fn start_workers(&mut self, runtime_env: &runtime::Environment) -> io::Result<()> {
+        if self.threads == 0 {
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<Job>(self.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        self.sender = Some(Mutex::new(sender));
+
+        for _ in 0..self.threads {
+            let receiver = receiver.clone();
+            let worker = runtime::spawn(WorkerGroup::Worker, runtime_env, move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            })?;
+            self.workers.push(worker);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of the started worker threads.
+    pub fn thread_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Registers `f` to be called every `interval` , and returns the id to unregister it.
+    ///
+    /// `f` is called on a worker thread for the first time when `interval` passes after method
+    /// [`start`] is called or after this method is called, whichever is later. A run is skipped
+    /// if the last run has not finished yet, or if the task queue is full.
+    ///
+    /// `name` is used in the log.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use mouse::runtime;
+    /// use mouse::workers::Environment;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::thread;
+    /// use std::time::Duration;
+    ///
+    /// let mut env = Environment::default();
+    /// env.start(&runtime::Environment::default()).unwrap();
+    ///
+    /// let counter = Arc::new(AtomicUsize::new(0));
+    /// let id = {
+    ///     let counter = counter.clone();
+    ///     env.schedule("count", Duration::from_millis(10), move || {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    /// };
+    ///
+    /// thread::sleep(Duration::from_millis(100));
+    /// assert_eq!(true, env.unschedule(id));
+    /// assert_eq!(true, 0 < counter.load(Ordering::Relaxed));
+    /// ```
+    ///
+    /// [`start`]: Self::start
+    pub fn schedule<F>(&self, name: &str, interval: Duration, f: F) -> u64
+    where
+        F: 'static + Fn() + Send + Sync,
+    {
+        assert_ne!(Duration::from_secs(0), interval);
+
+        let mut state = self.timetable.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+
+        state.tasks.push(Periodic {
+            id,
+            name: String::from(name),
+            interval,
+            next: Instant::now() + interval,
+            f: Arc::new(f),
+            is_running: Arc::default(),
+        });
+        self.timetable.changed.notify_all();
+
+        id
+    }
+
+    /// Unregisters the task registered by method [`schedule`] , and returns `true` if found.
+    ///
+    /// The run in progress is not interrupted.
+    ///
+    /// [`schedule`]: Self::schedule
+    pub fn unschedule(&self, id: u64) -> bool {
+        let mut state = self.timetable.state.lock().unwrap();
+        let len = state.tasks.len();
+        state.tasks.retain(|task| task.id != id);
+        len != state.tasks.len()
+    }
+}
+
+/// Queues `f` to run on a worker thread, and returns the handle to join it.
+///
+/// This function blocks while '--worker-queue-size' tasks are waiting. If the worker threads are
+/// not started, `f` runs in the caller thread before this function returns.
+///
+/// # Examples
+///
+/// ```
+/// use mouse::runtime;
+/// use mouse::workers::{spawn, Environment};
+///
+/// let mut env = Environment::default();
+/// env.start(&runtime::Environment::default()).unwrap();
+///
+/// let task = spawn(|| 1 + 2, &env);
+/// assert_eq!(3, task.join().unwrap());
+/// ```
+pub fn spawn<F, T>(f: F, env: &Environment) -> Task<T>
+where
+    F: 'static + FnOnce() -> T + Send,
+    T: 'static + Send,
+{
+    let (sender, receiver) = mpsc::channel();
+    let job: Job = Box::new(move || {
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        // The receiver can be dropped if the task is detached.
+        let _ = sender.send(result);
+    });
+
+    match &env.sender {
+        None => job(),
+        Some(sender) => {
+            // Clone the sender not to hold the lock while the queue is full.
+            let sender = sender.lock().unwrap().clone();
+            if let Err(mpsc::SendError(job)) = sender.send(job) {
+                // The workers have already finished. (Never comes here as long as `env` is
+                // alive.)
+                job();
+            }
+        }
+    }
+
+    Task { receiver }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn start(threads: usize, queue_size: usize) -> Environment {
+        let mut env = Environment::default();
+        env.threads = threads;
+        env.queue_size = queue_size;
+        env.start(&runtime::Environment::default()).unwrap();
+        env
+    }
+
+    #[test]
+    fn spawn_join() {
+        for &threads in &[0, 1, 4] {
+            let env = start(threads, 2);
+            assert_eq!(threads, env.thread_count());
+
+            let counter = Arc::new(AtomicUsize::new(0));
+            let tasks: Vec<_> = (0..16)
+                .map(|i| {
+                    let counter = counter.clone();
+                    spawn(
+                        move || {
+                            counter.fetch_add(1, Ordering::Relaxed);
+                            i * 2
+                        },
+                        &env,
+                    )
+                })
+                .collect();
+
+            for (i, task) in tasks.into_iter().enumerate() {
+                assert_eq!(i * 2, task.join().unwrap());
+            }
+            assert_eq!(16, counter.load(Ordering::Relaxed));
+        }
+    }
+
+    #[test]
+    fn panic_in_task() {
+        let env = start(1, 1);
+
+        let task = spawn(|| -> usize { panic!("foo") }, &env);
+        assert_eq!(true, task.join().is_err());
+
+        // The worker thread is still alive.
+        let task = spawn(|| 1, &env);
+        assert_eq!(1, task.join().unwrap());
+    }
+
+    #[test]
+    fn schedule() {
+        for &threads in &[0, 2] {
+            let env = start(threads, 4);
+            let counter = Arc::new(AtomicUsize::new(0));
+
+            let id = {
+                let counter = counter.clone();
+                env.schedule("count", Duration::from_millis(5), move || {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                })
+            };
+            let panicking = env.schedule("panic", Duration::from_millis(5), || panic!("foo"));
+
+            thread::sleep(Duration::from_millis(100));
+            assert_eq!(true, env.unschedule(id));
+            assert_eq!(false, env.unschedule(id));
+            assert_eq!(true, env.unschedule(panicking));
+
+            // Wait for the run in progress if any.
+            thread::sleep(Duration::from_millis(20));
+            let count = counter.load(Ordering::Relaxed);
+            assert_eq!(true, 2 <= count);
+
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(count, counter.load(Ordering::Relaxed));
+        }
+    }
+
+    #[test]
+    fn drop_stops_timer() {
+        let env = start(1, 1);
+        env.schedule("nop", Duration::from_secs(3600), || ());
+
+        // Never blocks till the task is due.
+        drop(env);
+    }
+
+    #[test]
+    fn drop_runs_queued_tasks() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        {
+            let env = start(1, 8);
+            for _ in 0..8 {
+                let counter = counter.clone();
+                spawn(move || counter.fetch_add(1, Ordering::Relaxed), &env);
+            }
+        }
+        assert_eq!(8, counter.load(Ordering::Relaxed));
+    }
+}